@@ -48,6 +48,7 @@ pub fn run(
     let mut aziotid_keys = aziot_keyd_config::Principal {
         uid: aziotid_uid.as_raw(),
         keys: vec!["aziot_identityd_master_id".to_owned()],
+        export: false,
     };
 
     // Authorization of IS with CS.
@@ -60,6 +61,7 @@ pub fn run(
     let mut aziotcs_keys = aziot_keyd_config::Principal {
         uid: aziotcs_uid.as_raw(),
         keys: vec![],
+        export: false,
     };
 
     let provisioning = {
@@ -277,6 +279,8 @@ pub fn run(
         },
 
         localid,
+
+        trust_bundle: Default::default(),
     };
 
     let preloaded_device_id_pk_bytes = preloaded_device_id_pk.and_then(|preloaded_device_id_pk| {
@@ -302,7 +306,11 @@ pub fn run(
     );
 
     let certd_config = {
-        let super_config::CertIssuance { est, local_ca } = cert_issuance;
+        let super_config::CertIssuance {
+            est,
+            local_ca,
+            auto_renew,
+        } = cert_issuance;
 
         let est = if let Some(super_config::Est {
             trusted_certs,
@@ -407,6 +415,33 @@ pub fn run(
             None => None,
         };
 
+        let rotate_key = auto_renew
+            .as_ref()
+            .map_or(false, |auto_renew| auto_renew.rotate_key);
+
+        let cert_issuance = aziot_certd_config::CertIssuance {
+            est,
+            local_ca,
+            acme: None,
+            auto_renew,
+            default_policy: None,
+            certs: cert_issuance_certs,
+        };
+
+        if rotate_key {
+            // Rotating a cert's key pair moves a new key pair that CS has keyd generate under a renewal key ID
+            // to the cert's key pair ID. Certs whose key pairs are never rotated don't give CS access to any more keys.
+            for id in cert_issuance.certs.keys() {
+                if cert_issuance.can_rotate_key(id) && !aziotcs_keys.keys.contains(id) {
+                    aziotcs_keys.keys.push(id.clone());
+                }
+            }
+
+            aziotcs_keys
+                .keys
+                .push(format!("{}*", aziot_certd_config::RENEWAL_KEY_ID_PREFIX));
+        }
+
         let mut principal = vec![];
         if !aziotid_certs.certs.is_empty() {
             principal.push(aziotid_certs);
@@ -415,14 +450,12 @@ pub fn run(
         aziot_certd_config::Config {
             homedir_path: super::AZIOT_CERTD_HOMEDIR_PATH.into(),
 
-            cert_issuance: aziot_certd_config::CertIssuance {
-                est,
-                local_ca,
-                certs: cert_issuance_certs,
-            },
+            cert_issuance,
 
             preloaded_certs,
 
+            pkcs11: None,
+
             endpoints: aziot_certd_config::Endpoints {
                 aziot_certd: aziot_certd_endpoint,
                 aziot_keyd: aziot_keyd_endpoint.clone(),
@@ -439,6 +472,11 @@ pub fn run(
         }
 
         aziot_keyd_config::Config {
+            handle_lifetime_secs: None,
+            max_concurrency: None,
+
+            backend: aziot_keyd_config::Backend::Library,
+
             aziot_keys,
 
             preloaded_keys: preloaded_keys
@@ -475,7 +513,12 @@ fn into_cert_options(
 ) -> aziot_certd_config::CertIssuanceOptions {
     let method = match opts.method {
         super_config::CertIssuanceMethod::Est { url, .. } => {
-            aziot_certd_config::CertIssuanceMethod::Est { url, auth }
+            aziot_certd_config::CertIssuanceMethod::Est {
+                url,
+                auth,
+                reenroll: false,
+                server_keygen: false,
+            }
         }
         super_config::CertIssuanceMethod::LocalCa => {
             aziot_certd_config::CertIssuanceMethod::LocalCa
@@ -488,6 +531,7 @@ fn into_cert_options(
     aziot_certd_config::CertIssuanceOptions {
         common_name: opts.common_name,
         expiry_days: opts.expiry_days,
+        policy: None,
         method,
     }
 }
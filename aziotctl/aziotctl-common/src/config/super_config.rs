@@ -189,6 +189,9 @@ pub enum DpsAttestationMethod {
 pub struct CertIssuance {
     pub est: Option<Est>,
     pub local_ca: Option<LocalCa>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_renew: Option<aziot_certd_config::AutoRenew>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
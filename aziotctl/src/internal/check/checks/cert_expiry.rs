@@ -214,13 +214,22 @@ async fn validate_cert(
     cert_id: &str,
     cert_name: &str,
 ) -> anyhow::Result<(CheckResult, Option<CertificateValidity>)> {
-    let path = aziot_certd_config::util::get_path(
+    let location = aziot_certd_config::util::get_location(
         &certd_config.homedir_path,
+        certd_config.pkcs11.as_ref(),
         &certd_config.preloaded_certs,
         cert_id,
         false,
     )
     .map_err(|e| anyhow!("{}", e))?;
+    let path = match location {
+        aziot_certd_config::util::CertLocation::File(path) => path,
+
+        // Certs in PKCS#11 tokens can only be read through certd, which the read-certs check already does.
+        aziot_certd_config::util::CertLocation::Pkcs11 { .. } => {
+            return Ok((CheckResult::Ignored, None))
+        }
+    };
 
     if path.exists() {
         let cert_info = CertificateValidity::new(path, cert_name, cert_id).await?;
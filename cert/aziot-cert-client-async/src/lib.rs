@@ -93,6 +93,30 @@ impl Client {
         Ok(res.pem.0)
     }
 
+    /// Get the attributes that the EST server requires the CSR for the given cert to contain.
+    ///
+    /// The result is empty if the cert isn't issued via EST, or the EST server doesn't require any attributes.
+    pub async fn get_csr_attrs(
+        &self,
+        id: &str,
+    ) -> Result<aziot_cert_common::CsrAttrs, std::io::Error> {
+        let res: aziot_cert_common_http::get_csr_attrs::Response = http_common::request::<(), _>(
+            &self.inner,
+            http::Method::GET,
+            &format!(
+                "http://certd.sock/certificates/{}/csrattrs?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            None,
+        )
+        .await?;
+        Ok(res.csr_attrs)
+    }
+
     pub async fn delete_cert(&self, id: &str) -> Result<(), std::io::Error> {
         let () = http_common::request_no_content::<()>(
             &self.inner,
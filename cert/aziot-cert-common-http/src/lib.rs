@@ -99,6 +99,14 @@ pub mod create_cert {
     }
 }
 
+pub mod get_csr_attrs {
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        #[serde(rename = "csrAttrs")]
+        pub csr_attrs: aziot_cert_common::CsrAttrs,
+    }
+}
+
 pub mod get_cert {
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct Response {
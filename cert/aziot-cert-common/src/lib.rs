@@ -2,3 +2,25 @@
 
 #![deny(rust_2018_idioms)]
 #![warn(clippy::all, clippy::pedantic)]
+
+/// The attributes that an EST server requires CSRs to contain, as published at its `/csrattrs` endpoint.
+///
+/// OIDs are in dotted-decimal form, such as `2.5.4.3` for the common name.
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct CsrAttrs {
+    /// OIDs of the attributes that the subject name of the CSR must contain.
+    #[serde(rename = "subjectAttributes")]
+    pub subject_attributes: Vec<String>,
+
+    /// OIDs of the extensions that the CSR must request.
+    pub extensions: Vec<String>,
+
+    /// Whether the CSR must contain a challengePassword attribute.
+    #[serde(rename = "challengePassword")]
+    pub challenge_password: bool,
+
+    /// OIDs that the EST server listed that aren't subject attributes or extensions, such as the key type or signature algorithm
+    /// that it expects. CSRs are not validated against these.
+    #[serde(rename = "otherAttributes")]
+    pub other_attributes: Vec<String>,
+}
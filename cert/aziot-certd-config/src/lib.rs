@@ -26,6 +26,10 @@ pub struct Config {
     #[serde(default)]
     pub preloaded_certs: std::collections::BTreeMap<String, PreloadedCert>,
 
+    /// Configuration of the PKCS#11 library used for certs stored in PKCS#11 tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pkcs11: Option<Pkcs11>,
+
     /// Map of service names to endpoint URIs.
     ///
     /// Only configurable in debug builds for the sake of tests.
@@ -53,11 +57,64 @@ pub struct CertIssuance {
     /// Configuration of parameters for issuing certs via a local CA cert.
     pub local_ca: Option<LocalCa>,
 
+    /// Configuration of parameters for issuing certs via ACME.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acme: Option<Acme>,
+
+    /// Configuration of automatic renewal of the certs in `certs`. Certs are not renewed automatically if this is not set.
+    pub auto_renew: Option<AutoRenew>,
+
+    /// Policy of the locally issued certs whose ID has no policy of its own, including IDs that are not in `certs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_policy: Option<CertPolicy>,
+
     /// Map of certificate IDs to the details used to issue them.
     #[serde(flatten)]
     pub certs: std::collections::BTreeMap<String, CertIssuanceOptions>,
 }
 
+impl CertIssuance {
+    /// Whether the key pair of the cert with the given ID is replaced when the cert is renewed with `auto_renew.rotate_key`.
+    ///
+    /// The key of a cert that is used to authenticate with an EST server, or that is renewed with `/simplereenroll`,
+    /// is never rotated, because renewing the cert requires authenticating with its current key.
+    /// The key of a cert that is issued with `/serverkeygen` is replaced by the EST server anyway.
+    /// The key of the local CA cert is never rotated, because the certs it has issued would no longer chain to it.
+    pub fn can_rotate_key(&self, id: &str) -> bool {
+        if matches!(&self.local_ca, Some(local_ca) if local_ca.cert == id) {
+            return false;
+        }
+
+        let mut est_auth_x509 = self
+            .est
+            .as_ref()
+            .and_then(|est| est.auth.x509.as_ref())
+            .into_iter()
+            .chain(
+                self.certs
+                    .values()
+                    .filter_map(|options| match &options.method {
+                        CertIssuanceMethod::Est {
+                            auth: Some(auth), ..
+                        } => auth.x509.as_ref(),
+                        _ => None,
+                    }),
+            );
+        if est_auth_x509.any(|x509| x509.identity.0 == id) {
+            return false;
+        }
+
+        match self.certs.get(id).map(|options| &options.method) {
+            Some(CertIssuanceMethod::Est {
+                reenroll,
+                server_keygen,
+                ..
+            }) => !reenroll && !server_keygen,
+            _ => true,
+        }
+    }
+}
+
 /// Configuration of parameters for issuing certs via EST.
 #[derive(Debug, PartialEq)]
 pub struct Est {
@@ -176,6 +233,179 @@ pub struct LocalCa {
     pub pk: String,
 }
 
+/// Configuration of parameters for issuing certs via ACME (RFC 8555).
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Acme {
+    /// URL of the ACME server's directory.
+    pub directory_url: url::Url,
+
+    /// ID of the key pair in keyd that is used as the ACME account key.
+    ///
+    /// It is created as an EC P-256 key pair if it does not exist. RSA key pairs are also supported.
+    pub account_key: String,
+
+    /// Contact URLs of the account, such as `"mailto:admin@example.com"`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contact: Vec<String>,
+
+    /// External account binding, for ACME servers that require accounts to be bound to an account in a non-ACME system.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eab: Option<AcmeEab>,
+
+    /// How the ACME server's challenges are answered.
+    #[serde(default)]
+    pub challenge: AcmeChallenge,
+
+    /// Whether the terms of service of the ACME server are agreed to when the account is created.
+    ///
+    /// ACME servers that publish terms of service require this to be set.
+    #[serde(default)]
+    pub terms_of_service_agreed: bool,
+
+    /// List of certs that should be treated as trusted roots for validating the ACME server's TLS certificate.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_certs: Vec<String>,
+}
+
+/// External account binding of the ACME account, as described in RFC 8555 section 7.3.4.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AcmeEab {
+    /// Key identifier given by the ACME server's operator.
+    pub key_id: String,
+
+    /// Base64url-encoded HMAC key given by the ACME server's operator.
+    pub hmac_key: String,
+}
+
+/// How the ACME server's challenges are answered.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum AcmeChallenge {
+    /// Serve the key authorization over HTTP on the given port, as described in RFC 8555 section 8.3.
+    #[serde(rename = "http-01")]
+    Http01 {
+        #[serde(default = "default_http01_port")]
+        port: u16,
+    },
+
+    /// Serve a self-signed cert over TLS with the `acme-tls/1` ALPN protocol on the given port, as described in RFC 8737.
+    #[serde(rename = "tls-alpn-01")]
+    TlsAlpn01 {
+        #[serde(default = "default_tls_alpn01_port")]
+        port: u16,
+    },
+}
+
+impl Default for AcmeChallenge {
+    fn default() -> Self {
+        AcmeChallenge::Http01 {
+            port: default_http01_port(),
+        }
+    }
+}
+
+fn default_http01_port() -> u16 {
+    80
+}
+
+fn default_tls_alpn01_port() -> u16 {
+    443
+}
+
+/// Configuration of automatic renewal of issued certs.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AutoRenew {
+    /// When a cert is renewed.
+    pub threshold: RenewalThreshold,
+
+    /// Whether a renewed cert gets a new key pair instead of reusing the existing one.
+    ///
+    /// The new key pair is generated by keyd under an ID that starts with [`RENEWAL_KEY_ID_PREFIX`], and then moved to the cert's key pair ID.
+    /// certd's keyd principal must be authorized for those IDs as well as for the key pair IDs of the renewed certs.
+    #[serde(default)]
+    pub rotate_key: bool,
+}
+
+/// The prefix of the IDs of the key pairs that are generated when a cert is renewed with `rotate_key`.
+///
+/// The rest of the ID is random, so it can't collide with the ID of an existing key pair.
+pub const RENEWAL_KEY_ID_PREFIX: &str = "aziot-certd-renewal-";
+
+/// When a cert is renewed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenewalThreshold {
+    /// When the given percentage of the cert's lifetime has passed. Written as `"80%"`.
+    Percentage(u8),
+
+    /// When the cert expires in the given number of days. Written as `"10d"`.
+    DaysBeforeExpiry(u32),
+}
+
+impl std::fmt::Display for RenewalThreshold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenewalThreshold::Percentage(percentage) => write!(f, "{}%", percentage),
+            RenewalThreshold::DaysBeforeExpiry(days) => write!(f, "{}d", days),
+        }
+    }
+}
+
+impl std::str::FromStr for RenewalThreshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(percentage) = s.strip_suffix('%') {
+            let percentage: u8 = percentage
+                .parse()
+                .map_err(|err| format!("invalid percentage {:?}: {}", s, err))?;
+            if percentage == 0 || percentage > 100 {
+                return Err(format!(
+                    "invalid percentage {:?}: must be between 1% and 100%",
+                    s
+                ));
+            }
+
+            Ok(RenewalThreshold::Percentage(percentage))
+        } else if let Some(days) = s.strip_suffix('d') {
+            let days: u32 = days
+                .parse()
+                .map_err(|err| format!("invalid number of days {:?}: {}", s, err))?;
+            if days == 0 {
+                return Err(format!(
+                    "invalid number of days {:?}: must be greater than 0",
+                    s
+                ));
+            }
+
+            Ok(RenewalThreshold::DaysBeforeExpiry(days))
+        } else {
+            Err(format!(
+                "invalid renewal threshold {:?}: expected a percentage like \"80%\" or a number of days like \"10d\"",
+                s
+            ))
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for RenewalThreshold {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        let s: String = serde::Deserialize::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl serde::Serialize for RenewalThreshold {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
 /// Details for issuing a single cert.
 #[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct CertIssuanceOptions {
@@ -190,6 +420,126 @@ pub struct CertIssuanceOptions {
     /// The method used to issue a certificate.
     #[serde(flatten)]
     pub method: CertIssuanceMethod,
+
+    /// Policy that the certificate must conform to when it is issued locally.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<CertPolicy>,
+}
+
+/// Policy that a certificate must conform to when it is issued locally, by the local CA or self-signed.
+///
+/// Certificates issued by EST or ACME servers are subject to the policies of those servers instead.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct CertPolicy {
+    /// Extensions of the CSR that may be copied onto the certificate, as names like `"subjectAltName"` or dotted OIDs.
+    ///
+    /// A CSR with any other extension is rejected. If not set, all extensions of the CSR are copied.
+    /// Extensions that are forced by `basic_constraints`, `key_usage` or `extended_key_usage` are always allowed,
+    /// since they replace the CSR's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_extensions: Option<Vec<String>>,
+
+    /// The basicConstraints extension of the certificate, replacing the CSR's.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_basic_constraints",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub basic_constraints: Option<BasicConstraints>,
+
+    /// The keyUsage extension of the certificate, replacing the CSR's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_usage: Option<Vec<KeyUsage>>,
+
+    /// The extKeyUsage extension of the certificate, replacing the CSR's, as names like `"serverAuth"` or dotted OIDs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extended_key_usage: Option<Vec<String>>,
+
+    /// Patterns that every DNS name, IP address, URI and email address in the CSR's subjectAltName extension must match.
+    ///
+    /// Wildcards may be used. A CSR with a subjectAltName entry of any other type is rejected. If not set, all entries are allowed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_subject_alt_names: Option<Vec<String>>,
+
+    /// Maximum number of days between certificate issuance and expiry. Caps `expiry_days`.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_expiry_days",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_expiry_days: Option<u32>,
+
+    /// Digest algorithm that the certificate is signed with.
+    ///
+    /// If not set, SHA-384 is used for P-384 issuer keys and SHA-256 for all others. Cannot be set for Ed25519 issuer keys,
+    /// which don't use a separate digest algorithm.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<Digest>,
+
+    /// Template of the certificate's subject name, replacing the CSR's subject name and `common_name`.
+    ///
+    /// This is a comma-separated list of attributes like `"CN={common_name}, O=Contoso, OU={cert_id}"`.
+    /// `{common_name}` is replaced by `common_name` if it's set and the CSR's common name otherwise, and `{cert_id}` by the certificate ID.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+}
+
+/// The basicConstraints extension of a certificate.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct BasicConstraints {
+    /// Whether the certificate is a CA certificate.
+    #[serde(default)]
+    pub ca: bool,
+
+    /// Maximum number of intermediate CA certificates below this one. Only valid for CA certificates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_len: Option<u32>,
+}
+
+fn deserialize_basic_constraints<'de, D>(
+    deserializer: D,
+) -> Result<Option<BasicConstraints>, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    let result: Option<BasicConstraints> = serde::Deserialize::deserialize(deserializer)?;
+
+    if let Some(BasicConstraints {
+        ca: false,
+        path_len: Some(_),
+    }) = result
+    {
+        return Err(serde::de::Error::custom(
+            "path_len can only be set if ca is true",
+        ));
+    }
+
+    Ok(result)
+}
+
+/// A key usage of the keyUsage extension.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyUsage {
+    DigitalSignature,
+    NonRepudiation,
+    KeyEncipherment,
+    DataEncipherment,
+    KeyAgreement,
+    KeyCertSign,
+    #[serde(rename = "cRLSign")]
+    CrlSign,
+    EncipherOnly,
+    DecipherOnly,
+}
+
+/// A digest algorithm for signing certificates.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Digest {
+    Sha256,
+    Sha384,
+    Sha512,
 }
 
 pub fn deserialize_expiry_days<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
@@ -221,6 +571,29 @@ pub enum CertIssuanceMethod {
             serialize_with = "serialize_est_auth"
         )]
         auth: Option<EstAuth>,
+
+        /// Whether to renew the certificate with `/simplereenroll`, authenticated with the current certificate and its key.
+        ///
+        /// Only used if the current certificate exists and has not expired. Otherwise it is issued with `/simpleenroll`.
+        /// Ignored if `server_keygen` is set, since `/serverkeygen` always issues a new key pair.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        reenroll: bool,
+
+        /// Whether the EST server generates the key pair, via `/serverkeygen`.
+        ///
+        /// The private key returned by the server is imported into keyd, replacing the key pair of the certificate.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        server_keygen: bool,
+    },
+
+    /// The certificate is to be issued via ACME.
+    ///
+    /// The certificate's identifiers are the DNS names and IP addresses in the CSR's subjectAltName extension,
+    /// or the CSR's common name if it has no subjectAltName extension.
+    Acme {
+        /// URL of the ACME server's directory. Defaults to `directory_url` in `[cert_issuance.acme]`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        directory_url: Option<url::Url>,
     },
 
     /// The certificate is to be issued via a local CA cert.
@@ -266,7 +639,8 @@ where
 pub enum PreloadedCert {
     /// A URI for the location.
     ///
-    /// Only `file://` URIs are supported.
+    /// Only `file://` and `pkcs11:` URIs are supported. A `pkcs11:` URI must identify the token and the object label,
+    /// and requires [`Config::pkcs11`] to be set.
     Uri(url::Url),
 
     /// A list of IDs of other certs, preloaded or otherwise.
@@ -275,6 +649,20 @@ pub enum PreloadedCert {
     Ids(Vec<String>),
 }
 
+/// Configuration of the PKCS#11 library used for certs stored in PKCS#11 tokens.
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Pkcs11 {
+    /// Path of the PKCS#11 library.
+    pub lib_path: std::path::PathBuf,
+
+    /// PKCS#11 URI of the token that certs are stored in, such as `pkcs11:token=Certs?pin-value=1234`.
+    ///
+    /// If set, certs that aren't preloaded are stored as `CKO_CERTIFICATE` objects in this token, labeled with their cert ID,
+    /// instead of as files under `homedir_path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_slot: Option<url::Url>,
+}
+
 /// Map of service names to endpoint URIs.
 #[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct Endpoints {
@@ -381,9 +769,10 @@ mod tests {
 homedir_path = "/var/lib/aziot/certd"
 
 [cert_issuance]
-device-ca = { method = "est", common_name = "custom-name" }
+device-ca = { method = "est", common_name = "custom-name", server_keygen = true }
 module-id = { method = "self_signed", expiry_days = 90, common_name = "custom-name"}
 module-server = { method = "local_ca" }
+web-server = { method = "acme" }
 
 [cert_issuance.device-id]
 method = "est"
@@ -396,6 +785,28 @@ identity_cert = "device-id"
 identity_pk = "device-id"
 bootstrap_identity_cert = "bootstrap"
 bootstrap_identity_pk = "bootstrap"
+reenroll = true
+
+[cert_issuance.edge-server]
+method = "local_ca"
+
+[cert_issuance.edge-server.policy]
+allowed_extensions = ["subjectAltName", "2.5.29.14"]
+basic_constraints = { ca = false }
+key_usage = ["digitalSignature", "keyEncipherment"]
+extended_key_usage = ["serverAuth"]
+allowed_subject_alt_names = ["*.example.com", "10.0.0.*"]
+max_expiry_days = 90
+digest = "sha384"
+subject = "CN={common_name}, O=Contoso, OU={cert_id}"
+
+[cert_issuance.auto_renew]
+threshold = "80%"
+rotate_key = true
+
+[cert_issuance.default_policy]
+basic_constraints = { ca = false }
+max_expiry_days = 30
 
 [cert_issuance.est]
 identity_cert = "est-id"
@@ -410,6 +821,14 @@ trusted_certs = [
 default = "https://estendpoint.com/.well-known/est/"
 device-ca = "https://estendpoint.com/.well-known/est/device-ca/"
 
+[cert_issuance.acme]
+directory_url = "https://acme.example.com/directory"
+account_key = "acme-account"
+contact = ["mailto:admin@example.com"]
+eab = { key_id = "kid-1", hmac_key = "zWNDZM6eQGHWpSRTPal5eIUYFTu7EajVIoguysqZ9wG44nMEtx3MUAsUDkMTQ12W" }
+challenge = { type = "tls-alpn-01", port = 5001 }
+terms_of_service_agreed = true
+
 [preloaded_certs]
 bootstrap = "file:///var/secrets/bootstrap.cer"
 est-ca = "file:///var/secrets/est-ca.cer"
@@ -417,6 +836,10 @@ trust-bundle = [
 	"est-ca",
 ]
 
+[pkcs11]
+lib_path = "/usr/lib/libsofthsm2.so"
+base_slot = "pkcs11:token=Certs?pin-value=1234"
+
 [[principal]]
 uid = 1000
 certs = ["test"]
@@ -459,16 +882,48 @@ certs = ["test"]
 
                     local_ca: None,
 
+                    acme: Some(super::Acme {
+                        directory_url: "https://acme.example.com/directory".parse().unwrap(),
+                        account_key: "acme-account".to_owned(),
+                        contact: vec!["mailto:admin@example.com".to_owned()],
+                        eab: Some(super::AcmeEab {
+                            key_id: "kid-1".to_owned(),
+                            hmac_key:
+                                "zWNDZM6eQGHWpSRTPal5eIUYFTu7EajVIoguysqZ9wG44nMEtx3MUAsUDkMTQ12W"
+                                    .to_owned(),
+                        }),
+                        challenge: super::AcmeChallenge::TlsAlpn01 { port: 5001 },
+                        terms_of_service_agreed: true,
+                        trusted_certs: vec![],
+                    }),
+
+                    auto_renew: Some(super::AutoRenew {
+                        threshold: super::RenewalThreshold::Percentage(80),
+                        rotate_key: true,
+                    }),
+
+                    default_policy: Some(super::CertPolicy {
+                        basic_constraints: Some(super::BasicConstraints {
+                            ca: false,
+                            path_len: None,
+                        }),
+                        max_expiry_days: Some(30),
+                        ..Default::default()
+                    }),
+
                     certs: [
                         (
                             "device-ca",
                             super::CertIssuanceOptions {
                                 method: super::CertIssuanceMethod::Est {
                                     url: None,
-                                    auth: None
+                                    auth: None,
+                                    reenroll: false,
+                                    server_keygen: true,
                                 },
                                 common_name: Some("custom-name".to_owned()),
                                 expiry_days: None,
+                                policy: None,
                             }
                         ),
                         (
@@ -495,10 +950,45 @@ certs = ["test"]
                                                 "bootstrap".to_owned()
                                             )),
                                         })
-                                    })
+                                    }),
+                                    reenroll: true,
+                                    server_keygen: false,
                                 },
                                 common_name: Some("test-device".to_owned()),
-                                expiry_days: Some(365)
+                                expiry_days: Some(365),
+                                policy: None,
+                            }
+                        ),
+                        (
+                            "edge-server",
+                            super::CertIssuanceOptions {
+                                method: super::CertIssuanceMethod::LocalCa,
+                                common_name: None,
+                                expiry_days: None,
+                                policy: Some(super::CertPolicy {
+                                    allowed_extensions: Some(vec![
+                                        "subjectAltName".to_owned(),
+                                        "2.5.29.14".to_owned(),
+                                    ]),
+                                    basic_constraints: Some(super::BasicConstraints {
+                                        ca: false,
+                                        path_len: None,
+                                    }),
+                                    key_usage: Some(vec![
+                                        super::KeyUsage::DigitalSignature,
+                                        super::KeyUsage::KeyEncipherment,
+                                    ]),
+                                    extended_key_usage: Some(vec!["serverAuth".to_owned()]),
+                                    allowed_subject_alt_names: Some(vec![
+                                        "*.example.com".to_owned(),
+                                        "10.0.0.*".to_owned(),
+                                    ]),
+                                    max_expiry_days: Some(90),
+                                    digest: Some(super::Digest::Sha384),
+                                    subject: Some(
+                                        "CN={common_name}, O=Contoso, OU={cert_id}".to_owned()
+                                    ),
+                                }),
                             }
                         ),
                         (
@@ -507,6 +997,7 @@ certs = ["test"]
                                 method: super::CertIssuanceMethod::SelfSigned,
                                 common_name: Some("custom-name".to_owned()),
                                 expiry_days: Some(90),
+                                policy: None,
                             }
                         ),
                         (
@@ -515,6 +1006,18 @@ certs = ["test"]
                                 method: super::CertIssuanceMethod::LocalCa,
                                 common_name: None,
                                 expiry_days: None,
+                                policy: None,
+                            }
+                        ),
+                        (
+                            "web-server",
+                            super::CertIssuanceOptions {
+                                method: super::CertIssuanceMethod::Acme {
+                                    directory_url: None
+                                },
+                                common_name: None,
+                                expiry_days: None,
+                                policy: None,
                             }
                         ),
                     ]
@@ -544,6 +1047,11 @@ certs = ["test"]
                 .into_iter()
                 .collect(),
 
+                pkcs11: Some(super::Pkcs11 {
+                    lib_path: "/usr/lib/libsofthsm2.so".into(),
+                    base_slot: Some("pkcs11:token=Certs?pin-value=1234".parse().unwrap()),
+                }),
+
                 endpoints: super::Endpoints {
                     aziot_certd: http_common::Connector::Unix {
                         socket_path: std::path::Path::new("/run/aziot/certd.sock").into()
@@ -561,6 +1069,52 @@ certs = ["test"]
         );
     }
 
+    #[test]
+    fn parse_renewal_threshold() {
+        assert_eq!(
+            "80%".parse::<super::RenewalThreshold>().unwrap(),
+            super::RenewalThreshold::Percentage(80)
+        );
+        assert_eq!(
+            "10d".parse::<super::RenewalThreshold>().unwrap(),
+            super::RenewalThreshold::DaysBeforeExpiry(10)
+        );
+
+        for invalid in &["0%", "101%", "0d", "-1d", "10", "ten days"] {
+            assert!(invalid.parse::<super::RenewalThreshold>().is_err());
+        }
+
+        for threshold in &[
+            super::RenewalThreshold::Percentage(100),
+            super::RenewalThreshold::DaysBeforeExpiry(30),
+        ] {
+            assert_eq!(
+                threshold
+                    .to_string()
+                    .parse::<super::RenewalThreshold>()
+                    .unwrap(),
+                *threshold
+            );
+        }
+    }
+
+    #[test]
+    fn parse_basic_constraints() {
+        let policy: super::CertPolicy =
+            toml::from_str("basic_constraints = { ca = true, path_len = 0 }").unwrap();
+        assert_eq!(
+            policy.basic_constraints,
+            Some(super::BasicConstraints {
+                ca: true,
+                path_len: Some(0),
+            }),
+        );
+
+        let policy: Result<super::CertPolicy, _> =
+            toml::from_str("basic_constraints = { path_len = 0 }");
+        assert!(policy.is_err());
+    }
+
     #[cfg(debug_assertions)]
     #[test]
     fn parse_config_with_explicit_endpoints() {
@@ -582,6 +1136,8 @@ aziot_certd = "unix:///run/aziot/certd.sock"
 
                 preloaded_certs: Default::default(),
 
+                pkcs11: None,
+
                 endpoints: super::Endpoints {
                     aziot_certd: http_common::Connector::Unix {
                         socket_path: std::path::Path::new("/run/aziot/certd.sock").into()
@@ -1,19 +1,65 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use crate::PreloadedCert;
+use crate::{Pkcs11, PreloadedCert};
 
+/// The location of a cert.
+#[derive(Debug, PartialEq)]
+pub enum CertLocation {
+    /// A file containing the PEM-encoded cert chain.
+    File(std::path::PathBuf),
+
+    /// `CKO_CERTIFICATE` objects in a PKCS#11 token, one per cert in the chain.
+    Pkcs11 {
+        /// Path of the PKCS#11 library.
+        lib_path: std::path::PathBuf,
+
+        /// PKCS#11 URI of the token and the label of the objects.
+        uri: url::Url,
+    },
+}
+
+/// Get the location of the cert with the given ID.
+///
 /// `create_dir_if_not_exist` should only be set to true when this method is
 /// called from `aziot-certd`, or else the directory may be created with the
 /// incorrect permissions.
-pub fn get_path(
+pub fn get_location(
     homedir_path: &std::path::Path,
+    pkcs11: Option<&Pkcs11>,
     preloaded_certs: &std::collections::BTreeMap<String, PreloadedCert>,
     cert_id: &str,
     create_dir_if_not_exist: bool,
-) -> Result<std::path::PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<CertLocation, Box<dyn std::error::Error + Send + Sync>> {
     if let Some(preloaded_cert) = preloaded_certs.get(cert_id) {
-        let path = get_preloaded_cert_path(preloaded_cert, cert_id)?;
-        return Ok(path);
+        let location = get_preloaded_cert_location(pkcs11, preloaded_cert, cert_id)?;
+        return Ok(location);
+    }
+
+    if let Some(Pkcs11 {
+        lib_path,
+        base_slot: Some(base_slot),
+    }) = pkcs11
+    {
+        // Insert the object label at the end of the path, before the query that holds the PIN.
+        let base_slot = base_slot.as_str();
+        let (path, query) = match base_slot.find('?') {
+            Some(query_start) => base_slot.split_at(query_start),
+            None => (base_slot, ""),
+        };
+        let object_label =
+            percent_encoding::utf8_percent_encode(cert_id, percent_encoding::NON_ALPHANUMERIC);
+        let uri = format!("{};object={}{}", path, object_label, query);
+        let uri = uri.parse().map_err(|err| {
+            format!(
+                "pkcs11.base_slot could not be combined with cert ID {:?}: {}",
+                cert_id, err,
+            )
+        })?;
+
+        return Ok(CertLocation::Pkcs11 {
+            lib_path: lib_path.clone(),
+            uri,
+        });
     }
 
     let mut path = homedir_path.to_owned();
@@ -32,33 +78,47 @@ pub fn get_path(
     let hash = hex::encode(hash);
     path.push(format!("{}-{}.cer", id_sanitized, hash));
 
-    Ok(path)
+    Ok(CertLocation::File(path))
 }
 
-fn get_preloaded_cert_path(
+fn get_preloaded_cert_location(
+    pkcs11: Option<&Pkcs11>,
     preloaded_cert: &PreloadedCert,
     cert_id: &str,
-) -> Result<std::path::PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<CertLocation, Box<dyn std::error::Error + Send + Sync>> {
     match preloaded_cert {
-        PreloadedCert::Uri(uri) => {
-            let scheme = uri.scheme();
-            if scheme != "file" {
-                return Err(format!(
-                    "preloaded cert {:?} does not have a valid URI: unrecognized scheme {:?}",
-                    cert_id, scheme,
-                )
-                .into());
+        PreloadedCert::Uri(uri) => match uri.scheme() {
+            "file" => {
+                let path = uri.to_file_path().map_err(|()| {
+                    format!(
+                        "preloaded cert {:?} does not have a valid URI: not a valid path",
+                        cert_id,
+                    )
+                })?;
+
+                Ok(CertLocation::File(path))
             }
 
-            let path = uri.to_file_path().map_err(|()| {
-                format!(
-                    "preloaded cert {:?} does not have a valid URI: not a valid path",
-                    cert_id,
-                )
-            })?;
+            "pkcs11" => {
+                let pkcs11 = pkcs11.ok_or_else(|| {
+                    format!(
+                        "preloaded cert {:?} has a PKCS#11 URI but pkcs11.lib_path is not configured",
+                        cert_id,
+                    )
+                })?;
 
-            Ok(path)
-        }
+                Ok(CertLocation::Pkcs11 {
+                    lib_path: pkcs11.lib_path.clone(),
+                    uri: uri.clone(),
+                })
+            }
+
+            scheme => Err(format!(
+                "preloaded cert {:?} does not have a valid URI: unrecognized scheme {:?}",
+                cert_id, scheme,
+            )
+            .into()),
+        },
 
         PreloadedCert::Ids(_) => Err(format!(
             "preloaded cert {:?} is a list of IDs, not a single URI",
@@ -67,3 +127,94 @@ fn get_preloaded_cert_path(
         .into()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::CertLocation;
+
+    #[test]
+    fn get_location() {
+        let preloaded_certs = vec![
+            (
+                "file-cert".to_owned(),
+                crate::PreloadedCert::Uri("file:///var/secrets/file-cert.cer".parse().unwrap()),
+            ),
+            (
+                "token-cert".to_owned(),
+                crate::PreloadedCert::Uri("pkcs11:token=Preloaded;object=cert".parse().unwrap()),
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let pkcs11 = crate::Pkcs11 {
+            lib_path: "/usr/lib/libsofthsm2.so".into(),
+            base_slot: Some("pkcs11:token=Certs?pin-value=1234".parse().unwrap()),
+        };
+
+        assert_eq!(
+            super::get_location(
+                "/var/lib/aziot/certd".as_ref(),
+                Some(&pkcs11),
+                &preloaded_certs,
+                "file-cert",
+                false,
+            )
+            .unwrap(),
+            CertLocation::File("/var/secrets/file-cert.cer".into()),
+        );
+
+        assert_eq!(
+            super::get_location(
+                "/var/lib/aziot/certd".as_ref(),
+                Some(&pkcs11),
+                &preloaded_certs,
+                "token-cert",
+                false,
+            )
+            .unwrap(),
+            CertLocation::Pkcs11 {
+                lib_path: "/usr/lib/libsofthsm2.so".into(),
+                uri: "pkcs11:token=Preloaded;object=cert".parse().unwrap(),
+            },
+        );
+
+        assert_eq!(
+            super::get_location(
+                "/var/lib/aziot/certd".as_ref(),
+                Some(&pkcs11),
+                &preloaded_certs,
+                "device-id",
+                false,
+            )
+            .unwrap(),
+            CertLocation::Pkcs11 {
+                lib_path: "/usr/lib/libsofthsm2.so".into(),
+                uri: "pkcs11:token=Certs;object=device%2Did?pin-value=1234"
+                    .parse()
+                    .unwrap(),
+            },
+        );
+
+        assert!(matches!(
+            super::get_location(
+                "/var/lib/aziot/certd".as_ref(),
+                None,
+                &preloaded_certs,
+                "device-id",
+                false,
+            )
+            .unwrap(),
+            CertLocation::File(_),
+        ));
+
+        assert!(super::get_location(
+            "/var/lib/aziot/certd".as_ref(),
+            None,
+            &preloaded_certs,
+            "token-cert",
+            false,
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,1356 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Issuance of certs via ACME (RFC 8555).
+//!
+//! Challenges are answered with http-01 (RFC 8555 section 8.3) or tls-alpn-01 (RFC 8737), by running a temporary responder
+//! on the configured port while the ACME server validates the certificate's identifiers.
+
+use aziot_certd_config::{AcmeChallenge, AcmeEab};
+use http_common::MaybeProxyConnector;
+
+/// The number of times an authorization or order is polled before giving up on it.
+const POLL_ATTEMPTS: u32 = 30;
+
+/// The interval between polls if the ACME server doesn't specify one with a `Retry-After` header.
+const DEFAULT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// The upper bound of the interval between polls, regardless of the ACME server's `Retry-After` header.
+const MAX_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// The ALPN protocol of tls-alpn-01, in wire format.
+const ACME_TLS_ALPN_PROTOCOL: &[u8] = b"\x0aacme-tls/1";
+
+/// The OID of the id-pe-acmeIdentifier extension of tls-alpn-01 certs.
+const ACME_IDENTIFIER_OID: &str = "1.3.6.1.5.5.7.1.31";
+
+/// The ACME account key, which is a key pair in keyd.
+pub(crate) struct AccountKey {
+    key_client: std::sync::Arc<aziot_key_client::Client>,
+
+    /// The ID of the key pair in keyd.
+    ///
+    /// An order can take longer than the lifetime of a key handle, so a new handle is loaded for every signature.
+    id: String,
+    kind: AccountKeyKind,
+
+    /// The public key as a JWK, serialized in the canonical form of RFC 7638 that its thumbprint is computed over.
+    jwk: String,
+}
+
+#[derive(Clone, Copy)]
+enum AccountKeyKind {
+    /// EC P-256, signed with ES256.
+    EcP256,
+
+    /// RSA, signed with RS256.
+    Rsa,
+}
+
+impl AccountKey {
+    /// Loads the account key with the given ID, creating it as an EC P-256 key pair if it doesn't exist.
+    pub(crate) fn load(
+        key_client: std::sync::Arc<aziot_key_client::Client>,
+        key_engine: &mut openssl2::FunctionalEngine,
+        id: &str,
+    ) -> Result<Self, crate::Error> {
+        let handle = key_client
+            .create_key_pair_if_not_exists(id, Some("ec-p256:rsa-2048"))
+            .map_err(create_cert_error)?;
+
+        let key_handle = std::ffi::CString::new(handle.0).map_err(create_cert_error)?;
+        let public_key = key_engine
+            .load_public_key(&key_handle)
+            .map_err(create_cert_error)?;
+
+        let (kind, jwk) = jwk(&public_key)?;
+
+        Ok(AccountKey {
+            key_client,
+            id: id.to_owned(),
+            kind,
+            jwk,
+        })
+    }
+
+    fn alg(&self) -> &'static str {
+        match self.kind {
+            AccountKeyKind::EcP256 => "ES256",
+            AccountKeyKind::Rsa => "RS256",
+        }
+    }
+
+    /// Returns the base64url-encoded SHA-256 thumbprint of the JWK, as described in RFC 7638.
+    fn thumbprint(&self) -> String {
+        let digest = openssl::sha::sha256(self.jwk.as_bytes());
+        base64url(&digest)
+    }
+
+    /// Signs the given JWS signing input with the algorithm returned by [`AccountKey::alg`].
+    fn sign(&self, signing_input: &[u8]) -> Result<Vec<u8>, crate::Error> {
+        let digest = openssl::sha::sha256(signing_input);
+
+        let handle = self
+            .key_client
+            .load_key_pair(&self.id)
+            .map_err(create_cert_error)?;
+
+        match self.kind {
+            AccountKeyKind::EcP256 => {
+                let signature = self
+                    .key_client
+                    .sign(&handle, aziot_key_common::SignMechanism::Ecdsa, &digest)
+                    .map_err(create_cert_error)?;
+
+                // keyd returns a DER-encoded ECDSA-Sig-Value, but JWS requires the fixed-size concatenation of r and s.
+                let signature =
+                    openssl::ecdsa::EcdsaSig::from_der(&signature).map_err(create_cert_error)?;
+                let mut result = pad(&signature.r().to_vec(), 32)?;
+                result.extend_from_slice(&pad(&signature.s().to_vec(), 32)?);
+                Ok(result)
+            }
+
+            AccountKeyKind::Rsa => self
+                .key_client
+                .sign(
+                    &handle,
+                    aziot_key_common::SignMechanism::RsaPkcs1 {
+                        digest: aziot_key_common::DigestAlgorithm::Sha256,
+                    },
+                    &digest,
+                )
+                .map_err(create_cert_error),
+        }
+    }
+
+    /// Returns a JWS in the flattened JSON serialization, signed with this key.
+    ///
+    /// The protected header identifies the key with `kid` if `kid` is set, and with the JWK otherwise.
+    /// A `payload` of `None` creates a POST-as-GET request.
+    fn jws(
+        &self,
+        url: &str,
+        nonce: &str,
+        kid: Option<&str>,
+        payload: Option<&serde_json::Value>,
+    ) -> Result<Vec<u8>, crate::Error> {
+        let mut protected = serde_json::json!({
+            "alg": self.alg(),
+            "nonce": nonce,
+            "url": url,
+        });
+        if let Some(kid) = kid {
+            protected["kid"] = kid.into();
+        } else {
+            protected["jwk"] = serde_json::from_str(&self.jwk).map_err(create_cert_error)?;
+        }
+        let protected = serde_json::to_vec(&protected).map_err(create_cert_error)?;
+        let protected = base64url(&protected);
+
+        let payload = match payload {
+            Some(payload) => {
+                let payload = serde_json::to_vec(payload).map_err(create_cert_error)?;
+                base64url(&payload)
+            }
+            None => String::new(),
+        };
+
+        let signature = self.sign(format!("{}.{}", protected, payload).as_bytes())?;
+
+        let jws = serde_json::json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": base64url(&signature),
+        });
+        let jws = serde_json::to_vec(&jws).map_err(create_cert_error)?;
+        Ok(jws)
+    }
+}
+
+/// A request for a cert from an ACME server.
+///
+/// It holds everything that's needed from the config, so that the cert can be requested without keeping the [`crate::Api`] locked
+/// while the ACME server validates the challenges.
+pub(crate) struct CertRequest {
+    pub(crate) csr: Vec<u8>,
+    pub(crate) directory_url: url::Url,
+    pub(crate) account_key: AccountKey,
+    pub(crate) contact: Vec<String>,
+    pub(crate) eab: Option<AcmeEab>,
+    pub(crate) challenge: AcmeChallenge,
+    pub(crate) terms_of_service_agreed: bool,
+    pub(crate) trusted_certs: Vec<openssl::x509::X509>,
+    pub(crate) proxy_uri: Option<hyper::Uri>,
+}
+
+/// Requests a cert for the given CSR from the ACME server.
+///
+/// Returns the PEM-encoded cert chain.
+pub(crate) async fn create_cert(request: CertRequest) -> Result<Vec<u8>, crate::Error> {
+    let CertRequest {
+        csr,
+        directory_url,
+        account_key,
+        contact,
+        eab,
+        challenge,
+        terms_of_service_agreed,
+        trusted_certs,
+        proxy_uri,
+    } = request;
+    let account_key = &account_key;
+
+    let x509_req = openssl::x509::X509Req::from_pem(&csr)
+        .map_err(|err| crate::Error::invalid_parameter("csr", err))?;
+    let identifiers = identifiers(&x509_req)?;
+    let csr = x509_req.to_der().map_err(create_cert_error)?;
+
+    let proxy_connector =
+        MaybeProxyConnector::new(proxy_uri, None, &trusted_certs).map_err(create_cert_error)?;
+    let inner = hyper::Client::builder().build(proxy_connector);
+
+    let directory = get_directory(&inner, &directory_url).await?;
+
+    let mut client = Client {
+        inner,
+        account_key,
+        new_nonce_url: directory.new_nonce,
+        nonce: None,
+        kid: None,
+    };
+
+    // Create the account, or look up the existing one for the account key.
+
+    if let Some(terms_of_service) = &directory.meta.terms_of_service {
+        if !terms_of_service_agreed {
+            return Err(create_cert_error(format!(
+                "ACME server requires agreeing to its terms of service at {}, but cert_issuance.acme.terms_of_service_agreed is not set",
+                terms_of_service,
+            )));
+        }
+    }
+
+    let mut new_account = serde_json::json!({
+        "termsOfServiceAgreed": terms_of_service_agreed,
+    });
+    if !contact.is_empty() {
+        new_account["contact"] = contact.into();
+    }
+    if let Some(eab) = &eab {
+        new_account["externalAccountBinding"] =
+            external_account_binding(eab, &directory.new_account, account_key)?;
+    } else if directory.meta.external_account_required {
+        return Err(create_cert_error(
+            "ACME server requires external account binding, but cert_issuance.acme.eab is not configured",
+        ));
+    }
+    let response = client
+        .post(&directory.new_account, Some(&new_account), None)
+        .await?;
+    client.kid = Some(response.location()?);
+
+    // Create the order and answer the challenges of its authorizations.
+
+    let response = client
+        .post(
+            &directory.new_order,
+            Some(&serde_json::json!({ "identifiers": identifiers })),
+            None,
+        )
+        .await?;
+    let order_url = response.location()?;
+    let order: Order = response.json()?;
+
+    let mut pending_challenges = vec![];
+    for authorization_url in order.authorizations {
+        let Authorization {
+            status,
+            identifier,
+            challenges,
+        } = client.post(&authorization_url, None, None).await?.json()?;
+        match status {
+            Status::Valid => continue,
+            Status::Pending => (),
+            status => {
+                return Err(create_cert_error(format!(
+                    "authorization for identifier {:?} has unexpected status {:?}",
+                    identifier.value, status,
+                )))
+            }
+        }
+
+        let challenge_type = match challenge {
+            AcmeChallenge::Http01 { .. } => "http-01",
+            AcmeChallenge::TlsAlpn01 { .. } => "tls-alpn-01",
+        };
+        let authorization_challenge = challenges
+            .into_iter()
+            .find(|authorization_challenge| authorization_challenge.type_ == challenge_type)
+            .ok_or_else(|| {
+                create_cert_error(format!(
+                    "ACME server did not offer a {} challenge for identifier {:?}",
+                    challenge_type, identifier.value,
+                ))
+            })?;
+        let token = authorization_challenge.token.ok_or_else(|| {
+            create_cert_error(format!(
+                "{} challenge for identifier {:?} does not have a token",
+                challenge_type, identifier.value,
+            ))
+        })?;
+        let key_authorization = format!("{}.{}", token, account_key.thumbprint());
+
+        pending_challenges.push(PendingChallenge {
+            authorization_url,
+            challenge_url: authorization_challenge.url,
+            identifier,
+            token,
+            key_authorization,
+        });
+    }
+
+    if !pending_challenges.is_empty() {
+        let responder = Responder::start(challenge, &pending_challenges).await?;
+        let result = answer_challenges(&mut client, &pending_challenges).await;
+        responder.stop();
+        result?;
+    }
+
+    // Finalize the order and download the cert.
+
+    let order = poll_order(&mut client, &order_url, &[Status::Pending]).await?;
+    if order.status != Status::Ready {
+        return Err(order_error(&order));
+    }
+
+    let _ = client
+        .post(
+            &order.finalize,
+            Some(&serde_json::json!({ "csr": base64url(&csr) })),
+            None,
+        )
+        .await?;
+
+    let order = poll_order(
+        &mut client,
+        &order_url,
+        &[Status::Ready, Status::Processing],
+    )
+    .await?;
+    let certificate_url = match (order.status, &order.certificate) {
+        (Status::Valid, Some(certificate_url)) => certificate_url,
+        _ => return Err(order_error(&order)),
+    };
+
+    let response = client
+        .post(
+            certificate_url,
+            None,
+            Some("application/pem-certificate-chain"),
+        )
+        .await?;
+    Ok(response.body.to_vec())
+}
+
+/// Returns the identifiers of the cert to be issued for the given CSR.
+///
+/// These are the DNS names and IP addresses in the CSR's subjectAltName extension, or the CSR's common name if the CSR has no
+/// subjectAltName extension.
+fn identifiers(x509_req: &openssl::x509::X509ReqRef) -> Result<Vec<Identifier>, crate::Error> {
+    let mut identifiers = vec![];
+
+    // x509_req.extensions() returns an Err variant if no extensions are present in the req.
+    if let Ok(extensions) = x509_req.extensions() {
+        let subject_alt_names = unsafe {
+            openssl_sys::X509V3_get_d2i(
+                foreign_types_shared::ForeignType::as_ptr(&extensions),
+                openssl_sys::NID_subject_alt_name,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        if !subject_alt_names.is_null() {
+            let subject_alt_names: openssl::stack::Stack<openssl::x509::GeneralName> =
+                unsafe { foreign_types_shared::ForeignType::from_ptr(subject_alt_names.cast()) };
+
+            for subject_alt_name in &subject_alt_names {
+                if let Some(dns_name) = subject_alt_name.dnsname() {
+                    identifiers.push(Identifier::dns(dns_name));
+                } else if let Some(ip_address) = subject_alt_name.ipaddress() {
+                    let ip_address: std::net::IpAddr = match ip_address.len() {
+                        4 => {
+                            let mut octets = [0_u8; 4];
+                            octets.copy_from_slice(ip_address);
+                            octets.into()
+                        }
+                        16 => {
+                            let mut octets = [0_u8; 16];
+                            octets.copy_from_slice(ip_address);
+                            octets.into()
+                        }
+                        _ => {
+                            return Err(crate::Error::invalid_parameter(
+                                "csr",
+                                "CSR contains a malformed subjectAltName IP address",
+                            ))
+                        }
+                    };
+                    identifiers.push(Identifier::ip(ip_address));
+                }
+            }
+        }
+    }
+
+    if identifiers.is_empty() {
+        let common_name = x509_req
+            .subject_name()
+            .entries_by_nid(openssl::nid::Nid::COMMONNAME)
+            .next()
+            .map(|common_name| common_name.data().as_utf8())
+            .transpose()
+            .map_err(|err| crate::Error::invalid_parameter("csr", err))?;
+        if let Some(common_name) = common_name {
+            identifiers.push(match common_name.parse() {
+                Ok(ip_address) => Identifier::ip(ip_address),
+                Err(_) => Identifier::dns(&common_name),
+            });
+        }
+    }
+
+    if identifiers.is_empty() {
+        return Err(crate::Error::invalid_parameter(
+            "csr",
+            "CSR has neither a subjectAltName DNS name or IP address nor a common name",
+        ));
+    }
+
+    Ok(identifiers)
+}
+
+/// Returns the `externalAccountBinding` field of a newAccount request, as described in RFC 8555 section 7.3.4.
+fn external_account_binding(
+    eab: &AcmeEab,
+    url: &str,
+    account_key: &AccountKey,
+) -> Result<serde_json::Value, crate::Error> {
+    let hmac_key =
+        base64::decode_config(eab.hmac_key.trim_end_matches('='), base64::URL_SAFE_NO_PAD)
+            .map_err(|err| {
+                create_cert_error(format!(
+                    "cert_issuance.acme.eab.hmac_key is not valid base64url: {}",
+                    err
+                ))
+            })?;
+
+    let protected = serde_json::json!({
+        "alg": "HS256",
+        "kid": eab.key_id,
+        "url": url,
+    });
+    let protected = serde_json::to_vec(&protected).map_err(create_cert_error)?;
+    let protected = base64url(&protected);
+
+    let payload = base64url(account_key.jwk.as_bytes());
+
+    let hmac_key = openssl::pkey::PKey::hmac(&hmac_key).map_err(create_cert_error)?;
+    let mut signer = openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &hmac_key)
+        .map_err(create_cert_error)?;
+    signer
+        .update(format!("{}.{}", protected, payload).as_bytes())
+        .map_err(create_cert_error)?;
+    let signature = signer.sign_to_vec().map_err(create_cert_error)?;
+
+    Ok(serde_json::json!({
+        "protected": protected,
+        "payload": payload,
+        "signature": base64url(&signature),
+    }))
+}
+
+/// Triggers the validation of the given challenges and waits for their authorizations to become valid.
+async fn answer_challenges(
+    client: &mut Client<'_>,
+    pending_challenges: &[PendingChallenge],
+) -> Result<(), crate::Error> {
+    for pending_challenge in pending_challenges {
+        let _ = client
+            .post(
+                &pending_challenge.challenge_url,
+                Some(&serde_json::json!({})),
+                None,
+            )
+            .await?;
+    }
+
+    for pending_challenge in pending_challenges {
+        let mut attempts = 0;
+        loop {
+            let response = client
+                .post(&pending_challenge.authorization_url, None, None)
+                .await?;
+            let retry_after = response.retry_after();
+            let authorization: Authorization = response.json()?;
+
+            match authorization.status {
+                Status::Valid => break,
+
+                Status::Pending if attempts < POLL_ATTEMPTS => {
+                    attempts += 1;
+                    tokio::time::sleep(retry_after).await;
+                }
+
+                status => {
+                    let error = authorization
+                        .challenges
+                        .iter()
+                        .find_map(|challenge| challenge.error.as_ref());
+                    return Err(create_cert_error(match error {
+                        Some(error) => format!(
+                            "authorization for identifier {:?} failed: {}",
+                            pending_challenge.identifier.value, error,
+                        ),
+                        None => format!(
+                            "authorization for identifier {:?} has status {:?}",
+                            pending_challenge.identifier.value, status,
+                        ),
+                    }));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Polls the order with the given URL for as long as its status is one of `waiting`.
+async fn poll_order(
+    client: &mut Client<'_>,
+    url: &str,
+    waiting: &[Status],
+) -> Result<Order, crate::Error> {
+    let mut attempts = 0;
+    loop {
+        let response = client.post(url, None, None).await?;
+        let retry_after = response.retry_after();
+        let order: Order = response.json()?;
+
+        if !waiting.contains(&order.status) || attempts == POLL_ATTEMPTS {
+            return Ok(order);
+        }
+
+        attempts += 1;
+        tokio::time::sleep(retry_after).await;
+    }
+}
+
+fn order_error(order: &Order) -> crate::Error {
+    match &order.error {
+        Some(error) => create_cert_error(format!("order failed: {}", error)),
+        None => create_cert_error(format!("order has unexpected status {:?}", order.status)),
+    }
+}
+
+async fn get_directory(
+    client: &hyper::Client<
+        MaybeProxyConnector<hyper_openssl::HttpsConnector<hyper::client::HttpConnector>>,
+    >,
+    url: &url::Url,
+) -> Result<Directory, crate::Error> {
+    let request = hyper::Request::get(url.as_str())
+        .body(Default::default())
+        .map_err(create_cert_error)?;
+    let response = client.request(request).await.map_err(create_cert_error)?;
+    let response = Response::new(response).await?;
+    response.json()
+}
+
+struct Client<'a> {
+    inner: hyper::Client<
+        MaybeProxyConnector<hyper_openssl::HttpsConnector<hyper::client::HttpConnector>>,
+    >,
+    account_key: &'a AccountKey,
+    new_nonce_url: String,
+
+    /// The nonce from the most recent response, if it hasn't been used yet.
+    nonce: Option<String>,
+
+    /// The account URL, which identifies the account key in requests after newAccount.
+    kid: Option<String>,
+}
+
+impl Client<'_> {
+    /// Sends a JWS-signed POST request with the given payload, or a POST-as-GET request if `payload` is `None`.
+    ///
+    /// The request is retried once if the ACME server rejects its nonce.
+    async fn post(
+        &mut self,
+        url: &str,
+        payload: Option<&serde_json::Value>,
+        accept: Option<&str>,
+    ) -> Result<Response, crate::Error> {
+        let mut retried = false;
+        loop {
+            let nonce = match self.nonce.take() {
+                Some(nonce) => nonce,
+                None => self.new_nonce().await?,
+            };
+
+            let body = self
+                .account_key
+                .jws(url, &nonce, self.kid.as_deref(), payload)?;
+
+            let request = hyper::Request::post(url)
+                .header(hyper::header::CONTENT_TYPE, "application/jose+json");
+            let request = if let Some(accept) = accept {
+                request.header(hyper::header::ACCEPT, accept)
+            } else {
+                request
+            };
+            let request = request.body(body.into()).map_err(create_cert_error)?;
+
+            let response = self
+                .inner
+                .request(request)
+                .await
+                .map_err(create_cert_error)?;
+            self.nonce = replay_nonce(response.headers());
+
+            match Response::new(response).await {
+                Err(ResponseError::Problem(problem))
+                    if !retried && problem.type_ == "urn:ietf:params:acme:error:badNonce" =>
+                {
+                    retried = true;
+                }
+                response => return response.map_err(Into::into),
+            }
+        }
+    }
+
+    async fn new_nonce(&mut self) -> Result<String, crate::Error> {
+        let request = hyper::Request::head(&self.new_nonce_url)
+            .body(Default::default())
+            .map_err(create_cert_error)?;
+        let response = self
+            .inner
+            .request(request)
+            .await
+            .map_err(create_cert_error)?;
+        replay_nonce(response.headers()).ok_or_else(|| {
+            create_cert_error(format!(
+                "ACME server returned {} without a replay-nonce header for newNonce",
+                response.status(),
+            ))
+        })
+    }
+}
+
+fn replay_nonce(headers: &hyper::HeaderMap) -> Option<String> {
+    headers
+        .get("replay-nonce")
+        .and_then(|nonce| nonce.to_str().ok())
+        .map(ToOwned::to_owned)
+}
+
+struct Response {
+    headers: hyper::HeaderMap,
+    body: hyper::body::Bytes,
+}
+
+impl Response {
+    /// Reads the body of the given response, and converts an error status into an error.
+    async fn new(response: hyper::Response<hyper::Body>) -> Result<Self, ResponseError> {
+        let (
+            http::response::Parts {
+                status, headers, ..
+            },
+            body,
+        ) = response.into_parts();
+
+        let body = hyper::body::to_bytes(body)
+            .await
+            .map_err(|err| ResponseError::Other(create_cert_error(err)))?;
+
+        if !status.is_success() {
+            return Err(match serde_json::from_slice::<Problem>(&body) {
+                Ok(problem) => ResponseError::Problem(problem),
+                Err(_) => ResponseError::Other(create_cert_error(format!(
+                    "ACME server returned {}: {}",
+                    status,
+                    String::from_utf8_lossy(&body),
+                ))),
+            });
+        }
+
+        Ok(Response { headers, body })
+    }
+
+    fn json<T>(&self) -> Result<T, crate::Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        serde_json::from_slice(&self.body).map_err(create_cert_error)
+    }
+
+    fn location(&self) -> Result<String, crate::Error> {
+        self.headers
+            .get(hyper::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| create_cert_error("ACME server response is missing location header"))
+    }
+
+    /// Returns how long to wait before polling again, according to the `Retry-After` header.
+    ///
+    /// Only the delay-seconds form of the header is supported.
+    fn retry_after(&self) -> std::time::Duration {
+        self.headers
+            .get(hyper::header::RETRY_AFTER)
+            .and_then(|retry_after| retry_after.to_str().ok())
+            .and_then(|retry_after| retry_after.parse().ok())
+            .map_or(DEFAULT_POLL_INTERVAL, std::time::Duration::from_secs)
+            .min(MAX_POLL_INTERVAL)
+    }
+}
+
+enum ResponseError {
+    Problem(Problem),
+    Other(crate::Error),
+}
+
+impl From<ResponseError> for crate::Error {
+    fn from(err: ResponseError) -> Self {
+        match err {
+            ResponseError::Problem(problem) => {
+                create_cert_error(format!("ACME server returned an error: {}", problem))
+            }
+            ResponseError::Other(err) => err,
+        }
+    }
+}
+
+/// A temporary server that answers the ACME server's challenges until it's stopped.
+struct Responder(tokio::task::JoinHandle<()>);
+
+impl Responder {
+    async fn start(
+        challenge: AcmeChallenge,
+        pending_challenges: &[PendingChallenge],
+    ) -> Result<Self, crate::Error> {
+        let server = match challenge {
+            AcmeChallenge::Http01 { port } => {
+                let key_authorizations: std::sync::Arc<std::collections::BTreeMap<_, _>> =
+                    std::sync::Arc::new(
+                        pending_challenges
+                            .iter()
+                            .map(|pending_challenge| {
+                                (
+                                    pending_challenge.token.clone(),
+                                    pending_challenge.key_authorization.clone(),
+                                )
+                            })
+                            .collect(),
+                    );
+
+                let make_service = hyper::service::make_service_fn(move |_| {
+                    let key_authorizations = key_authorizations.clone();
+                    async move {
+                        Ok::<_, std::convert::Infallible>(hyper::service::service_fn(
+                            move |request| {
+                                let response = http01_response(&key_authorizations, &request);
+                                async move { Ok::<_, std::convert::Infallible>(response) }
+                            },
+                        ))
+                    }
+                });
+
+                let server =
+                    hyper::Server::try_bind(&(std::net::Ipv4Addr::UNSPECIFIED, port).into())
+                        .map_err(|err| {
+                            create_cert_error(format!(
+                                "could not bind http-01 responder to port {}: {}",
+                                port, err
+                            ))
+                        })?
+                        .serve(make_service);
+
+                tokio::spawn(async move {
+                    if let Err(err) = server.await {
+                        log::warn!("http-01 responder failed: {}", err);
+                    }
+                })
+            }
+
+            AcmeChallenge::TlsAlpn01 { port } => {
+                let mut contexts = std::collections::BTreeMap::new();
+                for pending_challenge in pending_challenges {
+                    let (server_name, context) = tls_alpn01_context(
+                        &pending_challenge.identifier,
+                        &pending_challenge.key_authorization,
+                    )?;
+                    let _ = contexts.insert(server_name, context);
+                }
+
+                let mut context =
+                    openssl::ssl::SslContext::builder(openssl::ssl::SslMethod::tls_server())
+                        .map_err(create_cert_error)?;
+                context.set_servername_callback(move |ssl, _| {
+                    let server_name = ssl
+                        .servername(openssl::ssl::NameType::HOST_NAME)
+                        .map(str::to_ascii_lowercase);
+                    let context = server_name
+                        .and_then(|server_name| contexts.get(&server_name))
+                        .ok_or(openssl::ssl::SniError::ALERT_FATAL)?;
+                    ssl.set_ssl_context(context)
+                        .map_err(|_| openssl::ssl::SniError::ALERT_FATAL)
+                });
+                let context = context.build();
+
+                let listener =
+                    tokio::net::TcpListener::bind((std::net::Ipv4Addr::UNSPECIFIED, port))
+                        .await
+                        .map_err(|err| {
+                            create_cert_error(format!(
+                                "could not bind tls-alpn-01 responder to port {}: {}",
+                                port, err
+                            ))
+                        })?;
+
+                tokio::spawn(async move {
+                    loop {
+                        let stream = match listener.accept().await {
+                            Ok((stream, _)) => stream,
+                            Err(err) => {
+                                log::warn!("tls-alpn-01 responder failed: {}", err);
+                                break;
+                            }
+                        };
+
+                        let ssl = openssl::ssl::Ssl::new(&context);
+                        tokio::spawn(async move {
+                            // The ACME server only needs the handshake, so the connection is closed right after it.
+                            if let Err(err) = tls_alpn01_handshake(ssl, stream).await {
+                                log::debug!("tls-alpn-01 handshake failed: {}", err);
+                            }
+                        });
+                    }
+                })
+            }
+        };
+
+        Ok(Responder(server))
+    }
+
+    fn stop(self) {
+        self.0.abort();
+    }
+}
+
+async fn tls_alpn01_handshake(
+    ssl: Result<openssl::ssl::Ssl, openssl::error::ErrorStack>,
+    stream: tokio::net::TcpStream,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut stream = tokio_openssl::SslStream::new(ssl?, stream)?;
+    std::pin::Pin::new(&mut stream).accept().await?;
+    Ok(())
+}
+
+fn http01_response(
+    key_authorizations: &std::collections::BTreeMap<String, String>,
+    request: &hyper::Request<hyper::Body>,
+) -> hyper::Response<hyper::Body> {
+    let mut response = hyper::Response::new(hyper::Body::empty());
+    if let Some(key_authorization) = http01_key_authorization(key_authorizations, request) {
+        let _ = response.headers_mut().insert(
+            hyper::header::CONTENT_TYPE,
+            hyper::header::HeaderValue::from_static("application/octet-stream"),
+        );
+        *response.body_mut() = key_authorization.to_owned().into();
+    } else {
+        *response.status_mut() = hyper::StatusCode::NOT_FOUND;
+    }
+    response
+}
+
+/// Returns the key authorization that the given http-01 request is for, if any.
+fn http01_key_authorization<'a>(
+    key_authorizations: &'a std::collections::BTreeMap<String, String>,
+    request: &hyper::Request<hyper::Body>,
+) -> Option<&'a str> {
+    if request.method() != hyper::Method::GET {
+        return None;
+    }
+
+    request
+        .uri()
+        .path()
+        .strip_prefix("/.well-known/acme-challenge/")
+        .and_then(|token| key_authorizations.get(token))
+        .map(String::as_str)
+}
+
+/// Returns the TLS server name that the ACME server validates the given identifier with, and the TLS context to answer it with.
+///
+/// The context has the `acme-tls/1` ALPN protocol and a self-signed cert for the identifier with the id-pe-acmeIdentifier extension,
+/// as described in RFC 8737 section 3.
+fn tls_alpn01_context(
+    identifier: &Identifier,
+    key_authorization: &str,
+) -> Result<(String, openssl::ssl::SslContext), crate::Error> {
+    let server_name = identifier.tls_alpn01_server_name();
+    let x509_and_private_key = tls_alpn01_cert(identifier, key_authorization);
+    let (x509, private_key) = x509_and_private_key.map_err(create_cert_error)?;
+
+    let mut context = openssl::ssl::SslContext::builder(openssl::ssl::SslMethod::tls_server())
+        .map_err(create_cert_error)?;
+    context
+        .set_min_proto_version(Some(openssl::ssl::SslVersion::TLS1_2))
+        .map_err(create_cert_error)?;
+    context.set_certificate(&x509).map_err(create_cert_error)?;
+    context
+        .set_private_key(&private_key)
+        .map_err(create_cert_error)?;
+    context.set_alpn_select_callback(|_, client_protocols| {
+        openssl::ssl::select_next_proto(ACME_TLS_ALPN_PROTOCOL, client_protocols)
+            .ok_or(openssl::ssl::AlpnError::ALERT_FATAL)
+    });
+
+    Ok((server_name, context.build()))
+}
+
+fn tls_alpn01_cert(
+    identifier: &Identifier,
+    key_authorization: &str,
+) -> Result<
+    (
+        openssl::x509::X509,
+        openssl::pkey::PKey<openssl::pkey::Private>,
+    ),
+    openssl::error::ErrorStack,
+> {
+    let ec_group = openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1)?;
+    let ec_key = openssl::ec::EcKey::generate(&ec_group)?;
+    let private_key = openssl::pkey::PKey::from_ec_key(ec_key)?;
+
+    let mut subject_name = openssl::x509::X509Name::builder()?;
+    subject_name.append_entry_by_text("CN", "ACME challenge")?;
+    let subject_name = subject_name.build();
+
+    let mut serial_number = openssl::bn::BigNum::new()?;
+    serial_number.rand(64, openssl::bn::MsbOption::MAYBE_ZERO, false)?;
+    let serial_number = serial_number.to_asn1_integer()?;
+
+    let mut x509 = openssl::x509::X509::builder()?;
+    x509.set_version(2)?;
+    x509.set_serial_number(&serial_number)?;
+    x509.set_subject_name(&subject_name)?;
+    x509.set_issuer_name(&subject_name)?;
+    x509.set_pubkey(&private_key)?;
+    x509.set_not_before(&*openssl::asn1::Asn1Time::days_from_now(0)?)?;
+    x509.set_not_after(&*openssl::asn1::Asn1Time::days_from_now(1)?)?;
+
+    let subject_alt_name = {
+        let mut subject_alt_name = openssl::x509::extension::SubjectAlternativeName::new();
+        if identifier.type_ == "ip" {
+            let _ = subject_alt_name.ip(&identifier.value);
+        } else {
+            let _ = subject_alt_name.dns(&identifier.value);
+        }
+        subject_alt_name.build(&x509.x509v3_context(None, None))?
+    };
+    x509.append_extension(subject_alt_name)?;
+
+    // The extension's value is an OCTET STRING of the SHA-256 digest of the key authorization.
+    let acme_identifier = openssl::x509::X509Extension::new(
+        None,
+        None,
+        ACME_IDENTIFIER_OID,
+        &format!(
+            "critical,DER:0420{}",
+            hex::encode(openssl::sha::sha256(key_authorization.as_bytes()))
+        ),
+    )?;
+    x509.append_extension(acme_identifier)?;
+
+    x509.sign(&private_key, openssl::hash::MessageDigest::sha256())?;
+
+    Ok((x509.build(), private_key))
+}
+
+/// Returns the type of the given public key, and the public key as a JWK in the canonical form of RFC 7638.
+fn jwk(
+    public_key: &openssl::pkey::PKeyRef<openssl::pkey::Public>,
+) -> Result<(AccountKeyKind, String), crate::Error> {
+    if let Ok(ec_key) = public_key.ec_key() {
+        if ec_key.group().curve_name() != Some(openssl::nid::Nid::X9_62_PRIME256V1) {
+            return Err(create_cert_error(
+                "ACME account key is an EC key pair that does not use the P-256 curve",
+            ));
+        }
+
+        let mut big_num_context = openssl::bn::BigNumContext::new().map_err(create_cert_error)?;
+        let mut x = openssl::bn::BigNum::new().map_err(create_cert_error)?;
+        let mut y = openssl::bn::BigNum::new().map_err(create_cert_error)?;
+        ec_key
+            .public_key()
+            .affine_coordinates_gfp(ec_key.group(), &mut x, &mut y, &mut big_num_context)
+            .map_err(create_cert_error)?;
+
+        let jwk = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            base64url(&pad(&x.to_vec(), 32)?),
+            base64url(&pad(&y.to_vec(), 32)?),
+        );
+        Ok((AccountKeyKind::EcP256, jwk))
+    } else if let Ok(rsa) = public_key.rsa() {
+        let jwk = format!(
+            r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#,
+            base64url(&rsa.e().to_vec()),
+            base64url(&rsa.n().to_vec()),
+        );
+        Ok((AccountKeyKind::Rsa, jwk))
+    } else {
+        Err(create_cert_error(
+            "ACME account key is neither an EC nor an RSA key pair",
+        ))
+    }
+}
+
+/// Left-pads the given big-endian integer with zeros to the given length.
+fn pad(value: &[u8], len: usize) -> Result<Vec<u8>, crate::Error> {
+    let padding_len = len
+        .checked_sub(value.len())
+        .ok_or_else(|| create_cert_error(format!("integer is longer than {} bytes", len)))?;
+    let mut result = vec![0; padding_len];
+    result.extend_from_slice(value);
+    Ok(result)
+}
+
+fn base64url(value: &[u8]) -> String {
+    base64::encode_config(value, base64::URL_SAFE_NO_PAD)
+}
+
+fn create_cert_error(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> crate::Error {
+    crate::Error::Internal(crate::InternalError::CreateCert(err.into()))
+}
+
+struct PendingChallenge {
+    authorization_url: String,
+    challenge_url: String,
+    identifier: Identifier,
+    token: String,
+    key_authorization: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+    #[serde(default)]
+    meta: DirectoryMeta,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DirectoryMeta {
+    #[serde(default)]
+    external_account_required: bool,
+
+    terms_of_service: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Pending,
+    Ready,
+    Processing,
+    Valid,
+    Invalid,
+    Deactivated,
+    Expired,
+    Revoked,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Order {
+    status: Status,
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+    error: Option<Problem>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Authorization {
+    status: Status,
+    identifier: Identifier,
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    type_: String,
+    url: String,
+    token: Option<String>,
+    error: Option<Problem>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct Identifier {
+    #[serde(rename = "type")]
+    type_: String,
+    value: String,
+}
+
+impl Identifier {
+    fn dns(name: &str) -> Self {
+        Identifier {
+            type_: "dns".to_owned(),
+            value: name.to_ascii_lowercase(),
+        }
+    }
+
+    /// Returns an IP address identifier, as described in RFC 8738.
+    fn ip(address: std::net::IpAddr) -> Self {
+        Identifier {
+            type_: "ip".to_owned(),
+            value: address.to_string(),
+        }
+    }
+
+    /// Returns the TLS server name that the ACME server sends when validating this identifier with tls-alpn-01.
+    ///
+    /// This is the DNS name itself, or the reverse DNS name of an IP address as described in RFC 8738 section 6.
+    fn tls_alpn01_server_name(&self) -> String {
+        match self.value.parse() {
+            Ok(std::net::IpAddr::V4(address)) if self.type_ == "ip" => {
+                let octets: Vec<_> = address
+                    .octets()
+                    .iter()
+                    .rev()
+                    .map(ToString::to_string)
+                    .collect();
+                format!("{}.in-addr.arpa", octets.join("."))
+            }
+
+            Ok(std::net::IpAddr::V6(address)) if self.type_ == "ip" => {
+                let nibbles: Vec<_> = address
+                    .octets()
+                    .iter()
+                    .rev()
+                    .map(|octet| format!("{:x}.{:x}", octet & 0x0f, octet >> 4))
+                    .collect();
+                format!("{}.ip6.arpa", nibbles.join("."))
+            }
+
+            _ => self.value.clone(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Problem {
+    #[serde(rename = "type", default)]
+    type_: String,
+    #[serde(default)]
+    detail: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.detail, self.type_)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn jwk_thumbprint() {
+        // Example from RFC 7638 section 3.1
+        let n = base64::decode_config("0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw", base64::URL_SAFE_NO_PAD).unwrap();
+        let e = base64::decode_config("AQAB", base64::URL_SAFE_NO_PAD).unwrap();
+        let rsa = openssl::rsa::Rsa::from_public_components(
+            openssl::bn::BigNum::from_slice(&n).unwrap(),
+            openssl::bn::BigNum::from_slice(&e).unwrap(),
+        )
+        .unwrap();
+        let public_key = openssl::pkey::PKey::from_rsa(rsa).unwrap();
+
+        let (_, jwk) = super::jwk(&public_key).unwrap();
+        assert_eq!(
+            super::base64url(&openssl::sha::sha256(jwk.as_bytes())),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs",
+        );
+    }
+
+    #[test]
+    fn identifiers() {
+        let ec_group =
+            openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1).unwrap();
+        let private_key =
+            openssl::pkey::PKey::from_ec_key(openssl::ec::EcKey::generate(&ec_group).unwrap())
+                .unwrap();
+
+        let csr = |common_name: &str, subject_alt_names: &[(&str, &str)]| {
+            let mut subject_name = openssl::x509::X509Name::builder().unwrap();
+            subject_name
+                .append_entry_by_text("CN", common_name)
+                .unwrap();
+            let subject_name = subject_name.build();
+
+            let mut x509_req = openssl::x509::X509Req::builder().unwrap();
+            x509_req.set_subject_name(&subject_name).unwrap();
+            x509_req.set_pubkey(&private_key).unwrap();
+
+            if !subject_alt_names.is_empty() {
+                let mut subject_alt_name = openssl::x509::extension::SubjectAlternativeName::new();
+                for &(type_, value) in subject_alt_names {
+                    if type_ == "ip" {
+                        let _ = subject_alt_name.ip(value);
+                    } else {
+                        let _ = subject_alt_name.dns(value);
+                    }
+                }
+                let subject_alt_name = subject_alt_name
+                    .build(&x509_req.x509v3_context(None))
+                    .unwrap();
+                let mut extensions = openssl::stack::Stack::new().unwrap();
+                extensions.push(subject_alt_name).unwrap();
+                x509_req.add_extensions(&extensions).unwrap();
+            }
+
+            x509_req
+                .sign(&private_key, openssl::hash::MessageDigest::sha256())
+                .unwrap();
+            x509_req.build()
+        };
+
+        let identifiers = |x509_req: &openssl::x509::X509Req| -> Vec<(String, String)> {
+            super::identifiers(x509_req)
+                .unwrap()
+                .into_iter()
+                .map(|identifier| (identifier.type_, identifier.value))
+                .collect()
+        };
+
+        assert_eq!(
+            identifiers(&csr(
+                "device",
+                &[
+                    ("dns", "Device.Example.com"),
+                    ("ip", "192.0.2.1"),
+                    ("ip", "2001:db8::1"),
+                ]
+            )),
+            vec![
+                ("dns".to_owned(), "device.example.com".to_owned()),
+                ("ip".to_owned(), "192.0.2.1".to_owned()),
+                ("ip".to_owned(), "2001:db8::1".to_owned()),
+            ],
+        );
+
+        assert_eq!(
+            identifiers(&csr("device.example.com", &[])),
+            vec![("dns".to_owned(), "device.example.com".to_owned())],
+        );
+
+        assert_eq!(
+            identifiers(&csr("192.0.2.1", &[])),
+            vec![("ip".to_owned(), "192.0.2.1".to_owned())],
+        );
+    }
+
+    #[test]
+    fn tls_alpn01_server_name() {
+        assert_eq!(
+            super::Identifier::dns("device.example.com").tls_alpn01_server_name(),
+            "device.example.com",
+        );
+
+        // Examples from RFC 8738 section 6
+        assert_eq!(
+            super::Identifier::ip("192.0.2.1".parse().unwrap()).tls_alpn01_server_name(),
+            "1.2.0.192.in-addr.arpa",
+        );
+        assert_eq!(
+            super::Identifier::ip("2001:db8::1".parse().unwrap()).tls_alpn01_server_name(),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa",
+        );
+    }
+
+    #[test]
+    fn tls_alpn01_cert() {
+        let identifier = super::Identifier::dns("device.example.com");
+        let key_authorization = "token.thumbprint";
+
+        let (x509, _) = super::tls_alpn01_cert(&identifier, key_authorization).unwrap();
+
+        let subject_alt_names = x509.subject_alt_names().unwrap();
+        let dns_names: Vec<_> = subject_alt_names
+            .iter()
+            .filter_map(openssl::x509::GeneralNameRef::dnsname)
+            .collect();
+        assert_eq!(dns_names, vec!["device.example.com"]);
+
+        // The id-pe-acmeIdentifier extension, marked critical, with an OCTET STRING of the key authorization's SHA-256 digest.
+        let mut expected_extension = vec![
+            0x30, 0x31, 0x06, 0x08, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x01, 0x1f, 0x01, 0x01,
+            0xff, 0x04, 0x22, 0x04, 0x20,
+        ];
+        expected_extension.extend_from_slice(&openssl::sha::sha256(key_authorization.as_bytes()));
+        let x509 = x509.to_der().unwrap();
+        assert!(x509
+            .windows(expected_extension.len())
+            .any(|window| window == &expected_extension[..]));
+    }
+
+    #[test]
+    fn http01_key_authorization() {
+        let key_authorizations = vec![("token".to_owned(), "token.thumbprint".to_owned())]
+            .into_iter()
+            .collect();
+
+        let request = |method, uri| {
+            hyper::Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(hyper::Body::empty())
+                .unwrap()
+        };
+
+        assert_eq!(
+            super::http01_key_authorization(
+                &key_authorizations,
+                &request(
+                    hyper::Method::GET,
+                    "http://device.example.com/.well-known/acme-challenge/token",
+                ),
+            ),
+            Some("token.thumbprint"),
+        );
+
+        assert_eq!(
+            super::http01_key_authorization(
+                &key_authorizations,
+                &request(
+                    hyper::Method::GET,
+                    "http://device.example.com/.well-known/acme-challenge/other-token",
+                ),
+            ),
+            None,
+        );
+
+        assert_eq!(
+            super::http01_key_authorization(
+                &key_authorizations,
+                &request(
+                    hyper::Method::POST,
+                    "http://device.example.com/.well-known/acme-challenge/token",
+                ),
+            ),
+            None,
+        );
+    }
+}
@@ -0,0 +1,350 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The CSR attributes that EST servers publish at `/csrattrs`, and validation of CSRs against them.
+
+use aziot_cert_common::CsrAttrs;
+
+const CHALLENGE_PASSWORD: &str = "1.2.840.113549.1.9.7";
+const EXTENSION_REQUEST: &str = "1.2.840.113549.1.9.14";
+
+/// OIDs of X.520 attribute types, which are the ones that make up subject names.
+const SUBJECT_ATTRIBUTE_PREFIX: &str = "2.5.4.";
+
+/// OIDs of X.509 certificate extensions.
+const EXTENSION_PREFIX: &str = "2.5.29.";
+
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+
+/// Parses the DER-encoded `CsrAttrs` structure of RFC 7030:
+///
+/// ```text
+/// CsrAttrs ::= SEQUENCE SIZE (0..MAX) OF AttrOrOID
+/// AttrOrOID ::= CHOICE { oid OBJECT IDENTIFIER, attribute Attribute }
+/// Attribute ::= SEQUENCE { type OBJECT IDENTIFIER, values SET SIZE(1..MAX) OF AttributeValue }
+/// ```
+///
+/// An empty input is treated as an empty `CsrAttrs`.
+pub(crate) fn parse(der: &[u8]) -> Result<CsrAttrs, ParseError> {
+    let mut result = CsrAttrs::default();
+
+    if der.is_empty() {
+        return Ok(result);
+    }
+
+    let mut reader = Reader(der);
+    let attr_or_oids = reader.read(TAG_SEQUENCE)?;
+    if !reader.is_empty() {
+        return Err(ParseError("trailing data after CsrAttrs"));
+    }
+
+    let mut attr_or_oids = Reader(attr_or_oids);
+    while !attr_or_oids.is_empty() {
+        let (tag, contents) = attr_or_oids.read_any()?;
+        match tag {
+            TAG_OID => add_oid(&mut result, decode_oid(contents)?),
+
+            TAG_SEQUENCE => {
+                let mut attribute = Reader(contents);
+                let attribute_type = decode_oid(attribute.read(TAG_OID)?)?;
+                let values = attribute.read(TAG_SET)?;
+
+                if attribute_type == EXTENSION_REQUEST {
+                    add_requested_extensions(&mut result, values)?;
+                } else {
+                    add_oid(&mut result, attribute_type);
+                }
+            }
+
+            _ => return Err(ParseError("AttrOrOID is neither an OID nor an Attribute")),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Checks that the given PEM-encoded CSR contains all the attributes that the EST server requires.
+pub(crate) fn validate(csr_attrs: &CsrAttrs, csr: &[u8]) -> Result<(), crate::Error> {
+    let csr = openssl::x509::X509Req::from_pem(csr)
+        .map_err(|err| crate::Error::invalid_parameter("csr", err))?;
+
+    let mut missing = vec![];
+
+    let subject_attributes: Vec<String> = csr
+        .subject_name()
+        .entries()
+        .map(|entry| oid_to_string(entry.object()))
+        .collect();
+    for oid in &csr_attrs.subject_attributes {
+        if !subject_attributes.contains(oid) {
+            missing.push(format!("subject attribute {}", oid));
+        }
+    }
+
+    // X509ReqRef::extensions fails if the CSR doesn't request any extensions.
+    let extensions: Vec<String> = csr
+        .extensions()
+        .map(|extensions| extensions.iter().map(extension_oid).collect())
+        .unwrap_or_default();
+    for oid in &csr_attrs.extensions {
+        if !extensions.contains(oid) {
+            missing.push(format!("extension {}", oid));
+        }
+    }
+
+    if csr_attrs.challenge_password {
+        let index = unsafe {
+            openssl_sys2::X509_REQ_get_attr_by_NID(
+                foreign_types_shared::ForeignTypeRef::as_ptr(&*csr),
+                openssl::nid::Nid::PKCS9_CHALLENGEPASSWORD.as_raw(),
+                -1,
+            )
+        };
+        if index < 0 {
+            missing.push("challengePassword attribute".to_owned());
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(crate::Error::invalid_parameter(
+            "csr",
+            format!(
+                "CSR does not contain attributes required by the EST server: {}",
+                missing.join(", "),
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+fn add_oid(result: &mut CsrAttrs, oid: String) {
+    let list = if oid == CHALLENGE_PASSWORD {
+        result.challenge_password = true;
+        return;
+    } else if oid.starts_with(SUBJECT_ATTRIBUTE_PREFIX) {
+        &mut result.subject_attributes
+    } else if oid.starts_with(EXTENSION_PREFIX) {
+        &mut result.extensions
+    } else {
+        &mut result.other_attributes
+    };
+
+    if !list.contains(&oid) {
+        list.push(oid);
+    }
+}
+
+/// Adds the extensions in the values of an extensionRequest attribute.
+///
+/// Each value is an `Extensions` structure, a single `Extension`, or the OID of an extension,
+/// depending on the server.
+fn add_requested_extensions(result: &mut CsrAttrs, values: &[u8]) -> Result<(), ParseError> {
+    let mut values = Reader(values);
+    while !values.is_empty() {
+        let (tag, value) = values.read_any()?;
+        match tag {
+            TAG_OID => add_extension(result, decode_oid(value)?),
+
+            TAG_SEQUENCE => {
+                let mut value = Reader(value);
+                let (tag, first) = value.read_any()?;
+                match tag {
+                    // Extension ::= SEQUENCE { extnID OBJECT IDENTIFIER, ... }
+                    TAG_OID => add_extension(result, decode_oid(first)?),
+
+                    // Extensions ::= SEQUENCE OF Extension
+                    TAG_SEQUENCE => {
+                        add_extension(result, decode_oid(Reader(first).read(TAG_OID)?)?);
+                        while !value.is_empty() {
+                            let extension = value.read(TAG_SEQUENCE)?;
+                            add_extension(result, decode_oid(Reader(extension).read(TAG_OID)?)?);
+                        }
+                    }
+
+                    _ => return Err(ParseError("extensionRequest value is malformed")),
+                }
+            }
+
+            _ => return Err(ParseError("extensionRequest value is malformed")),
+        }
+    }
+
+    Ok(())
+}
+
+fn add_extension(result: &mut CsrAttrs, oid: String) {
+    if !result.extensions.contains(&oid) {
+        result.extensions.push(oid);
+    }
+}
+
+/// Decodes the contents of a DER-encoded OBJECT IDENTIFIER into dotted-decimal form.
+fn decode_oid(contents: &[u8]) -> Result<String, ParseError> {
+    if contents.is_empty() || contents[contents.len() - 1] & 0x80 != 0 {
+        return Err(ParseError("OID is truncated"));
+    }
+
+    let mut subidentifiers = vec![];
+    let mut subidentifier: u64 = 0;
+    for &b in contents {
+        subidentifier = subidentifier
+            .checked_mul(0x80)
+            .ok_or(ParseError("OID component is too large"))?
+            | u64::from(b & 0x7f);
+        if b & 0x80 == 0 {
+            subidentifiers.push(subidentifier);
+            subidentifier = 0;
+        }
+    }
+
+    // The first subidentifier encodes the first two arcs.
+    let (first_arc, second_arc) = match subidentifiers[0] {
+        first @ 0..=39 => (0, first),
+        first @ 40..=79 => (1, first - 40),
+        first => (2, first - 80),
+    };
+
+    let arcs: Vec<String> = [first_arc, second_arc]
+        .iter()
+        .chain(&subidentifiers[1..])
+        .map(ToString::to_string)
+        .collect();
+    Ok(arcs.join("."))
+}
+
+/// Returns the dotted OID of the given extension.
+pub(crate) fn extension_oid(extension: &openssl::x509::X509ExtensionRef) -> String {
+    unsafe {
+        let extension = foreign_types_shared::ForeignTypeRef::as_ptr(extension);
+        let object = openssl_sys::X509_EXTENSION_get_object(extension);
+        oid_to_string(foreign_types_shared::ForeignTypeRef::from_ptr(object))
+    }
+}
+
+/// Returns the dotted form of the given OID.
+pub(crate) fn oid_to_string(object: &openssl::asn1::Asn1ObjectRef) -> String {
+    let mut buf = [0_u8; 128];
+    let len = unsafe {
+        openssl_sys::OBJ_obj2txt(
+            buf.as_mut_ptr().cast(),
+            std::convert::TryInto::try_into(buf.len()).expect("128 fits in c_int"),
+            foreign_types_shared::ForeignTypeRef::as_ptr(object),
+            1,
+        )
+    };
+    let len = std::convert::TryInto::<usize>::try_into(len)
+        .unwrap_or_default()
+        .min(buf.len() - 1);
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+/// Reads DER-encoded values with single-byte tags.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Reads the next value, which must have the given tag, and returns its contents.
+    fn read(&mut self, expected_tag: u8) -> Result<&'a [u8], ParseError> {
+        let (tag, contents) = self.read_any()?;
+        if tag != expected_tag {
+            return Err(ParseError("unexpected tag"));
+        }
+        Ok(contents)
+    }
+
+    /// Reads the next value, and returns its tag and contents.
+    fn read_any(&mut self) -> Result<(u8, &'a [u8]), ParseError> {
+        let (&tag, rest) = self
+            .0
+            .split_first()
+            .ok_or(ParseError("value is truncated"))?;
+        if tag & 0x1f == 0x1f {
+            return Err(ParseError("multi-byte tags are not supported"));
+        }
+
+        let (&len, mut rest) = rest.split_first().ok_or(ParseError("value is truncated"))?;
+        let len = if len & 0x80 == 0 {
+            usize::from(len)
+        } else {
+            let num_len_bytes = usize::from(len & 0x7f);
+            if num_len_bytes == 0 || num_len_bytes > std::mem::size_of::<u32>() {
+                return Err(ParseError("value length is invalid"));
+            }
+            if rest.len() < num_len_bytes {
+                return Err(ParseError("value is truncated"));
+            }
+
+            let (len_bytes, after_len) = rest.split_at(num_len_bytes);
+            rest = after_len;
+            len_bytes
+                .iter()
+                .fold(0_usize, |len, &b| (len << 8) | usize::from(b))
+        };
+
+        if rest.len() < len {
+            return Err(ParseError("value is truncated"));
+        }
+
+        let (contents, rest) = rest.split_at(len);
+        self.0 = rest;
+        Ok((tag, contents))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ParseError(&'static str);
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "malformed CsrAttrs: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse() {
+        // Example from RFC 7030 section 4.5.2
+        let der = base64::decode("MEEGCSqGSIb3DQEJBzASBgcqhkjOPQIBMQcGBSuBBAAiMBYGCSqGSIb3DQEJDjEJBgcrBgEBAQEWBggqhkjOPQQDAw==").unwrap();
+        let csr_attrs = super::parse(&der).unwrap();
+        assert_eq!(
+            csr_attrs,
+            aziot_cert_common::CsrAttrs {
+                subject_attributes: vec![],
+                extensions: vec!["1.3.6.1.1.1.1.22".to_owned()],
+                challenge_password: true,
+                other_attributes: vec![
+                    "1.2.840.10045.2.1".to_owned(),
+                    "1.2.840.10045.4.3.3".to_owned(),
+                ],
+            },
+        );
+
+        // Attributes with the subject attribute and extension OIDs that aziot-identityd's CSRs contain
+        let der = [
+            0x30, 0x24, 0x06, 0x03, 0x55, 0x04, 0x03, 0x30, 0x1d, 0x06, 0x09, 0x2a, 0x86, 0x48,
+            0x86, 0xf7, 0x0d, 0x01, 0x09, 0x0e, 0x31, 0x10, 0x30, 0x0e, 0x30, 0x05, 0x06, 0x03,
+            0x55, 0x1d, 0x13, 0x30, 0x05, 0x06, 0x03, 0x55, 0x1d, 0x25,
+        ];
+        let csr_attrs = super::parse(&der).unwrap();
+        assert_eq!(
+            csr_attrs,
+            aziot_cert_common::CsrAttrs {
+                subject_attributes: vec!["2.5.4.3".to_owned()],
+                extensions: vec!["2.5.29.19".to_owned(), "2.5.29.37".to_owned()],
+                challenge_password: false,
+                other_attributes: vec![],
+            },
+        );
+
+        assert_eq!(super::parse(&[]).unwrap(), Default::default());
+        assert!(super::parse(&[0x30, 0x05, 0x06, 0x03, 0x55]).is_err());
+    }
+}
@@ -49,9 +49,11 @@ impl std::error::Error for Error {
 pub enum InternalError {
     CreateCert(Box<dyn std::error::Error + Send + Sync>),
     DeleteFile(std::io::Error),
+    GetCsrAttrs(Box<dyn std::error::Error + Send + Sync>),
     GetPath(Box<dyn std::error::Error + Send + Sync>),
     InvalidProxyUri(Box<dyn std::error::Error + Send + Sync>),
     LoadKeyOpensslEngine(openssl2::Error),
+    Pkcs11(Box<dyn std::error::Error + Send + Sync>),
     ReadFile(std::io::Error),
 }
 
@@ -60,13 +62,17 @@ impl std::fmt::Display for InternalError {
         match self {
             InternalError::CreateCert(_) => f.write_str("could not create cert"),
             InternalError::DeleteFile(_) => f.write_str("could not delete cert file"),
+            InternalError::GetCsrAttrs(_) => {
+                f.write_str("could not get CSR attributes from EST server")
+            }
             InternalError::GetPath(_) => {
-                f.write_str("could not get file path corresponding to cert ID")
+                f.write_str("could not get location corresponding to cert ID")
             }
             InternalError::InvalidProxyUri(_) => f.write_str("invalid proxy uri"),
             InternalError::LoadKeyOpensslEngine(_) => {
                 f.write_str("could not load aziot-key-openssl-engine")
             }
+            InternalError::Pkcs11(_) => f.write_str("could not access cert in PKCS#11 token"),
             InternalError::ReadFile(_) => f.write_str("could not read cert file"),
         }
     }
@@ -78,9 +84,11 @@ impl std::error::Error for InternalError {
         match self {
             InternalError::CreateCert(err) => Some(&**err),
             InternalError::DeleteFile(err) => Some(err),
+            InternalError::GetCsrAttrs(err) => Some(&**err),
             InternalError::GetPath(err) => Some(&**err),
             InternalError::InvalidProxyUri(err) => Some(&**err),
             InternalError::LoadKeyOpensslEngine(err) => Some(err),
+            InternalError::Pkcs11(err) => Some(&**err),
             InternalError::ReadFile(err) => Some(err),
         }
     }
@@ -2,6 +2,11 @@
 
 use http_common::MaybeProxyConnector;
 
+/// Requests a cert for the given CSR from the EST server.
+///
+/// If `reenroll` is set, the cert is requested with `/simplereenroll` instead of `/simpleenroll`.
+/// RFC 7030 requires such requests to be authenticated with the cert being renewed, so `client_cert` should be
+/// the current cert and its private key in that case.
 pub(crate) async fn create_cert(
     csr: Vec<u8>,
     url: &url::Url,
@@ -9,74 +14,192 @@ pub(crate) async fn create_cert(
     client_cert: Option<(&[u8], &openssl::pkey::PKeyRef<openssl::pkey::Private>)>,
     trusted_certs: Vec<openssl::x509::X509>,
     proxy_uri: Option<hyper::Uri>,
+    reenroll: bool,
 ) -> Result<Vec<u8>, crate::Error> {
-    let proxy_connector = match client_cert {
-        Some((device_id_certs, device_id_private_key)) => MaybeProxyConnector::new(
-            proxy_uri,
-            Some((&device_id_private_key, &device_id_certs)),
-            &trusted_certs,
-        )
-        .map_err(|err| crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err))))?,
-        None => MaybeProxyConnector::new(proxy_uri, None, &[]).map_err(|err| {
-            crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err)))
-        })?,
-    };
-
-    let client: hyper::Client<_, hyper::Body> = hyper::Client::builder().build(proxy_connector);
-
-    let (simple_enroll_uri, ca_certs_uri) = {
-        let mut uri = url.to_string();
-        if !uri.ends_with('/') {
-            uri.push('/');
-        }
+    let client = Client::new(url, basic_auth, client_cert, &trusted_certs, proxy_uri)?;
 
-        let mut simple_enroll_uri = uri.clone();
-        simple_enroll_uri.push_str("simpleenroll");
+    let enroll_request = client.request(
+        if reenroll {
+            "simplereenroll"
+        } else {
+            "simpleenroll"
+        },
+        Some(csr),
+    );
+    let ca_certs_request = client.request("cacerts", None);
 
-        let mut ca_certs_uri = uri;
-        ca_certs_uri.push_str("cacerts");
+    let (enroll_response, ca_certs_response) = futures_util::future::join(
+        get_pkcs7_response(&client.inner, enroll_request),
+        get_pkcs7_response(&client.inner, ca_certs_request),
+    )
+    .await;
+    let enroll_response = enroll_response
+        .map_err(|err| crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err))))?;
+    let ca_certs_response = ca_certs_response
+        .map_err(|err| crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err))))?;
 
-        (simple_enroll_uri, ca_certs_uri)
-    };
+    let mut result = enroll_response;
+    result.extend_from_slice(&ca_certs_response);
 
-    let simple_enroll_request = hyper::Request::post(&simple_enroll_uri);
-    let ca_certs_request = hyper::Request::get(&ca_certs_uri);
-
-    let (simple_enroll_request, ca_certs_request) = if let Some((username, password)) = basic_auth {
-        let authorization_header_value = format!("{}:{}", username, password);
-        let authorization_header_value = base64::encode(authorization_header_value);
-        let authorization_header_value = format!("Basic {}", authorization_header_value);
-
-        let simple_enroll_request =
-            simple_enroll_request.header(hyper::header::AUTHORIZATION, &authorization_header_value);
-        let ca_certs_request =
-            ca_certs_request.header(hyper::header::AUTHORIZATION, authorization_header_value);
-        (simple_enroll_request, ca_certs_request)
-    } else {
-        (simple_enroll_request, ca_certs_request)
-    };
+    Ok(result)
+}
 
-    let simple_enroll_request = simple_enroll_request
-        .header(hyper::header::CONTENT_TYPE, "application/pkcs10")
-        .header("content-transfer-encoding", "base64")
-        .body(csr.into());
+/// Requests a cert and its private key from the EST server with `/serverkeygen`.
+///
+/// The EST server generates the key pair, so the key pair of the CSR is only used to sign the CSR.
+///
+/// Returns the PEM-encoded cert chain and the DER-encoded PKCS#8 private key.
+pub(crate) async fn create_cert_with_server_keygen(
+    csr: Vec<u8>,
+    url: &url::Url,
+    basic_auth: Option<(&str, &str)>,
+    client_cert: Option<(&[u8], &openssl::pkey::PKeyRef<openssl::pkey::Private>)>,
+    trusted_certs: Vec<openssl::x509::X509>,
+    proxy_uri: Option<hyper::Uri>,
+) -> Result<(Vec<u8>, Vec<u8>), crate::Error> {
+    let client = Client::new(url, basic_auth, client_cert, &trusted_certs, proxy_uri)?;
 
-    let ca_certs_request = ca_certs_request.body(Default::default());
+    let server_keygen_request = client.request("serverkeygen", Some(csr));
+    let ca_certs_request = client.request("cacerts", None);
 
-    let (simple_enroll_response, ca_certs_response) = futures_util::future::join(
-        get_pkcs7_response(&client, simple_enroll_request),
-        get_pkcs7_response(&client, ca_certs_request),
+    let (server_keygen_response, ca_certs_response) = futures_util::future::join(
+        get_response(&client.inner, server_keygen_request),
+        get_pkcs7_response(&client.inner, ca_certs_request),
     )
     .await;
-    let simple_enroll_response = simple_enroll_response
-        .map_err(|err| crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err))))?;
+    let (content_type, body) = server_keygen_response?;
+    let (mut result, private_key) = parse_server_keygen_response(&content_type, &body)?;
     let ca_certs_response = ca_certs_response
         .map_err(|err| crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err))))?;
 
-    let mut result = simple_enroll_response;
     result.extend_from_slice(&ca_certs_response);
 
-    Ok(result)
+    Ok((result, private_key))
+}
+
+/// Requests the CSR attributes that the EST server requires with `/csrattrs`.
+///
+/// Returns the DER-encoded `CsrAttrs` structure, which is empty if the server doesn't require any attributes.
+pub(crate) async fn get_csr_attrs(
+    url: &url::Url,
+    basic_auth: Option<(&str, &str)>,
+    client_cert: Option<(&[u8], &openssl::pkey::PKeyRef<openssl::pkey::Private>)>,
+    trusted_certs: Vec<openssl::x509::X509>,
+    proxy_uri: Option<hyper::Uri>,
+) -> Result<Vec<u8>, crate::Error> {
+    let client = Client::new(url, basic_auth, client_cert, &trusted_certs, proxy_uri)?;
+
+    let csr_attrs_request = client.request("csrattrs", None);
+
+    let (status, headers, body) = send(&client.inner, csr_attrs_request).await?;
+
+    // RFC 7030 and RFC 8951 allow servers that don't require any attributes to respond with 204 or 404.
+    if status == hyper::StatusCode::NO_CONTENT || status == hyper::StatusCode::NOT_FOUND {
+        return Ok(vec![]);
+    }
+
+    let content_type = check_response(status, &headers, &body)?;
+    if content_type != "application/csrattrs" && !content_type.starts_with("application/csrattrs;")
+    {
+        return Err(crate::Error::Internal(crate::InternalError::CreateCert(
+            format!(
+                "EST csrattrs response has unexpected content-type header: {}",
+                content_type
+            )
+            .into(),
+        )));
+    }
+
+    let body: Vec<u8> = body
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    let body = base64::decode(body)
+        .map_err(|err| crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err))))?;
+    Ok(body)
+}
+
+struct Client {
+    inner: hyper::Client<
+        MaybeProxyConnector<hyper_openssl::HttpsConnector<hyper::client::HttpConnector>>,
+    >,
+    base_uri: String,
+    authorization_header_value: Option<String>,
+}
+
+impl Client {
+    fn new(
+        url: &url::Url,
+        basic_auth: Option<(&str, &str)>,
+        client_cert: Option<(&[u8], &openssl::pkey::PKeyRef<openssl::pkey::Private>)>,
+        trusted_certs: &[openssl::x509::X509],
+        proxy_uri: Option<hyper::Uri>,
+    ) -> Result<Self, crate::Error> {
+        let proxy_connector = match client_cert {
+            Some((device_id_certs, device_id_private_key)) => MaybeProxyConnector::new(
+                proxy_uri,
+                Some((&device_id_private_key, &device_id_certs)),
+                trusted_certs,
+            )
+            .map_err(|err| {
+                crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err)))
+            })?,
+            None => MaybeProxyConnector::new(proxy_uri, None, &[]).map_err(|err| {
+                crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err)))
+            })?,
+        };
+
+        let inner = hyper::Client::builder().build(proxy_connector);
+
+        let mut base_uri = url.to_string();
+        if !base_uri.ends_with('/') {
+            base_uri.push('/');
+        }
+
+        let authorization_header_value = basic_auth.map(|(username, password)| {
+            let authorization_header_value = format!("{}:{}", username, password);
+            let authorization_header_value = base64::encode(authorization_header_value);
+            format!("Basic {}", authorization_header_value)
+        });
+
+        Ok(Client {
+            inner,
+            base_uri,
+            authorization_header_value,
+        })
+    }
+
+    /// Builds a request for the given EST operation. Requests with a CSR are `POST`s, and all others are `GET`s.
+    fn request(
+        &self,
+        operation: &str,
+        csr: Option<Vec<u8>>,
+    ) -> Result<hyper::Request<hyper::Body>, http::Error> {
+        let mut uri = self.base_uri.clone();
+        uri.push_str(operation);
+
+        let request = if csr.is_some() {
+            hyper::Request::post(uri)
+        } else {
+            hyper::Request::get(uri)
+        };
+
+        let request = if let Some(authorization_header_value) = &self.authorization_header_value {
+            request.header(hyper::header::AUTHORIZATION, authorization_header_value)
+        } else {
+            request
+        };
+
+        if let Some(csr) = csr {
+            request
+                .header(hyper::header::CONTENT_TYPE, "application/pkcs10")
+                .header("content-transfer-encoding", "base64")
+                .body(csr.into())
+        } else {
+            request.body(Default::default())
+        }
+    }
 }
 
 async fn get_pkcs7_response(
@@ -85,6 +208,39 @@ async fn get_pkcs7_response(
     >,
     request: Result<hyper::Request<hyper::Body>, http::Error>,
 ) -> Result<Vec<u8>, crate::Error> {
+    let (content_type, body) = get_response(client, request).await?;
+
+    if !is_pkcs7_content_type(&content_type) {
+        return Err(crate::Error::Internal(crate::InternalError::CreateCert(
+            format!(
+                "EST response has unexpected content-type header: {}",
+                content_type
+            )
+            .into(),
+        )));
+    }
+
+    parse_pkcs7(&body)
+}
+
+/// Sends the given request and returns the content type and body of the response, if it was successful.
+async fn get_response(
+    client: &hyper::Client<
+        MaybeProxyConnector<hyper_openssl::HttpsConnector<hyper::client::HttpConnector>>,
+    >,
+    request: Result<hyper::Request<hyper::Body>, http::Error>,
+) -> Result<(String, hyper::body::Bytes), crate::Error> {
+    let (status, headers, body) = send(client, request).await?;
+    let content_type = check_response(status, &headers, &body)?;
+    Ok((content_type, body))
+}
+
+async fn send(
+    client: &hyper::Client<
+        MaybeProxyConnector<hyper_openssl::HttpsConnector<hyper::client::HttpConnector>>,
+    >,
+    request: Result<hyper::Request<hyper::Body>, http::Error>,
+) -> Result<(hyper::StatusCode, hyper::HeaderMap, hyper::body::Bytes), crate::Error> {
     let request = request
         .map_err(|err| crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err))))?;
 
@@ -103,11 +259,21 @@ async fn get_pkcs7_response(
         .await
         .map_err(|err| crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err))))?;
 
+    Ok((status, headers, body))
+}
+
+/// Checks that the given response is successful, and returns its content type.
+fn check_response(
+    status: hyper::StatusCode,
+    headers: &hyper::HeaderMap,
+    body: &[u8],
+) -> Result<String, crate::Error> {
     if status != hyper::StatusCode::OK {
         return Err(crate::Error::Internal(crate::InternalError::CreateCert(
             format!(
                 "EST endpoint did not return successful response: {} {:?}",
-                status, body,
+                status,
+                hyper::body::Bytes::copy_from_slice(body),
             )
             .into(),
         )));
@@ -129,23 +295,25 @@ async fn get_pkcs7_response(
                 )
                 .into(),
             ))
-        })?;
-    if content_type != "application/pkcs7-mime"
-        && !content_type.starts_with("application/pkcs7-mime;")
-    {
-        return Err(crate::Error::Internal(crate::InternalError::CreateCert(
-            format!(
-                "EST response has unexpected content-type header: {}",
-                content_type
-            )
-            .into(),
-        )));
-    }
+        })?
+        .to_owned();
 
+    Ok(content_type)
+}
+
+fn is_pkcs7_content_type(content_type: &str) -> bool {
+    content_type == "application/pkcs7-mime" || content_type.starts_with("application/pkcs7-mime;")
+}
+
+/// Parses the base64-encoded PKCS#7 certs-only message of an EST response into a PEM-encoded cert chain.
+fn parse_pkcs7(body: &[u8]) -> Result<Vec<u8>, crate::Error> {
     // openssl::pkcs7::Pkcs7::from_pem requires the blob in PEM format, ie it must be wrapped in BEGIN/END PKCS7
     // but the EST server response does not contain this wrapper. Add it.
     let mut pkcs7 = b"-----BEGIN PKCS7-----\n"[..].to_owned();
-    pkcs7.extend_from_slice(&body);
+    pkcs7.extend_from_slice(body);
+    if !pkcs7.ends_with(b"\n") {
+        pkcs7.extend_from_slice(b"\n");
+    }
     pkcs7.extend_from_slice(b"-----END PKCS7-----\n");
 
     let pkcs7 = openssl::pkcs7::Pkcs7::from_pem(&pkcs7)
@@ -175,8 +343,338 @@ async fn get_pkcs7_response(
     Ok(result)
 }
 
+/// Parses the `multipart/mixed` response of `/serverkeygen` into the PEM-encoded cert chain and the DER-encoded PKCS#8 private key.
+///
+/// RFC 7030 also allows the server to encrypt the private key with `application/pkcs7-mime; smime-type=server-generated-key`.
+/// That is not supported.
+fn parse_server_keygen_response(
+    content_type: &str,
+    body: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), crate::Error> {
+    let boundary = content_type
+        .strip_prefix("multipart/mixed")
+        .and_then(|params| {
+            params.split(';').find_map(|param| {
+                let param = param.trim();
+                let boundary = param.strip_prefix("boundary=")?;
+                Some(boundary.trim_matches('"'))
+            })
+        })
+        .ok_or_else(|| {
+            crate::Error::Internal(crate::InternalError::CreateCert(
+                format!(
+                    "EST serverkeygen response has unexpected content-type header: {}",
+                    content_type
+                )
+                .into(),
+            ))
+        })?;
+
+    let mut x509 = None;
+    let mut private_key = None;
+
+    for (part_content_type, part_body) in multipart_parts(body, boundary) {
+        if part_content_type == "application/pkcs8"
+            || part_content_type.starts_with("application/pkcs8;")
+        {
+            let part_body: Vec<u8> = part_body
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            let part_body = base64::decode(part_body).map_err(|err| {
+                crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err)))
+            })?;
+            private_key = Some(part_body);
+        } else if is_pkcs7_content_type(&part_content_type)
+            && !part_content_type.contains("server-generated-key")
+        {
+            x509 = Some(parse_pkcs7(part_body)?);
+        } else {
+            return Err(crate::Error::Internal(crate::InternalError::CreateCert(
+                format!(
+                    "EST serverkeygen response has part with unsupported content-type: {}",
+                    part_content_type
+                )
+                .into(),
+            )));
+        }
+    }
+
+    let x509 = x509.ok_or_else(|| {
+        crate::Error::Internal(crate::InternalError::CreateCert(
+            "EST serverkeygen response does not contain the cert".into(),
+        ))
+    })?;
+    let private_key = private_key.ok_or_else(|| {
+        crate::Error::Internal(crate::InternalError::CreateCert(
+            "EST serverkeygen response does not contain the private key".into(),
+        ))
+    })?;
+
+    Ok((x509, private_key))
+}
+
+/// Splits a `multipart/mixed` body into the content types and bodies of its parts.
+fn multipart_parts<'a>(body: &'a [u8], boundary: &str) -> Vec<(String, &'a [u8])> {
+    let delimiter = format!("--{}", boundary);
+    let delimiter = delimiter.as_bytes();
+
+    let mut delimiter_positions = vec![];
+    let mut i = 0;
+    while i + delimiter.len() <= body.len() {
+        if &body[i..(i + delimiter.len())] == delimiter {
+            delimiter_positions.push(i);
+            i += delimiter.len();
+        } else {
+            i += 1;
+        }
+    }
+
+    let mut result = vec![];
+
+    for window in delimiter_positions.windows(2) {
+        let part = &body[(window[0] + delimiter.len())..window[1]];
+
+        // Each part starts with the line break that ends the delimiter line, followed by its headers,
+        // a blank line and its body. The line break before the next delimiter belongs to the delimiter.
+        let part = strip_line_break_prefix(part);
+        let part = strip_line_break_suffix(part);
+
+        let (headers, part_body) = if let Some(part_body) = part
+            .strip_prefix(b"\r\n")
+            .or_else(|| part.strip_prefix(b"\n"))
+        {
+            // A part without headers starts with the blank line right away.
+            (&[][..], part_body)
+        } else {
+            match find_subslice(part, b"\r\n\r\n") {
+                Some(end) => (&part[..end], &part[(end + 4)..]),
+                None => match find_subslice(part, b"\n\n") {
+                    Some(end) => (&part[..end], &part[(end + 2)..]),
+                    None => (&[][..], part),
+                },
+            }
+        };
+
+        let content_type = String::from_utf8_lossy(headers)
+            .lines()
+            .find_map(|header| {
+                let (name, value) = header.split_once(':')?;
+                if name.trim().eq_ignore_ascii_case("content-type") {
+                    Some(value.trim().to_owned())
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        result.push((content_type, part_body));
+    }
+
+    result
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn strip_line_break_prefix(s: &[u8]) -> &[u8] {
+    s.strip_prefix(b"\r\n")
+        .or_else(|| s.strip_prefix(b"\n"))
+        .unwrap_or(s)
+}
+
+fn strip_line_break_suffix(s: &[u8]) -> &[u8] {
+    s.strip_suffix(b"\r\n")
+        .or_else(|| s.strip_suffix(b"\n"))
+        .unwrap_or(s)
+}
+
 extern "C" {
     fn aziot_certd_pkcs7_to_x509(
         pkcs7: *const openssl_sys::PKCS7,
     ) -> *const openssl_sys::stack_st_X509;
 }
+
+#[cfg(test)]
+mod tests {
+    /// A PKCS#8 P-256 private key.
+    const PRIVATE_KEY: &str = "\
+MIGHAgEAMBMGByqGSM49AgEGCCqGSM49AwEHBG0wawIBAQQgzNmH3lw7/Q/VwRR6
+bKmXL898UIuhzMgUwqj37dhTulGhRANCAARczIZYVSFRlO640/te0KnVEw/uyBDy
+ZATdAxtl3N27RUuguww4H7jzHlDh9ZjsKKMWqTufmDJpqquu/81jLEv8";
+
+    /// A PKCS#7 certs-only message with a self-signed cert for `PRIVATE_KEY`.
+    const CERT: &str = "\
+MIIBqwYJKoZIhvcNAQcCoIIBnDCCAZgCAQExADALBgkqhkiG9w0BBwGgggGAMIIB
+fDCCASGgAwIBAgIUfP+K/BEexP8mvgV63QxTHSL+YTEwCgYIKoZIzj0EAwIwEzER
+MA8GA1UEAwwIZXN0LXRlc3QwHhcNMjYxMDE3MTI0ODIwWhcNMzYxMDE0MTI0ODIw
+WjATMREwDwYDVQQDDAhlc3QtdGVzdDBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IA
+BFzMhlhVIVGU7rjT+17QqdUTD+7IEPJkBN0DG2Xc3btFS6C7DDgfuPMeUOH1mOwo
+oxapO5+YMmmqq67/zWMsS/yjUzBRMB0GA1UdDgQWBBRI6+S5AuQDk+V5TCV3N09P
+0Ckw0jAfBgNVHSMEGDAWgBRI6+S5AuQDk+V5TCV3N09P0Ckw0jAPBgNVHRMBAf8E
+BTADAQH/MAoGCCqGSM49BAMCA0kAMEYCIQD4dyQqdZUSNinLV8DAwuI2uozy9eMy
+YM/bXmrWnPDfiAIhAIhvZGSzKV0Mgu6YmiJ588hSB58iTZrQR12DIwpAo+/JMQA=";
+
+    /// Builds a `multipart/mixed` body with the given parts, using the given line break.
+    fn multipart_body(boundary: &str, line_break: &str, parts: &[(&str, &str)]) -> Vec<u8> {
+        let mut body = format!("This is the preamble.{}", line_break);
+        for (content_type, part_body) in parts {
+            body.push_str(&format!("--{}{}", boundary, line_break));
+            body.push_str(&format!("Content-Type: {}{}", content_type, line_break));
+            body.push_str(&format!(
+                "Content-Transfer-Encoding: base64{}{}",
+                line_break, line_break
+            ));
+            body.push_str(&part_body.replace('\n', line_break));
+            body.push_str(line_break);
+        }
+        body.push_str(&format!(
+            "--{}--{}This is the epilogue.{}",
+            boundary, line_break, line_break
+        ));
+        body.into_bytes()
+    }
+
+    fn create_cert_error_message(err: &crate::Error) -> String {
+        match err {
+            crate::Error::Internal(crate::InternalError::CreateCert(err)) => err.to_string(),
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn multipart_parts() {
+        for &line_break in &["\r\n", "\n"] {
+            let body = multipart_body(
+                "boundary",
+                line_break,
+                &[("application/pkcs8", "first"), ("text/plain", "second")],
+            );
+            let parts = super::multipart_parts(&body, "boundary");
+            assert_eq!(
+                parts,
+                vec![
+                    ("application/pkcs8".to_owned(), &b"first"[..]),
+                    ("text/plain".to_owned(), &b"second"[..]),
+                ],
+                "line break {:?}",
+                line_break,
+            );
+        }
+
+        // A part without headers has no content type.
+        let parts = super::multipart_parts(b"--b\r\n\r\nbody\r\n--b--\r\n", "b");
+        assert_eq!(parts, vec![(String::new(), &b"body"[..])]);
+
+        assert!(super::multipart_parts(b"no delimiters", "b").is_empty());
+    }
+
+    #[test]
+    fn parse_server_keygen_response() {
+        let expected_private_key = base64::decode(PRIVATE_KEY.replace('\n', "")).unwrap();
+
+        for &line_break in &["\r\n", "\n"] {
+            for &(content_type, boundary) in &[
+                ("multipart/mixed; boundary=est", "est"),
+                ("multipart/mixed;boundary=\"est boundary\"", "est boundary"),
+                ("multipart/mixed; charset=utf-8; boundary=\"est\"", "est"),
+            ] {
+                let body = multipart_body(
+                    boundary,
+                    line_break,
+                    &[
+                        ("application/pkcs8", PRIVATE_KEY),
+                        ("application/pkcs7-mime; smime-type=certs-only", CERT),
+                    ],
+                );
+                let (x509, private_key) = super::parse_server_keygen_response(content_type, &body)
+                    .unwrap_or_else(|err| {
+                        panic!(
+                            "{:?} with line break {:?}: {}",
+                            content_type,
+                            line_break,
+                            create_cert_error_message(&err)
+                        )
+                    });
+                assert_eq!(private_key, expected_private_key);
+
+                let x509 = openssl::x509::X509::stack_from_pem(&x509).unwrap();
+                assert_eq!(x509.len(), 1);
+                let private_key = openssl::pkey::PKey::private_key_from_der(&private_key).unwrap();
+                assert!(x509[0].public_key().unwrap().public_eq(&private_key));
+            }
+        }
+    }
+
+    #[test]
+    fn parse_server_keygen_response_invalid() {
+        let content_type = "multipart/mixed; boundary=est";
+
+        let err = super::parse_server_keygen_response(
+            content_type,
+            &multipart_body("est", "\r\n", &[("application/pkcs8", PRIVATE_KEY)]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            create_cert_error_message(&err),
+            "EST serverkeygen response does not contain the cert"
+        );
+
+        let err = super::parse_server_keygen_response(
+            content_type,
+            &multipart_body("est", "\r\n", &[("application/pkcs7-mime", CERT)]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            create_cert_error_message(&err),
+            "EST serverkeygen response does not contain the private key"
+        );
+
+        // Private keys encrypted for the client are not supported.
+        let err = super::parse_server_keygen_response(
+            content_type,
+            &multipart_body(
+                "est",
+                "\r\n",
+                &[
+                    (
+                        "application/pkcs7-mime; smime-type=server-generated-key",
+                        CERT,
+                    ),
+                    ("application/pkcs7-mime; smime-type=certs-only", CERT),
+                ],
+            ),
+        )
+        .unwrap_err();
+        assert_eq!(
+            create_cert_error_message(&err),
+            "EST serverkeygen response has part with unsupported content-type: application/pkcs7-mime; smime-type=server-generated-key"
+        );
+
+        // The boundary is required.
+        let err = super::parse_server_keygen_response(
+            "multipart/mixed",
+            &multipart_body("est", "\r\n", &[("application/pkcs8", PRIVATE_KEY)]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            create_cert_error_message(&err),
+            "EST serverkeygen response has unexpected content-type header: multipart/mixed"
+        );
+
+        // A body with a different boundary than the content type's has no parts.
+        let err = super::parse_server_keygen_response(
+            content_type,
+            &multipart_body("other", "\r\n", &[("application/pkcs8", PRIVATE_KEY)]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            create_cert_error_message(&err),
+            "EST serverkeygen response does not contain the cert"
+        );
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 mod create;
+mod get_csr_attrs;
 mod get_or_import_or_delete;
 
 #[derive(Clone)]
@@ -13,6 +14,7 @@ http_common::make_service! {
     api_version: aziot_cert_common_http::ApiVersion,
     routes: [
         create::Route,
+        get_csr_attrs::Route,
         get_or_import_or_delete::Route,
     ],
 }
@@ -13,6 +13,10 @@
 
 use async_trait::async_trait;
 
+mod acme;
+
+mod csr_attrs;
+
 mod error;
 use error::{Error, InternalError};
 
@@ -20,9 +24,16 @@ mod est;
 
 mod http;
 
+mod policy;
+
+mod renewal;
+
+mod store;
+
+use aziot_cert_common::CsrAttrs;
 use aziot_certd_config::{
-    CertIssuance, CertIssuanceMethod, CertIssuanceOptions, Config, Endpoints, EstAuthBasic,
-    EstAuthX509, LocalCa, PreloadedCert, Principal,
+    CertIssuance, CertIssuanceMethod, CertIssuanceOptions, Config, Endpoints, EstAuth,
+    EstAuthBasic, EstAuthX509, LocalCa, Pkcs11, PreloadedCert, Principal,
 };
 
 use config_common::watcher::UpdateConfig;
@@ -36,6 +47,7 @@ pub async fn main(
         homedir_path,
         cert_issuance,
         preloaded_certs,
+        pkcs11,
         endpoints:
             Endpoints {
                 aziot_certd: connector,
@@ -64,17 +76,23 @@ pub async fn main(
             homedir_path,
             cert_issuance,
             preloaded_certs,
+            pkcs11,
             principals: principal_to_map(principal),
 
             key_client,
             key_engine,
             proxy_uri,
+
+            csr_attrs_cache: Default::default(),
+            renewal_failures: Default::default(),
         }
     };
     let api = std::sync::Arc::new(futures_util::lock::Mutex::new(api));
 
     config_common::watcher::start_watcher(config_path, config_directory_path, api.clone());
 
+    renewal::start(api.clone());
+
     let service = http::Service { api };
 
     Ok((connector, service))
@@ -84,11 +102,18 @@ struct Api {
     homedir_path: std::path::PathBuf,
     cert_issuance: CertIssuance,
     preloaded_certs: std::collections::BTreeMap<String, PreloadedCert>,
+    pkcs11: Option<Pkcs11>,
     principals: std::collections::BTreeMap<libc::uid_t, Vec<wildmatch::WildMatch>>,
 
     key_client: std::sync::Arc<aziot_key_client::Client>,
     key_engine: openssl2::FunctionalEngine,
     proxy_uri: Option<hyper::Uri>,
+
+    /// The CSR attributes of each EST server, and when they were retrieved.
+    csr_attrs_cache: std::collections::BTreeMap<url::Url, (std::time::Instant, CsrAttrs)>,
+
+    /// How often renewing each cert has failed in a row.
+    renewal_failures: std::collections::BTreeMap<String, renewal::Failures>,
 }
 
 impl Api {
@@ -99,14 +124,16 @@ impl Api {
         issuer: Option<(String, aziot_key_common::KeyHandle)>,
         user: libc::uid_t,
     ) -> Result<Vec<u8>, Error> {
-        let mut this = this.lock().await;
+        {
+            let this = this.lock().await;
 
-        if !this.authorize(user, &id) {
-            return Err(Error::Unauthorized(user, id));
+            if !this.authorize(user, &id) {
+                return Err(Error::Unauthorized(user, id));
+            }
         }
 
-        let x509 = create_cert(
-            &mut *this,
+        let x509 = create_cert_releasing_lock(
+            &this,
             &id,
             &csr,
             issuer
@@ -123,33 +150,48 @@ impl Api {
             return Err(Error::Unauthorized(user, id.to_string()));
         }
 
-        let path =
-            aziot_certd_config::util::get_path(&self.homedir_path, &self.preloaded_certs, id, true)
-                .map_err(|err| Error::Internal(InternalError::GetPath(err)))?;
-        std::fs::write(path, pem)
-            .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+        let location = get_location(
+            &self.homedir_path,
+            self.pkcs11.as_ref(),
+            &self.preloaded_certs,
+            id,
+        )?;
+        store::write(&location, pem)?;
         Ok(())
     }
 
     pub fn get_cert(&mut self, id: &str) -> Result<Vec<u8>, Error> {
-        let bytes = get_cert_inner(&self.homedir_path, &self.preloaded_certs, id)?
-            .ok_or_else(|| Error::invalid_parameter("id", "not found"))?;
+        let bytes = get_cert_inner(
+            &self.homedir_path,
+            self.pkcs11.as_ref(),
+            &self.preloaded_certs,
+            id,
+        )?
+        .ok_or_else(|| Error::invalid_parameter("id", "not found"))?;
         Ok(bytes)
     }
 
-    pub fn delete_cert(&mut self, id: &str, user: libc::uid_t) -> Result<(), Error> {
+    pub async fn get_csr_attrs(&mut self, id: &str, user: libc::uid_t) -> Result<CsrAttrs, Error> {
         if !self.authorize(user, id) {
             return Err(Error::Unauthorized(user, id.to_string()));
         }
 
-        let path =
-            aziot_certd_config::util::get_path(&self.homedir_path, &self.preloaded_certs, id, true)
-                .map_err(|err| Error::Internal(InternalError::GetPath(err)))?;
-        match std::fs::remove_file(path) {
-            Ok(()) => Ok(()),
-            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
-            Err(err) => Err(Error::Internal(InternalError::DeleteFile(err))),
+        let csr_attrs = get_csr_attrs(self, id).await?.unwrap_or_default();
+        Ok(csr_attrs)
+    }
+
+    pub fn delete_cert(&mut self, id: &str, user: libc::uid_t) -> Result<(), Error> {
+        if !self.authorize(user, id) {
+            return Err(Error::Unauthorized(user, id.to_string()));
         }
+
+        let location = get_location(
+            &self.homedir_path,
+            self.pkcs11.as_ref(),
+            &self.preloaded_certs,
+            id,
+        )?;
+        store::delete(&location)
     }
 
     fn authorize(&self, user: libc::uid_t, id: &str) -> bool {
@@ -181,42 +223,78 @@ impl UpdateConfig for Api {
             homedir_path: _,
             cert_issuance,
             preloaded_certs,
+            pkcs11,
             endpoints: _,
             principal,
         } = new_config;
         self.cert_issuance = cert_issuance;
         self.preloaded_certs = preloaded_certs;
+        self.pkcs11 = pkcs11;
         self.principals = principal_to_map(principal);
+        self.csr_attrs_cache.clear();
+        self.renewal_failures.clear();
 
         log::info!("Config update finished.");
         Ok(())
     }
 }
 
-fn load_inner(path: &std::path::Path) -> Result<Option<Vec<u8>>, Error> {
-    match std::fs::read(path) {
-        Ok(cert_bytes) => Ok(Some(cert_bytes)),
-        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
-        Err(err) => Err(Error::Internal(InternalError::ReadFile(err))),
+/// How long the CSR attributes of an EST server are cached for.
+const CSR_ATTRS_CACHE_DURATION: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+fn get_location(
+    homedir_path: &std::path::Path,
+    pkcs11: Option<&Pkcs11>,
+    preloaded_certs: &std::collections::BTreeMap<String, PreloadedCert>,
+    id: &str,
+) -> Result<aziot_certd_config::util::CertLocation, Error> {
+    aziot_certd_config::util::get_location(homedir_path, pkcs11, preloaded_certs, id, true)
+        .map_err(|err| Error::Internal(InternalError::GetPath(err)))
+}
+
+/// Returns the digest to sign certs and CSRs with for the given private key.
+///
+/// Ed25519 keys hash the message themselves and must not be given a digest.
+/// P-384 keys use SHA-384 to match the strength of the curve. All other keys use SHA-256.
+fn signing_digest(
+    private_key: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
+) -> openssl::hash::MessageDigest {
+    if private_key.id() == openssl::pkey::Id::ED25519 {
+        return openssl::hash::MessageDigest::null();
     }
+
+    if let Ok(ec_key) = private_key.ec_key() {
+        if ec_key.group().curve_name() == Some(openssl::nid::Nid::SECP384R1) {
+            return openssl::hash::MessageDigest::sha384();
+        }
+    }
+
+    openssl::hash::MessageDigest::sha256()
 }
 
-fn create_cert<'a>(
+/// Issues the cert with the given ID for the given CSR, and returns it without storing it.
+///
+/// Other certs that are needed to issue it, like an EST identity cert, are stored.
+fn issue_cert<'a>(
     api: &'a mut Api,
     id: &'a str,
     csr: &'a [u8],
     issuer: Option<(&'a str, &'a aziot_key_common::KeyHandle)>,
 ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<u8>, Error>> + Send + 'a>> {
-    // Creating a cert is recursive in some cases. An async fn cannot recurse because its RPIT Future type would end up being infinitely sized,
+    // Issuing a cert is recursive in some cases. An async fn cannot recurse because its RPIT Future type would end up being infinitely sized,
     // so it needs to be boxed. So we have a non-async fn returning a boxed future, where the future being boxed is the result of an inner asyn fn,
     // and the recursive call is for the outer boxed-future-returning fn.
 
-    async fn create_cert_inner(
+    async fn issue_cert_inner(
         api: &mut Api,
         id: &str,
         csr: &[u8],
         issuer: Option<(&str, &aziot_key_common::KeyHandle)>,
     ) -> Result<Vec<u8>, Error> {
+        if issuer.is_none() {
+            validate_csr_attrs(api, id, csr).await?;
+        }
+
         // Look up issuance options for this certificate ID.
         let cert_options = api.cert_issuance.certs.get(id);
 
@@ -243,6 +321,10 @@ fn create_cert<'a>(
             let mut subject_name = x509_req.subject_name();
             let version = x509_req.version();
             let common_name;
+            let policy = cert_options
+                .and_then(|options| options.policy.as_ref())
+                .or(api.cert_issuance.default_policy.as_ref());
+            let policy_subject_name;
 
             if let Some(options) = cert_options {
                 if let Some(d) = options.expiry_days {
@@ -260,6 +342,29 @@ fn create_cert<'a>(
                     subject_name = &common_name;
                 }
             }
+
+            if let Some(policy) = policy {
+                if let Some(max_expiry_days) = policy.max_expiry_days {
+                    expiry_days = expiry_days.min(max_expiry_days);
+                }
+
+                if let Some(template) = &policy.subject {
+                    let common_name = subject_name
+                        .entries_by_nid(openssl::nid::Nid::COMMONNAME)
+                        .next()
+                        .map(|common_name| {
+                            common_name
+                                .data()
+                                .as_utf8()
+                                .map(|common_name| common_name.to_string())
+                        })
+                        .transpose()
+                        .map_err(|err| Error::invalid_parameter("csr", err))?;
+                    policy_subject_name =
+                        policy::subject_name(template, id, common_name.as_deref())?;
+                    subject_name = &policy_subject_name;
+                }
+            }
             let not_after = openssl::asn1::Asn1Time::days_from_now(expiry_days)
                 .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
 
@@ -278,13 +383,15 @@ fn create_cert<'a>(
             )
             .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
 
-            // Copy extensions from x509_req to the new cert.
-            let req_extensions = x509_req.extensions();
-
-            // x509_req.extensions() returns an Err variant if no extensions are present in the req.
-            // Ignore this Err and only copy extensions if provided in the req.
-            if let Ok(req_extensions) = req_extensions {
-                for extension in req_extensions {
+            if let Some(policy) = policy {
+                // Copy the extensions that the policy allows from x509_req to the new cert, and add the ones it forces.
+                for extension in policy::extensions(policy, id, &x509_req)? {
+                    x509.append_extension(extension)
+                        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+                }
+            } else {
+                // Copy extensions from x509_req to the new cert.
+                for extension in policy::extensions_without_policy(id, &x509_req)? {
                     x509.append_extension(extension)
                         .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
                 }
@@ -296,6 +403,10 @@ fn create_cert<'a>(
                 .key_engine
                 .load_private_key(&issuer_private_key)
                 .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+            let digest = match policy.and_then(|policy| policy.digest) {
+                Some(digest) => policy::digest(digest, id, &issuer_private_key)?,
+                None => signing_digest(&issuer_private_key),
+            };
 
             let x509 = if issuer_id == id {
                 // Issuer is the same as the cert being created, which means the caller wants the cert to be self-signed.
@@ -306,7 +417,7 @@ fn create_cert<'a>(
                 x509.set_issuer_name(subject_name)
                     .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
 
-                x509.sign(&issuer_private_key, openssl::hash::MessageDigest::sha256())
+                x509.sign(&issuer_private_key, digest)
                     .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
 
                 let x509 = x509.build();
@@ -318,14 +429,13 @@ fn create_cert<'a>(
             } else {
                 // Load the issuer and use it to sign the CSR.
 
-                let issuer_path = aziot_certd_config::util::get_path(
+                let issuer_location = get_location(
                     &api.homedir_path,
+                    api.pkcs11.as_ref(),
                     &api.preloaded_certs,
                     issuer_id,
-                    true,
-                )
-                .map_err(|err| Error::Internal(InternalError::GetPath(err)))?;
-                let issuer_x509_pem = load_inner(&issuer_path)
+                )?;
+                let issuer_x509_pem = store::read(&issuer_location)
                     .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?
                     .ok_or_else(|| Error::invalid_parameter("issuer.certId", "not found"))?;
                 let issuer_x509 = openssl::x509::X509::stack_from_pem(&issuer_x509_pem)
@@ -347,7 +457,7 @@ fn create_cert<'a>(
                 x509.set_not_after(not_after)
                     .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
 
-                x509.sign(&issuer_private_key, openssl::hash::MessageDigest::sha256())
+                x509.sign(&issuer_private_key, digest)
                     .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
 
                 let x509 = x509.build();
@@ -360,16 +470,6 @@ fn create_cert<'a>(
                 x509
             };
 
-            let path = aziot_certd_config::util::get_path(
-                &api.homedir_path,
-                &api.preloaded_certs,
-                id,
-                true,
-            )
-            .map_err(|err| Error::Internal(InternalError::GetPath(err)))?;
-            std::fs::write(path, &x509)
-                .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
-
             Ok(x509)
         } else {
             // Issuer is not explicitly specified, so use the issuance options for this cert from the configuration.
@@ -382,60 +482,54 @@ fn create_cert<'a>(
                 CertIssuanceMethod::Est {
                     url: cert_url,
                     auth: cert_auth,
+                    reenroll,
+                    server_keygen,
                 } => {
-                    let defaults = api.cert_issuance.est.as_ref();
-
-                    let auth = cert_auth.as_ref().or_else(|| {
-                        defaults.map(|default| &default.auth)
-                    }).ok_or_else(|| {
-                        Error::Internal(InternalError::CreateCert(
-                            format!(
-                                "cert {:?} is configured to be issued by EST, but EST auth is not configured",
-                                id,
-                            )
-                            .into(),
-                        ))
-                    })?;
-
-                    let url = cert_url.as_ref().or_else(|| {
-                        defaults
-                            .map(|default| &default.urls)
-                            .and_then(|urls| urls.get(id).or_else(|| urls.get("default")))
-                    }).ok_or_else(|| {
-                        Error::Internal(InternalError::CreateCert(
-                            format!(
-                                "cert {:?} is configured to be issued by EST, but EST URL is not configured",
-                                id,
-                            )
-                            .into(),
-                        ))
-                    })?;
+                    let (url, auth) = est_url_and_auth(
+                        &api.cert_issuance,
+                        id,
+                        cert_url.as_ref(),
+                        cert_auth.as_ref(),
+                    )?;
 
                     let auth_basic = auth
                         .basic
                         .as_ref()
                         .map(|EstAuthBasic { username, password }| (&**username, &**password));
 
-                    let mut trusted_certs_x509 = vec![];
-
-                    if let Some(default) = defaults {
-                        for trusted_cert in &default.trusted_certs {
-                            let pem =
-                                get_cert_inner(&api.homedir_path, &api.preloaded_certs, trusted_cert)?
-                                    .ok_or_else(|| {
-                                        Error::Internal(InternalError::CreateCert(
-                                            format!(
-                                        "cert_issuance.est.trusted_certs contains unreadable cert {:?}",
-                                        trusted_cert,
-                                    )
-                                            .into(),
-                                        ))
-                                    })?;
-                            let x509 =
-                                openssl::x509::X509::stack_from_pem(&pem).map_err(|err| {
-                                    Error::Internal(InternalError::CreateCert(Box::new(err)))
-                                })?;
-                            trusted_certs_x509.extend(x509);
+                    let trusted_certs_x509 = est_trusted_certs(api)?;
+
+                    if *reenroll && !*server_keygen {
+                        // Renew the current cert with /simplereenroll, authenticated with the current cert itself.
+                        let current_cert = get_cert_inner(
+                            &api.homedir_path,
+                            api.pkcs11.as_ref(),
+                            &api.preloaded_certs,
+                            id,
+                        )?;
+                        let key_id = key_id(api, id);
+                        let current_private_key = get_reenroll_private_key(
+                            id,
+                            current_cert.as_deref(),
+                            &key_id,
+                            &api.key_client,
+                            &mut api.key_engine,
+                        )?;
+                        if let (Some(current_cert), Some(current_private_key)) =
+                            (current_cert, current_private_key)
+                        {
+                            let x509 = est::create_cert(
+                                csr.to_owned(),
+                                url,
+                                auth_basic,
+                                Some((&current_cert, &current_private_key)),
+                                trusted_certs_x509,
+                                api.proxy_uri.clone(),
+                                true,
+                            )
+                            .await?;
+
+                            return Ok(x509);
                         }
                     }
 
@@ -450,6 +544,7 @@ fn create_cert<'a>(
 
                         let identity = match get_cert_inner(
                             &api.homedir_path,
+                            api.pkcs11.as_ref(),
                             &api.preloaded_certs,
                             identity_cert,
                         ) {
@@ -487,6 +582,20 @@ fn create_cert<'a>(
                                         Error::Internal(InternalError::CreateCert(Box::new(err)))
                                     })?;
 
+                                if *server_keygen {
+                                    let (x509, private_key) = est::create_cert_with_server_keygen(
+                                        csr.to_owned(),
+                                        url,
+                                        auth_basic,
+                                        Some((&identity_cert, &identity_private_key)),
+                                        trusted_certs_x509,
+                                        api.proxy_uri.clone(),
+                                    )
+                                    .await?;
+                                    import_server_generated_key(api, id, &x509, &private_key)?;
+                                    return Ok(x509);
+                                }
+
                                 let x509 = est::create_cert(
                                     csr.to_owned(),
                                     url,
@@ -494,20 +603,10 @@ fn create_cert<'a>(
                                     Some((&identity_cert, &identity_private_key)),
                                     trusted_certs_x509,
                                     api.proxy_uri.clone(),
+                                    false,
                                 )
                                 .await?;
 
-                                let path = aziot_certd_config::util::get_path(
-                                    &api.homedir_path,
-                                    &api.preloaded_certs,
-                                    id,
-                                    true,
-                                )
-                                .map_err(|err| Error::Internal(InternalError::GetPath(err)))?;
-                                std::fs::write(path, &x509).map_err(|err| {
-                                    Error::Internal(InternalError::CreateCert(Box::new(err)))
-                                })?;
-
                                 Ok(x509)
                             }
 
@@ -518,7 +617,7 @@ fn create_cert<'a>(
                                     bootstrap_identity_private_key,
                                 )) = bootstrap_identity
                                 {
-                                    match get_cert_inner(&api.homedir_path, &api.preloaded_certs, bootstrap_identity_cert) {
+                                    match get_cert_inner(&api.homedir_path, api.pkcs11.as_ref(), &api.preloaded_certs, bootstrap_identity_cert) {
                                         Ok(Some(bootstrap_identity_cert)) => match api.key_client.load_key_pair(bootstrap_identity_private_key) {
                                             Ok(bootstrap_identity_private_key) => Ok((bootstrap_identity_cert, bootstrap_identity_private_key)),
                                             Err(err) => Err(format!("could not get EST bootstrap identity cert private key: {}", err)),
@@ -664,7 +763,7 @@ fn create_cert<'a>(
                                         identity_csr
                                             .sign(
                                                 &identity_private_key,
-                                                openssl::hash::MessageDigest::sha256(),
+                                                signing_digest(&identity_private_key),
                                             )
                                             .map_err(|err| {
                                                 Error::Internal(InternalError::CreateCert(
@@ -712,27 +811,21 @@ fn create_cert<'a>(
                                             )),
                                             trusted_certs_x509,
                                             api.proxy_uri.clone(),
+                                            false,
                                         )
                                         .await?;
 
-                                        let path = aziot_certd_config::util::get_path(
+                                        let location = get_location(
                                             &api.homedir_path,
+                                            api.pkcs11.as_ref(),
                                             &api.preloaded_certs,
                                             identity_cert,
-                                            true,
-                                        )
-                                        .map_err(|err| {
-                                            Error::Internal(InternalError::GetPath(err))
-                                        })?;
-                                        std::fs::write(path, &x509).map_err(|err| {
-                                            Error::Internal(InternalError::CreateCert(Box::new(
-                                                err,
-                                            )))
-                                        })?;
+                                        )?;
+                                        store::write(&location, &x509)?;
 
                                         // EST identity cert was obtained and persisted successfully. Now recurse to retry the original cert request.
 
-                                        let x509 = create_cert(api, id, csr, issuer).await?;
+                                        let x509 = issue_cert(api, id, csr, issuer).await?;
                                         Ok(x509)
                                     }
 
@@ -752,6 +845,20 @@ fn create_cert<'a>(
                     } else {
                         // We need to only use basic auth with the EST server.
 
+                        if *server_keygen {
+                            let (x509, private_key) = est::create_cert_with_server_keygen(
+                                csr.to_owned(),
+                                url,
+                                auth_basic,
+                                None,
+                                trusted_certs_x509,
+                                api.proxy_uri.clone(),
+                            )
+                            .await?;
+                            import_server_generated_key(api, id, &x509, &private_key)?;
+                            return Ok(x509);
+                        }
+
                         let x509 = est::create_cert(
                             csr.to_owned(),
                             url,
@@ -759,24 +866,22 @@ fn create_cert<'a>(
                             None,
                             trusted_certs_x509,
                             api.proxy_uri.clone(),
+                            false,
                         )
                         .await?;
 
-                        let path = aziot_certd_config::util::get_path(
-                            &api.homedir_path,
-                            &api.preloaded_certs,
-                            id,
-                            true,
-                        )
-                        .map_err(|err| Error::Internal(InternalError::GetPath(err)))?;
-                        std::fs::write(path, &x509).map_err(|err| {
-                            Error::Internal(InternalError::CreateCert(Box::new(err)))
-                        })?;
-
                         Ok(x509)
                     }
                 }
 
+                CertIssuanceMethod::Acme { directory_url } => {
+                    let directory_url = directory_url.clone();
+                    let request = acme_cert_request(api, id, csr, directory_url.as_ref())?;
+                    let x509 = acme::create_cert(request).await?;
+
+                    Ok(x509)
+                }
+
                 CertIssuanceMethod::LocalCa => {
                     // Indirect reference to the local CA. Look it up.
 
@@ -802,8 +907,8 @@ fn create_cert<'a>(
 
                     // Recurse with the local CA set explicitly as the issuer parameter.
 
-                    let x509 = create_cert(api, id, csr, Some((&issuer_cert, &issuer_private_key)))
-                        .await?;
+                    let x509 =
+                        issue_cert(api, id, csr, Some((&issuer_cert, &issuer_private_key))).await?;
                     Ok(x509)
                 }
 
@@ -817,33 +922,125 @@ fn create_cert<'a>(
                         .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
 
                     // Recurse with explicit issuer.
-                    let x509 = create_cert(api, id, csr, Some((id, &key_pair_handle))).await?;
+                    let x509 = issue_cert(api, id, csr, Some((id, &key_pair_handle))).await?;
                     Ok(x509)
                 }
             }
         }
     }
 
-    Box::pin(create_cert_inner(api, id, csr, issuer))
+    Box::pin(issue_cert_inner(api, id, csr, issuer))
+}
+
+/// Issues the cert with the given ID like [`issue_cert`], and then stores it.
+async fn create_cert_releasing_lock(
+    api: &futures_util::lock::Mutex<Api>,
+    id: &str,
+    csr: &[u8],
+    issuer: Option<(&str, &aziot_key_common::KeyHandle)>,
+) -> Result<Vec<u8>, Error> {
+    let x509 = issue_cert_releasing_lock(api, id, csr, issuer).await?;
+
+    let api = api.lock().await;
+    let location = get_location(
+        &api.homedir_path,
+        api.pkcs11.as_ref(),
+        &api.preloaded_certs,
+        id,
+    )?;
+    store::write(&location, &x509)?;
+
+    Ok(x509)
+}
+
+/// Issues the cert with the given ID like [`issue_cert`], but with `api` only locked while it's used.
+///
+/// Requesting a cert from an ACME server involves waiting for the server to validate the challenges, which can take
+/// several minutes, so the lock is released for that. Other issuance methods keep `api` locked throughout.
+async fn issue_cert_releasing_lock(
+    api: &futures_util::lock::Mutex<Api>,
+    id: &str,
+    csr: &[u8],
+    issuer: Option<(&str, &aziot_key_common::KeyHandle)>,
+) -> Result<Vec<u8>, Error> {
+    let request = {
+        let mut api = api.lock().await;
+
+        let directory_url = match (
+            issuer,
+            api.cert_issuance
+                .certs
+                .get(id)
+                .map(|options| &options.method),
+        ) {
+            (None, Some(CertIssuanceMethod::Acme { directory_url })) => directory_url.clone(),
+            _ => return issue_cert(&mut api, id, csr, issuer).await,
+        };
+
+        acme_cert_request(&mut api, id, csr, directory_url.as_ref())?
+    };
+
+    let x509 = acme::create_cert(request).await?;
+    Ok(x509)
+}
+
+/// Builds the request for the cert with the given ID from the ACME server in `[cert_issuance.acme]`,
+/// or the one at `directory_url` if the cert's issuance options override it.
+fn acme_cert_request(
+    api: &mut Api,
+    id: &str,
+    csr: &[u8],
+    directory_url: Option<&url::Url>,
+) -> Result<acme::CertRequest, Error> {
+    let acme = api.cert_issuance.acme.as_ref().ok_or_else(|| {
+        Error::Internal(InternalError::CreateCert(
+            format!(
+                "cert {:?} is configured to be issued by ACME, but ACME is not configured",
+                id,
+            )
+            .into(),
+        ))
+    })?;
+
+    let trusted_certs =
+        load_trusted_certs(api, "cert_issuance.acme.trusted_certs", &acme.trusted_certs)?;
+
+    let account_key = acme::AccountKey::load(
+        api.key_client.clone(),
+        &mut api.key_engine,
+        &acme.account_key,
+    )?;
+
+    Ok(acme::CertRequest {
+        csr: csr.to_owned(),
+        directory_url: directory_url.unwrap_or(&acme.directory_url).clone(),
+        account_key,
+        contact: acme.contact.clone(),
+        eab: acme.eab.clone(),
+        challenge: acme.challenge,
+        terms_of_service_agreed: acme.terms_of_service_agreed,
+        trusted_certs,
+        proxy_uri: api.proxy_uri.clone(),
+    })
 }
 
 fn get_cert_inner(
     homedir_path: &std::path::Path,
+    pkcs11: Option<&Pkcs11>,
     preloaded_certs: &std::collections::BTreeMap<String, PreloadedCert>,
     id: &str,
 ) -> Result<Option<Vec<u8>>, Error> {
     match preloaded_certs.get(id) {
         Some(PreloadedCert::Uri(_)) | None => {
-            let path = aziot_certd_config::util::get_path(homedir_path, preloaded_certs, id, true)
-                .map_err(|err| Error::Internal(InternalError::GetPath(err)))?;
-            let bytes = load_inner(&path)?;
+            let location = get_location(homedir_path, pkcs11, preloaded_certs, id)?;
+            let bytes = store::read(&location)?;
             Ok(bytes)
         }
 
         Some(PreloadedCert::Ids(ids)) => {
             let mut result = vec![];
             for id in ids {
-                if let Some(bytes) = get_cert_inner(homedir_path, preloaded_certs, id)? {
+                if let Some(bytes) = get_cert_inner(homedir_path, pkcs11, preloaded_certs, id)? {
                     result.extend_from_slice(&bytes);
                 }
             }
@@ -852,6 +1049,347 @@ fn get_cert_inner(
     }
 }
 
+/// Returns the EST URL and auth parameters of the given cert, falling back to the defaults in `[cert_issuance.est]`.
+fn est_url_and_auth<'a>(
+    cert_issuance: &'a CertIssuance,
+    id: &str,
+    cert_url: Option<&'a url::Url>,
+    cert_auth: Option<&'a EstAuth>,
+) -> Result<(&'a url::Url, &'a EstAuth), Error> {
+    let defaults = cert_issuance.est.as_ref();
+
+    let auth = cert_auth
+        .or_else(|| defaults.map(|default| &default.auth))
+        .ok_or_else(|| {
+            Error::Internal(InternalError::CreateCert(
+                format!(
+                    "cert {:?} is configured to be issued by EST, but EST auth is not configured",
+                    id,
+                )
+                .into(),
+            ))
+        })?;
+
+    let url = cert_url
+        .or_else(|| {
+            defaults
+                .map(|default| &default.urls)
+                .and_then(|urls| urls.get(id).or_else(|| urls.get("default")))
+        })
+        .ok_or_else(|| {
+            Error::Internal(InternalError::CreateCert(
+                format!(
+                    "cert {:?} is configured to be issued by EST, but EST URL is not configured",
+                    id,
+                )
+                .into(),
+            ))
+        })?;
+
+    Ok((url, auth))
+}
+
+/// Loads the certs in `cert_issuance.est.trusted_certs`, to validate the EST server's TLS certificate with.
+fn est_trusted_certs(api: &Api) -> Result<Vec<openssl::x509::X509>, Error> {
+    match &api.cert_issuance.est {
+        Some(default) => load_trusted_certs(
+            api,
+            "cert_issuance.est.trusted_certs",
+            &default.trusted_certs,
+        ),
+        None => Ok(vec![]),
+    }
+}
+
+/// Loads the given certs, which are configured in the setting with the given name.
+fn load_trusted_certs(
+    api: &Api,
+    setting_name: &str,
+    trusted_certs: &[String],
+) -> Result<Vec<openssl::x509::X509>, Error> {
+    let mut trusted_certs_x509 = vec![];
+
+    for trusted_cert in trusted_certs {
+        let pem = get_cert_inner(
+            &api.homedir_path,
+            api.pkcs11.as_ref(),
+            &api.preloaded_certs,
+            trusted_cert,
+        )?
+        .ok_or_else(|| {
+            Error::Internal(InternalError::CreateCert(
+                format!(
+                    "{} contains unreadable cert {:?}",
+                    setting_name, trusted_cert,
+                )
+                .into(),
+            ))
+        })?;
+        let x509 = openssl::x509::X509::stack_from_pem(&pem)
+            .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+        trusted_certs_x509.extend(x509);
+    }
+
+    Ok(trusted_certs_x509)
+}
+
+/// Returns the CSR attributes that the EST server of the given cert requires, or `None` if the cert isn't issued via EST.
+///
+/// The attributes are cached per EST server for [`CSR_ATTRS_CACHE_DURATION`], and until the config changes.
+async fn get_csr_attrs(api: &mut Api, id: &str) -> Result<Option<CsrAttrs>, Error> {
+    let (url, auth) = match api
+        .cert_issuance
+        .certs
+        .get(id)
+        .map(|options| &options.method)
+    {
+        Some(CertIssuanceMethod::Est { url, auth, .. }) => {
+            let (url, auth) =
+                est_url_and_auth(&api.cert_issuance, id, url.as_ref(), auth.as_ref())?;
+            (url.clone(), auth.clone())
+        }
+        _ => return Ok(None),
+    };
+
+    if let Some((retrieved_at, csr_attrs)) = api.csr_attrs_cache.get(&url) {
+        if retrieved_at.elapsed() < CSR_ATTRS_CACHE_DURATION {
+            return Ok(Some(csr_attrs.clone()));
+        }
+    }
+
+    let auth_basic = auth
+        .basic
+        .as_ref()
+        .map(|EstAuthBasic { username, password }| (&**username, &**password));
+
+    let trusted_certs_x509 = est_trusted_certs(api)?;
+
+    // Authenticate with the EST identity cert if it has been issued already. Servers may allow /csrattrs requests
+    // without a client cert, so don't issue the EST identity cert just for this.
+    let identity = match &auth.x509 {
+        Some(EstAuthX509 {
+            identity: (identity_cert, identity_private_key),
+            ..
+        }) => {
+            match get_cert_inner(
+                &api.homedir_path,
+                api.pkcs11.as_ref(),
+                &api.preloaded_certs,
+                identity_cert,
+            )? {
+                Some(identity_cert) => {
+                    let identity_private_key = api
+                        .key_client
+                        .load_key_pair(identity_private_key)
+                        .map_err(|err| {
+                        Error::Internal(InternalError::GetCsrAttrs(Box::new(err)))
+                    })?;
+                    let identity_private_key = std::ffi::CString::new(identity_private_key.0)
+                        .map_err(|err| {
+                            Error::Internal(InternalError::GetCsrAttrs(Box::new(err)))
+                        })?;
+                    let identity_private_key = api
+                        .key_engine
+                        .load_private_key(&identity_private_key)
+                        .map_err(|err| {
+                            Error::Internal(InternalError::GetCsrAttrs(Box::new(err)))
+                        })?;
+                    Some((identity_cert, identity_private_key))
+                }
+
+                None => None,
+            }
+        }
+
+        None => None,
+    };
+
+    let csr_attrs = est::get_csr_attrs(
+        &url,
+        auth_basic,
+        identity
+            .as_ref()
+            .map(|(identity_cert, identity_private_key)| {
+                (&**identity_cert, &**identity_private_key)
+            }),
+        trusted_certs_x509,
+        api.proxy_uri.clone(),
+    )
+    .await
+    .map_err(|err| Error::Internal(InternalError::GetCsrAttrs(Box::new(err))))?;
+    let csr_attrs = csr_attrs::parse(&csr_attrs)
+        .map_err(|err| Error::Internal(InternalError::GetCsrAttrs(Box::new(err))))?;
+
+    api.csr_attrs_cache
+        .insert(url, (std::time::Instant::now(), csr_attrs.clone()));
+
+    Ok(Some(csr_attrs))
+}
+
+/// Checks that the CSR for the given cert contains the attributes that its EST server requires, if it's issued via EST.
+///
+/// If the CSR attributes can't be retrieved, the CSR is not validated, and it's up to the EST server to accept or reject it.
+async fn validate_csr_attrs(api: &mut Api, id: &str, csr: &[u8]) -> Result<(), Error> {
+    let csr_attrs = match get_csr_attrs(api, id).await {
+        Ok(Some(csr_attrs)) => csr_attrs,
+        Ok(None) => return Ok(()),
+        Err(err) => {
+            log::warn!(
+                "Could not get the CSR attributes required by the EST server of cert {:?}: {}",
+                id,
+                http_common::server::error_to_message(&err)
+            );
+            return Ok(());
+        }
+    };
+
+    csr_attrs::validate(&csr_attrs, csr)
+}
+
+/// Loads the private key of the current cert with the given ID, to authenticate a `/simplereenroll` request with.
+///
+/// Returns `None` if the cert hasn't been issued yet, has expired, or no longer matches its key pair,
+/// in which case the cert has to be enrolled again instead.
+fn get_reenroll_private_key(
+    id: &str,
+    current_cert: Option<&[u8]>,
+    key_id: &str,
+    key_client: &aziot_key_client::Client,
+    key_engine: &mut openssl2::FunctionalEngine,
+) -> Result<Option<openssl::pkey::PKey<openssl::pkey::Private>>, Error> {
+    let current_cert = match current_cert {
+        Some(current_cert) => current_cert,
+        None => return Ok(None),
+    };
+    let current_x509 = openssl::x509::X509::stack_from_pem(current_cert)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    let current_x509 = match current_x509.first() {
+        Some(current_x509) => current_x509,
+        None => return Ok(None),
+    };
+
+    let now = openssl::asn1::Asn1Time::days_from_now(0)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    if current_x509.not_after() <= now {
+        log::info!(
+            "Cert {:?} has expired, so it will be enrolled again instead of being reenrolled.",
+            id
+        );
+        return Ok(None);
+    }
+
+    let key_pair_handle = key_client
+        .load_key_pair(key_id)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    let key_pair_handle = std::ffi::CString::new(key_pair_handle.0)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    let current_private_key = key_engine
+        .load_private_key(&key_pair_handle)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+
+    let current_public_key = current_x509
+        .public_key()
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    if !current_public_key.public_eq(&current_private_key) {
+        log::info!(
+            "Cert {:?} does not match its key pair, so it will be enrolled again instead of being reenrolled.",
+            id
+        );
+        return Ok(None);
+    }
+
+    Ok(Some(current_private_key))
+}
+
+/// Imports the private key that an EST server generated for the given cert into keyd as the cert's key pair.
+///
+/// The key is checked against the cert before it's imported, so that an unusable response from the server doesn't replace
+/// the cert's current key pair. The caller stores the cert right after, so that the key pair and cert stay matched.
+fn import_server_generated_key(
+    api: &Api,
+    id: &str,
+    x509: &[u8],
+    private_key: &[u8],
+) -> Result<(), Error> {
+    let leaf_public_key = openssl::x509::X509::stack_from_pem(x509)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?
+        .first()
+        .ok_or_else(|| {
+            Error::Internal(InternalError::CreateCert(
+                "EST serverkeygen response does not contain the cert".into(),
+            ))
+        })?
+        .public_key()
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    let parsed_private_key = openssl::pkey::PKey::private_key_from_der(private_key)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    if !leaf_public_key.public_eq(&parsed_private_key) {
+        return Err(Error::Internal(InternalError::CreateCert(
+            "private key in EST serverkeygen response does not match the cert".into(),
+        )));
+    }
+
+    let _ = api
+        .key_client
+        .import_key_pair(
+            &key_id(api, id),
+            private_key,
+            aziot_key_common::KeyPairFormat::Pkcs8Der,
+            None,
+        )
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+
+    Ok(())
+}
+
+/// Returns the ID of the key pair of the given cert.
+///
+/// This is the private key configured for the cert if it's the local CA or an EST identity cert,
+/// and the key pair with the same ID as the cert otherwise.
+fn key_id(api: &Api, id: &str) -> String {
+    if let Some(LocalCa { cert, pk }) = &api.cert_issuance.local_ca {
+        if cert == id {
+            return pk.clone();
+        }
+    }
+
+    for x509 in est_auth_x509(api) {
+        if x509.identity.0 == id {
+            return x509.identity.1.clone();
+        }
+
+        if let Some((bootstrap_identity_cert, bootstrap_identity_pk)) = &x509.bootstrap_identity {
+            if bootstrap_identity_cert == id {
+                return bootstrap_identity_pk.clone();
+            }
+        }
+    }
+
+    id.to_owned()
+}
+
+/// Returns the X509 auth parameters of the EST server, both the default ones and the ones configured for individual certs.
+fn est_auth_x509(api: &Api) -> impl Iterator<Item = &EstAuthX509> {
+    let default = api
+        .cert_issuance
+        .est
+        .as_ref()
+        .and_then(|est| est.auth.x509.as_ref());
+
+    let per_cert = api
+        .cert_issuance
+        .certs
+        .values()
+        .filter_map(|options| match &options.method {
+            CertIssuanceMethod::Est {
+                auth: Some(auth), ..
+            } => auth.x509.as_ref(),
+            _ => None,
+        });
+
+    default.into_iter().chain(per_cert)
+}
+
 fn principal_to_map(
     principal: Vec<Principal>,
 ) -> std::collections::BTreeMap<libc::uid_t, Vec<wildmatch::WildMatch>> {
@@ -867,3 +1405,51 @@ fn principal_to_map(
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    fn ec_private_key(curve: openssl::nid::Nid) -> openssl::pkey::PKey<openssl::pkey::Private> {
+        let group = openssl::ec::EcGroup::from_curve_name(curve).unwrap();
+        let ec_key = openssl::ec::EcKey::generate(&group).unwrap();
+        openssl::pkey::PKey::from_ec_key(ec_key).unwrap()
+    }
+
+    #[test]
+    fn signing_digest() {
+        for (private_key, expected_digest) in &[
+            (
+                ec_private_key(openssl::nid::Nid::X9_62_PRIME256V1),
+                openssl::nid::Nid::SHA256,
+            ),
+            (
+                ec_private_key(openssl::nid::Nid::SECP384R1),
+                openssl::nid::Nid::SHA384,
+            ),
+            (
+                openssl::pkey::PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap(),
+                openssl::nid::Nid::SHA256,
+            ),
+            (
+                openssl::pkey::PKey::generate_ed25519().unwrap(),
+                openssl::nid::Nid::UNDEF,
+            ),
+        ] {
+            let digest = super::signing_digest(private_key);
+            assert_eq!(digest.type_(), *expected_digest);
+
+            // The digest must be one that openssl accepts for signing a cert with the key.
+            let mut name = openssl::x509::X509NameBuilder::new().unwrap();
+            name.append_entry_by_text("CN", "test").unwrap();
+            let name = name.build();
+
+            let mut cert = openssl::x509::X509::builder().unwrap();
+            cert.set_subject_name(&name).unwrap();
+            cert.set_issuer_name(&name).unwrap();
+            cert.set_pubkey(private_key).unwrap();
+            cert.sign(private_key, digest).unwrap();
+            let cert = cert.build();
+
+            assert!(cert.verify(private_key).unwrap());
+        }
+    }
+}
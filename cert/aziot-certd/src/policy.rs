@@ -0,0 +1,720 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Enforcement of the policies of locally issued certs.
+
+use aziot_certd_config::{CertPolicy, Digest, KeyUsage};
+
+use crate::csr_attrs::{extension_oid, oid_to_string};
+
+const SUBJECT_ALT_NAME: &str = "2.5.29.17";
+const BASIC_CONSTRAINTS: &str = "2.5.29.19";
+const KEY_USAGE: &str = "2.5.29.15";
+const EXTENDED_KEY_USAGE: &str = "2.5.29.37";
+const NETSCAPE_CERT_TYPE: &str = "2.16.840.1.113730.1.1";
+
+/// The keyCertSign and cRLSign bits of the keyUsage extension, which only CA certs may have.
+const KEY_USAGE_CA_BITS: &[(std::os::raw::c_int, &str)] = &[(5, "keyCertSign"), (6, "cRLSign")];
+
+/// The sslCA, emailCA and objCA bits of the nsCertType extension, which only CA certs may have.
+const NETSCAPE_CERT_TYPE_CA_BITS: &[(std::os::raw::c_int, &str)] =
+    &[(5, "sslCA"), (6, "emailCA"), (7, "objCA")];
+
+/// Returns the extensions of the cert with the given ID to be issued for the given CSR.
+///
+/// These are the CSR's extensions, except for the ones that the policy replaces, followed by the ones that the policy forces.
+/// Returns an error if the CSR has an extension or a subjectAltName entry that the policy does not allow.
+///
+/// Unless the policy allows a CA cert, the cert always gets a critical basicConstraints extension with `CA:FALSE`,
+/// and a CSR that requests a CA cert or CA-only key usages is rejected. The policy allows a CA cert by forcing
+/// a basicConstraints extension with `ca = true`, or by allowing the CSR's basicConstraints extension explicitly.
+pub(crate) fn extensions(
+    policy: &CertPolicy,
+    id: &str,
+    x509_req: &openssl::x509::X509ReqRef,
+) -> Result<Vec<openssl::x509::X509Extension>, crate::Error> {
+    let allowed_extensions: Option<Vec<String>> = policy
+        .allowed_extensions
+        .as_ref()
+        .map(|allowed_extensions| {
+            allowed_extensions
+                .iter()
+                .map(|allowed_extension| {
+                    let object =
+                        openssl::asn1::Asn1Object::from_str(allowed_extension).map_err(|err| {
+                            invalid_policy(
+                                id,
+                                format!(
+                                    "allowed_extensions contains unknown extension {:?}: {}",
+                                    allowed_extension, err
+                                ),
+                            )
+                        })?;
+                    Ok(oid_to_string(&object))
+                })
+                .collect::<Result<_, crate::Error>>()
+        })
+        .transpose()?;
+
+    let mut forced_extensions = vec![];
+    if policy.basic_constraints.is_some() {
+        forced_extensions.push(BASIC_CONSTRAINTS);
+    }
+    if policy.key_usage.is_some() {
+        forced_extensions.push(KEY_USAGE);
+    }
+    if policy.extended_key_usage.is_some() {
+        forced_extensions.push(EXTENDED_KEY_USAGE);
+    }
+
+    let allows_ca = match policy.basic_constraints {
+        Some(basic_constraints) => basic_constraints.ca,
+        None => allowed_extensions
+            .as_ref()
+            .map_or(false, |allowed_extensions| {
+                allowed_extensions
+                    .iter()
+                    .any(|oid| oid == BASIC_CONSTRAINTS)
+            }),
+    };
+
+    let mut result = vec![];
+
+    // x509_req.extensions() returns an Err variant if no extensions are present in the req.
+    if let Ok(req_extensions) = x509_req.extensions() {
+        for extension in req_extensions {
+            let oid = extension_oid(&extension);
+
+            if forced_extensions.contains(&&*oid) {
+                continue;
+            }
+
+            if let Some(allowed_extensions) = &allowed_extensions {
+                if !allowed_extensions.contains(&oid) {
+                    return Err(crate::Error::invalid_parameter(
+                        "csr",
+                        format!(
+                            "CSR contains extension {} that the policy of cert {:?} does not allow",
+                            oid, id,
+                        ),
+                    ));
+                }
+            }
+
+            if !allows_ca {
+                match &*oid {
+                    BASIC_CONSTRAINTS => {
+                        check_not_ca(id, &extension)?;
+
+                        // Replaced by a critical one below.
+                        continue;
+                    }
+
+                    KEY_USAGE => check_no_ca_bits(id, &extension, "keyUsage", KEY_USAGE_CA_BITS)?,
+
+                    NETSCAPE_CERT_TYPE => {
+                        check_no_ca_bits(id, &extension, "nsCertType", NETSCAPE_CERT_TYPE_CA_BITS)?
+                    }
+
+                    _ => (),
+                }
+            }
+
+            if oid == SUBJECT_ALT_NAME {
+                if let Some(allowed_subject_alt_names) = &policy.allowed_subject_alt_names {
+                    check_subject_alt_names(allowed_subject_alt_names, id, &extension)?;
+                }
+            }
+
+            result.push(extension);
+        }
+    }
+
+    if let Some(basic_constraints) = policy.basic_constraints {
+        let mut extension = openssl::x509::extension::BasicConstraints::new();
+        extension.critical();
+        if basic_constraints.ca {
+            extension.ca();
+        }
+        if let Some(path_len) = basic_constraints.path_len {
+            extension.pathlen(path_len);
+        }
+        let extension = extension
+            .build()
+            .map_err(|err| invalid_policy(id, format!("basic_constraints is invalid: {}", err)))?;
+        result.push(extension);
+    } else if !allows_ca {
+        let extension = openssl::x509::extension::BasicConstraints::new()
+            .critical()
+            .build()
+            .map_err(|err| {
+                crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err)))
+            })?;
+        result.push(extension);
+    }
+
+    if let Some(key_usage) = &policy.key_usage {
+        let mut extension = openssl::x509::extension::KeyUsage::new();
+        extension.critical();
+        for key_usage in key_usage {
+            match key_usage {
+                KeyUsage::DigitalSignature => extension.digital_signature(),
+                KeyUsage::NonRepudiation => extension.non_repudiation(),
+                KeyUsage::KeyEncipherment => extension.key_encipherment(),
+                KeyUsage::DataEncipherment => extension.data_encipherment(),
+                KeyUsage::KeyAgreement => extension.key_agreement(),
+                KeyUsage::KeyCertSign => extension.key_cert_sign(),
+                KeyUsage::CrlSign => extension.crl_sign(),
+                KeyUsage::EncipherOnly => extension.encipher_only(),
+                KeyUsage::DecipherOnly => extension.decipher_only(),
+            };
+        }
+        let extension = extension
+            .build()
+            .map_err(|err| invalid_policy(id, format!("key_usage is invalid: {}", err)))?;
+        result.push(extension);
+    }
+
+    if let Some(extended_key_usage) = &policy.extended_key_usage {
+        let mut extension = openssl::x509::extension::ExtendedKeyUsage::new();
+        for extended_key_usage in extended_key_usage {
+            extension.other(extended_key_usage);
+        }
+        let extension = extension
+            .build()
+            .map_err(|err| invalid_policy(id, format!("extended_key_usage is invalid: {}", err)))?;
+        result.push(extension);
+    }
+
+    Ok(result)
+}
+
+/// Returns the extensions of the cert with the given ID to be issued for the given CSR if the cert has no policy.
+///
+/// These are all the CSR's extensions, with a critical basicConstraints extension with `CA:FALSE`. Returns an error
+/// if the CSR requests a CA cert or CA-only key usages, since only a policy can allow that.
+pub(crate) fn extensions_without_policy(
+    id: &str,
+    x509_req: &openssl::x509::X509ReqRef,
+) -> Result<Vec<openssl::x509::X509Extension>, crate::Error> {
+    extensions(&CertPolicy::default(), id, x509_req)
+}
+
+/// Checks that the given basicConstraints extension does not request a CA cert.
+fn check_not_ca(id: &str, extension: &openssl::x509::X509ExtensionRef) -> Result<(), crate::Error> {
+    let basic_constraints = unsafe {
+        openssl_sys::X509V3_EXT_d2i(foreign_types_shared::ForeignTypeRef::as_ptr(extension))
+    };
+    if basic_constraints.is_null() {
+        return Err(crate::Error::invalid_parameter(
+            "csr",
+            "CSR contains a malformed basicConstraints extension",
+        ));
+    }
+    let basic_constraints: *mut openssl_sys2::BASIC_CONSTRAINTS = basic_constraints.cast();
+    let ca = unsafe {
+        let ca = (*basic_constraints).ca != 0;
+        openssl_sys2::BASIC_CONSTRAINTS_free(basic_constraints);
+        ca
+    };
+
+    if ca {
+        return Err(crate::Error::invalid_parameter(
+            "csr",
+            format!(
+                "CSR requests a CA cert, but cert {:?} does not have a policy that allows it",
+                id,
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks that the given bit string extension, such as keyUsage, has none of the given bits that only CA certs may have.
+fn check_no_ca_bits(
+    id: &str,
+    extension: &openssl::x509::X509ExtensionRef,
+    extension_name: &str,
+    ca_bits: &[(std::os::raw::c_int, &'static str)],
+) -> Result<(), crate::Error> {
+    let bit_string = unsafe {
+        openssl_sys::X509V3_EXT_d2i(foreign_types_shared::ForeignTypeRef::as_ptr(extension))
+    };
+    if bit_string.is_null() {
+        return Err(crate::Error::invalid_parameter(
+            "csr",
+            format!("CSR contains a malformed {} extension", extension_name),
+        ));
+    }
+    let bit_string: *mut openssl_sys::ASN1_BIT_STRING = bit_string.cast();
+    let ca_bit = unsafe {
+        let ca_bit = ca_bits
+            .iter()
+            .find(|&&(bit, _)| openssl_sys2::ASN1_BIT_STRING_get_bit(bit_string, bit) != 0);
+        openssl_sys::ASN1_BIT_STRING_free(bit_string);
+        ca_bit
+    };
+
+    if let Some((_, bit_name)) = ca_bit {
+        return Err(crate::Error::invalid_parameter(
+            "csr",
+            format!(
+                "CSR requests {} in its {} extension, which only a CA cert may have, but cert {:?} does not have a policy that allows a CA cert",
+                bit_name, extension_name, id,
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks that every entry of the given subjectAltName extension matches one of the allowed patterns.
+fn check_subject_alt_names(
+    allowed_subject_alt_names: &[String],
+    id: &str,
+    extension: &openssl::x509::X509ExtensionRef,
+) -> Result<(), crate::Error> {
+    let subject_alt_names = unsafe {
+        openssl_sys::X509V3_EXT_d2i(foreign_types_shared::ForeignTypeRef::as_ptr(extension))
+    };
+    if subject_alt_names.is_null() {
+        return Err(crate::Error::invalid_parameter(
+            "csr",
+            "CSR contains a malformed subjectAltName extension",
+        ));
+    }
+    let subject_alt_names: openssl::stack::Stack<openssl::x509::GeneralName> =
+        unsafe { foreign_types_shared::ForeignType::from_ptr(subject_alt_names.cast()) };
+
+    for subject_alt_name in &subject_alt_names {
+        // DNS names are case-insensitive, so they're matched in lowercase.
+        let (value, is_dns_name) = if let Some(dns_name) = subject_alt_name.dnsname() {
+            (dns_name.to_ascii_lowercase(), true)
+        } else if let Some(ip_address) = subject_alt_name.ipaddress() {
+            let ip_address = match ip_address.len() {
+                4 => {
+                    let mut octets = [0_u8; 4];
+                    octets.copy_from_slice(ip_address);
+                    std::net::IpAddr::from(octets).to_string()
+                }
+                16 => {
+                    let mut octets = [0_u8; 16];
+                    octets.copy_from_slice(ip_address);
+                    std::net::IpAddr::from(octets).to_string()
+                }
+                _ => {
+                    return Err(crate::Error::invalid_parameter(
+                        "csr",
+                        "CSR contains a malformed subjectAltName IP address",
+                    ))
+                }
+            };
+            (ip_address, false)
+        } else if let Some(uri) = subject_alt_name.uri() {
+            (uri.to_owned(), false)
+        } else if let Some(email) = subject_alt_name.email() {
+            (email.to_owned(), false)
+        } else {
+            return Err(crate::Error::invalid_parameter(
+                "csr",
+                format!(
+                    "CSR contains a subjectAltName entry of a type that the policy of cert {:?} does not allow",
+                    id,
+                ),
+            ));
+        };
+
+        let allowed = allowed_subject_alt_names
+            .iter()
+            .any(|allowed_subject_alt_name| {
+                if is_dns_name {
+                    wildmatch::WildMatch::new(&allowed_subject_alt_name.to_ascii_lowercase())
+                        .is_match(&value)
+                } else {
+                    wildmatch::WildMatch::new(allowed_subject_alt_name).is_match(&value)
+                }
+            });
+        if !allowed {
+            return Err(crate::Error::invalid_parameter(
+                "csr",
+                format!(
+                    "CSR contains subjectAltName {:?} that the policy of cert {:?} does not allow",
+                    value, id,
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the subject name described by the given template.
+///
+/// The template is a comma-separated list of attributes like `"CN={common_name}, O=Contoso, OU={cert_id}"`.
+/// Placeholders are expanded after the template is split into attributes, so their values can't add attributes of their own.
+pub(crate) fn subject_name(
+    template: &str,
+    id: &str,
+    common_name: Option<&str>,
+) -> Result<openssl::x509::X509Name, crate::Error> {
+    let mut subject_name = openssl::x509::X509Name::builder()
+        .map_err(|err| crate::Error::Internal(crate::InternalError::CreateCert(Box::new(err))))?;
+
+    for attribute in template.split(',') {
+        let (field, value) = attribute.split_once('=').ok_or_else(|| {
+            invalid_policy(
+                id,
+                format!(
+                    "subject contains attribute {:?} that is not of the form FIELD=VALUE",
+                    attribute.trim(),
+                ),
+            )
+        })?;
+        let field = field.trim();
+        let value = expand_placeholders(value.trim(), id, common_name)?;
+
+        subject_name
+            .append_entry_by_text(field, &value)
+            .map_err(|err| {
+                invalid_policy(
+                    id,
+                    format!("subject contains invalid attribute {:?}: {}", field, err),
+                )
+            })?;
+    }
+
+    Ok(subject_name.build())
+}
+
+fn expand_placeholders(
+    value: &str,
+    id: &str,
+    common_name: Option<&str>,
+) -> Result<String, crate::Error> {
+    let mut result = String::new();
+
+    let mut rest = value;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| invalid_policy(id, "subject contains an unterminated placeholder"))?
+            + start;
+        match &rest[(start + 1)..end] {
+            "cert_id" => result.push_str(id),
+
+            "common_name" => result.push_str(common_name.ok_or_else(|| {
+                crate::Error::invalid_parameter(
+                    "csr",
+                    format!(
+                        "CSR does not have a common name, which the subject of cert {:?} requires",
+                        id,
+                    ),
+                )
+            })?),
+
+            placeholder => {
+                return Err(invalid_policy(
+                    id,
+                    format!("subject contains unknown placeholder {{{}}}", placeholder),
+                ))
+            }
+        }
+
+        rest = &rest[(end + 1)..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+/// Returns the digest that the cert is signed with by the given issuer key.
+pub(crate) fn digest(
+    digest: Digest,
+    id: &str,
+    issuer_private_key: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
+) -> Result<openssl::hash::MessageDigest, crate::Error> {
+    if issuer_private_key.id() == openssl::pkey::Id::ED25519 {
+        return Err(invalid_policy(
+            id,
+            "digest cannot be set for certs issued with an Ed25519 key",
+        ));
+    }
+
+    Ok(match digest {
+        Digest::Sha256 => openssl::hash::MessageDigest::sha256(),
+        Digest::Sha384 => openssl::hash::MessageDigest::sha384(),
+        Digest::Sha512 => openssl::hash::MessageDigest::sha512(),
+    })
+}
+
+fn invalid_policy(id: &str, message: impl std::fmt::Display) -> crate::Error {
+    crate::Error::Internal(crate::InternalError::CreateCert(
+        format!("policy of cert {:?} is invalid: {}", id, message).into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use aziot_certd_config::{BasicConstraints, CertPolicy, KeyUsage};
+
+    fn csr(extensions: &[(&str, &str)]) -> openssl::x509::X509Req {
+        let ec_group =
+            openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1).unwrap();
+        let private_key =
+            openssl::pkey::PKey::from_ec_key(openssl::ec::EcKey::generate(&ec_group).unwrap())
+                .unwrap();
+
+        let mut x509_req = openssl::x509::X509Req::builder().unwrap();
+        x509_req.set_pubkey(&private_key).unwrap();
+
+        let mut stack = openssl::stack::Stack::new().unwrap();
+        for &(name, value) in extensions {
+            let extension = openssl::x509::X509Extension::new(
+                None,
+                Some(&x509_req.x509v3_context(None)),
+                name,
+                value,
+            )
+            .unwrap();
+            stack.push(extension).unwrap();
+        }
+        x509_req.add_extensions(&stack).unwrap();
+
+        x509_req
+            .sign(&private_key, openssl::hash::MessageDigest::sha256())
+            .unwrap();
+        x509_req.build()
+    }
+
+    /// Returns the DER of a cert that has the given extensions and nothing else.
+    fn cert_der(extensions: Vec<openssl::x509::X509Extension>) -> Vec<u8> {
+        let mut x509 = openssl::x509::X509::builder().unwrap();
+        for extension in extensions {
+            x509.append_extension(extension).unwrap();
+        }
+        x509.build().to_der().unwrap()
+    }
+
+    fn expected_cert_der(extensions: &[(&str, &str)]) -> Vec<u8> {
+        cert_der(
+            extensions
+                .iter()
+                .map(|&(name, value)| {
+                    openssl::x509::X509Extension::new(None, None, name, value).unwrap()
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn extensions() {
+        let csr_extensions = [
+            ("subjectAltName", "DNS:Device.Example.com,IP:10.0.0.5"),
+            ("basicConstraints", "critical,CA:TRUE"),
+            ("keyUsage", "keyCertSign"),
+        ];
+        let x509_req = csr(&csr_extensions);
+
+        // Without restrictions, all extensions are copied, except that a CA cert is rejected.
+        assert!(super::extensions(&CertPolicy::default(), "test", &x509_req).is_err());
+        let x509_req_without_ca = csr(&[("keyUsage", "digitalSignature,keyCertSign")]);
+        assert!(super::extensions(&CertPolicy::default(), "test", &x509_req_without_ca).is_err());
+
+        let ca_policy = CertPolicy {
+            allowed_extensions: Some(vec![
+                "subjectAltName".to_owned(),
+                "basicConstraints".to_owned(),
+                "keyUsage".to_owned(),
+            ]),
+            ..Default::default()
+        };
+        let extensions = super::extensions(&ca_policy, "test", &x509_req).unwrap();
+        assert_eq!(cert_der(extensions), expected_cert_der(&csr_extensions));
+
+        // Unless the policy allows a CA cert, the CSR's basicConstraints extension is replaced by a critical one.
+        let non_ca_csr_extensions = [
+            ("basicConstraints", "CA:FALSE"),
+            ("subjectAltName", "DNS:Device.Example.com,IP:10.0.0.5"),
+            ("keyUsage", "digitalSignature"),
+        ];
+        let extensions =
+            super::extensions(&CertPolicy::default(), "test", &csr(&non_ca_csr_extensions))
+                .unwrap();
+        assert_eq!(
+            cert_der(extensions),
+            expected_cert_der(&[
+                ("subjectAltName", "DNS:Device.Example.com,IP:10.0.0.5"),
+                ("keyUsage", "digitalSignature"),
+                ("basicConstraints", "critical,CA:FALSE"),
+            ]),
+        );
+
+        // Forced extensions replace the CSR's, and are allowed even if they aren't in allowed_extensions.
+        let policy = CertPolicy {
+            allowed_extensions: Some(vec!["subjectAltName".to_owned()]),
+            basic_constraints: Some(BasicConstraints {
+                ca: false,
+                path_len: None,
+            }),
+            key_usage: Some(vec![KeyUsage::DigitalSignature]),
+            extended_key_usage: Some(vec!["serverAuth".to_owned(), "1.2.3.4".to_owned()]),
+            allowed_subject_alt_names: Some(vec![
+                "*.example.com".to_owned(),
+                "10.0.0.*".to_owned(),
+            ]),
+            ..Default::default()
+        };
+        let extensions = super::extensions(&policy, "test", &x509_req).unwrap();
+        assert_eq!(
+            cert_der(extensions),
+            expected_cert_der(&[
+                ("subjectAltName", "DNS:Device.Example.com,IP:10.0.0.5"),
+                ("basicConstraints", "critical,CA:FALSE"),
+                ("keyUsage", "critical,digitalSignature"),
+                ("extendedKeyUsage", "serverAuth,1.2.3.4"),
+            ]),
+        );
+
+        // Extensions that aren't allowed are rejected.
+        let policy = CertPolicy {
+            allowed_extensions: Some(vec!["2.5.29.17".to_owned()]),
+            ..Default::default()
+        };
+        assert!(super::extensions(&policy, "test", &x509_req).is_err());
+
+        // subjectAltName entries that aren't allowed are rejected.
+        let policy = CertPolicy {
+            allowed_subject_alt_names: Some(vec!["*.example.com".to_owned()]),
+            ..Default::default()
+        };
+        assert!(super::extensions(&policy, "test", &x509_req).is_err());
+
+        let x509_req = csr(&[("subjectAltName", "URI:urn:example:device")]);
+        let policy = CertPolicy {
+            allowed_subject_alt_names: Some(vec!["*.example.com".to_owned()]),
+            ..Default::default()
+        };
+        assert!(super::extensions(&policy, "test", &x509_req).is_err());
+        let policy = CertPolicy {
+            allowed_subject_alt_names: Some(vec!["urn:example:*".to_owned()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            cert_der(super::extensions(&policy, "test", &x509_req).unwrap()),
+            expected_cert_der(&[
+                ("subjectAltName", "URI:urn:example:device"),
+                ("basicConstraints", "critical,CA:FALSE"),
+            ]),
+        );
+
+        // A CA cert can be forced with a path length.
+        let x509_req = csr(&[]);
+        let policy = CertPolicy {
+            basic_constraints: Some(BasicConstraints {
+                ca: true,
+                path_len: Some(0),
+            }),
+            ..Default::default()
+        };
+        let extensions = super::extensions(&policy, "test", &x509_req).unwrap();
+        assert_eq!(
+            cert_der(extensions),
+            expected_cert_der(&[("basicConstraints", "critical,CA:TRUE,pathlen:0")]),
+        );
+    }
+
+    #[test]
+    fn extensions_without_policy() {
+        // All extensions are copied, followed by a critical basicConstraints extension that replaces the CSR's.
+        let csr_extensions = [
+            ("subjectAltName", "DNS:device.example.com"),
+            ("basicConstraints", "critical,CA:FALSE"),
+            ("extendedKeyUsage", "clientAuth"),
+        ];
+        let extensions = super::extensions_without_policy("test", &csr(&csr_extensions)).unwrap();
+        assert_eq!(
+            cert_der(extensions),
+            expected_cert_der(&[
+                ("subjectAltName", "DNS:device.example.com"),
+                ("extendedKeyUsage", "clientAuth"),
+                ("basicConstraints", "critical,CA:FALSE"),
+            ]),
+        );
+
+        let extensions = super::extensions_without_policy("test", &csr(&[])).unwrap();
+        assert_eq!(
+            cert_der(extensions),
+            expected_cert_der(&[("basicConstraints", "critical,CA:FALSE")]),
+        );
+
+        // A CA cert is rejected.
+        let x509_req = csr(&[("basicConstraints", "CA:TRUE")]);
+        assert!(super::extensions_without_policy("test", &x509_req).is_err());
+        let x509_req = csr(&[("basicConstraints", "critical,CA:TRUE,pathlen:0")]);
+        assert!(super::extensions_without_policy("test", &x509_req).is_err());
+
+        // So are the key usages of a CA cert.
+        for &(name, value) in &[
+            ("keyUsage", "keyCertSign"),
+            ("keyUsage", "digitalSignature,cRLSign"),
+            ("nsCertType", "sslCA"),
+            ("nsCertType", "client,emailCA"),
+            ("nsCertType", "objCA"),
+        ] {
+            let x509_req = csr(&[(name, value)]);
+            assert!(super::extensions_without_policy("test", &x509_req).is_err());
+        }
+        let x509_req = csr(&[("nsCertType", "client,server")]);
+        assert!(super::extensions_without_policy("test", &x509_req).is_ok());
+    }
+
+    #[test]
+    fn subject_name() {
+        let subject_name = super::subject_name(
+            "CN={common_name}, O=Contoso, OU={cert_id}-ou",
+            "test",
+            Some("device, O=Evil"),
+        )
+        .unwrap();
+        let entries: Vec<_> = subject_name
+            .entries()
+            .map(|entry| {
+                (
+                    entry.object().nid().short_name().unwrap().to_owned(),
+                    entry.data().as_utf8().unwrap().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("CN".to_owned(), "device, O=Evil".to_owned()),
+                ("O".to_owned(), "Contoso".to_owned()),
+                ("OU".to_owned(), "test-ou".to_owned()),
+            ],
+        );
+
+        assert!(super::subject_name("CN={common_name}", "test", None).is_err());
+        assert!(super::subject_name("CN={unknown}", "test", Some("device")).is_err());
+        assert!(super::subject_name("CN={cert_id", "test", Some("device")).is_err());
+        assert!(super::subject_name("CN=device, Contoso", "test", None).is_err());
+        assert!(super::subject_name("XYZ=device", "test", None).is_err());
+    }
+
+    #[test]
+    fn digest() {
+        let private_key = openssl::pkey::PKey::generate_ed25519().unwrap();
+        assert!(super::digest(aziot_certd_config::Digest::Sha384, "test", &private_key).is_err());
+
+        let private_key =
+            openssl::pkey::PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap();
+        assert_eq!(
+            super::digest(aziot_certd_config::Digest::Sha384, "test", &private_key)
+                .unwrap()
+                .type_(),
+            openssl::hash::MessageDigest::sha384().type_(),
+        );
+    }
+}
@@ -0,0 +1,834 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Automatic renewal of the certs in `cert_issuance.certs`, as configured by `cert_issuance.auto_renew`.
+//!
+//! A background task periodically checks every cert that has been issued, and re-issues the ones that have reached
+//! the renewal threshold with the same issuance method as before.
+//!
+//! If `auto_renew.rotate_key` is set, the new cert is issued for a new key pair that keyd generates under a random ID
+//! that starts with [`RENEWAL_KEY_ID_PREFIX`]. Once the new cert has been issued, keyd moves the new key pair to the
+//! cert's key pair ID, and only then is the new cert written. If issuing the cert or moving the key pair fails,
+//! the new key pair is deleted and the current cert and key pair are kept.
+//!
+//! Failed renewals are retried with exponential backoff. If moving the new key pair fails [`MAX_KEY_MOVE_FAILURES`] times in a row,
+//! such as because keyd stores the new key pair in a different token or slot than the cert's key pair, the cert is renewed
+//! with its existing key pair instead until the config is changed.
+
+use aziot_certd_config::{AutoRenew, CertIssuanceMethod, RenewalThreshold, RENEWAL_KEY_ID_PREFIX};
+
+use crate::{Api, Error, InternalError};
+
+/// How long to wait between checks when no cert is due for renewal sooner.
+///
+/// This also bounds how long it takes for config changes to be picked up.
+const MAX_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// How long to wait before trying again when renewing a cert failed for the first time.
+///
+/// This doubles with every further failure, up to [`MAX_CHECK_INTERVAL`].
+const RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// How often moving a new key pair to a cert's key pair ID may fail in a row before its key pair is no longer rotated.
+const MAX_KEY_MOVE_FAILURES: u32 = 3;
+
+/// How often renewing a cert has failed in a row.
+#[derive(Debug, Default)]
+pub(crate) struct Failures {
+    renewals: u32,
+    key_moves: u32,
+}
+
+/// Starts the background task that renews certs.
+pub(crate) fn start(api: std::sync::Arc<futures_util::lock::Mutex<Api>>) {
+    tokio::spawn(async move {
+        loop {
+            let next_check = check_all(&api).await;
+
+            tokio::time::sleep(next_check).await;
+        }
+    });
+}
+
+/// Renews all the certs that are due for renewal, and returns how long to wait until the next check.
+///
+/// `api` is only locked while it's used, so that it's not locked while a cert is requested from an ACME server.
+async fn check_all(api: &futures_util::lock::Mutex<Api>) -> std::time::Duration {
+    let (auto_renew, ids) = {
+        let api = api.lock().await;
+
+        let auto_renew = match &api.cert_issuance.auto_renew {
+            Some(auto_renew) => auto_renew.clone(),
+            None => return MAX_CHECK_INTERVAL,
+        };
+
+        let ids: Vec<String> = api.cert_issuance.certs.keys().cloned().collect();
+
+        (auto_renew, ids)
+    };
+
+    let mut next_check = MAX_CHECK_INTERVAL;
+
+    for id in ids {
+        let until_renewal = match check(api, &id, &auto_renew).await {
+            Ok(Some(until_renewal)) => {
+                if let Some(failures) = api.lock().await.renewal_failures.get_mut(&id) {
+                    failures.renewals = 0;
+                }
+                until_renewal
+            }
+            Ok(None) => continue,
+            Err(err) => {
+                let mut api = api.lock().await;
+                let failures = api.renewal_failures.entry(id.clone()).or_default();
+                failures.renewals = failures.renewals.saturating_add(1);
+                let retry_interval = retry_interval(failures.renewals);
+
+                log::warn!(
+                    "Could not renew cert {:?}: {}. Trying again in {} seconds.",
+                    id,
+                    http_common::server::error_to_message(&err),
+                    retry_interval.as_secs()
+                );
+                retry_interval
+            }
+        };
+
+        next_check = next_check.min(until_renewal);
+    }
+
+    next_check
+}
+
+/// How long to wait before trying to renew a cert again after it failed the given number of times in a row.
+fn retry_interval(failed_renewals: u32) -> std::time::Duration {
+    1_u32
+        .checked_shl(failed_renewals.saturating_sub(1))
+        .and_then(|factor| RETRY_INTERVAL.checked_mul(factor))
+        .map_or(MAX_CHECK_INTERVAL, |retry_interval| {
+            retry_interval.min(MAX_CHECK_INTERVAL)
+        })
+}
+
+/// Renews the given cert if it's due for renewal, and returns how long until it's due for renewal next.
+///
+/// Returns `None` if the cert hasn't been issued yet. Such certs are issued when they're first requested.
+async fn check(
+    api: &futures_util::lock::Mutex<Api>,
+    id: &str,
+    auto_renew: &AutoRenew,
+) -> Result<Option<std::time::Duration>, Error> {
+    let (current, rotate_key, mismatched) = {
+        let mut api = api.lock().await;
+
+        let current = match get_cert(&api, id)? {
+            Some(current) => current,
+            None => return Ok(None),
+        };
+
+        let rotate_key = auto_renew.rotate_key && can_rotate_key(&api, id);
+
+        // If the key pair was rotated but the renewed cert could not be written, the current cert no longer matches
+        // the key pair. Renew it right away instead of waiting for the threshold.
+        let mismatched = rotate_key && !matches_key_pair(&mut api, id, &current)?;
+
+        (current, rotate_key, mismatched)
+    };
+
+    if !mismatched {
+        if let Some(until_renewal) = time_until_renewal(&current, auto_renew.threshold)? {
+            return Ok(Some(until_renewal));
+        }
+    }
+
+    log::info!(
+        "Renewing cert {:?}, which expires at {}.",
+        id,
+        current.not_after()
+    );
+
+    if !renew(api, id, &current, rotate_key).await? {
+        log::warn!(
+            "Not renewing cert {:?} because it does not match its key pair.",
+            id
+        );
+        return Ok(Some(MAX_CHECK_INTERVAL));
+    }
+
+    let renewed = get_cert(&*api.lock().await, id)?.ok_or_else(|| {
+        Error::Internal(InternalError::CreateCert(
+            "renewed cert could not be read back".into(),
+        ))
+    })?;
+
+    log::info!(
+        "Renewed cert {:?}{}. The new cert expires at {}.",
+        id,
+        if rotate_key { " with a new key" } else { "" },
+        renewed.not_after()
+    );
+
+    if let Some(until_renewal) = time_until_renewal(&renewed, auto_renew.threshold)? {
+        return Ok(Some(until_renewal));
+    }
+
+    // Don't try again immediately, or a cert issued with a short lifetime would be renewed in a loop.
+    log::warn!(
+        "Renewed cert {:?} is already due for renewal according to the threshold {}.",
+        id,
+        auto_renew.threshold
+    );
+    Ok(Some(RETRY_INTERVAL))
+}
+
+/// Re-issues the given cert with the same subject and extensions, using its issuance method from the config.
+///
+/// If `rotate_key` is not set, returns `false` without renewing the cert if its key pair no longer matches it,
+/// since a cert issued for that key pair would not be a renewal of the current one.
+///
+/// If `rotate_key` is set, the new cert is issued for a new key pair. See [`renew_with_new_key`].
+async fn renew(
+    api: &futures_util::lock::Mutex<Api>,
+    id: &str,
+    current: &openssl::x509::X509Ref,
+    rotate_key: bool,
+) -> Result<bool, Error> {
+    let current_public_key = current
+        .public_key()
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+
+    if rotate_key {
+        renew_with_new_key(api, id, current, &current_public_key).await?;
+        return Ok(true);
+    }
+
+    let csr = {
+        let mut api = api.lock().await;
+
+        let key_id = crate::key_id(&api, id);
+        let (_, private_key) = load_key_pair(&mut api, &key_id)?;
+        if !current_public_key.public_eq(&private_key) {
+            return Ok(false);
+        }
+
+        create_csr(current, &current_public_key, &private_key)
+            .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?
+    };
+
+    let _ = crate::create_cert_releasing_lock(api, id, &csr, None).await?;
+    Ok(true)
+}
+
+/// Re-issues the given cert for a new key pair of the same algorithm as its current one.
+///
+/// The new key pair is generated by keyd under a new ID, so it never leaves keyd and is created in the same kind of
+/// storage as any other key pair. Once the new cert has been issued, the new key pair replaces the cert's key pair,
+/// and then the new cert is written. See [`finish_rotation`].
+async fn renew_with_new_key(
+    api: &futures_util::lock::Mutex<Api>,
+    id: &str,
+    current: &openssl::x509::X509Ref,
+    current_public_key: &openssl::pkey::PKeyRef<openssl::pkey::Public>,
+) -> Result<(), Error> {
+    let preferred_algorithm = preferred_algorithm(current_public_key).ok_or_else(|| {
+        Error::Internal(InternalError::CreateCert(
+            "the algorithm of the cert's key pair is not supported for key rotation".into(),
+        ))
+    })?;
+    let new_key_id = new_key_id()?;
+
+    let new_key_pair_handle = {
+        let api = api.lock().await;
+
+        api.key_client
+            .create_key_pair_if_not_exists(&new_key_id, Some(preferred_algorithm))
+            .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?
+    };
+
+    let issued = issue_for_new_key(api, id, current, &new_key_id, &new_key_pair_handle).await;
+
+    let mut api = api.lock().await;
+    let key_id = crate::key_id(&api, id);
+    let Api {
+        homedir_path,
+        pkcs11,
+        preloaded_certs,
+        key_client,
+        renewal_failures,
+        ..
+    } = &mut *api;
+    let failures = renewal_failures.entry(id.to_owned()).or_default();
+    finish_rotation(
+        &**key_client,
+        &key_id,
+        &new_key_id,
+        issued,
+        failures,
+        |x509| {
+            let location = crate::get_location(homedir_path, pkcs11.as_ref(), preloaded_certs, id)?;
+            crate::store::write(&location, x509)
+        },
+    )
+}
+
+/// Issues a new cert with the subject and extensions of the given cert for the key pair with ID `new_key_id`,
+/// and returns it without storing it.
+async fn issue_for_new_key(
+    api: &futures_util::lock::Mutex<Api>,
+    id: &str,
+    current: &openssl::x509::X509Ref,
+    new_key_id: &str,
+    new_key_pair_handle: &aziot_key_common::KeyHandle,
+) -> Result<Vec<u8>, Error> {
+    let (csr, self_signed) = {
+        let mut api = api.lock().await;
+
+        let (public_key, private_key) = load_key_pair(&mut api, new_key_id)?;
+        let csr = create_csr(current, &public_key, &private_key)
+            .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+
+        let self_signed = matches!(
+            api.cert_issuance
+                .certs
+                .get(id)
+                .map(|options| &options.method),
+            Some(CertIssuanceMethod::SelfSigned)
+        );
+
+        (csr, self_signed)
+    };
+
+    // A self-signed cert has to be signed with the new key pair too, instead of the one under the cert's ID.
+    let issuer = if self_signed {
+        Some((id, new_key_pair_handle))
+    } else {
+        None
+    };
+    crate::issue_cert_releasing_lock(api, id, &csr, issuer).await
+}
+
+/// The key pair operations of keyd that [`finish_rotation`] uses.
+trait KeyPairs {
+    fn move_key_pair(&self, id: &str, to_id: &str) -> std::io::Result<()>;
+
+    fn delete_key_pair(&self, id: &str) -> std::io::Result<()>;
+}
+
+impl KeyPairs for aziot_key_client::Client {
+    fn move_key_pair(&self, id: &str, to_id: &str) -> std::io::Result<()> {
+        aziot_key_client::Client::move_key_pair(self, id, to_id)
+    }
+
+    fn delete_key_pair(&self, id: &str) -> std::io::Result<()> {
+        aziot_key_client::Client::delete_key_pair(self, id)
+    }
+}
+
+/// Replaces the key pair with ID `key_id` with the one with ID `new_key_id`, and then writes the cert that was
+/// `issued` for it with `write_cert`.
+///
+/// The key pair is replaced before the cert is written, so that the stored cert never belongs to a key pair that
+/// isn't stored yet. If the cert could not be issued or the key pair could not be replaced, the new key pair is
+/// deleted and the current cert and key pair are kept as they are. Failures to replace the key pair are counted in `failures`.
+fn finish_rotation(
+    key_pairs: &impl KeyPairs,
+    key_id: &str,
+    new_key_id: &str,
+    issued: Result<Vec<u8>, Error>,
+    failures: &mut Failures,
+    write_cert: impl FnOnce(&[u8]) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let x509 = match issued.and_then(|x509| {
+        if let Err(err) = key_pairs.move_key_pair(new_key_id, key_id) {
+            failures.key_moves = failures.key_moves.saturating_add(1);
+            if failures.key_moves == MAX_KEY_MOVE_FAILURES {
+                log::warn!(
+                    "Could not move a new key pair to {:?} {} times in a row. Its cert will be renewed without rotating the key pair. \
+                     Ensure that keyd stores key pairs with IDs that start with {:?} in the same place as {:?}.",
+                    key_id,
+                    MAX_KEY_MOVE_FAILURES,
+                    RENEWAL_KEY_ID_PREFIX,
+                    key_id,
+                );
+            }
+            return Err(Error::Internal(InternalError::CreateCert(Box::new(err))));
+        }
+        failures.key_moves = 0;
+        Ok(x509)
+    }) {
+        Ok(x509) => x509,
+        Err(err) => {
+            if let Err(err) = key_pairs.delete_key_pair(new_key_id) {
+                log::warn!(
+                    "Could not delete new key pair {:?} after key rotation failed: {}",
+                    new_key_id,
+                    err
+                );
+            }
+
+            return Err(err);
+        }
+    };
+
+    // If this fails, the current cert no longer matches the key pair. `check` renews such certs right away.
+    write_cert(&x509)
+}
+
+/// Returns the `preferredAlgorithms` value that makes keyd generate a key pair of the same algorithm and size
+/// as the given public key, or `None` if keyd can't generate such a key pair.
+fn preferred_algorithm(
+    public_key: &openssl::pkey::PKeyRef<openssl::pkey::Public>,
+) -> Option<&'static str> {
+    if public_key.id() == openssl::pkey::Id::ED25519 {
+        return Some("ed25519");
+    }
+
+    if let Ok(ec_key) = public_key.ec_key() {
+        return match ec_key.group().curve_name() {
+            Some(openssl::nid::Nid::X9_62_PRIME256V1) => Some("ec-p256"),
+            Some(openssl::nid::Nid::SECP384R1) => Some("ec-p384"),
+            _ => None,
+        };
+    }
+
+    if let Ok(rsa) = public_key.rsa() {
+        return match rsa.size() {
+            256 => Some("rsa-2048"),
+            512 => Some("rsa-4096"),
+            _ => None,
+        };
+    }
+
+    None
+}
+
+/// Returns a new random key pair ID that starts with [`RENEWAL_KEY_ID_PREFIX`].
+fn new_key_id() -> Result<String, Error> {
+    let mut suffix = [0_u8; 16];
+    openssl::rand::rand_bytes(&mut suffix)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    Ok(format!("{}{}", RENEWAL_KEY_ID_PREFIX, hex::encode(suffix)))
+}
+
+/// Whether the key pair of the given cert matches it.
+fn matches_key_pair(api: &mut Api, id: &str, cert: &openssl::x509::X509Ref) -> Result<bool, Error> {
+    let public_key = cert
+        .public_key()
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    let key_id = crate::key_id(api, id);
+    let (_, private_key) = load_key_pair(api, &key_id)?;
+    Ok(public_key.public_eq(&private_key))
+}
+
+/// Loads the public and private key of the key pair with the given ID.
+fn load_key_pair(
+    api: &mut Api,
+    key_id: &str,
+) -> Result<
+    (
+        openssl::pkey::PKey<openssl::pkey::Public>,
+        openssl::pkey::PKey<openssl::pkey::Private>,
+    ),
+    Error,
+> {
+    let key_pair_handle = api
+        .key_client
+        .load_key_pair(key_id)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    let key_pair_handle = std::ffi::CString::new(key_pair_handle.0)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    let public_key = api
+        .key_engine
+        .load_public_key(&key_pair_handle)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    let private_key = api
+        .key_engine
+        .load_private_key(&key_pair_handle)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    Ok((public_key, private_key))
+}
+
+fn get_cert(api: &Api, id: &str) -> Result<Option<openssl::x509::X509>, Error> {
+    let pem = crate::get_cert_inner(
+        &api.homedir_path,
+        api.pkcs11.as_ref(),
+        &api.preloaded_certs,
+        id,
+    )?;
+    let pem = match pem {
+        Some(pem) => pem,
+        None => return Ok(None),
+    };
+
+    // The first cert is the leaf. The rest are its issuers.
+    let cert = openssl::x509::X509::stack_from_pem(&pem)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?
+        .into_iter()
+        .next();
+    Ok(cert)
+}
+
+/// Returns how long until the given cert is due for renewal, or `None` if it's due already.
+fn time_until_renewal(
+    cert: &openssl::x509::X509Ref,
+    threshold: RenewalThreshold,
+) -> Result<Option<std::time::Duration>, Error> {
+    let now = openssl::asn1::Asn1Time::days_from_now(0)
+        .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+    let seconds_from_now = |time: &openssl::asn1::Asn1TimeRef| -> Result<i64, Error> {
+        let diff = now
+            .diff(time)
+            .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))?;
+        Ok(i64::from(diff.days) * 24 * 60 * 60 + i64::from(diff.secs))
+    };
+
+    let not_before = seconds_from_now(cert.not_before())?;
+    let not_after = seconds_from_now(cert.not_after())?;
+
+    Ok(until_renewal(not_before, not_after, threshold))
+}
+
+/// Returns how long until a cert that is valid from `not_before` to `not_after` is due for renewal,
+/// or `None` if it's due already. Both times are in seconds from now.
+fn until_renewal(
+    not_before: i64,
+    not_after: i64,
+    threshold: RenewalThreshold,
+) -> Option<std::time::Duration> {
+    let renew_at = match threshold {
+        RenewalThreshold::Percentage(percentage) => {
+            not_before + (not_after - not_before) * i64::from(percentage) / 100
+        }
+        RenewalThreshold::DaysBeforeExpiry(days) => not_after - i64::from(days) * 24 * 60 * 60,
+    };
+
+    std::convert::TryInto::<u64>::try_into(renew_at)
+        .ok()
+        .filter(|&renew_at| renew_at > 0)
+        .map(std::time::Duration::from_secs)
+}
+
+/// Whether the key pair of the given cert may be rotated when renewing it.
+///
+/// See [`aziot_certd_config::CertIssuance::can_rotate_key`]. The key is also not rotated once moving new key pairs
+/// to its ID has failed [`MAX_KEY_MOVE_FAILURES`] times in a row.
+fn can_rotate_key(api: &Api, id: &str) -> bool {
+    let key_move_failures = api
+        .renewal_failures
+        .get(id)
+        .map_or(0, |failures| failures.key_moves);
+
+    key_move_failures < MAX_KEY_MOVE_FAILURES && api.cert_issuance.can_rotate_key(id)
+}
+
+/// Creates a PEM-encoded CSR for the given key pair with the subject and extensions of the given cert.
+fn create_csr(
+    current: &openssl::x509::X509Ref,
+    public_key: &openssl::pkey::PKeyRef<openssl::pkey::Public>,
+    private_key: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
+) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+    let mut csr = openssl::x509::X509Req::builder()?;
+    csr.set_version(0)?;
+    csr.set_subject_name(current.subject_name())?;
+
+    let extensions = extensions(current)?;
+    if !extensions.is_empty() {
+        csr.add_extensions(&extensions)?;
+    }
+
+    csr.set_pubkey(public_key)?;
+    csr.sign(private_key, crate::signing_digest(private_key))?;
+
+    let csr = csr.build();
+    let csr = csr.to_pem()?;
+    Ok(csr)
+}
+
+/// Returns the extensions of the given cert, except for the key identifiers since those are specific to the issued cert.
+fn extensions(
+    cert: &openssl::x509::X509Ref,
+) -> Result<openssl::stack::Stack<openssl::x509::X509Extension>, openssl::error::ErrorStack> {
+    let mut result = openssl::stack::Stack::new()?;
+
+    unsafe {
+        let cert = foreign_types_shared::ForeignTypeRef::as_ptr(cert);
+
+        for i in 0..openssl_sys::X509_get_ext_count(cert) {
+            let extension = openssl_sys::X509_get_ext(cert, i);
+            if extension.is_null() {
+                return Err(openssl::error::ErrorStack::get());
+            }
+
+            let nid = openssl_sys::OBJ_obj2nid(openssl_sys::X509_EXTENSION_get_object(extension));
+            let nid = openssl::nid::Nid::from_raw(nid);
+            if nid == openssl::nid::Nid::AUTHORITY_KEY_IDENTIFIER
+                || nid == openssl::nid::Nid::SUBJECT_KEY_IDENTIFIER
+            {
+                continue;
+            }
+
+            let extension = openssl_sys2::X509_EXTENSION_dup(extension);
+            if extension.is_null() {
+                return Err(openssl::error::ErrorStack::get());
+            }
+            let extension: openssl::x509::X509Extension =
+                foreign_types_shared::ForeignType::from_ptr(extension);
+            result.push(extension)?;
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use aziot_certd_config::RenewalThreshold;
+
+    use crate::{Error, InternalError};
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    #[test]
+    fn until_renewal() {
+        for &(not_before, not_after, threshold, expected) in &[
+            (-100, 900, RenewalThreshold::Percentage(80), Some(700)),
+            (-100, 900, RenewalThreshold::Percentage(100), Some(900)),
+            (-100, 900, RenewalThreshold::Percentage(10), None),
+            (-100, 900, RenewalThreshold::Percentage(0), None),
+            (-900, 100, RenewalThreshold::Percentage(80), None),
+            (
+                0,
+                30 * DAY,
+                RenewalThreshold::DaysBeforeExpiry(10),
+                Some(20 * 24 * 60 * 60),
+            ),
+            (0, 30 * DAY, RenewalThreshold::DaysBeforeExpiry(30), None),
+            (0, 30 * DAY, RenewalThreshold::DaysBeforeExpiry(40), None),
+            (-30 * DAY, -DAY, RenewalThreshold::DaysBeforeExpiry(0), None),
+        ] {
+            let expected = expected.map(std::time::Duration::from_secs);
+            assert_eq!(
+                expected,
+                super::until_renewal(not_before, not_after, threshold),
+                "{} {} {}",
+                not_before,
+                not_after,
+                threshold
+            );
+        }
+    }
+
+    #[test]
+    fn time_until_renewal() {
+        let private_key = ec_private_key(openssl::nid::Nid::X9_62_PRIME256V1);
+
+        let mut cert = openssl::x509::X509::builder().unwrap();
+        cert.set_pubkey(&private_key).unwrap();
+        cert.set_not_before(&openssl::asn1::Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&openssl::asn1::Asn1Time::days_from_now(10).unwrap())
+            .unwrap();
+        cert.sign(&private_key, openssl::hash::MessageDigest::sha256())
+            .unwrap();
+        let cert = cert.build();
+
+        let until_renewal = super::time_until_renewal(&cert, RenewalThreshold::Percentage(50))
+            .unwrap()
+            .unwrap();
+        assert!(until_renewal.as_secs() > 5 * 24 * 60 * 60 - 60);
+        assert!(until_renewal.as_secs() <= 5 * 24 * 60 * 60);
+
+        let until_renewal = super::time_until_renewal(&cert, RenewalThreshold::DaysBeforeExpiry(2))
+            .unwrap()
+            .unwrap();
+        assert!(until_renewal.as_secs() > 8 * 24 * 60 * 60 - 60);
+        assert!(until_renewal.as_secs() <= 8 * 24 * 60 * 60);
+
+        assert!(
+            super::time_until_renewal(&cert, RenewalThreshold::DaysBeforeExpiry(10))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn preferred_algorithm() {
+        let public_key = |private_key: openssl::pkey::PKey<openssl::pkey::Private>| {
+            openssl::pkey::PKey::public_key_from_der(&private_key.public_key_to_der().unwrap())
+                .unwrap()
+        };
+
+        for (private_key, expected) in vec![
+            (
+                ec_private_key(openssl::nid::Nid::X9_62_PRIME256V1),
+                Some("ec-p256"),
+            ),
+            (
+                ec_private_key(openssl::nid::Nid::SECP384R1),
+                Some("ec-p384"),
+            ),
+            (ec_private_key(openssl::nid::Nid::SECP521R1), None),
+            (
+                openssl::pkey::PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap(),
+                Some("rsa-2048"),
+            ),
+            (
+                openssl::pkey::PKey::from_rsa(openssl::rsa::Rsa::generate(3072).unwrap()).unwrap(),
+                None,
+            ),
+            (
+                openssl::pkey::PKey::generate_ed25519().unwrap(),
+                Some("ed25519"),
+            ),
+        ] {
+            assert_eq!(
+                expected,
+                super::preferred_algorithm(&public_key(private_key))
+            );
+        }
+    }
+
+    #[test]
+    fn new_key_id() {
+        let id1 = super::new_key_id().unwrap();
+        let id2 = super::new_key_id().unwrap();
+
+        assert!(id1.starts_with(aziot_certd_config::RENEWAL_KEY_ID_PREFIX));
+        assert_eq!(
+            aziot_certd_config::RENEWAL_KEY_ID_PREFIX.len() + 32,
+            id1.len()
+        );
+        assert_ne!(id1, id2);
+    }
+
+    #[test]
+    fn retry_interval() {
+        for &(failed_renewals, expected) in &[
+            (1, 5 * 60),
+            (2, 10 * 60),
+            (3, 20 * 60),
+            (4, 40 * 60),
+            (5, 60 * 60),
+            (32, 60 * 60),
+            (u32::MAX, 60 * 60),
+        ] {
+            assert_eq!(
+                std::time::Duration::from_secs(expected),
+                super::retry_interval(failed_renewals),
+                "{}",
+                failed_renewals
+            );
+        }
+    }
+
+    #[test]
+    fn finish_rotation() {
+        let key_pairs = KeyPairs::new(false);
+        let mut failures = super::Failures {
+            renewals: 0,
+            key_moves: 1,
+        };
+        let written = std::cell::RefCell::new(vec![]);
+        super::finish_rotation(
+            &key_pairs,
+            "key",
+            "new-key",
+            Ok(b"cert".to_vec()),
+            &mut failures,
+            |x509| {
+                written.borrow_mut().push(x509.to_vec());
+                key_pairs.calls.borrow_mut().push("write cert".to_owned());
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            vec!["move new-key to key", "write cert"],
+            *key_pairs.calls.borrow()
+        );
+        assert_eq!(vec![b"cert".to_vec()], *written.borrow());
+        assert_eq!(0, failures.key_moves);
+    }
+
+    #[test]
+    fn finish_rotation_rolls_back_if_issuing_fails() {
+        let key_pairs = KeyPairs::new(false);
+        let mut failures = super::Failures::default();
+        let result = super::finish_rotation(
+            &key_pairs,
+            "key",
+            "new-key",
+            Err(Error::Internal(InternalError::CreateCert(
+                "could not issue cert".into(),
+            ))),
+            &mut failures,
+            |_| panic!("cert must not be written"),
+        );
+        assert!(result.is_err());
+        assert_eq!(vec!["delete new-key"], *key_pairs.calls.borrow());
+        assert_eq!(0, failures.key_moves);
+    }
+
+    #[test]
+    fn finish_rotation_rolls_back_if_moving_fails() {
+        let key_pairs = KeyPairs::new(true);
+        let mut failures = super::Failures::default();
+        for _ in 0..super::MAX_KEY_MOVE_FAILURES {
+            let result = super::finish_rotation(
+                &key_pairs,
+                "key",
+                "new-key",
+                Ok(b"cert".to_vec()),
+                &mut failures,
+                |_| panic!("cert must not be written"),
+            );
+            assert!(result.is_err());
+        }
+        assert_eq!(
+            vec!["move new-key to key", "delete new-key"].repeat(3),
+            *key_pairs.calls.borrow()
+        );
+        assert_eq!(super::MAX_KEY_MOVE_FAILURES, failures.key_moves);
+    }
+
+    /// Records the calls made to it.
+    struct KeyPairs {
+        calls: std::cell::RefCell<Vec<String>>,
+        fail_move: bool,
+    }
+
+    impl KeyPairs {
+        fn new(fail_move: bool) -> Self {
+            KeyPairs {
+                calls: Default::default(),
+                fail_move,
+            }
+        }
+    }
+
+    impl super::KeyPairs for KeyPairs {
+        fn move_key_pair(&self, id: &str, to_id: &str) -> std::io::Result<()> {
+            self.calls
+                .borrow_mut()
+                .push(format!("move {} to {}", id, to_id));
+            if self.fail_move {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "could not move key pair",
+                ))
+            } else {
+                Ok(())
+            }
+        }
+
+        fn delete_key_pair(&self, id: &str) -> std::io::Result<()> {
+            self.calls.borrow_mut().push(format!("delete {}", id));
+            Ok(())
+        }
+    }
+
+    fn ec_private_key(curve: openssl::nid::Nid) -> openssl::pkey::PKey<openssl::pkey::Private> {
+        let group = openssl::ec::EcGroup::from_curve_name(curve).unwrap();
+        let ec_key = openssl::ec::EcKey::generate(&group).unwrap();
+        openssl::pkey::PKey::from_ec_key(ec_key).unwrap()
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Reading, writing and deleting certs at their [`CertLocation`].
+
+use aziot_certd_config::util::CertLocation;
+
+use crate::error::{Error, InternalError};
+
+lazy_static::lazy_static! {
+    static ref PKCS11_SESSION_POOLS: std::sync::Mutex<std::collections::BTreeMap<(std::path::PathBuf, String), std::sync::Arc<pkcs11::SessionPool>>> = Default::default();
+}
+
+/// The number of sessions that a PKCS#11 session pool keeps open while they're not in use.
+const PKCS11_MAX_IDLE_SESSIONS: usize = 2;
+
+/// Reads the PEM-encoded cert chain at the given location.
+///
+/// Returns `None` if there is no cert at the location.
+pub(crate) fn read(location: &CertLocation) -> Result<Option<Vec<u8>>, Error> {
+    match location {
+        CertLocation::File(path) => match std::fs::read(path) {
+            Ok(cert_bytes) => Ok(Some(cert_bytes)),
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Error::Internal(InternalError::ReadFile(err))),
+        },
+
+        CertLocation::Pkcs11 { lib_path, uri } => {
+            let certs = with_pkcs11_session(lib_path, uri, pkcs11::Session::get_cert)?;
+            let certs = match certs {
+                Some(certs) => certs,
+                None => return Ok(None),
+            };
+
+            let mut cert_bytes = vec![];
+            for cert in certs {
+                let cert = cert
+                    .to_pem()
+                    .map_err(|err| Error::Internal(InternalError::Pkcs11(Box::new(err))))?;
+                cert_bytes.extend_from_slice(&cert);
+            }
+
+            Ok(Some(cert_bytes))
+        }
+    }
+}
+
+/// Writes the PEM-encoded cert chain to the given location, replacing any existing cert.
+///
+/// Files are replaced atomically, so that readers see either the old or the new cert but never a partially-written one.
+/// Certs in a PKCS#11 token are only deleted once the new ones have all been imported, so a failed import keeps the old cert.
+pub(crate) fn write(location: &CertLocation, pem: &[u8]) -> Result<(), Error> {
+    match location {
+        CertLocation::File(path) => {
+            let mut temp_path = path.clone().into_os_string();
+            temp_path.push(".tmp");
+            let temp_path: std::path::PathBuf = temp_path.into();
+
+            std::fs::write(&temp_path, pem)
+                .and_then(|()| std::fs::rename(&temp_path, path))
+                .map_err(|err| Error::Internal(InternalError::CreateCert(Box::new(err))))
+        }
+
+        CertLocation::Pkcs11 { lib_path, uri } => {
+            let certs = openssl::x509::X509::stack_from_pem(pem)
+                .map_err(|err| Error::invalid_parameter("pem", err))?;
+
+            with_pkcs11_session(lib_path, uri, |session, label| {
+                session.import_cert(label, &certs)
+            })
+        }
+    }
+}
+
+/// Deletes the cert at the given location. Succeeds if there is no cert at the location.
+pub(crate) fn delete(location: &CertLocation) -> Result<(), Error> {
+    match location {
+        CertLocation::File(path) => match std::fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(Error::Internal(InternalError::DeleteFile(err))),
+        },
+
+        CertLocation::Pkcs11 { lib_path, uri } => {
+            with_pkcs11_session(lib_path, uri, pkcs11::Session::delete_cert)
+        }
+    }
+}
+
+/// Runs `f` with a session against the token identified by the given URI, and the URI's object label.
+///
+/// Sessions come from a pool per token, so they're only opened and logged in to once and not for every operation.
+/// If the session was lost, such as because the device was removed and reinserted, `f` is retried with a new session.
+fn with_pkcs11_session<T, E>(
+    lib_path: &std::path::Path,
+    uri: &url::Url,
+    mut f: impl FnMut(&pkcs11::Session, &str) -> Result<T, E>,
+) -> Result<T, Error>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    // ParsePkcs11UriError isn't Send + Sync, so only its message is kept.
+    let uri: pkcs11::Uri = uri.as_str().parse().map_err(|err| {
+        Error::Internal(InternalError::Pkcs11(
+            format!("could not parse PKCS#11 URI: {}", err).into(),
+        ))
+    })?;
+    let label = uri.object_label.clone().ok_or_else(|| {
+        Error::Internal(InternalError::Pkcs11(
+            "PKCS#11 URI does not specify an object label".into(),
+        ))
+    })?;
+
+    let session_pool = pkcs11_session_pool(lib_path, uri)?;
+
+    let (result, retried) = session_pool.with_session(|session| f(&session, &label));
+    if retried {
+        log::warn!(
+            "PKCS#11 session to {} was lost and has been reopened: {:?}",
+            lib_path.display(),
+            session_pool.metrics(),
+        );
+    }
+
+    result.map_err(|err| Error::Internal(InternalError::Pkcs11(Box::new(err))))
+}
+
+/// Gets the session pool for the slot identified by the given URI, creating it if this is the first use of the slot.
+///
+/// The pools live as long as the process, so that their idle sessions stay logged in.
+fn pkcs11_session_pool(
+    lib_path: &std::path::Path,
+    uri: pkcs11::Uri,
+) -> Result<std::sync::Arc<pkcs11::SessionPool>, Error> {
+    let slot_uri = pkcs11::Uri {
+        object_label: None,
+        ..uri
+    };
+
+    let mut pkcs11_session_pools = PKCS11_SESSION_POOLS
+        .lock()
+        .expect("session pools mutex poisoned");
+    match pkcs11_session_pools.entry((lib_path.to_owned(), slot_uri.to_string())) {
+        std::collections::btree_map::Entry::Occupied(entry) => Ok(entry.get().clone()),
+
+        std::collections::btree_map::Entry::Vacant(entry) => {
+            let context = pkcs11::Context::load(lib_path.to_owned())
+                .map_err(|err| Error::Internal(InternalError::Pkcs11(Box::new(err))))?;
+            let session_pool = pkcs11::SessionPool::new(
+                context,
+                slot_uri.slot_identifier,
+                slot_uri.pin,
+                PKCS11_MAX_IDLE_SESSIONS,
+            );
+            Ok(entry.insert(std::sync::Arc::new(session_pool)).clone())
+        }
+    }
+}
@@ -97,14 +97,16 @@ pub enum MaybeProxyConnector<C> {
 }
 
 impl MaybeProxyConnector<hyper_openssl::HttpsConnector<hyper::client::HttpConnector>> {
+    /// Creates a connector that optionally goes through the given proxy.
+    ///
+    /// If `identity` is set, it is used as the client cert for TLS connections. Any `trusted_certs` are trusted
+    /// in addition to the system's default trusted roots.
     pub fn new(
         proxy_uri: Option<hyper::Uri>,
         identity: Option<(&openssl::pkey::PKeyRef<openssl::pkey::Private>, &[u8])>,
         trusted_certs: &[openssl::x509::X509],
     ) -> io::Result<Self> {
-        let https_connector = if let Some((key, certs)) = identity {
-            let tls_connector = identity_to_tls_connector(key, certs, trusted_certs)?;
-
+        let https_connector = if let Some(tls_connector) = tls_connector(identity, trusted_certs)? {
             let mut http_connector = hyper::client::HttpConnector::new();
             http_connector.enforce_http(false);
             hyper_openssl::HttpsConnector::with_connector(http_connector, tls_connector)?
@@ -114,13 +116,11 @@ impl MaybeProxyConnector<hyper_openssl::HttpsConnector<hyper::client::HttpConnec
 
         if let Some(proxy_uri) = proxy_uri {
             let proxy = uri_to_proxy(proxy_uri)?;
-            let proxy_connector = match identity {
+            let proxy_connector = match tls_connector(identity, trusted_certs)? {
                 None => hyper_proxy::ProxyConnector::from_proxy(https_connector, proxy)?,
-                Some((key, certs)) => {
+                Some(proxy_tls_connector) => {
                     // DEVNOTE: SslConnectionBuilder::build() consumes the builder. So, we need
                     //          to create two copies of it.
-                    let proxy_tls_connector = identity_to_tls_connector(key, certs, trusted_certs)?;
-
                     let mut proxy_connector =
                         hyper_proxy::ProxyConnector::from_proxy(https_connector, proxy)?;
                     proxy_connector.set_tls(Some(proxy_tls_connector.build()));
@@ -134,6 +134,29 @@ impl MaybeProxyConnector<hyper_openssl::HttpsConnector<hyper::client::HttpConnec
     }
 }
 
+/// Returns `None` if neither an identity nor any trusted certs are given, in which case the default TLS connector
+/// should be used.
+fn tls_connector(
+    identity: Option<(&openssl::pkey::PKeyRef<openssl::pkey::Private>, &[u8])>,
+    trusted_certs: &[openssl::x509::X509],
+) -> io::Result<Option<openssl::ssl::SslConnectorBuilder>> {
+    match identity {
+        Some((key, certs)) => Ok(Some(identity_to_tls_connector(key, certs, trusted_certs)?)),
+        None if trusted_certs.is_empty() => Ok(None),
+        None => {
+            let mut tls_connector =
+                openssl::ssl::SslConnector::builder(openssl::ssl::SslMethod::tls())?;
+
+            let cert_store = tls_connector.cert_store_mut();
+            for trusted_cert in trusted_certs {
+                cert_store.add_cert(trusted_cert.clone())?;
+            }
+
+            Ok(Some(tls_connector))
+        }
+    }
+}
+
 fn identity_to_tls_connector(
     key: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
     certs: &[u8],
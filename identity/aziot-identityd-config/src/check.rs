@@ -56,6 +56,18 @@ impl Settings {
             }
         }
 
+        for path in &self.trust_bundle.files {
+            if !path.is_absolute() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "invalid config for trust bundle: {} is not an absolute path",
+                        path.display()
+                    ),
+                ));
+            }
+        }
+
         Ok(self)
     }
 }
@@ -24,6 +24,10 @@ pub struct Settings {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub localid: Option<LocalId>,
+
+    /// Certificates returned to callers of the trust bundle API.
+    #[serde(default, skip_serializing_if = "TrustBundle::is_empty")]
+    pub trust_bundle: TrustBundle,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize)]
@@ -54,6 +58,28 @@ pub struct LocalId {
     pub domain: String,
 }
 
+/// Sources of the certificates that make up the trust bundle.
+///
+/// The certificates from all sources are concatenated in the order they are listed here,
+/// with duplicate certificates removed.
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct TrustBundle {
+    /// IDs of certificates in the certificates service.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub certs: Vec<String>,
+
+    /// Paths of PEM files on the filesystem.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<std::path::PathBuf>,
+}
+
+impl TrustBundle {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.certs.is_empty() && self.files.is_empty()
+    }
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 #[serde(tag = "method")]
 #[serde(rename_all = "lowercase")]
@@ -142,7 +168,7 @@ impl Default for Endpoints {
 
 #[cfg(test)]
 mod tests {
-    use super::{DpsAttestationMethod, ManualAuthMethod, ProvisioningType, Settings};
+    use super::{DpsAttestationMethod, ManualAuthMethod, ProvisioningType, Settings, TrustBundle};
 
     fn load_settings(
         filename: impl AsRef<std::path::Path>,
@@ -182,6 +208,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn trust_bundle_settings_succeeds() {
+        let s = load_settings("test/good_sas_config.toml").unwrap();
+        assert_eq!(s.trust_bundle, TrustBundle::default());
+
+        let s = load_settings("test/good_trust_bundle_config.toml").unwrap();
+        assert_eq!(
+            s.trust_bundle,
+            TrustBundle {
+                certs: vec!["aziot-edged-trust-bundle".to_owned()],
+                files: vec!["/etc/ssl/certs/contoso-root-ca.pem".into()],
+            }
+        );
+    }
+
     #[test]
     fn bad_provisioning_settings_fails() {
         assert!(
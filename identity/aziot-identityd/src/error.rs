@@ -66,6 +66,7 @@ pub enum InternalError {
     CreateCertificate(Box<dyn std::error::Error + Send + Sync>),
     CreateHomeDir(std::io::Error),
     GetModulePath(Box<dyn std::error::Error + Send + Sync>),
+    GetTrustBundle(Box<dyn std::error::Error + Send + Sync>),
     InvalidProxyUri(Box<dyn std::error::Error + Send + Sync>),
     InvalidUri(http::uri::InvalidUri),
     LoadKeyOpensslEngine(openssl2::Error),
@@ -87,6 +88,7 @@ impl std::fmt::Display for InternalError {
             InternalError::CreateCertificate(_) => f.write_str("could not create certificate"),
             InternalError::CreateHomeDir(_) => f.write_str("could not create home directory"),
             InternalError::GetModulePath(_) => f.write_str("could not get module backup file path"),
+            InternalError::GetTrustBundle(_) => f.write_str("could not get trust bundle"),
             InternalError::InvalidProxyUri(_) => f.write_str("invalid proxy uri"),
             InternalError::InvalidUri(_) => f.write_str("invalid resource uri"),
             InternalError::LoadKeyOpensslEngine(_) => {
@@ -123,6 +125,7 @@ impl std::error::Error for InternalError {
             InternalError::CreateCertificate(err) => Some(&**err),
             InternalError::CreateHomeDir(err) => Some(err),
             InternalError::GetModulePath(err) => Some(&**err),
+            InternalError::GetTrustBundle(err) => Some(&**err),
             InternalError::InvalidProxyUri(err) => Some(&**err),
             InternalError::InvalidUri(err) => Some(err),
             InternalError::LoadKeyOpensslEngine(err) => Some(err),
@@ -760,16 +760,14 @@ impl IdentityManager {
                     Some(pem)
                 }
             }
-            Err(_) => {
-                // TODO: Need to check if key exists.
-                // If this function fails, delete any key it creates but don't delete an existing key.
-
-                None
-            }
+            Err(_) => None,
         };
 
         // Create new certificate if needed.
         if device_id_cert.is_none() {
+            // If creating the certificate fails, delete the key if it was created here, but don't delete an existing key.
+            let key_existed = self.key_client.load_key_pair(identity_pk).await.is_ok();
+
             let key_handle = self
                 .key_client
                 .create_key_pair_if_not_exists(identity_pk, Some("rsa-2048:*"))
@@ -804,7 +802,15 @@ impl IdentityManager {
             .await;
 
             if let Err(err) = result {
-                // TODO: need to delete key from keyd.
+                if !key_existed {
+                    if let Err(delete_err) = self.key_client.delete_key_pair(identity_pk).await {
+                        log::warn!(
+                            "Could not delete key pair {} after failing to create certificate: {}",
+                            identity_pk,
+                            delete_err
+                        );
+                    }
+                }
 
                 return Err(err);
             }
@@ -354,10 +354,30 @@ impl Api {
             return Err(Error::Authorization);
         }
 
-        //TODO: invoke get trust bundle
-        Ok(aziot_cert_common_http::Pem {
-            0: std::vec::Vec::default(),
-        })
+        // The certs are fetched on every request rather than cached, so that changes to the certs
+        // in certd or on the filesystem are reflected in the response immediately.
+        let mut pems = vec![];
+
+        for cert_id in &self.settings.trust_bundle.certs {
+            let pem = self
+                .cert_client
+                .get_cert(cert_id)
+                .await
+                .map_err(|err| Error::Internal(InternalError::GetTrustBundle(Box::new(err))))?;
+            pems.push(pem);
+        }
+
+        for path in &self.settings.trust_bundle.files {
+            let pem = tokio::fs::read(path)
+                .await
+                .map_err(|err| Error::Internal(InternalError::GetTrustBundle(Box::new(err))))?;
+            pems.push(pem);
+        }
+
+        let trust_bundle = concat_trust_bundle(&pems)
+            .map_err(|err| Error::Internal(InternalError::GetTrustBundle(Box::new(err))))?;
+
+        Ok(aziot_cert_common_http::Pem(trust_bundle))
     }
 
     pub async fn reprovision_device(
@@ -622,6 +642,22 @@ pub(crate) fn create_csr(
     Ok(csr)
 }
 
+/// Concatenates the certs in the given PEM blobs, skipping any cert that has already been seen.
+fn concat_trust_bundle(pems: &[Vec<u8>]) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+    let mut seen = std::collections::BTreeSet::new();
+    let mut trust_bundle = vec![];
+
+    for pem in pems {
+        for cert in openssl::x509::X509::stack_from_pem(pem)? {
+            if seen.insert(cert.to_der()?) {
+                trust_bundle.extend_from_slice(&cert.to_pem()?);
+            }
+        }
+    }
+
+    Ok(trust_bundle)
+}
+
 pub struct SettingsAuthenticator {
     pub allowed_users: std::collections::BTreeMap<config::Uid, config::Principal>,
 }
@@ -707,6 +743,30 @@ mod tests {
 
     use crate::configext::prepare_authorized_principals;
 
+    fn self_signed_cert(common_name: &str) -> Vec<u8> {
+        let rsa = openssl::rsa::Rsa::generate(2048).unwrap();
+        let private_key = openssl::pkey::PKey::from_rsa(rsa).unwrap();
+
+        let mut name = openssl::x509::X509Name::builder().unwrap();
+        name.append_entry_by_nid(openssl::nid::Nid::COMMONNAME, common_name)
+            .unwrap();
+        let name = name.build();
+
+        let mut cert = openssl::x509::X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&private_key).unwrap();
+        let not_before = openssl::asn1::Asn1Time::days_from_now(0).unwrap();
+        cert.set_not_before(&not_before).unwrap();
+        let not_after = openssl::asn1::Asn1Time::days_from_now(30).unwrap();
+        cert.set_not_after(&not_after).unwrap();
+        cert.sign(&private_key, openssl::hash::MessageDigest::sha256())
+            .unwrap();
+
+        cert.build().to_pem().unwrap()
+    }
+
     #[test]
     fn convert_to_map_creates_principal_lookup() {
         let local_p: Principal = Principal {
@@ -825,6 +885,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn concat_trust_bundle_removes_duplicates() {
+        let cert1 = self_signed_cert("root1");
+        let cert2 = self_signed_cert("root2");
+
+        let mut bundle = cert1.clone();
+        bundle.extend_from_slice(&cert2);
+
+        let trust_bundle =
+            super::concat_trust_bundle(&[bundle, cert2.clone(), cert1.clone()]).unwrap();
+
+        let mut expected = cert1;
+        expected.extend_from_slice(&cert2);
+        assert_eq!(trust_bundle, expected);
+
+        assert!(super::concat_trust_bundle(&[]).unwrap().is_empty());
+    }
+
     #[test]
     fn empty_auth_settings_deny_any_action() {
         let auth = SettingsAuthorizer {};
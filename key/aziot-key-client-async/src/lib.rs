@@ -27,6 +27,35 @@ impl Client {
         let body = aziot_key_common_http::create_key_pair_if_not_exists::Request {
             id: id.to_owned(),
             preferred_algorithms: preferred_algorithms.map(ToOwned::to_owned),
+            import_key_pair_bytes: None,
+            import_format: None,
+            import_password: None,
+        };
+
+        let res: aziot_key_common_http::create_key_pair_if_not_exists::Response =
+            http_common::request(
+                &self.inner,
+                http::Method::POST,
+                &format!("http://keyd.sock/keypair?api-version={}", self.api_version),
+                Some(&body),
+            )
+            .await?;
+        Ok(res.handle)
+    }
+
+    pub async fn import_key_pair(
+        &self,
+        id: &str,
+        bytes: &[u8],
+        format: aziot_key_common::KeyPairFormat,
+        password: Option<&str>,
+    ) -> std::io::Result<aziot_key_common::KeyHandle> {
+        let body = aziot_key_common_http::create_key_pair_if_not_exists::Request {
+            id: id.to_owned(),
+            preferred_algorithms: None,
+            import_key_pair_bytes: Some(http_common::ByteString(bytes.to_owned())),
+            import_format: Some(format),
+            import_password: password.map(ToOwned::to_owned),
         };
 
         let res: aziot_key_common_http::create_key_pair_if_not_exists::Response =
@@ -58,6 +87,47 @@ impl Client {
         Ok(res.handle)
     }
 
+    pub async fn delete_key_pair(&self, id: &str) -> std::io::Result<()> {
+        let () = http_common::request_no_content::<()>(
+            &self.inner,
+            http::Method::DELETE,
+            &format!(
+                "http://keyd.sock/keypair/{}?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Moves the key pair `id` to `to_id`, replacing the key pair with that ID if it exists.
+    pub async fn move_key_pair(&self, id: &str, to_id: &str) -> std::io::Result<()> {
+        let body = aziot_key_common_http::move_key_pair::Request {
+            to_id: to_id.to_owned(),
+        };
+
+        let () = http_common::request_no_content(
+            &self.inner,
+            http::Method::POST,
+            &format!(
+                "http://keyd.sock/keypair/{}/move?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            Some(&body),
+        )
+        .await?;
+        Ok(())
+    }
+
     pub async fn get_key_pair_public_parameter(
         &self,
         handle: &aziot_key_common::KeyHandle,
@@ -136,6 +206,35 @@ impl Client {
         Ok(res.handle)
     }
 
+    pub async fn delete_key(&self, id: &str) -> std::io::Result<()> {
+        let () = http_common::request_no_content::<()>(
+            &self.inner,
+            http::Method::DELETE,
+            &format!(
+                "http://keyd.sock/key/{}?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn list_keys(&self) -> std::io::Result<Vec<aziot_key_common::KeyInfo>> {
+        let res: aziot_key_common_http::list_keys::Response = http_common::request::<(), _>(
+            &self.inner,
+            http::Method::GET,
+            &format!("http://keyd.sock/keys?api-version={}", self.api_version),
+            None,
+        )
+        .await?;
+        Ok(res.keys.into_iter().map(Into::into).collect())
+    }
+
     pub async fn create_derived_key(
         &self,
         base_handle: &aziot_key_common::KeyHandle,
@@ -144,6 +243,34 @@ impl Client {
         let body = aziot_key_common_http::create_derived_key::Request {
             base_handle: base_handle.clone(),
             derivation_data: http_common::ByteString(derivation_data.to_owned()),
+            mechanism: None,
+        };
+
+        let res: aziot_key_common_http::create_derived_key::Response = http_common::request(
+            &self.inner,
+            http::Method::POST,
+            &format!(
+                "http://keyd.sock/derivedkey?api-version={}",
+                self.api_version
+            ),
+            Some(&body),
+        )
+        .await?;
+        Ok(res.handle)
+    }
+
+    /// Creates a derived key like [`Client::create_derived_key`], but with the given derivation mechanism
+    /// instead of the default HMAC-SHA256.
+    pub async fn create_derived_key_with_mechanism(
+        &self,
+        base_handle: &aziot_key_common::KeyHandle,
+        derivation_data: &[u8],
+        mechanism: aziot_key_common::KeyDerivationMechanism,
+    ) -> std::io::Result<aziot_key_common::KeyHandle> {
+        let body = aziot_key_common_http::create_derived_key::Request {
+            base_handle: base_handle.clone(),
+            derivation_data: http_common::ByteString(derivation_data.to_owned()),
+            mechanism: Some(key_derivation_mechanism(mechanism)),
         };
 
         let res: aziot_key_common_http::create_derived_key::Response = http_common::request(
@@ -180,6 +307,122 @@ impl Client {
         Ok(res.key.0)
     }
 
+    pub async fn export_key(
+        &self,
+        id: &str,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapping_key: &[u8],
+    ) -> std::io::Result<Vec<u8>> {
+        let body = aziot_key_common_http::export_key::Request {
+            mechanism,
+            wrapping_key: http_common::ByteString(wrapping_key.to_owned()),
+        };
+
+        let res: aziot_key_common_http::export_key::Response = http_common::request(
+            &self.inner,
+            http::Method::POST,
+            &format!(
+                "http://keyd.sock/key/{}/export?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            Some(&body),
+        )
+        .await?;
+        Ok(res.wrapped_key.0)
+    }
+
+    pub async fn export_key_pair(
+        &self,
+        id: &str,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapping_key: &[u8],
+    ) -> std::io::Result<Vec<u8>> {
+        let body = aziot_key_common_http::export_key::Request {
+            mechanism,
+            wrapping_key: http_common::ByteString(wrapping_key.to_owned()),
+        };
+
+        let res: aziot_key_common_http::export_key::Response = http_common::request(
+            &self.inner,
+            http::Method::POST,
+            &format!(
+                "http://keyd.sock/keypair/{}/export?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            Some(&body),
+        )
+        .await?;
+        Ok(res.wrapped_key.0)
+    }
+
+    pub async fn import_wrapped_key(
+        &self,
+        id: &str,
+        unwrapping_key_handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapped_key: &[u8],
+    ) -> std::io::Result<aziot_key_common::KeyHandle> {
+        let body = aziot_key_common_http::import_wrapped_key::Request {
+            mechanism,
+            unwrapping_key_handle: unwrapping_key_handle.clone(),
+            wrapped_key: http_common::ByteString(wrapped_key.to_owned()),
+        };
+
+        let res: aziot_key_common_http::import_wrapped_key::Response = http_common::request(
+            &self.inner,
+            http::Method::POST,
+            &format!(
+                "http://keyd.sock/key/{}/import?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            Some(&body),
+        )
+        .await?;
+        Ok(res.handle)
+    }
+
+    pub async fn import_wrapped_key_pair(
+        &self,
+        id: &str,
+        unwrapping_key_handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapped_key: &[u8],
+    ) -> std::io::Result<aziot_key_common::KeyHandle> {
+        let body = aziot_key_common_http::import_wrapped_key::Request {
+            mechanism,
+            unwrapping_key_handle: unwrapping_key_handle.clone(),
+            wrapped_key: http_common::ByteString(wrapped_key.to_owned()),
+        };
+
+        let res: aziot_key_common_http::import_wrapped_key::Response = http_common::request(
+            &self.inner,
+            http::Method::POST,
+            &format!(
+                "http://keyd.sock/keypair/{}/import?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            Some(&body),
+        )
+        .await?;
+        Ok(res.handle)
+    }
+
     pub async fn sign(
         &self,
         handle: &aziot_key_common::KeyHandle,
@@ -195,11 +438,45 @@ impl Client {
                     }
                 }
 
+                aziot_key_common::SignMechanism::Eddsa => {
+                    aziot_key_common_http::sign::Parameters::Eddsa {
+                        message: http_common::ByteString(digest.to_owned()),
+                    }
+                }
+
+                aziot_key_common::SignMechanism::RsaPkcs1 {
+                    digest: digest_algorithm,
+                } => aziot_key_common_http::sign::Parameters::RsaPkcs1 {
+                    digest_algorithm,
+                    digest: http_common::ByteString(digest.to_owned()),
+                },
+
+                aziot_key_common::SignMechanism::RsaPss {
+                    digest: digest_algorithm,
+                    salt_len,
+                } => aziot_key_common_http::sign::Parameters::RsaPss {
+                    digest_algorithm,
+                    salt_len,
+                    digest: http_common::ByteString(digest.to_owned()),
+                },
+
                 aziot_key_common::SignMechanism::HmacSha256 => {
                     aziot_key_common_http::sign::Parameters::HmacSha256 {
                         message: http_common::ByteString(digest.to_owned()),
                     }
                 }
+
+                aziot_key_common::SignMechanism::HmacSha384 => {
+                    aziot_key_common_http::sign::Parameters::HmacSha384 {
+                        message: http_common::ByteString(digest.to_owned()),
+                    }
+                }
+
+                aziot_key_common::SignMechanism::HmacSha512 => {
+                    aziot_key_common_http::sign::Parameters::HmacSha512 {
+                        message: http_common::ByteString(digest.to_owned()),
+                    }
+                }
             },
         };
 
@@ -214,6 +491,81 @@ impl Client {
         Ok(signature)
     }
 
+    /// Performs a key agreement between the given key pair and the peer public key, and returns the raw shared secret.
+    ///
+    /// `peer_public_key` is a DER-encoded `SubjectPublicKeyInfo`.
+    pub async fn derive_shared_secret(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::KeyAgreementMechanism,
+        peer_public_key: &[u8],
+    ) -> std::io::Result<Vec<u8>> {
+        let body = aziot_key_common_http::derive_shared_secret::Request {
+            key_handle: handle.clone(),
+            peer_public_key: http_common::ByteString(peer_public_key.to_owned()),
+            parameters: key_agreement_parameters(mechanism),
+            key_id: None,
+            usage: vec![],
+        };
+
+        let res: aziot_key_common_http::derive_shared_secret::Response = http_common::request(
+            &self.inner,
+            http::Method::POST,
+            &format!(
+                "http://keyd.sock/sharedsecret?api-version={}",
+                self.api_version
+            ),
+            Some(&body),
+        )
+        .await?;
+        let shared_secret = res.shared_secret.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "response does not contain shared secret",
+            )
+        })?;
+        Ok(shared_secret.0)
+    }
+
+    /// Performs a key agreement between the given key pair and the peer public key, and stores the shared secret
+    /// as a new key with the given ID and usage.
+    ///
+    /// `peer_public_key` is a DER-encoded `SubjectPublicKeyInfo`.
+    pub async fn derive_shared_secret_key(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::KeyAgreementMechanism,
+        peer_public_key: &[u8],
+        id: &str,
+        usage: &[aziot_key_common::KeyUsage],
+    ) -> std::io::Result<aziot_key_common::KeyHandle> {
+        let body = aziot_key_common_http::derive_shared_secret::Request {
+            key_handle: handle.clone(),
+            peer_public_key: http_common::ByteString(peer_public_key.to_owned()),
+            parameters: key_agreement_parameters(mechanism),
+            key_id: Some(id.to_owned()),
+            usage: usage.to_owned(),
+        };
+
+        let res: aziot_key_common_http::derive_shared_secret::Response = http_common::request(
+            &self.inner,
+            http::Method::POST,
+            &format!(
+                "http://keyd.sock/sharedsecret?api-version={}",
+                self.api_version
+            ),
+            Some(&body),
+        )
+        .await?;
+        let key_handle = res.key_handle.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "response does not contain key handle",
+            )
+        })?;
+        Ok(key_handle)
+    }
+
     pub async fn encrypt(
         &self,
         handle: &aziot_key_common::KeyHandle,
@@ -237,6 +589,26 @@ impl Client {
                 aziot_key_common::EncryptMechanism::RsaNoPadding => {
                     aziot_key_common_http::encrypt::Parameters::RsaNoPadding
                 }
+
+                aziot_key_common::EncryptMechanism::AesCbcPad { iv } => {
+                    aziot_key_common_http::encrypt::Parameters::AesCbcPad {
+                        iv: http_common::ByteString(iv),
+                    }
+                }
+
+                aziot_key_common::EncryptMechanism::AesCtr { iv } => {
+                    aziot_key_common_http::encrypt::Parameters::AesCtr {
+                        iv: http_common::ByteString(iv),
+                    }
+                }
+
+                aziot_key_common::EncryptMechanism::AesKeyWrap => {
+                    aziot_key_common_http::encrypt::Parameters::AesKeyWrap
+                }
+
+                aziot_key_common::EncryptMechanism::AesKeyWrapPad => {
+                    aziot_key_common_http::encrypt::Parameters::AesKeyWrapPad
+                }
             },
             plaintext: http_common::ByteString(plaintext.to_owned()),
         };
@@ -275,6 +647,26 @@ impl Client {
                 aziot_key_common::EncryptMechanism::RsaNoPadding => {
                     aziot_key_common_http::encrypt::Parameters::RsaNoPadding
                 }
+
+                aziot_key_common::EncryptMechanism::AesCbcPad { iv } => {
+                    aziot_key_common_http::encrypt::Parameters::AesCbcPad {
+                        iv: http_common::ByteString(iv),
+                    }
+                }
+
+                aziot_key_common::EncryptMechanism::AesCtr { iv } => {
+                    aziot_key_common_http::encrypt::Parameters::AesCtr {
+                        iv: http_common::ByteString(iv),
+                    }
+                }
+
+                aziot_key_common::EncryptMechanism::AesKeyWrap => {
+                    aziot_key_common_http::encrypt::Parameters::AesKeyWrap
+                }
+
+                aziot_key_common::EncryptMechanism::AesKeyWrapPad => {
+                    aziot_key_common_http::encrypt::Parameters::AesKeyWrapPad
+                }
             },
             ciphertext: http_common::ByteString(ciphertext.to_owned()),
         };
@@ -289,4 +681,255 @@ impl Client {
         let plaintext = res.plaintext.0;
         Ok(plaintext)
     }
+
+    pub async fn rotate_handle_validation_key(&self) -> std::io::Result<()> {
+        let () = http_common::request_no_content::<()>(
+            &self.inner,
+            http::Method::POST,
+            &format!(
+                "http://keyd.sock/handlevalidationkey/rotate?api-version={}",
+                self.api_version
+            ),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+    /// Starts a multi-part encryption, and returns the ID of the new stream.
+    ///
+    /// Pass the plaintext to [`Client::update_stream`] in parts. The ciphertext is identical to what [`Client::encrypt`] returns for the whole plaintext.
+    pub async fn create_encrypt_stream(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::EncryptMechanism,
+    ) -> std::io::Result<String> {
+        self.create_encrypt_stream_inner("encrypt", handle, mechanism)
+            .await
+    }
+
+    /// Starts a multi-part decryption, and returns the ID of the new stream.
+    ///
+    /// For AEAD, the plaintext returned by [`Client::update_stream`] is only authenticated once the final update succeeds.
+    pub async fn create_decrypt_stream(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::EncryptMechanism,
+    ) -> std::io::Result<String> {
+        self.create_encrypt_stream_inner("decrypt", handle, mechanism)
+            .await
+    }
+
+    async fn create_encrypt_stream_inner(
+        &self,
+        operation: &str,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::EncryptMechanism,
+    ) -> std::io::Result<String> {
+        let body = aziot_key_common_http::create_stream::EncryptRequest {
+            key_handle: handle.clone(),
+            parameters: encrypt_parameters(mechanism),
+        };
+
+        let res: aziot_key_common_http::create_stream::Response = http_common::request(
+            &self.inner,
+            http::Method::POST,
+            &format!(
+                "http://keyd.sock/{}/stream?api-version={}",
+                operation, self.api_version
+            ),
+            Some(&body),
+        )
+        .await?;
+        Ok(res.stream_id)
+    }
+
+    /// Starts a multi-part HMAC signature, and returns the ID of the new stream.
+    ///
+    /// The signature is returned by the final [`Client::update_stream`].
+    pub async fn create_sign_stream(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::SignMechanism,
+    ) -> std::io::Result<String> {
+        let body = aziot_key_common_http::create_stream::SignRequest {
+            key_handle: handle.clone(),
+            parameters: sign_stream_parameters(mechanism)?,
+        };
+
+        let res: aziot_key_common_http::create_stream::Response = http_common::request(
+            &self.inner,
+            http::Method::POST,
+            &format!(
+                "http://keyd.sock/sign/stream?api-version={}",
+                self.api_version
+            ),
+            Some(&body),
+        )
+        .await?;
+        Ok(res.stream_id)
+    }
+
+    /// Passes the next part of the input to the given stream, and returns the output produced so far.
+    ///
+    /// If `is_final` is set, the operation is completed and the stream is closed.
+    pub async fn update_stream(
+        &self,
+        stream_id: &str,
+        data: &[u8],
+        is_final: bool,
+    ) -> std::io::Result<Vec<u8>> {
+        let body = aziot_key_common_http::update_stream::Request {
+            data: http_common::ByteString(data.to_owned()),
+            is_final,
+        };
+
+        let res: aziot_key_common_http::update_stream::Response = http_common::request(
+            &self.inner,
+            http::Method::POST,
+            &format!(
+                "http://keyd.sock/streams/{}?api-version={}",
+                percent_encoding::percent_encode(
+                    stream_id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            Some(&body),
+        )
+        .await?;
+        Ok(res.output.0)
+    }
+
+    /// Abandons the given stream without completing its operation.
+    pub async fn abort_stream(&self, stream_id: &str) -> std::io::Result<()> {
+        let () = http_common::request_no_content::<()>(
+            &self.inner,
+            http::Method::DELETE,
+            &format!(
+                "http://keyd.sock/streams/{}?api-version={}",
+                percent_encoding::percent_encode(
+                    stream_id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+fn encrypt_parameters(
+    mechanism: aziot_key_common::EncryptMechanism,
+) -> aziot_key_common_http::encrypt::Parameters {
+    match mechanism {
+        aziot_key_common::EncryptMechanism::Aead { iv, aad } => {
+            aziot_key_common_http::encrypt::Parameters::Aead {
+                iv: http_common::ByteString(iv),
+                aad: http_common::ByteString(aad),
+            }
+        }
+
+        aziot_key_common::EncryptMechanism::RsaPkcs1 => {
+            aziot_key_common_http::encrypt::Parameters::RsaPkcs1
+        }
+
+        aziot_key_common::EncryptMechanism::RsaNoPadding => {
+            aziot_key_common_http::encrypt::Parameters::RsaNoPadding
+        }
+
+        aziot_key_common::EncryptMechanism::AesCbcPad { iv } => {
+            aziot_key_common_http::encrypt::Parameters::AesCbcPad {
+                iv: http_common::ByteString(iv),
+            }
+        }
+
+        aziot_key_common::EncryptMechanism::AesCtr { iv } => {
+            aziot_key_common_http::encrypt::Parameters::AesCtr {
+                iv: http_common::ByteString(iv),
+            }
+        }
+
+        aziot_key_common::EncryptMechanism::AesKeyWrap => {
+            aziot_key_common_http::encrypt::Parameters::AesKeyWrap
+        }
+
+        aziot_key_common::EncryptMechanism::AesKeyWrapPad => {
+            aziot_key_common_http::encrypt::Parameters::AesKeyWrapPad
+        }
+    }
+}
+
+fn sign_stream_parameters(
+    mechanism: aziot_key_common::SignMechanism,
+) -> std::io::Result<aziot_key_common_http::create_stream::SignParameters> {
+    match mechanism {
+        aziot_key_common::SignMechanism::HmacSha256 => {
+            Ok(aziot_key_common_http::create_stream::SignParameters::HmacSha256)
+        }
+
+        aziot_key_common::SignMechanism::HmacSha384 => {
+            Ok(aziot_key_common_http::create_stream::SignParameters::HmacSha384)
+        }
+
+        aziot_key_common::SignMechanism::HmacSha512 => {
+            Ok(aziot_key_common_http::create_stream::SignParameters::HmacSha512)
+        }
+
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "mechanism does not support multi-part operations",
+        )),
+    }
+}
+
+fn key_derivation_mechanism(
+    mechanism: aziot_key_common::KeyDerivationMechanism,
+) -> aziot_key_common_http::create_derived_key::Mechanism {
+    match mechanism {
+        aziot_key_common::KeyDerivationMechanism::HmacSha256 => {
+            aziot_key_common_http::create_derived_key::Mechanism::HmacSha256
+        }
+
+        aziot_key_common::KeyDerivationMechanism::Hkdf { digest, salt, len } => {
+            aziot_key_common_http::create_derived_key::Mechanism::Hkdf {
+                digest_algorithm: digest,
+                salt: http_common::ByteString(salt),
+                length: len,
+            }
+        }
+
+        aziot_key_common::KeyDerivationMechanism::KbkdfCounter {
+            digest,
+            context,
+            len,
+        } => aziot_key_common_http::create_derived_key::Mechanism::KbkdfCounter {
+            digest_algorithm: digest,
+            context: http_common::ByteString(context),
+            length: len,
+        },
+    }
+}
+
+fn key_agreement_parameters(
+    mechanism: aziot_key_common::KeyAgreementMechanism,
+) -> aziot_key_common_http::derive_shared_secret::Parameters {
+    match mechanism {
+        aziot_key_common::KeyAgreementMechanism::Ecdh => {
+            aziot_key_common_http::derive_shared_secret::Parameters::Ecdh
+        }
+
+        aziot_key_common::KeyAgreementMechanism::EcdhHkdf {
+            digest,
+            salt,
+            info,
+            len,
+        } => aziot_key_common_http::derive_shared_secret::Parameters::EcdhHkdf {
+            digest_algorithm: digest,
+            salt: http_common::ByteString(salt),
+            info: http_common::ByteString(info),
+            length: len,
+        },
+    }
 }
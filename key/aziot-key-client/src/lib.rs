@@ -35,6 +35,35 @@ impl Client {
         let body = aziot_key_common_http::create_key_pair_if_not_exists::Request {
             id: id.to_owned(),
             preferred_algorithms: preferred_algorithms.map(ToOwned::to_owned),
+            import_key_pair_bytes: None,
+            import_format: None,
+            import_password: None,
+        };
+
+        let res: aziot_key_common_http::create_key_pair_if_not_exists::Response = request(
+            &mut stream,
+            &http::Method::POST,
+            format_args!("/keypair?api-version={}", self.api_version),
+            Some(&body),
+        )?;
+        Ok(res.handle)
+    }
+
+    pub fn import_key_pair(
+        &self,
+        id: &str,
+        bytes: &[u8],
+        format: aziot_key_common::KeyPairFormat,
+        password: Option<&str>,
+    ) -> std::io::Result<aziot_key_common::KeyHandle> {
+        let mut stream = self.connector.connect()?;
+
+        let body = aziot_key_common_http::create_key_pair_if_not_exists::Request {
+            id: id.to_owned(),
+            preferred_algorithms: None,
+            import_key_pair_bytes: Some(http_common::ByteString(bytes.to_owned())),
+            import_format: Some(format),
+            import_password: password.map(ToOwned::to_owned),
         };
 
         let res: aziot_key_common_http::create_key_pair_if_not_exists::Response = request(
@@ -65,6 +94,49 @@ impl Client {
         Ok(res.handle)
     }
 
+    pub fn delete_key_pair(&self, id: &str) -> std::io::Result<()> {
+        let mut stream = self.connector.connect()?;
+
+        let () = request_no_content::<_, ()>(
+            &mut stream,
+            &http::Method::DELETE,
+            format_args!(
+                "/keypair/{}?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            None,
+        )?;
+        Ok(())
+    }
+
+    /// Moves the key pair `id` to `to_id`, replacing the key pair with that ID if it exists.
+    pub fn move_key_pair(&self, id: &str, to_id: &str) -> std::io::Result<()> {
+        let mut stream = self.connector.connect()?;
+
+        let body = aziot_key_common_http::move_key_pair::Request {
+            to_id: to_id.to_owned(),
+        };
+
+        let () = request_no_content(
+            &mut stream,
+            &http::Method::POST,
+            format_args!(
+                "/keypair/{}/move?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            Some(&body),
+        )?;
+        Ok(())
+    }
+
     pub fn get_key_pair_public_parameter(
         &self,
         handle: &aziot_key_common::KeyHandle,
@@ -145,6 +217,37 @@ impl Client {
         Ok(res.handle)
     }
 
+    pub fn delete_key(&self, id: &str) -> std::io::Result<()> {
+        let mut stream = self.connector.connect()?;
+
+        let () = request_no_content::<_, ()>(
+            &mut stream,
+            &http::Method::DELETE,
+            format_args!(
+                "/key/{}?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            None,
+        )?;
+        Ok(())
+    }
+
+    pub fn list_keys(&self) -> std::io::Result<Vec<aziot_key_common::KeyInfo>> {
+        let mut stream = self.connector.connect()?;
+
+        let res: aziot_key_common_http::list_keys::Response = request::<_, (), _>(
+            &mut stream,
+            &http::Method::GET,
+            format_args!("/keys?api-version={}", self.api_version),
+            None,
+        )?;
+        Ok(res.keys.into_iter().map(Into::into).collect())
+    }
+
     pub fn create_derived_key(
         &self,
         base_handle: &aziot_key_common::KeyHandle,
@@ -155,6 +258,32 @@ impl Client {
         let body = aziot_key_common_http::create_derived_key::Request {
             base_handle: base_handle.clone(),
             derivation_data: http_common::ByteString(derivation_data.to_owned()),
+            mechanism: None,
+        };
+
+        let res: aziot_key_common_http::create_derived_key::Response = request(
+            &mut stream,
+            &http::Method::POST,
+            format_args!("/derivedkey?api-version={}", self.api_version),
+            Some(&body),
+        )?;
+        Ok(res.handle)
+    }
+
+    /// Creates a derived key like [`Client::create_derived_key`], but with the given derivation mechanism
+    /// instead of the default HMAC-SHA256.
+    pub fn create_derived_key_with_mechanism(
+        &self,
+        base_handle: &aziot_key_common::KeyHandle,
+        derivation_data: &[u8],
+        mechanism: aziot_key_common::KeyDerivationMechanism,
+    ) -> std::io::Result<aziot_key_common::KeyHandle> {
+        let mut stream = self.connector.connect()?;
+
+        let body = aziot_key_common_http::create_derived_key::Request {
+            base_handle: base_handle.clone(),
+            derivation_data: http_common::ByteString(derivation_data.to_owned()),
+            mechanism: Some(key_derivation_mechanism(mechanism)),
         };
 
         let res: aziot_key_common_http::create_derived_key::Response = request(
@@ -185,6 +314,126 @@ impl Client {
         Ok(res.key.0)
     }
 
+    pub fn export_key(
+        &self,
+        id: &str,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapping_key: &[u8],
+    ) -> std::io::Result<Vec<u8>> {
+        let mut stream = self.connector.connect()?;
+
+        let body = aziot_key_common_http::export_key::Request {
+            mechanism,
+            wrapping_key: http_common::ByteString(wrapping_key.to_owned()),
+        };
+
+        let res: aziot_key_common_http::export_key::Response = request(
+            &mut stream,
+            &http::Method::POST,
+            format_args!(
+                "/key/{}/export?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            Some(&body),
+        )?;
+        Ok(res.wrapped_key.0)
+    }
+
+    pub fn export_key_pair(
+        &self,
+        id: &str,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapping_key: &[u8],
+    ) -> std::io::Result<Vec<u8>> {
+        let mut stream = self.connector.connect()?;
+
+        let body = aziot_key_common_http::export_key::Request {
+            mechanism,
+            wrapping_key: http_common::ByteString(wrapping_key.to_owned()),
+        };
+
+        let res: aziot_key_common_http::export_key::Response = request(
+            &mut stream,
+            &http::Method::POST,
+            format_args!(
+                "/keypair/{}/export?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            Some(&body),
+        )?;
+        Ok(res.wrapped_key.0)
+    }
+
+    pub fn import_wrapped_key(
+        &self,
+        id: &str,
+        unwrapping_key_handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapped_key: &[u8],
+    ) -> std::io::Result<aziot_key_common::KeyHandle> {
+        let mut stream = self.connector.connect()?;
+
+        let body = aziot_key_common_http::import_wrapped_key::Request {
+            mechanism,
+            unwrapping_key_handle: unwrapping_key_handle.clone(),
+            wrapped_key: http_common::ByteString(wrapped_key.to_owned()),
+        };
+
+        let res: aziot_key_common_http::import_wrapped_key::Response = request(
+            &mut stream,
+            &http::Method::POST,
+            format_args!(
+                "/key/{}/import?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            Some(&body),
+        )?;
+        Ok(res.handle)
+    }
+
+    pub fn import_wrapped_key_pair(
+        &self,
+        id: &str,
+        unwrapping_key_handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapped_key: &[u8],
+    ) -> std::io::Result<aziot_key_common::KeyHandle> {
+        let mut stream = self.connector.connect()?;
+
+        let body = aziot_key_common_http::import_wrapped_key::Request {
+            mechanism,
+            unwrapping_key_handle: unwrapping_key_handle.clone(),
+            wrapped_key: http_common::ByteString(wrapped_key.to_owned()),
+        };
+
+        let res: aziot_key_common_http::import_wrapped_key::Response = request(
+            &mut stream,
+            &http::Method::POST,
+            format_args!(
+                "/keypair/{}/import?api-version={}",
+                percent_encoding::percent_encode(
+                    id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            Some(&body),
+        )?;
+        Ok(res.handle)
+    }
+
     pub fn sign(
         &self,
         handle: &aziot_key_common::KeyHandle,
@@ -202,11 +451,45 @@ impl Client {
                     }
                 }
 
+                aziot_key_common::SignMechanism::Eddsa => {
+                    aziot_key_common_http::sign::Parameters::Eddsa {
+                        message: http_common::ByteString(digest.to_owned()),
+                    }
+                }
+
+                aziot_key_common::SignMechanism::RsaPkcs1 {
+                    digest: digest_algorithm,
+                } => aziot_key_common_http::sign::Parameters::RsaPkcs1 {
+                    digest_algorithm,
+                    digest: http_common::ByteString(digest.to_owned()),
+                },
+
+                aziot_key_common::SignMechanism::RsaPss {
+                    digest: digest_algorithm,
+                    salt_len,
+                } => aziot_key_common_http::sign::Parameters::RsaPss {
+                    digest_algorithm,
+                    salt_len,
+                    digest: http_common::ByteString(digest.to_owned()),
+                },
+
                 aziot_key_common::SignMechanism::HmacSha256 => {
                     aziot_key_common_http::sign::Parameters::HmacSha256 {
                         message: http_common::ByteString(digest.to_owned()),
                     }
                 }
+
+                aziot_key_common::SignMechanism::HmacSha384 => {
+                    aziot_key_common_http::sign::Parameters::HmacSha384 {
+                        message: http_common::ByteString(digest.to_owned()),
+                    }
+                }
+
+                aziot_key_common::SignMechanism::HmacSha512 => {
+                    aziot_key_common_http::sign::Parameters::HmacSha512 {
+                        message: http_common::ByteString(digest.to_owned()),
+                    }
+                }
             },
         };
 
@@ -220,6 +503,77 @@ impl Client {
         Ok(signature)
     }
 
+    /// Performs a key agreement between the given key pair and the peer public key, and returns the raw shared secret.
+    ///
+    /// `peer_public_key` is a DER-encoded `SubjectPublicKeyInfo`.
+    pub fn derive_shared_secret(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::KeyAgreementMechanism,
+        peer_public_key: &[u8],
+    ) -> std::io::Result<Vec<u8>> {
+        let mut stream = self.connector.connect()?;
+
+        let body = aziot_key_common_http::derive_shared_secret::Request {
+            key_handle: handle.clone(),
+            peer_public_key: http_common::ByteString(peer_public_key.to_owned()),
+            parameters: key_agreement_parameters(mechanism),
+            key_id: None,
+            usage: vec![],
+        };
+
+        let res: aziot_key_common_http::derive_shared_secret::Response = request(
+            &mut stream,
+            &http::Method::POST,
+            format_args!("/sharedsecret?api-version={}", self.api_version),
+            Some(&body),
+        )?;
+        let shared_secret = res.shared_secret.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "response does not contain shared secret",
+            )
+        })?;
+        Ok(shared_secret.0)
+    }
+
+    /// Performs a key agreement between the given key pair and the peer public key, and stores the shared secret
+    /// as a new key with the given ID and usage.
+    ///
+    /// `peer_public_key` is a DER-encoded `SubjectPublicKeyInfo`.
+    pub fn derive_shared_secret_key(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::KeyAgreementMechanism,
+        peer_public_key: &[u8],
+        id: &str,
+        usage: &[aziot_key_common::KeyUsage],
+    ) -> std::io::Result<aziot_key_common::KeyHandle> {
+        let mut stream = self.connector.connect()?;
+
+        let body = aziot_key_common_http::derive_shared_secret::Request {
+            key_handle: handle.clone(),
+            peer_public_key: http_common::ByteString(peer_public_key.to_owned()),
+            parameters: key_agreement_parameters(mechanism),
+            key_id: Some(id.to_owned()),
+            usage: usage.to_owned(),
+        };
+
+        let res: aziot_key_common_http::derive_shared_secret::Response = request(
+            &mut stream,
+            &http::Method::POST,
+            format_args!("/sharedsecret?api-version={}", self.api_version),
+            Some(&body),
+        )?;
+        let key_handle = res.key_handle.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "response does not contain key handle",
+            )
+        })?;
+        Ok(key_handle)
+    }
+
     pub fn encrypt(
         &self,
         handle: &aziot_key_common::KeyHandle,
@@ -245,6 +599,26 @@ impl Client {
                 aziot_key_common::EncryptMechanism::RsaNoPadding => {
                     aziot_key_common_http::encrypt::Parameters::RsaNoPadding
                 }
+
+                aziot_key_common::EncryptMechanism::AesCbcPad { iv } => {
+                    aziot_key_common_http::encrypt::Parameters::AesCbcPad {
+                        iv: http_common::ByteString(iv),
+                    }
+                }
+
+                aziot_key_common::EncryptMechanism::AesCtr { iv } => {
+                    aziot_key_common_http::encrypt::Parameters::AesCtr {
+                        iv: http_common::ByteString(iv),
+                    }
+                }
+
+                aziot_key_common::EncryptMechanism::AesKeyWrap => {
+                    aziot_key_common_http::encrypt::Parameters::AesKeyWrap
+                }
+
+                aziot_key_common::EncryptMechanism::AesKeyWrapPad => {
+                    aziot_key_common_http::encrypt::Parameters::AesKeyWrapPad
+                }
             },
             plaintext: http_common::ByteString(plaintext.to_owned()),
         };
@@ -284,6 +658,26 @@ impl Client {
                 aziot_key_common::EncryptMechanism::RsaNoPadding => {
                     aziot_key_common_http::encrypt::Parameters::RsaNoPadding
                 }
+
+                aziot_key_common::EncryptMechanism::AesCbcPad { iv } => {
+                    aziot_key_common_http::encrypt::Parameters::AesCbcPad {
+                        iv: http_common::ByteString(iv),
+                    }
+                }
+
+                aziot_key_common::EncryptMechanism::AesCtr { iv } => {
+                    aziot_key_common_http::encrypt::Parameters::AesCtr {
+                        iv: http_common::ByteString(iv),
+                    }
+                }
+
+                aziot_key_common::EncryptMechanism::AesKeyWrap => {
+                    aziot_key_common_http::encrypt::Parameters::AesKeyWrap
+                }
+
+                aziot_key_common::EncryptMechanism::AesKeyWrapPad => {
+                    aziot_key_common_http::encrypt::Parameters::AesKeyWrapPad
+                }
             },
             ciphertext: http_common::ByteString(ciphertext.to_owned()),
         };
@@ -297,6 +691,125 @@ impl Client {
         let plaintext = res.plaintext.0;
         Ok(plaintext)
     }
+    /// Starts a multi-part encryption, and returns the ID of the new stream.
+    ///
+    /// Pass the plaintext to [`Client::update_stream`] in parts. The ciphertext is identical to what [`Client::encrypt`] returns for the whole plaintext.
+    pub fn create_encrypt_stream(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::EncryptMechanism,
+    ) -> std::io::Result<String> {
+        self.create_encrypt_stream_inner("encrypt", handle, mechanism)
+    }
+
+    /// Starts a multi-part decryption, and returns the ID of the new stream.
+    ///
+    /// For AEAD, the plaintext returned by [`Client::update_stream`] is only authenticated once the final update succeeds.
+    pub fn create_decrypt_stream(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::EncryptMechanism,
+    ) -> std::io::Result<String> {
+        self.create_encrypt_stream_inner("decrypt", handle, mechanism)
+    }
+
+    fn create_encrypt_stream_inner(
+        &self,
+        operation: &str,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::EncryptMechanism,
+    ) -> std::io::Result<String> {
+        let mut stream = self.connector.connect()?;
+
+        let body = aziot_key_common_http::create_stream::EncryptRequest {
+            key_handle: handle.clone(),
+            parameters: encrypt_parameters(mechanism),
+        };
+
+        let res: aziot_key_common_http::create_stream::Response = request(
+            &mut stream,
+            &http::Method::POST,
+            format_args!("/{}/stream?api-version={}", operation, self.api_version),
+            Some(&body),
+        )?;
+        Ok(res.stream_id)
+    }
+
+    /// Starts a multi-part HMAC signature, and returns the ID of the new stream.
+    ///
+    /// The signature is returned by the final [`Client::update_stream`].
+    pub fn create_sign_stream(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::SignMechanism,
+    ) -> std::io::Result<String> {
+        let mut stream = self.connector.connect()?;
+
+        let body = aziot_key_common_http::create_stream::SignRequest {
+            key_handle: handle.clone(),
+            parameters: sign_stream_parameters(mechanism)?,
+        };
+
+        let res: aziot_key_common_http::create_stream::Response = request(
+            &mut stream,
+            &http::Method::POST,
+            format_args!("/sign/stream?api-version={}", self.api_version),
+            Some(&body),
+        )?;
+        Ok(res.stream_id)
+    }
+
+    /// Passes the next part of the input to the given stream, and returns the output produced so far.
+    ///
+    /// If `is_final` is set, the operation is completed and the stream is closed.
+    pub fn update_stream(
+        &self,
+        stream_id: &str,
+        data: &[u8],
+        is_final: bool,
+    ) -> std::io::Result<Vec<u8>> {
+        let mut stream = self.connector.connect()?;
+
+        let body = aziot_key_common_http::update_stream::Request {
+            data: http_common::ByteString(data.to_owned()),
+            is_final,
+        };
+
+        let res: aziot_key_common_http::update_stream::Response = request(
+            &mut stream,
+            &http::Method::POST,
+            format_args!(
+                "/streams/{}?api-version={}",
+                percent_encoding::percent_encode(
+                    stream_id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            Some(&body),
+        )?;
+        Ok(res.output.0)
+    }
+
+    /// Abandons the given stream without completing its operation.
+    pub fn abort_stream(&self, stream_id: &str) -> std::io::Result<()> {
+        let mut stream = self.connector.connect()?;
+
+        let () = request_no_content::<_, ()>(
+            &mut stream,
+            &http::Method::DELETE,
+            format_args!(
+                "/streams/{}?api-version={}",
+                percent_encoding::percent_encode(
+                    stream_id.as_bytes(),
+                    http_common::PATH_SEGMENT_ENCODE_SET
+                ),
+                self.api_version,
+            ),
+            None,
+        )?;
+        Ok(())
+    }
 }
 
 impl std::fmt::Debug for Client {
@@ -305,6 +818,120 @@ impl std::fmt::Debug for Client {
     }
 }
 
+fn encrypt_parameters(
+    mechanism: aziot_key_common::EncryptMechanism,
+) -> aziot_key_common_http::encrypt::Parameters {
+    match mechanism {
+        aziot_key_common::EncryptMechanism::Aead { iv, aad } => {
+            aziot_key_common_http::encrypt::Parameters::Aead {
+                iv: http_common::ByteString(iv),
+                aad: http_common::ByteString(aad),
+            }
+        }
+
+        aziot_key_common::EncryptMechanism::RsaPkcs1 => {
+            aziot_key_common_http::encrypt::Parameters::RsaPkcs1
+        }
+
+        aziot_key_common::EncryptMechanism::RsaNoPadding => {
+            aziot_key_common_http::encrypt::Parameters::RsaNoPadding
+        }
+
+        aziot_key_common::EncryptMechanism::AesCbcPad { iv } => {
+            aziot_key_common_http::encrypt::Parameters::AesCbcPad {
+                iv: http_common::ByteString(iv),
+            }
+        }
+
+        aziot_key_common::EncryptMechanism::AesCtr { iv } => {
+            aziot_key_common_http::encrypt::Parameters::AesCtr {
+                iv: http_common::ByteString(iv),
+            }
+        }
+
+        aziot_key_common::EncryptMechanism::AesKeyWrap => {
+            aziot_key_common_http::encrypt::Parameters::AesKeyWrap
+        }
+
+        aziot_key_common::EncryptMechanism::AesKeyWrapPad => {
+            aziot_key_common_http::encrypt::Parameters::AesKeyWrapPad
+        }
+    }
+}
+
+fn sign_stream_parameters(
+    mechanism: aziot_key_common::SignMechanism,
+) -> std::io::Result<aziot_key_common_http::create_stream::SignParameters> {
+    match mechanism {
+        aziot_key_common::SignMechanism::HmacSha256 => {
+            Ok(aziot_key_common_http::create_stream::SignParameters::HmacSha256)
+        }
+
+        aziot_key_common::SignMechanism::HmacSha384 => {
+            Ok(aziot_key_common_http::create_stream::SignParameters::HmacSha384)
+        }
+
+        aziot_key_common::SignMechanism::HmacSha512 => {
+            Ok(aziot_key_common_http::create_stream::SignParameters::HmacSha512)
+        }
+
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "mechanism does not support multi-part operations",
+        )),
+    }
+}
+
+fn key_derivation_mechanism(
+    mechanism: aziot_key_common::KeyDerivationMechanism,
+) -> aziot_key_common_http::create_derived_key::Mechanism {
+    match mechanism {
+        aziot_key_common::KeyDerivationMechanism::HmacSha256 => {
+            aziot_key_common_http::create_derived_key::Mechanism::HmacSha256
+        }
+
+        aziot_key_common::KeyDerivationMechanism::Hkdf { digest, salt, len } => {
+            aziot_key_common_http::create_derived_key::Mechanism::Hkdf {
+                digest_algorithm: digest,
+                salt: http_common::ByteString(salt),
+                length: len,
+            }
+        }
+
+        aziot_key_common::KeyDerivationMechanism::KbkdfCounter {
+            digest,
+            context,
+            len,
+        } => aziot_key_common_http::create_derived_key::Mechanism::KbkdfCounter {
+            digest_algorithm: digest,
+            context: http_common::ByteString(context),
+            length: len,
+        },
+    }
+}
+
+fn key_agreement_parameters(
+    mechanism: aziot_key_common::KeyAgreementMechanism,
+) -> aziot_key_common_http::derive_shared_secret::Parameters {
+    match mechanism {
+        aziot_key_common::KeyAgreementMechanism::Ecdh => {
+            aziot_key_common_http::derive_shared_secret::Parameters::Ecdh
+        }
+
+        aziot_key_common::KeyAgreementMechanism::EcdhHkdf {
+            digest,
+            salt,
+            info,
+            len,
+        } => aziot_key_common_http::derive_shared_secret::Parameters::EcdhHkdf {
+            digest_algorithm: digest,
+            salt: http_common::ByteString(salt),
+            info: http_common::ByteString(info),
+            length: len,
+        },
+    }
+}
+
 fn request<TUri, TRequest, TResponse>(
     stream: &mut http_common::Stream,
     method: &http::Method,
@@ -315,6 +942,60 @@ where
     TUri: std::fmt::Display,
     TRequest: serde::Serialize,
     TResponse: serde::de::DeserializeOwned,
+{
+    let (res_status_code, body) = request_inner(stream, method, uri, body)?;
+
+    let res: TResponse = match res_status_code {
+        200 => {
+            let res = serde_json::from_slice(&body)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+            res
+        }
+
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "malformed HTTP response",
+            ))
+        }
+    };
+    Ok(res)
+}
+
+fn request_no_content<TUri, TRequest>(
+    stream: &mut http_common::Stream,
+    method: &http::Method,
+    uri: TUri,
+    body: Option<&TRequest>,
+) -> std::io::Result<()>
+where
+    TUri: std::fmt::Display,
+    TRequest: serde::Serialize,
+{
+    let (res_status_code, _) = request_inner(stream, method, uri, body)?;
+
+    match res_status_code {
+        204 => Ok(()),
+
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "malformed HTTP response",
+        )),
+    }
+}
+
+/// Sends the request and returns the status code and body of a non-error response.
+///
+/// Error responses are converted into an `Err` containing the error message from the response body.
+fn request_inner<TUri, TRequest>(
+    stream: &mut http_common::Stream,
+    method: &http::Method,
+    uri: TUri,
+    body: Option<&TRequest>,
+) -> std::io::Result<(u16, Vec<u8>)>
+where
+    TUri: std::fmt::Display,
+    TRequest: serde::Serialize,
 {
     use std::io::{Read, Write};
 
@@ -367,27 +1048,20 @@ where
         }
     };
 
-    let res: TResponse = match res_status_code {
-        Some(200) => {
-            let res = serde_json::from_slice(body)
-                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
-            res
-        }
+    match res_status_code {
+        Some(res_status_code @ 200..=299) => Ok((res_status_code, body.to_owned())),
 
         Some(400..=499) | Some(500..=599) => {
             let res: http_common::ErrorBody<'static> = serde_json::from_slice(body)
                 .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, res.message));
+            Err(std::io::Error::new(std::io::ErrorKind::Other, res.message))
         }
 
-        Some(_) | None => {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "malformed HTTP response",
-            ))
-        }
-    };
-    Ok(res)
+        Some(_) | None => Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "malformed HTTP response",
+        )),
+    }
 }
 
 fn try_parse_response(
@@ -407,6 +1081,11 @@ fn try_parse_response(
 
     let res_status_code = res.code;
 
+    // A 204 response never has a body.
+    if res_status_code == Some(204) {
+        return Ok(Some((res_status_code, &[])));
+    }
+
     let mut content_length = None;
     let mut is_json = false;
     for header in &headers {
@@ -35,6 +35,39 @@ pub mod create_derived_key {
 
         #[serde(rename = "derivationData")]
         pub derivation_data: http_common::ByteString,
+
+        /// If not set, the key is derived with HMAC-SHA256.
+        #[serde(
+            rename = "derivationMechanism",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub mechanism: Option<Mechanism>,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[serde(tag = "algorithm", content = "parameters")]
+    pub enum Mechanism {
+        #[serde(rename = "HMAC-SHA256")]
+        HmacSha256,
+
+        #[serde(rename = "HKDF")]
+        Hkdf {
+            #[serde(rename = "digestAlgorithm")]
+            digest_algorithm: aziot_key_common::DigestAlgorithm,
+            #[serde(default)]
+            salt: http_common::ByteString,
+            length: usize,
+        },
+
+        #[serde(rename = "KBKDF-COUNTER")]
+        KbkdfCounter {
+            #[serde(rename = "digestAlgorithm")]
+            digest_algorithm: aziot_key_common::DigestAlgorithm,
+            #[serde(default)]
+            context: http_common::ByteString,
+            length: usize,
+        },
     }
 
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -72,6 +105,20 @@ pub mod create_key_pair_if_not_exists {
 
         #[serde(rename = "preferredAlgorithms")]
         pub preferred_algorithms: Option<String>,
+
+        /// If set, the key pair is imported from these bytes instead of being generated.
+        #[serde(
+            rename = "keyPairBytes",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub import_key_pair_bytes: Option<http_common::ByteString>,
+
+        #[serde(rename = "format", default, skip_serializing_if = "Option::is_none")]
+        pub import_format: Option<aziot_key_common::KeyPairFormat>,
+
+        #[serde(rename = "password", default, skip_serializing_if = "Option::is_none")]
+        pub import_password: Option<String>,
     }
 
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -81,6 +128,45 @@ pub mod create_key_pair_if_not_exists {
     }
 }
 
+pub mod create_stream {
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct EncryptRequest {
+        #[serde(rename = "keyHandle")]
+        pub key_handle: aziot_key_common::KeyHandle,
+
+        #[serde(flatten)]
+        pub parameters: crate::encrypt::Parameters,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct SignRequest {
+        #[serde(rename = "keyHandle")]
+        pub key_handle: aziot_key_common::KeyHandle,
+
+        #[serde(flatten)]
+        pub parameters: SignParameters,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[serde(tag = "algorithm")]
+    pub enum SignParameters {
+        #[serde(rename = "HMAC-SHA256")]
+        HmacSha256,
+
+        #[serde(rename = "HMAC-SHA384")]
+        HmacSha384,
+
+        #[serde(rename = "HMAC-SHA512")]
+        HmacSha512,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        #[serde(rename = "streamId")]
+        pub stream_id: String,
+    }
+}
+
 pub mod decrypt {
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct Request {
@@ -99,6 +185,63 @@ pub mod decrypt {
     }
 }
 
+pub mod derive_shared_secret {
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Request {
+        #[serde(rename = "keyHandle")]
+        pub key_handle: aziot_key_common::KeyHandle,
+
+        #[serde(rename = "peerPublicKey")]
+        pub peer_public_key: http_common::ByteString,
+
+        #[serde(flatten)]
+        pub parameters: Parameters,
+
+        /// If set, the shared secret is stored as a new key with this ID, and its handle is returned.
+        #[serde(rename = "keyId", default, skip_serializing_if = "Option::is_none")]
+        pub key_id: Option<String>,
+
+        #[serde(
+            rename = "usage",
+            default,
+            skip_serializing_if = "Vec::is_empty",
+            with = "crate::key_usage"
+        )]
+        pub usage: Vec<aziot_key_common::KeyUsage>,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    #[serde(tag = "algorithm", content = "parameters")]
+    pub enum Parameters {
+        #[serde(rename = "ECDH")]
+        Ecdh,
+
+        #[serde(rename = "ECDH-HKDF")]
+        EcdhHkdf {
+            #[serde(rename = "digestAlgorithm")]
+            digest_algorithm: aziot_key_common::DigestAlgorithm,
+            #[serde(default)]
+            salt: http_common::ByteString,
+            #[serde(default)]
+            info: http_common::ByteString,
+            length: usize,
+        },
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        #[serde(rename = "keyHandle", default, skip_serializing_if = "Option::is_none")]
+        pub key_handle: Option<aziot_key_common::KeyHandle>,
+
+        #[serde(
+            rename = "sharedSecret",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub shared_secret: Option<http_common::ByteString>,
+    }
+}
+
 pub mod encrypt {
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct Request {
@@ -125,6 +268,18 @@ pub mod encrypt {
 
         #[serde(rename = "RSA-NO-PADDING")]
         RsaNoPadding,
+
+        #[serde(rename = "AES-CBC-PAD")]
+        AesCbcPad { iv: http_common::ByteString },
+
+        #[serde(rename = "AES-CTR")]
+        AesCtr { iv: http_common::ByteString },
+
+        #[serde(rename = "AES-KEY-WRAP")]
+        AesKeyWrap,
+
+        #[serde(rename = "AES-KEY-WRAP-PAD")]
+        AesKeyWrapPad,
     }
 
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -147,6 +302,36 @@ pub mod export_derived_key {
     }
 }
 
+pub mod export_key {
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Request {
+        #[serde(rename = "mechanism")]
+        pub mechanism: aziot_key_common::WrapMechanism,
+
+        #[serde(rename = "wrappingKey")]
+        pub wrapping_key: http_common::ByteString,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        #[serde(rename = "wrappedKey")]
+        pub wrapped_key: http_common::ByteString,
+    }
+}
+
+pub mod get_key_parameter {
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Request {
+        #[serde(rename = "keyHandle")]
+        pub key_handle: aziot_key_common::KeyHandle,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub value: String,
+    }
+}
+
 pub mod get_key_pair_public_parameter {
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct Request {
@@ -160,6 +345,81 @@ pub mod get_key_pair_public_parameter {
     }
 }
 
+pub mod import_wrapped_key {
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Request {
+        #[serde(rename = "mechanism")]
+        pub mechanism: aziot_key_common::WrapMechanism,
+
+        #[serde(rename = "unwrappingKeyHandle")]
+        pub unwrapping_key_handle: aziot_key_common::KeyHandle,
+
+        #[serde(rename = "wrappedKey")]
+        pub wrapped_key: http_common::ByteString,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        #[serde(rename = "keyHandle")]
+        pub handle: aziot_key_common::KeyHandle,
+    }
+}
+
+pub mod list_keys {
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        #[serde(rename = "keys")]
+        pub keys: Vec<KeyInfo>,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct KeyInfo {
+        #[serde(rename = "keyId")]
+        pub id: String,
+
+        #[serde(rename = "type")]
+        pub kind: aziot_key_common::KeyKind,
+
+        #[serde(rename = "algorithm", skip_serializing_if = "Option::is_none")]
+        pub algorithm: Option<String>,
+
+        #[serde(rename = "usage", with = "crate::key_usage")]
+        pub usage: Vec<aziot_key_common::KeyUsage>,
+
+        #[serde(rename = "location")]
+        pub location: String,
+
+        #[serde(rename = "preloaded")]
+        pub preloaded: bool,
+    }
+
+    impl From<aziot_key_common::KeyInfo> for KeyInfo {
+        fn from(info: aziot_key_common::KeyInfo) -> Self {
+            KeyInfo {
+                id: info.id,
+                kind: info.kind,
+                algorithm: info.algorithm,
+                usage: info.usage,
+                location: info.location,
+                preloaded: info.preloaded,
+            }
+        }
+    }
+
+    impl From<KeyInfo> for aziot_key_common::KeyInfo {
+        fn from(info: KeyInfo) -> Self {
+            aziot_key_common::KeyInfo {
+                id: info.id,
+                kind: info.kind,
+                algorithm: info.algorithm,
+                usage: info.usage,
+                location: info.location,
+                preloaded: info.preloaded,
+            }
+        }
+    }
+}
+
 pub mod load {
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct Response {
@@ -168,6 +428,14 @@ pub mod load {
     }
 }
 
+pub mod move_key_pair {
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Request {
+        #[serde(rename = "toKeyId")]
+        pub to_id: String,
+    }
+}
+
 pub mod sign {
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct Request {
@@ -184,8 +452,33 @@ pub mod sign {
         #[serde(rename = "ECDSA")]
         Ecdsa { digest: http_common::ByteString },
 
+        #[serde(rename = "EdDSA")]
+        Eddsa { message: http_common::ByteString },
+
+        #[serde(rename = "RSA-PKCS1")]
+        RsaPkcs1 {
+            #[serde(rename = "digestAlgorithm")]
+            digest_algorithm: aziot_key_common::DigestAlgorithm,
+            digest: http_common::ByteString,
+        },
+
+        #[serde(rename = "RSA-PSS")]
+        RsaPss {
+            #[serde(rename = "digestAlgorithm")]
+            digest_algorithm: aziot_key_common::DigestAlgorithm,
+            #[serde(rename = "saltLength")]
+            salt_len: usize,
+            digest: http_common::ByteString,
+        },
+
         #[serde(rename = "HMAC-SHA256")]
         HmacSha256 { message: http_common::ByteString },
+
+        #[serde(rename = "HMAC-SHA384")]
+        HmacSha384 { message: http_common::ByteString },
+
+        #[serde(rename = "HMAC-SHA512")]
+        HmacSha512 { message: http_common::ByteString },
     }
 
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -194,6 +487,22 @@ pub mod sign {
     }
 }
 
+pub mod update_stream {
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Request {
+        pub data: http_common::ByteString,
+
+        /// If set, the operation is completed after `data` has been processed, and the stream is closed.
+        #[serde(rename = "final", default)]
+        pub is_final: bool,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub output: http_common::ByteString,
+    }
+}
+
 mod key_usage {
     pub(crate) fn deserialize<'de, D>(
         deserializer: D,
@@ -215,6 +524,7 @@ mod key_usage {
                 E: serde::de::Error,
             {
                 s.split(',')
+                    .filter(|usage| !usage.is_empty())
                     .map(|usage| match usage {
                         "derive" => Ok(aziot_key_common::KeyUsage::Derive),
                         "encrypt" => Ok(aziot_key_common::KeyUsage::Encrypt),
@@ -19,13 +19,90 @@ pub enum KeyUsage {
     Sign,
 }
 
+/// A key or key pair, as reported by the key service's key inventory.
+#[derive(Clone, Debug)]
+pub struct KeyInfo {
+    pub id: String,
+    pub kind: KeyKind,
+
+    /// `"ECDSA"`, `"RSA"` or `"ED25519"` for key pairs.
+    ///
+    /// Keys don't record their algorithm, so for keys it's inferred from their usage: `"AES"` for keys with the encrypt usage,
+    /// and `"HMAC-SHA256"` for other keys with the derive or sign usage. `None` if the algorithm could not be determined,
+    /// which includes keys whose usage is not known.
+    pub algorithm: Option<String>,
+
+    /// Always empty for key pairs. May also be empty for keys whose usage is not known.
+    pub usage: Vec<KeyUsage>,
+
+    /// A `file://` or `pkcs11:` URI identifying where the key is stored.
+    pub location: String,
+
+    pub preloaded: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyKind {
+    Key,
+    KeyPair,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum SignMechanism {
     // ECDSA keys
     Ecdsa,
 
+    // Ed25519 keys. Signs the message itself rather than a digest.
+    Eddsa,
+
+    // RSA keys. Signs a digest computed by the caller with the given digest algorithm.
+    RsaPkcs1 {
+        digest: DigestAlgorithm,
+    },
+    RsaPss {
+        digest: DigestAlgorithm,
+        salt_len: usize,
+    },
+
     // Symmetric keys
     HmacSha256,
+    HmacSha384,
+    HmacSha512,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum DigestAlgorithm {
+    #[serde(rename = "SHA256")]
+    Sha256,
+
+    #[serde(rename = "SHA384")]
+    Sha384,
+
+    #[serde(rename = "SHA512")]
+    Sha512,
+}
+
+/// The mechanism used to derive a key from a base key with `create_derived_key`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum KeyDerivationMechanism {
+    /// The derived key is HMAC-SHA256(base key, derivation data).
+    #[default]
+    HmacSha256,
+
+    /// RFC 5869 HKDF, with the base key as the input keying material and the derivation data as the info.
+    Hkdf {
+        digest: DigestAlgorithm,
+        salt: Vec<u8>,
+        len: usize,
+    },
+
+    /// NIST SP 800-108 KDF in counter mode with an HMAC PRF, with the derivation data as the label.
+    KbkdfCounter {
+        digest: DigestAlgorithm,
+        context: Vec<u8>,
+        len: usize,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -38,4 +115,69 @@ pub enum EncryptMechanism {
 
     /// RSA with no padding. Padding will have been performed by the caller.
     RsaNoPadding,
+
+    /// AES-CBC with PKCS#7 padding.
+    AesCbcPad { iv: Vec<u8> },
+
+    /// AES-CTR with a 128-bit big-endian counter block.
+    AesCtr { iv: Vec<u8> },
+
+    /// RFC 3394 AES key wrap.
+    AesKeyWrap,
+
+    /// RFC 5649 AES key wrap with padding.
+    AesKeyWrapPad,
+}
+
+/// The mechanism used to agree on a shared secret with `derive_shared_secret`.
+#[derive(Clone, Debug)]
+pub enum KeyAgreementMechanism {
+    /// ECDH. The shared secret is the raw ECDH output.
+    Ecdh,
+
+    /// ECDH followed by HKDF. The shared secret is the HKDF output.
+    EcdhHkdf {
+        digest: DigestAlgorithm,
+        salt: Vec<u8>,
+        info: Vec<u8>,
+        len: usize,
+    },
+}
+
+/// The result of `derive_shared_secret`.
+#[derive(Clone, Debug)]
+pub enum SharedSecret {
+    /// The shared secret was stored as a new key, and this is the handle to it.
+    Key(KeyHandle),
+
+    /// The raw shared secret.
+    Raw(Vec<u8>),
+}
+
+/// The format of a private key being imported with `import_key_pair`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum KeyPairFormat {
+    /// PEM-encoded PKCS#8, optionally encrypted with a password.
+    #[serde(rename = "pkcs8-pem")]
+    Pkcs8Pem,
+
+    /// DER-encoded PKCS#8, optionally encrypted with a password.
+    #[serde(rename = "pkcs8-der")]
+    Pkcs8Der,
+
+    /// PKCS#12, encrypted with a password.
+    #[serde(rename = "pkcs12")]
+    Pkcs12,
+}
+
+/// The mechanism used to wrap a key or key pair for export to another device.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum WrapMechanism {
+    /// The wrapping key is an RSA public key. The key is encrypted under a random AES key, which is itself encrypted with RSA-OAEP.
+    #[serde(rename = "RSA-OAEP")]
+    RsaOaep,
+
+    /// The wrapping key is an EC public key. The key is encrypted under an AES key derived with ECDH from an ephemeral EC key.
+    #[serde(rename = "ECDH")]
+    Ecdh,
 }
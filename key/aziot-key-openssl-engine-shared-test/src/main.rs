@@ -269,7 +269,13 @@ fn generate_cert(
         | GenerateCertKind::Server { ca_key_handle, .. } => ca_key_handle.to_owned(),
     };
     let ca_key = load_private_key(&mut engine, ca_key_handle)?;
-    builder.sign(&ca_key, openssl::hash::MessageDigest::sha256())?;
+    // Ed25519 keys hash the message themselves, so they must not be given a digest.
+    let digest = if ca_key.id() == openssl::pkey::Id::ED25519 {
+        openssl::hash::MessageDigest::null()
+    } else {
+        openssl::hash::MessageDigest::sha256()
+    };
+    builder.sign(&ca_key, digest)?;
 
     let cert = builder.build();
 
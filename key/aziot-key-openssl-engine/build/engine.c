@@ -33,6 +33,15 @@ int aziot_key_get_engine_ex_index() {
 	return ENGINE_get_ex_new_index(0, NULL, NULL, aziot_key_dupf_engine_ex_data, NULL);
 }
 
+int aziot_key_get_ed25519_key_engine_ex_index() {
+	/* Each Ed25519 key loaded by the engine has its own ENGINE that holds the key's ex data. See ed25519.rs for details.
+	 *
+	 * For the same reason as the engine ex data above, that ENGINE uses its destroy hook to clean up its ex data instead of freef.
+	 * Those ENGINEs are never copied, so there is no dupf either.
+	 */
+	return ENGINE_get_ex_new_index(0, NULL, NULL, NULL, NULL);
+}
+
 #if OPENSSL_VERSION_NUMBER >= 0x10100000L
 int aziot_key_dupf_ec_key_ex_data(CRYPTO_EX_DATA *to, const CRYPTO_EX_DATA *from, void *from_d, int idx, long argl, void *argp);
 #else
@@ -0,0 +1,327 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Unlike EC and RSA keys, Ed25519 keys do not have a per-key method table that can be overridden,
+// so the engine instead provides its own EVP_PKEY_METHOD for Ed25519 keys whose digestsign function calls the Keys Service.
+// Keys loaded by the engine are bound to that method with EVP_PKEY_set1_engine, like the other kinds of keys.
+//
+// Ed25519 EVP_PKEYs also cannot hold ex data in openssl 1.1.1, so the key handle cannot be stored in the key itself.
+// Instead, each key loaded by the engine is created with an ENGINE of its own, which holds the key handle as ex data
+// and has no methods. The key holds the only functional reference to that ENGINE, so openssl destroys the ENGINE,
+// and with it the key handle, when the key is freed.
+//
+// The digestsign function looks up the key handle through the ENGINE of the EVP_PKEY it is invoked for. Keys that were not loaded
+// by the engine, which only reach it if the engine is made the default for Ed25519 keys, are signed by openssl's own function instead.
+
+impl crate::ex_data::HasExData<crate::ex_data::KeyExData> for openssl_sys::ENGINE {
+    unsafe fn index() -> openssl::ex_data::Index<Self, crate::ex_data::KeyExData> {
+        crate::ex_data::ex_indices().ed25519_key_engine
+    }
+}
+
+/// Creates an Ed25519 key with the given raw public key, whose private key operations use the given key handle.
+pub(super) unsafe fn private_key(
+    public_key: &[u8],
+    key_ex_data: crate::ex_data::KeyExData,
+) -> Result<*mut openssl_sys::EVP_PKEY, openssl2::Error> {
+    let e = openssl2::openssl_returns_nonnull(openssl_sys2::ENGINE_new())?;
+    let e: openssl2::StructuralEngine = foreign_types_shared::ForeignType::from_ptr(e);
+    let e = foreign_types_shared::ForeignType::as_ptr(&e);
+
+    openssl2::openssl_returns_1(openssl_sys2::ENGINE_set_id(
+        e,
+        std::ffi::CStr::from_bytes_with_nul(b"aziot-key-openssl-engine-ed25519-key\0")
+            .expect("hard-coded engine ID is valid CStr")
+            .as_ptr(),
+    ))?;
+    openssl2::openssl_returns_1(openssl_sys2::ENGINE_set_destroy_function(
+        e,
+        key_engine_destroy,
+    ))?;
+    crate::ex_data::set(e, key_ex_data)?;
+
+    // The key takes a functional reference to the ENGINE. The structural reference above is released when this function returns.
+    let openssl_key_raw =
+        openssl2::openssl_returns_nonnull(openssl_sys2::EVP_PKEY_new_raw_public_key(
+            openssl_sys::EVP_PKEY_ED25519,
+            e,
+            public_key.as_ptr(),
+            public_key.len(),
+        ))?;
+
+    Ok(openssl_key_raw)
+}
+
+unsafe extern "C" fn key_engine_destroy(e: *mut openssl_sys::ENGINE) -> std::os::raw::c_int {
+    let ex_index =
+        <openssl_sys::ENGINE as crate::ex_data::HasExData<crate::ex_data::KeyExData>>::index()
+            .as_raw();
+
+    let ex_data: *const crate::ex_data::KeyExData =
+        (<openssl_sys::ENGINE as openssl2::ExDataAccessors>::GET_FN)(e, ex_index) as _;
+    if !ex_data.is_null() {
+        // Restore the Arc and then drop it.
+        let ex_data = std::sync::Arc::from_raw(ex_data);
+        drop(ex_data);
+    }
+
+    1
+}
+
+/// Returns the key handle of the given key, or `None` if the key was not loaded by the engine.
+unsafe fn key_ex_data<'a>(
+    pkey: *const openssl_sys::EVP_PKEY,
+) -> Option<&'a crate::ex_data::KeyExData> {
+    let e = openssl_sys2::EVP_PKEY_get0_engine(pkey);
+    if e.is_null() {
+        return None;
+    }
+
+    // The ex index is private to this engine, so any other ENGINE has no ex data at it.
+    let ex_index =
+        <openssl_sys::ENGINE as crate::ex_data::HasExData<crate::ex_data::KeyExData>>::index()
+            .as_raw();
+    let ex_data: *const crate::ex_data::KeyExData =
+        (<openssl_sys::ENGINE as openssl2::ExDataAccessors>::GET_FN)(e, ex_index) as _;
+    ex_data.as_ref()
+}
+
+pub(super) unsafe fn get_evp_ed25519_method(
+) -> Result<*const openssl_sys2::EVP_PKEY_METHOD, openssl2::Error> {
+    static mut RESULT: *const openssl_sys2::EVP_PKEY_METHOD = std::ptr::null();
+    static RESULT_INIT: std::sync::Once = std::sync::Once::new();
+
+    RESULT_INIT.call_once(|| {
+        // If we can't create the method, log the error and swallow it, leaving RESULT as nullptr.
+        // The caller will get an error from openssl_returns_nonnull_const below.
+        let _ = super::r#catch(None, || {
+            let openssl_method = openssl2::openssl_returns_nonnull_const(
+                openssl_sys2::EVP_PKEY_meth_find(openssl_sys::EVP_PKEY_ED25519),
+            )?;
+
+            let aziot_key_method =
+                openssl2::openssl_returns_nonnull(openssl_sys2::EVP_PKEY_meth_new(
+                    openssl_sys::EVP_PKEY_ED25519,
+                    openssl_sys2::EVP_PKEY_FLAG_SIGCTX_CUSTOM,
+                ))?;
+            openssl_sys2::EVP_PKEY_meth_copy(aziot_key_method, openssl_method);
+
+            // Verification only needs the public key, so reuse openssl's function for it.
+            let mut openssl_digestverify = None;
+            openssl_sys2::EVP_PKEY_meth_get_digestverify(openssl_method, &mut openssl_digestverify);
+            openssl_sys2::EVP_PKEY_meth_set_digestverify(aziot_key_method, openssl_digestverify);
+
+            openssl_sys2::EVP_PKEY_meth_set_digestsign(
+                aziot_key_method,
+                Some(aziot_key_ed25519_digestsign),
+            );
+
+            RESULT = aziot_key_method as _;
+
+            Ok(())
+        });
+    });
+
+    openssl2::openssl_returns_nonnull_const(RESULT)
+}
+
+unsafe extern "C" fn aziot_key_ed25519_digestsign(
+    ctx: *mut openssl_sys::EVP_MD_CTX,
+    sig: *mut std::os::raw::c_uchar,
+    siglen: *mut usize,
+    tbs: *const std::os::raw::c_uchar,
+    tbslen: usize,
+) -> std::os::raw::c_int {
+    let result = super::r#catch(Some(|| super::Error::AZIOT_KEY_ED25519_SIGN), || {
+        let key_ex_data = {
+            let pkey_ctx =
+                openssl2::openssl_returns_nonnull(openssl_sys2::EVP_MD_CTX_pkey_ctx(ctx))?;
+            let pkey =
+                openssl2::openssl_returns_nonnull(openssl_sys2::EVP_PKEY_CTX_get0_pkey(pkey_ctx))?;
+            key_ex_data(pkey)
+        };
+        let crate::ex_data::KeyExData { client, handle } = if let Some(key_ex_data) = key_ex_data {
+            key_ex_data
+        } else {
+            let openssl_method = openssl2::openssl_returns_nonnull_const(
+                openssl_sys2::EVP_PKEY_meth_find(openssl_sys::EVP_PKEY_ED25519),
+            )?;
+            let mut openssl_digestsign = None;
+            openssl_sys2::EVP_PKEY_meth_get_digestsign(openssl_method, &mut openssl_digestsign);
+            let openssl_digestsign =
+                openssl_digestsign.ok_or("openssl's Ed25519 method cannot sign")?;
+            return Ok(openssl_digestsign(ctx, sig, siglen, tbs, tbslen));
+        };
+
+        // The caller only wants to know the size of the signature.
+        if sig.is_null() {
+            *siglen = openssl2::ed25519::SIGNATURE_LEN;
+            return Ok(1);
+        }
+
+        let mechanism = aziot_key_common::SignMechanism::Eddsa;
+
+        let message = std::slice::from_raw_parts(tbs, tbslen);
+
+        let signature = client.sign(handle, mechanism, message)?;
+        if signature.len() > *siglen {
+            return Err("signature buffer is too small".into());
+        }
+
+        let sig = std::slice::from_raw_parts_mut(sig, signature.len());
+        sig.copy_from_slice(&signature);
+        *siglen = signature.len();
+
+        Ok(1)
+    });
+    match result {
+        Ok(result) => result,
+        Err(()) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    /// Serves the requests the engine makes for an Ed25519 key on a unix socket, signing with the given key.
+    fn fake_key_service(
+        key: openssl::pkey::PKey<openssl::pkey::Private>,
+    ) -> aziot_key_client::Client {
+        static NEXT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+        let socket_path = std::env::temp_dir().join(format!(
+            "aziot-key-openssl-engine-test-{}-{}.sock",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+        ));
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+
+                let mut req = vec![];
+                let mut buf = [0_u8; 512];
+                let (headers, body) = loop {
+                    let read = stream.read(&mut buf).unwrap();
+                    assert_ne!(read, 0, "connection closed mid-request");
+                    req.extend_from_slice(&buf[..read]);
+
+                    let headers_end = match req.windows(4).position(|w| w == b"\r\n\r\n") {
+                        Some(headers_end) => headers_end,
+                        None => continue,
+                    };
+                    let headers = std::str::from_utf8(&req[..headers_end]).unwrap();
+                    let content_length = headers
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length: "))
+                        .map_or(0, |len| len.parse().unwrap());
+                    if req.len() - headers_end - 4 == content_length {
+                        break (headers.to_owned(), req[(headers_end + 4)..].to_owned());
+                    }
+                };
+
+                let res_body = if headers.starts_with("POST /parameters/algorithm?") {
+                    r#"{"value":"ED25519"}"#.to_owned()
+                } else if headers.starts_with("POST /parameters/ec-point?") {
+                    format!(
+                        r#"{{"value":"{}"}}"#,
+                        base64::encode(openssl2::ed25519::public_key_to_raw(&key).unwrap())
+                    )
+                } else if headers.starts_with("POST /sign?") {
+                    let body: aziot_key_common_http::sign::Request =
+                        serde_json::from_slice(&body).unwrap();
+                    let message =
+                        if let aziot_key_common_http::sign::Parameters::Eddsa { message } =
+                            body.parameters
+                        {
+                            message.0
+                        } else {
+                            panic!("expected EdDSA sign parameters");
+                        };
+                    let mut signer = openssl::sign::Signer::new_without_digest(&key).unwrap();
+                    let signature = signer.sign_oneshot_to_vec(&message).unwrap();
+                    format!(r#"{{"signature":"{}"}}"#, base64::encode(signature))
+                } else {
+                    panic!("unexpected request {:?}", headers);
+                };
+
+                // The client expects the whole response in a single read.
+                let res = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                    res_body.len(),
+                    res_body,
+                );
+                stream.write_all(res.as_bytes()).unwrap();
+            }
+        });
+
+        aziot_key_client::Client::new(
+            aziot_key_common_http::ApiVersion::V2020_09_01,
+            http_common::Connector::Unix {
+                socket_path: socket_path.into(),
+            },
+        )
+    }
+
+    #[test]
+    fn sign_with_key_service() {
+        let key = openssl::pkey::PKey::generate_ed25519().unwrap();
+        let public_key = openssl2::ed25519::public_key_from_raw(
+            &openssl2::ed25519::public_key_to_raw(&key).unwrap(),
+        )
+        .unwrap();
+
+        let client = std::sync::Arc::new(fake_key_service(key));
+        let mut engine = crate::load(client.clone()).unwrap();
+
+        let clients_before = std::sync::Arc::strong_count(&client);
+
+        let key_handle = std::ffi::CString::new("key-handle").unwrap();
+        let engine_key = engine.load_private_key(&key_handle).unwrap();
+
+        // The key holds the key handle, and with it a reference to the client.
+        assert_eq!(std::sync::Arc::strong_count(&client), clients_before + 1);
+
+        let message = b"message to sign";
+        let mut signer = openssl::sign::Signer::new_without_digest(&engine_key).unwrap();
+        let signature = signer.sign_oneshot_to_vec(message).unwrap();
+
+        let mut verifier = openssl::sign::Verifier::new_without_digest(&public_key).unwrap();
+        assert!(verifier.verify_oneshot(&signature, message).unwrap());
+
+        // Freeing the key frees its key handle.
+        drop(signer);
+        drop(engine_key);
+        assert_eq!(std::sync::Arc::strong_count(&client), clients_before);
+    }
+
+    #[test]
+    fn sign_key_not_loaded_by_engine() {
+        let client = std::sync::Arc::new(aziot_key_client::Client::new(
+            aziot_key_common_http::ApiVersion::V2020_09_01,
+            http_common::Connector::Unix {
+                socket_path: std::path::Path::new("/nonexistent.sock").into(),
+            },
+        ));
+        let engine = crate::load(client).unwrap();
+
+        let key = openssl::pkey::PKey::generate_ed25519().unwrap();
+        unsafe {
+            openssl2::openssl_returns_1(openssl_sys2::EVP_PKEY_set1_engine(
+                foreign_types_shared::ForeignType::as_ptr(&key),
+                foreign_types_shared::ForeignTypeRef::as_ptr(&*engine),
+            ))
+            .unwrap();
+        }
+
+        // The engine's method is used for the key, but it has no key handle, so openssl's own function signs with it.
+        let message = b"message to sign";
+        let mut signer = openssl::sign::Signer::new_without_digest(&key).unwrap();
+        let signature = signer.sign_oneshot_to_vec(message).unwrap();
+
+        let mut verifier = openssl::sign::Verifier::new_without_digest(&key).unwrap();
+        assert!(verifier.verify_oneshot(&signature, message).unwrap());
+    }
+}
@@ -140,7 +140,7 @@ unsafe extern "C" fn engine_load_privkey(
     _callback_data: *mut std::ffi::c_void,
 ) -> *mut openssl_sys::EVP_PKEY {
     let result = super::r#catch(Some(|| super::Error::ENGINE_LOAD_PRIVKEY), || {
-        let engine = crate::ex_data::get(&*e)?;
+        let engine: &Engine = crate::ex_data::get(&*e)?;
 
         let client = engine.client.clone();
 
@@ -226,12 +226,24 @@ unsafe extern "C" fn engine_load_privkey(
                 openssl_key_raw
             }
 
+            #[cfg(ossl111)]
+            "ED25519" => {
+                let public_key = client.get_key_pair_public_parameter(&key_handle, "ec-point")?;
+                let public_key = base64::decode(&public_key)?;
+
+                // Signing is done by the engine's Ed25519 EVP_PKEY_METHOD, which gets the key handle from the key's own ENGINE.
+                let openssl_key_raw = super::ed25519::private_key(&public_key, key_ex_data)?;
+
+                openssl_key_raw
+            }
+
             key_algorithm => {
                 return Err(format!("unrecognized key algorithm {}", key_algorithm).into())
             }
         };
 
         // Needed for openssl 1.1, otherwise the key is not associated with the engine.
+        // For Ed25519 keys, this is what makes openssl sign with the engine's EVP_PKEY_METHOD.
         #[cfg(ossl110)]
         openssl2::openssl_returns_1(openssl_sys2::EVP_PKEY_set1_engine(openssl_key_raw, e))?;
 
@@ -250,7 +262,7 @@ unsafe extern "C" fn engine_load_pubkey(
     _callback_data: *mut std::ffi::c_void,
 ) -> *mut openssl_sys::EVP_PKEY {
     let result = super::r#catch(Some(|| super::Error::ENGINE_LOAD_PUBKEY), || {
-        let engine = crate::ex_data::get(&*e)?;
+        let engine: &Engine = crate::ex_data::get(&*e)?;
 
         let key_handle = std::ffi::CStr::from_ptr(key_id).to_str()?;
         let key_handle = aziot_key_common::KeyHandle(key_handle.to_owned());
@@ -303,6 +315,17 @@ unsafe extern "C" fn engine_load_pubkey(
                 openssl_key_raw
             }
 
+            #[cfg(ossl111)]
+            "ED25519" => {
+                let public_key = client.get_key_pair_public_parameter(&key_handle, "ec-point")?;
+                let public_key = base64::decode(&public_key)?;
+
+                let openssl_key = openssl2::ed25519::public_key_from_raw(&public_key)?;
+                let openssl_key_raw = openssl2::foreign_type_into_ptr(openssl_key);
+
+                openssl_key_raw
+            }
+
             key_algorithm => {
                 return Err(format!("unrecognized key algorithm {}", key_algorithm).into())
             }
@@ -334,8 +357,15 @@ unsafe extern "C" fn engine_pkey_meths(
     //    The caller wants us to populate the methods of nid in pmeths. Return non-zero on success, zero on failure.
 
     let result = super::r#catch(Some(|| super::Error::ENGINE_PKEY_METHS), || {
+        #[cfg(not(ossl111))]
         const SUPPORTED_NIDS: &[std::os::raw::c_int] =
             &[openssl_sys::EVP_PKEY_EC, openssl_sys::EVP_PKEY_RSA];
+        #[cfg(ossl111)]
+        const SUPPORTED_NIDS: &[std::os::raw::c_int] = &[
+            openssl_sys::EVP_PKEY_EC,
+            openssl_sys::EVP_PKEY_RSA,
+            openssl_sys::EVP_PKEY_ED25519,
+        ];
 
         if pmeth.is_null() {
             // Mode 1
@@ -359,6 +389,12 @@ unsafe extern "C" fn engine_pkey_meths(
                     Ok(1)
                 }
 
+                #[cfg(ossl111)]
+                openssl_sys::EVP_PKEY_ED25519 => {
+                    *pmeth = super::ed25519::get_evp_ed25519_method()?;
+                    Ok(1)
+                }
+
                 nid => Err(format!("unsupported nid 0x{:08x}", nid).into()),
             }
         }
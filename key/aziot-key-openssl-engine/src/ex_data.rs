@@ -3,6 +3,7 @@
 #[derive(Clone, Copy)]
 pub(crate) struct ExIndices {
     pub(crate) engine: openssl::ex_data::Index<openssl_sys::ENGINE, crate::engine::Engine>,
+    pub(crate) ed25519_key_engine: openssl::ex_data::Index<openssl_sys::ENGINE, KeyExData>,
     pub(crate) ec_key: openssl::ex_data::Index<openssl_sys::EC_KEY, KeyExData>,
     pub(crate) rsa: openssl::ex_data::Index<openssl_sys::RSA, KeyExData>,
 }
@@ -17,6 +18,7 @@ pub(crate) unsafe fn ex_indices() -> ExIndices {
         let _ = super::r#catch(None, || {
             extern "C" {
                 fn aziot_key_get_engine_ex_index() -> std::os::raw::c_int;
+                fn aziot_key_get_ed25519_key_engine_ex_index() -> std::os::raw::c_int;
                 fn aziot_key_get_ec_key_ex_index() -> std::os::raw::c_int;
                 fn aziot_key_get_rsa_ex_index() -> std::os::raw::c_int;
             }
@@ -30,6 +32,15 @@ pub(crate) unsafe fn ex_indices() -> ExIndices {
                 .into());
             }
 
+            let ed25519_key_engine_ex_index = aziot_key_get_ed25519_key_engine_ex_index();
+            if ed25519_key_engine_ex_index == -1 {
+                return Err(format!(
+                    "could not register Ed25519 key ENGINE ex index: {}",
+                    openssl::error::ErrorStack::get()
+                )
+                .into());
+            }
+
             let ec_key_ex_index = aziot_key_get_ec_key_ex_index();
             if ec_key_ex_index == -1 {
                 return Err(format!(
@@ -50,6 +61,7 @@ pub(crate) unsafe fn ex_indices() -> ExIndices {
 
             let ex_indices = ExIndices {
                 engine: openssl::ex_data::Index::from_raw(engine_ex_index),
+                ed25519_key_engine: openssl::ex_data::Index::from_raw(ed25519_key_engine_ex_index),
                 ec_key: openssl::ex_data::Index::from_raw(ec_key_ex_index),
                 rsa: openssl::ex_data::Index::from_raw(rsa_ex_index),
             };
@@ -26,6 +26,9 @@
 
 mod ec_key;
 
+#[cfg(ossl111)]
+mod ed25519;
+
 mod engine;
 
 pub(crate) mod ex_data;
@@ -81,6 +84,8 @@ openssl_errors::openssl_errors! {
 
             AZIOT_KEY_EC_SIGN("aziot_key_ec_sign");
 
+            AZIOT_KEY_ED25519_SIGN("aziot_key_ed25519_sign");
+
             AZIOT_KEY_RSA_PRIV_ENC("aziot_key_rsa_priv_enc");
         }
 
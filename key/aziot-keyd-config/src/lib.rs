@@ -4,8 +4,34 @@
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::default_trait_access)]
 
+/// The default lifetime of key handles issued by the service, in seconds (0, so that they never expire).
+///
+/// Identity service hands key handles to modules, which may hold on to them and not re-obtain them when they expire,
+/// so expiry is opt-in.
+pub const DEFAULT_HANDLE_LIFETIME_SECS: u64 = 0;
+
+/// The default maximum number of requests that the service processes concurrently.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
 #[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct Config {
+    /// How long key handles issued by the service remain valid, in seconds. Defaults to [`DEFAULT_HANDLE_LIFETIME_SECS`].
+    ///
+    /// A value of 0 means that key handles never expire. Regardless of this setting, all outstanding key handles
+    /// can be revoked by rotating the handle validation key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handle_lifetime_secs: Option<u64>,
+
+    /// The maximum number of requests that the service processes concurrently. Defaults to [`DEFAULT_MAX_CONCURRENCY`].
+    ///
+    /// This is ignored if the key backend is not thread-safe, in which case requests are processed one at a time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<std::num::NonZeroUsize>,
+
+    /// The backend that stores keys and performs operations with them. Defaults to [`Backend::Library`].
+    #[serde(default, skip_serializing_if = "Backend::is_default")]
+    pub backend: Backend,
+
     /// Parameters passed down to libaziot-keys. The allowed names and values are determined by the libaziot-keys implementation.
     #[serde(default)]
     pub aziot_keys: std::collections::BTreeMap<String, String>,
@@ -31,6 +57,27 @@ pub struct Config {
     pub principal: Vec<Principal>,
 }
 
+/// The backend that stores keys and performs operations with them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// The libaziot-keys library that the service is linked against. `aziot_keys` and `preloaded_keys` are passed down to it.
+    #[default]
+    Library,
+
+    /// An in-process backend that keeps keys in memory. Keys are lost when the service stops, so this is only suitable for tests.
+    ///
+    /// `aziot_keys` and `preloaded_keys` are ignored.
+    Memory,
+}
+
+impl Backend {
+    #[allow(clippy::trivially_copy_pass_by_ref)] // Signature required by serde's skip_serializing_if
+    fn is_default(&self) -> bool {
+        *self == Backend::default()
+    }
+}
+
 /// Map of service names to endpoint URIs.
 #[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct Endpoints {
@@ -56,6 +103,13 @@ pub struct Principal {
 
     /// Key IDs for which the given UID has access. Wildcards may be used.
     pub keys: Vec<String>,
+
+    /// Whether the given UID may also export these keys wrapped under a public key,
+    /// and import wrapped keys under these IDs. Defaults to `false`.
+    ///
+    /// Unlike access to keys, this is not implicitly granted to root.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub export: bool,
 }
 
 #[cfg(test)]
@@ -63,6 +117,9 @@ mod tests {
     #[test]
     fn parse_config() {
         let actual = r#"
+handle_lifetime_secs = 3600
+max_concurrency = 8
+
 [aziot_keys]
 homedir_path = "/var/lib/aziot/keyd"
 pkcs11_lib_path = "/usr/lib64/pkcs11/libsofthsm2.so"
@@ -75,12 +132,22 @@ device-id = "pkcs11:token=Key pairs;object=device-id?pin-value=1234"
 [[principal]]
 uid = 1000
 keys = ["test"]
+
+[[principal]]
+uid = 0
+keys = ["device-id"]
+export = true
 "#;
         let actual: super::Config = toml::from_str(actual).unwrap();
 
         assert_eq!(
             actual,
             super::Config {
+                handle_lifetime_secs: Some(3600),
+                max_concurrency: std::num::NonZeroUsize::new(8),
+
+                backend: super::Backend::Library,
+
                 aziot_keys: [
                     ("homedir_path", "/var/lib/aziot/keyd"),
                     ("pkcs11_lib_path", "/usr/lib64/pkcs11/libsofthsm2.so"),
@@ -107,10 +174,18 @@ keys = ["test"]
                     },
                 },
 
-                principal: vec![super::Principal {
-                    uid: 1000,
-                    keys: vec!["test".to_owned()]
-                }],
+                principal: vec![
+                    super::Principal {
+                        uid: 1000,
+                        keys: vec!["test".to_owned()],
+                        export: false,
+                    },
+                    super::Principal {
+                        uid: 0,
+                        keys: vec!["device-id".to_owned()],
+                        export: true,
+                    },
+                ],
             }
         );
     }
@@ -127,6 +202,11 @@ aziot_keyd = "unix:///run/aziot/keyd.sock"
         assert_eq!(
             actual,
             super::Config {
+                handle_lifetime_secs: None,
+                max_concurrency: None,
+
+                backend: super::Backend::Library,
+
                 aziot_keys: Default::default(),
 
                 preloaded_keys: Default::default(),
@@ -141,4 +221,19 @@ aziot_keyd = "unix:///run/aziot/keyd.sock"
             }
         );
     }
+
+    #[test]
+    fn parse_config_with_memory_backend() {
+        let actual = r#"
+backend = "memory"
+
+[[principal]]
+uid = 1000
+keys = ["*"]
+"#;
+        let actual: super::Config = toml::from_str(actual).unwrap();
+
+        assert_eq!(actual.backend, super::Backend::Memory);
+        assert!(actual.aziot_keys.is_empty());
+    }
 }
@@ -0,0 +1,733 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use crate::keys::{
+    sys, CreateKeyIfNotExistsError, CreateKeyPairIfNotExistsError, DecryptError, DeleteKeyError,
+    DeleteKeyPairError, DeriveKeyError, DeriveSharedSecretError, EncryptError, EnumerateKeysError,
+    ExportKeyError, ExportKeyPairError, GetKeyPairPublicParameterError, GetKeyParameterError,
+    ImportKeyError, ImportKeyPairError, ImportWrappedKeyError, ImportWrappedKeyPairError, Keys,
+    KeysRawError, LoadKeyError, LoadKeyPairError, LoadLibraryError, MoveKeyPairError,
+    SetLibraryParameterError, SignError, StreamError, VerifyError,
+};
+
+use super::{Derivation, KeyBackend, KeyStream};
+
+/// The libaziot-keys library that keyd is linked against.
+pub(crate) struct Library(Keys);
+
+impl Library {
+    pub(crate) fn new() -> Result<Self, LoadLibraryError> {
+        Ok(Library(Keys::new()?))
+    }
+
+    /// Libraries that implement an API version before 2.1.0.0 only derive keys with HMAC-SHA256, and don't know the
+    /// `*_DERIVED_WITH_MECHANISM` mechanisms that other derivation mechanisms are passed with.
+    fn check_derivation(&self, derivation: Option<&Derivation<'_>>) -> Result<(), KeysRawError> {
+        match derivation {
+            Some(derivation)
+                if !matches!(
+                    derivation.mechanism,
+                    aziot_key_common::KeyDerivationMechanism::HmacSha256
+                ) && !self.0.supports_derivation_mechanisms() =>
+            {
+                Err(KeysRawError::INVALID_PARAMETER)
+            }
+
+            _ => Ok(()),
+        }
+    }
+}
+
+impl KeyBackend for Library {
+    fn is_thread_safe(&self) -> bool {
+        self.0.is_thread_safe()
+    }
+
+    fn set_parameter(&mut self, name: &str, value: &str) -> Result<(), SetLibraryParameterError> {
+        let err = || SetLibraryParameterError {
+            name: name.to_owned(),
+            err: KeysRawError::INVALID_PARAMETER,
+        };
+        let name_cstr = std::ffi::CString::new(name).map_err(|_| err())?;
+        let value = std::ffi::CString::new(value).map_err(|_| err())?;
+        self.0.set_parameter(&name_cstr, &value)
+    }
+
+    fn create_key_pair_if_not_exists(
+        &self,
+        id: &str,
+        preferred_algorithms: Option<&str>,
+    ) -> Result<(), CreateKeyPairIfNotExistsError> {
+        let id = to_cstring(id).map_err(|err| CreateKeyPairIfNotExistsError { err })?;
+        let preferred_algorithms = preferred_algorithms
+            .map(to_cstring)
+            .transpose()
+            .map_err(|err| CreateKeyPairIfNotExistsError { err })?;
+        self.0
+            .create_key_pair_if_not_exists(&id, preferred_algorithms.as_deref())
+    }
+
+    fn load_key_pair(&self, id: &str) -> Result<(), LoadKeyPairError> {
+        let id = to_cstring(id).map_err(|err| LoadKeyPairError { err })?;
+        self.0.load_key_pair(&id)
+    }
+
+    fn get_key_pair_public_parameter(
+        &self,
+        id: &str,
+        parameter_name: &str,
+    ) -> Result<String, GetKeyPairPublicParameterError> {
+        let id = to_cstring(id).map_err(|err| GetKeyPairPublicParameterError::Api { err })?;
+        self.0.get_key_pair_public_parameter(&id, parameter_name)
+    }
+
+    fn create_key_if_not_exists(
+        &self,
+        id: &str,
+        usage: &[aziot_key_common::KeyUsage],
+    ) -> Result<(), CreateKeyIfNotExistsError> {
+        let id = to_cstring(id).map_err(|err| CreateKeyIfNotExistsError { err })?;
+        self.0
+            .create_key_if_not_exists(&id, key_usage_to_sys(usage))
+    }
+
+    fn load_key(&self, id: &str) -> Result<(), LoadKeyError> {
+        let id = to_cstring(id).map_err(|err| LoadKeyError { err })?;
+        self.0.load_key(&id)
+    }
+
+    fn import_key(
+        &self,
+        id: &str,
+        bytes: &[u8],
+        usage: &[aziot_key_common::KeyUsage],
+    ) -> Result<(), ImportKeyError> {
+        let id = to_cstring(id).map_err(|err| ImportKeyError { err })?;
+        self.0.import_key(&id, bytes, key_usage_to_sys(usage))
+    }
+
+    fn sign(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::SignMechanism,
+        derivation: Option<&Derivation<'_>>,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, SignError> {
+        let id = to_cstring(id).map_err(|err| SignError { err })?;
+        self.check_derivation(derivation)
+            .map_err(|err| SignError { err })?;
+        with_sign_mechanism(mechanism, derivation, |mechanism, parameters| {
+            self.0
+                .sign(&id, mechanism, parameters, digest)
+                .map_err(|SignError { err }| err)
+        })
+        .map_err(|err| SignError { err })
+    }
+
+    fn verify(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::SignMechanism,
+        derivation: Option<&Derivation<'_>>,
+        digest: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, VerifyError> {
+        let id = to_cstring(id).map_err(|err| VerifyError { err })?;
+        self.check_derivation(derivation)
+            .map_err(|err| VerifyError { err })?;
+        with_sign_mechanism(mechanism, derivation, |mechanism, parameters| {
+            self.0
+                .verify(&id, mechanism, parameters, digest, signature)
+                .map_err(|VerifyError { err }| err)
+        })
+        .map_err(|err| VerifyError { err })
+    }
+
+    fn encrypt(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::EncryptMechanism,
+        derivation: Option<&Derivation<'_>>,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, EncryptError> {
+        let id = to_cstring(id).map_err(|err| EncryptError { err })?;
+        self.check_derivation(derivation)
+            .map_err(|err| EncryptError { err })?;
+        with_encrypt_mechanism(mechanism, derivation, |mechanism, parameters| {
+            self.0
+                .encrypt(&id, mechanism, parameters, plaintext)
+                .map_err(|EncryptError { err }| err)
+        })
+        .map_err(|err| EncryptError { err })
+    }
+
+    fn decrypt(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::EncryptMechanism,
+        derivation: Option<&Derivation<'_>>,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, DecryptError> {
+        let id = to_cstring(id).map_err(|err| DecryptError { err })?;
+        self.check_derivation(derivation)
+            .map_err(|err| DecryptError { err })?;
+        with_encrypt_mechanism(mechanism, derivation, |mechanism, parameters| {
+            self.0
+                .decrypt(&id, mechanism, parameters, ciphertext)
+                .map_err(|DecryptError { err }| err)
+        })
+        .map_err(|err| DecryptError { err })
+    }
+
+    fn import_key_pair(
+        &self,
+        id: &str,
+        bytes: &[u8],
+        format: aziot_key_common::KeyPairFormat,
+        password: Option<&str>,
+    ) -> Result<(), ImportKeyPairError> {
+        let id = to_cstring(id).map_err(|err| ImportKeyPairError { err })?;
+        let password = password
+            .map(to_cstring)
+            .transpose()
+            .map_err(|err| ImportKeyPairError { err })?;
+        let format = match format {
+            aziot_key_common::KeyPairFormat::Pkcs8Pem => sys::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_PEM,
+            aziot_key_common::KeyPairFormat::Pkcs8Der => sys::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_DER,
+            aziot_key_common::KeyPairFormat::Pkcs12 => sys::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS12,
+        };
+        self.0
+            .import_key_pair(&id, format, bytes, password.as_deref())
+    }
+
+    fn delete_key_pair(&self, id: &str) -> Result<(), DeleteKeyPairError> {
+        let id = to_cstring(id).map_err(|err| DeleteKeyPairError { err })?;
+        self.0.delete_key_pair(&id)
+    }
+
+    fn move_key_pair(&self, from_id: &str, to_id: &str) -> Result<(), MoveKeyPairError> {
+        let from_id = to_cstring(from_id).map_err(|err| MoveKeyPairError { err })?;
+        let to_id = to_cstring(to_id).map_err(|err| MoveKeyPairError { err })?;
+        self.0.move_key_pair(&from_id, &to_id)
+    }
+
+    fn get_key_parameter(
+        &self,
+        id: &str,
+        parameter_name: &str,
+    ) -> Result<String, GetKeyParameterError> {
+        let id = to_cstring(id).map_err(|err| GetKeyParameterError::Api { err })?;
+        self.0.get_key_parameter(&id, parameter_name)
+    }
+
+    fn delete_key(&self, id: &str) -> Result<(), DeleteKeyError> {
+        let id = to_cstring(id).map_err(|err| DeleteKeyError { err })?;
+        self.0.delete_key(&id)
+    }
+
+    fn enumerate_keys(&self) -> Result<Vec<aziot_key_common::KeyInfo>, EnumerateKeysError> {
+        self.0.enumerate_keys()
+    }
+
+    fn derive_key(&self, id: &str, derivation: &Derivation<'_>) -> Result<Vec<u8>, DeriveKeyError> {
+        let id = to_cstring(id).map_err(|err| DeriveKeyError { err })?;
+        with_key_derivation_mechanism(derivation.mechanism, |mechanism, parameters| {
+            self.0
+                .derive_key(&id, mechanism, parameters, derivation.data)
+        })
+    }
+
+    fn export_key(
+        &self,
+        id: &str,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapping_key: &[u8],
+    ) -> Result<Vec<u8>, ExportKeyError> {
+        let id = to_cstring(id).map_err(|err| ExportKeyError { err })?;
+        self.0
+            .export_key(&id, wrap_mechanism_to_sys(mechanism), wrapping_key)
+    }
+
+    fn export_key_pair(
+        &self,
+        id: &str,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapping_key: &[u8],
+    ) -> Result<Vec<u8>, ExportKeyPairError> {
+        let id = to_cstring(id).map_err(|err| ExportKeyPairError { err })?;
+        self.0
+            .export_key_pair(&id, wrap_mechanism_to_sys(mechanism), wrapping_key)
+    }
+
+    fn import_wrapped_key(
+        &self,
+        id: &str,
+        unwrapping_key_pair_id: &str,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapped_key: &[u8],
+    ) -> Result<(), ImportWrappedKeyError> {
+        let id = to_cstring(id).map_err(|err| ImportWrappedKeyError { err })?;
+        let unwrapping_key_pair_id =
+            to_cstring(unwrapping_key_pair_id).map_err(|err| ImportWrappedKeyError { err })?;
+        self.0.import_wrapped_key(
+            &id,
+            &unwrapping_key_pair_id,
+            wrap_mechanism_to_sys(mechanism),
+            wrapped_key,
+        )
+    }
+
+    fn import_wrapped_key_pair(
+        &self,
+        id: &str,
+        unwrapping_key_pair_id: &str,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapped_key: &[u8],
+    ) -> Result<(), ImportWrappedKeyPairError> {
+        let id = to_cstring(id).map_err(|err| ImportWrappedKeyPairError { err })?;
+        let unwrapping_key_pair_id =
+            to_cstring(unwrapping_key_pair_id).map_err(|err| ImportWrappedKeyPairError { err })?;
+        self.0.import_wrapped_key_pair(
+            &id,
+            &unwrapping_key_pair_id,
+            wrap_mechanism_to_sys(mechanism),
+            wrapped_key,
+        )
+    }
+
+    fn derive_shared_secret(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::KeyAgreementMechanism,
+        peer_public_key: &[u8],
+    ) -> Result<Vec<u8>, DeriveSharedSecretError> {
+        let id = to_cstring(id).map_err(|err| DeriveSharedSecretError { err })?;
+
+        match mechanism {
+            aziot_key_common::KeyAgreementMechanism::Ecdh => self.0.derive_shared_secret(
+                &id,
+                sys::AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_ECDH,
+                std::ptr::null(),
+                peer_public_key,
+            ),
+
+            aziot_key_common::KeyAgreementMechanism::EcdhHkdf {
+                digest,
+                salt,
+                info,
+                len,
+            } => {
+                let parameters = sys::AZIOT_KEYS_ECDH_HKDF_PARAMETERS {
+                    digest_algorithm: digest_algorithm_to_sys(*digest),
+                    salt: salt.as_ptr(),
+                    salt_len: salt.len(),
+                    info: info.as_ptr(),
+                    info_len: info.len(),
+                    output_len: *len,
+                };
+
+                self.0.derive_shared_secret(
+                    &id,
+                    sys::AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_ECDH_HKDF,
+                    (&parameters as *const sys::AZIOT_KEYS_ECDH_HKDF_PARAMETERS).cast(),
+                    peer_public_key,
+                )
+            }
+        }
+    }
+
+    fn import_key_if_not_exists(
+        &self,
+        id: &str,
+        bytes: &[u8],
+        usage: &[aziot_key_common::KeyUsage],
+    ) -> Result<(), ImportKeyError> {
+        let id = to_cstring(id).map_err(|err| ImportKeyError { err })?;
+        self.0
+            .import_key_if_not_exists(&id, bytes, key_usage_to_sys(usage))
+    }
+
+    fn encrypt_init(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::EncryptMechanism,
+        derivation: Option<&Derivation<'_>>,
+    ) -> Result<Box<dyn KeyStream>, StreamError> {
+        let id = to_cstring(id).map_err(|err| StreamError { err })?;
+        self.check_derivation(derivation)
+            .map_err(|err| StreamError { err })?;
+        let stream = with_encrypt_mechanism(mechanism, derivation, |mechanism, parameters| {
+            self.0
+                .encrypt_init(&id, mechanism, parameters)
+                .map_err(|StreamError { err }| err)
+        })
+        .map_err(|err| StreamError { err })?;
+        Ok(Box::new(stream))
+    }
+
+    fn decrypt_init(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::EncryptMechanism,
+        derivation: Option<&Derivation<'_>>,
+    ) -> Result<Box<dyn KeyStream>, StreamError> {
+        let id = to_cstring(id).map_err(|err| StreamError { err })?;
+        self.check_derivation(derivation)
+            .map_err(|err| StreamError { err })?;
+        let stream = with_encrypt_mechanism(mechanism, derivation, |mechanism, parameters| {
+            self.0
+                .decrypt_init(&id, mechanism, parameters)
+                .map_err(|StreamError { err }| err)
+        })
+        .map_err(|err| StreamError { err })?;
+        Ok(Box::new(stream))
+    }
+
+    fn sign_init(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::SignMechanism,
+        derivation: Option<&Derivation<'_>>,
+    ) -> Result<Box<dyn KeyStream>, StreamError> {
+        let id = to_cstring(id).map_err(|err| StreamError { err })?;
+        self.check_derivation(derivation)
+            .map_err(|err| StreamError { err })?;
+        let stream = with_sign_mechanism(mechanism, derivation, |mechanism, parameters| {
+            self.0
+                .sign_init(&id, mechanism, parameters)
+                .map_err(|StreamError { err }| err)
+        })
+        .map_err(|err| StreamError { err })?;
+        Ok(Box::new(stream))
+    }
+}
+
+impl KeyStream for crate::keys::Stream {
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>, StreamError> {
+        crate::keys::Stream::update(self, data)
+    }
+
+    fn finish(&mut self) -> Result<Vec<u8>, StreamError> {
+        crate::keys::Stream::finish(self)
+    }
+}
+
+/// IDs and other strings are passed to libaziot-keys as C strings, so they cannot contain NUL.
+fn to_cstring(s: &str) -> Result<std::ffi::CString, KeysRawError> {
+    std::ffi::CString::new(s).map_err(|_| KeysRawError::INVALID_PARAMETER)
+}
+
+/// Invokes `f` with the libaziot-keys mechanism and parameters for the given signature mechanism.
+///
+/// If `derivation` is set, the mechanism is wrapped in `AZIOT_KEYS_SIGN_MECHANISM_DERIVED`, or in `AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM`
+/// if the key is derived with a mechanism other than HMAC-SHA256. Only HMAC mechanisms can be used with derived keys.
+fn with_sign_mechanism<T>(
+    mechanism: &aziot_key_common::SignMechanism,
+    derivation: Option<&Derivation<'_>>,
+    f: impl FnOnce(sys::AZIOT_KEYS_SIGN_MECHANISM, *const std::ffi::c_void) -> Result<T, KeysRawError>,
+) -> Result<T, KeysRawError> {
+    if let Some(derivation) = derivation {
+        let mechanism = hmac_sign_mechanism(mechanism).ok_or(KeysRawError::INVALID_PARAMETER)?;
+
+        if let aziot_key_common::KeyDerivationMechanism::HmacSha256 = derivation.mechanism {
+            let parameters = sys::AZIOT_KEYS_SIGN_DERIVED_PARAMETERS {
+                derivation_data: derivation.data.as_ptr(),
+                derivation_data_len: derivation.data.len(),
+                mechanism,
+                parameters: std::ptr::null(),
+            };
+
+            return f(
+                sys::AZIOT_KEYS_SIGN_MECHANISM_DERIVED,
+                (&parameters as *const sys::AZIOT_KEYS_SIGN_DERIVED_PARAMETERS).cast(),
+            );
+        }
+
+        return with_key_derivation_mechanism(
+            derivation.mechanism,
+            |derivation_mechanism, derivation_parameters| {
+                let parameters = sys::AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS {
+                    derivation_data: derivation.data.as_ptr(),
+                    derivation_data_len: derivation.data.len(),
+                    mechanism,
+                    parameters: std::ptr::null(),
+                    derivation_mechanism,
+                    derivation_parameters,
+                };
+
+                f(
+                    sys::AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM,
+                    (&parameters as *const sys::AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS)
+                        .cast(),
+                )
+            },
+        );
+    }
+
+    match mechanism {
+        aziot_key_common::SignMechanism::Ecdsa => {
+            f(sys::AZIOT_KEYS_SIGN_MECHANISM_ECDSA, std::ptr::null())
+        }
+
+        aziot_key_common::SignMechanism::Eddsa => {
+            f(sys::AZIOT_KEYS_SIGN_MECHANISM_EDDSA, std::ptr::null())
+        }
+
+        aziot_key_common::SignMechanism::RsaPkcs1 { digest } => {
+            let parameters = sys::AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS {
+                digest_algorithm: digest_algorithm_to_sys(*digest),
+            };
+
+            f(
+                sys::AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1,
+                (&parameters as *const sys::AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS).cast(),
+            )
+        }
+
+        aziot_key_common::SignMechanism::RsaPss { digest, salt_len } => {
+            let parameters = sys::AZIOT_KEYS_SIGN_RSA_PSS_PARAMETERS {
+                digest_algorithm: digest_algorithm_to_sys(*digest),
+                salt_len: *salt_len,
+            };
+
+            f(
+                sys::AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS,
+                (&parameters as *const sys::AZIOT_KEYS_SIGN_RSA_PSS_PARAMETERS).cast(),
+            )
+        }
+
+        aziot_key_common::SignMechanism::HmacSha256
+        | aziot_key_common::SignMechanism::HmacSha384
+        | aziot_key_common::SignMechanism::HmacSha512 => {
+            let mechanism =
+                hmac_sign_mechanism(mechanism).expect("mechanism is known to be an HMAC mechanism");
+            f(mechanism, std::ptr::null())
+        }
+    }
+}
+
+fn hmac_sign_mechanism(
+    mechanism: &aziot_key_common::SignMechanism,
+) -> Option<sys::AZIOT_KEYS_SIGN_MECHANISM> {
+    match mechanism {
+        aziot_key_common::SignMechanism::HmacSha256 => {
+            Some(sys::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256)
+        }
+        aziot_key_common::SignMechanism::HmacSha384 => {
+            Some(sys::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA384)
+        }
+        aziot_key_common::SignMechanism::HmacSha512 => {
+            Some(sys::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA512)
+        }
+        _ => None,
+    }
+}
+
+fn digest_algorithm_to_sys(
+    digest_algorithm: aziot_key_common::DigestAlgorithm,
+) -> sys::AZIOT_KEYS_DIGEST_ALGORITHM {
+    match digest_algorithm {
+        aziot_key_common::DigestAlgorithm::Sha256 => sys::AZIOT_KEYS_DIGEST_ALGORITHM_SHA256,
+        aziot_key_common::DigestAlgorithm::Sha384 => sys::AZIOT_KEYS_DIGEST_ALGORITHM_SHA384,
+        aziot_key_common::DigestAlgorithm::Sha512 => sys::AZIOT_KEYS_DIGEST_ALGORITHM_SHA512,
+    }
+}
+
+/// Invokes `f` with the libaziot-keys mechanism and parameters for the given encryption mechanism.
+///
+/// If `derivation` is set, the mechanism is wrapped in `AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED`, or in `AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM`
+/// if the key is derived with a mechanism other than HMAC-SHA256. RSA mechanisms cannot be used with derived keys.
+fn with_encrypt_mechanism<T>(
+    mechanism: &aziot_key_common::EncryptMechanism,
+    derivation: Option<&Derivation<'_>>,
+    f: impl FnOnce(
+        sys::AZIOT_KEYS_ENCRYPT_MECHANISM,
+        *const std::ffi::c_void,
+    ) -> Result<T, KeysRawError>,
+) -> Result<T, KeysRawError> {
+    let derivation = if let Some(derivation) = derivation {
+        derivation
+    } else {
+        return with_plain_encrypt_mechanism(mechanism, f);
+    };
+
+    if matches!(
+        mechanism,
+        aziot_key_common::EncryptMechanism::RsaPkcs1
+            | aziot_key_common::EncryptMechanism::RsaNoPadding
+    ) {
+        return Err(KeysRawError::INVALID_PARAMETER);
+    }
+
+    with_plain_encrypt_mechanism(mechanism, |mechanism, parameters| {
+        if let aziot_key_common::KeyDerivationMechanism::HmacSha256 = derivation.mechanism {
+            let parameters = sys::AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS {
+                derivation_data: derivation.data.as_ptr(),
+                derivation_data_len: derivation.data.len(),
+                mechanism,
+                parameters,
+            };
+
+            return f(
+                sys::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED,
+                (&parameters as *const sys::AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS).cast(),
+            );
+        }
+
+        with_key_derivation_mechanism(
+            derivation.mechanism,
+            |derivation_mechanism, derivation_parameters| {
+                let parameters = sys::AZIOT_KEYS_ENCRYPT_DERIVED_WITH_MECHANISM_PARAMETERS {
+                    derivation_data: derivation.data.as_ptr(),
+                    derivation_data_len: derivation.data.len(),
+                    mechanism,
+                    parameters,
+                    derivation_mechanism,
+                    derivation_parameters,
+                };
+
+                f(
+                    sys::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM,
+                    (&parameters
+                        as *const sys::AZIOT_KEYS_ENCRYPT_DERIVED_WITH_MECHANISM_PARAMETERS)
+                        .cast(),
+                )
+            },
+        )
+    })
+}
+
+fn with_plain_encrypt_mechanism<T>(
+    mechanism: &aziot_key_common::EncryptMechanism,
+    f: impl FnOnce(
+        sys::AZIOT_KEYS_ENCRYPT_MECHANISM,
+        *const std::ffi::c_void,
+    ) -> Result<T, KeysRawError>,
+) -> Result<T, KeysRawError> {
+    match mechanism {
+        aziot_key_common::EncryptMechanism::Aead { iv, aad } => {
+            let parameters = sys::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS {
+                iv: iv.as_ptr(),
+                iv_len: iv.len(),
+                aad: aad.as_ptr(),
+                aad_len: aad.len(),
+            };
+
+            f(
+                sys::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD,
+                (&parameters as *const sys::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS).cast(),
+            )
+        }
+
+        aziot_key_common::EncryptMechanism::AesCbcPad { iv }
+        | aziot_key_common::EncryptMechanism::AesCtr { iv } => {
+            let parameters = sys::AZIOT_KEYS_ENCRYPT_IV_PARAMETERS {
+                iv: iv.as_ptr(),
+                iv_len: iv.len(),
+            };
+
+            let mechanism = if let aziot_key_common::EncryptMechanism::AesCbcPad { .. } = mechanism
+            {
+                sys::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD
+            } else {
+                sys::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR
+            };
+
+            f(
+                mechanism,
+                (&parameters as *const sys::AZIOT_KEYS_ENCRYPT_IV_PARAMETERS).cast(),
+            )
+        }
+
+        aziot_key_common::EncryptMechanism::AesKeyWrap => f(
+            sys::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP,
+            std::ptr::null(),
+        ),
+
+        aziot_key_common::EncryptMechanism::AesKeyWrapPad => f(
+            sys::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP_PAD,
+            std::ptr::null(),
+        ),
+
+        aziot_key_common::EncryptMechanism::RsaPkcs1 => f(
+            sys::AZIOT_KEYS_ENCRYPT_MECHANISM_RSA_PKCS1,
+            std::ptr::null(),
+        ),
+
+        aziot_key_common::EncryptMechanism::RsaNoPadding => f(
+            sys::AZIOT_KEYS_ENCRYPT_MECHANISM_RSA_NO_PADDING,
+            std::ptr::null(),
+        ),
+    }
+}
+
+/// Calls `f` with the `libaziot-keys` representation of the given key derivation mechanism and its parameters.
+fn with_key_derivation_mechanism<T>(
+    mechanism: &aziot_key_common::KeyDerivationMechanism,
+    f: impl FnOnce(sys::AZIOT_KEYS_KEY_DERIVATION_MECHANISM, *const std::ffi::c_void) -> T,
+) -> T {
+    match mechanism {
+        aziot_key_common::KeyDerivationMechanism::HmacSha256 => f(
+            sys::AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256,
+            std::ptr::null(),
+        ),
+
+        aziot_key_common::KeyDerivationMechanism::Hkdf { digest, salt, len } => {
+            let parameters = sys::AZIOT_KEYS_KEY_DERIVATION_HKDF_PARAMETERS {
+                digest_algorithm: digest_algorithm_to_sys(*digest),
+                salt: salt.as_ptr(),
+                salt_len: salt.len(),
+                output_len: *len,
+            };
+
+            f(
+                sys::AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HKDF,
+                (&parameters as *const sys::AZIOT_KEYS_KEY_DERIVATION_HKDF_PARAMETERS).cast(),
+            )
+        }
+
+        aziot_key_common::KeyDerivationMechanism::KbkdfCounter {
+            digest,
+            context,
+            len,
+        } => {
+            let parameters = sys::AZIOT_KEYS_KEY_DERIVATION_KBKDF_PARAMETERS {
+                digest_algorithm: digest_algorithm_to_sys(*digest),
+                context: context.as_ptr(),
+                context_len: context.len(),
+                output_len: *len,
+            };
+
+            f(
+                sys::AZIOT_KEYS_KEY_DERIVATION_MECHANISM_KBKDF_COUNTER,
+                (&parameters as *const sys::AZIOT_KEYS_KEY_DERIVATION_KBKDF_PARAMETERS).cast(),
+            )
+        }
+    }
+}
+
+fn key_usage_to_sys(usage: &[aziot_key_common::KeyUsage]) -> sys::AZIOT_KEYS_KEY_USAGE {
+    let mut usage_raw = 0;
+    for &usage in usage {
+        match usage {
+            aziot_key_common::KeyUsage::Derive => {
+                usage_raw |= sys::AZIOT_KEYS_KEY_USAGE_DERIVE;
+            }
+            aziot_key_common::KeyUsage::Encrypt => {
+                usage_raw |= sys::AZIOT_KEYS_KEY_USAGE_ENCRYPT;
+            }
+            aziot_key_common::KeyUsage::Sign => {
+                usage_raw |= sys::AZIOT_KEYS_KEY_USAGE_SIGN;
+            }
+        }
+    }
+    usage_raw
+}
+
+fn wrap_mechanism_to_sys(
+    mechanism: aziot_key_common::WrapMechanism,
+) -> sys::AZIOT_KEYS_WRAP_MECHANISM {
+    match mechanism {
+        aziot_key_common::WrapMechanism::RsaOaep => sys::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP,
+        aziot_key_common::WrapMechanism::Ecdh => sys::AZIOT_KEYS_WRAP_MECHANISM_ECDH,
+    }
+}
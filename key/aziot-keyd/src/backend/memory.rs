@@ -0,0 +1,954 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use crate::keys::{
+    CreateKeyIfNotExistsError, CreateKeyPairIfNotExistsError, DecryptError, DeleteKeyError,
+    DeleteKeyPairError, DeriveKeyError, DeriveSharedSecretError, EncryptError, EnumerateKeysError,
+    GetKeyPairPublicParameterError, GetKeyParameterError, ImportKeyError, KeysRawError,
+    LoadKeyError, LoadKeyPairError, MoveKeyPairError, SetLibraryParameterError, SignError,
+    VerifyError,
+};
+
+use super::{Derivation, KeyBackend};
+
+/// An in-process backend that keeps all keys in memory.
+///
+/// Keys are lost when the service stops, so this is only suitable for tests. It supports:
+///
+/// - NIST P-256 key pairs, with ECDSA signatures and ECDH key agreement.
+/// - Keys with HMAC-SHA256/384/512 signatures and AES-256-GCM encryption.
+/// - Keys derived from other keys with HMAC-SHA256.
+#[derive(Default)]
+pub(crate) struct Memory {
+    inner: std::sync::Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    keys: std::collections::BTreeMap<String, Key>,
+    key_pairs: std::collections::BTreeMap<String, openssl::ec::EcKey<openssl::pkey::Private>>,
+}
+
+struct Key {
+    bytes: Vec<u8>,
+
+    /// Whether the key can be used for signing and key derivation. Like libaziot-keys, these two usages are not distinguished.
+    sign: bool,
+
+    encrypt: bool,
+
+    imported: bool,
+
+    /// Unix timestamp (in seconds) of when the key was created.
+    creation_time: u64,
+}
+
+impl Key {
+    fn new(bytes: Vec<u8>, usage: &[aziot_key_common::KeyUsage], imported: bool) -> Self {
+        Key {
+            bytes,
+            sign: usage.iter().any(|usage| {
+                matches!(
+                    usage,
+                    aziot_key_common::KeyUsage::Derive | aziot_key_common::KeyUsage::Sign
+                )
+            }),
+            encrypt: usage
+                .iter()
+                .any(|usage| matches!(usage, aziot_key_common::KeyUsage::Encrypt)),
+            imported,
+            // The memory backend is only used by tests, so a clock before the Unix epoch is reported as 0 instead of failing.
+            creation_time: crate::unix_time_now().unwrap_or(0),
+        }
+    }
+}
+
+impl Memory {
+    fn inner(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().expect("memory backend lock poisoned")
+    }
+
+    /// Returns the bytes of the given key, or of the key derived from it if `derivation` is set.
+    ///
+    /// `usable` checks that the key may be used for the operation. Derivation instead requires the base key to have the sign usage.
+    fn key_bytes(
+        &self,
+        id: &str,
+        derivation: Option<&Derivation<'_>>,
+        usable: impl FnOnce(&Key) -> bool,
+    ) -> Result<Vec<u8>, KeysRawError> {
+        let inner = self.inner();
+        let key = inner.keys.get(id).ok_or(KeysRawError::INVALID_PARAMETER)?;
+
+        if let Some(derivation) = derivation {
+            if !key.sign {
+                return Err(KeysRawError::INVALID_PARAMETER);
+            }
+
+            return derive(&key.bytes, derivation);
+        }
+
+        if !usable(key) {
+            return Err(KeysRawError::INVALID_PARAMETER);
+        }
+
+        Ok(key.bytes.clone())
+    }
+}
+
+impl KeyBackend for Memory {
+    fn is_thread_safe(&self) -> bool {
+        true
+    }
+
+    fn set_parameter(&mut self, name: &str, _value: &str) -> Result<(), SetLibraryParameterError> {
+        log::warn!(
+            "The in-memory key backend does not use any parameters. Ignoring {}.",
+            name
+        );
+        Ok(())
+    }
+
+    fn create_key_pair_if_not_exists(
+        &self,
+        id: &str,
+        preferred_algorithms: Option<&str>,
+    ) -> Result<(), CreateKeyPairIfNotExistsError> {
+        let mut inner = self.inner();
+        if inner.key_pairs.contains_key(id) {
+            return Ok(());
+        }
+
+        // Like libaziot-keys, fall back to the default if no algorithm is specified, and skip algorithms that are not supported.
+        let supported = preferred_algorithms.map_or(true, |preferred_algorithms| {
+            preferred_algorithms
+                .split(':')
+                .any(|algorithm| algorithm == "ec-p256" || algorithm == "*")
+        });
+        if !supported {
+            return Err(CreateKeyPairIfNotExistsError {
+                err: KeysRawError::INVALID_PARAMETER,
+            });
+        }
+
+        let key_pair = p256()
+            .and_then(|group| openssl::ec::EcKey::generate(&group))
+            .map_err(|_| CreateKeyPairIfNotExistsError {
+                err: KeysRawError::EXTERNAL,
+            })?;
+        inner.key_pairs.insert(id.to_owned(), key_pair);
+
+        Ok(())
+    }
+
+    fn load_key_pair(&self, id: &str) -> Result<(), LoadKeyPairError> {
+        if self.inner().key_pairs.contains_key(id) {
+            Ok(())
+        } else {
+            Err(LoadKeyPairError {
+                err: KeysRawError::INVALID_PARAMETER,
+            })
+        }
+    }
+
+    fn get_key_pair_public_parameter(
+        &self,
+        id: &str,
+        parameter_name: &str,
+    ) -> Result<String, GetKeyPairPublicParameterError> {
+        let inner = self.inner();
+        let key_pair = inner
+            .key_pairs
+            .get(id)
+            .ok_or(GetKeyPairPublicParameterError::Api {
+                err: KeysRawError::INVALID_PARAMETER,
+            })?;
+
+        match parameter_name {
+            "algorithm" => Ok("ECDSA".to_owned()),
+
+            "ec-curve-oid" => Ok(base64::encode(P256_OID_DER)),
+
+            "ec-point" => {
+                let mut big_num_context = openssl::bn::BigNumContext::new().map_err(|_| {
+                    GetKeyPairPublicParameterError::Api {
+                        err: KeysRawError::EXTERNAL,
+                    }
+                })?;
+                let point = key_pair
+                    .public_key()
+                    .to_bytes(
+                        key_pair.group(),
+                        openssl::ec::PointConversionForm::COMPRESSED,
+                        &mut big_num_context,
+                    )
+                    .map_err(|_| GetKeyPairPublicParameterError::Api {
+                        err: KeysRawError::EXTERNAL,
+                    })?;
+                Ok(base64::encode(&point))
+            }
+
+            _ => Err(GetKeyPairPublicParameterError::Api {
+                err: KeysRawError::INVALID_PARAMETER,
+            }),
+        }
+    }
+
+    fn create_key_if_not_exists(
+        &self,
+        id: &str,
+        usage: &[aziot_key_common::KeyUsage],
+    ) -> Result<(), CreateKeyIfNotExistsError> {
+        let mut inner = self.inner();
+        if inner.keys.contains_key(id) {
+            return Ok(());
+        }
+
+        let mut bytes = vec![0_u8; AES_256_KEY_LEN];
+        openssl::rand::rand_bytes(&mut bytes).map_err(|_| CreateKeyIfNotExistsError {
+            err: KeysRawError::EXTERNAL,
+        })?;
+        inner
+            .keys
+            .insert(id.to_owned(), Key::new(bytes, usage, false));
+
+        Ok(())
+    }
+
+    fn load_key(&self, id: &str) -> Result<(), LoadKeyError> {
+        if self.inner().keys.contains_key(id) {
+            Ok(())
+        } else {
+            Err(LoadKeyError {
+                err: KeysRawError::INVALID_PARAMETER,
+            })
+        }
+    }
+
+    fn import_key(
+        &self,
+        id: &str,
+        bytes: &[u8],
+        usage: &[aziot_key_common::KeyUsage],
+    ) -> Result<(), ImportKeyError> {
+        self.inner()
+            .keys
+            .insert(id.to_owned(), Key::new(bytes.to_owned(), usage, true));
+        Ok(())
+    }
+
+    fn sign(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::SignMechanism,
+        derivation: Option<&Derivation<'_>>,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, SignError> {
+        if let (aziot_key_common::SignMechanism::Ecdsa, None) = (mechanism, derivation) {
+            let inner = self.inner();
+            let key_pair = inner.key_pairs.get(id).ok_or(SignError {
+                err: KeysRawError::INVALID_PARAMETER,
+            })?;
+
+            let signature = openssl::ecdsa::EcdsaSig::sign(digest, key_pair)
+                .and_then(|signature| signature.to_der())
+                .map_err(|_| SignError {
+                    err: KeysRawError::EXTERNAL,
+                })?;
+            return Ok(signature);
+        }
+
+        let message_digest = hmac_digest(mechanism).ok_or(SignError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })?;
+        let key = self
+            .key_bytes(id, derivation, |key| key.sign)
+            .map_err(|err| SignError { err })?;
+        hmac(message_digest, &key, digest).map_err(|err| SignError { err })
+    }
+
+    fn verify(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::SignMechanism,
+        derivation: Option<&Derivation<'_>>,
+        digest: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, VerifyError> {
+        if let (aziot_key_common::SignMechanism::Ecdsa, None) = (mechanism, derivation) {
+            let inner = self.inner();
+            let key_pair = inner.key_pairs.get(id).ok_or(VerifyError {
+                err: KeysRawError::INVALID_PARAMETER,
+            })?;
+
+            let ok = openssl::ecdsa::EcdsaSig::from_der(signature)
+                .and_then(|signature| signature.verify(digest, key_pair))
+                .unwrap_or(false);
+            return Ok(ok);
+        }
+
+        let message_digest = hmac_digest(mechanism).ok_or(VerifyError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })?;
+        let key = self
+            .key_bytes(id, derivation, |key| key.sign)
+            .map_err(|err| VerifyError { err })?;
+        let expected = hmac(message_digest, &key, digest).map_err(|err| VerifyError { err })?;
+
+        let ok = expected.len() == signature.len() && openssl::memcmp::eq(&expected, signature);
+        Ok(ok)
+    }
+
+    fn encrypt(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::EncryptMechanism,
+        derivation: Option<&Derivation<'_>>,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, EncryptError> {
+        let (iv, aad) = aead_parameters(mechanism).map_err(|err| EncryptError { err })?;
+        let key = self
+            .key_bytes(id, derivation, |key| key.encrypt)
+            .map_err(|err| EncryptError { err })?;
+        if key.len() != AES_256_KEY_LEN {
+            return Err(EncryptError {
+                err: KeysRawError::INVALID_PARAMETER,
+            });
+        }
+
+        // The ciphertext is followed by the 16-byte tag.
+        let mut tag = [0_u8; 16];
+        let mut ciphertext = openssl::symm::encrypt_aead(
+            openssl::symm::Cipher::aes_256_gcm(),
+            &key,
+            Some(iv),
+            aad,
+            plaintext,
+            &mut tag,
+        )
+        .map_err(|_| EncryptError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })?;
+        ciphertext.extend_from_slice(&tag);
+
+        Ok(ciphertext)
+    }
+
+    fn decrypt(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::EncryptMechanism,
+        derivation: Option<&Derivation<'_>>,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, DecryptError> {
+        let (iv, aad) = aead_parameters(mechanism).map_err(|err| DecryptError { err })?;
+        let key = self
+            .key_bytes(id, derivation, |key| key.encrypt)
+            .map_err(|err| DecryptError { err })?;
+        if key.len() != AES_256_KEY_LEN {
+            return Err(DecryptError {
+                err: KeysRawError::INVALID_PARAMETER,
+            });
+        }
+
+        if ciphertext.len() < 16 {
+            return Err(DecryptError {
+                err: KeysRawError::INVALID_PARAMETER,
+            });
+        }
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - 16);
+
+        // Failing to authenticate the ciphertext is the caller's error.
+        let plaintext = openssl::symm::decrypt_aead(
+            openssl::symm::Cipher::aes_256_gcm(),
+            &key,
+            Some(iv),
+            aad,
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| DecryptError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })?;
+
+        Ok(plaintext)
+    }
+
+    fn delete_key_pair(&self, id: &str) -> Result<(), DeleteKeyPairError> {
+        self.inner().key_pairs.remove(id);
+        Ok(())
+    }
+
+    fn move_key_pair(&self, from_id: &str, to_id: &str) -> Result<(), MoveKeyPairError> {
+        let mut inner = self.inner();
+        let key_pair = inner.key_pairs.remove(from_id).ok_or(MoveKeyPairError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })?;
+        inner.key_pairs.insert(to_id.to_owned(), key_pair);
+        Ok(())
+    }
+
+    fn get_key_parameter(
+        &self,
+        id: &str,
+        parameter_name: &str,
+    ) -> Result<String, GetKeyParameterError> {
+        let inner = self.inner();
+        let key = inner.keys.get(id).ok_or(GetKeyParameterError::Api {
+            err: KeysRawError::INVALID_PARAMETER,
+        })?;
+
+        match parameter_name {
+            "usage" => {
+                let mut usage = vec![];
+                if key.sign {
+                    usage.push("derive");
+                    usage.push("sign");
+                }
+                if key.encrypt {
+                    usage.push("encrypt");
+                }
+                Ok(usage.join(","))
+            }
+
+            "origin" => Ok(if key.imported {
+                "imported"
+            } else {
+                "generated"
+            }
+            .to_owned()),
+
+            "creation-time" => Ok(key.creation_time.to_string()),
+
+            _ => Err(GetKeyParameterError::Api {
+                err: KeysRawError::INVALID_PARAMETER,
+            }),
+        }
+    }
+
+    fn delete_key(&self, id: &str) -> Result<(), DeleteKeyError> {
+        self.inner().keys.remove(id);
+        Ok(())
+    }
+
+    fn enumerate_keys(&self) -> Result<Vec<aziot_key_common::KeyInfo>, EnumerateKeysError> {
+        let inner = self.inner();
+
+        let key_pairs = inner.key_pairs.keys().map(|id| aziot_key_common::KeyInfo {
+            id: id.clone(),
+            kind: aziot_key_common::KeyKind::KeyPair,
+            algorithm: Some("ECDSA".to_owned()),
+            usage: vec![],
+            location: format!("memory:{}", id),
+            preloaded: false,
+        });
+
+        let keys = inner.keys.iter().map(|(id, key)| {
+            let mut usage = vec![];
+            if key.sign {
+                usage.push(aziot_key_common::KeyUsage::Derive);
+                usage.push(aziot_key_common::KeyUsage::Sign);
+            }
+            if key.encrypt {
+                usage.push(aziot_key_common::KeyUsage::Encrypt);
+            }
+
+            aziot_key_common::KeyInfo {
+                id: id.clone(),
+                kind: aziot_key_common::KeyKind::Key,
+                algorithm: Some(if key.encrypt { "AES" } else { "HMAC-SHA256" }.to_owned()),
+                usage,
+                location: format!("memory:{}", id),
+                preloaded: false,
+            }
+        });
+
+        Ok(key_pairs.chain(keys).collect())
+    }
+
+    fn derive_key(&self, id: &str, derivation: &Derivation<'_>) -> Result<Vec<u8>, DeriveKeyError> {
+        self.key_bytes(id, Some(derivation), |_| true)
+            .map_err(|err| DeriveKeyError { err })
+    }
+
+    fn derive_shared_secret(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::KeyAgreementMechanism,
+        peer_public_key: &[u8],
+    ) -> Result<Vec<u8>, DeriveSharedSecretError> {
+        let invalid_parameter = |_| DeriveSharedSecretError {
+            err: KeysRawError::INVALID_PARAMETER,
+        };
+        let external = |_| DeriveSharedSecretError {
+            err: KeysRawError::EXTERNAL,
+        };
+
+        if !matches!(mechanism, aziot_key_common::KeyAgreementMechanism::Ecdh) {
+            return Err(DeriveSharedSecretError {
+                err: KeysRawError::INVALID_PARAMETER,
+            });
+        }
+
+        let inner = self.inner();
+        let key_pair = inner.key_pairs.get(id).ok_or(DeriveSharedSecretError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })?;
+        let private_key = openssl::pkey::PKey::from_ec_key(key_pair.clone()).map_err(external)?;
+
+        // Fails if the peer public key is not an EC key on the same curve as the key pair.
+        let peer_public_key =
+            openssl::pkey::PKey::public_key_from_der(peer_public_key).map_err(invalid_parameter)?;
+        let mut deriver = openssl::derive::Deriver::new(&private_key).map_err(external)?;
+        deriver
+            .set_peer(&peer_public_key)
+            .map_err(invalid_parameter)?;
+        deriver.derive_to_vec().map_err(external)
+    }
+
+    fn import_key_if_not_exists(
+        &self,
+        id: &str,
+        bytes: &[u8],
+        usage: &[aziot_key_common::KeyUsage],
+    ) -> Result<(), ImportKeyError> {
+        match self.inner().keys.entry(id.to_owned()) {
+            std::collections::btree_map::Entry::Occupied(_) => Err(ImportKeyError {
+                err: KeysRawError::INVALID_PARAMETER,
+            }),
+
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                entry.insert(Key::new(bytes.to_owned(), usage, true));
+                Ok(())
+            }
+        }
+    }
+}
+
+const AES_256_KEY_LEN: usize = 32;
+
+/// The DER encoding of the OID of the NIST P-256 curve.
+const P256_OID_DER: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+
+fn p256() -> Result<openssl::ec::EcGroup, openssl::error::ErrorStack> {
+    openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1)
+}
+
+fn hmac_digest(
+    mechanism: &aziot_key_common::SignMechanism,
+) -> Option<openssl::hash::MessageDigest> {
+    match mechanism {
+        aziot_key_common::SignMechanism::HmacSha256 => Some(openssl::hash::MessageDigest::sha256()),
+        aziot_key_common::SignMechanism::HmacSha384 => Some(openssl::hash::MessageDigest::sha384()),
+        aziot_key_common::SignMechanism::HmacSha512 => Some(openssl::hash::MessageDigest::sha512()),
+        _ => None,
+    }
+}
+
+fn hmac(
+    message_digest: openssl::hash::MessageDigest,
+    key: &[u8],
+    data: &[u8],
+) -> Result<Vec<u8>, KeysRawError> {
+    let key = openssl::pkey::PKey::hmac(key).map_err(|_| KeysRawError::EXTERNAL)?;
+    let mut signer =
+        openssl::sign::Signer::new(message_digest, &key).map_err(|_| KeysRawError::EXTERNAL)?;
+    signer
+        .sign_oneshot_to_vec(data)
+        .map_err(|_| KeysRawError::EXTERNAL)
+}
+
+/// Only HMAC-SHA256 derivation is supported.
+fn derive(base_key: &[u8], derivation: &Derivation<'_>) -> Result<Vec<u8>, KeysRawError> {
+    match derivation.mechanism {
+        aziot_key_common::KeyDerivationMechanism::HmacSha256 => hmac(
+            openssl::hash::MessageDigest::sha256(),
+            base_key,
+            derivation.data,
+        ),
+
+        _ => Err(KeysRawError::INVALID_PARAMETER),
+    }
+}
+
+/// Only AEAD encryption is supported.
+fn aead_parameters(
+    mechanism: &aziot_key_common::EncryptMechanism,
+) -> Result<(&[u8], &[u8]), KeysRawError> {
+    match mechanism {
+        aziot_key_common::EncryptMechanism::Aead { iv, aad } => Ok((iv, aad)),
+        _ => Err(KeysRawError::INVALID_PARAMETER),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Derivation, KeyBackend, Memory};
+    use crate::keys::sys;
+
+    fn aead() -> aziot_key_common::EncryptMechanism {
+        aziot_key_common::EncryptMechanism::Aead {
+            iv: vec![0x01; 12],
+            aad: b"aad".to_vec(),
+        }
+    }
+
+    #[test]
+    fn create() {
+        let keys = Memory::default();
+        let keys: &dyn KeyBackend = &keys;
+
+        keys.create_key_if_not_exists("key", &[aziot_key_common::KeyUsage::Sign])
+            .unwrap();
+        keys.load_key("key").unwrap();
+        let signature = keys
+            .sign(
+                "key",
+                &aziot_key_common::SignMechanism::HmacSha256,
+                None,
+                b"data",
+            )
+            .unwrap();
+
+        // Creating a key that already exists keeps the existing key.
+        keys.create_key_if_not_exists("key", &[aziot_key_common::KeyUsage::Sign])
+            .unwrap();
+        assert_eq!(
+            keys.sign(
+                "key",
+                &aziot_key_common::SignMechanism::HmacSha256,
+                None,
+                b"data",
+            )
+            .unwrap(),
+            signature,
+        );
+        assert_eq!(
+            keys.get_key_parameter("key", "origin").unwrap(),
+            "generated"
+        );
+        assert_eq!(
+            keys.get_key_parameter("key", "usage").unwrap(),
+            "derive,sign",
+        );
+
+        keys.create_key_pair_if_not_exists("key-pair", Some("rsa-2048:ec-p256"))
+            .unwrap();
+        keys.load_key_pair("key-pair").unwrap();
+        assert_eq!(
+            keys.get_key_pair_public_parameter("key-pair", "algorithm")
+                .unwrap(),
+            "ECDSA",
+        );
+        assert_eq!(
+            keys.create_key_pair_if_not_exists("rsa-key-pair", Some("rsa-2048"))
+                .unwrap_err()
+                .err
+                .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        let mut ids: Vec<_> = keys
+            .enumerate_keys()
+            .unwrap()
+            .into_iter()
+            .map(|key_info| key_info.id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, ["key", "key-pair"]);
+
+        keys.move_key_pair("key-pair", "moved-key-pair").unwrap();
+        assert_eq!(
+            keys.load_key_pair("key-pair").unwrap_err().err.0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        keys.load_key_pair("moved-key-pair").unwrap();
+        assert_eq!(
+            keys.move_key_pair("key-pair", "moved-key-pair")
+                .unwrap_err()
+                .err
+                .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        keys.delete_key("key").unwrap();
+        keys.delete_key_pair("moved-key-pair").unwrap();
+        assert_eq!(
+            keys.load_key("key").unwrap_err().err.0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            keys.load_key_pair("moved-key-pair").unwrap_err().err.0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn sign_verify() {
+        let keys = Memory::default();
+        let keys: &dyn KeyBackend = &keys;
+
+        keys.import_key("key", b"Jefe", &[aziot_key_common::KeyUsage::Sign])
+            .unwrap();
+
+        // RFC 4231 test case 2
+        let signature = keys
+            .sign(
+                "key",
+                &aziot_key_common::SignMechanism::HmacSha256,
+                None,
+                b"what do ya want for nothing?",
+            )
+            .unwrap();
+        assert_eq!(
+            signature
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+        );
+        assert!(keys
+            .verify(
+                "key",
+                &aziot_key_common::SignMechanism::HmacSha256,
+                None,
+                b"what do ya want for nothing?",
+                &signature,
+            )
+            .unwrap());
+        assert!(!keys
+            .verify(
+                "key",
+                &aziot_key_common::SignMechanism::HmacSha256,
+                None,
+                b"what do ya want for something?",
+                &signature,
+            )
+            .unwrap());
+
+        // A derived key signs with HMAC-SHA256(base key, derivation data).
+        let derivation = Derivation {
+            data: b"derivation data",
+            mechanism: &aziot_key_common::KeyDerivationMechanism::HmacSha256,
+        };
+        let derived_key = keys.derive_key("key", &derivation).unwrap();
+        let derived_signature = keys
+            .sign(
+                "key",
+                &aziot_key_common::SignMechanism::HmacSha256,
+                Some(&derivation),
+                b"data",
+            )
+            .unwrap();
+        assert_eq!(
+            derived_signature,
+            super::hmac(
+                openssl::hash::MessageDigest::sha256(),
+                &derived_key,
+                b"data"
+            )
+            .unwrap(),
+        );
+
+        keys.create_key_pair_if_not_exists("key-pair", None)
+            .unwrap();
+        let digest = openssl::sha::sha256(b"data");
+        let signature = keys
+            .sign(
+                "key-pair",
+                &aziot_key_common::SignMechanism::Ecdsa,
+                None,
+                &digest,
+            )
+            .unwrap();
+        assert!(keys
+            .verify(
+                "key-pair",
+                &aziot_key_common::SignMechanism::Ecdsa,
+                None,
+                &digest,
+                &signature,
+            )
+            .unwrap());
+        assert!(!keys
+            .verify(
+                "key-pair",
+                &aziot_key_common::SignMechanism::Ecdsa,
+                None,
+                &openssl::sha::sha256(b"other data"),
+                &signature,
+            )
+            .unwrap());
+    }
+
+    #[test]
+    fn encrypt_decrypt() {
+        let keys = Memory::default();
+        let keys: &dyn KeyBackend = &keys;
+
+        keys.create_key_if_not_exists("key", &[aziot_key_common::KeyUsage::Encrypt])
+            .unwrap();
+
+        let ciphertext = keys.encrypt("key", &aead(), None, b"plaintext").unwrap();
+        assert_eq!(ciphertext.len(), b"plaintext".len() + 16);
+        assert_eq!(
+            keys.decrypt("key", &aead(), None, &ciphertext).unwrap(),
+            b"plaintext",
+        );
+
+        // Tampered ciphertext can't be authenticated.
+        let mut tampered = ciphertext.clone();
+        tampered[0] ^= 0x01;
+        assert_eq!(
+            keys.decrypt("key", &aead(), None, &tampered)
+                .unwrap_err()
+                .err
+                .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            keys.decrypt("key", &aead(), None, &ciphertext[..15])
+                .unwrap_err()
+                .err
+                .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        // Only AEAD is supported.
+        assert_eq!(
+            keys.encrypt(
+                "key",
+                &aziot_key_common::EncryptMechanism::AesCbcPad { iv: vec![0; 16] },
+                None,
+                b"plaintext",
+            )
+            .unwrap_err()
+            .err
+            .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        // Encryption with a derived key requires the base key to have the derive usage.
+        keys.create_key_if_not_exists("derive-key", &[aziot_key_common::KeyUsage::Derive])
+            .unwrap();
+        let derivation = Derivation {
+            data: b"derivation data",
+            mechanism: &aziot_key_common::KeyDerivationMechanism::HmacSha256,
+        };
+        let ciphertext = keys
+            .encrypt("derive-key", &aead(), Some(&derivation), b"plaintext")
+            .unwrap();
+        assert_eq!(
+            keys.decrypt("derive-key", &aead(), Some(&derivation), &ciphertext)
+                .unwrap(),
+            b"plaintext",
+        );
+        assert_eq!(
+            keys.encrypt("key", &aead(), Some(&derivation), b"plaintext")
+                .unwrap_err()
+                .err
+                .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn usage_rejected() {
+        let keys = Memory::default();
+        let keys: &dyn KeyBackend = &keys;
+
+        keys.create_key_if_not_exists("sign-key", &[aziot_key_common::KeyUsage::Sign])
+            .unwrap();
+        keys.create_key_if_not_exists("encrypt-key", &[aziot_key_common::KeyUsage::Encrypt])
+            .unwrap();
+
+        assert_eq!(
+            keys.encrypt("sign-key", &aead(), None, b"plaintext")
+                .unwrap_err()
+                .err
+                .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            keys.decrypt("sign-key", &aead(), None, &[0; 32])
+                .unwrap_err()
+                .err
+                .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            keys.sign(
+                "encrypt-key",
+                &aziot_key_common::SignMechanism::HmacSha256,
+                None,
+                b"data",
+            )
+            .unwrap_err()
+            .err
+            .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            keys.verify(
+                "encrypt-key",
+                &aziot_key_common::SignMechanism::HmacSha256,
+                None,
+                b"data",
+                &[0; 32],
+            )
+            .unwrap_err()
+            .err
+            .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        // Derivation requires the base key to have the sign or derive usage.
+        let derivation = Derivation {
+            data: b"derivation data",
+            mechanism: &aziot_key_common::KeyDerivationMechanism::HmacSha256,
+        };
+        assert_eq!(
+            keys.derive_key("encrypt-key", &derivation)
+                .unwrap_err()
+                .err
+                .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        // Only HMAC-SHA256 derivation is supported.
+        let derivation = Derivation {
+            data: b"derivation data",
+            mechanism: &aziot_key_common::KeyDerivationMechanism::Hkdf {
+                digest: aziot_key_common::DigestAlgorithm::Sha256,
+                salt: vec![],
+                len: 32,
+            },
+        };
+        assert_eq!(
+            keys.derive_key("sign-key", &derivation).unwrap_err().err.0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        // Key pairs only support ECDSA.
+        keys.create_key_pair_if_not_exists("key-pair", None)
+            .unwrap();
+        assert_eq!(
+            keys.sign(
+                "key-pair",
+                &aziot_key_common::SignMechanism::HmacSha256,
+                None,
+                b"data",
+            )
+            .unwrap_err()
+            .err
+            .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            keys.encrypt("key-pair", &aead(), None, b"plaintext")
+                .unwrap_err()
+                .err
+                .0,
+            sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+}
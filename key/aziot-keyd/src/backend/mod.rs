@@ -0,0 +1,300 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The backends that store keys and perform operations with them on behalf of [`crate::Api`].
+//!
+//! [`KeyBackend`] mirrors the libaziot-keys function list. [`Library`] implements it by calling into
+//! libaziot-keys, and [`Memory`] implements it in-process without any FFI.
+//!
+//! Only the backends in this module are supported, and they're selected with [`aziot_keyd_config::Backend`].
+//! The trait is not part of this crate's public API, so a custom backend still has to be provided as a replacement
+//! libaziot-keys for the [`Library`] backend to load. A new in-process backend is added here along with a new variant
+//! of [`aziot_keyd_config::Backend`].
+
+mod library;
+pub(crate) use library::Library;
+
+mod memory;
+pub(crate) use memory::Memory;
+
+use crate::keys::{
+    CreateKeyIfNotExistsError, CreateKeyPairIfNotExistsError, DecryptError, DeleteKeyError,
+    DeleteKeyPairError, DeriveKeyError, DeriveSharedSecretError, EncryptError, EnumerateKeysError,
+    ExportKeyError, ExportKeyPairError, GetKeyPairPublicParameterError, GetKeyParameterError,
+    ImportKeyError, ImportKeyPairError, ImportWrappedKeyError, ImportWrappedKeyPairError,
+    KeysRawError, LoadKeyError, LoadKeyPairError, SetLibraryParameterError, SignError, StreamError,
+    VerifyError,
+};
+
+/// A backend for the key service.
+///
+/// The required methods correspond to `AZIOT_KEYS_FUNCTION_LIST_2_0_0_0`. Functions that were added in later versions
+/// of libaziot-keys are provided methods that fail with [`KeysRawError::INVALID_PARAMETER`], so a backend only needs to
+/// implement the ones it supports.
+///
+/// Errors use the same `AZIOT_KEYS_RC` codes as libaziot-keys. In particular, `INVALID_PARAMETER` is reported to
+/// the caller as a bad request, and anything else as an internal error.
+pub(crate) trait KeyBackend: Send + Sync {
+    /// Whether the backend's methods can be called concurrently from multiple threads.
+    fn is_thread_safe(&self) -> bool {
+        false
+    }
+
+    fn set_parameter(&mut self, name: &str, value: &str) -> Result<(), SetLibraryParameterError>;
+
+    fn create_key_pair_if_not_exists(
+        &self,
+        id: &str,
+        preferred_algorithms: Option<&str>,
+    ) -> Result<(), CreateKeyPairIfNotExistsError>;
+
+    fn load_key_pair(&self, id: &str) -> Result<(), LoadKeyPairError>;
+
+    /// Returns the value of the given public parameter of the key pair.
+    ///
+    /// `"algorithm"` is one of `"ECDSA"`, `"RSA"` or `"ED25519"`. All other parameters are base64-encoded byte strings.
+    fn get_key_pair_public_parameter(
+        &self,
+        id: &str,
+        parameter_name: &str,
+    ) -> Result<String, GetKeyPairPublicParameterError>;
+
+    fn create_key_if_not_exists(
+        &self,
+        id: &str,
+        usage: &[aziot_key_common::KeyUsage],
+    ) -> Result<(), CreateKeyIfNotExistsError>;
+
+    fn load_key(&self, id: &str) -> Result<(), LoadKeyError>;
+
+    fn import_key(
+        &self,
+        id: &str,
+        bytes: &[u8],
+        usage: &[aziot_key_common::KeyUsage],
+    ) -> Result<(), ImportKeyError>;
+
+    /// Signs `digest` with the given key or key pair, or with the key derived from it if `derivation` is set.
+    fn sign(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::SignMechanism,
+        derivation: Option<&Derivation<'_>>,
+        digest: &[u8],
+    ) -> Result<Vec<u8>, SignError>;
+
+    fn verify(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::SignMechanism,
+        derivation: Option<&Derivation<'_>>,
+        digest: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, VerifyError>;
+
+    fn encrypt(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::EncryptMechanism,
+        derivation: Option<&Derivation<'_>>,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, EncryptError>;
+
+    fn decrypt(
+        &self,
+        id: &str,
+        mechanism: &aziot_key_common::EncryptMechanism,
+        derivation: Option<&Derivation<'_>>,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, DecryptError>;
+
+    fn import_key_pair(
+        &self,
+        _id: &str,
+        _bytes: &[u8],
+        _format: aziot_key_common::KeyPairFormat,
+        _password: Option<&str>,
+    ) -> Result<(), ImportKeyPairError> {
+        Err(ImportKeyPairError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    fn delete_key_pair(&self, _id: &str) -> Result<(), DeleteKeyPairError> {
+        Err(DeleteKeyPairError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    /// Moves the key pair `from_id` to `to_id`, replacing any existing key pair with that ID, without the private key leaving the backend.
+    fn move_key_pair(&self, _from_id: &str, _to_id: &str) -> Result<(), MoveKeyPairError> {
+        Err(MoveKeyPairError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    /// Returns the value of the given parameter of the key, formatted as described in [`crate::Api::get_key_parameter`].
+    fn get_key_parameter(
+        &self,
+        _id: &str,
+        _parameter_name: &str,
+    ) -> Result<String, GetKeyParameterError> {
+        Err(GetKeyParameterError::Api {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    fn delete_key(&self, _id: &str) -> Result<(), DeleteKeyError> {
+        Err(DeleteKeyError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    fn enumerate_keys(&self) -> Result<Vec<aziot_key_common::KeyInfo>, EnumerateKeysError> {
+        Err(EnumerateKeysError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    /// Returns the key derived from the given key.
+    fn derive_key(
+        &self,
+        _id: &str,
+        _derivation: &Derivation<'_>,
+    ) -> Result<Vec<u8>, DeriveKeyError> {
+        Err(DeriveKeyError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    fn export_key(
+        &self,
+        _id: &str,
+        _mechanism: aziot_key_common::WrapMechanism,
+        _wrapping_key: &[u8],
+    ) -> Result<Vec<u8>, ExportKeyError> {
+        Err(ExportKeyError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    fn export_key_pair(
+        &self,
+        _id: &str,
+        _mechanism: aziot_key_common::WrapMechanism,
+        _wrapping_key: &[u8],
+    ) -> Result<Vec<u8>, ExportKeyPairError> {
+        Err(ExportKeyPairError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    fn import_wrapped_key(
+        &self,
+        _id: &str,
+        _unwrapping_key_pair_id: &str,
+        _mechanism: aziot_key_common::WrapMechanism,
+        _wrapped_key: &[u8],
+    ) -> Result<(), ImportWrappedKeyError> {
+        Err(ImportWrappedKeyError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    fn import_wrapped_key_pair(
+        &self,
+        _id: &str,
+        _unwrapping_key_pair_id: &str,
+        _mechanism: aziot_key_common::WrapMechanism,
+        _wrapped_key: &[u8],
+    ) -> Result<(), ImportWrappedKeyPairError> {
+        Err(ImportWrappedKeyPairError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    fn derive_shared_secret(
+        &self,
+        _id: &str,
+        _mechanism: &aziot_key_common::KeyAgreementMechanism,
+        _peer_public_key: &[u8],
+    ) -> Result<Vec<u8>, DeriveSharedSecretError> {
+        Err(DeriveSharedSecretError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    /// Like [`KeyBackend::import_key`], but fails with [`KeysRawError::INVALID_PARAMETER`] instead of replacing an existing key.
+    ///
+    /// Checking for the existing key and importing the new one must be atomic.
+    fn import_key_if_not_exists(
+        &self,
+        _id: &str,
+        _bytes: &[u8],
+        _usage: &[aziot_key_common::KeyUsage],
+    ) -> Result<(), ImportKeyError> {
+        Err(ImportKeyError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    fn encrypt_init(
+        &self,
+        _id: &str,
+        _mechanism: &aziot_key_common::EncryptMechanism,
+        _derivation: Option<&Derivation<'_>>,
+    ) -> Result<Box<dyn KeyStream>, StreamError> {
+        Err(StreamError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    fn decrypt_init(
+        &self,
+        _id: &str,
+        _mechanism: &aziot_key_common::EncryptMechanism,
+        _derivation: Option<&Derivation<'_>>,
+    ) -> Result<Box<dyn KeyStream>, StreamError> {
+        Err(StreamError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+
+    fn sign_init(
+        &self,
+        _id: &str,
+        _mechanism: &aziot_key_common::SignMechanism,
+        _derivation: Option<&Derivation<'_>>,
+    ) -> Result<Box<dyn KeyStream>, StreamError> {
+        Err(StreamError {
+            err: KeysRawError::INVALID_PARAMETER,
+        })
+    }
+}
+
+/// A multi-part operation started by [`KeyBackend::encrypt_init`], [`KeyBackend::decrypt_init`] or [`KeyBackend::sign_init`].
+///
+/// Dropping the stream abandons the operation if it was not completed.
+pub(crate) trait KeyStream: Send {
+    /// Passes the next part of the input to the stream, and returns the output produced so far.
+    fn update(&mut self, data: &[u8]) -> Result<Vec<u8>, StreamError>;
+
+    /// Completes the stream's operation, and returns the remaining output.
+    fn finish(&mut self) -> Result<Vec<u8>, StreamError>;
+}
+
+/// Identifies a key derived from a base key. Operations that take one use the derived key instead of the base key.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Derivation<'a> {
+    pub(crate) data: &'a [u8],
+    pub(crate) mechanism: &'a aziot_key_common::KeyDerivationMechanism,
+}
+
+/// Creates the backend selected in the config.
+pub(crate) fn new(
+    backend: aziot_keyd_config::Backend,
+) -> Result<Box<dyn KeyBackend>, crate::keys::LoadLibraryError> {
+    match backend {
+        aziot_keyd_config::Backend::Library => Ok(Box::new(Library::new()?)),
+        aziot_keyd_config::Backend::Memory => Ok(Box::new(Memory::default())),
+    }
+}
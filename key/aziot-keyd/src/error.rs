@@ -53,18 +53,34 @@ pub enum InternalError {
     CreateKeyIfNotExistsGenerate(crate::keys::CreateKeyIfNotExistsError),
     CreateKeyIfNotExistsImport(crate::keys::ImportKeyError),
     CreateKeyPairIfNotExists(crate::keys::CreateKeyPairIfNotExistsError),
+    CreateKeyPairIfNotExistsImport(crate::keys::ImportKeyPairError),
+    GetKeyParameter(crate::keys::GetKeyParameterError),
     GetKeyPairPublicParameter(crate::keys::GetKeyPairPublicParameterError),
     Decrypt(crate::keys::DecryptError),
+    DeleteKey(crate::keys::DeleteKeyError),
+    DeleteKeyPair(crate::keys::DeleteKeyPairError),
     DeriveKey(crate::keys::DeriveKeyError),
+    DeriveSharedSecret(crate::keys::DeriveSharedSecretError),
     Encrypt(crate::keys::EncryptError),
+    EnumerateKeys(crate::keys::EnumerateKeysError),
+    ExportKey(crate::keys::ExportKeyError),
+    ExportKeyPair(crate::keys::ExportKeyPairError),
     GenerateNonce(openssl::error::ErrorStack),
+    GenerateStreamId(openssl::error::ErrorStack),
+    ImportWrappedKey(crate::keys::ImportWrappedKeyError),
+    ImportWrappedKeyPair(crate::keys::ImportWrappedKeyPairError),
     LoadKey(crate::keys::LoadKeyError),
     LoadKeyPair(crate::keys::LoadKeyPairError),
     LoadLibrary(crate::keys::LoadLibraryError),
+    MoveKeyPair(crate::keys::MoveKeyPairError),
     ReadConfig(Box<dyn std::error::Error + Send + Sync>),
+    RotateHandleValidationKey,
     SetLibraryParameter(crate::keys::SetLibraryParameterError),
     Sign(crate::keys::SignError),
+    Stream(crate::keys::StreamError),
+    SystemTime(std::time::SystemTimeError),
     Verify(crate::keys::VerifyError),
+    Worker(tokio::task::JoinError),
 }
 
 impl std::fmt::Display for InternalError {
@@ -73,44 +89,83 @@ impl std::fmt::Display for InternalError {
             InternalError::CreateKeyIfNotExistsGenerate(_) => f.write_str("could not generate key"),
             InternalError::CreateKeyIfNotExistsImport(_) => f.write_str("could not import key"),
             InternalError::CreateKeyPairIfNotExists(_) => f.write_str("could not create key pair"),
+            InternalError::CreateKeyPairIfNotExistsImport(_) => {
+                f.write_str("could not import key pair")
+            }
             InternalError::Decrypt(_) => f.write_str("could not decrypt"),
+            InternalError::DeleteKey(_) => f.write_str("could not delete key"),
+            InternalError::DeleteKeyPair(_) => f.write_str("could not delete key pair"),
             InternalError::DeriveKey(_) => f.write_str("could not derive key"),
+            InternalError::DeriveSharedSecret(_) => f.write_str("could not derive shared secret"),
             InternalError::Encrypt(_) => f.write_str("could not encrypt"),
+            InternalError::EnumerateKeys(_) => f.write_str("could not enumerate keys"),
+            InternalError::ExportKey(_) => f.write_str("could not export key"),
+            InternalError::ExportKeyPair(_) => f.write_str("could not export key pair"),
+            InternalError::GetKeyParameter(_) => f.write_str("could not get key parameter"),
             InternalError::GetKeyPairPublicParameter(_) => {
                 f.write_str("could not get key pair parameter")
             }
             InternalError::GenerateNonce(_) => f.write_str("could not generate nonce"),
+            InternalError::GenerateStreamId(_) => f.write_str("could not generate stream ID"),
+            InternalError::ImportWrappedKey(_) => f.write_str("could not import wrapped key"),
+            InternalError::ImportWrappedKeyPair(_) => {
+                f.write_str("could not import wrapped key pair")
+            }
             InternalError::LoadKey(_) => f.write_str("could not load key"),
             InternalError::LoadKeyPair(_) => f.write_str("could not load key pair"),
             InternalError::LoadLibrary(_) => f.write_str("could not load libaziot-keys"),
+            InternalError::MoveKeyPair(_) => f.write_str("could not move key pair"),
             InternalError::ReadConfig(_) => f.write_str("could not read config"),
+            InternalError::RotateHandleValidationKey => {
+                f.write_str("could not rotate handle validation key")
+            }
             InternalError::SetLibraryParameter(_) => {
                 f.write_str("could not set parameter on libaziot-keys")
             }
             InternalError::Sign(_) => f.write_str("could not sign"),
+            InternalError::Stream(_) => f.write_str("could not process stream"),
+            InternalError::SystemTime(_) => f.write_str("could not get the system time"),
             InternalError::Verify(_) => f.write_str("could not verify"),
+            InternalError::Worker(_) => f.write_str("worker failed to run operation"),
         }
     }
 }
 
 impl std::error::Error for InternalError {
+    #[allow(clippy::match_same_arms)]
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             InternalError::CreateKeyIfNotExistsGenerate(err) => Some(err),
             InternalError::CreateKeyIfNotExistsImport(err) => Some(err),
             InternalError::CreateKeyPairIfNotExists(err) => Some(err),
+            InternalError::CreateKeyPairIfNotExistsImport(err) => Some(err),
             InternalError::Decrypt(err) => Some(err),
+            InternalError::DeleteKey(err) => Some(err),
+            InternalError::DeleteKeyPair(err) => Some(err),
             InternalError::DeriveKey(err) => Some(err),
+            InternalError::DeriveSharedSecret(err) => Some(err),
             InternalError::Encrypt(err) => Some(err),
+            InternalError::EnumerateKeys(err) => Some(err),
+            InternalError::ExportKey(err) => Some(err),
+            InternalError::ExportKeyPair(err) => Some(err),
+            InternalError::GetKeyParameter(err) => Some(err),
             InternalError::GetKeyPairPublicParameter(err) => Some(err),
             InternalError::GenerateNonce(err) => Some(err),
+            InternalError::GenerateStreamId(err) => Some(err),
+            InternalError::ImportWrappedKey(err) => Some(err),
+            InternalError::ImportWrappedKeyPair(err) => Some(err),
             InternalError::LoadKey(err) => Some(err),
             InternalError::LoadKeyPair(err) => Some(err),
             InternalError::LoadLibrary(err) => Some(err),
+            InternalError::MoveKeyPair(err) => Some(err),
             InternalError::ReadConfig(err) => Some(&**err),
+            InternalError::RotateHandleValidationKey => None,
             InternalError::SetLibraryParameter(err) => Some(err),
             InternalError::Sign(err) => Some(err),
+            InternalError::Stream(err) => Some(err),
+            InternalError::SystemTime(err) => Some(err),
             InternalError::Verify(err) => Some(err),
+            InternalError::Worker(err) => Some(err),
         }
     }
 }
@@ -136,6 +191,33 @@ impl From<crate::keys::CreateKeyPairIfNotExistsError> for Error {
     }
 }
 
+impl From<crate::keys::ImportKeyPairError> for Error {
+    fn from(err: crate::keys::ImportKeyPairError) -> Self {
+        match err.err.0 {
+            crate::keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER => Error::InvalidParameter(None),
+            _ => Error::Internal(InternalError::CreateKeyPairIfNotExistsImport(err)),
+        }
+    }
+}
+
+impl From<crate::keys::DeriveSharedSecretError> for Error {
+    fn from(err: crate::keys::DeriveSharedSecretError) -> Self {
+        match err.err.0 {
+            crate::keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER => Error::InvalidParameter(None),
+            _ => Error::Internal(InternalError::DeriveSharedSecret(err)),
+        }
+    }
+}
+
+impl From<crate::keys::StreamError> for Error {
+    fn from(err: crate::keys::StreamError) -> Self {
+        match err.err.0 {
+            crate::keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER => Error::InvalidParameter(None),
+            _ => Error::Internal(InternalError::Stream(err)),
+        }
+    }
+}
+
 impl From<crate::keys::LoadKeyPairError> for Error {
     fn from(err: crate::keys::LoadKeyPairError) -> Self {
         match err.err.0 {
@@ -145,6 +227,24 @@ impl From<crate::keys::LoadKeyPairError> for Error {
     }
 }
 
+impl From<crate::keys::DeleteKeyPairError> for Error {
+    fn from(err: crate::keys::DeleteKeyPairError) -> Self {
+        match err.err.0 {
+            crate::keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER => Error::InvalidParameter(None),
+            _ => Error::Internal(InternalError::DeleteKeyPair(err)),
+        }
+    }
+}
+
+impl From<crate::keys::MoveKeyPairError> for Error {
+    fn from(err: crate::keys::MoveKeyPairError) -> Self {
+        match err.err.0 {
+            crate::keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER => Error::InvalidParameter(None),
+            _ => Error::Internal(InternalError::MoveKeyPair(err)),
+        }
+    }
+}
+
 impl From<crate::keys::GetKeyPairPublicParameterError> for Error {
     fn from(err: crate::keys::GetKeyPairPublicParameterError) -> Self {
         match err {
@@ -158,6 +258,19 @@ impl From<crate::keys::GetKeyPairPublicParameterError> for Error {
     }
 }
 
+impl From<crate::keys::GetKeyParameterError> for Error {
+    fn from(err: crate::keys::GetKeyParameterError) -> Self {
+        match err {
+            crate::keys::GetKeyParameterError::Api {
+                err:
+                    crate::keys::KeysRawError(crate::keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER),
+            } => Error::InvalidParameter(None),
+
+            _ => Error::Internal(InternalError::GetKeyParameter(err)),
+        }
+    }
+}
+
 impl From<crate::keys::CreateKeyIfNotExistsError> for Error {
     fn from(err: crate::keys::CreateKeyIfNotExistsError) -> Self {
         match err.err.0 {
@@ -185,6 +298,15 @@ impl From<crate::keys::ImportKeyError> for Error {
     }
 }
 
+impl From<crate::keys::DeleteKeyError> for Error {
+    fn from(err: crate::keys::DeleteKeyError) -> Self {
+        match err.err.0 {
+            crate::keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER => Error::InvalidParameter(None),
+            _ => Error::Internal(InternalError::DeleteKey(err)),
+        }
+    }
+}
+
 impl From<crate::keys::DeriveKeyError> for Error {
     fn from(err: crate::keys::DeriveKeyError) -> Self {
         match err.err.0 {
@@ -229,3 +351,45 @@ impl From<crate::keys::DecryptError> for Error {
         }
     }
 }
+
+impl From<crate::keys::ExportKeyError> for Error {
+    fn from(err: crate::keys::ExportKeyError) -> Self {
+        match err.err.0 {
+            crate::keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER => Error::InvalidParameter(None),
+            _ => Error::Internal(InternalError::ExportKey(err)),
+        }
+    }
+}
+
+impl From<crate::keys::ExportKeyPairError> for Error {
+    fn from(err: crate::keys::ExportKeyPairError) -> Self {
+        match err.err.0 {
+            crate::keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER => Error::InvalidParameter(None),
+            _ => Error::Internal(InternalError::ExportKeyPair(err)),
+        }
+    }
+}
+
+impl From<crate::keys::ImportWrappedKeyError> for Error {
+    fn from(err: crate::keys::ImportWrappedKeyError) -> Self {
+        match err.err.0 {
+            crate::keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER => Error::InvalidParameter(None),
+            _ => Error::Internal(InternalError::ImportWrappedKey(err)),
+        }
+    }
+}
+
+impl From<crate::keys::ImportWrappedKeyPairError> for Error {
+    fn from(err: crate::keys::ImportWrappedKeyPairError) -> Self {
+        match err.err.0 {
+            crate::keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER => Error::InvalidParameter(None),
+            _ => Error::Internal(InternalError::ImportWrappedKeyPair(err)),
+        }
+    }
+}
+
+impl From<crate::keys::EnumerateKeysError> for Error {
+    fn from(err: crate::keys::EnumerateKeysError) -> Self {
+        Error::Internal(InternalError::EnumerateKeys(err))
+    }
+}
@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 pub(super) struct Route {
-    api: std::sync::Arc<futures_util::lock::Mutex<crate::Api>>,
+    api: std::sync::Arc<crate::Api>,
 }
 
 #[async_trait::async_trait]
@@ -43,10 +43,43 @@ impl http_common::server::Route for Route {
             message: "missing request body".into(),
         })?;
 
-        let mut api = self.api.lock().await;
-        let api = &mut *api;
+        let aziot_key_common_http::create_derived_key::Request {
+            base_handle,
+            derivation_data,
+            mechanism,
+        } = body;
 
-        let handle = match api.create_derived_key(&body.base_handle, &body.derivation_data.0) {
+        let mechanism = match mechanism {
+            None | Some(aziot_key_common_http::create_derived_key::Mechanism::HmacSha256) => {
+                aziot_key_common::KeyDerivationMechanism::HmacSha256
+            }
+
+            Some(aziot_key_common_http::create_derived_key::Mechanism::Hkdf {
+                digest_algorithm,
+                salt,
+                length,
+            }) => aziot_key_common::KeyDerivationMechanism::Hkdf {
+                digest: digest_algorithm,
+                salt: salt.0,
+                len: length,
+            },
+
+            Some(aziot_key_common_http::create_derived_key::Mechanism::KbkdfCounter {
+                digest_algorithm,
+                context,
+                length,
+            }) => aziot_key_common::KeyDerivationMechanism::KbkdfCounter {
+                digest: digest_algorithm,
+                context: context.0,
+                len: length,
+            },
+        };
+
+        let handle = match self
+            .api
+            .run(move |api| api.create_derived_key(&base_handle, &derivation_data.0, &mechanism))
+            .await
+        {
             Ok(handle) => handle,
             Err(err) => return Err(super::to_http_error(&err)),
         };
@@ -0,0 +1,107 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+lazy_static::lazy_static! {
+    static ref URI_REGEX: regex::Regex =
+        regex::Regex::new("^/(?P<operation>(encrypt|decrypt))/stream$")
+        .expect("hard-coded regex must compile");
+}
+
+pub(super) struct Route {
+    api: std::sync::Arc<crate::Api>,
+    decrypt: bool,
+    user: libc::uid_t,
+}
+
+#[async_trait::async_trait]
+impl http_common::server::Route for Route {
+    type ApiVersion = aziot_key_common_http::ApiVersion;
+    fn api_version() -> &'static dyn http_common::DynRangeBounds<Self::ApiVersion> {
+        &((aziot_key_common_http::ApiVersion::V2020_09_01)..)
+    }
+
+    type Service = super::Service;
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(std::borrow::Cow<'_, str>, std::borrow::Cow<'_, str>)],
+        extensions: &http::Extensions,
+    ) -> Option<Self> {
+        let captures = URI_REGEX.captures(path)?;
+
+        let decrypt = &captures["operation"] == "decrypt";
+
+        let uid = extensions.get::<libc::uid_t>().copied()?;
+
+        Some(Route {
+            api: service.api.clone(),
+            decrypt,
+            user: uid,
+        })
+    }
+
+    type DeleteBody = serde::de::IgnoredAny;
+    type DeleteResponse = ();
+
+    type GetResponse = ();
+
+    type PostBody = aziot_key_common_http::create_stream::EncryptRequest;
+    type PostResponse = aziot_key_common_http::create_stream::Response;
+    async fn post(
+        self,
+        body: Option<Self::PostBody>,
+    ) -> http_common::server::RouteResponse<Option<Self::PostResponse>> {
+        let body = body.ok_or_else(|| http_common::server::Error {
+            status_code: http::StatusCode::BAD_REQUEST,
+            message: "missing request body".into(),
+        })?;
+
+        let mechanism = match body.parameters {
+            aziot_key_common_http::encrypt::Parameters::Aead { iv, aad } => {
+                aziot_key_common::EncryptMechanism::Aead {
+                    iv: iv.0,
+                    aad: aad.0,
+                }
+            }
+
+            aziot_key_common_http::encrypt::Parameters::RsaPkcs1 => {
+                aziot_key_common::EncryptMechanism::RsaPkcs1
+            }
+
+            aziot_key_common_http::encrypt::Parameters::RsaNoPadding => {
+                aziot_key_common::EncryptMechanism::RsaNoPadding
+            }
+
+            aziot_key_common_http::encrypt::Parameters::AesCbcPad { iv } => {
+                aziot_key_common::EncryptMechanism::AesCbcPad { iv: iv.0 }
+            }
+
+            aziot_key_common_http::encrypt::Parameters::AesCtr { iv } => {
+                aziot_key_common::EncryptMechanism::AesCtr { iv: iv.0 }
+            }
+
+            aziot_key_common_http::encrypt::Parameters::AesKeyWrap => {
+                aziot_key_common::EncryptMechanism::AesKeyWrap
+            }
+
+            aziot_key_common_http::encrypt::Parameters::AesKeyWrapPad => {
+                aziot_key_common::EncryptMechanism::AesKeyWrapPad
+            }
+        };
+
+        let Route { api, decrypt, user } = self;
+        let key_handle = body.key_handle;
+        let stream_id = match api
+            .run(move |api| api.create_encrypt_stream(&key_handle, &mechanism, decrypt, user))
+            .await
+        {
+            Ok(stream_id) => stream_id,
+            Err(err) => return Err(super::to_http_error(&err)),
+        };
+
+        let res = aziot_key_common_http::create_stream::Response { stream_id };
+        Ok((hyper::StatusCode::OK, Some(res)))
+    }
+
+    type PutBody = serde::de::IgnoredAny;
+    type PutResponse = ();
+}
@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 pub(super) struct Route {
-    api: std::sync::Arc<futures_util::lock::Mutex<crate::Api>>,
+    api: std::sync::Arc<crate::Api>,
     user: libc::uid_t,
 }
 
@@ -54,15 +54,12 @@ impl http_common::server::Route for Route {
             },
         );
 
-        let mut api = self.api.lock().await;
-        let api = &mut *api;
-
-        let handle = match api.create_key_if_not_exists(
-            &body.id,
-            create_key_value,
-            &body.usage,
-            self.user,
-        ) {
+        let (id, usage, user) = (body.id, body.usage, self.user);
+        let handle = match self
+            .api
+            .run(move |api| api.create_key_if_not_exists(&id, create_key_value, &usage, user))
+            .await
+        {
             Ok(handle) => handle,
             Err(err) => return Err(super::to_http_error(&err)),
         };
@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 pub(super) struct Route {
-    api: std::sync::Arc<futures_util::lock::Mutex<crate::Api>>,
+    api: std::sync::Arc<crate::Api>,
     user: libc::uid_t,
 }
 
@@ -47,14 +47,40 @@ impl http_common::server::Route for Route {
             message: "missing request body".into(),
         })?;
 
-        let mut api = self.api.lock().await;
-        let api = &mut *api;
+        let aziot_key_common_http::create_key_pair_if_not_exists::Request {
+            id,
+            preferred_algorithms,
+            import_key_pair_bytes,
+            import_format,
+            import_password,
+        } = body;
 
-        let handle = match api.create_key_pair_if_not_exists(
-            &body.id,
-            body.preferred_algorithms.as_deref(),
-            self.user,
-        ) {
+        let user = self.user;
+        let result = if let Some(import_key_pair_bytes) = import_key_pair_bytes {
+            let format = import_format.ok_or_else(|| http_common::server::Error {
+                status_code: http::StatusCode::BAD_REQUEST,
+                message: "format is required when importing a key pair".into(),
+            })?;
+
+            self.api
+                .run(move |api| {
+                    api.import_key_pair(
+                        &id,
+                        &import_key_pair_bytes.0,
+                        format,
+                        import_password.as_deref(),
+                        user,
+                    )
+                })
+                .await
+        } else {
+            self.api
+                .run(move |api| {
+                    api.create_key_pair_if_not_exists(&id, preferred_algorithms.as_deref(), user)
+                })
+                .await
+        };
+        let handle = match result {
             Ok(handle) => handle,
             Err(err) => return Err(super::to_http_error(&err)),
         };
@@ -0,0 +1,80 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+pub(super) struct Route {
+    api: std::sync::Arc<crate::Api>,
+    user: libc::uid_t,
+}
+
+#[async_trait::async_trait]
+impl http_common::server::Route for Route {
+    type ApiVersion = aziot_key_common_http::ApiVersion;
+    fn api_version() -> &'static dyn http_common::DynRangeBounds<Self::ApiVersion> {
+        &((aziot_key_common_http::ApiVersion::V2020_09_01)..)
+    }
+
+    type Service = super::Service;
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(std::borrow::Cow<'_, str>, std::borrow::Cow<'_, str>)],
+        extensions: &http::Extensions,
+    ) -> Option<Self> {
+        if path != "/sign/stream" {
+            return None;
+        }
+
+        let uid = extensions.get::<libc::uid_t>().copied()?;
+
+        Some(Route {
+            api: service.api.clone(),
+            user: uid,
+        })
+    }
+
+    type DeleteBody = serde::de::IgnoredAny;
+    type DeleteResponse = ();
+
+    type GetResponse = ();
+
+    type PostBody = aziot_key_common_http::create_stream::SignRequest;
+    type PostResponse = aziot_key_common_http::create_stream::Response;
+    async fn post(
+        self,
+        body: Option<Self::PostBody>,
+    ) -> http_common::server::RouteResponse<Option<Self::PostResponse>> {
+        let body = body.ok_or_else(|| http_common::server::Error {
+            status_code: http::StatusCode::BAD_REQUEST,
+            message: "missing request body".into(),
+        })?;
+
+        let mechanism = match body.parameters {
+            aziot_key_common_http::create_stream::SignParameters::HmacSha256 => {
+                aziot_key_common::SignMechanism::HmacSha256
+            }
+
+            aziot_key_common_http::create_stream::SignParameters::HmacSha384 => {
+                aziot_key_common::SignMechanism::HmacSha384
+            }
+
+            aziot_key_common_http::create_stream::SignParameters::HmacSha512 => {
+                aziot_key_common::SignMechanism::HmacSha512
+            }
+        };
+
+        let Route { api, user } = self;
+        let key_handle = body.key_handle;
+        let stream_id = match api
+            .run(move |api| api.create_sign_stream(&key_handle, mechanism, user))
+            .await
+        {
+            Ok(stream_id) => stream_id,
+            Err(err) => return Err(super::to_http_error(&err)),
+        };
+
+        let res = aziot_key_common_http::create_stream::Response { stream_id };
+        Ok((hyper::StatusCode::OK, Some(res)))
+    }
+
+    type PutBody = serde::de::IgnoredAny;
+    type PutResponse = ();
+}
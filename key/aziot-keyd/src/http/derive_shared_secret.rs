@@ -0,0 +1,113 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+pub(super) struct Route {
+    api: std::sync::Arc<crate::Api>,
+    user: libc::uid_t,
+}
+
+#[async_trait::async_trait]
+impl http_common::server::Route for Route {
+    type ApiVersion = aziot_key_common_http::ApiVersion;
+    fn api_version() -> &'static dyn http_common::DynRangeBounds<Self::ApiVersion> {
+        &((aziot_key_common_http::ApiVersion::V2020_09_01)..)
+    }
+
+    type Service = super::Service;
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(std::borrow::Cow<'_, str>, std::borrow::Cow<'_, str>)],
+        extensions: &http::Extensions,
+    ) -> Option<Self> {
+        if path != "/sharedsecret" {
+            return None;
+        }
+
+        let uid = extensions.get::<libc::uid_t>().copied()?;
+
+        Some(Route {
+            api: service.api.clone(),
+            user: uid,
+        })
+    }
+
+    type DeleteBody = serde::de::IgnoredAny;
+    type DeleteResponse = ();
+
+    type GetResponse = ();
+
+    type PostBody = aziot_key_common_http::derive_shared_secret::Request;
+    type PostResponse = aziot_key_common_http::derive_shared_secret::Response;
+    async fn post(
+        self,
+        body: Option<Self::PostBody>,
+    ) -> http_common::server::RouteResponse<Option<Self::PostResponse>> {
+        let Route { api, user } = self;
+
+        let body = body.ok_or_else(|| http_common::server::Error {
+            status_code: http::StatusCode::BAD_REQUEST,
+            message: "missing request body".into(),
+        })?;
+
+        let aziot_key_common_http::derive_shared_secret::Request {
+            key_handle,
+            peer_public_key,
+            parameters,
+            key_id,
+            usage,
+        } = body;
+
+        let mechanism = match parameters {
+            aziot_key_common_http::derive_shared_secret::Parameters::Ecdh => {
+                aziot_key_common::KeyAgreementMechanism::Ecdh
+            }
+            aziot_key_common_http::derive_shared_secret::Parameters::EcdhHkdf {
+                digest_algorithm,
+                salt,
+                info,
+                length,
+            } => aziot_key_common::KeyAgreementMechanism::EcdhHkdf {
+                digest: digest_algorithm,
+                salt: salt.0,
+                info: info.0,
+                len: length,
+            },
+        };
+
+        let shared_secret = match api
+            .run(move |api| {
+                api.derive_shared_secret(
+                    &key_handle,
+                    &mechanism,
+                    &peer_public_key.0,
+                    key_id.as_deref(),
+                    &usage,
+                    user,
+                )
+            })
+            .await
+        {
+            Ok(shared_secret) => shared_secret,
+            Err(err) => return Err(super::to_http_error(&err)),
+        };
+
+        let res = match shared_secret {
+            aziot_key_common::SharedSecret::Key(key_handle) => {
+                aziot_key_common_http::derive_shared_secret::Response {
+                    key_handle: Some(key_handle),
+                    shared_secret: None,
+                }
+            }
+            aziot_key_common::SharedSecret::Raw(shared_secret) => {
+                aziot_key_common_http::derive_shared_secret::Response {
+                    key_handle: None,
+                    shared_secret: Some(http_common::ByteString(shared_secret)),
+                }
+            }
+        };
+        Ok((hyper::StatusCode::OK, Some(res)))
+    }
+
+    type PutBody = serde::de::IgnoredAny;
+    type PutResponse = ();
+}
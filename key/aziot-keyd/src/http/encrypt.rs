@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 pub(super) struct Route {
-    api: std::sync::Arc<futures_util::lock::Mutex<crate::Api>>,
+    api: std::sync::Arc<crate::Api>,
 }
 
 #[async_trait::async_trait]
@@ -58,12 +58,30 @@ impl http_common::server::Route for Route {
             aziot_key_common_http::encrypt::Parameters::RsaNoPadding => {
                 aziot_key_common::EncryptMechanism::RsaNoPadding
             }
-        };
 
-        let mut api = self.api.lock().await;
-        let api = &mut *api;
+            aziot_key_common_http::encrypt::Parameters::AesCbcPad { iv } => {
+                aziot_key_common::EncryptMechanism::AesCbcPad { iv: iv.0 }
+            }
+
+            aziot_key_common_http::encrypt::Parameters::AesCtr { iv } => {
+                aziot_key_common::EncryptMechanism::AesCtr { iv: iv.0 }
+            }
+
+            aziot_key_common_http::encrypt::Parameters::AesKeyWrap => {
+                aziot_key_common::EncryptMechanism::AesKeyWrap
+            }
+
+            aziot_key_common_http::encrypt::Parameters::AesKeyWrapPad => {
+                aziot_key_common::EncryptMechanism::AesKeyWrapPad
+            }
+        };
 
-        let ciphertext = match api.encrypt(&body.key_handle, mechanism, &body.plaintext.0) {
+        let (key_handle, plaintext) = (body.key_handle, body.plaintext);
+        let ciphertext = match self
+            .api
+            .run(move |api| api.encrypt(&key_handle, &mechanism, &plaintext.0))
+            .await
+        {
             Ok(ciphertext) => ciphertext,
             Err(err) => return Err(super::to_http_error(&err)),
         };
@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 pub(super) struct Route {
-    api: std::sync::Arc<futures_util::lock::Mutex<crate::Api>>,
+    api: std::sync::Arc<crate::Api>,
 }
 
 #[async_trait::async_trait]
@@ -43,10 +43,11 @@ impl http_common::server::Route for Route {
             message: "missing request body".into(),
         })?;
 
-        let mut api = self.api.lock().await;
-        let api = &mut *api;
-
-        let derived_key = match api.export_derived_key(&body.handle) {
+        let derived_key = match self
+            .api
+            .run(move |api| api.export_derived_key(&body.handle))
+            .await
+        {
             Ok(derived_key) => derived_key,
             Err(err) => return Err(super::to_http_error(&err)),
         };
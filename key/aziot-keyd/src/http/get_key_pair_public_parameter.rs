@@ -7,7 +7,7 @@ lazy_static::lazy_static! {
 }
 
 pub(super) struct Route {
-    api: std::sync::Arc<futures_util::lock::Mutex<crate::Api>>,
+    api: std::sync::Arc<crate::Api>,
     parameter_name: String,
 }
 
@@ -54,14 +54,15 @@ impl http_common::server::Route for Route {
             message: "missing request body".into(),
         })?;
 
-        let mut api = self.api.lock().await;
-        let api = &mut *api;
-
-        let parameter_value =
-            match api.get_key_pair_public_parameter(&body.key_handle, &self.parameter_name) {
-                Ok(parameter_value) => parameter_value,
-                Err(err) => return Err(super::to_http_error(&err)),
-            };
+        let parameter_name = self.parameter_name;
+        let parameter_value = match self
+            .api
+            .run(move |api| api.get_key_pair_public_parameter(&body.key_handle, &parameter_name))
+            .await
+        {
+            Ok(parameter_value) => parameter_value,
+            Err(err) => return Err(super::to_http_error(&err)),
+        };
 
         let res = aziot_key_common_http::get_key_pair_public_parameter::Response {
             value: parameter_value,
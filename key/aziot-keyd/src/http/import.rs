@@ -0,0 +1,118 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+lazy_static::lazy_static! {
+    static ref URI_REGEX: regex::Regex =
+        regex::Regex::new("^/(?P<type>(key|keypair))/(?P<keyId>[^/]+)/import$")
+        .expect("hard-coded regex must compile");
+}
+
+pub(super) struct Route {
+    api: std::sync::Arc<crate::Api>,
+    type_: String,
+    key_id: String,
+    user: libc::uid_t,
+}
+
+#[async_trait::async_trait]
+impl http_common::server::Route for Route {
+    type ApiVersion = aziot_key_common_http::ApiVersion;
+    fn api_version() -> &'static dyn http_common::DynRangeBounds<Self::ApiVersion> {
+        &((aziot_key_common_http::ApiVersion::V2020_09_01)..)
+    }
+
+    type Service = super::Service;
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(std::borrow::Cow<'_, str>, std::borrow::Cow<'_, str>)],
+        extensions: &http::Extensions,
+    ) -> Option<Self> {
+        let captures = URI_REGEX.captures(path)?;
+
+        let type_ = &captures["type"];
+        let type_ = percent_encoding::percent_decode_str(type_)
+            .decode_utf8()
+            .ok()?;
+
+        let key_id = &captures["keyId"];
+        let key_id = percent_encoding::percent_decode_str(key_id)
+            .decode_utf8()
+            .ok()?;
+
+        let uid = extensions.get::<libc::uid_t>().copied()?;
+
+        Some(Route {
+            api: service.api.clone(),
+            type_: type_.into_owned(),
+            key_id: key_id.into_owned(),
+            user: uid,
+        })
+    }
+
+    type DeleteBody = serde::de::IgnoredAny;
+    type DeleteResponse = ();
+
+    type GetResponse = ();
+
+    type PostBody = aziot_key_common_http::import_wrapped_key::Request;
+    type PostResponse = aziot_key_common_http::import_wrapped_key::Response;
+    async fn post(
+        self,
+        body: Option<Self::PostBody>,
+    ) -> http_common::server::RouteResponse<Option<Self::PostResponse>> {
+        let Route {
+            api,
+            type_,
+            key_id,
+            user,
+        } = self;
+
+        let body = body.ok_or_else(|| http_common::server::Error {
+            status_code: http::StatusCode::BAD_REQUEST,
+            message: "missing request body".into(),
+        })?;
+
+        let result = match &*type_ {
+            "keypair" => {
+                api.run(move |api| {
+                    api.import_wrapped_key_pair(
+                        &key_id,
+                        &body.unwrapping_key_handle,
+                        body.mechanism,
+                        &body.wrapped_key.0,
+                        user,
+                    )
+                })
+                .await
+            }
+            "key" => {
+                api.run(move |api| {
+                    api.import_wrapped_key(
+                        &key_id,
+                        &body.unwrapping_key_handle,
+                        body.mechanism,
+                        &body.wrapped_key.0,
+                        user,
+                    )
+                })
+                .await
+            }
+            type_ => {
+                return Err(http_common::server::Error {
+                    status_code: hyper::StatusCode::BAD_REQUEST,
+                    message: format!("invalid type {:?}", type_).into(),
+                })
+            }
+        };
+        let handle = match result {
+            Ok(handle) => handle,
+            Err(err) => return Err(super::to_http_error(&err)),
+        };
+
+        let res = aziot_key_common_http::import_wrapped_key::Response { handle };
+        Ok((hyper::StatusCode::OK, Some(res)))
+    }
+
+    type PutBody = serde::de::IgnoredAny;
+    type PutResponse = ();
+}
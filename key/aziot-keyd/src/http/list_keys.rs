@@ -0,0 +1,56 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+pub(super) struct Route {
+    api: std::sync::Arc<crate::Api>,
+    user: libc::uid_t,
+}
+
+#[async_trait::async_trait]
+impl http_common::server::Route for Route {
+    type ApiVersion = aziot_key_common_http::ApiVersion;
+    fn api_version() -> &'static dyn http_common::DynRangeBounds<Self::ApiVersion> {
+        &((aziot_key_common_http::ApiVersion::V2020_09_01)..)
+    }
+
+    type Service = super::Service;
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(std::borrow::Cow<'_, str>, std::borrow::Cow<'_, str>)],
+        extensions: &http::Extensions,
+    ) -> Option<Self> {
+        if path != "/keys" {
+            return None;
+        }
+
+        let uid = extensions.get::<libc::uid_t>().copied()?;
+
+        Some(Route {
+            api: service.api.clone(),
+            user: uid,
+        })
+    }
+
+    type DeleteBody = serde::de::IgnoredAny;
+    type DeleteResponse = ();
+
+    type GetResponse = aziot_key_common_http::list_keys::Response;
+    async fn get(self) -> http_common::server::RouteResponse<Self::GetResponse> {
+        let user = self.user;
+        let keys = match self.api.run(move |api| api.list_keys(user)).await {
+            Ok(keys) => keys,
+            Err(err) => return Err(super::to_http_error(&err)),
+        };
+
+        let res = aziot_key_common_http::list_keys::Response {
+            keys: keys.into_iter().map(Into::into).collect(),
+        };
+        Ok((hyper::StatusCode::OK, res))
+    }
+
+    type PostBody = serde::de::IgnoredAny;
+    type PostResponse = ();
+
+    type PutBody = serde::de::IgnoredAny;
+    type PutResponse = ();
+}
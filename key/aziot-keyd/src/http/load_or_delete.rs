@@ -7,7 +7,7 @@ lazy_static::lazy_static! {
 }
 
 pub(super) struct Route {
-    api: std::sync::Arc<futures_util::lock::Mutex<crate::Api>>,
+    api: std::sync::Arc<crate::Api>,
     type_: String,
     key_id: String,
     user: libc::uid_t,
@@ -51,21 +51,46 @@ impl http_common::server::Route for Route {
 
     type DeleteBody = serde::de::IgnoredAny;
     type DeleteResponse = ();
+    async fn delete(
+        self,
+        _body: Option<Self::DeleteBody>,
+    ) -> http_common::server::RouteResponse<Option<Self::DeleteResponse>> {
+        let Route {
+            api,
+            type_,
+            key_id,
+            user,
+        } = self;
+
+        let result = match &*type_ {
+            "keypair" => api.run(move |api| api.delete_key_pair(&key_id, user)).await,
+            "key" => api.run(move |api| api.delete_key(&key_id, user)).await,
+            type_ => {
+                return Err(http_common::server::Error {
+                    status_code: hyper::StatusCode::BAD_REQUEST,
+                    message: format!("invalid type {:?}", type_).into(),
+                })
+            }
+        };
+        if let Err(err) = result {
+            return Err(super::to_http_error(&err));
+        }
+
+        Ok((hyper::StatusCode::NO_CONTENT, None))
+    }
 
     type GetResponse = aziot_key_common_http::load::Response;
     async fn get(self) -> http_common::server::RouteResponse<Self::GetResponse> {
-        let mut api = self.api.lock().await;
-        let api = &mut *api;
-
-        let handle = match &*self.type_ {
-            "keypair" => match api.load_key_pair(&self.key_id, self.user) {
-                Ok(handle) => handle,
-                Err(err) => return Err(super::to_http_error(&err)),
-            },
-            "key" => match api.load_key(&self.key_id, self.user) {
-                Ok(handle) => handle,
-                Err(err) => return Err(super::to_http_error(&err)),
-            },
+        let Route {
+            api,
+            type_,
+            key_id,
+            user,
+        } = self;
+
+        let result = match &*type_ {
+            "keypair" => api.run(move |api| api.load_key_pair(&key_id, user)).await,
+            "key" => api.run(move |api| api.load_key(&key_id, user)).await,
             type_ => {
                 return Err(http_common::server::Error {
                     status_code: hyper::StatusCode::BAD_REQUEST,
@@ -73,6 +98,10 @@ impl http_common::server::Route for Route {
                 })
             }
         };
+        let handle = match result {
+            Ok(handle) => handle,
+            Err(err) => return Err(super::to_http_error(&err)),
+        };
 
         let res = aziot_key_common_http::load::Response { handle };
         Ok((hyper::StatusCode::OK, res))
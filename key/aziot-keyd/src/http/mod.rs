@@ -1,18 +1,28 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 mod create_derived_key;
+mod create_encrypt_stream;
 mod create_key_if_not_exists;
 mod create_key_pair_if_not_exists;
+mod create_sign_stream;
 mod decrypt;
+mod derive_shared_secret;
 mod encrypt;
+mod export;
 mod export_derived_key;
 mod get_key_pair_public_parameter;
-mod load;
+mod get_key_parameter;
+mod import;
+mod list_keys;
+mod load_or_delete;
+mod move_key_pair;
+mod rotate_handle_validation_key;
 mod sign;
+mod update_or_abort_stream;
 
 #[derive(Clone)]
 pub struct Service {
-    pub(crate) api: std::sync::Arc<futures_util::lock::Mutex<crate::Api>>,
+    pub(crate) api: std::sync::Arc<crate::Api>,
 }
 
 http_common::make_service! {
@@ -20,14 +30,24 @@ http_common::make_service! {
     api_version: aziot_key_common_http::ApiVersion,
     routes: [
         create_derived_key::Route,
+        create_encrypt_stream::Route,
         create_key_if_not_exists::Route,
         create_key_pair_if_not_exists::Route,
+        create_sign_stream::Route,
         decrypt::Route,
+        derive_shared_secret::Route,
         encrypt::Route,
+        export::Route,
         export_derived_key::Route,
+        get_key_parameter::Route,
         get_key_pair_public_parameter::Route,
-        load::Route,
+        import::Route,
+        list_keys::Route,
+        load_or_delete::Route,
+        move_key_pair::Route,
+        rotate_handle_validation_key::Route,
         sign::Route,
+        update_or_abort_stream::Route,
     ],
 }
 
@@ -0,0 +1,59 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+pub(super) struct Route {
+    api: std::sync::Arc<crate::Api>,
+    user: libc::uid_t,
+}
+
+#[async_trait::async_trait]
+impl http_common::server::Route for Route {
+    type ApiVersion = aziot_key_common_http::ApiVersion;
+    fn api_version() -> &'static dyn http_common::DynRangeBounds<Self::ApiVersion> {
+        &((aziot_key_common_http::ApiVersion::V2020_09_01)..)
+    }
+
+    type Service = super::Service;
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(std::borrow::Cow<'_, str>, std::borrow::Cow<'_, str>)],
+        extensions: &http::Extensions,
+    ) -> Option<Self> {
+        if path != "/handlevalidationkey/rotate" {
+            return None;
+        }
+
+        let uid = extensions.get::<libc::uid_t>().copied()?;
+
+        Some(Route {
+            api: service.api.clone(),
+            user: uid,
+        })
+    }
+
+    type DeleteBody = serde::de::IgnoredAny;
+    type DeleteResponse = ();
+
+    type GetResponse = ();
+
+    type PostBody = serde::de::IgnoredAny;
+    type PostResponse = ();
+    async fn post(
+        self,
+        _body: Option<Self::PostBody>,
+    ) -> http_common::server::RouteResponse<Option<Self::PostResponse>> {
+        let user = self.user;
+        if let Err(err) = self
+            .api
+            .run(move |api| api.rotate_handle_validation_key(user))
+            .await
+        {
+            return Err(super::to_http_error(&err));
+        }
+
+        Ok((hyper::StatusCode::NO_CONTENT, None))
+    }
+
+    type PutBody = serde::de::IgnoredAny;
+    type PutResponse = ();
+}
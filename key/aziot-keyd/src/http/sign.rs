@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 pub(super) struct Route {
-    api: std::sync::Arc<futures_util::lock::Mutex<crate::Api>>,
+    api: std::sync::Arc<crate::Api>,
 }
 
 #[async_trait::async_trait]
@@ -48,15 +48,51 @@ impl http_common::server::Route for Route {
                 (aziot_key_common::SignMechanism::Ecdsa, digest)
             }
 
+            aziot_key_common_http::sign::Parameters::Eddsa { message } => {
+                (aziot_key_common::SignMechanism::Eddsa, message)
+            }
+
+            aziot_key_common_http::sign::Parameters::RsaPkcs1 {
+                digest_algorithm,
+                digest,
+            } => (
+                aziot_key_common::SignMechanism::RsaPkcs1 {
+                    digest: digest_algorithm,
+                },
+                digest,
+            ),
+
+            aziot_key_common_http::sign::Parameters::RsaPss {
+                digest_algorithm,
+                salt_len,
+                digest,
+            } => (
+                aziot_key_common::SignMechanism::RsaPss {
+                    digest: digest_algorithm,
+                    salt_len,
+                },
+                digest,
+            ),
+
             aziot_key_common_http::sign::Parameters::HmacSha256 { message } => {
                 (aziot_key_common::SignMechanism::HmacSha256, message)
             }
-        };
 
-        let mut api = self.api.lock().await;
-        let api = &mut *api;
+            aziot_key_common_http::sign::Parameters::HmacSha384 { message } => {
+                (aziot_key_common::SignMechanism::HmacSha384, message)
+            }
+
+            aziot_key_common_http::sign::Parameters::HmacSha512 { message } => {
+                (aziot_key_common::SignMechanism::HmacSha512, message)
+            }
+        };
 
-        let signature = match api.sign(&body.key_handle, mechanism, &digest.0) {
+        let key_handle = body.key_handle;
+        let signature = match self
+            .api
+            .run(move |api| api.sign(&key_handle, mechanism, &digest.0))
+            .await
+        {
             Ok(signature) => signature,
             Err(err) => return Err(super::to_http_error(&err)),
         };
@@ -4,6 +4,12 @@
 #[derive(Clone, Copy, Debug)]
 pub struct KeysRawError(pub(crate) sys::AZIOT_KEYS_RC);
 
+impl KeysRawError {
+    pub(crate) const INVALID_PARAMETER: Self =
+        KeysRawError(sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER);
+    pub(crate) const EXTERNAL: Self = KeysRawError(sys::AZIOT_KEYS_RC_ERR_EXTERNAL);
+}
+
 impl std::fmt::Display for KeysRawError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.0 {
@@ -16,6 +22,10 @@ impl std::fmt::Display for KeysRawError {
     }
 }
 
+/// The functions of libaziot-keys.
+///
+/// keyd supports libraries that implement API version 2.0.0.0 or later. Functions that were added after 2.0.0.0 are `None`
+/// if the library implements an earlier version, and calling them fails with `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`.
 #[derive(Debug)]
 pub(crate) enum Keys {
     V2_0_0_0 {
@@ -100,29 +110,253 @@ pub(crate) enum Keys {
             plaintext: *mut std::os::raw::c_uchar,
             plaintext_len: *mut usize,
         ) -> sys::AZIOT_KEYS_RC,
+
+        delete_key_pair:
+            Option<unsafe extern "C" fn(id: *const std::os::raw::c_char) -> sys::AZIOT_KEYS_RC>,
+
+        delete_key:
+            Option<unsafe extern "C" fn(id: *const std::os::raw::c_char) -> sys::AZIOT_KEYS_RC>,
+
+        enumerate_keys: Option<
+            unsafe extern "C" fn(
+                callback: sys::AZIOT_KEYS_ENUMERATE_KEYS_CALLBACK,
+                context: *mut std::ffi::c_void,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        /// Zero if the library implements an API version before 2.1.0.0.
+        capabilities: sys::AZIOT_KEYS_CAPABILITIES,
+
+        get_key_parameter: Option<
+            unsafe extern "C" fn(
+                id: *const std::os::raw::c_char,
+                r#type: sys::AZIOT_KEYS_KEY_PARAMETER_TYPE,
+                value: *mut std::os::raw::c_uchar,
+                value_len: *mut usize,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        export_key: Option<
+            unsafe extern "C" fn(
+                id: *const std::os::raw::c_char,
+                mechanism: sys::AZIOT_KEYS_WRAP_MECHANISM,
+                wrapping_key: *const std::os::raw::c_uchar,
+                wrapping_key_len: usize,
+                wrapped_key: *mut std::os::raw::c_uchar,
+                wrapped_key_len: *mut usize,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        export_key_pair: Option<
+            unsafe extern "C" fn(
+                id: *const std::os::raw::c_char,
+                mechanism: sys::AZIOT_KEYS_WRAP_MECHANISM,
+                wrapping_key: *const std::os::raw::c_uchar,
+                wrapping_key_len: usize,
+                wrapped_key: *mut std::os::raw::c_uchar,
+                wrapped_key_len: *mut usize,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        import_wrapped_key: Option<
+            unsafe extern "C" fn(
+                id: *const std::os::raw::c_char,
+                unwrapping_key_pair_id: *const std::os::raw::c_char,
+                mechanism: sys::AZIOT_KEYS_WRAP_MECHANISM,
+                wrapped_key: *const std::os::raw::c_uchar,
+                wrapped_key_len: usize,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        import_wrapped_key_pair: Option<
+            unsafe extern "C" fn(
+                id: *const std::os::raw::c_char,
+                unwrapping_key_pair_id: *const std::os::raw::c_char,
+                mechanism: sys::AZIOT_KEYS_WRAP_MECHANISM,
+                wrapped_key: *const std::os::raw::c_uchar,
+                wrapped_key_len: usize,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        import_key_pair: Option<
+            unsafe extern "C" fn(
+                id: *const std::os::raw::c_char,
+                format: sys::AZIOT_KEYS_KEY_PAIR_FORMAT,
+                bytes: *const std::os::raw::c_uchar,
+                bytes_len: usize,
+                password: *const std::os::raw::c_char,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        derive_shared_secret: Option<
+            unsafe extern "C" fn(
+                id: *const std::os::raw::c_char,
+                mechanism: sys::AZIOT_KEYS_KEY_AGREEMENT_MECHANISM,
+                parameters: *const std::ffi::c_void,
+                peer_public_key: *const std::os::raw::c_uchar,
+                peer_public_key_len: usize,
+                secret: *mut std::os::raw::c_uchar,
+                secret_len: *mut usize,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        import_key_if_not_exists: Option<
+            unsafe extern "C" fn(
+                id: *const std::os::raw::c_char,
+                bytes: *const u8,
+                bytes_len: usize,
+                usage: sys::AZIOT_KEYS_KEY_USAGE,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        derive_key_with_mechanism: Option<
+            unsafe extern "C" fn(
+                base_id: *const std::os::raw::c_char,
+                mechanism: sys::AZIOT_KEYS_KEY_DERIVATION_MECHANISM,
+                parameters: *const std::ffi::c_void,
+                derivation_data: *const std::os::raw::c_uchar,
+                derivation_data_len: usize,
+                derived_key: *mut std::os::raw::c_uchar,
+                derived_key_len: *mut usize,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        encrypt_init: Option<
+            unsafe extern "C" fn(
+                id: *const std::os::raw::c_char,
+                mechanism: sys::AZIOT_KEYS_ENCRYPT_MECHANISM,
+                parameters: *const std::ffi::c_void,
+                stream: *mut *mut sys::AZIOT_KEYS_STREAM,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        decrypt_init: Option<
+            unsafe extern "C" fn(
+                id: *const std::os::raw::c_char,
+                mechanism: sys::AZIOT_KEYS_ENCRYPT_MECHANISM,
+                parameters: *const std::ffi::c_void,
+                stream: *mut *mut sys::AZIOT_KEYS_STREAM,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        sign_init: Option<
+            unsafe extern "C" fn(
+                id: *const std::os::raw::c_char,
+                mechanism: sys::AZIOT_KEYS_SIGN_MECHANISM,
+                parameters: *const std::ffi::c_void,
+                stream: *mut *mut sys::AZIOT_KEYS_STREAM,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
+
+        stream_functions: Option<StreamFunctions>,
+
+        move_key_pair: Option<
+            unsafe extern "C" fn(
+                from_id: *const std::os::raw::c_char,
+                to_id: *const std::os::raw::c_char,
+            ) -> sys::AZIOT_KEYS_RC,
+        >,
     },
 }
 
+/// The functions of libaziot-keys for multi-part operations, added in API version 2.1.0.0.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct StreamFunctions {
+    update: unsafe extern "C" fn(
+        stream: *mut sys::AZIOT_KEYS_STREAM,
+        data: *const std::os::raw::c_uchar,
+        data_len: usize,
+    ) -> sys::AZIOT_KEYS_RC,
+
+    finish: unsafe extern "C" fn(stream: *mut sys::AZIOT_KEYS_STREAM) -> sys::AZIOT_KEYS_RC,
+
+    read: unsafe extern "C" fn(
+        stream: *mut sys::AZIOT_KEYS_STREAM,
+        output: *mut std::os::raw::c_uchar,
+        output_len: *mut usize,
+    ) -> sys::AZIOT_KEYS_RC,
+
+    free: unsafe extern "C" fn(stream: *mut sys::AZIOT_KEYS_STREAM),
+}
+
+// A function that was added in a later API version than the library implements is `None`,
+// but a library that implements that version must define it.
+macro_rules! later_function {
+    ($function_list:ident, $name:ident) => {
+        match $function_list {
+            Some(function_list) => Some(
+                (*function_list)
+                    .$name
+                    .ok_or(LoadLibraryError::MissingFunction(stringify!($name)))?,
+            ),
+            None => None,
+        }
+    };
+}
+
 impl Keys {
+    /// The API versions that keyd supports, newest first.
+    const API_VERSIONS: &'static [sys::AZIOT_KEYS_VERSION] = &[
+        sys::AZIOT_KEYS_VERSION_2_1_0_0,
+        sys::AZIOT_KEYS_VERSION_2_0_0_0,
+    ];
+
+    #[allow(clippy::too_many_lines)] // One field per libaziot-keys function.
     pub(crate) fn new() -> Result<Self, LoadLibraryError> {
         unsafe {
+            // Request the newest version that the library implements. Libraries fail with INVALID_PARAMETER for versions they don't recognize.
             let mut function_list: *const sys::AZIOT_KEYS_FUNCTION_LIST = std::ptr::null_mut();
-            keys_ok(sys::aziot_keys_get_function_list(
-                sys::AZIOT_KEYS_VERSION_2_0_0_0,
-                &mut function_list,
-            ))
-            .map_err(LoadLibraryError::GetFunctionList)?;
+            let mut requested_api_version = None;
+            for &api_version in Self::API_VERSIONS {
+                match keys_ok(sys::aziot_keys_get_function_list(
+                    api_version,
+                    &mut function_list,
+                )) {
+                    Ok(()) => {
+                        requested_api_version = Some(api_version);
+                        break;
+                    }
+                    Err(KeysRawError(sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER)) => (),
+                    Err(err) => return Err(LoadLibraryError::GetFunctionList(err)),
+                }
+            }
+            if requested_api_version.is_none() {
+                return Err(LoadLibraryError::GetFunctionList(
+                    KeysRawError::INVALID_PARAMETER,
+                ));
+            }
 
             let api_version = (*function_list).version;
-            if api_version != sys::AZIOT_KEYS_VERSION_2_0_0_0 {
+            if Some(api_version) != requested_api_version {
                 return Err(LoadLibraryError::UnsupportedApiVersion(api_version));
             }
 
-            // AZIOT_KEYS_FUNCTION_LIST has looser alignment than AZIOT_KEYS_FUNCTION_LIST_2_0_0_0, but the pointer comes from the library itself,
-            // so it will be correctly aligned already.
+            let function_list_2_1_0_0 = function_list_of::<sys::AZIOT_KEYS_FUNCTION_LIST_2_1_0_0>(
+                function_list,
+                api_version,
+                sys::AZIOT_KEYS_VERSION_2_1_0_0,
+            );
             #[allow(clippy::cast_ptr_alignment)]
             let function_list = function_list.cast::<sys::AZIOT_KEYS_FUNCTION_LIST_2_0_0_0>();
 
+            let stream_functions = match function_list_2_1_0_0 {
+                Some(function_list_2_1_0_0) => Some(StreamFunctions {
+                    update: (*function_list_2_1_0_0)
+                        .stream_update
+                        .ok_or(LoadLibraryError::MissingFunction("stream_update"))?,
+                    finish: (*function_list_2_1_0_0)
+                        .stream_final
+                        .ok_or(LoadLibraryError::MissingFunction("stream_final"))?,
+                    read: (*function_list_2_1_0_0)
+                        .stream_read
+                        .ok_or(LoadLibraryError::MissingFunction("stream_read"))?,
+                    free: (*function_list_2_1_0_0)
+                        .stream_free
+                        .ok_or(LoadLibraryError::MissingFunction("stream_free"))?,
+                }),
+                None => None,
+            };
+
             let result = Keys::V2_0_0_0 {
                 set_parameter: (*function_list)
                     .set_parameter
@@ -173,15 +407,87 @@ impl Keys {
                 decrypt: (*function_list)
                     .decrypt
                     .ok_or(LoadLibraryError::MissingFunction("decrypt"))?,
+
+                delete_key_pair: later_function!(function_list_2_1_0_0, delete_key_pair),
+
+                delete_key: later_function!(function_list_2_1_0_0, delete_key),
+
+                enumerate_keys: later_function!(function_list_2_1_0_0, enumerate_keys),
+
+                capabilities: function_list_2_1_0_0.map_or(0, |function_list_2_1_0_0| {
+                    (*function_list_2_1_0_0).capabilities
+                }),
+
+                get_key_parameter: later_function!(function_list_2_1_0_0, get_key_parameter),
+
+                export_key: later_function!(function_list_2_1_0_0, export_key),
+
+                export_key_pair: later_function!(function_list_2_1_0_0, export_key_pair),
+
+                import_wrapped_key: later_function!(function_list_2_1_0_0, import_wrapped_key),
+
+                import_wrapped_key_pair: later_function!(
+                    function_list_2_1_0_0,
+                    import_wrapped_key_pair
+                ),
+
+                import_key_pair: later_function!(function_list_2_1_0_0, import_key_pair),
+
+                derive_shared_secret: later_function!(function_list_2_1_0_0, derive_shared_secret),
+
+                import_key_if_not_exists: later_function!(
+                    function_list_2_1_0_0,
+                    import_key_if_not_exists
+                ),
+
+                derive_key_with_mechanism: later_function!(
+                    function_list_2_1_0_0,
+                    derive_key_with_mechanism
+                ),
+
+                encrypt_init: later_function!(function_list_2_1_0_0, encrypt_init),
+
+                decrypt_init: later_function!(function_list_2_1_0_0, decrypt_init),
+
+                sign_init: later_function!(function_list_2_1_0_0, sign_init),
+
+                stream_functions,
+
+                move_key_pair: later_function!(function_list_2_1_0_0, move_key_pair),
             };
 
-            log::info!("Loaded libaziot-keys with version 0x{:08x}", api_version);
+            log::info!(
+                "Loaded libaziot-keys with version 0x{:08x} (thread-safe: {})",
+                api_version,
+                result.is_thread_safe(),
+            );
 
             Ok(result)
         }
     }
 }
 
+impl Keys {
+    /// Whether derived keys can use derivation mechanisms other than HMAC-SHA256, which was added in API version 2.1.0.0.
+    pub(crate) fn supports_derivation_mechanisms(&self) -> bool {
+        match self {
+            Keys::V2_0_0_0 {
+                derive_key_with_mechanism,
+                ..
+            } => derive_key_with_mechanism.is_some(),
+        }
+    }
+
+    /// Whether the library allows its functions to be called concurrently from multiple threads.
+    pub(crate) fn is_thread_safe(&self) -> bool {
+        match self {
+            Keys::V2_0_0_0 { capabilities, .. } => {
+                capabilities & sys::AZIOT_KEYS_CAPABILITY_THREAD_SAFE != 0
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum LoadLibraryError {
     GetFunctionList(KeysRawError),
@@ -234,8 +540,8 @@ impl Keys {
 
 #[derive(Debug)]
 pub struct SetLibraryParameterError {
-    name: String,
-    err: KeysRawError,
+    pub(crate) name: String,
+    pub(crate) err: KeysRawError,
 }
 
 impl std::fmt::Display for SetLibraryParameterError {
@@ -252,7 +558,7 @@ impl std::error::Error for SetLibraryParameterError {}
 
 impl Keys {
     pub(crate) fn create_key_pair_if_not_exists(
-        &mut self,
+        &self,
         id: &std::ffi::CStr,
         preferred_algorithms: Option<&std::ffi::CStr>,
     ) -> Result<(), CreateKeyPairIfNotExistsError> {
@@ -289,7 +595,52 @@ impl std::fmt::Display for CreateKeyPairIfNotExistsError {
 impl std::error::Error for CreateKeyPairIfNotExistsError {}
 
 impl Keys {
-    pub(crate) fn load_key_pair(&mut self, id: &std::ffi::CStr) -> Result<(), LoadKeyPairError> {
+    pub(crate) fn import_key_pair(
+        &self,
+        id: &std::ffi::CStr,
+        format: sys::AZIOT_KEYS_KEY_PAIR_FORMAT,
+        bytes: &[u8],
+        password: Option<&std::ffi::CStr>,
+    ) -> Result<(), ImportKeyPairError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 {
+                    import_key_pair, ..
+                } => {
+                    let import_key_pair = supported(*import_key_pair, "import_key_pair")
+                        .map_err(|err| ImportKeyPairError { err })?;
+
+                    keys_ok(import_key_pair(
+                        id.as_ptr(),
+                        format,
+                        bytes.as_ptr(),
+                        bytes.len(),
+                        password.map_or(std::ptr::null(), std::ffi::CStr::as_ptr),
+                    ))
+                    .map_err(|err| ImportKeyPairError { err })?;
+
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ImportKeyPairError {
+    pub err: KeysRawError,
+}
+
+impl std::fmt::Display for ImportKeyPairError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not import key pair: {}", self.err)
+    }
+}
+
+impl std::error::Error for ImportKeyPairError {}
+
+impl Keys {
+    pub(crate) fn load_key_pair(&self, id: &std::ffi::CStr) -> Result<(), LoadKeyPairError> {
         unsafe {
             match self {
                 Keys::V2_0_0_0 { load_key_pair, .. } => {
@@ -315,9 +666,77 @@ impl std::fmt::Display for LoadKeyPairError {
 
 impl std::error::Error for LoadKeyPairError {}
 
+impl Keys {
+    pub(crate) fn delete_key_pair(&self, id: &std::ffi::CStr) -> Result<(), DeleteKeyPairError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 {
+                    delete_key_pair, ..
+                } => {
+                    let delete_key_pair = supported(*delete_key_pair, "delete_key_pair")
+                        .map_err(|err| DeleteKeyPairError { err })?;
+
+                    keys_ok(delete_key_pair(id.as_ptr()))
+                        .map_err(|err| DeleteKeyPairError { err })?;
+
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DeleteKeyPairError {
+    pub err: KeysRawError,
+}
+
+impl std::fmt::Display for DeleteKeyPairError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not delete key pair: {}", self.err)
+    }
+}
+
+impl std::error::Error for DeleteKeyPairError {}
+
+impl Keys {
+    pub(crate) fn move_key_pair(
+        &self,
+        from_id: &std::ffi::CStr,
+        to_id: &std::ffi::CStr,
+    ) -> Result<(), MoveKeyPairError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 { move_key_pair, .. } => {
+                    let move_key_pair = supported(*move_key_pair, "move_key_pair")
+                        .map_err(|err| MoveKeyPairError { err })?;
+
+                    keys_ok(move_key_pair(from_id.as_ptr(), to_id.as_ptr()))
+                        .map_err(|err| MoveKeyPairError { err })?;
+
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MoveKeyPairError {
+    pub err: KeysRawError,
+}
+
+impl std::fmt::Display for MoveKeyPairError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not move key pair: {}", self.err)
+    }
+}
+
+impl std::error::Error for MoveKeyPairError {}
+
 impl Keys {
     pub(crate) fn get_key_pair_public_parameter(
-        &mut self,
+        &self,
         id: &std::ffi::CStr,
         parameter_name: &str,
     ) -> Result<String, GetKeyPairPublicParameterError> {
@@ -353,6 +772,9 @@ impl Keys {
                                     sys::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_RSA => {
                                         "RSA".to_owned()
                                     }
+                                    sys::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_ED25519 => {
+                                        "ED25519".to_owned()
+                                    }
                                     algorithm => return Err(
                                         GetKeyPairPublicParameterError::UnrecognizedKeyAlgorithm {
                                             algorithm,
@@ -383,12 +805,9 @@ impl Keys {
                                 }
                             };
 
-                            let parameter_value = get_key_pair_parameter_byte_buf(
-                                *get_key_pair_parameter,
-                                id,
-                                parameter_type,
-                            )
-                            .map_err(|err| GetKeyPairPublicParameterError::Api { err })?;
+                            let parameter_value =
+                                get_parameter_byte_buf(*get_key_pair_parameter, id, parameter_type)
+                                    .map_err(|err| GetKeyPairPublicParameterError::Api { err })?;
                             let parameter_value = base64::encode(&parameter_value);
                             Ok(parameter_value)
                         }
@@ -436,9 +855,109 @@ impl std::fmt::Display for GetKeyPairPublicParameterError {
 
 impl std::error::Error for GetKeyPairPublicParameterError {}
 
+impl Keys {
+    pub(crate) fn get_key_parameter(
+        &self,
+        id: &std::ffi::CStr,
+        parameter_name: &str,
+    ) -> Result<String, GetKeyParameterError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 {
+                    get_key_parameter, ..
+                } => {
+                    let get_key_parameter = supported(*get_key_parameter, "get_key_parameter")
+                        .map_err(|err| GetKeyParameterError::Api { err })?;
+
+                    let parameter_type = match parameter_name {
+                        "usage" => sys::AZIOT_KEYS_KEY_PARAMETER_TYPE_USAGE,
+                        "origin" => sys::AZIOT_KEYS_KEY_PARAMETER_TYPE_ORIGIN,
+                        "creation-time" => sys::AZIOT_KEYS_KEY_PARAMETER_TYPE_CREATION_TIME,
+                        _ => {
+                            return Err(GetKeyParameterError::Api {
+                                err: KeysRawError(sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER),
+                            })
+                        }
+                    };
+
+                    let value = get_parameter_byte_buf(get_key_parameter, id, parameter_type)
+                        .map_err(|err| GetKeyParameterError::Api { err })?;
+
+                    let parameter_value = match parameter_type {
+                        sys::AZIOT_KEYS_KEY_PARAMETER_TYPE_USAGE => {
+                            let usage = std::convert::TryInto::try_into(&value[..])
+                                .map(u32::from_ne_bytes)
+                                .map_err(|_| GetKeyParameterError::MalformedValue { value })?;
+
+                            let mut parameter_value = vec![];
+                            if usage & sys::AZIOT_KEYS_KEY_USAGE_DERIVE != 0 {
+                                // DERIVE and SIGN are the same constant
+                                parameter_value.push("derive");
+                                parameter_value.push("sign");
+                            }
+                            if usage & sys::AZIOT_KEYS_KEY_USAGE_ENCRYPT != 0 {
+                                parameter_value.push("encrypt");
+                            }
+                            parameter_value.join(",")
+                        }
+
+                        sys::AZIOT_KEYS_KEY_PARAMETER_TYPE_ORIGIN => {
+                            let origin = std::convert::TryInto::try_into(&value[..])
+                                .map(u32::from_ne_bytes)
+                                .map_err(|_| GetKeyParameterError::MalformedValue {
+                                    value: value.clone(),
+                                })?;
+
+                            match origin {
+                                sys::AZIOT_KEYS_KEY_ORIGIN_UNKNOWN => "unknown".to_owned(),
+                                sys::AZIOT_KEYS_KEY_ORIGIN_GENERATED => "generated".to_owned(),
+                                sys::AZIOT_KEYS_KEY_ORIGIN_IMPORTED => "imported".to_owned(),
+                                _ => return Err(GetKeyParameterError::MalformedValue { value }),
+                            }
+                        }
+
+                        sys::AZIOT_KEYS_KEY_PARAMETER_TYPE_CREATION_TIME => {
+                            let creation_time = std::convert::TryInto::try_into(&value[..])
+                                .map(u64::from_ne_bytes)
+                                .map_err(|_| GetKeyParameterError::MalformedValue { value })?;
+                            creation_time.to_string()
+                        }
+
+                        _ => unreachable!(),
+                    };
+                    Ok(parameter_value)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GetKeyParameterError {
+    Api { err: KeysRawError },
+    MalformedValue { value: Vec<u8> },
+}
+
+impl std::fmt::Display for GetKeyParameterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GetKeyParameterError::Api { err } => {
+                write!(f, "could not get key parameter: {}", err)
+            }
+            GetKeyParameterError::MalformedValue { value } => write!(
+                f,
+                "could not get key parameter: library returned malformed value {:?}",
+                value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GetKeyParameterError {}
+
 impl Keys {
     pub(crate) fn create_key_if_not_exists(
-        &mut self,
+        &self,
         id: &std::ffi::CStr,
         usage: sys::AZIOT_KEYS_KEY_USAGE,
     ) -> Result<(), CreateKeyIfNotExistsError> {
@@ -472,7 +991,7 @@ impl std::fmt::Display for CreateKeyIfNotExistsError {
 impl std::error::Error for CreateKeyIfNotExistsError {}
 
 impl Keys {
-    pub(crate) fn load_key(&mut self, id: &std::ffi::CStr) -> Result<(), LoadKeyError> {
+    pub(crate) fn load_key(&self, id: &std::ffi::CStr) -> Result<(), LoadKeyError> {
         unsafe {
             match self {
                 Keys::V2_0_0_0 { load_key, .. } => {
@@ -500,7 +1019,7 @@ impl std::error::Error for LoadKeyError {}
 
 impl Keys {
     pub(crate) fn import_key(
-        &mut self,
+        &self,
         id: &std::ffi::CStr,
         bytes: &[u8],
         usage: sys::AZIOT_KEYS_KEY_USAGE,
@@ -518,6 +1037,38 @@ impl Keys {
     }
 }
 
+impl Keys {
+    pub(crate) fn import_key_if_not_exists(
+        &self,
+        id: &std::ffi::CStr,
+        bytes: &[u8],
+        usage: sys::AZIOT_KEYS_KEY_USAGE,
+    ) -> Result<(), ImportKeyError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 {
+                    import_key_if_not_exists,
+                    ..
+                } => {
+                    let import_key_if_not_exists =
+                        supported(*import_key_if_not_exists, "import_key_if_not_exists")
+                            .map_err(|err| ImportKeyError { err })?;
+
+                    keys_ok(import_key_if_not_exists(
+                        id.as_ptr(),
+                        bytes.as_ptr(),
+                        bytes.len(),
+                        usage,
+                    ))
+                    .map_err(|err| ImportKeyError { err })?;
+
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ImportKeyError {
     pub err: KeysRawError,
@@ -531,29 +1082,92 @@ impl std::fmt::Display for ImportKeyError {
 
 impl std::error::Error for ImportKeyError {}
 
+impl Keys {
+    pub(crate) fn delete_key(&self, id: &std::ffi::CStr) -> Result<(), DeleteKeyError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 { delete_key, .. } => {
+                    let delete_key = supported(*delete_key, "delete_key")
+                        .map_err(|err| DeleteKeyError { err })?;
+
+                    keys_ok(delete_key(id.as_ptr())).map_err(|err| DeleteKeyError { err })?;
+
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DeleteKeyError {
+    pub err: KeysRawError,
+}
+
+impl std::fmt::Display for DeleteKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not delete key: {}", self.err)
+    }
+}
+
+impl std::error::Error for DeleteKeyError {}
+
 impl Keys {
     pub(crate) fn derive_key(
-        &mut self,
+        &self,
         base_id: &std::ffi::CStr,
+        mechanism: sys::AZIOT_KEYS_KEY_DERIVATION_MECHANISM,
+        parameters: *const std::ffi::c_void,
         derivation_data: &[u8],
     ) -> Result<Vec<u8>, DeriveKeyError> {
         unsafe {
             match self {
-                Keys::V2_0_0_0 { derive_key, .. } => {
+                Keys::V2_0_0_0 {
+                    derive_key,
+                    derive_key_with_mechanism,
+                    ..
+                } => {
+                    // Libraries that implement an API version before 2.1.0.0 can only derive keys with HMAC-SHA256, using `derive_key`.
+                    let derive_key_with_mechanism =
+                        if mechanism == sys::AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256 {
+                            *derive_key_with_mechanism
+                        } else {
+                            Some(
+                                supported(*derive_key_with_mechanism, "derive_key_with_mechanism")
+                                    .map_err(|err| DeriveKeyError { err })?,
+                            )
+                        };
+
                     let derivation_data_len =
                         std::convert::TryInto::try_into(derivation_data.len())
                             .expect("usize -> c_ulong");
 
+                    let derive = |derived_key: *mut std::os::raw::c_uchar,
+                                  derived_key_len: &mut usize| {
+                        let result = match derive_key_with_mechanism {
+                            Some(derive_key_with_mechanism) => derive_key_with_mechanism(
+                                base_id.as_ptr(),
+                                mechanism,
+                                parameters,
+                                derivation_data.as_ptr(),
+                                derivation_data_len,
+                                derived_key,
+                                derived_key_len,
+                            ),
+                            None => derive_key(
+                                base_id.as_ptr(),
+                                derivation_data.as_ptr(),
+                                derivation_data_len,
+                                derived_key,
+                                derived_key_len,
+                            ),
+                        };
+                        keys_ok(result).map_err(|err| DeriveKeyError { err })
+                    };
+
                     let mut derived_key_len = 0;
 
-                    keys_ok(derive_key(
-                        base_id.as_ptr(),
-                        derivation_data.as_ptr(),
-                        derivation_data_len,
-                        std::ptr::null_mut(),
-                        &mut derived_key_len,
-                    ))
-                    .map_err(|err| DeriveKeyError { err })?;
+                    derive(std::ptr::null_mut(), &mut derived_key_len)?;
 
                     let mut derived_key = {
                         let derived_key_len = std::convert::TryInto::try_into(derived_key_len)
@@ -561,14 +1175,7 @@ impl Keys {
                         vec![0_u8; derived_key_len]
                     };
 
-                    keys_ok(derive_key(
-                        base_id.as_ptr(),
-                        derivation_data.as_ptr(),
-                        derivation_data_len,
-                        derived_key.as_mut_ptr(),
-                        &mut derived_key_len,
-                    ))
-                    .map_err(|err| DeriveKeyError { err })?;
+                    derive(derived_key.as_mut_ptr(), &mut derived_key_len)?;
 
                     let derived_key_len =
                         std::convert::TryInto::try_into(derived_key_len).expect("c_ulong -> usize");
@@ -602,7 +1209,7 @@ impl std::error::Error for DeriveKeyError {}
 
 impl Keys {
     pub(crate) fn sign(
-        &mut self,
+        &self,
         id: &std::ffi::CStr,
         mechanism: sys::AZIOT_KEYS_SIGN_MECHANISM,
         parameters: *const std::ffi::c_void,
@@ -676,7 +1283,7 @@ impl std::error::Error for SignError {}
 
 impl Keys {
     pub(crate) fn verify(
-        &mut self,
+        &self,
         id: &std::ffi::CStr,
         mechanism: sys::AZIOT_KEYS_SIGN_MECHANISM,
         parameters: *const std::ffi::c_void,
@@ -728,7 +1335,7 @@ impl std::error::Error for VerifyError {}
 
 impl Keys {
     pub(crate) fn encrypt(
-        &mut self,
+        &self,
         id: &std::ffi::CStr,
         mechanism: sys::AZIOT_KEYS_ENCRYPT_MECHANISM,
         parameters: *const std::ffi::c_void,
@@ -802,7 +1409,7 @@ impl std::error::Error for EncryptError {}
 
 impl Keys {
     pub(crate) fn decrypt(
-        &mut self,
+        &self,
         id: &std::ffi::CStr,
         mechanism: sys::AZIOT_KEYS_ENCRYPT_MECHANISM,
         parameters: *const std::ffi::c_void,
@@ -874,35 +1481,378 @@ impl std::fmt::Display for DecryptError {
 
 impl std::error::Error for DecryptError {}
 
-fn keys_ok(result: sys::AZIOT_KEYS_RC) -> Result<(), KeysRawError> {
-    match result {
-        sys::AZIOT_KEYS_RC_OK => Ok(()),
-        err => Err(KeysRawError(err)),
-    }
-}
-
-unsafe fn get_key_pair_parameter_byte_buf(
-    get_key_pair_parameter: unsafe extern "C" fn(
-        id: *const std::os::raw::c_char,
-        r#type: sys::AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE,
-        value: *mut std::os::raw::c_uchar,
-        value_len: *mut usize,
-    ) -> sys::AZIOT_KEYS_RC,
-    id: &std::ffi::CStr,
-    r#type: sys::AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE,
-) -> Result<Vec<u8>, KeysRawError> {
-    let mut value_len: usize = 0;
+impl Keys {
+    pub(crate) fn enumerate_keys(
+        &self,
+    ) -> Result<Vec<aziot_key_common::KeyInfo>, EnumerateKeysError> {
+        unsafe extern "C" fn callback(
+            context: *mut std::ffi::c_void,
+            info: *const sys::AZIOT_KEYS_KEY_INFO,
+        ) {
+            let result = &mut *context.cast::<Vec<aziot_key_common::KeyInfo>>();
+            let info = &*info;
+
+            let id = std::ffi::CStr::from_ptr(info.id)
+                .to_string_lossy()
+                .into_owned();
+            let location = std::ffi::CStr::from_ptr(info.location)
+                .to_string_lossy()
+                .into_owned();
+
+            let (kind, algorithm) = if info.kind == sys::AZIOT_KEYS_KEY_KIND_KEY_PAIR {
+                let algorithm = match info.algorithm {
+                    sys::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_EC => Some("ECDSA"),
+                    sys::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_RSA => Some("RSA"),
+                    sys::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_ED25519 => Some("ED25519"),
+                    _ => None,
+                };
+                (aziot_key_common::KeyKind::KeyPair, algorithm)
+            } else {
+                // libaziot-keys uses AES for encryption keys and HMAC-SHA256 for all other keys.
+                let algorithm = if info.usage & sys::AZIOT_KEYS_KEY_USAGE_ENCRYPT != 0 {
+                    Some("AES")
+                } else if info.usage & sys::AZIOT_KEYS_KEY_USAGE_DERIVE != 0 {
+                    Some("HMAC-SHA256")
+                } else {
+                    None
+                };
+                (aziot_key_common::KeyKind::Key, algorithm)
+            };
 
-    keys_ok(get_key_pair_parameter(
-        id.as_ptr(),
-        r#type,
-        std::ptr::null_mut(),
-        &mut value_len,
-    ))?;
+            let mut usage = vec![];
+            if info.usage & sys::AZIOT_KEYS_KEY_USAGE_DERIVE != 0 {
+                // DERIVE and SIGN are the same flag
+                usage.push(aziot_key_common::KeyUsage::Derive);
+                usage.push(aziot_key_common::KeyUsage::Sign);
+            }
+            if info.usage & sys::AZIOT_KEYS_KEY_USAGE_ENCRYPT != 0 {
+                usage.push(aziot_key_common::KeyUsage::Encrypt);
+            }
 
-    let mut value = vec![0_u8; value_len];
+            result.push(aziot_key_common::KeyInfo {
+                id,
+                kind,
+                algorithm: algorithm.map(ToOwned::to_owned),
+                usage,
+                location,
+                preloaded: info.preloaded != 0,
+            });
+        }
 
-    keys_ok(get_key_pair_parameter(
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 { enumerate_keys, .. } => {
+                    let enumerate_keys = supported(*enumerate_keys, "enumerate_keys")
+                        .map_err(|err| EnumerateKeysError { err })?;
+
+                    let mut result: Vec<aziot_key_common::KeyInfo> = vec![];
+
+                    keys_ok(enumerate_keys(
+                        Some(callback),
+                        (&mut result as *mut Vec<aziot_key_common::KeyInfo>).cast(),
+                    ))
+                    .map_err(|err| EnumerateKeysError { err })?;
+
+                    Ok(result)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EnumerateKeysError {
+    pub err: KeysRawError,
+}
+
+impl std::fmt::Display for EnumerateKeysError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not enumerate keys: {}", self.err)
+    }
+}
+
+impl std::error::Error for EnumerateKeysError {}
+
+impl Keys {
+    pub(crate) fn export_key(
+        &self,
+        id: &std::ffi::CStr,
+        mechanism: sys::AZIOT_KEYS_WRAP_MECHANISM,
+        wrapping_key: &[u8],
+    ) -> Result<Vec<u8>, ExportKeyError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 { export_key, .. } => {
+                    let export_key = supported(*export_key, "export_key")
+                        .map_err(|err| ExportKeyError { err })?;
+
+                    let mut wrapped_key_len = 0;
+
+                    keys_ok(export_key(
+                        id.as_ptr(),
+                        mechanism,
+                        wrapping_key.as_ptr(),
+                        wrapping_key.len(),
+                        std::ptr::null_mut(),
+                        &mut wrapped_key_len,
+                    ))
+                    .map_err(|err| ExportKeyError { err })?;
+
+                    let mut wrapped_key = vec![0_u8; wrapped_key_len];
+
+                    keys_ok(export_key(
+                        id.as_ptr(),
+                        mechanism,
+                        wrapping_key.as_ptr(),
+                        wrapping_key.len(),
+                        wrapped_key.as_mut_ptr(),
+                        &mut wrapped_key_len,
+                    ))
+                    .map_err(|err| ExportKeyError { err })?;
+
+                    if wrapped_key_len > wrapped_key.len() {
+                        // libaziot-keys scribbled past the end of the buffer. Crash as soon as possible.
+                        std::process::abort();
+                    }
+
+                    wrapped_key.truncate(wrapped_key_len);
+
+                    Ok(wrapped_key)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ExportKeyError {
+    pub err: KeysRawError,
+}
+
+impl std::fmt::Display for ExportKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not export key: {}", self.err)
+    }
+}
+
+impl std::error::Error for ExportKeyError {}
+
+impl Keys {
+    pub(crate) fn export_key_pair(
+        &self,
+        id: &std::ffi::CStr,
+        mechanism: sys::AZIOT_KEYS_WRAP_MECHANISM,
+        wrapping_key: &[u8],
+    ) -> Result<Vec<u8>, ExportKeyPairError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 {
+                    export_key_pair, ..
+                } => {
+                    let export_key_pair = supported(*export_key_pair, "export_key_pair")
+                        .map_err(|err| ExportKeyPairError { err })?;
+
+                    let mut wrapped_key_len = 0;
+
+                    keys_ok(export_key_pair(
+                        id.as_ptr(),
+                        mechanism,
+                        wrapping_key.as_ptr(),
+                        wrapping_key.len(),
+                        std::ptr::null_mut(),
+                        &mut wrapped_key_len,
+                    ))
+                    .map_err(|err| ExportKeyPairError { err })?;
+
+                    let mut wrapped_key = vec![0_u8; wrapped_key_len];
+
+                    keys_ok(export_key_pair(
+                        id.as_ptr(),
+                        mechanism,
+                        wrapping_key.as_ptr(),
+                        wrapping_key.len(),
+                        wrapped_key.as_mut_ptr(),
+                        &mut wrapped_key_len,
+                    ))
+                    .map_err(|err| ExportKeyPairError { err })?;
+
+                    if wrapped_key_len > wrapped_key.len() {
+                        // libaziot-keys scribbled past the end of the buffer. Crash as soon as possible.
+                        std::process::abort();
+                    }
+
+                    wrapped_key.truncate(wrapped_key_len);
+
+                    Ok(wrapped_key)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ExportKeyPairError {
+    pub err: KeysRawError,
+}
+
+impl std::fmt::Display for ExportKeyPairError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not export key pair: {}", self.err)
+    }
+}
+
+impl std::error::Error for ExportKeyPairError {}
+
+impl Keys {
+    pub(crate) fn import_wrapped_key(
+        &self,
+        id: &std::ffi::CStr,
+        unwrapping_key_pair_id: &std::ffi::CStr,
+        mechanism: sys::AZIOT_KEYS_WRAP_MECHANISM,
+        wrapped_key: &[u8],
+    ) -> Result<(), ImportWrappedKeyError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 {
+                    import_wrapped_key, ..
+                } => {
+                    let import_wrapped_key = supported(*import_wrapped_key, "import_wrapped_key")
+                        .map_err(|err| ImportWrappedKeyError { err })?;
+
+                    keys_ok(import_wrapped_key(
+                        id.as_ptr(),
+                        unwrapping_key_pair_id.as_ptr(),
+                        mechanism,
+                        wrapped_key.as_ptr(),
+                        wrapped_key.len(),
+                    ))
+                    .map_err(|err| ImportWrappedKeyError { err })?;
+
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ImportWrappedKeyError {
+    pub err: KeysRawError,
+}
+
+impl std::fmt::Display for ImportWrappedKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not import wrapped key: {}", self.err)
+    }
+}
+
+impl std::error::Error for ImportWrappedKeyError {}
+
+impl Keys {
+    pub(crate) fn import_wrapped_key_pair(
+        &self,
+        id: &std::ffi::CStr,
+        unwrapping_key_pair_id: &std::ffi::CStr,
+        mechanism: sys::AZIOT_KEYS_WRAP_MECHANISM,
+        wrapped_key: &[u8],
+    ) -> Result<(), ImportWrappedKeyPairError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 {
+                    import_wrapped_key_pair,
+                    ..
+                } => {
+                    let import_wrapped_key_pair =
+                        supported(*import_wrapped_key_pair, "import_wrapped_key_pair")
+                            .map_err(|err| ImportWrappedKeyPairError { err })?;
+
+                    keys_ok(import_wrapped_key_pair(
+                        id.as_ptr(),
+                        unwrapping_key_pair_id.as_ptr(),
+                        mechanism,
+                        wrapped_key.as_ptr(),
+                        wrapped_key.len(),
+                    ))
+                    .map_err(|err| ImportWrappedKeyPairError { err })?;
+
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ImportWrappedKeyPairError {
+    pub err: KeysRawError,
+}
+
+impl std::fmt::Display for ImportWrappedKeyPairError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not import wrapped key pair: {}", self.err)
+    }
+}
+
+impl std::error::Error for ImportWrappedKeyPairError {}
+
+/// Returns the given function, or an `INVALID_PARAMETER` error if the library implements an API version that predates it.
+fn supported<F>(function: Option<F>, name: &str) -> Result<F, KeysRawError> {
+    function.ok_or_else(|| {
+        log::warn!(
+            "libaziot-keys does not support {} because it implements an older API version",
+            name
+        );
+        KeysRawError::INVALID_PARAMETER
+    })
+}
+
+// The function list of each API version starts with the function list of the previous version,
+// so the pointer can be used as a pointer to the function list of any version up to `api_version`.
+//
+// AZIOT_KEYS_FUNCTION_LIST has looser alignment than these, but the pointer comes from the library itself,
+// so it will be correctly aligned already.
+#[allow(clippy::cast_ptr_alignment)]
+fn function_list_of<T>(
+    function_list: *const sys::AZIOT_KEYS_FUNCTION_LIST,
+    api_version: sys::AZIOT_KEYS_VERSION,
+    min_api_version: sys::AZIOT_KEYS_VERSION,
+) -> Option<*const T> {
+    if api_version >= min_api_version {
+        Some(function_list.cast())
+    } else {
+        None
+    }
+}
+
+fn keys_ok(result: sys::AZIOT_KEYS_RC) -> Result<(), KeysRawError> {
+    match result {
+        sys::AZIOT_KEYS_RC_OK => Ok(()),
+        err => Err(KeysRawError(err)),
+    }
+}
+
+unsafe fn get_parameter_byte_buf(
+    get_parameter: unsafe extern "C" fn(
+        id: *const std::os::raw::c_char,
+        r#type: sys::AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE,
+        value: *mut std::os::raw::c_uchar,
+        value_len: *mut usize,
+    ) -> sys::AZIOT_KEYS_RC,
+    id: &std::ffi::CStr,
+    r#type: sys::AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE,
+) -> Result<Vec<u8>, KeysRawError> {
+    let mut value_len: usize = 0;
+
+    keys_ok(get_parameter(
+        id.as_ptr(),
+        r#type,
+        std::ptr::null_mut(),
+        &mut value_len,
+    ))?;
+
+    let mut value = vec![0_u8; value_len];
+
+    keys_ok(get_parameter(
         id.as_ptr(),
         r#type,
         value.as_mut_ptr(),
@@ -932,3 +1882,248 @@ pub(crate) mod sys {
 
     include!("keys.generated.rs");
 }
+
+impl Keys {
+    pub(crate) fn derive_shared_secret(
+        &self,
+        id: &std::ffi::CStr,
+        mechanism: sys::AZIOT_KEYS_KEY_AGREEMENT_MECHANISM,
+        parameters: *const std::ffi::c_void,
+        peer_public_key: &[u8],
+    ) -> Result<Vec<u8>, DeriveSharedSecretError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 {
+                    derive_shared_secret,
+                    ..
+                } => {
+                    let derive_shared_secret =
+                        supported(*derive_shared_secret, "derive_shared_secret")
+                            .map_err(|err| DeriveSharedSecretError { err })?;
+
+                    let mut secret_len = 0;
+
+                    keys_ok(derive_shared_secret(
+                        id.as_ptr(),
+                        mechanism,
+                        parameters,
+                        peer_public_key.as_ptr(),
+                        peer_public_key.len(),
+                        std::ptr::null_mut(),
+                        &mut secret_len,
+                    ))
+                    .map_err(|err| DeriveSharedSecretError { err })?;
+
+                    let mut secret = vec![0_u8; secret_len];
+
+                    keys_ok(derive_shared_secret(
+                        id.as_ptr(),
+                        mechanism,
+                        parameters,
+                        peer_public_key.as_ptr(),
+                        peer_public_key.len(),
+                        secret.as_mut_ptr(),
+                        &mut secret_len,
+                    ))
+                    .map_err(|err| DeriveSharedSecretError { err })?;
+
+                    if secret_len > secret.len() {
+                        // libaziot-keys scribbled past the end of the buffer. Crash as soon as possible.
+                        std::process::abort();
+                    }
+
+                    secret.truncate(secret_len);
+
+                    Ok(secret)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DeriveSharedSecretError {
+    pub err: KeysRawError,
+}
+
+impl std::fmt::Display for DeriveSharedSecretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not derive shared secret: {}", self.err)
+    }
+}
+
+impl std::error::Error for DeriveSharedSecretError {}
+
+impl Keys {
+    pub(crate) fn encrypt_init(
+        &self,
+        id: &std::ffi::CStr,
+        mechanism: sys::AZIOT_KEYS_ENCRYPT_MECHANISM,
+        parameters: *const std::ffi::c_void,
+    ) -> Result<Stream, StreamError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 {
+                    encrypt_init,
+                    stream_functions,
+                    ..
+                } => {
+                    let encrypt_init = supported(*encrypt_init, "encrypt_init")
+                        .map_err(|err| StreamError { err })?;
+                    let functions = supported(*stream_functions, "stream functions")
+                        .map_err(|err| StreamError { err })?;
+
+                    let mut raw = std::ptr::null_mut();
+
+                    keys_ok(encrypt_init(id.as_ptr(), mechanism, parameters, &mut raw))
+                        .map_err(|err| StreamError { err })?;
+
+                    Ok(Stream { raw, functions })
+                }
+            }
+        }
+    }
+
+    pub(crate) fn decrypt_init(
+        &self,
+        id: &std::ffi::CStr,
+        mechanism: sys::AZIOT_KEYS_ENCRYPT_MECHANISM,
+        parameters: *const std::ffi::c_void,
+    ) -> Result<Stream, StreamError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 {
+                    decrypt_init,
+                    stream_functions,
+                    ..
+                } => {
+                    let decrypt_init = supported(*decrypt_init, "decrypt_init")
+                        .map_err(|err| StreamError { err })?;
+                    let functions = supported(*stream_functions, "stream functions")
+                        .map_err(|err| StreamError { err })?;
+
+                    let mut raw = std::ptr::null_mut();
+
+                    keys_ok(decrypt_init(id.as_ptr(), mechanism, parameters, &mut raw))
+                        .map_err(|err| StreamError { err })?;
+
+                    Ok(Stream { raw, functions })
+                }
+            }
+        }
+    }
+
+    pub(crate) fn sign_init(
+        &self,
+        id: &std::ffi::CStr,
+        mechanism: sys::AZIOT_KEYS_SIGN_MECHANISM,
+        parameters: *const std::ffi::c_void,
+    ) -> Result<Stream, StreamError> {
+        unsafe {
+            match self {
+                Keys::V2_0_0_0 {
+                    sign_init,
+                    stream_functions,
+                    ..
+                } => {
+                    let sign_init =
+                        supported(*sign_init, "sign_init").map_err(|err| StreamError { err })?;
+                    let functions = supported(*stream_functions, "stream functions")
+                        .map_err(|err| StreamError { err })?;
+
+                    let mut raw = std::ptr::null_mut();
+
+                    keys_ok(sign_init(id.as_ptr(), mechanism, parameters, &mut raw))
+                        .map_err(|err| StreamError { err })?;
+
+                    Ok(Stream { raw, functions })
+                }
+            }
+        }
+    }
+}
+
+/// A multi-part operation started by [`Keys::encrypt_init`], [`Keys::decrypt_init`] or [`Keys::sign_init`].
+///
+/// Dropping the stream frees it, abandoning the operation if it was not completed.
+pub(crate) struct Stream {
+    raw: *mut sys::AZIOT_KEYS_STREAM,
+    functions: StreamFunctions,
+}
+
+impl Stream {
+    /// Passes the next part of the input to the stream, and returns the output produced so far.
+    pub(crate) fn update(&mut self, data: &[u8]) -> Result<Vec<u8>, StreamError> {
+        unsafe {
+            keys_ok((self.functions.update)(self.raw, data.as_ptr(), data.len()))
+                .map_err(|err| StreamError { err })?;
+        }
+
+        self.read()
+    }
+
+    /// Completes the stream's operation, and returns the remaining output.
+    pub(crate) fn finish(&mut self) -> Result<Vec<u8>, StreamError> {
+        unsafe {
+            keys_ok((self.functions.finish)(self.raw)).map_err(|err| StreamError { err })?;
+        }
+
+        self.read()
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>, StreamError> {
+        unsafe {
+            let mut output_len = 0;
+
+            keys_ok((self.functions.read)(
+                self.raw,
+                std::ptr::null_mut(),
+                &mut output_len,
+            ))
+            .map_err(|err| StreamError { err })?;
+
+            let mut output = vec![0_u8; output_len];
+
+            keys_ok((self.functions.read)(
+                self.raw,
+                output.as_mut_ptr(),
+                &mut output_len,
+            ))
+            .map_err(|err| StreamError { err })?;
+
+            if output_len > output.len() {
+                // libaziot-keys scribbled past the end of the buffer. Crash as soon as possible.
+                std::process::abort();
+            }
+
+            output.truncate(output_len);
+
+            Ok(output)
+        }
+    }
+}
+
+// A stream is not tied to the thread that created it. It is only ever used through `&mut Stream`,
+// so it is never used by two threads at the same time.
+unsafe impl Send for Stream {}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        unsafe {
+            (self.functions.free)(self.raw);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StreamError {
+    pub err: KeysRawError,
+}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not process multi-part operation: {}", self.err)
+    }
+}
+
+impl std::error::Error for StreamError {}
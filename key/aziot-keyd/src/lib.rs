@@ -16,6 +16,8 @@ pub use error::{Error, InternalError};
 
 mod keys;
 
+mod backend;
+
 mod http;
 
 use aziot_keyd_config::{Config, Endpoints, Principal};
@@ -28,8 +30,11 @@ pub async fn main(
     config_directory_path: std::path::PathBuf,
 ) -> Result<(http_common::Connector, http::Service), Box<dyn std::error::Error>> {
     let Config {
+        backend,
         aziot_keys,
         preloaded_keys,
+        handle_lifetime_secs,
+        max_concurrency,
         endpoints: Endpoints {
             aziot_keyd: connector,
         },
@@ -37,53 +42,48 @@ pub async fn main(
     } = config;
 
     let api = {
-        let mut keys = keys::Keys::new()?;
+        let mut keys = backend::new(backend)?;
 
         for (name, value) in aziot_keys {
-            let name = std::ffi::CString::new(name.clone()).map_err(|err| {
-                Error::Internal(InternalError::ReadConfig(
-                    format!(
-                        "key {:?} in [aziot_keys] section of the configuration could not be converted to a C string: {}",
-                        name, err,
-                    )
-                    .into(),
-                ))
-            })?;
-
-            let value =
-                std::ffi::CString::new(value).map_err(|err| Error::Internal(InternalError::ReadConfig(format!(
-                    "value of key {:?} in [aziot_keys] section of the configuration could not be converted to a C string: {}",
-                    name, err,
-                ).into())))?;
-
             keys.set_parameter(&name, &value)?;
         }
 
         for (key_id, value) in preloaded_keys {
-            let name = format!("preloaded_key:{}", key_id);
-            let name =
-                std::ffi::CString::new(name).map_err(|err| Error::Internal(InternalError::ReadConfig(format!(
-                    "key ID {:?} in [preloaded_keys] section of the configuration could not be converted to a C string: {}",
-                    key_id, err,
-                ).into())))?;
-
-            let value =
-                std::ffi::CString::new(value).map_err(|err| Error::Internal(InternalError::ReadConfig(format!(
-                    "location of key ID {:?} in [preloaded_keys] section of the configuration could not be converted to a C string: {}",
-                    key_id, err,
-                ).into())))?;
-
-            keys.set_parameter(&name, &value)?;
+            keys.set_parameter(&format!("preloaded_key:{}", key_id), &value)?;
         }
 
+        let handle_validation =
+            HandleValidation::new(&*keys, handle_lifetime(handle_lifetime_secs))?;
+
+        // A backend that is not thread-safe must only ever be called from one worker at a time.
+        let max_concurrency = if keys.is_thread_safe() {
+            max_concurrency.map_or(
+                aziot_keyd_config::DEFAULT_MAX_CONCURRENCY,
+                std::num::NonZeroUsize::get,
+            )
+        } else {
+            1
+        };
+        log::info!(
+            "Processing up to {} requests concurrently.",
+            max_concurrency
+        );
+
         Api {
             keys,
-            principals: principal_to_map(principal),
+            principals: std::sync::RwLock::new(principal_to_map(principal)),
+            handle_validation: std::sync::RwLock::new(handle_validation),
+            workers: std::sync::Arc::new(tokio::sync::Semaphore::new(max_concurrency)),
+            streams: Default::default(),
         }
     };
-    let api = std::sync::Arc::new(futures_util::lock::Mutex::new(api));
+    let api = std::sync::Arc::new(api);
 
-    config_common::watcher::start_watcher(config_path, config_directory_path, api.clone());
+    config_common::watcher::start_watcher(
+        config_path,
+        config_directory_path,
+        std::sync::Arc::new(futures_util::lock::Mutex::new(ApiConfig(api.clone()))),
+    );
 
     let service = http::Service { api };
 
@@ -91,149 +91,253 @@ pub async fn main(
 }
 
 struct Api {
-    keys: keys::Keys,
-    principals: std::collections::BTreeMap<libc::uid_t, Vec<wildmatch::WildMatch>>,
+    keys: Box<dyn backend::KeyBackend>,
+    principals: std::sync::RwLock<Principals>,
+    handle_validation: std::sync::RwLock<HandleValidation>,
+
+    /// Bounds the number of operations that run concurrently. See [`Api::run`]
+    workers: std::sync::Arc<tokio::sync::Semaphore>,
+
+    /// Multi-part operations that have been started but not yet completed or aborted, keyed by stream ID.
+    streams: std::sync::Mutex<std::collections::BTreeMap<String, std::sync::Arc<OpenStream>>>,
 }
 
 impl Api {
     pub fn create_key_pair_if_not_exists(
-        &mut self,
+        &self,
         id: &str,
         preferred_algorithms: Option<&str>,
         user: libc::uid_t,
     ) -> Result<aziot_key_common::KeyHandle, Error> {
+        check_not_reserved(id)?;
+
         if !self.authorize(user, id) {
             return Err(Error::Unauthorized(user, id.to_owned()));
         }
 
-        let id_cstr = std::ffi::CString::new(id.to_owned())
-            .map_err(|err| Error::invalid_parameter("id", err))?;
-        let preferred_algorithms = preferred_algorithms
-            .map(|preferred_algorithms| std::ffi::CString::new(preferred_algorithms.to_owned()))
-            .transpose()
-            .map_err(|err| Error::invalid_parameter("preferred_algorithms", err))?;
-        self.keys.create_key_pair_if_not_exists(
-            &id_cstr,
-            preferred_algorithms.as_ref().map(AsRef::as_ref),
+        self.keys
+            .create_key_pair_if_not_exists(id, preferred_algorithms)?;
+
+        let handle = key_id_to_handle(
+            &KeyId::KeyPair(id.into()),
+            &*self.keys,
+            &self.handle_validation(),
         )?;
+        Ok(handle)
+    }
+
+    pub fn import_key_pair(
+        &self,
+        id: &str,
+        bytes: &[u8],
+        format: aziot_key_common::KeyPairFormat,
+        password: Option<&str>,
+        user: libc::uid_t,
+    ) -> Result<aziot_key_common::KeyHandle, Error> {
+        check_not_reserved(id)?;
+
+        if !self.authorize(user, id) {
+            return Err(Error::Unauthorized(user, id.to_owned()));
+        }
+
+        self.keys.import_key_pair(id, bytes, format, password)?;
 
-        let handle = key_id_to_handle(&KeyId::KeyPair(id.into()), &mut self.keys)?;
+        let handle = key_id_to_handle(
+            &KeyId::KeyPair(id.into()),
+            &*self.keys,
+            &self.handle_validation(),
+        )?;
         Ok(handle)
     }
 
     pub fn load_key_pair(
-        &mut self,
+        &self,
         id: &str,
         user: libc::uid_t,
     ) -> Result<aziot_key_common::KeyHandle, Error> {
+        check_not_reserved(id)?;
+
         if !self.authorize(user, id) {
             return Err(Error::Unauthorized(user, id.to_owned()));
         }
 
-        let id_cstr = std::ffi::CString::new(id.to_owned())
-            .map_err(|err| Error::invalid_parameter("id", err))?;
-        self.keys.load_key_pair(&id_cstr)?;
+        self.keys.load_key_pair(id)?;
 
-        let handle = key_id_to_handle(&KeyId::KeyPair(id.into()), &mut self.keys)?;
+        let handle = key_id_to_handle(
+            &KeyId::KeyPair(id.into()),
+            &*self.keys,
+            &self.handle_validation(),
+        )?;
         Ok(handle)
     }
 
+    pub fn delete_key_pair(&self, id: &str, user: libc::uid_t) -> Result<(), Error> {
+        check_not_reserved(id)?;
+
+        if !self.authorize(user, id) {
+            return Err(Error::Unauthorized(user, id.to_owned()));
+        }
+
+        self.keys.delete_key_pair(id)?;
+
+        Ok(())
+    }
+
+    /// Moves the key pair `from_id` to `to_id`, replacing the key pair with that ID if it exists.
+    ///
+    /// The user must be authorized for both IDs, since this deletes `from_id` and overwrites `to_id`.
+    pub fn move_key_pair(
+        &self,
+        from_id: &str,
+        to_id: &str,
+        user: libc::uid_t,
+    ) -> Result<(), Error> {
+        check_not_reserved(from_id)?;
+        check_not_reserved(to_id)?;
+
+        if !self.authorize(user, from_id) {
+            return Err(Error::Unauthorized(user, from_id.to_owned()));
+        }
+        if !self.authorize(user, to_id) {
+            return Err(Error::Unauthorized(user, to_id.to_owned()));
+        }
+
+        self.keys.move_key_pair(from_id, to_id)?;
+
+        Ok(())
+    }
+
     pub fn get_key_pair_public_parameter(
-        &mut self,
+        &self,
         handle: &aziot_key_common::KeyHandle,
         parameter_name: &str,
     ) -> Result<String, Error> {
-        let (_, id_cstr) = key_handle_to_id(handle, &mut self.keys)?;
+        let (_, id) = key_handle_to_id(handle, &*self.keys, &self.handle_validation())?;
 
         let parameter_value = self
             .keys
-            .get_key_pair_public_parameter(&id_cstr, parameter_name)?;
+            .get_key_pair_public_parameter(&id, parameter_name)?;
+        Ok(parameter_value)
+    }
+
+    pub fn get_key_parameter(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        parameter_name: &str,
+    ) -> Result<String, Error> {
+        let (key_id, id) = key_handle_to_id(handle, &*self.keys, &self.handle_validation())?;
+
+        // Derived keys have no parameters of their own.
+        if !matches!(key_id, KeyId::Key(_)) {
+            return Err(Error::invalid_parameter("handle", "not a key handle"));
+        }
+
+        let parameter_value = self.keys.get_key_parameter(&id, parameter_name)?;
         Ok(parameter_value)
     }
 
     pub fn create_key_if_not_exists(
-        &mut self,
+        &self,
         id: &str,
         value: aziot_key_common::CreateKeyValue,
         usage: &[aziot_key_common::KeyUsage],
         user: libc::uid_t,
     ) -> Result<aziot_key_common::KeyHandle, Error> {
+        check_not_reserved(id)?;
+
         if !self.authorize(user, id) {
             return Err(Error::Unauthorized(user, id.to_owned()));
         }
 
-        let id_cstr = std::ffi::CString::new(id.to_owned())
-            .map_err(|err| Error::invalid_parameter("id", err))?;
-
-        let mut usage_raw = 0;
-        for &usage in usage {
-            match usage {
-                aziot_key_common::KeyUsage::Derive => {
-                    usage_raw |= keys::sys::AZIOT_KEYS_KEY_USAGE_DERIVE;
-                }
-                aziot_key_common::KeyUsage::Encrypt => {
-                    usage_raw |= keys::sys::AZIOT_KEYS_KEY_USAGE_ENCRYPT;
-                }
-                aziot_key_common::KeyUsage::Sign => {
-                    usage_raw |= keys::sys::AZIOT_KEYS_KEY_USAGE_SIGN;
-                }
-            }
-        }
-
         match value {
             aziot_key_common::CreateKeyValue::Generate => {
-                self.keys.create_key_if_not_exists(&id_cstr, usage_raw)?;
+                self.keys.create_key_if_not_exists(id, usage)?;
             }
 
             aziot_key_common::CreateKeyValue::Import { bytes } => {
-                self.keys.import_key(&id_cstr, &bytes, usage_raw)?;
+                self.keys.import_key(id, &bytes, usage)?;
             }
         }
 
-        let handle = key_id_to_handle(&KeyId::Key(id.into()), &mut self.keys)?;
+        let handle = key_id_to_handle(
+            &KeyId::Key(id.into()),
+            &*self.keys,
+            &self.handle_validation(),
+        )?;
         Ok(handle)
     }
 
     pub fn load_key(
-        &mut self,
+        &self,
         id: &str,
         user: libc::uid_t,
     ) -> Result<aziot_key_common::KeyHandle, Error> {
+        check_not_reserved(id)?;
+
         if !self.authorize(user, id) {
             return Err(Error::Unauthorized(user, id.to_owned()));
         }
 
-        let id_cstr = std::ffi::CString::new(id.to_owned())
-            .map_err(|err| Error::invalid_parameter("id", err))?;
-        self.keys.load_key(&id_cstr)?;
+        self.keys.load_key(id)?;
 
-        let handle = key_id_to_handle(&KeyId::Key(id.into()), &mut self.keys)?;
+        let handle = key_id_to_handle(
+            &KeyId::Key(id.into()),
+            &*self.keys,
+            &self.handle_validation(),
+        )?;
         Ok(handle)
     }
 
+    pub fn delete_key(&self, id: &str, user: libc::uid_t) -> Result<(), Error> {
+        check_not_reserved(id)?;
+
+        if !self.authorize(user, id) {
+            return Err(Error::Unauthorized(user, id.to_owned()));
+        }
+
+        self.keys.delete_key(id)?;
+
+        Ok(())
+    }
+
+    pub fn list_keys(&self, user: libc::uid_t) -> Result<Vec<aziot_key_common::KeyInfo>, Error> {
+        let mut keys = self.keys.enumerate_keys()?;
+        keys.retain(|key| self.authorize(user, &key.id));
+        Ok(keys)
+    }
+
     pub fn create_derived_key(
-        &mut self,
+        &self,
         base_handle: &aziot_key_common::KeyHandle,
         derivation_data: &[u8],
+        mechanism: &aziot_key_common::KeyDerivationMechanism,
     ) -> Result<aziot_key_common::KeyHandle, Error> {
         let handle = key_id_to_handle(
             &KeyId::Derived(
                 std::borrow::Cow::Borrowed(base_handle),
                 derivation_data.into(),
+                std::borrow::Cow::Borrowed(mechanism),
             ),
-            &mut self.keys,
+            &*self.keys,
+            &self.handle_validation(),
         )?;
         Ok(handle)
     }
 
     pub fn export_derived_key(
-        &mut self,
+        &self,
         handle: &aziot_key_common::KeyHandle,
     ) -> Result<Vec<u8>, Error> {
-        let (id, id_cstr) = key_handle_to_id(handle, &mut self.keys)?;
-
-        let derived_key = if let KeyId::Derived(_, derivation_data) = id {
-            self.keys.derive_key(&id_cstr, &derivation_data)?
+        let (key_id, id) = key_handle_to_id(handle, &*self.keys, &self.handle_validation())?;
+
+        let derived_key = if let KeyId::Derived(_, derivation_data, mechanism) = key_id {
+            self.keys.derive_key(
+                &id,
+                &backend::Derivation {
+                    data: &derivation_data,
+                    mechanism: &mechanism,
+                },
+            )?
         } else {
             return Err(Error::invalid_parameter(
                 "handle",
@@ -243,42 +347,123 @@ impl Api {
         Ok(derived_key)
     }
 
+    pub fn export_key(
+        &self,
+        id: &str,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapping_key: &[u8],
+        user: libc::uid_t,
+    ) -> Result<Vec<u8>, Error> {
+        check_not_reserved(id)?;
+
+        if !self.authorize_export(user, id) {
+            return Err(Error::Unauthorized(user, id.to_owned()));
+        }
+
+        let wrapped_key = self.keys.export_key(id, mechanism, wrapping_key)?;
+
+        log::info!("User {} exported key {}.", user, id);
+
+        Ok(wrapped_key)
+    }
+
+    pub fn export_key_pair(
+        &self,
+        id: &str,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapping_key: &[u8],
+        user: libc::uid_t,
+    ) -> Result<Vec<u8>, Error> {
+        check_not_reserved(id)?;
+
+        if !self.authorize_export(user, id) {
+            return Err(Error::Unauthorized(user, id.to_owned()));
+        }
+
+        let wrapped_key = self.keys.export_key_pair(id, mechanism, wrapping_key)?;
+
+        log::info!("User {} exported key pair {}.", user, id);
+
+        Ok(wrapped_key)
+    }
+
+    pub fn import_wrapped_key(
+        &self,
+        id: &str,
+        unwrapping_key_handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapped_key: &[u8],
+        user: libc::uid_t,
+    ) -> Result<aziot_key_common::KeyHandle, Error> {
+        check_not_reserved(id)?;
+
+        if !self.authorize_export(user, id) {
+            return Err(Error::Unauthorized(user, id.to_owned()));
+        }
+
+        let unwrapping_key_pair_id = self.unwrapping_key_pair_id(unwrapping_key_handle)?;
+        self.keys
+            .import_wrapped_key(id, &unwrapping_key_pair_id, mechanism, wrapped_key)?;
+
+        log::info!("User {} imported wrapped key {}.", user, id);
+
+        let handle = key_id_to_handle(
+            &KeyId::Key(id.into()),
+            &*self.keys,
+            &self.handle_validation(),
+        )?;
+        Ok(handle)
+    }
+
+    pub fn import_wrapped_key_pair(
+        &self,
+        id: &str,
+        unwrapping_key_handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::WrapMechanism,
+        wrapped_key: &[u8],
+        user: libc::uid_t,
+    ) -> Result<aziot_key_common::KeyHandle, Error> {
+        check_not_reserved(id)?;
+
+        if !self.authorize_export(user, id) {
+            return Err(Error::Unauthorized(user, id.to_owned()));
+        }
+
+        let unwrapping_key_pair_id = self.unwrapping_key_pair_id(unwrapping_key_handle)?;
+        self.keys
+            .import_wrapped_key_pair(id, &unwrapping_key_pair_id, mechanism, wrapped_key)?;
+
+        log::info!("User {} imported wrapped key pair {}.", user, id);
+
+        let handle = key_id_to_handle(
+            &KeyId::KeyPair(id.into()),
+            &*self.keys,
+            &self.handle_validation(),
+        )?;
+        Ok(handle)
+    }
+
     pub fn sign(
-        &mut self,
+        &self,
         handle: &aziot_key_common::KeyHandle,
         mechanism: aziot_key_common::SignMechanism,
         digest: &[u8],
     ) -> Result<Vec<u8>, Error> {
-        let (id, id_cstr) = key_handle_to_id(handle, &mut self.keys)?;
-        let signature = match (id, mechanism) {
-            (KeyId::KeyPair(_), aziot_key_common::SignMechanism::Ecdsa) => self.keys.sign(
-                &id_cstr,
-                keys::sys::AZIOT_KEYS_SIGN_MECHANISM_ECDSA,
-                std::ptr::null(),
-                digest,
-            )?,
-
-            (KeyId::Key(_), aziot_key_common::SignMechanism::HmacSha256) => self.keys.sign(
-                &id_cstr,
-                keys::sys::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256,
-                std::ptr::null(),
-                digest,
-            )?,
-
-            (KeyId::Derived(_, derivation_data), aziot_key_common::SignMechanism::HmacSha256) => {
-                let parameters = keys::sys::AZIOT_KEYS_SIGN_DERIVED_PARAMETERS {
-                    derivation_data: derivation_data.as_ptr(),
-                    derivation_data_len: derivation_data.len(),
-                    mechanism: keys::sys::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256,
-                    parameters: std::ptr::null(),
-                };
-
-                self.keys.sign(
-                    &id_cstr,
-                    keys::sys::AZIOT_KEYS_SIGN_MECHANISM_DERIVED,
-                    (&parameters as *const keys::sys::AZIOT_KEYS_SIGN_DERIVED_PARAMETERS).cast(),
-                    digest,
-                )?
+        let (key_id, id) = key_handle_to_id(handle, &*self.keys, &self.handle_validation())?;
+        let signature = match (&key_id, &mechanism) {
+            (
+                KeyId::KeyPair(_),
+                aziot_key_common::SignMechanism::Ecdsa
+                | aziot_key_common::SignMechanism::Eddsa
+                | aziot_key_common::SignMechanism::RsaPkcs1 { .. }
+                | aziot_key_common::SignMechanism::RsaPss { .. },
+            ) => self.keys.sign(&id, &mechanism, None, digest)?,
+
+            (KeyId::Key(_) | KeyId::Derived(..), mechanism)
+                if is_hmac_sign_mechanism(mechanism) =>
+            {
+                self.keys
+                    .sign(&id, mechanism, key_id.derivation().as_ref(), digest)?
             }
 
             _ => {
@@ -293,75 +478,26 @@ impl Api {
     }
 
     pub fn encrypt(
-        &mut self,
+        &self,
         handle: &aziot_key_common::KeyHandle,
-        mechanism: aziot_key_common::EncryptMechanism,
+        mechanism: &aziot_key_common::EncryptMechanism,
         plaintext: &[u8],
     ) -> Result<Vec<u8>, Error> {
-        let (id, id_cstr) = key_handle_to_id(handle, &mut self.keys)?;
-
-        let ciphertext = match (id, mechanism) {
-            (KeyId::Key(_), aziot_key_common::EncryptMechanism::Aead { iv, aad }) => {
-                let parameters = keys::sys::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS {
-                    iv: iv.as_ptr(),
-                    iv_len: iv.len(),
-                    aad: aad.as_ptr(),
-                    aad_len: aad.len(),
-                };
-
-                self.keys.encrypt(
-                    &id_cstr,
-                    keys::sys::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD,
-                    (&parameters as *const keys::sys::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS).cast(),
-                    plaintext,
-                )?
-            }
-
-            (KeyId::KeyPair(_), aziot_key_common::EncryptMechanism::RsaPkcs1) => {
-                self.keys.encrypt(
-                    &id_cstr,
-                    keys::sys::AZIOT_KEYS_ENCRYPT_MECHANISM_RSA_PKCS1,
-                    std::ptr::null_mut(),
-                    plaintext,
-                )?
-            }
-
-            (KeyId::KeyPair(_), aziot_key_common::EncryptMechanism::RsaNoPadding) => {
-                self.keys.encrypt(
-                    &id_cstr,
-                    keys::sys::AZIOT_KEYS_ENCRYPT_MECHANISM_RSA_NO_PADDING,
-                    std::ptr::null_mut(),
-                    plaintext,
-                )?
+        let (key_id, id) = key_handle_to_id(handle, &*self.keys, &self.handle_validation())?;
+
+        let ciphertext = match (&key_id, mechanism) {
+            (KeyId::Key(_) | KeyId::Derived(..), mechanism)
+                if is_symmetric_encrypt_mechanism(mechanism) =>
+            {
+                self.keys
+                    .encrypt(&id, mechanism, key_id.derivation().as_ref(), plaintext)?
             }
 
             (
-                KeyId::Derived(_, derivation_data),
-                aziot_key_common::EncryptMechanism::Aead { iv, aad },
-            ) => {
-                let parameters = keys::sys::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS {
-                    iv: iv.as_ptr(),
-                    iv_len: iv.len(),
-                    aad: aad.as_ptr(),
-                    aad_len: aad.len(),
-                };
-
-                let parameters = keys::sys::AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS {
-                    derivation_data: derivation_data.as_ptr(),
-                    derivation_data_len: derivation_data.len(),
-                    mechanism: keys::sys::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD,
-                    parameters: (&parameters
-                        as *const keys::sys::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS)
-                        .cast(),
-                };
-
-                self.keys.encrypt(
-                    &id_cstr,
-                    keys::sys::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED,
-                    (&parameters as *const keys::sys::AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS).cast(),
-                    plaintext,
-                )?
-            }
+                KeyId::KeyPair(_),
+                aziot_key_common::EncryptMechanism::RsaPkcs1
+                | aziot_key_common::EncryptMechanism::RsaNoPadding,
+            ) => self.keys.encrypt(&id, mechanism, None, plaintext)?,
 
             _ => {
                 return Err(Error::invalid_parameter(
@@ -375,57 +511,17 @@ impl Api {
     }
 
     pub fn decrypt(
-        &mut self,
+        &self,
         handle: &aziot_key_common::KeyHandle,
-        mechanism: aziot_key_common::EncryptMechanism,
+        mechanism: &aziot_key_common::EncryptMechanism,
         ciphertext: &[u8],
     ) -> Result<Vec<u8>, Error> {
-        let (id, id_cstr) = key_handle_to_id(handle, &mut self.keys)?;
-
-        let plaintext = match (id, mechanism) {
-            (KeyId::Key(_), aziot_key_common::EncryptMechanism::Aead { iv, aad }) => {
-                let parameters = keys::sys::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS {
-                    iv: iv.as_ptr(),
-                    iv_len: iv.len(),
-                    aad: aad.as_ptr(),
-                    aad_len: aad.len(),
-                };
-
-                self.keys.decrypt(
-                    &id_cstr,
-                    keys::sys::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD,
-                    (&parameters as *const keys::sys::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS).cast(),
-                    ciphertext,
-                )?
-            }
+        let (key_id, id) = key_handle_to_id(handle, &*self.keys, &self.handle_validation())?;
 
-            (
-                KeyId::Derived(_, derivation_data),
-                aziot_key_common::EncryptMechanism::Aead { iv, aad },
-            ) => {
-                let parameters = keys::sys::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS {
-                    iv: iv.as_ptr(),
-                    iv_len: iv.len(),
-                    aad: aad.as_ptr(),
-                    aad_len: aad.len(),
-                };
-
-                let parameters = keys::sys::AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS {
-                    derivation_data: derivation_data.as_ptr(),
-                    derivation_data_len: derivation_data.len(),
-                    mechanism: keys::sys::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD,
-                    parameters: (&parameters
-                        as *const keys::sys::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS)
-                        .cast(),
-                };
-
-                self.keys.decrypt(
-                    &id_cstr,
-                    keys::sys::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED,
-                    (&parameters as *const keys::sys::AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS).cast(),
-                    ciphertext,
-                )?
-            }
+        let plaintext = match &key_id {
+            KeyId::Key(_) | KeyId::Derived(..) if is_symmetric_encrypt_mechanism(mechanism) => self
+                .keys
+                .decrypt(&id, mechanism, key_id.derivation().as_ref(), ciphertext)?,
 
             _ => {
                 return Err(Error::invalid_parameter(
@@ -438,6 +534,309 @@ impl Api {
         Ok(plaintext)
     }
 
+    /// Starts a multi-part encryption, or decryption if `decrypt` is set, and returns the ID of the new stream.
+    pub fn create_encrypt_stream(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: &aziot_key_common::EncryptMechanism,
+        decrypt: bool,
+        user: libc::uid_t,
+    ) -> Result<String, Error> {
+        let (key_id, id) = key_handle_to_id(handle, &*self.keys, &self.handle_validation())?;
+
+        let stream = match &key_id {
+            KeyId::Key(_) | KeyId::Derived(..) => {
+                if !is_symmetric_encrypt_mechanism(mechanism) {
+                    return Err(Error::invalid_parameter(
+                        "mechanism",
+                        "mechanism cannot be used with this key type",
+                    ));
+                }
+
+                let derivation = key_id.derivation();
+                if decrypt {
+                    self.keys
+                        .decrypt_init(&id, mechanism, derivation.as_ref())?
+                } else {
+                    self.keys
+                        .encrypt_init(&id, mechanism, derivation.as_ref())?
+                }
+            }
+
+            KeyId::KeyPair(_) => {
+                return Err(Error::invalid_parameter(
+                    "mechanism",
+                    "mechanism does not support multi-part operations",
+                ))
+            }
+        };
+
+        self.insert_stream(stream, user)
+    }
+
+    /// Starts a multi-part signature and returns the ID of the new stream.
+    ///
+    /// Only HMAC mechanisms are supported. The other mechanisms sign a digest that the caller computes itself.
+    pub fn create_sign_stream(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: aziot_key_common::SignMechanism,
+        user: libc::uid_t,
+    ) -> Result<String, Error> {
+        let (key_id, id) = key_handle_to_id(handle, &*self.keys, &self.handle_validation())?;
+
+        let stream = match &key_id {
+            KeyId::Key(_) | KeyId::Derived(..) if is_hmac_sign_mechanism(&mechanism) => {
+                self.keys
+                    .sign_init(&id, &mechanism, key_id.derivation().as_ref())?
+            }
+
+            _ => {
+                return Err(Error::invalid_parameter(
+                    "mechanism",
+                    "mechanism does not support multi-part operations",
+                ))
+            }
+        };
+
+        self.insert_stream(stream, user)
+    }
+
+    /// Passes the next part of the input to the given stream, and returns the output the operation has produced so far.
+    ///
+    /// If `is_final` is set, the operation is completed after `data` has been processed and the stream is closed.
+    /// The stream is also closed if the operation fails.
+    pub fn update_stream(
+        &self,
+        stream_id: &str,
+        data: &[u8],
+        is_final: bool,
+        user: libc::uid_t,
+    ) -> Result<Vec<u8>, Error> {
+        let stream = self.get_stream(stream_id, user)?;
+
+        // Remove the stream before it is completed so that no other request can use it afterwards.
+        if is_final {
+            self.remove_stream(stream_id);
+        }
+
+        let result = (|| {
+            let mut state = stream.state.lock().expect("stream lock poisoned");
+            state.last_used = std::time::Instant::now();
+
+            let mut output = state.stream.update(data)?;
+            if is_final {
+                output.extend(state.stream.finish()?);
+            }
+
+            Ok(output)
+        })();
+
+        if result.is_err() {
+            self.remove_stream(stream_id);
+        }
+
+        result
+    }
+
+    /// Abandons the given stream without completing its operation.
+    pub fn abort_stream(&self, stream_id: &str, user: libc::uid_t) -> Result<(), Error> {
+        let _ = self.get_stream(stream_id, user)?;
+        self.remove_stream(stream_id);
+        Ok(())
+    }
+
+    /// Performs a key agreement between the given key pair and the peer public key.
+    ///
+    /// If `key_id` is set, the shared secret is stored as a new key with that ID and the given usage, and a handle to it is returned.
+    /// It is an error if a key with that ID already exists.
+    /// Otherwise the raw shared secret is returned, which requires the same permission as exporting the key pair.
+    pub fn derive_shared_secret(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+        mechanism: &aziot_key_common::KeyAgreementMechanism,
+        peer_public_key: &[u8],
+        key_id: Option<&str>,
+        usage: &[aziot_key_common::KeyUsage],
+        user: libc::uid_t,
+    ) -> Result<aziot_key_common::SharedSecret, Error> {
+        let (handle_id, id) = key_handle_to_id(handle, &*self.keys, &self.handle_validation())?;
+        let key_pair_id = if let KeyId::KeyPair(key_pair_id) = handle_id {
+            key_pair_id
+        } else {
+            return Err(Error::invalid_parameter("handle", "not a key pair handle"));
+        };
+
+        match key_id {
+            Some(key_id) => {
+                check_not_reserved(key_id)?;
+
+                if !self.authorize(user, key_id) {
+                    return Err(Error::Unauthorized(user, key_id.to_owned()));
+                }
+            }
+
+            None => {
+                if !self.authorize_export(user, &key_pair_id) {
+                    return Err(Error::Unauthorized(user, key_pair_id.into_owned()));
+                }
+            }
+        }
+
+        let secret = self
+            .keys
+            .derive_shared_secret(&id, mechanism, peer_public_key)?;
+
+        if let Some(key_id) = key_id {
+            // The shared secret must not replace an existing key, so the backend checks for one and imports the secret atomically.
+            if let Err(err) = self.keys.import_key_if_not_exists(key_id, &secret, usage) {
+                return Err(match self.keys.load_key(key_id) {
+                    Ok(()) => Error::invalid_parameter(
+                        "keyId",
+                        format!("a key with ID {:?} already exists", key_id),
+                    ),
+                    Err(_) => err.into(),
+                });
+            }
+
+            let handle = key_id_to_handle(
+                &KeyId::Key(key_id.into()),
+                &*self.keys,
+                &self.handle_validation(),
+            )?;
+            Ok(aziot_key_common::SharedSecret::Key(handle))
+        } else {
+            log::info!(
+                "User {} derived a raw shared secret with key pair {}.",
+                user,
+                key_pair_id,
+            );
+
+            Ok(aziot_key_common::SharedSecret::Raw(secret))
+        }
+    }
+
+    pub fn rotate_handle_validation_key(&self, user: libc::uid_t) -> Result<(), Error> {
+        let mut handle_validation = self
+            .handle_validation
+            .write()
+            .expect("handle validation lock poisoned");
+
+        // Rotating the handle validation key affects every caller, so only root is allowed to do it.
+        if user != 0 {
+            return Err(Error::Unauthorized(user, handle_validation.key_id.clone()));
+        }
+
+        handle_validation.rotate(&*self.keys)?;
+
+        log::info!(
+            "Rotated handle validation key to generation {}. All previously issued key handles are now invalid.",
+            handle_validation.generation,
+        );
+
+        Ok(())
+    }
+
+    /// Runs the given operation on the blocking thread pool once one of the `max_concurrency` workers is free.
+    ///
+    /// All calls into the key backend happen inside operations, so the number of workers bounds how many calls
+    /// the backend handles concurrently.
+    pub(crate) async fn run<F, T>(self: &std::sync::Arc<Self>, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Api) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        // The permit is moved into the blocking task so that the worker is only released once the operation finishes,
+        // even if this future is dropped while the operation is still running.
+        let permit = self
+            .workers
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("worker semaphore is never closed");
+
+        let api = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            f(&api)
+        })
+        .await
+        .map_err(|err| Error::Internal(InternalError::Worker(err)))?
+    }
+
+    fn insert_stream(
+        &self,
+        stream: Box<dyn backend::KeyStream>,
+        user: libc::uid_t,
+    ) -> Result<String, Error> {
+        let mut streams = self.streams.lock().expect("streams lock poisoned");
+
+        // Streams that are currently in use are locked, so they are never considered idle.
+        streams.retain(|_, stream| {
+            stream.state.try_lock().map_or(true, |state| {
+                state.last_used.elapsed() < OpenStream::IDLE_TIMEOUT
+            })
+        });
+
+        if streams
+            .values()
+            .filter(|stream| stream.user == user)
+            .count()
+            >= OpenStream::MAX_PER_USER
+        {
+            return Err(Error::invalid_parameter(
+                "keyHandle",
+                "too many streams are open; complete or abort existing streams first",
+            ));
+        }
+
+        let mut stream_id = [0_u8; 32];
+        openssl::rand::rand_bytes(&mut stream_id)
+            .map_err(|err| Error::Internal(InternalError::GenerateStreamId(err)))?;
+        let stream_id = base64::encode_config(stream_id, base64::URL_SAFE_NO_PAD);
+
+        streams.insert(
+            stream_id.clone(),
+            std::sync::Arc::new(OpenStream {
+                user,
+                state: std::sync::Mutex::new(OpenStreamState {
+                    stream,
+                    last_used: std::time::Instant::now(),
+                }),
+            }),
+        );
+
+        Ok(stream_id)
+    }
+
+    fn get_stream(
+        &self,
+        stream_id: &str,
+        user: libc::uid_t,
+    ) -> Result<std::sync::Arc<OpenStream>, Error> {
+        let streams = self.streams.lock().expect("streams lock poisoned");
+
+        // A stream that belongs to another user is reported the same way as one that does not exist.
+        streams
+            .get(stream_id)
+            .filter(|stream| stream.user == user)
+            .cloned()
+            .ok_or_else(|| Error::invalid_parameter("streamId", "stream does not exist"))
+    }
+
+    fn remove_stream(&self, stream_id: &str) {
+        self.streams
+            .lock()
+            .expect("streams lock poisoned")
+            .remove(stream_id);
+    }
+
+    fn handle_validation(&self) -> std::sync::RwLockReadGuard<'_, HandleValidation> {
+        self.handle_validation
+            .read()
+            .expect("handle validation lock poisoned")
+    }
+
     fn authorize(&self, user: libc::uid_t, id: &str) -> bool {
         // Root user is always authorized.
         if user == 0 {
@@ -445,36 +844,96 @@ impl Api {
         }
 
         // Authorize user based on stored principals config.
-        if let Some(keys) = self.principals.get(&user) {
+        let principals = self.principals.read().expect("principals lock poisoned");
+        if let Some(keys) = principals.keys.get(&user) {
             return keys.iter().any(|key| key.is_match(id));
         }
 
         false
     }
+
+    /// Checks whether the user may export the given key or key pair, or import a wrapped one with the given ID.
+    ///
+    /// Unlike [`Api::authorize`], root is not implicitly authorized.
+    fn authorize_export(&self, user: libc::uid_t, id: &str) -> bool {
+        let principals = self.principals.read().expect("principals lock poisoned");
+        if let Some(keys) = principals.export.get(&user) {
+            return keys.iter().any(|key| key.is_match(id));
+        }
+
+        false
+    }
+
+    fn unwrapping_key_pair_id(
+        &self,
+        handle: &aziot_key_common::KeyHandle,
+    ) -> Result<String, Error> {
+        let (key_id, id) = key_handle_to_id(handle, &*self.keys, &self.handle_validation())?;
+        if !matches!(key_id, KeyId::KeyPair(_)) {
+            return Err(Error::invalid_parameter(
+                "unwrapping_key_handle",
+                "not a key pair handle",
+            ));
+        }
+
+        Ok(id)
+    }
 }
 
+/// Applies configuration changes detected by the config watcher to the shared [`Api`].
+struct ApiConfig(std::sync::Arc<Api>);
+
 #[async_trait]
-impl UpdateConfig for Api {
+impl UpdateConfig for ApiConfig {
     type Config = Config;
     type Error = Error;
 
     async fn update_config(&mut self, new_config: Self::Config) -> Result<(), Self::Error> {
         log::info!("Detected change in config files. Updating config.");
 
-        // Only allow runtime updates to principals.
+        // Only allow runtime updates to principals and the handle lifetime.
         let Config {
+            backend: _,
             aziot_keys: _,
             preloaded_keys: _,
+            handle_lifetime_secs,
+            max_concurrency: _,
             endpoints: _,
             principal,
         } = new_config;
-        self.principals = principal_to_map(principal);
+        *self.0.principals.write().expect("principals lock poisoned") = principal_to_map(principal);
+        self.0
+            .handle_validation
+            .write()
+            .expect("handle validation lock poisoned")
+            .handle_lifetime = handle_lifetime(handle_lifetime_secs);
 
         log::info!("Config update finished.");
         Ok(())
     }
 }
 
+/// A multi-part operation started by [`Api::create_encrypt_stream`] or [`Api::create_sign_stream`].
+struct OpenStream {
+    /// The user that started the stream. No other user can use it.
+    user: libc::uid_t,
+
+    state: std::sync::Mutex<OpenStreamState>,
+}
+
+struct OpenStreamState {
+    stream: Box<dyn backend::KeyStream>,
+    last_used: std::time::Instant,
+}
+
+impl OpenStream {
+    /// Streams that have not been used for this long are abandoned.
+    const IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+    /// The maximum number of streams a user can have open at once.
+    const MAX_PER_USER: usize = 32;
+}
+
 /// Decoded from a [`aziot_key_common::KeyHandle`]
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 enum KeyId<'a> {
@@ -483,6 +942,8 @@ enum KeyId<'a> {
     Derived(
         std::borrow::Cow<'a, aziot_key_common::KeyHandle>,
         std::borrow::Cow<'a, [u8]>,
+        // Handles created before derivation mechanisms were configurable don't have this field.
+        #[serde(default)] std::borrow::Cow<'a, aziot_key_common::KeyDerivationMechanism>,
     ),
 }
 
@@ -491,36 +952,181 @@ impl KeyId<'_> {
         match self {
             KeyId::KeyPair(id) => KeyId::KeyPair(std::borrow::Cow::Borrowed(&**id)),
             KeyId::Key(id) => KeyId::Key(std::borrow::Cow::Borrowed(&**id)),
-            KeyId::Derived(base_handle, derivation_data) => KeyId::Derived(
+            KeyId::Derived(base_handle, derivation_data, mechanism) => KeyId::Derived(
                 std::borrow::Cow::Borrowed(&**base_handle),
                 std::borrow::Cow::Borrowed(&**derivation_data),
+                std::borrow::Cow::Borrowed(&**mechanism),
             ),
         }
     }
+
+    /// Returns the derivation of a derived key, or `None` for keys and key pairs.
+    fn derivation(&self) -> Option<backend::Derivation<'_>> {
+        match self {
+            KeyId::KeyPair(_) | KeyId::Key(_) => None,
+            KeyId::Derived(_, derivation_data, mechanism) => Some(backend::Derivation {
+                data: derivation_data,
+                mechanism,
+            }),
+        }
+    }
 }
 
-fn handle_validation_key_id(keys: &mut keys::Keys) -> Result<&'static std::ffi::CStr, Error> {
-    const HANDLE_VALIDATION_KEY_ID_C: &[u8] = b"handle-validation-key\0";
-    let handle_validation_key_id = std::ffi::CStr::from_bytes_with_nul(HANDLE_VALIDATION_KEY_ID_C)
-        .expect("hard-coded key ID is valid CStr");
-    keys.create_key_if_not_exists(
-        handle_validation_key_id,
-        keys::sys::AZIOT_KEYS_KEY_USAGE_SIGN,
-    )
-    .map_err(|err| Error::Internal(InternalError::CreateKeyIfNotExistsGenerate(err)))?;
-    Ok(handle_validation_key_id)
+/// The key used to sign and validate key handles.
+struct HandleValidation {
+    /// The generation of the handle validation key. It is incremented every time the key is rotated.
+    ///
+    /// Generation 0 is the original `handle-validation-key`. Generation N > 0 is `handle-validation-key-N`.
+    /// Older generations are never used again, but are left in place so that the current generation can be discovered at startup.
+    generation: u32,
+
+    key_id: String,
+
+    /// How long newly issued handles remain valid. `None` means they never expire.
+    handle_lifetime: Option<std::time::Duration>,
+}
+
+impl HandleValidation {
+    const KEY_ID_PREFIX: &'static str = "handle-validation-key";
+
+    fn new(
+        keys: &dyn backend::KeyBackend,
+        handle_lifetime: Option<std::time::Duration>,
+    ) -> Result<Self, Error> {
+        // The current generation is the highest one whose key exists. The keys are enumerated rather than loaded
+        // one generation at a time, so that a missing generation does not hide the ones after it.
+        let generation = match keys.enumerate_keys() {
+            Ok(key_infos) => key_infos
+                .iter()
+                .filter(|key_info| key_info.kind == aziot_key_common::KeyKind::Key)
+                .filter_map(|key_info| Self::generation(&key_info.id))
+                .max()
+                .unwrap_or(0),
+
+            // Backends that can't enumerate keys are probed one generation at a time instead.
+            Err(keys::EnumerateKeysError {
+                err: keys::KeysRawError(keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER),
+            }) => {
+                let mut generation = 0;
+                loop {
+                    let next_key_id = Self::key_id(generation + 1);
+                    match keys.load_key(&next_key_id) {
+                        Ok(()) => generation += 1,
+                        Err(keys::LoadKeyError {
+                            err: keys::KeysRawError(keys::sys::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER),
+                        }) => break generation,
+                        Err(err) => return Err(Error::Internal(InternalError::LoadKey(err))),
+                    }
+                }
+            }
+
+            Err(err) => return Err(Error::Internal(InternalError::EnumerateKeys(err))),
+        };
+
+        Ok(HandleValidation {
+            generation,
+            key_id: Self::key_id(generation),
+            handle_lifetime,
+        })
+    }
+
+    fn key_id(generation: u32) -> String {
+        if generation == 0 {
+            Self::KEY_ID_PREFIX.to_owned()
+        } else {
+            format!("{}-{}", Self::KEY_ID_PREFIX, generation)
+        }
+    }
+
+    /// Returns the generation of the handle validation key with the given ID, or `None` if the ID is not one.
+    fn generation(id: &str) -> Option<u32> {
+        if id == Self::KEY_ID_PREFIX {
+            return Some(0);
+        }
+
+        let generation: u32 = id
+            .strip_prefix(Self::KEY_ID_PREFIX)?
+            .strip_prefix('-')?
+            .parse()
+            .ok()?;
+
+        // Only the canonical form of each generation, like `handle-validation-key-1` and not `handle-validation-key-01`.
+        if generation > 0 && Self::key_id(generation) == id {
+            Some(generation)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the given ID is reserved for the handle validation key, including all of its past and future generations.
+    fn is_reserved(id: &str) -> bool {
+        id.strip_prefix(Self::KEY_ID_PREFIX)
+            .map_or(false, |suffix| suffix.is_empty() || suffix.starts_with('-'))
+    }
+
+    /// Returns the ID of the handle validation key, creating the key if it doesn't already exist.
+    fn get_or_create_key(&self, keys: &dyn backend::KeyBackend) -> Result<&str, Error> {
+        keys.create_key_if_not_exists(&self.key_id, &[aziot_key_common::KeyUsage::Sign])
+            .map_err(|err| Error::Internal(InternalError::CreateKeyIfNotExistsGenerate(err)))?;
+        Ok(&self.key_id)
+    }
+
+    /// Switches to a new handle validation key. All handles signed with the previous key become invalid.
+    fn rotate(&mut self, keys: &dyn backend::KeyBackend) -> Result<(), Error> {
+        let generation = self
+            .generation
+            .checked_add(1)
+            .ok_or_else(|| Error::Internal(InternalError::RotateHandleValidationKey))?;
+        let key_id = Self::key_id(generation);
+
+        keys.create_key_if_not_exists(&key_id, &[aziot_key_common::KeyUsage::Sign])
+            .map_err(|err| Error::Internal(InternalError::CreateKeyIfNotExistsGenerate(err)))?;
+
+        self.generation = generation;
+        self.key_id = key_id;
+        Ok(())
+    }
+}
+
+/// Rejects IDs that are reserved for the handle validation key, which is only ever used by keyd itself.
+///
+/// Deleting or replacing a generation of the handle validation key would make the generation discovered at startup
+/// an older one, which would make the handles that were revoked by rotating it valid again.
+fn check_not_reserved(id: &str) -> Result<(), Error> {
+    if HandleValidation::is_reserved(id) {
+        return Err(Error::invalid_parameter(
+            "keyId",
+            format!("key ID {:?} is reserved for the handle validation key", id),
+        ));
+    }
+
+    Ok(())
+}
+
+fn handle_lifetime(handle_lifetime_secs: Option<u64>) -> Option<std::time::Duration> {
+    match handle_lifetime_secs.unwrap_or(aziot_keyd_config::DEFAULT_HANDLE_LIFETIME_SECS) {
+        0 => None,
+        secs => Some(std::time::Duration::from_secs(secs)),
+    }
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 struct Sr<'a> {
     key_id: KeyId<'a>,
     nonce: String,
+
+    /// Unix timestamp (in seconds) after which the handle is no longer valid.
+    ///
+    /// Handles issued by older versions of the service do not have this field, and never expire.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expiry: Option<u64>,
 }
 
 fn key_handle_to_id(
     handle: &aziot_key_common::KeyHandle,
-    keys: &mut keys::Keys,
-) -> Result<(KeyId<'static>, std::ffi::CString), Error> {
+    keys: &dyn backend::KeyBackend,
+    handle_validation: &HandleValidation,
+) -> Result<(KeyId<'static>, String), Error> {
     // DEVNOTE:
     //
     // Map errors from using the handle validation key to Error::Internal instead of relying on `?`,
@@ -548,12 +1154,12 @@ fn key_handle_to_id(
     let sr = sr.ok_or_else(|| Error::invalid_parameter("handle", "invalid handle"))?;
     let sig = sig.ok_or_else(|| Error::invalid_parameter("handle", "invalid handle"))?;
 
-    let handle_validation_key = handle_validation_key_id(keys)?;
+    let handle_validation_key = handle_validation.get_or_create_key(keys)?;
     let ok = keys
         .verify(
             handle_validation_key,
-            keys::sys::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256,
-            std::ptr::null(),
+            &aziot_key_common::SignMechanism::HmacSha256,
+            None,
             sr.as_bytes(),
             &sig,
         )
@@ -565,33 +1171,30 @@ fn key_handle_to_id(
     let sr: Sr<'static> = serde_json::from_str(&sr)
         .map_err(|_e| Error::invalid_parameter("handle", "invalid handle"))?;
 
-    let id = sr.key_id;
-
-    let id_cstr = match &id {
-        KeyId::KeyPair(id) => {
-            let id_cstr = std::ffi::CString::new(id.clone().into_owned())
-                .map_err(|err| Error::invalid_parameter("handle", err))?;
-            id_cstr
+    if let Some(expiry) = sr.expiry {
+        if unix_time_now()? >= expiry {
+            return Err(Error::invalid_parameter("handle", "handle has expired"));
         }
+    }
 
-        KeyId::Key(id) => {
-            let id_cstr = std::ffi::CString::new(id.clone().into_owned())
-                .map_err(|err| Error::invalid_parameter("handle", err))?;
-            id_cstr
-        }
+    let id = sr.key_id;
 
-        KeyId::Derived(base_handle, _) => {
-            let (_, base_id_cstr) = key_handle_to_id(&base_handle, keys)?;
-            base_id_cstr
+    let base_id = match &id {
+        KeyId::KeyPair(id) | KeyId::Key(id) => id.clone().into_owned(),
+
+        KeyId::Derived(base_handle, _, _) => {
+            let (_, base_id) = key_handle_to_id(&base_handle, keys, handle_validation)?;
+            base_id
         }
     };
 
-    Ok((id, id_cstr))
+    Ok((id, base_id))
 }
 
 fn key_id_to_handle(
     id: &KeyId<'_>,
-    keys: &mut keys::Keys,
+    keys: &dyn backend::KeyBackend,
+    handle_validation: &HandleValidation,
 ) -> Result<aziot_key_common::KeyHandle, Error> {
     let sr = {
         let mut nonce = [0_u8; 64];
@@ -599,26 +1202,40 @@ fn key_id_to_handle(
             .map_err(|err| Error::Internal(InternalError::GenerateNonce(err)))?;
         let nonce = base64::encode(&nonce[..]);
 
-        let sr = Sr {
+        let expiry = handle_validation
+            .handle_lifetime
+            .map(|handle_lifetime| -> Result<_, Error> {
+                Ok(unix_time_now()?.saturating_add(handle_lifetime.as_secs()))
+            })
+            .transpose()?;
+
+        Sr {
             key_id: id.borrow(),
             nonce,
-        };
-        let sr = serde_json::to_string(&sr).expect("cannot fail to convert Sr to JSON");
-        sr
+            expiry,
+        }
     };
 
-    let handle_validation_key = handle_validation_key_id(keys)?;
+    sr_to_handle(&sr, keys, handle_validation)
+}
+
+fn sr_to_handle(
+    sr: &Sr<'_>,
+    keys: &dyn backend::KeyBackend,
+    handle_validation: &HandleValidation,
+) -> Result<aziot_key_common::KeyHandle, Error> {
+    let sr = serde_json::to_string(sr).expect("cannot fail to convert Sr to JSON");
+
+    let handle_validation_key = handle_validation.get_or_create_key(keys)?;
     let sig = keys
         .sign(
             handle_validation_key,
-            keys::sys::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256,
-            std::ptr::null(),
+            &aziot_key_common::SignMechanism::HmacSha256,
+            None,
             sr.as_bytes(),
         )
         .map_err(|err| Error::Internal(InternalError::Sign(err)))?;
 
-    // TODO: se for expiry
-
     // This *could* use percent-encoding instead of string concat. However, the only potential problem with base64-encoded values can arise from a trailing =,
     // since = is also used between a key and its value. But that usage of = is not ambiguous, so it isn't a problem.
     let token = format!(
@@ -631,17 +1248,385 @@ fn key_id_to_handle(
     Ok(handle)
 }
 
-fn principal_to_map(
-    principal: Vec<Principal>,
-) -> std::collections::BTreeMap<libc::uid_t, Vec<wildmatch::WildMatch>> {
-    let mut result: std::collections::BTreeMap<_, Vec<_>> = Default::default();
+fn is_hmac_sign_mechanism(mechanism: &aziot_key_common::SignMechanism) -> bool {
+    matches!(
+        mechanism,
+        aziot_key_common::SignMechanism::HmacSha256
+            | aziot_key_common::SignMechanism::HmacSha384
+            | aziot_key_common::SignMechanism::HmacSha512
+    )
+}
+
+/// Whether the mechanism can be used with keys, as opposed to key pairs.
+fn is_symmetric_encrypt_mechanism(mechanism: &aziot_key_common::EncryptMechanism) -> bool {
+    !matches!(
+        mechanism,
+        aziot_key_common::EncryptMechanism::RsaPkcs1
+            | aziot_key_common::EncryptMechanism::RsaNoPadding
+    )
+}
+
+fn unix_time_now() -> Result<u64, Error> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|err| Error::Internal(InternalError::SystemTime(err)))?;
+    Ok(now.as_secs())
+}
 
-    for Principal { uid, keys } in principal {
-        result
-            .entry(uid)
-            .or_default()
-            .extend(keys.into_iter().map(|key| wildmatch::WildMatch::new(&key)));
+/// The keys that each user is authorized to access, built from the `[[principal]]` sections of the config.
+#[derive(Default)]
+struct Principals {
+    keys: std::collections::BTreeMap<libc::uid_t, Vec<wildmatch::WildMatch>>,
+
+    /// Keys that the user may also export and import. See [`Api::authorize_export`]
+    export: std::collections::BTreeMap<libc::uid_t, Vec<wildmatch::WildMatch>>,
+}
+
+fn principal_to_map(principal: Vec<Principal>) -> Principals {
+    let mut result: Principals = Default::default();
+
+    for Principal { uid, keys, export } in principal {
+        let keys: Vec<_> = keys
+            .into_iter()
+            .map(|key| wildmatch::WildMatch::new(&key))
+            .collect();
+
+        if export {
+            result
+                .export
+                .entry(uid)
+                .or_default()
+                .extend(keys.iter().cloned());
+        }
+
+        result.keys.entry(uid).or_default().extend(keys);
     }
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        backend, handle_lifetime, key_handle_to_id, key_id_to_handle, sr_to_handle, unix_time_now,
+        Api, Error, HandleValidation, KeyId, Sr,
+    };
+
+    fn test_api(keys: backend::Memory, handle_validation: HandleValidation) -> Api {
+        Api {
+            keys: Box::new(keys),
+            principals: Default::default(),
+            handle_validation: std::sync::RwLock::new(handle_validation),
+            workers: std::sync::Arc::new(tokio::sync::Semaphore::new(1)),
+            streams: Default::default(),
+        }
+    }
+
+    fn handle_with_expiry(
+        keys: &dyn backend::KeyBackend,
+        handle_validation: &HandleValidation,
+        expiry: Option<u64>,
+    ) -> aziot_key_common::KeyHandle {
+        let sr = Sr {
+            key_id: KeyId::Key("key".into()),
+            nonce: "nonce".to_owned(),
+            expiry,
+        };
+        sr_to_handle(&sr, keys, handle_validation).unwrap()
+    }
+
+    #[test]
+    fn handle_lifetime_secs() {
+        // Handles never expire by default.
+        assert_eq!(handle_lifetime(None), None);
+        assert_eq!(handle_lifetime(Some(0)), None);
+        assert_eq!(
+            handle_lifetime(Some(1)),
+            Some(std::time::Duration::from_secs(1)),
+        );
+        assert_eq!(
+            handle_lifetime(Some(u64::MAX)),
+            Some(std::time::Duration::from_secs(u64::MAX)),
+        );
+    }
+
+    #[test]
+    fn handle_expiry() {
+        let keys = backend::Memory::default();
+
+        // Handles expire after the configured lifetime.
+        let handle_validation =
+            HandleValidation::new(&keys, Some(std::time::Duration::from_secs(60))).unwrap();
+        let handle =
+            key_id_to_handle(&KeyId::Key("key".into()), &keys, &handle_validation).unwrap();
+        let (_, base_id) = key_handle_to_id(&handle, &keys, &handle_validation).unwrap();
+        assert_eq!(base_id, "key");
+
+        let now = unix_time_now().unwrap();
+        for expiry in &[now - 60, now] {
+            let handle = handle_with_expiry(&keys, &handle_validation, Some(*expiry));
+            match key_handle_to_id(&handle, &keys, &handle_validation) {
+                Err(Error::InvalidParameter(Some(("handle", err)))) => {
+                    assert_eq!(err.to_string(), "handle has expired");
+                }
+                result => panic!("expected handle to have expired but got {:?}", result),
+            }
+        }
+
+        let handle = handle_with_expiry(&keys, &handle_validation, Some(now + 60));
+        key_handle_to_id(&handle, &keys, &handle_validation).unwrap();
+
+        // Handles issued by older versions of the service do not expire.
+        let handle = handle_with_expiry(&keys, &handle_validation, None);
+        key_handle_to_id(&handle, &keys, &handle_validation).unwrap();
+
+        // A lifetime that overflows the expiry timestamp saturates instead.
+        let handle_validation =
+            HandleValidation::new(&keys, Some(std::time::Duration::from_secs(u64::MAX))).unwrap();
+        let handle =
+            key_id_to_handle(&KeyId::Key("key".into()), &keys, &handle_validation).unwrap();
+        key_handle_to_id(&handle, &keys, &handle_validation).unwrap();
+
+        // Without a lifetime, handles never expire.
+        let handle_validation = HandleValidation::new(&keys, None).unwrap();
+        let handle =
+            key_id_to_handle(&KeyId::Key("key".into()), &keys, &handle_validation).unwrap();
+        let sr = handle
+            .0
+            .split('&')
+            .next()
+            .unwrap()
+            .strip_prefix("sr=")
+            .unwrap();
+        let sr: Sr<'_> = serde_json::from_slice(&base64::decode(sr).unwrap()).unwrap();
+        assert_eq!(sr.expiry, None);
+        key_handle_to_id(&handle, &keys, &handle_validation).unwrap();
+    }
+
+    #[test]
+    fn handle_rejected_after_rotation() {
+        let keys = backend::Memory::default();
+
+        let mut handle_validation = HandleValidation::new(&keys, None).unwrap();
+        let handle =
+            key_id_to_handle(&KeyId::Key("key".into()), &keys, &handle_validation).unwrap();
+
+        handle_validation.rotate(&keys).unwrap();
+        assert!(matches!(
+            key_handle_to_id(&handle, &keys, &handle_validation),
+            Err(Error::InvalidParameter(Some(("handle", _)))),
+        ));
+
+        // The rotated key is picked up at startup.
+        let handle_validation = HandleValidation::new(&keys, None).unwrap();
+        assert_eq!(handle_validation.generation, 1);
+    }
+
+    #[test]
+    fn rotated_key_found_after_missing_generation() {
+        let keys = backend::Memory::default();
+
+        let mut handle_validation = HandleValidation::new(&keys, None).unwrap();
+        handle_validation.rotate(&keys).unwrap();
+        handle_validation.rotate(&keys).unwrap();
+        handle_validation.rotate(&keys).unwrap();
+        assert_eq!(handle_validation.generation, 3);
+
+        // A generation that went missing does not hide the ones after it.
+        backend::KeyBackend::delete_key(&keys, "handle-validation-key-2").unwrap();
+        let handle_validation = HandleValidation::new(&keys, None).unwrap();
+        assert_eq!(handle_validation.generation, 3);
+        assert_eq!(handle_validation.key_id, "handle-validation-key-3");
+
+        // IDs that merely look like a generation are ignored.
+        backend::KeyBackend::create_key_if_not_exists(&keys, "handle-validation-key-04", &[])
+            .unwrap();
+        let handle_validation = HandleValidation::new(&keys, None).unwrap();
+        assert_eq!(handle_validation.generation, 3);
+    }
+
+    #[test]
+    fn handle_validation_key_is_reserved() {
+        let keys = backend::Memory::default();
+        let mut handle_validation = HandleValidation::new(&keys, None).unwrap();
+        handle_validation.get_or_create_key(&keys).unwrap();
+        handle_validation.rotate(&keys).unwrap();
+        let api = test_api(keys, handle_validation);
+
+        let handle = api
+            .create_key_if_not_exists(
+                "key",
+                aziot_key_common::CreateKeyValue::Generate,
+                &[aziot_key_common::KeyUsage::Sign],
+                0,
+            )
+            .unwrap();
+
+        // Even root can't delete or replace any generation of the handle validation key.
+        for id in &[
+            "handle-validation-key",
+            "handle-validation-key-1",
+            "handle-validation-key-2",
+        ] {
+            assert!(matches!(
+                api.delete_key(id, 0),
+                Err(Error::InvalidParameter(Some(("keyId", _)))),
+            ));
+            assert!(matches!(
+                api.delete_key_pair(id, 0),
+                Err(Error::InvalidParameter(Some(("keyId", _)))),
+            ));
+            assert!(matches!(
+                api.move_key_pair("key-pair", id, 0),
+                Err(Error::InvalidParameter(Some(("keyId", _)))),
+            ));
+            assert!(matches!(
+                api.create_key_if_not_exists(
+                    id,
+                    aziot_key_common::CreateKeyValue::Generate,
+                    &[aziot_key_common::KeyUsage::Sign],
+                    0,
+                ),
+                Err(Error::InvalidParameter(Some(("keyId", _)))),
+            ));
+            assert!(matches!(
+                api.load_key(id, 0),
+                Err(Error::InvalidParameter(Some(("keyId", _)))),
+            ));
+            assert!(matches!(
+                api.export_key(id, aziot_key_common::WrapMechanism::RsaOaep, &[], 0),
+                Err(Error::InvalidParameter(Some(("keyId", _)))),
+            ));
+        }
+
+        // The handle validation key is unaffected, so handles issued before are still valid.
+        let handle_validation = HandleValidation::new(&*api.keys, None).unwrap();
+        assert_eq!(handle_validation.generation, 1);
+        key_handle_to_id(&handle, &*api.keys, &api.handle_validation()).unwrap();
+
+        // Other IDs with the same prefix are not reserved.
+        api.create_key_if_not_exists(
+            "handle-validation-keys",
+            aziot_key_common::CreateKeyValue::Generate,
+            &[aziot_key_common::KeyUsage::Sign],
+            0,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn derive_shared_secret_does_not_replace_key() {
+        let keys = backend::Memory::default();
+        let handle_validation = HandleValidation::new(&keys, None).unwrap();
+        let api = test_api(keys, handle_validation);
+
+        let key_pair_handle = api
+            .create_key_pair_if_not_exists("key-pair", None, 0)
+            .unwrap();
+        api.create_key_if_not_exists(
+            "key",
+            aziot_key_common::CreateKeyValue::Generate,
+            &[aziot_key_common::KeyUsage::Sign],
+            0,
+        )
+        .unwrap();
+
+        let peer_key_pair =
+            openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1)
+                .and_then(|group| openssl::ec::EcKey::generate(&group))
+                .and_then(openssl::pkey::PKey::from_ec_key)
+                .unwrap();
+        let peer_public_key = peer_key_pair.public_key_to_der().unwrap();
+
+        let derive_shared_secret = |key_id| {
+            api.derive_shared_secret(
+                &key_pair_handle,
+                &aziot_key_common::KeyAgreementMechanism::Ecdh,
+                &peer_public_key,
+                Some(key_id),
+                &[aziot_key_common::KeyUsage::Sign],
+                0,
+            )
+        };
+        let sign = |key_id| {
+            api.keys
+                .sign(
+                    key_id,
+                    &aziot_key_common::SignMechanism::HmacSha256,
+                    None,
+                    b"digest",
+                )
+                .unwrap()
+        };
+
+        let signature = sign("key");
+        assert!(matches!(
+            derive_shared_secret("key"),
+            Err(Error::InvalidParameter(Some(("keyId", _)))),
+        ));
+        assert_eq!(sign("key"), signature);
+
+        // A new key ID gets the shared secret.
+        let handle = match derive_shared_secret("new-key") {
+            Ok(aziot_key_common::SharedSecret::Key(handle)) => handle,
+            result => panic!("expected a handle to the new key, got {:?}", result),
+        };
+        let (key_id, _) = key_handle_to_id(&handle, &*api.keys, &api.handle_validation()).unwrap();
+        assert!(matches!(key_id, KeyId::Key(key_id) if key_id == "new-key"));
+
+        let secret = api
+            .keys
+            .derive_shared_secret(
+                "key-pair",
+                &aziot_key_common::KeyAgreementMechanism::Ecdh,
+                &peer_public_key,
+            )
+            .unwrap();
+        api.keys
+            .import_key("secret", &secret, &[aziot_key_common::KeyUsage::Sign])
+            .unwrap();
+        assert_eq!(sign("new-key"), sign("secret"));
+    }
+
+    #[test]
+    fn run_holds_worker_until_operation_finishes() {
+        let keys = backend::Memory::default();
+        let handle_validation = HandleValidation::new(&keys, None).unwrap();
+        let api = std::sync::Arc::new(Api {
+            keys: Box::new(keys),
+            principals: Default::default(),
+            handle_validation: std::sync::RwLock::new(handle_validation),
+            workers: std::sync::Arc::new(tokio::sync::Semaphore::new(1)),
+            streams: Default::default(),
+        });
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let (started_send, started_recv) = std::sync::mpsc::channel();
+        let (finish_send, finish_recv) = std::sync::mpsc::channel::<()>();
+
+        // Poll the operation once so that it starts, then drop it, like a request whose client disconnected.
+        runtime.block_on(async {
+            let run = api.run(move |_| {
+                started_send.send(()).unwrap();
+                finish_recv.recv().unwrap();
+                Ok(())
+            });
+            futures_util::pin_mut!(run);
+            futures_util::future::poll_fn(|cx| {
+                assert!(std::future::Future::poll(run.as_mut(), cx).is_pending());
+                std::task::Poll::Ready(())
+            })
+            .await;
+        });
+        started_recv.recv().unwrap();
+
+        // The operation is still running, so its worker must not be available to another operation.
+        assert_eq!(api.workers.available_permits(), 0);
+
+        finish_send.send(()).unwrap();
+        let _permit = runtime.block_on(api.workers.acquire()).unwrap();
+    }
+}
@@ -12,12 +12,21 @@
  * that this library exports, as well as the function pointers to the key operations. See its docs for more details.
  *
  * All calls to [`aziot_keys_get_function_list`] or any function in [`AZIOT_KEYS_FUNCTION_LIST`] are serialized, ie a function will not be called
- * while another function is running. However, it is not guaranteed that all function calls will be made from the same operating system thread.
- * Thus, implementations do not need to worry about locking to prevent concurrent access, but should also not store data in thread-local storage.
+ * while another function is running, unless the implementation advertises [`AZIOT_KEYS_CAPABILITY_THREAD_SAFE`] in its function list.
+ * However, it is not guaranteed that all function calls will be made from the same operating system thread.
+ * Thus, implementations that do not advertise thread-safety do not need to worry about locking to prevent concurrent access,
+ * but should also not store data in thread-local storage.
  */
 
 #include <stdint.h>
 
+/**
+ * A multi-part operation started by `encrypt_init`, `decrypt_init` or `sign_init`.
+ *
+ * The contents of this type are private to the implementation. The caller only ever handles pointers to it.
+ */
+typedef struct AZIOT_KEYS_STREAM AZIOT_KEYS_STREAM;
+
 /**
  * Represents the version of the API exported by this library.
  */
@@ -77,7 +86,8 @@ typedef unsigned int AZIOT_KEYS_ENCRYPT_MECHANISM;
  */
 typedef struct {
     /**
-     * The value of `base.version` must be [`AZIOT_KEYS_VERSION_2_0_0_0`].
+     * The value of `base.version` must be [`AZIOT_KEYS_VERSION_2_0_0_0`],
+     * unless this function list is embedded in the function list of a later API version, in which case it must be that version.
      */
     AZIOT_KEYS_FUNCTION_LIST base;
     /**
@@ -111,7 +121,8 @@ typedef struct {
      *
      * `preferred_algorithms` dictates the caller's preference for the key algorithm. It is a string with components separated by COLON U+003A `:`,
      * where each component specifies the name of an algorithm and will be attempted by the implementation in that order.
-     * The valid components are `"ec-p256"` for secp256r1, `"rsa-2048"` for 2048-bit RSA, `"rsa-4096"` for 4096-bit RSA, and `"*"` which indicates
+     * The valid components are `"ec-p256"` for secp256r1, `"ec-p384"` for secp384r1, `"ed25519"` for Ed25519,
+     * `"rsa-2048"` for 2048-bit RSA, `"rsa-4096"` for 4096-bit RSA, and `"*"` which indicates
      * any algorithm of the implementation's choice. For example, the caller might use `"ec-p256:rsa-2048:*"` to indicate that it would like
      * the implementation to use secp256r1, else RSA-2048 if that fails, else any other algorithm of the implementation's choice if that also fails.
      *
@@ -187,6 +198,7 @@ typedef struct {
      * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
      *   - `id` is `NULL`.
      *   - `id` is invalid.
+     *   - `usage` is empty.
      *
      * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
      */
@@ -218,7 +230,9 @@ typedef struct {
      * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
      *   - `id` is `NULL`.
      *   - `id` is invalid.
+     *   - `id` is a pre-loaded key.
      *   - `bytes` is `NULL`.
+     *   - `usage` is empty.
      *
      * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
      */
@@ -307,8 +321,9 @@ typedef struct {
      * `ok` is set to 0 if the signature is invalid and non-zero if the signature is valid.
      * The value stored in `ok` is only meaningful if the function returns `AZIOT_KEYS_RC_OK`, otherwise it must be ignored.
      *
-     * Note: The implementation is not required to support verification with key pairs, ie `AZIOT_KEYS_SIGN_MECHANISM_ECDSA`.
-     * The caller can do the verification themselves with the public parameters of the EC key as obtained via `get_key_pair_parameter`.
+     * Note: The implementation is not required to support verification with key pairs, ie `AZIOT_KEYS_SIGN_MECHANISM_ECDSA`,
+     * `AZIOT_KEYS_SIGN_MECHANISM_EDDSA`, `AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1` and `AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS`.
+     * The caller can do the verification themselves with the public parameters of the key pair as obtained via `get_key_pair_parameter`.
      *
      * # Errors
      *
@@ -390,240 +405,1251 @@ typedef struct {
 } AZIOT_KEYS_FUNCTION_LIST_2_0_0_0;
 
 /**
- * Used with `sign` / `verify` with the [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED`] mechanism.
+ * The kind of an entry reported by `enumerate_keys`.
+ *
+ * One of the `AZIOT_KEYS_KEY_KIND_*` constants.
  */
-typedef struct {
-    /**
-     * The data used to derive the new key.
-     */
-    const unsigned char *derivation_data;
-    /**
-     * The length of the `derivation_data` buffer.
-     */
-    uintptr_t derivation_data_len;
-    /**
-     * The signature mechanism to use with the derived key.
-     *
-     * One of the `AZIOT_KEYS_SIGN_MECHANISM_*` constants.
-     */
-    AZIOT_KEYS_SIGN_MECHANISM mechanism;
-    /**
-     * The parameters of the signature mechanism specified by `mechanism`.
-     */
-    const void *parameters;
-} AZIOT_KEYS_SIGN_DERIVED_PARAMETERS;
+typedef unsigned int AZIOT_KEYS_KEY_KIND;
 
 /**
- * Used with `encrypt` / `decrypt` with the [`AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD`] mechanism.
+ * The algorithm of a key pair, as returned by `get_key_pair_parameter`.
+ *
+ * One of the `AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_*` constants.
+ */
+typedef unsigned int AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM;
+
+/**
+ * Describes a key or key pair, as reported by `enumerate_keys`.
  */
 typedef struct {
     /**
-     * The IV.
-     */
-    const unsigned char *iv;
-    /**
-     * The length of the `iv` buffer.
-     */
-    uintptr_t iv_len;
-    /**
-     * The AAD.
+     * The ID of the key or key pair, as a NUL-terminated string.
      */
-    const unsigned char *aad;
+    const char *id;
     /**
-     * The length of the `aad` buffer.
+     * Whether this is a key or a key pair.
      */
-    uintptr_t aad_len;
-} AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS;
-
-/**
- * Used with `encrypt` / `decrypt` with the [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED`] mechanism.
- */
-typedef struct {
+    AZIOT_KEYS_KEY_KIND kind;
     /**
-     * The data used to derive the new key.
+     * The algorithm of the key pair. One of the `AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_*` constants.
+     *
+     * Always zero for keys.
      */
-    const unsigned char *derivation_data;
+    AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM algorithm;
     /**
-     * The length of the `derivation_data` buffer.
+     * The usage of the key. A combination of the `AZIOT_KEYS_KEY_USAGE_*` constants.
+     *
+     * Always zero for key pairs, and zero for keys whose usage is not known.
      */
-    uintptr_t derivation_data_len;
+    AZIOT_KEYS_KEY_USAGE usage;
     /**
-     * The encryption mechanism to use with the derived key.
+     * Where the key or key pair is stored, as a NUL-terminated string.
      *
-     * One of the `AZIOT_KEYS_ENCRYPT_MECHANISM_*` constants.
+     * This is a `file://` URI for keys in the filesystem, or a `pkcs11:` URI for keys in a PKCS#11 token.
+     * PKCS#11 URIs never contain the PIN.
      */
-    AZIOT_KEYS_ENCRYPT_MECHANISM mechanism;
+    const char *location;
     /**
-     * The parameters of the encryption mechanism specified by `mechanism`.
+     * Non-zero if the key or key pair was configured as a pre-loaded key.
      */
-    const void *parameters;
-} AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS;
-
-/**
- * The algorithm of a key pair, as returned by `get_key_pair_parameter`.
- *
- * One of the `AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_*` constants.
- */
-typedef unsigned int AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM;
-
-/**
- * The operation succeeded.
- */
-#define AZIOT_KEYS_RC_OK 0
-
-/**
- * The operation failed because a parameter has an invalid value.
- */
-#define AZIOT_KEYS_RC_ERR_INVALID_PARAMETER 1
-
-/**
- * The library encountered an error with an external resource, such as an I/O error or RPC error.
- */
-#define AZIOT_KEYS_RC_ERR_EXTERNAL 2
-
-/**
- * Version 2.0.0.0
- */
-#define AZIOT_KEYS_VERSION_2_0_0_0 33554432
-
-/**
- * Used as the parameter type with `get_key_pair_parameter` to get the key algorithm.
- *
- * The value returned by `get_key_pair_parameter` will be one of the `AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_*` constants.
- */
-#define AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_ALGORITHM 1
-
-/**
- * Used as the parameter type with `get_key_pair_parameter` to get the curve OID of an EC key.
- *
- * The value returned by `get_key_pair_parameter` will be a byte buffer containing a DER-encoded OID.
- */
-#define AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_EC_CURVE_OID 2
-
-/**
- * Used as the parameter type with `get_key_pair_parameter` to get the point of an EC key.
- *
- * The value returned by `get_key_pair_parameter` will be a byte buffer containing a DER-encoded octet string in RFC 5490 format.
- */
-#define AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_EC_POINT 3
-
-/**
- * Used as the parameter type with `get_key_pair_parameter` to get the modulus of an RSA key.
- *
- * The value returned by `get_key_pair_parameter` will be a byte buffer holding a big-endian bignum.
- */
-#define AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_RSA_MODULUS 4
-
-/**
- * Used as the parameter type with `get_key_pair_parameter` to get the exponent of an RSA key.
- *
- * The value returned by `get_key_pair_parameter` will be a byte buffer holding a big-endian bignum.
- */
-#define AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_RSA_EXPONENT 5
-
-/**
- * The key pair is an EC key.
- */
-#define AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_EC 1
-
-/**
- * The key pair is an RSA key.
- */
-#define AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_RSA 2
-
-/**
- * The key can be used for deriving other keys.
- *
- * Cannot be combined with [`AZIOT_KEYS_KEY_USAGE_ENCRYPT`]
- */
-#define AZIOT_KEYS_KEY_USAGE_DERIVE 1
-
-/**
- * The key can be used for encryption.
- *
- * Cannot be combined with [`AZIOT_KEYS_KEY_USAGE_DERIVE`] or [`AZIOT_KEYS_KEY_USAGE_SIGN`]
- */
-#define AZIOT_KEYS_KEY_USAGE_ENCRYPT 16
-
-/**
- * The key can be used for signing.
- *
- * Cannot be combined with [`AZIOT_KEYS_KEY_USAGE_ENCRYPT`]
- */
-#define AZIOT_KEYS_KEY_USAGE_SIGN AZIOT_KEYS_KEY_USAGE_DERIVE
+    int preloaded;
+} AZIOT_KEYS_KEY_INFO;
 
 /**
- * Used with `sign` / `verify` to sign / verify using ECDSA.
- *
- * The `parameters` parameter of `sign` / `verify` is unused and ignored.
+ * The callback invoked by `enumerate_keys` for each key or key pair.
  */
-#define AZIOT_KEYS_SIGN_MECHANISM_ECDSA 1
+typedef void (*AZIOT_KEYS_ENUMERATE_KEYS_CALLBACK)(void *context, const AZIOT_KEYS_KEY_INFO *info);
 
 /**
- * Used with `sign` / `verify` to sign / verify using HMAC-SHA256.
+ * The capabilities of an implementation, as reported in [`AZIOT_KEYS_FUNCTION_LIST_2_1_0_0`].
  *
- * The `parameters` parameter of `sign` / `verify` is unused and ignored.
+ * This is a bitflag type, so its values can be combined.
  */
-#define AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256 2
+typedef unsigned int AZIOT_KEYS_CAPABILITIES;
 
 /**
- * Used with `sign` / `verify` to sign / verify using a derived key.
+ * Used as the parameter type with `get_key_parameter`.
  *
- * The `id` parameter of `sign` / `verify` is set to the ID of the base key.
- * The `parameters` parameter of `sign` / `verify` must be set to an `AZIOT_KEYS_SIGN_DERIVED_PARAMETERS` value.
+ * One of the `AZIOT_KEYS_KEY_PARAMETER_TYPE_*` constants.
  */
-#define AZIOT_KEYS_SIGN_MECHANISM_DERIVED 3
+typedef unsigned int AZIOT_KEYS_KEY_PARAMETER_TYPE;
 
 /**
- * Used with `encrypt` / `decrypt` to encrypt / decrypt using an AEAD mechanism, like AES-GCM.
- *
- * The exact AEAD algorithm used is left to the implementation and need not always be the same.
- * The caller must not make any assumptions about the format of the ciphertext.
+ * The mechanism used with `export_key` / `export_key_pair` and `import_wrapped_key` / `import_wrapped_key_pair`.
  *
- * The `parameters` parameter of `encrypt` / `decrypt` must be set to an `AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS` value.
+ * One of the `AZIOT_KEYS_WRAP_MECHANISM_*` constants.
  */
-#define AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD 1
+typedef unsigned int AZIOT_KEYS_WRAP_MECHANISM;
 
 /**
- * Used with `encrypt` / `decrypt` to encrypt / decrypt using RSA with PKCS1 padding.
+ * The format of the private key passed to `import_key_pair`.
  *
- * The `parameters` parameter of `encrypt` / `decrypt` is unused and ignored.
+ * One of the `AZIOT_KEYS_KEY_PAIR_FORMAT_*` constants.
  */
-#define AZIOT_KEYS_ENCRYPT_MECHANISM_RSA_PKCS1 2
+typedef unsigned int AZIOT_KEYS_KEY_PAIR_FORMAT;
 
 /**
- * Used with `encrypt` / `decrypt` to encrypt / decrypt using RSA with no padding. Padding will have been performed by the caller.
+ * The mechanism used with `derive_shared_secret`.
  *
- * The `parameters` parameter of `encrypt` / `decrypt` is unused and ignored.
+ * One of the `AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_*` constants.
  */
-#define AZIOT_KEYS_ENCRYPT_MECHANISM_RSA_NO_PADDING 3
+typedef unsigned int AZIOT_KEYS_KEY_AGREEMENT_MECHANISM;
 
 /**
- * Used with `encrypt` / `decrypt` to encrypt / decrypt using a derived key.
+ * The mechanism used to derive a key from a base key, with `derive_key_with_mechanism` and
+ * the `AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM` / `AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM` mechanisms.
  *
- * The `id` parameter of `encrypt` / `decrypt` is set to the ID of the base key.
- * The `parameters` parameter of `encrypt` / `decrypt` must be set to an `AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS` value.
+ * One of the `AZIOT_KEYS_KEY_DERIVATION_MECHANISM_*` constants.
  */
-#define AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED 4
-
+typedef unsigned int AZIOT_KEYS_KEY_DERIVATION_MECHANISM;
 
 /**
- * Get the list of functions for operations corresponding to the specified version.
- *
- * Implementations can use this function for initialization, since it is guaranteed to be called before any operations.
- * However it is not an error to call this function multiple times, for the same or different version,
- * so implementations must ensure they only run their initialization once.
- *
- * The pointer returned from this function must not be freed by the caller, and its contents must not be mutated.
+ * The specific implementation of [`AZIOT_KEYS_FUNCTION_LIST`] for API version 2.1.0.0
  *
- * # Errors
- *
- * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
- *   - `version` is not recognized by this implementation.
- *   - `pfunction_list` is `NULL`.
+ * This is a superset of API version 2.0.0.0 that adds functions to delete, enumerate, move, export and import keys and key pairs,
+ * to get the parameters of keys, to perform ECDH key agreement, to derive keys with a specific derivation mechanism,
+ * and to perform multi-part operations, as well as the capabilities of the implementation.
+ * Keys derived with a specific derivation mechanism can also be used with the [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM`]
+ * and [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM`] mechanisms, which libraries that implement this version must support.
  */
-AZIOT_KEYS_RC aziot_keys_get_function_list(AZIOT_KEYS_VERSION version,
-                                           const AZIOT_KEYS_FUNCTION_LIST **pfunction_list);
+typedef struct {
+    /**
+     * The functions from API version 2.0.0.0. The value of `v2_0_0_0.base.version` must be [`AZIOT_KEYS_VERSION_2_1_0_0`],
+     * unless this function list is embedded in the function list of a later API version, in which case it must be that version.
+     *
+     * Since this is the first field, a pointer to this struct can also be used as a pointer to [`AZIOT_KEYS_FUNCTION_LIST`].
+     */
+    AZIOT_KEYS_FUNCTION_LIST_2_0_0_0 v2_0_0_0;
+    /**
+     * Delete an existing key pair identified by the specified `id`.
+     *
+     * This function succeeds if a key pair with the given ID does not exist.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - `id` is a pre-loaded key pair.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*delete_key_pair)(const char *id);
+    /**
+     * Delete an existing key identified by the specified `id`.
+     *
+     * This function succeeds if a key with the given ID does not exist.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - `id` is a pre-loaded key.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*delete_key)(const char *id);
+    /**
+     * Enumerate all keys and key pairs known to this library.
+     *
+     * This includes pre-loaded keys, keys and key pairs in the PKCS#11 base slot, and keys and key pairs in the filesystem.
+     * Filesystem keys and key pairs that were created by an older version of this library are only included
+     * if their ID can be recovered from their file name, or after they have been created again.
+     *
+     * `callback` is invoked once for each key or key pair, with `context` as its first parameter.
+     * The `info` pointer and all the strings it points to are only valid for the duration of that invocation.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `callback` is `NULL`.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*enumerate_keys)(AZIOT_KEYS_ENUMERATE_KEYS_CALLBACK callback, void *context);
+    /**
+     * The capabilities of this implementation.
+     *
+     * A combination of the `AZIOT_KEYS_CAPABILITY_*` constants.
+     */
+    AZIOT_KEYS_CAPABILITIES capabilities;
+    /**
+     * Get the value of a parameter of the key identified by the specified `id`.
+     *
+     * `type_` must be set to one of the `AZIOT_KEYS_KEY_PARAMETER_TYPE_*` constants.
+     *
+     * `value` is an output byte buffer allocated by the caller to store the parameter value.
+     * The caller sets `value_len` to the address of the length of the buffer.
+     * The implementation populates `value` with the parameter value and sets `value_len` to the number of bytes it wrote to `value`.
+     *
+     * It is allowed for the caller to call the function with `value` set to `NULL`. In this case the implementation calculates
+     * an upper bound for how many bytes will be needed to store the parameter value, sets that in `value_len` and returns.
+     *
+     * The format of the data stored in `value` is determined by the `type_`. See the documentation of those constants for details.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - The key specified by `id` does not exist.
+     *   - `type_` is not a valid parameter type.
+     *   - `value` is insufficiently large to hold the parameter value.
+     *   - `value_len` is `NULL`.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*get_key_parameter)(const char *id, AZIOT_KEYS_KEY_PARAMETER_TYPE type_, unsigned char *value, uintptr_t *value_len);
+    /**
+     * Export the key identified by the specified `id`, wrapped under the public key `wrapping_key`.
+     *
+     * `mechanism` must be set to one of the `AZIOT_KEYS_WRAP_MECHANISM_*` constants.
+     * `wrapping_key` is the DER-encoded `SubjectPublicKeyInfo` of the public key to wrap the key under. Its algorithm must match the `mechanism`.
+     *
+     * `wrapped_key` is an output byte buffer allocated by the caller to store the wrapped key.
+     * The caller sets `wrapped_key_len` to the address of the length of the buffer.
+     * The implementation populates `wrapped_key` with the wrapped key and sets `wrapped_key_len` to the number of bytes it wrote to `wrapped_key`.
+     *
+     * It is allowed for the caller to call the function with `wrapped_key` set to `NULL`. In this case the implementation calculates
+     * an upper bound for how many bytes will be needed to store the wrapped key, sets that in `wrapped_key_len` and returns.
+     *
+     * The wrapped key includes the usage of the key, and can be imported with `import_wrapped_key` using the private key of `wrapping_key`.
+     * Keys whose usage is not known, such as pre-loaded keys, cannot be exported.
+     * The implementation must not export keys that it cannot export securely, such as keys held in hardware that are not extractable.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - The key specified by `id` does not exist, or cannot be exported.
+     *   - `mechanism` is not recognized by this implementation.
+     *   - `wrapping_key` is `NULL`, invalid, or not valid for the `mechanism`.
+     *   - `wrapped_key` is insufficiently large to hold the wrapped key.
+     *   - `wrapped_key_len` is `NULL`.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*export_key)(const char *id, AZIOT_KEYS_WRAP_MECHANISM mechanism, const unsigned char *wrapping_key, uintptr_t wrapping_key_len, unsigned char *wrapped_key, uintptr_t *wrapped_key_len);
+    /**
+     * Export the private key of the key pair identified by the specified `id`, wrapped under the public key `wrapping_key`.
+     *
+     * The parameters have the same meaning as for `export_key`. The wrapped key pair can be imported with `import_wrapped_key_pair`.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - The key pair specified by `id` does not exist, or cannot be exported.
+     *   - `mechanism` is not recognized by this implementation.
+     *   - `wrapping_key` is `NULL`, invalid, or not valid for the `mechanism`.
+     *   - `wrapped_key` is insufficiently large to hold the wrapped key pair.
+     *   - `wrapped_key_len` is `NULL`.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*export_key_pair)(const char *id, AZIOT_KEYS_WRAP_MECHANISM mechanism, const unsigned char *wrapping_key, uintptr_t wrapping_key_len, unsigned char *wrapped_key, uintptr_t *wrapped_key_len);
+    /**
+     * Import a key that was exported with `export_key`, and save it such that it can be looked up later using the ID `id`.
+     *
+     * `unwrapping_key_pair_id` is the ID of the key pair whose public key the key was wrapped under.
+     * `mechanism` must be the same mechanism that the key was exported with.
+     *
+     * The key is imported with the usage that it was exported with. If a key with ID `id` already exists, it is replaced.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - `id` is a pre-loaded key.
+     *   - `unwrapping_key_pair_id` is `NULL`.
+     *   - The key pair specified by `unwrapping_key_pair_id` does not exist, or cannot be used to unwrap keys.
+     *   - `mechanism` is not recognized by this implementation.
+     *   - `wrapped_key` is `NULL`, or is not a key that was wrapped with the `mechanism` under the public key of `unwrapping_key_pair_id`.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*import_wrapped_key)(const char *id, const char *unwrapping_key_pair_id, AZIOT_KEYS_WRAP_MECHANISM mechanism, const unsigned char *wrapped_key, uintptr_t wrapped_key_len);
+    /**
+     * Import a key pair that was exported with `export_key_pair`, and save it such that it can be looked up later using the ID `id`.
+     *
+     * The parameters have the same meaning as for `import_wrapped_key`. If a key pair with ID `id` already exists, it is replaced.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - `id` is a pre-loaded key pair.
+     *   - `unwrapping_key_pair_id` is `NULL`.
+     *   - The key pair specified by `unwrapping_key_pair_id` does not exist, or cannot be used to unwrap keys.
+     *   - `mechanism` is not recognized by this implementation.
+     *   - `wrapped_key` is `NULL`, or is not a key pair that was wrapped with the `mechanism` under the public key of `unwrapping_key_pair_id`.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*import_wrapped_key_pair)(const char *id, const char *unwrapping_key_pair_id, AZIOT_KEYS_WRAP_MECHANISM mechanism, const unsigned char *wrapped_key, uintptr_t wrapped_key_len);
+    /**
+     * Import a key pair from the given private key, and save it such that it can be looked up later using the ID `id`.
+     *
+     * `format` must be set to one of the `AZIOT_KEYS_KEY_PAIR_FORMAT_*` constants, and `bytes` must contain the private key in that format.
+     *
+     * `password` is the password that the private key is encrypted with. It is required for `AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS12`,
+     * and may be set for the PKCS#8 formats if the private key is encrypted. Otherwise it must be `NULL`.
+     *
+     * The key pair is stored in the same location that `create_key_pair_if_not_exists` would have generated it in.
+     * If a key pair with ID `id` already exists, it is replaced.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - `id` is a pre-loaded key pair.
+     *   - `format` is not recognized by this implementation.
+     *   - `bytes` is `NULL`, or is not a private key in the given `format`.
+     *   - The private key is of a type that this implementation does not support.
+     *   - `password` is `NULL` but the private key is encrypted, or `password` is incorrect.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*import_key_pair)(const char *id, AZIOT_KEYS_KEY_PAIR_FORMAT format, const unsigned char *bytes, uintptr_t bytes_len, const char *password);
+    /**
+     * Perform a key agreement between the private key of the key pair identified by `id` and the peer public key `peer_public_key`,
+     * and store the resulting secret in `secret`.
+     *
+     * `peer_public_key` must be a DER-encoded `SubjectPublicKeyInfo` of an EC public key on the same curve as the key pair.
+     *
+     * `mechanism` must be set to one of the `AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_*` constants.
+     * `parameters` must be set based on the `mechanism`, as documented on the constants.
+     *
+     * If `secret` is `NULL`, `secret_len` is set to the length of the secret. Otherwise, `secret` must point to a buffer of
+     * `secret_len` bytes, and `secret_len` is set to the number of bytes written to it.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - The key pair specified by `id` does not exist, or is not an EC key pair.
+     *   - `mechanism` is not recognized by this implementation.
+     *   - `parameters` is invalid.
+     *   - `peer_public_key` is `NULL`, or is not an EC public key on the same curve as the key pair.
+     *   - `secret` is insufficiently large to hold the result.
+     *   - `secret_len` is `NULL`.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*derive_shared_secret)(const char *id, AZIOT_KEYS_KEY_AGREEMENT_MECHANISM mechanism, const void *parameters, const unsigned char *peer_public_key, uintptr_t peer_public_key_len, unsigned char *secret, uintptr_t *secret_len);
+    /**
+     * Import a symmetric key with the given `id`, unless a key with that ID already exists.
+     *
+     * This is identical to `import_key`, except that it fails instead of overwriting an existing key.
+     * Checking for the existing key and importing the new one happen atomically with respect to
+     * other calls that create, import or delete a key with the same ID.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - `id` is a pre-loaded key.
+     *   - A key with the ID specified by `id` already exists.
+     *   - `bytes` is `NULL`.
+     *   - `usage` is empty.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*import_key_if_not_exists)(const char *id, const uint8_t *bytes, uintptr_t bytes_len, AZIOT_KEYS_KEY_USAGE usage);
+    /**
+     * Derive a key with a given base key using some derivation data and the given derivation mechanism, and return the derived key.
+     *
+     * This is identical to `derive_key`, except that the derivation process is specified by `mechanism` instead of always being
+     * [`AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256`]. The derivation process must be identical to the one used by
+     * `encrypt` with the `AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM` mechanism and `sign` with the
+     * `AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM` mechanism for the same derivation mechanism and parameters.
+     *
+     * `mechanism` must be set to one of the `AZIOT_KEYS_KEY_DERIVATION_MECHANISM_*` constants.
+     * `parameters` must be set based on the `mechanism`, as documented on the constants.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `base_id` is `NULL`.
+     *   - `base_id` is invalid.
+     *   - The key specified by `base_id` does not exist.
+     *   - `mechanism` is not recognized by this implementation, or is not supported for the key specified by `base_id`.
+     *   - `parameters` is invalid.
+     *   - `derivation_data` is `NULL`.
+     *   - `derived_key` is insufficiently large to hold the parameter value.
+     *   - `derived_key_len` is `NULL`.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*derive_key_with_mechanism)(const char *base_id, AZIOT_KEYS_KEY_DERIVATION_MECHANISM mechanism, const void *parameters, const uint8_t *derivation_data, uintptr_t derivation_data_len, unsigned char *derived_key, uintptr_t *derived_key_len);
+    /**
+     * Start a multi-part encryption using the key identified by the specified `id`.
+     *
+     * This is the multi-part equivalent of `encrypt`, for plaintext that is too large to be passed to `encrypt` at once.
+     * The ciphertext produced by the multi-part operation is identical to the ciphertext that `encrypt` would produce
+     * for the whole plaintext with the same mechanism and parameters.
+     *
+     * `mechanism` and `parameters` are interpreted as for `encrypt`. Only [`AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD`],
+     * [`AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD`], [`AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR`], and [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED`]
+     * and [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM`] with one of those mechanisms support multi-part operations. The implementation may not support
+     * [`AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD`] for every key.
+     *
+     * On success, the implementation sets `stream` to a new stream. The caller passes the plaintext to `stream_update`,
+     * completes the operation with `stream_final`, reads the ciphertext with `stream_read`, and frees the stream with `stream_free`.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - The key specified by `id` does not exist.
+     *   - `mechanism` is not a valid encryption mechanism for the key specified by `id`, or does not support multi-part operations.
+     *   - `parameters` is invalid.
+     *   - `stream` is `NULL`.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*encrypt_init)(const char *id, AZIOT_KEYS_ENCRYPT_MECHANISM mechanism, const void *parameters, AZIOT_KEYS_STREAM **stream);
+    /**
+     * Start a multi-part decryption using the key identified by the specified `id`.
+     *
+     * This is the multi-part equivalent of `decrypt`. It accepts the same mechanisms as `encrypt_init`.
+     *
+     * For [`AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD`], the plaintext is only authenticated once the whole ciphertext has been received,
+     * so none of it can be read from the stream until `stream_final` succeeds.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - The key specified by `id` does not exist.
+     *   - `mechanism` is not a valid encryption mechanism for the key specified by `id`, or does not support multi-part operations.
+     *   - `parameters` is invalid.
+     *   - `stream` is `NULL`.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*decrypt_init)(const char *id, AZIOT_KEYS_ENCRYPT_MECHANISM mechanism, const void *parameters, AZIOT_KEYS_STREAM **stream);
+    /**
+     * Start a multi-part signature using the key identified by the specified `id`.
+     *
+     * This is the multi-part equivalent of `sign`, where the data passed to `stream_update` is treated as the `digest`.
+     * Only [`AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256`], [`AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA384`], [`AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA512`]
+     * and [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED`] and [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM`] with one of those mechanisms
+     * support multi-part operations.
+     * The signature can be read with `stream_read` after `stream_final` succeeds.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `id` is `NULL`.
+     *   - `id` is invalid.
+     *   - The key specified by `id` does not exist.
+     *   - `mechanism` is not a valid signing mechanism for the key specified by `id`, or does not support multi-part operations.
+     *   - `parameters` is invalid.
+     *   - `stream` is `NULL`.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*sign_init)(const char *id, AZIOT_KEYS_SIGN_MECHANISM mechanism, const void *parameters, AZIOT_KEYS_STREAM **stream);
+    /**
+     * Pass the next part of the input of the multi-part operation to the given stream.
+     *
+     * Any output produced by the operation is kept in the stream until it is read with `stream_read`.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `stream` is `NULL`.
+     *   - `stream_final` has already been called for `stream`.
+     *   - `data` is `NULL`.
+     *   - `data` is invalid for the operation.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*stream_update)(AZIOT_KEYS_STREAM *stream, const unsigned char *data, uintptr_t data_len);
+    /**
+     * Complete the multi-part operation of the given stream.
+     *
+     * Any remaining output produced by the operation is kept in the stream until it is read with `stream_read`.
+     * No more input can be passed to the stream after this function has been called, even if it fails.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `stream` is `NULL`.
+     *   - `stream_final` has already been called for `stream`.
+     *   - The input passed to the stream is invalid for the operation, such as a decryption whose ciphertext fails to authenticate.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*stream_final)(AZIOT_KEYS_STREAM *stream);
+    /**
+     * Read the output that the multi-part operation of the given stream has produced so far.
+     *
+     * `output` is an output byte buffer allocated by the caller to store the output.
+     * The caller sets `output_len` to the address of the length of the buffer.
+     * The implementation populates `output` with the output, sets `output_len` to the number of bytes it wrote to `output`,
+     * and removes those bytes from the stream.
+     *
+     * It is allowed for the caller to call the function with `output` set to `NULL`. In this case the implementation sets
+     * `output_len` to the number of bytes of output available in the stream and returns.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `stream` is `NULL`.
+     *   - `output` is insufficiently large to hold the output.
+     *   - `output_len` is `NULL`.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*stream_read)(AZIOT_KEYS_STREAM *stream, unsigned char *output, uintptr_t *output_len);
+    /**
+     * Free the given stream, abandoning its multi-part operation if it has not been completed.
+     *
+     * `stream` may be `NULL`, in which case this function does nothing.
+     */
+    void (*stream_free)(AZIOT_KEYS_STREAM *stream);
+    /**
+     * Move the key pair identified by `from_id` so that it is identified by `to_id` instead.
+     *
+     * If a key pair with `to_id` already exists, it is replaced. After this function succeeds, the key pair no longer exists with `from_id`.
+     *
+     * The private key never leaves the storage it was created in. In particular, a key pair that is stored in an HSM stays in the same HSM,
+     * so `from_id` and `to_id` must both be stored in it.
+     *
+     * # Errors
+     *
+     * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+     *   - `from_id` is `NULL`.
+     *   - `from_id` is invalid.
+     *   - The key pair specified by `from_id` does not exist.
+     *   - `to_id` is `NULL`.
+     *   - `to_id` is invalid.
+     *   - `from_id` or `to_id` identifies a preloaded key pair.
+     *   - `from_id` and `to_id` are stored in different locations, such as one on the filesystem and one in an HSM.
+     *
+     * - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+     */
+    AZIOT_KEYS_RC (*move_key_pair)(const char *from_id, const char *to_id);
+} AZIOT_KEYS_FUNCTION_LIST_2_1_0_0;
+
+/**
+ * How a key was created, as returned by `get_key_parameter`.
+ *
+ * One of the `AZIOT_KEYS_KEY_ORIGIN_*` constants.
+ */
+typedef unsigned int AZIOT_KEYS_KEY_ORIGIN;
+
+/**
+ * The digest algorithm used to compute the digest passed to `sign`.
+ *
+ * One of the `AZIOT_KEYS_DIGEST_ALGORITHM_*` constants.
+ */
+typedef unsigned int AZIOT_KEYS_DIGEST_ALGORITHM;
+
+/**
+ * Used with `sign` with the [`AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1`] mechanism.
+ */
+typedef struct {
+    /**
+     * The digest algorithm that was used to compute the digest.
+     *
+     * One of the `AZIOT_KEYS_DIGEST_ALGORITHM_*` constants.
+     */
+    AZIOT_KEYS_DIGEST_ALGORITHM digest_algorithm;
+} AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS;
+
+/**
+ * Used with `sign` with the [`AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS`] mechanism.
+ */
+typedef struct {
+    /**
+     * The digest algorithm that was used to compute the digest.
+     *
+     * One of the `AZIOT_KEYS_DIGEST_ALGORITHM_*` constants.
+     */
+    AZIOT_KEYS_DIGEST_ALGORITHM digest_algorithm;
+    /**
+     * The length of the salt, in bytes.
+     */
+    uintptr_t salt_len;
+} AZIOT_KEYS_SIGN_RSA_PSS_PARAMETERS;
+
+/**
+ * Used with `sign` / `verify` with the [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED`] mechanism.
+ */
+typedef struct {
+    /**
+     * The data used to derive the new key.
+     */
+    const unsigned char *derivation_data;
+    /**
+     * The length of the `derivation_data` buffer.
+     */
+    uintptr_t derivation_data_len;
+    /**
+     * The signature mechanism to use with the derived key.
+     *
+     * One of the `AZIOT_KEYS_SIGN_MECHANISM_*` constants.
+     */
+    AZIOT_KEYS_SIGN_MECHANISM mechanism;
+    /**
+     * The parameters of the signature mechanism specified by `mechanism`.
+     */
+    const void *parameters;
+} AZIOT_KEYS_SIGN_DERIVED_PARAMETERS;
+
+/**
+ * Used with `sign` / `verify` with the [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM`] mechanism.
+ */
+typedef struct {
+    /**
+     * The data used to derive the new key.
+     */
+    const unsigned char *derivation_data;
+    /**
+     * The length of the `derivation_data` buffer.
+     */
+    uintptr_t derivation_data_len;
+    /**
+     * The signature mechanism to use with the derived key.
+     *
+     * One of the `AZIOT_KEYS_SIGN_MECHANISM_*` constants.
+     */
+    AZIOT_KEYS_SIGN_MECHANISM mechanism;
+    /**
+     * The parameters of the signature mechanism specified by `mechanism`.
+     */
+    const void *parameters;
+    /**
+     * The mechanism used to derive the new key from the base key.
+     *
+     * One of the `AZIOT_KEYS_KEY_DERIVATION_MECHANISM_*` constants.
+     */
+    AZIOT_KEYS_KEY_DERIVATION_MECHANISM derivation_mechanism;
+    /**
+     * The parameters of the derivation mechanism specified by `derivation_mechanism`.
+     */
+    const void *derivation_parameters;
+} AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS;
+
+/**
+ * Used with `derive_shared_secret` with the [`AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_ECDH_HKDF`] mechanism.
+ */
+typedef struct {
+    /**
+     * The digest algorithm used by HKDF.
+     *
+     * One of the `AZIOT_KEYS_DIGEST_ALGORITHM_*` constants.
+     */
+    AZIOT_KEYS_DIGEST_ALGORITHM digest_algorithm;
+    /**
+     * The HKDF salt. May be `NULL` if `salt_len` is 0, in which case a string of zeros of the digest's length is used.
+     */
+    const unsigned char *salt;
+    /**
+     * The length of the `salt` buffer.
+     */
+    uintptr_t salt_len;
+    /**
+     * The HKDF info. May be `NULL` if `info_len` is 0.
+     */
+    const unsigned char *info;
+    /**
+     * The length of the `info` buffer.
+     */
+    uintptr_t info_len;
+    /**
+     * The length of the secret to derive, in bytes. Must be at most 255 times the digest's length.
+     */
+    uintptr_t output_len;
+} AZIOT_KEYS_ECDH_HKDF_PARAMETERS;
+
+/**
+ * Used with the [`AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HKDF`] derivation mechanism.
+ */
+typedef struct {
+    /**
+     * The digest algorithm used by HKDF.
+     *
+     * One of the `AZIOT_KEYS_DIGEST_ALGORITHM_*` constants.
+     */
+    AZIOT_KEYS_DIGEST_ALGORITHM digest_algorithm;
+    /**
+     * The HKDF salt. May be `NULL` if `salt_len` is 0, in which case a string of zeros of the digest's length is used.
+     */
+    const unsigned char *salt;
+    /**
+     * The length of the `salt` buffer.
+     */
+    uintptr_t salt_len;
+    /**
+     * The length of the key to derive, in bytes. Must be non-zero and at most 1024.
+     */
+    uintptr_t output_len;
+} AZIOT_KEYS_KEY_DERIVATION_HKDF_PARAMETERS;
+
+/**
+ * Used with the [`AZIOT_KEYS_KEY_DERIVATION_MECHANISM_KBKDF_COUNTER`] derivation mechanism.
+ */
+typedef struct {
+    /**
+     * The digest algorithm of the HMAC PRF.
+     *
+     * One of the `AZIOT_KEYS_DIGEST_ALGORITHM_*` constants.
+     */
+    AZIOT_KEYS_DIGEST_ALGORITHM digest_algorithm;
+    /**
+     * The context. May be `NULL` if `context_len` is 0.
+     */
+    const unsigned char *context;
+    /**
+     * The length of the `context` buffer.
+     */
+    uintptr_t context_len;
+    /**
+     * The length of the key to derive, in bytes. Must be non-zero and at most 1024.
+     */
+    uintptr_t output_len;
+} AZIOT_KEYS_KEY_DERIVATION_KBKDF_PARAMETERS;
+
+/**
+ * Used with `encrypt` / `decrypt` with the [`AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD`] mechanism.
+ */
+typedef struct {
+    /**
+     * The IV.
+     */
+    const unsigned char *iv;
+    /**
+     * The length of the `iv` buffer.
+     */
+    uintptr_t iv_len;
+    /**
+     * The AAD.
+     */
+    const unsigned char *aad;
+    /**
+     * The length of the `aad` buffer.
+     */
+    uintptr_t aad_len;
+} AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS;
+
+/**
+ * Used with `encrypt` / `decrypt` with the [`AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD`] and [`AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR`] mechanisms.
+ */
+typedef struct {
+    /**
+     * The IV. Must be 16 bytes.
+     */
+    const unsigned char *iv;
+    /**
+     * The length of the `iv` buffer.
+     */
+    uintptr_t iv_len;
+} AZIOT_KEYS_ENCRYPT_IV_PARAMETERS;
+
+/**
+ * Used with `encrypt` / `decrypt` with the [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED`] mechanism.
+ */
+typedef struct {
+    /**
+     * The data used to derive the new key.
+     */
+    const unsigned char *derivation_data;
+    /**
+     * The length of the `derivation_data` buffer.
+     */
+    uintptr_t derivation_data_len;
+    /**
+     * The encryption mechanism to use with the derived key.
+     *
+     * One of the `AZIOT_KEYS_ENCRYPT_MECHANISM_*` constants.
+     */
+    AZIOT_KEYS_ENCRYPT_MECHANISM mechanism;
+    /**
+     * The parameters of the encryption mechanism specified by `mechanism`.
+     */
+    const void *parameters;
+} AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS;
+
+/**
+ * Used with `encrypt` / `decrypt` with the [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM`] mechanism.
+ */
+typedef struct {
+    /**
+     * The data used to derive the new key.
+     */
+    const unsigned char *derivation_data;
+    /**
+     * The length of the `derivation_data` buffer.
+     */
+    uintptr_t derivation_data_len;
+    /**
+     * The encryption mechanism to use with the derived key.
+     *
+     * One of the `AZIOT_KEYS_ENCRYPT_MECHANISM_*` constants.
+     */
+    AZIOT_KEYS_ENCRYPT_MECHANISM mechanism;
+    /**
+     * The parameters of the encryption mechanism specified by `mechanism`.
+     */
+    const void *parameters;
+    /**
+     * The mechanism used to derive the new key from the base key.
+     *
+     * One of the `AZIOT_KEYS_KEY_DERIVATION_MECHANISM_*` constants.
+     */
+    AZIOT_KEYS_KEY_DERIVATION_MECHANISM derivation_mechanism;
+    /**
+     * The parameters of the derivation mechanism specified by `derivation_mechanism`.
+     */
+    const void *derivation_parameters;
+} AZIOT_KEYS_ENCRYPT_DERIVED_WITH_MECHANISM_PARAMETERS;
+
+/**
+ * The operation succeeded.
+ */
+#define AZIOT_KEYS_RC_OK 0
+
+/**
+ * The operation failed because a parameter has an invalid value.
+ */
+#define AZIOT_KEYS_RC_ERR_INVALID_PARAMETER 1
+
+/**
+ * The library encountered an error with an external resource, such as an I/O error or RPC error.
+ */
+#define AZIOT_KEYS_RC_ERR_EXTERNAL 2
+
+/**
+ * Version 2.0.0.0
+ */
+#define AZIOT_KEYS_VERSION_2_0_0_0 33554432
+
+/**
+ * Version 2.1.0.0
+ */
+#define AZIOT_KEYS_VERSION_2_1_0_0 33619968
+
+/**
+ * The implementation has no optional capabilities.
+ */
+#define AZIOT_KEYS_CAPABILITIES_NONE 0
+
+/**
+ * The functions of the implementation can be called concurrently from multiple threads.
+ *
+ * If an implementation does not declare this capability, the caller must ensure that only one of its functions
+ * is executing at any time.
+ */
+#define AZIOT_KEYS_CAPABILITY_THREAD_SAFE 1
+
+/**
+ * Used as the parameter type with `get_key_pair_parameter` to get the key algorithm.
+ *
+ * The value returned by `get_key_pair_parameter` will be one of the `AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_*` constants.
+ */
+#define AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_ALGORITHM 1
+
+/**
+ * Used as the parameter type with `get_key_pair_parameter` to get the curve OID of an EC key.
+ *
+ * The value returned by `get_key_pair_parameter` will be a byte buffer containing a DER-encoded OID.
+ * For Ed25519 keys, this is the OID of the Ed25519 algorithm itself.
+ */
+#define AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_EC_CURVE_OID 2
+
+/**
+ * Used as the parameter type with `get_key_pair_parameter` to get the point of an EC key.
+ *
+ * The value returned by `get_key_pair_parameter` will be a byte buffer containing a DER-encoded octet string in RFC 5490 format.
+ * For Ed25519 keys, it will instead be the 32-byte raw public key in RFC 8032 format.
+ */
+#define AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_EC_POINT 3
+
+/**
+ * Used as the parameter type with `get_key_pair_parameter` to get the modulus of an RSA key.
+ *
+ * The value returned by `get_key_pair_parameter` will be a byte buffer holding a big-endian bignum.
+ */
+#define AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_RSA_MODULUS 4
+
+/**
+ * Used as the parameter type with `get_key_pair_parameter` to get the exponent of an RSA key.
+ *
+ * The value returned by `get_key_pair_parameter` will be a byte buffer holding a big-endian bignum.
+ */
+#define AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_RSA_EXPONENT 5
+
+/**
+ * Used as the parameter type with `get_key_parameter` to get the usage of a key.
+ *
+ * The value returned by `get_key_parameter` will be a combination of the `AZIOT_KEYS_KEY_USAGE_*` constants.
+ * It will be zero if the usage of the key is not known, such as for pre-loaded keys; such keys can be used for any operation.
+ */
+#define AZIOT_KEYS_KEY_PARAMETER_TYPE_USAGE 1
+
+/**
+ * Used as the parameter type with `get_key_parameter` to get how a key was created.
+ *
+ * The value returned by `get_key_parameter` will be one of the `AZIOT_KEYS_KEY_ORIGIN_*` constants.
+ */
+#define AZIOT_KEYS_KEY_PARAMETER_TYPE_ORIGIN 2
+
+/**
+ * Used as the parameter type with `get_key_parameter` to get when a key was created.
+ *
+ * The value returned by `get_key_parameter` will be a `uint64_t` holding the number of seconds since the Unix epoch.
+ * It will be zero if the creation time of the key is not known.
+ */
+#define AZIOT_KEYS_KEY_PARAMETER_TYPE_CREATION_TIME 3
+
+/**
+ * It is not known how the key was created.
+ */
+#define AZIOT_KEYS_KEY_ORIGIN_UNKNOWN 0
+
+/**
+ * The key was generated by the implementation or by the hardware that stores it, so its value was never known to the caller.
+ */
+#define AZIOT_KEYS_KEY_ORIGIN_GENERATED 1
+
+/**
+ * The key was imported, such as with `import_key`.
+ */
+#define AZIOT_KEYS_KEY_ORIGIN_IMPORTED 2
+
+/**
+ * The key pair is an EC key.
+ */
+#define AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_EC 1
+
+/**
+ * The key pair is an RSA key.
+ */
+#define AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_RSA 2
+
+/**
+ * The key pair is an Ed25519 key.
+ */
+#define AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_ED25519 3
+
+/**
+ * The key can be used for deriving other keys.
+ *
+ * Cannot be combined with [`AZIOT_KEYS_KEY_USAGE_ENCRYPT`]
+ */
+#define AZIOT_KEYS_KEY_USAGE_DERIVE 1
+
+/**
+ * The key can be used for encryption.
+ *
+ * Cannot be combined with [`AZIOT_KEYS_KEY_USAGE_DERIVE`] or [`AZIOT_KEYS_KEY_USAGE_SIGN`]
+ */
+#define AZIOT_KEYS_KEY_USAGE_ENCRYPT 16
+
+/**
+ * The key can be used for signing.
+ *
+ * Cannot be combined with [`AZIOT_KEYS_KEY_USAGE_ENCRYPT`]
+ */
+#define AZIOT_KEYS_KEY_USAGE_SIGN AZIOT_KEYS_KEY_USAGE_DERIVE
+
+/**
+ * Used with `sign` / `verify` to sign / verify using ECDSA.
+ *
+ * The `parameters` parameter of `sign` / `verify` is unused and ignored.
+ */
+#define AZIOT_KEYS_SIGN_MECHANISM_ECDSA 1
+
+/**
+ * Used with `sign` / `verify` to sign / verify using HMAC-SHA256.
+ *
+ * The `parameters` parameter of `sign` / `verify` is unused and ignored.
+ */
+#define AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256 2
+
+/**
+ * Used with `sign` / `verify` to sign / verify using a derived key.
+ *
+ * The `id` parameter of `sign` / `verify` is set to the ID of the base key.
+ * The `parameters` parameter of `sign` / `verify` must be set to an `AZIOT_KEYS_SIGN_DERIVED_PARAMETERS` value.
+ */
+#define AZIOT_KEYS_SIGN_MECHANISM_DERIVED 3
+
+/**
+ * Used with `sign` to sign using EdDSA (Ed25519).
+ *
+ * Unlike the other mechanisms, the `digest` parameter of `sign` is the message itself, not a digest of it.
+ *
+ * The `parameters` parameter of `sign` is unused and ignored.
+ */
+#define AZIOT_KEYS_SIGN_MECHANISM_EDDSA 4
+
+/**
+ * Used with `sign` to sign using RSASSA-PKCS1-v1_5.
+ *
+ * The `digest` parameter of `sign` is the digest of the message, computed by the caller with the digest algorithm
+ * specified in `parameters`. The implementation wraps it in a `DigestInfo` before signing it.
+ *
+ * The `parameters` parameter of `sign` must be set to an `AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS` value.
+ */
+#define AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1 5
+
+/**
+ * Used with `sign` to sign using RSASSA-PSS.
+ *
+ * The `digest` parameter of `sign` is the digest of the message, computed by the caller with the digest algorithm
+ * specified in `parameters`. MGF1 uses the same digest algorithm.
+ *
+ * The `parameters` parameter of `sign` must be set to an `AZIOT_KEYS_SIGN_RSA_PSS_PARAMETERS` value.
+ */
+#define AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS 6
+
+/**
+ * Used with `sign` / `verify` to sign / verify using HMAC-SHA384.
+ *
+ * The `parameters` parameter of `sign` / `verify` is unused and ignored.
+ */
+#define AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA384 7
+
+/**
+ * Used with `sign` / `verify` to sign / verify using HMAC-SHA512.
+ *
+ * The `parameters` parameter of `sign` / `verify` is unused and ignored.
+ */
+#define AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA512 8
+
+/**
+ * Used with `sign` / `verify` to sign / verify using a key derived with a specific derivation mechanism.
+ *
+ * This is identical to [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED`], except that the derivation mechanism is specified by
+ * the parameters instead of always being [`AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256`]. Added in API version 2.1.0.0.
+ *
+ * The `id` parameter of `sign` / `verify` is set to the ID of the base key.
+ * The `parameters` parameter of `sign` / `verify` must be set to an `AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS` value.
+ */
+#define AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM 9
+
+/**
+ * SHA-256
+ */
+#define AZIOT_KEYS_DIGEST_ALGORITHM_SHA256 1
+
+/**
+ * SHA-384
+ */
+#define AZIOT_KEYS_DIGEST_ALGORITHM_SHA384 2
+
+/**
+ * SHA-512
+ */
+#define AZIOT_KEYS_DIGEST_ALGORITHM_SHA512 3
+
+/**
+ * A PEM-encoded PKCS#8 `PrivateKeyInfo` or `EncryptedPrivateKeyInfo`. Traditional (PKCS#1 or SEC1) PEM private keys are also accepted.
+ */
+#define AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_PEM 1
+
+/**
+ * A DER-encoded PKCS#8 `PrivateKeyInfo` or `EncryptedPrivateKeyInfo`.
+ */
+#define AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_DER 2
+
+/**
+ * A DER-encoded PKCS#12 archive containing a private key. Any certificates in the archive are ignored.
+ */
+#define AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS12 3
+
+/**
+ * Wrap the key with a random content-encryption key, which is itself encrypted with the RSA wrapping key using RSA-OAEP with SHA-256.
+ */
+#define AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP 1
+
+/**
+ * Wrap the key with a content-encryption key derived from an ECDH key agreement between an ephemeral key and the EC wrapping key.
+ */
+#define AZIOT_KEYS_WRAP_MECHANISM_ECDH 2
+
+/**
+ * ECDH. The secret is the raw shared secret, ie the X coordinate of the shared point.
+ *
+ * The `parameters` parameter of `derive_shared_secret` must be `NULL`.
+ */
+#define AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_ECDH 1
+
+/**
+ * ECDH followed by HKDF. The secret is the output of HKDF with the raw shared secret as the input keying material.
+ *
+ * The `parameters` parameter of `derive_shared_secret` must be set to a pointer to an [`AZIOT_KEYS_ECDH_HKDF_PARAMETERS`] value.
+ */
+#define AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_ECDH_HKDF 2
+
+/**
+ * The derived key is HMAC-SHA256(base key, derivation data). This is the mechanism used by `derive_key`.
+ *
+ * The derivation parameters are unused and ignored.
+ */
+#define AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256 0
+
+/**
+ * The derived key is the output of RFC 5869 HKDF, with the base key as the input keying material and the derivation data as the info.
+ *
+ * The derivation parameters must be set to a pointer to an [`AZIOT_KEYS_KEY_DERIVATION_HKDF_PARAMETERS`] value.
+ *
+ * Not supported for base keys stored in PKCS#11, since HKDF uses the base key as the message of the extract step rather than as an HMAC key.
+ */
+#define AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HKDF 1
+
+/**
+ * The derived key is the output of the NIST SP 800-108 KDF in counter mode, with HMAC as the PRF keyed with the base key,
+ * and the derivation data as the label.
+ *
+ * The input to the PRF for each block is `[i]_32 || label || 0x00 || context || [L]_32`, where `i` is the 1-based block counter
+ * and `L` is the length of the derived key in bits, both encoded as 32-bit big-endian integers.
+ *
+ * The derivation parameters must be set to a pointer to an [`AZIOT_KEYS_KEY_DERIVATION_KBKDF_PARAMETERS`] value.
+ */
+#define AZIOT_KEYS_KEY_DERIVATION_MECHANISM_KBKDF_COUNTER 2
+
+/**
+ * Used with `encrypt` / `decrypt` to encrypt / decrypt using an AEAD mechanism, like AES-GCM.
+ *
+ * The exact AEAD algorithm used is left to the implementation and need not always be the same.
+ * The caller must not make any assumptions about the format of the ciphertext.
+ *
+ * The `parameters` parameter of `encrypt` / `decrypt` must be set to an `AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS` value.
+ */
+#define AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD 1
+
+/**
+ * Used with `encrypt` / `decrypt` to encrypt / decrypt using RSA with PKCS1 padding.
+ *
+ * The `parameters` parameter of `encrypt` / `decrypt` is unused and ignored.
+ */
+#define AZIOT_KEYS_ENCRYPT_MECHANISM_RSA_PKCS1 2
+
+/**
+ * Used with `encrypt` / `decrypt` to encrypt / decrypt using RSA with no padding. Padding will have been performed by the caller.
+ *
+ * The `parameters` parameter of `encrypt` / `decrypt` is unused and ignored.
+ */
+#define AZIOT_KEYS_ENCRYPT_MECHANISM_RSA_NO_PADDING 3
+
+/**
+ * Used with `encrypt` / `decrypt` to encrypt / decrypt using a derived key.
+ *
+ * The `id` parameter of `encrypt` / `decrypt` is set to the ID of the base key.
+ * The `parameters` parameter of `encrypt` / `decrypt` must be set to an `AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS` value.
+ */
+#define AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED 4
+
+/**
+ * Used with `encrypt` / `decrypt` to encrypt / decrypt using AES-CBC with PKCS#7 padding.
+ *
+ * The AES key size is that of the key, so the key must be 16, 24 or 32 bytes. The ciphertext has no header or trailer,
+ * so it can be exchanged with other AES-CBC implementations.
+ *
+ * The `parameters` parameter of `encrypt` / `decrypt` must be set to an `AZIOT_KEYS_ENCRYPT_IV_PARAMETERS` value.
+ */
+#define AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD 5
+
+/**
+ * Used with `encrypt` / `decrypt` to encrypt / decrypt using AES-CTR.
+ *
+ * The IV is the initial 16-byte counter block, and the whole block is incremented as a 128-bit big-endian counter.
+ * The AES key size is that of the key, so the key must be 16, 24 or 32 bytes.
+ *
+ * The `parameters` parameter of `encrypt` / `decrypt` must be set to an `AZIOT_KEYS_ENCRYPT_IV_PARAMETERS` value.
+ */
+#define AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR 6
+
+/**
+ * Used with `encrypt` / `decrypt` to wrap / unwrap key material using the RFC 3394 AES key wrap algorithm with the default IV.
+ *
+ * The plaintext must be a multiple of 8 bytes and at least 16 bytes long.
+ * The AES key size is that of the key, so the key must be 16, 24 or 32 bytes.
+ *
+ * The `parameters` parameter of `encrypt` / `decrypt` is unused and ignored.
+ */
+#define AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP 7
+
+/**
+ * Used with `encrypt` / `decrypt` to wrap / unwrap key material of any non-zero length using the RFC 5649 AES key wrap with padding algorithm.
+ *
+ * The AES key size is that of the key, so the key must be 16, 24 or 32 bytes.
+ *
+ * The `parameters` parameter of `encrypt` / `decrypt` is unused and ignored.
+ */
+#define AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP_PAD 8
+
+/**
+ * Used with `encrypt` / `decrypt` to encrypt / decrypt using a key derived with a specific derivation mechanism.
+ *
+ * This is identical to [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED`], except that the derivation mechanism is specified by
+ * the parameters instead of always being [`AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256`]. Added in API version 2.1.0.0.
+ *
+ * The `id` parameter of `encrypt` / `decrypt` is set to the ID of the base key.
+ * The `parameters` parameter of `encrypt` / `decrypt` must be set to an `AZIOT_KEYS_ENCRYPT_DERIVED_WITH_MECHANISM_PARAMETERS` value.
+ */
+#define AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM 9
+
+/**
+ * The entry is a symmetric key.
+ */
+#define AZIOT_KEYS_KEY_KIND_KEY 1
+
+/**
+ * The entry is an asymmetric key pair.
+ */
+#define AZIOT_KEYS_KEY_KIND_KEY_PAIR 2
+
+
+
+
+
+
+
+
+
+
+
+/**
+ * Get the list of functions for operations corresponding to the specified version.
+ *
+ * Implementations can use this function for initialization, since it is guaranteed to be called before any operations.
+ * However it is not an error to call this function multiple times, for the same or different version,
+ * so implementations must ensure they only run their initialization once.
+ *
+ * The pointer returned from this function must not be freed by the caller, and its contents must not be mutated.
+ *
+ * # Errors
+ *
+ * - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+ *   - `version` is not recognized by this implementation.
+ *   - `pfunction_list` is `NULL`.
+ */
+AZIOT_KEYS_RC aziot_keys_get_function_list(AZIOT_KEYS_VERSION version,
+                                           const AZIOT_KEYS_FUNCTION_LIST **pfunction_list);
+
+
+
+
+
+
+
 
 
 
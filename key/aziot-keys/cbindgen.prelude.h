@@ -12,7 +12,9 @@
  * that this library exports, as well as the function pointers to the key operations. See its docs for more details.
  *
  * All calls to [`aziot_keys_get_function_list`] or any function in [`AZIOT_KEYS_FUNCTION_LIST`] are serialized, ie a function will not be called
- * while another function is running. However, it is not guaranteed that all function calls will be made from the same operating system thread.
- * Thus, implementations do not need to worry about locking to prevent concurrent access, but should also not store data in thread-local storage.
+ * while another function is running, unless the implementation advertises [`AZIOT_KEYS_CAPABILITY_THREAD_SAFE`] in its function list.
+ * However, it is not guaranteed that all function calls will be made from the same operating system thread.
+ * Thus, implementations that do not advertise thread-safety do not need to worry about locking to prevent concurrent access,
+ * but should also not store data in thread-local storage.
  */
 
@@ -0,0 +1,404 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! RFC 3394 AES key wrap and RFC 5649 AES key wrap with padding.
+
+/// The default IV of RFC 3394.
+const DEFAULT_IV: [u8; 8] = [0xa6; 8];
+
+/// The constant high half of the alternative IV of RFC 5649. The low half is the length of the plaintext.
+const ALTERNATIVE_IV_PREFIX: [u8; 4] = [0xa6, 0x59, 0x59, 0xa6];
+
+/// RFC 3394 key wrap with the default IV. The plaintext must be a multiple of 8 bytes and at least 16 bytes long.
+pub(crate) fn wrap(key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    if plaintext.len() < 16 || plaintext.len() % 8 != 0 {
+        return Err(crate::implementation::err_invalid_parameter(
+            "plaintext",
+            "AES key wrap requires a multiple of 8 bytes and at least 16 bytes",
+        ));
+    }
+
+    w(key, DEFAULT_IV, plaintext)
+}
+
+/// RFC 3394 key unwrap with the default IV.
+pub(crate) fn unwrap(key: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    if ciphertext.len() < 24 || ciphertext.len() % 8 != 0 {
+        return Err(crate::implementation::err_invalid_parameter(
+            "ciphertext",
+            "malformed",
+        ));
+    }
+
+    let (iv, plaintext) = w_inverse(key, ciphertext)?;
+    if !openssl::memcmp::eq(&iv, &DEFAULT_IV) {
+        return Err(crate::implementation::err_invalid_parameter(
+            "ciphertext",
+            "integrity check failed",
+        ));
+    }
+
+    Ok(plaintext)
+}
+
+/// RFC 5649 key wrap with padding. The plaintext can be of any non-zero length.
+pub(crate) fn wrap_pad(key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    let mli: u32 = std::convert::TryInto::try_into(plaintext.len())
+        .ok()
+        .filter(|&mli| mli > 0)
+        .ok_or_else(|| {
+            crate::implementation::err_invalid_parameter(
+                "plaintext",
+                "AES key wrap with padding requires between 1 and 2^32 - 1 bytes",
+            )
+        })?;
+
+    let mut iv = [0_u8; 8];
+    iv[..4].copy_from_slice(&ALTERNATIVE_IV_PREFIX);
+    iv[4..].copy_from_slice(&mli.to_be_bytes());
+
+    let mut padded = plaintext.to_owned();
+    padded.resize(plaintext.len().div_ceil(8) * 8, 0);
+
+    if padded.len() == 8 {
+        // A single block is encrypted directly with AES in ECB mode.
+        let mut block = [0_u8; 16];
+        block[..8].copy_from_slice(&iv);
+        block[8..].copy_from_slice(&padded);
+        let mut crypter = ecb_crypter(key, openssl::symm::Mode::Encrypt)?;
+        let block = crypt_block(&mut crypter, &block)?;
+        Ok(block.to_vec())
+    } else {
+        w(key, iv, &padded)
+    }
+}
+
+/// RFC 5649 key unwrap with padding.
+pub(crate) fn unwrap_pad(key: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    if ciphertext.len() < 16 || ciphertext.len() % 8 != 0 {
+        return Err(crate::implementation::err_invalid_parameter(
+            "ciphertext",
+            "malformed",
+        ));
+    }
+
+    let (iv, mut padded) = if ciphertext.len() == 16 {
+        let mut crypter = ecb_crypter(key, openssl::symm::Mode::Decrypt)?;
+        let block = crypt_block(&mut crypter, ciphertext)?;
+        let mut iv = [0_u8; 8];
+        iv.copy_from_slice(&block[..8]);
+        (iv, block[8..].to_vec())
+    } else {
+        w_inverse(key, ciphertext)?
+    };
+
+    let integrity_check_failed =
+        || crate::implementation::err_invalid_parameter("ciphertext", "integrity check failed");
+
+    if !openssl::memcmp::eq(&iv[..4], &ALTERNATIVE_IV_PREFIX) {
+        return Err(integrity_check_failed());
+    }
+
+    let mut mli = [0_u8; 4];
+    mli.copy_from_slice(&iv[4..]);
+    let mli: usize = std::convert::TryInto::try_into(u32::from_be_bytes(mli))
+        .map_err(|_| integrity_check_failed())?;
+    if mli + 8 <= padded.len() || mli > padded.len() {
+        return Err(integrity_check_failed());
+    }
+    if padded[mli..].iter().any(|&b| b != 0) {
+        return Err(integrity_check_failed());
+    }
+
+    padded.truncate(mli);
+    Ok(padded)
+}
+
+/// The wrapping process W of RFC 3394 section 2.2.1, with the given initial value.
+fn w(key: &[u8], iv: [u8; 8], plaintext: &[u8]) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    let mut crypter = ecb_crypter(key, openssl::symm::Mode::Encrypt)?;
+
+    let n = plaintext.len() / 8;
+    let mut a = iv;
+    let mut r = plaintext.to_owned();
+
+    let mut t = 1_u64;
+    for _ in 0..6 {
+        for r_i in r.chunks_exact_mut(8) {
+            let mut block = [0_u8; 16];
+            block[..8].copy_from_slice(&a);
+            block[8..].copy_from_slice(r_i);
+            let block = crypt_block(&mut crypter, &block)?;
+
+            a.copy_from_slice(&block[..8]);
+            xor_counter(&mut a, t);
+            r_i.copy_from_slice(&block[8..]);
+
+            t += 1;
+        }
+    }
+    debug_assert_eq!(t, 6 * (n as u64) + 1);
+
+    let mut ciphertext = Vec::with_capacity(8 + r.len());
+    ciphertext.extend_from_slice(&a);
+    ciphertext.extend_from_slice(&r);
+    Ok(ciphertext)
+}
+
+/// The unwrapping process W^-1 of RFC 3394 section 2.2.2. Returns the recovered initial value and plaintext.
+fn w_inverse(key: &[u8], ciphertext: &[u8]) -> Result<([u8; 8], Vec<u8>), crate::AZIOT_KEYS_RC> {
+    let mut crypter = ecb_crypter(key, openssl::symm::Mode::Decrypt)?;
+
+    let (a_0, r) = ciphertext.split_at(8);
+    let n = r.len() / 8;
+    let mut a = [0_u8; 8];
+    a.copy_from_slice(a_0);
+    let mut r = r.to_owned();
+
+    let mut t = 6 * (n as u64);
+    for _ in 0..6 {
+        for r_i in r.chunks_exact_mut(8).rev() {
+            let mut block = [0_u8; 16];
+            block[..8].copy_from_slice(&a);
+            xor_counter(&mut block[..8], t);
+            block[8..].copy_from_slice(r_i);
+            let block = crypt_block(&mut crypter, &block)?;
+
+            a.copy_from_slice(&block[..8]);
+            r_i.copy_from_slice(&block[8..]);
+
+            t -= 1;
+        }
+    }
+
+    Ok((a, r))
+}
+
+fn xor_counter(a: &mut [u8], t: u64) {
+    for (a, t) in a.iter_mut().zip(&t.to_be_bytes()) {
+        *a ^= t;
+    }
+}
+
+fn ecb_crypter(
+    key: &[u8],
+    mode: openssl::symm::Mode,
+) -> Result<openssl::symm::Crypter, crate::AZIOT_KEYS_RC> {
+    let cipher = match key.len() {
+        16 => openssl::symm::Cipher::aes_128_ecb(),
+        24 => openssl::symm::Cipher::aes_192_ecb(),
+        32 => openssl::symm::Cipher::aes_256_ecb(),
+        _ => {
+            return Err(crate::implementation::err_invalid_parameter(
+                "id",
+                "key is not a valid AES key",
+            ))
+        }
+    };
+
+    let mut crypter = openssl::symm::Crypter::new(cipher, mode, key, None)?;
+    crypter.pad(false);
+    Ok(crypter)
+}
+
+fn crypt_block(
+    crypter: &mut openssl::symm::Crypter,
+    block: &[u8],
+) -> Result<[u8; 16], crate::AZIOT_KEYS_RC> {
+    let mut out = [0_u8; 32];
+    let out_len = crypter.update(block, &mut out)?;
+    assert_eq!(out_len, 16);
+
+    let mut result = [0_u8; 16];
+    result.copy_from_slice(&out[..16]);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn rfc3394() {
+        // RFC 3394 section 4
+        let kek_128: Vec<u8> = (0x00..=0x0f).collect();
+        let kek_192: Vec<u8> = (0x00..=0x17).collect();
+        let kek_256: Vec<u8> = (0x00..=0x1f).collect();
+        let key_data_128 = hex::decode("00112233445566778899aabbccddeeff").unwrap();
+        let key_data_192 = hex::decode("00112233445566778899aabbccddeeff0001020304050607").unwrap();
+        let key_data_256 =
+            hex::decode("00112233445566778899aabbccddeeff000102030405060708090a0b0c0d0e0f")
+                .unwrap();
+
+        let test_cases = &[
+            (
+                &kek_128,
+                &key_data_128,
+                "1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5",
+            ),
+            (
+                &kek_192,
+                &key_data_128,
+                "96778b25ae6ca435f92b5b97c050aed2468ab8a17ad84e5d",
+            ),
+            (
+                &kek_256,
+                &key_data_128,
+                "64e8c3f9ce0f5ba263e9777905818a2a93c8191e7d6e8ae7",
+            ),
+            (
+                &kek_192,
+                &key_data_192,
+                "031d33264e15d33268f24ec260743edce1c6c7ddee725a936ba814915c6762d2",
+            ),
+            (
+                &kek_256,
+                &key_data_192,
+                "a8f9bc1612c68b3ff6e6f4fbe30e71e4769c8b80a32cb8958cd5d17d6b254da1",
+            ),
+            (
+                &kek_256,
+                &key_data_256,
+                "28c9f404c4b810f4cbccb35cfb87f8263f5786e2d80ed326cbc7f0e71a99f43bfb988b9b7a02dd21",
+            ),
+        ];
+
+        for &(kek, key_data, expected_ciphertext) in test_cases {
+            let expected_ciphertext = hex::decode(expected_ciphertext).unwrap();
+            assert_eq!(super::wrap(kek, key_data).unwrap(), expected_ciphertext);
+            assert_eq!(&super::unwrap(kek, &expected_ciphertext).unwrap(), key_data);
+
+            let mut tampered = expected_ciphertext.clone();
+            *tampered.last_mut().unwrap() ^= 0x01;
+            assert_eq!(
+                super::unwrap(kek, &tampered).unwrap_err(),
+                crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+            );
+        }
+    }
+
+    #[test]
+    fn rfc3394_invalid_lengths() {
+        let kek: Vec<u8> = (0x00..=0x0f).collect();
+
+        for len in &[0, 8, 15, 17] {
+            assert_eq!(
+                super::wrap(&kek, &vec![0; *len]).unwrap_err(),
+                crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+            );
+        }
+        for len in &[0, 16, 23, 25] {
+            assert_eq!(
+                super::unwrap(&kek, &vec![0; *len]).unwrap_err(),
+                crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+            );
+        }
+
+        assert_eq!(
+            super::wrap(&kek[..15], &[0; 16]).unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn rfc5649() {
+        // RFC 5649 section 6
+        let kek = hex::decode("5840df6e29b02af1ab493b705bf16ea1ae8338f4dcc176a8").unwrap();
+
+        let test_cases = &[
+            (
+                "c37b7e6492584340bed12207808941155068f738",
+                "138bdeaa9b8fa7fc61f97742e72248ee5ae6ae5360d1ae6a5f54f373fa543b6a",
+            ),
+            ("466f7250617369", "afbeb0f07dfbf5419200f2ccb50bb24f"),
+        ];
+
+        for &(key_data, expected_ciphertext) in test_cases {
+            let key_data = hex::decode(key_data).unwrap();
+            let expected_ciphertext = hex::decode(expected_ciphertext).unwrap();
+            assert_eq!(
+                super::wrap_pad(&kek, &key_data).unwrap(),
+                expected_ciphertext
+            );
+            assert_eq!(
+                super::unwrap_pad(&kek, &expected_ciphertext).unwrap(),
+                key_data
+            );
+
+            let mut tampered = expected_ciphertext.clone();
+            tampered[0] ^= 0x01;
+            assert_eq!(
+                super::unwrap_pad(&kek, &tampered).unwrap_err(),
+                crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+            );
+        }
+
+        assert_eq!(
+            super::wrap_pad(&kek, &[]).unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        for len in &[0, 8, 17] {
+            assert_eq!(
+                super::unwrap_pad(&kek, &vec![0; *len]).unwrap_err(),
+                crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+            );
+        }
+    }
+
+    #[test]
+    fn rfc5649_padding_check() {
+        let kek: Vec<u8> = (0x00..=0x0f).collect();
+
+        let alternative_iv = |mli: u32| {
+            let mut iv = [0_u8; 8];
+            iv[..4].copy_from_slice(&super::ALTERNATIVE_IV_PREFIX);
+            iv[4..].copy_from_slice(&mli.to_be_bytes());
+            iv
+        };
+
+        // A single 8-byte block is encrypted directly with AES-ECB.
+        let single_block = |iv: [u8; 8], padded: [u8; 8]| {
+            let mut block = [0_u8; 16];
+            block[..8].copy_from_slice(&iv);
+            block[8..].copy_from_slice(&padded);
+            let mut crypter = super::ecb_crypter(&kek, openssl::symm::Mode::Encrypt).unwrap();
+            super::crypt_block(&mut crypter, &block).unwrap().to_vec()
+        };
+
+        let assert_integrity_check_failed = |ciphertext: &[u8]| {
+            assert_eq!(
+                super::unwrap_pad(&kek, ciphertext).unwrap_err(),
+                crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+            );
+        };
+
+        // Sanity check that the helpers construct valid ciphertexts.
+        assert_eq!(
+            super::unwrap_pad(&kek, &single_block(alternative_iv(3), *b"abc\0\0\0\0\0")).unwrap(),
+            b"abc",
+        );
+        assert_eq!(
+            super::unwrap_pad(
+                &kek,
+                &super::w(&kek, alternative_iv(9), b"abcdefghi\0\0\0\0\0\0\0").unwrap(),
+            )
+            .unwrap(),
+            b"abcdefghi",
+        );
+
+        // The RFC 3394 default IV instead of the alternative IV.
+        assert_integrity_check_failed(&super::wrap(&kek, &[0; 16]).unwrap());
+        assert_integrity_check_failed(&single_block(super::DEFAULT_IV, [0; 8]));
+
+        // MLI larger than the padded plaintext.
+        assert_integrity_check_failed(&single_block(alternative_iv(9), [0; 8]));
+        assert_integrity_check_failed(&super::w(&kek, alternative_iv(17), &[0; 16]).unwrap());
+
+        // MLI that would need fewer 8-byte blocks than the padded plaintext has.
+        assert_integrity_check_failed(&single_block(alternative_iv(0), [0; 8]));
+        assert_integrity_check_failed(&super::w(&kek, alternative_iv(8), &[0; 16]).unwrap());
+
+        // Non-zero padding.
+        assert_integrity_check_failed(&single_block(alternative_iv(3), *b"abc\0\0\0\0\x01"));
+        assert_integrity_check_failed(
+            &super::w(&kek, alternative_iv(9), b"abcdefghi\0\0\x01\0\0\0\0").unwrap(),
+        );
+    }
+}
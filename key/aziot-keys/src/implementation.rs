@@ -5,12 +5,37 @@ use aziot_keys_common::PreloadedKeyLocation;
 lazy_static::lazy_static! {
     static ref HOMEDIR_PATH: std::sync::RwLock<Option<std::path::PathBuf>> = Default::default();
 
-    static ref PKCS11_LIB_PATH: std::sync::RwLock<Option<std::path::PathBuf>> = Default::default();
-    static ref PKCS11_BASE_SLOT: std::sync::RwLock<Option<pkcs11::Uri>> = Default::default();
+    static ref PKCS11_TOKENS: std::sync::RwLock<std::collections::BTreeMap<String, Pkcs11Token>> = Default::default();
+
+    static ref KEY_ROUTES: std::sync::RwLock<std::collections::BTreeMap<String, KeyRoute>> = Default::default();
 
     static ref PRELOADED_KEYS: std::sync::RwLock<std::collections::BTreeMap<String, PreloadedKeyLocation>> = Default::default();
 
-    static ref PKCS11_BASE_SLOT_SESSION: std::sync::Mutex<Option<std::sync::Arc<pkcs11::Session>>> = Default::default();
+    static ref PKCS11_SESSION_POOLS: std::sync::Mutex<std::collections::BTreeMap<(std::path::PathBuf, String), std::sync::Arc<pkcs11::SessionPool>>> = Default::default();
+
+    static ref LOCKS: (std::sync::Mutex<std::collections::BTreeSet<LockName>>, std::sync::Condvar) = Default::default();
+}
+
+/// The number of sessions that a PKCS#11 session pool keeps open while they're not in use.
+const PKCS11_MAX_IDLE_SESSIONS: usize = 4;
+
+/// The name of the PKCS#11 token configured by the unnamed `pkcs11_lib_path` and `pkcs11_base_slot` parameters.
+///
+/// Keys whose IDs don't match any route are stored in this token, if it's configured.
+const DEFAULT_PKCS11_TOKEN: &str = "default";
+
+/// A PKCS#11 token configured by the `pkcs11_lib_path` and `pkcs11_base_slot` parameters.
+#[derive(Debug, Default)]
+struct Pkcs11Token {
+    lib_path: Option<std::path::PathBuf>,
+    base_slot: Option<pkcs11::Uri>,
+}
+
+/// Where keys whose IDs match a `key_route:<pattern>` parameter are stored.
+#[derive(Clone, Debug, PartialEq)]
+enum KeyRoute {
+    Filesystem,
+    Pkcs11(String),
 }
 
 pub(crate) unsafe fn get_function_list(
@@ -22,7 +47,7 @@ pub(crate) unsafe fn get_function_list(
     let _ = logger::try_init();
 
     crate::r#catch(|| {
-        static AZIOT_KEYS_FUNCTION_LIST_2_0_0_0: crate::AZIOT_KEYS_FUNCTION_LIST_2_0_0_0 =
+        const AZIOT_KEYS_FUNCTION_LIST_2_0_0_0_FUNCTIONS: crate::AZIOT_KEYS_FUNCTION_LIST_2_0_0_0 =
             crate::AZIOT_KEYS_FUNCTION_LIST_2_0_0_0 {
                 base: crate::AZIOT_KEYS_FUNCTION_LIST {
                     version: crate::AZIOT_KEYS_VERSION_2_0_0_0,
@@ -42,16 +67,63 @@ pub(crate) unsafe fn get_function_list(
                 decrypt,
             };
 
+        static AZIOT_KEYS_FUNCTION_LIST_2_0_0_0: crate::AZIOT_KEYS_FUNCTION_LIST_2_0_0_0 =
+            AZIOT_KEYS_FUNCTION_LIST_2_0_0_0_FUNCTIONS;
+
+        static AZIOT_KEYS_FUNCTION_LIST_2_1_0_0: crate::AZIOT_KEYS_FUNCTION_LIST_2_1_0_0 =
+            crate::AZIOT_KEYS_FUNCTION_LIST_2_1_0_0 {
+                v2_0_0_0: crate::AZIOT_KEYS_FUNCTION_LIST_2_0_0_0 {
+                    base: crate::AZIOT_KEYS_FUNCTION_LIST {
+                        version: crate::AZIOT_KEYS_VERSION_2_1_0_0,
+                    },
+                    ..AZIOT_KEYS_FUNCTION_LIST_2_0_0_0_FUNCTIONS
+                },
+
+                delete_key_pair: crate::key_pair::delete_key_pair,
+                delete_key: crate::key::delete_key,
+                enumerate_keys,
+
+                // All global state is behind locks, and every operation on a PKCS#11 key opens its own session
+                // on a context that was initialized with mutex callbacks. Operations that create, import or delete a key
+                // hold the lock of its ID, and key files are only ever replaced atomically, so concurrent calls are safe.
+                capabilities: crate::AZIOT_KEYS_CAPABILITY_THREAD_SAFE,
+                get_key_parameter: crate::key::get_key_parameter,
+                export_key: crate::key::export_key,
+                export_key_pair: crate::key_pair::export_key_pair,
+                import_wrapped_key: crate::key::import_wrapped_key,
+                import_wrapped_key_pair: crate::key_pair::import_wrapped_key_pair,
+                import_key_pair: crate::key_pair::import_key_pair,
+                derive_shared_secret: crate::key_pair::derive_shared_secret,
+                import_key_if_not_exists: crate::key::import_key_if_not_exists,
+                derive_key_with_mechanism: crate::key::derive_key_with_mechanism,
+                encrypt_init: crate::stream::encrypt_init,
+                decrypt_init: crate::stream::decrypt_init,
+                sign_init: crate::stream::sign_init,
+                stream_update: crate::stream::stream_update,
+                stream_final: crate::stream::stream_final,
+                stream_read: crate::stream::stream_read,
+                stream_free: crate::stream::stream_free,
+                move_key_pair: crate::key_pair::move_key_pair,
+            };
+
+        let mut function_list_out = std::ptr::NonNull::new(pfunction_list)
+            .ok_or_else(|| err_invalid_parameter("pfunction_list", "expected non-NULL"))?;
+
         match version {
             crate::AZIOT_KEYS_VERSION_2_0_0_0 => {
-                let mut function_list_out = std::ptr::NonNull::new(pfunction_list)
-                    .ok_or_else(|| err_invalid_parameter("pfunction_list", "expected non-NULL"))?;
                 *function_list_out.as_mut() = (&AZIOT_KEYS_FUNCTION_LIST_2_0_0_0
                     as *const crate::AZIOT_KEYS_FUNCTION_LIST_2_0_0_0)
                     .cast();
                 Ok(())
             }
 
+            crate::AZIOT_KEYS_VERSION_2_1_0_0 => {
+                *function_list_out.as_mut() = (&AZIOT_KEYS_FUNCTION_LIST_2_1_0_0
+                    as *const crate::AZIOT_KEYS_FUNCTION_LIST_2_1_0_0)
+                    .cast();
+                Ok(())
+            }
+
             _ => Err(err_invalid_parameter("version", "unsupported version")),
         }
     })
@@ -70,6 +142,9 @@ pub(crate) unsafe extern "C" fn set_parameter(
             .to_str()
             .map_err(|err| err_invalid_parameter("name", err))?;
 
+        // The PKCS#11 token whose library path or base slot was set by this parameter, if any.
+        let mut changed_pkcs11_token = None;
+
         match name {
             "homedir_path" => {
                 let value = value
@@ -93,10 +168,24 @@ pub(crate) unsafe extern "C" fn set_parameter(
                 let value = value
                     .to_str()
                     .map_err(|err| err_invalid_parameter("value", err))?;
-                let value: std::path::PathBuf = value.into();
 
-                let mut guard = PKCS11_LIB_PATH.write().expect("fatal RwLock failure");
-                *guard = Some(value);
+                set_pkcs11_lib_path(DEFAULT_PKCS11_TOKEN, value);
+                changed_pkcs11_token = Some(DEFAULT_PKCS11_TOKEN);
+            }
+
+            name if name.starts_with("pkcs11_lib_path:") => {
+                let token_name = pkcs11_token_name(&name["pkcs11_lib_path:".len()..])?;
+
+                let value = value
+                    .as_ref()
+                    .ok_or_else(|| err_invalid_parameter("value", "expected non-NULL"))?;
+                let value = std::ffi::CStr::from_ptr(value);
+                let value = value
+                    .to_str()
+                    .map_err(|err| err_invalid_parameter("value", err))?;
+
+                set_pkcs11_lib_path(token_name, value);
+                changed_pkcs11_token = Some(token_name);
             }
 
             "pkcs11_base_slot" => {
@@ -107,12 +196,59 @@ pub(crate) unsafe extern "C" fn set_parameter(
                 let value = value
                     .to_str()
                     .map_err(|err| err_invalid_parameter("value", err))?;
+
+                let () = set_pkcs11_base_slot(DEFAULT_PKCS11_TOKEN, value)?;
+                changed_pkcs11_token = Some(DEFAULT_PKCS11_TOKEN);
+            }
+
+            name if name.starts_with("pkcs11_base_slot:") => {
+                let token_name = pkcs11_token_name(&name["pkcs11_base_slot:".len()..])?;
+
                 let value = value
-                    .parse()
+                    .as_ref()
+                    .ok_or_else(|| err_invalid_parameter("value", "expected non-NULL"))?;
+                let value = std::ffi::CStr::from_ptr(value);
+                let value = value
+                    .to_str()
                     .map_err(|err| err_invalid_parameter("value", err))?;
 
-                let mut guard = PKCS11_BASE_SLOT.write().expect("fatal RwLock failure");
-                *guard = Some(value);
+                let () = set_pkcs11_base_slot(token_name, value)?;
+                changed_pkcs11_token = Some(token_name);
+            }
+
+            name if name.starts_with("key_route:") => {
+                let pattern = &name["key_route:".len()..];
+                if pattern.is_empty() {
+                    return Err(err_invalid_parameter("name", "key ID pattern is empty"));
+                }
+
+                let value = value
+                    .as_ref()
+                    .ok_or_else(|| err_invalid_parameter("value", "expected non-NULL"))?;
+                let value = std::ffi::CStr::from_ptr(value);
+                let value = value
+                    .to_str()
+                    .map_err(|err| err_invalid_parameter("value", err))?;
+                let value = if value == "filesystem" {
+                    KeyRoute::Filesystem
+                } else {
+                    KeyRoute::Pkcs11(pkcs11_token_name(value)?.to_owned())
+                };
+
+                let mut guard = KEY_ROUTES.write().expect("fatal RwLock failure");
+                guard.insert(pattern.to_owned(), value);
+            }
+
+            "key_encryption_key" => {
+                let value = value
+                    .as_ref()
+                    .ok_or_else(|| err_invalid_parameter("value", "expected non-NULL"))?;
+                let value = std::ffi::CStr::from_ptr(value);
+                let value = value
+                    .to_str()
+                    .map_err(|err| err_invalid_parameter("value", err))?;
+
+                let () = crate::key_encryption_key::set(value)?;
             }
 
             name if name.starts_with("preloaded_key:") => {
@@ -139,30 +275,19 @@ pub(crate) unsafe extern "C" fn set_parameter(
             _ => return Err(err_invalid_parameter("name", "unrecognized value")),
         }
 
-        {
-            let pkcs11_lib_path = PKCS11_LIB_PATH.read().expect("fatal RwLock failure");
-            let pkcs11_base_slot = PKCS11_BASE_SLOT.read().expect("fatal RwLock failure");
+        if let Some(token_name) = changed_pkcs11_token {
+            let pkcs11_tokens = PKCS11_TOKENS.read().expect("fatal RwLock failure");
 
-            if let (Some(pkcs11_lib_path), Some(pkcs11_base_slot)) =
-                (&*pkcs11_lib_path, &*pkcs11_base_slot)
+            if let Some(Pkcs11Token {
+                lib_path: Some(pkcs11_lib_path),
+                base_slot: Some(pkcs11_base_slot),
+            }) = pkcs11_tokens.get(token_name)
             {
                 // Pre-emptively open a session to the base slot. This makes it faster to use it in the future,
                 // since we won't have to log in again.
 
-                let pkcs11_context =
-                    pkcs11::Context::load(pkcs11_lib_path.clone()).map_err(err_external)?;
-                let pkcs11_slot = pkcs11_context
-                    .find_slot(&pkcs11_base_slot.slot_identifier)
-                    .map_err(err_external)?;
-                let pkcs11_session = pkcs11_context
-                    .open_session(pkcs11_slot, pkcs11_base_slot.pin.clone())
-                    .map_err(err_external)?;
-
-                let mut pkcs11_base_slot_session = PKCS11_BASE_SLOT_SESSION
-                    .lock()
-                    .expect("fatal RwLock failure");
-                let pkcs11_base_slot_session = &mut *pkcs11_base_slot_session;
-                *pkcs11_base_slot_session = Some(pkcs11_session);
+                let pkcs11_session_pool = pkcs11_session_pool(pkcs11_lib_path, pkcs11_base_slot)?;
+                let _ = pkcs11_session_pool.get().map_err(err_external)?;
             }
         }
 
@@ -170,6 +295,154 @@ pub(crate) unsafe extern "C" fn set_parameter(
     })
 }
 
+/// Returns the pool of sessions against the slot identified by `uri`, creating it if this is the first time it's needed.
+///
+/// The pools live as long as the process, so that their idle sessions stay logged in.
+pub(crate) fn pkcs11_session_pool(
+    lib_path: &std::path::Path,
+    uri: &pkcs11::Uri,
+) -> Result<std::sync::Arc<pkcs11::SessionPool>, crate::AZIOT_KEYS_RC> {
+    let slot_uri = pkcs11::Uri {
+        object_label: None,
+        ..uri.clone()
+    };
+
+    let mut pkcs11_session_pools = PKCS11_SESSION_POOLS.lock().expect("fatal Mutex failure");
+    match pkcs11_session_pools.entry((lib_path.to_owned(), slot_uri.to_string())) {
+        std::collections::btree_map::Entry::Occupied(entry) => Ok(entry.get().clone()),
+
+        std::collections::btree_map::Entry::Vacant(entry) => {
+            let pkcs11_context =
+                pkcs11::Context::load(lib_path.to_owned()).map_err(err_external)?;
+            let pkcs11_session_pool = pkcs11::SessionPool::new(
+                pkcs11_context,
+                slot_uri.slot_identifier,
+                slot_uri.pin,
+                PKCS11_MAX_IDLE_SESSIONS,
+            );
+            Ok(entry
+                .insert(std::sync::Arc::new(pkcs11_session_pool))
+                .clone())
+        }
+    }
+}
+
+/// Runs `f` with a session against the slot identified by `uri`, from that slot's session pool.
+///
+/// If the session was lost, such as because the device was removed and reinserted, `f` is retried with a new session.
+/// The outer error is from getting a session, and the inner error is from `f`.
+pub(crate) fn with_pkcs11_session<T, E>(
+    lib_path: &std::path::Path,
+    uri: &pkcs11::Uri,
+    f: impl FnMut(std::sync::Arc<pkcs11::Session>) -> Result<T, E>,
+) -> Result<Result<T, E>, crate::AZIOT_KEYS_RC> {
+    let pkcs11_session_pool = pkcs11_session_pool(lib_path, uri)?;
+
+    let (result, retried) = pkcs11_session_pool.with_session(f);
+    let metrics = pkcs11_session_pool.metrics();
+    if retried {
+        log::warn!(
+            "PKCS#11 session to slot {:?} was lost and has been reopened: {:?}",
+            uri.slot_identifier,
+            metrics,
+        );
+    } else {
+        log::debug!(
+            "PKCS#11 session pool for slot {:?}: {:?}",
+            uri.slot_identifier,
+            metrics,
+        );
+    }
+
+    match result {
+        Ok(value) => Ok(Ok(value)),
+        Err(pkcs11::WithSessionError::Operation(err)) => Ok(Err(err)),
+        Err(pkcs11::WithSessionError::GetSession(err)) => Err(err_external(err)),
+    }
+}
+
+/// Validates the name of a PKCS#11 token in a parameter name or `key_route:<pattern>` value.
+fn pkcs11_token_name(token_name: &str) -> Result<&str, crate::AZIOT_KEYS_RC> {
+    if token_name.is_empty() {
+        return Err(err_invalid_parameter("name", "PKCS#11 token name is empty"));
+    }
+
+    if token_name == "filesystem" {
+        return Err(err_invalid_parameter(
+            "name",
+            "PKCS#11 token name \"filesystem\" is reserved",
+        ));
+    }
+
+    Ok(token_name)
+}
+
+fn set_pkcs11_lib_path(token_name: &str, value: &str) {
+    let mut guard = PKCS11_TOKENS.write().expect("fatal RwLock failure");
+    guard.entry(token_name.to_owned()).or_default().lib_path = Some(value.into());
+}
+
+fn set_pkcs11_base_slot(token_name: &str, value: &str) -> Result<(), crate::AZIOT_KEYS_RC> {
+    let value = value
+        .parse()
+        .map_err(|err| err_invalid_parameter("value", err))?;
+
+    let mut guard = PKCS11_TOKENS.write().expect("fatal RwLock failure");
+    guard.entry(token_name.to_owned()).or_default().base_slot = Some(value);
+
+    Ok(())
+}
+
+/// Returns where the key with the given ID is stored, according to the `key_route:<pattern>` parameters.
+///
+/// If more than one pattern matches the ID, the one with the most non-wildcard characters wins. IDs that don't match
+/// any pattern are routed to the default PKCS#11 token.
+fn key_route(id: &str) -> KeyRoute {
+    let key_routes = KEY_ROUTES.read().expect("fatal RwLock failure");
+
+    let mut result: Option<(usize, &KeyRoute)> = None;
+    for (pattern, route) in &*key_routes {
+        if !wildmatch::WildMatch::new(pattern).is_match(id) {
+            continue;
+        }
+
+        let literal_len = pattern.chars().filter(|&c| c != '*' && c != '?').count();
+        if let Some((best_literal_len, _)) = result {
+            if literal_len <= best_literal_len {
+                continue;
+            }
+        }
+
+        result = Some((literal_len, route));
+    }
+
+    match result {
+        Some((_, route)) => route.clone(),
+        None => KeyRoute::Pkcs11(DEFAULT_PKCS11_TOKEN.to_owned()),
+    }
+}
+
+/// Returns the PKCS#11 library used to access the pre-loaded key with the given ID.
+///
+/// This is the library of the token that the ID is routed to, or of the default token if it isn't routed to one.
+fn preloaded_key_pkcs11_lib_path<'a>(
+    pkcs11_tokens: &'a std::collections::BTreeMap<String, Pkcs11Token>,
+    route: &KeyRoute,
+) -> Option<&'a std::path::PathBuf> {
+    let routed_token = match route {
+        KeyRoute::Pkcs11(token_name) => pkcs11_tokens.get(token_name),
+        KeyRoute::Filesystem => None,
+    };
+
+    routed_token
+        .and_then(|token| token.lib_path.as_ref())
+        .or_else(|| {
+            pkcs11_tokens
+                .get(DEFAULT_PKCS11_TOKEN)
+                .and_then(|token| token.lib_path.as_ref())
+        })
+}
+
 pub(crate) unsafe extern "C" fn sign(
     id: *const std::os::raw::c_char,
     mechanism: crate::AZIOT_KEYS_SIGN_MECHANISM,
@@ -203,13 +476,19 @@ pub(crate) unsafe extern "C" fn sign(
         let locations = Location::of(id)?;
 
         let (expected_signature_len, expected_signature) = match mechanism {
-            crate::AZIOT_KEYS_SIGN_MECHANISM_ECDSA => {
+            crate::AZIOT_KEYS_SIGN_MECHANISM_ECDSA
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_EDDSA
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS => {
                 crate::key_pair::sign(&locations, mechanism, parameters, digest)?
             }
 
             crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256
-            | crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED => {
-                crate::key::sign(&locations, mechanism, parameters, digest)?
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA384
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA512
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM => {
+                crate::key::sign(id, &locations, mechanism, parameters, digest)?
             }
 
             _ => return Err(err_invalid_parameter("mechanism", "unrecognized value")),
@@ -279,12 +558,17 @@ pub(crate) unsafe extern "C" fn verify(
         let ok = match mechanism {
             // Verify is not supported for asymmetric keys.
             // Clients can verify signatures themselves from the public parameters of the key pair.
-            crate::AZIOT_KEYS_SIGN_MECHANISM_ECDSA => {
+            crate::AZIOT_KEYS_SIGN_MECHANISM_ECDSA
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_EDDSA
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS => {
                 return Err(err_invalid_parameter("mechanism", "unrecognized value"))
             }
 
-            crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256 => {
-                crate::key::verify(&locations, digest, signature)?
+            crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA384
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA512 => {
+                crate::key::verify(id, &locations, mechanism, digest, signature)?
             }
 
             _ => return Err(err_invalid_parameter("mechanism", "unrecognized value")),
@@ -330,8 +614,13 @@ pub(crate) unsafe extern "C" fn encrypt(
 
         let (expected_ciphertext_len, expected_ciphertext) = match mechanism {
             crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD
-            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED => {
-                crate::key::encrypt(&locations, mechanism, parameters, plaintext)?
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP_PAD => {
+                crate::key::encrypt(id, &locations, mechanism, parameters, plaintext)?
             }
 
             crate::AZIOT_KEYS_ENCRYPT_MECHANISM_RSA_PKCS1
@@ -397,8 +686,13 @@ pub(crate) unsafe extern "C" fn decrypt(
 
         let (expected_plaintext_len, expected_plaintext) = match mechanism {
             crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD
-            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED => {
-                crate::key::decrypt(&locations, mechanism, parameters, ciphertext)?
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP_PAD => {
+                crate::key::decrypt(id, &locations, mechanism, parameters, ciphertext)?
             }
 
             _ => return Err(err_invalid_parameter("mechanism", "unrecognized value")),
@@ -439,18 +733,20 @@ impl Location {
         let homedir_path_guard = HOMEDIR_PATH.read().expect("fatal RwLock failure");
         let homedir_path = homedir_path_guard.as_ref();
 
-        let pkcs11_lib_path_guard = PKCS11_LIB_PATH.read().expect("fatal RwLock failure");
-        let pkcs11_lib_path = pkcs11_lib_path_guard.as_ref();
-
-        let pkcs11_base_slot_guard = PKCS11_BASE_SLOT.read().expect("fatal RwLock failure");
-        let pkcs11_base_slot = pkcs11_base_slot_guard.as_ref();
+        let pkcs11_tokens_guard = PKCS11_TOKENS.read().expect("fatal RwLock failure");
+        let pkcs11_tokens = &*pkcs11_tokens_guard;
 
         let preloaded_keys_guard = PRELOADED_KEYS.read().expect("fatal RwLock failure");
         let preloaded_keys = &*preloaded_keys_guard;
 
+        let route = key_route(id);
+
         let mut locations = vec![];
 
-        match (preloaded_keys.get(id), pkcs11_lib_path) {
+        match (
+            preloaded_keys.get(id),
+            preloaded_key_pkcs11_lib_path(pkcs11_tokens, &route),
+        ) {
             (Some(PreloadedKeyLocation::Filesystem { path }), _) => {
                 locations.push(Location::Filesystem(path.clone()))
             }
@@ -474,44 +770,793 @@ impl Location {
 
         if locations.is_empty() {
             // Prefer to use PKCS#11 before filesystem if configured so
-            if let (Some(pkcs11_lib_path), Some(pkcs11_base_slot)) =
-                (pkcs11_lib_path, pkcs11_base_slot)
-            {
-                let mut uri = pkcs11_base_slot.clone();
-                uri.object_label = Some(id.to_owned());
-                locations.push(Location::Pkcs11 {
-                    lib_path: pkcs11_lib_path.clone(),
-                    uri,
-                });
+            if let KeyRoute::Pkcs11(token_name) = &route {
+                match pkcs11_tokens.get(token_name) {
+                    Some(Pkcs11Token {
+                        lib_path: Some(pkcs11_lib_path),
+                        base_slot: Some(pkcs11_base_slot),
+                    }) => {
+                        let mut uri = pkcs11_base_slot.clone();
+                        uri.object_label = Some(id.to_owned());
+                        locations.push(Location::Pkcs11 {
+                            lib_path: pkcs11_lib_path.clone(),
+                            uri,
+                        });
+                    }
+
+                    // The default token is optional, but a route to a named token must not silently fall back to the filesystem.
+                    _ if token_name == DEFAULT_PKCS11_TOKEN => (),
+
+                    _ => {
+                        return Err(err_invalid_parameter(
+                            "id",
+                            format!(
+                                "key is routed to PKCS#11 token {:?} which does not have both a lib path and a base slot configured",
+                                token_name,
+                            ),
+                        ))
+                    }
+                }
             }
 
             if let Some(homedir_path) = homedir_path {
-                let mut path = homedir_path.clone();
+                let path = homedir_key_path(homedir_path, id)?;
+                locations.push(Location::Filesystem(path));
+            }
+        }
+
+        if locations.is_empty() {
+            // No way to create keys
+            return Err(err_invalid_parameter("id", "no way to create keys"));
+        }
+
+        Ok(locations)
+    }
+}
+
+fn homedir_keys_dir(
+    homedir_path: &std::path::Path,
+) -> Result<std::path::PathBuf, crate::AZIOT_KEYS_RC> {
+    let mut path = homedir_path.to_owned();
+
+    path.push("keys");
+
+    if !path.exists() {
+        let () = std::fs::create_dir_all(&path).map_err(err_external)?;
+    }
+
+    Ok(path)
+}
+
+fn homedir_key_path(
+    homedir_path: &std::path::Path,
+    id: &str,
+) -> Result<std::path::PathBuf, crate::AZIOT_KEYS_RC> {
+    let mut path = homedir_keys_dir(homedir_path)?;
+
+    let id_sanitized: String = id.chars().filter(char::is_ascii_alphanumeric).collect();
+
+    let hash = openssl::hash::hash(openssl::hash::MessageDigest::sha256(), id.as_bytes())?;
+    let hash = hex::encode(hash);
+    path.push(format!("{}-{}.key", id_sanitized, hash));
+
+    Ok(path)
+}
+
+/// Something that [`lock`] can lock.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum LockName {
+    /// A key or key pair ID. Held by operations that create, import or delete the key for the whole operation,
+    /// so that checking whether the key exists and then creating it can't race with another such operation.
+    Id(String),
+
+    /// A key file. Held while the file is written or removed, so that re-encrypting a plaintext key file
+    /// when it's read can't race with the file being replaced or deleted.
+    Path(std::path::PathBuf),
+}
+
+/// Releases the lock taken by [`lock`] when dropped.
+pub(crate) struct LockGuard(LockName);
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        let (locked, unlocked) = &*LOCKS;
+        let mut locked = locked.lock().expect("fatal Mutex failure");
+        locked.remove(&self.0);
+        unlocked.notify_all();
+    }
+}
+
+/// Blocks until no other thread holds the lock with the given name, and then takes it.
+///
+/// Locks are not re-entrant. An operation takes at most one ID lock, except for moving a key pair which takes two with [`lock_ids`]
+/// in a fixed order, and only takes path locks while it holds them, so threads can't deadlock on each other.
+fn lock(name: LockName) -> LockGuard {
+    let (locked, unlocked) = &*LOCKS;
+    let mut locked = locked.lock().expect("fatal Mutex failure");
+    while locked.contains(&name) {
+        locked = unlocked.wait(locked).expect("fatal Mutex failure");
+    }
+    locked.insert(name.clone());
+    LockGuard(name)
+}
+
+/// Locks the key or key pair with the given ID against concurrent creation, import and deletion.
+pub(crate) fn lock_id(id: &str) -> LockGuard {
+    lock(LockName::Id(id.to_owned()))
+}
+
+/// Locks the two key pairs with the given IDs, for operations that act on both. The IDs must be different.
+///
+/// The locks are always taken in the order of the IDs, so that two operations on the same pair of IDs can't deadlock on each other.
+pub(crate) fn lock_ids(id1: &str, id2: &str) -> (LockGuard, LockGuard) {
+    let (first, second) = if id1 < id2 { (id1, id2) } else { (id2, id1) };
+    let first = lock_id(first);
+    let second = lock_id(second);
+    (first, second)
+}
+
+/// Reads the key file at `path`, decrypting it with the key-encryption key if it's encrypted.
+///
+/// If a key-encryption key is configured and the file is a plaintext key file under the homedir,
+/// it's re-encrypted in place. Returns `None` if the file does not exist.
+pub(crate) fn read_key_file(
+    path: &std::path::Path,
+) -> Result<Option<Vec<u8>>, crate::AZIOT_KEYS_RC> {
+    let contents = match read_file(path)? {
+        Some(contents) => contents,
+        None => return Ok(None),
+    };
+
+    if crate::key_encryption_key::is_encrypted(&contents) {
+        let plaintext = crate::key_encryption_key::decrypt(path, &contents)?;
+        return Ok(Some(plaintext));
+    }
+
+    if crate::key_encryption_key::is_set() && is_homedir_key_path(path) {
+        let _path_lock = lock(LockName::Path(path.to_owned()));
+
+        // The file may have been replaced or deleted since it was read, so only re-encrypt it if it still has the same contents.
+        if read_file(path)?.as_ref() == Some(&contents) {
+            let encrypted = crate::key_encryption_key::encrypt(path, &contents)?;
+            let () = write_file_atomically(path, &encrypted, true)?;
+            log::info!("Encrypted existing key file {}", path.display());
+        }
+    }
+
+    Ok(Some(contents))
+}
+
+/// Writes the key file at `path`, encrypting it with the key-encryption key if one is configured and the file is under the homedir.
+///
+/// If `overwrite` is false, fails if the file already exists instead of replacing it.
+///
+/// Preloaded key files elsewhere are managed by the user, so they're always written as plaintext.
+pub(crate) fn write_key_file(
+    path: &std::path::Path,
+    contents: &[u8],
+    overwrite: bool,
+) -> Result<(), crate::AZIOT_KEYS_RC> {
+    let _path_lock = lock(LockName::Path(path.to_owned()));
+
+    if crate::key_encryption_key::is_set() && is_homedir_key_path(path) {
+        let encrypted = crate::key_encryption_key::encrypt(path, contents)?;
+        let () = write_file_atomically(path, &encrypted, overwrite)?;
+    } else {
+        let () = write_file_atomically(path, contents, overwrite)?;
+    }
+
+    Ok(())
+}
+
+/// Deletes the key file at `path`, if it exists.
+pub(crate) fn delete_key_file(path: &std::path::Path) -> Result<(), crate::AZIOT_KEYS_RC> {
+    let _path_lock = lock(LockName::Path(path.to_owned()));
+
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err_external(err)),
+    }
+}
+
+fn read_file(path: &std::path::Path) -> Result<Option<Vec<u8>>, crate::AZIOT_KEYS_RC> {
+    match std::fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err_external(err)),
+    }
+}
+
+/// Writes `contents` to `path` such that concurrent readers see either the previous file or the complete new one.
+///
+/// The contents are written to a new temporary file next to `path`, which then replaces `path`. If `overwrite` is false,
+/// this fails if `path` already exists instead of replacing it.
+fn write_file_atomically(
+    path: &std::path::Path,
+    contents: &[u8],
+    overwrite: bool,
+) -> Result<(), crate::AZIOT_KEYS_RC> {
+    use std::io::Write;
+
+    fn write(
+        temp_path: &std::path::Path,
+        path: &std::path::Path,
+        contents: &[u8],
+        overwrite: bool,
+    ) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        drop(file);
+
+        if overwrite {
+            std::fs::rename(temp_path, path)
+        } else {
+            // Unlike a rename, linking fails if the destination already exists.
+            std::fs::hard_link(temp_path, path)?;
+            std::fs::remove_file(temp_path)
+        }
+    }
+
+    // Every write gets its own temporary file, so concurrent writers never write to the same one.
+    let mut suffix = [0_u8; 8];
+    openssl::rand::rand_bytes(&mut suffix)?;
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(format!(".{}.tmp", hex::encode(suffix)));
+    let temp_path = std::path::PathBuf::from(temp_path);
+
+    let result = write(&temp_path, path, contents, overwrite);
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => Err(err_invalid_parameter(
+            "id",
+            "a key with this ID already exists",
+        )),
+        Err(err) => Err(err_external(err)),
+    }
+}
+
+fn is_homedir_key_path(path: &std::path::Path) -> bool {
+    let homedir_path_guard = HOMEDIR_PATH.read().expect("fatal RwLock failure");
+    let homedir_path = match &*homedir_path_guard {
+        Some(homedir_path) => homedir_path,
+        None => return false,
+    };
+
+    path.parent() == Some(&homedir_path.join("keys"))
+}
+
+/// Metadata stored alongside a key or key pair that was created in the filesystem under the homedir.
+///
+/// Filesystem keys are stored in files whose names are derived from a hash of the key ID,
+/// so this is needed to recover the ID when enumerating keys. It also records how and when the key was created,
+/// and what a symmetric key may be used for, since a raw key file can't hold that information itself.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub(crate) struct Metadata {
+    id: String,
+
+    /// The `AZIOT_KEYS_KEY_USAGE` of a symmetric key. Not set for key pairs, or for keys whose usage is not known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) usage: Option<std::os::raw::c_uint>,
+
+    /// How the key or key pair was created. Not set for keys and key pairs whose origin is not known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) origin: Option<Origin>,
+
+    /// When the key or key pair was created, in seconds since the Unix epoch. Not set for keys and key pairs whose creation time is not known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) created_at: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Origin {
+    Generated,
+    Imported,
+}
+
+/// The metadata of a newly-created key or key pair, as passed to [`save_metadata`].
+#[derive(Clone, Copy)]
+pub(crate) struct Creation {
+    pub(crate) origin: Origin,
+    pub(crate) usage: Option<crate::AZIOT_KEYS_KEY_USAGE>,
+}
 
-                path.push("keys");
+/// Saves the metadata of the key or key pair with the given ID, if it's stored in the filesystem under the homedir.
+///
+/// If `creation` is `None`, existing metadata is left as-is. This is used to backfill the metadata of keys
+/// that were created before metadata was saved, when they're created or imported again. Read-only operations
+/// like loading and enumerating keys never write metadata.
+pub(crate) fn save_metadata(
+    id: &str,
+    locations: &[Location],
+    creation: Option<Creation>,
+) -> Result<(), crate::AZIOT_KEYS_RC> {
+    let path = match homedir_path_of(id, locations) {
+        Some(path) if path.exists() => path,
+        _ => return Ok(()),
+    };
+
+    let metadata_path = path.with_extension("json");
+    if creation.is_none() && metadata_path.exists() {
+        return Ok(());
+    }
+
+    let metadata = match creation {
+        Some(Creation { origin, usage }) => {
+            let created_at = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_err(err_external)?
+                .as_secs();
+
+            Metadata {
+                id: id.to_owned(),
+                usage: usage.map(|usage| usage.inner),
+                origin: Some(origin),
+                created_at: Some(created_at),
+            }
+        }
+
+        None => Metadata {
+            id: id.to_owned(),
+            usage: None,
+            origin: None,
+            created_at: None,
+        },
+    };
+    let metadata = serde_json::to_vec(&metadata).map_err(err_external)?;
+    let () = write_file_atomically(&metadata_path, &metadata, true)?;
+
+    Ok(())
+}
 
-                if !path.exists() {
-                    let () = std::fs::create_dir_all(&path).map_err(err_external)?;
+/// Loads the metadata of the key or key pair with the given ID, if it's stored in the filesystem under the homedir
+/// and has metadata.
+pub(crate) fn load_metadata(
+    id: &str,
+    locations: &[Location],
+) -> Result<Option<Metadata>, crate::AZIOT_KEYS_RC> {
+    let path = match homedir_path_of(id, locations) {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    let metadata = match std::fs::read(path.with_extension("json")) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err_external(err)),
+    };
+
+    // The metadata controls what the key may be used for, so malformed metadata is an error rather than being ignored.
+    let metadata: Metadata = serde_json::from_slice(&metadata).map_err(err_external)?;
+    if metadata.id != id {
+        return Ok(None);
+    }
+
+    Ok(Some(metadata))
+}
+
+/// Deletes the metadata of the key or key pair with the given ID, if any.
+pub(crate) fn delete_metadata(
+    id: &str,
+    locations: &[Location],
+) -> Result<(), crate::AZIOT_KEYS_RC> {
+    if let Some(path) = homedir_path_of(id, locations) {
+        match std::fs::remove_file(path.with_extension("json")) {
+            Ok(()) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err_external(err)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves the metadata of the key or key pair with ID `from_id` to the one with ID `to_id`, after the key itself has been moved.
+///
+/// If `from_id` has no metadata, any existing metadata of `to_id` is replaced with empty metadata, since it described the key that was replaced.
+pub(crate) fn move_metadata(
+    from_id: &str,
+    from_locations: &[Location],
+    to_id: &str,
+    to_locations: &[Location],
+) -> Result<(), crate::AZIOT_KEYS_RC> {
+    let metadata = load_metadata(from_id, from_locations)?;
+    delete_metadata(from_id, from_locations)?;
+    delete_metadata(to_id, to_locations)?;
+
+    match metadata {
+        Some(mut metadata) => {
+            let path = match homedir_path_of(to_id, to_locations) {
+                Some(path) if path.exists() => path,
+                _ => return Ok(()),
+            };
+
+            metadata.id = to_id.to_owned();
+            let metadata = serde_json::to_vec(&metadata).map_err(err_external)?;
+            let () = write_file_atomically(&path.with_extension("json"), &metadata, true)?;
+        }
+
+        None => save_metadata(to_id, to_locations, None)?,
+    }
+
+    Ok(())
+}
+
+/// Returns an error if the key or key pair with the given ID is pre-loaded.
+///
+/// Pre-loaded keys are provisioned by the operator, so they cannot be deleted or overwritten through this library.
+pub(crate) fn ensure_not_preloaded(id: &str) -> Result<(), crate::AZIOT_KEYS_RC> {
+    let preloaded_keys_guard = PRELOADED_KEYS.read().expect("fatal RwLock failure");
+    if preloaded_keys_guard.contains_key(id) {
+        return Err(err_invalid_parameter(
+            "id",
+            "pre-loaded keys cannot be deleted or overwritten",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the label of the object that the PKCS#11 URI identifies.
+///
+/// Operations that destroy objects require a label, since without one they would act on whichever object of the class the token returns first.
+pub(crate) fn pkcs11_object_label(uri: &pkcs11::Uri) -> Result<&str, crate::AZIOT_KEYS_RC> {
+    uri.object_label
+        .as_deref()
+        .ok_or_else(|| err_invalid_parameter("id", "PKCS#11 URI does not identify an object"))
+}
+
+/// Returns the filesystem path of the key or key pair with the given ID, if it's under the homedir.
+///
+/// Pre-loaded keys are not under the homedir, and the user may have other files next to them,
+/// so they don't get metadata files.
+fn homedir_path_of<'a>(id: &str, locations: &'a [Location]) -> Option<&'a std::path::Path> {
+    let preloaded_keys_guard = PRELOADED_KEYS.read().expect("fatal RwLock failure");
+    if preloaded_keys_guard.contains_key(id) {
+        return None;
+    }
+
+    locations.iter().find_map(|location| match location {
+        Location::Filesystem(path) => Some(&**path),
+        Location::Pkcs11 { .. } => None,
+    })
+}
+
+pub(crate) unsafe extern "C" fn enumerate_keys(
+    callback: crate::AZIOT_KEYS_ENUMERATE_KEYS_CALLBACK,
+    context: *mut std::ffi::c_void,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let callback =
+            callback.ok_or_else(|| err_invalid_parameter("callback", "expected non-NULL"))?;
+
+        // Collect all the entries before invoking the callback, so that no locks are held while the caller's code runs.
+        let entries = enumerate_keys_inner()?;
+
+        for entry in entries {
+            let id = std::ffi::CString::new(entry.id).map_err(err_external)?;
+            let location = std::ffi::CString::new(entry.location).map_err(err_external)?;
+
+            let info = crate::AZIOT_KEYS_KEY_INFO {
+                id: id.as_ptr(),
+                kind: entry.kind,
+                algorithm: entry.algorithm,
+                usage: entry.usage,
+                location: location.as_ptr(),
+                preloaded: if entry.preloaded { 1 } else { 0 },
+            };
+            callback(context, &info);
+        }
+
+        Ok(())
+    })
+}
+
+struct KeyEntry {
+    id: String,
+    kind: crate::AZIOT_KEYS_KEY_KIND,
+    algorithm: crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM,
+    usage: crate::AZIOT_KEYS_KEY_USAGE,
+    location: String,
+    preloaded: bool,
+}
+
+const NO_ALGORITHM: crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM =
+    crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM { inner: 0 };
+
+const NO_USAGE: crate::AZIOT_KEYS_KEY_USAGE = crate::AZIOT_KEYS_KEY_USAGE { inner: 0 };
+
+fn enumerate_keys_inner() -> Result<Vec<KeyEntry>, crate::AZIOT_KEYS_RC> {
+    let homedir_path_guard = HOMEDIR_PATH.read().expect("fatal RwLock failure");
+    let homedir_path = homedir_path_guard.as_ref();
+
+    let pkcs11_tokens_guard = PKCS11_TOKENS.read().expect("fatal RwLock failure");
+    let pkcs11_tokens = &*pkcs11_tokens_guard;
+
+    let preloaded_keys_guard = PRELOADED_KEYS.read().expect("fatal RwLock failure");
+    let preloaded_keys = &*preloaded_keys_guard;
+
+    let mut entries = vec![];
+
+    // IDs that have already been reported. Location::of prefers pre-loaded keys, then PKCS#11, then the filesystem,
+    // so an ID that is found in more than one place is only reported for the first one in that order.
+    let mut seen: std::collections::BTreeSet<String> = Default::default();
+
+    for (id, location) in preloaded_keys {
+        let entry = match location {
+            PreloadedKeyLocation::Filesystem { path } => {
+                filesystem_entry(id, path, NO_USAGE, true)?
+            }
+
+            PreloadedKeyLocation::Pkcs11 { uri } => {
+                match preloaded_key_pkcs11_lib_path(pkcs11_tokens, &key_route(id)) {
+                    Some(pkcs11_lib_path) => {
+                        let label = uri.object_label.as_deref();
+                        pkcs11_entries(pkcs11_lib_path, uri)?
+                            .into_iter()
+                            .find(|(key_label, _)| key_label.as_deref() == label)
+                            .map(|(_, entry)| KeyEntry {
+                                id: id.clone(),
+                                preloaded: true,
+                                ..entry
+                            })
+                    }
+
+                    None => None,
                 }
+            }
+        };
 
-                let id_sanitized: String = id.chars().filter(char::is_ascii_alphanumeric).collect();
+        if let Some(entry) = entry {
+            seen.insert(entry.id.clone());
+            entries.push(entry);
+        }
+    }
 
-                let hash =
-                    openssl::hash::hash(openssl::hash::MessageDigest::sha256(), id.as_bytes())?;
-                let hash = hex::encode(hash);
-                path.push(format!("{}-{}.key", id_sanitized, hash));
+    for (token_name, pkcs11_token) in pkcs11_tokens {
+        let (pkcs11_lib_path, pkcs11_base_slot) =
+            match (&pkcs11_token.lib_path, &pkcs11_token.base_slot) {
+                (Some(pkcs11_lib_path), Some(pkcs11_base_slot)) => {
+                    (pkcs11_lib_path, pkcs11_base_slot)
+                }
+                _ => continue,
+            };
 
-                locations.push(Location::Filesystem(path));
+        for (label, entry) in pkcs11_entries(pkcs11_lib_path, pkcs11_base_slot)? {
+            // Objects without labels can't be addressed by ID.
+            let label = match label {
+                Some(label) => label,
+                None => continue,
+            };
+
+            // Objects whose labels are routed to some other token or the filesystem can't be addressed by ID either.
+            if key_route(&label) != KeyRoute::Pkcs11(token_name.clone()) {
+                continue;
+            }
+
+            if seen.insert(label.clone()) {
+                entries.push(KeyEntry { id: label, ..entry });
             }
         }
+    }
 
-        if locations.is_empty() {
-            // No way to create keys
-            return Err(err_invalid_parameter("id", "no way to create keys"));
+    if let Some(homedir_path) = homedir_path {
+        let keys_dir = homedir_keys_dir(homedir_path)?;
+
+        let mut metadata_paths = vec![];
+        let mut key_paths = vec![];
+        for dir_entry in std::fs::read_dir(&keys_dir).map_err(err_external)? {
+            let path = dir_entry.map_err(err_external)?.path();
+            if path.extension() == Some(std::ffi::OsStr::new("json")) {
+                metadata_paths.push(path);
+            } else if path.extension() == Some(std::ffi::OsStr::new("key")) {
+                key_paths.push(path);
+            }
         }
+        metadata_paths.sort();
+        key_paths.sort();
+
+        for metadata_path in metadata_paths {
+            let metadata = std::fs::read(&metadata_path).map_err(err_external)?;
+            let metadata: Metadata = match serde_json::from_slice(&metadata) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    log::warn!(
+                        "ignoring malformed key metadata file {}: {}",
+                        metadata_path.display(),
+                        err
+                    );
+                    continue;
+                }
+            };
 
-        Ok(locations)
+            if seen.contains(&metadata.id) {
+                continue;
+            }
+
+            // Only report the metadata if it's for the key file that the ID actually maps to.
+            let path = homedir_key_path(homedir_path, &metadata.id)?;
+            if path.with_extension("json") != metadata_path {
+                continue;
+            }
+
+            let usage = crate::AZIOT_KEYS_KEY_USAGE {
+                inner: metadata.usage.unwrap_or_default(),
+            };
+            if let Some(entry) = filesystem_entry(&metadata.id, &path, usage, false)? {
+                seen.insert(entry.id.clone());
+                entries.push(entry);
+            }
+        }
+
+        // Keys and key pairs that were created before metadata was saved don't have metadata files.
+        // Their metadata is backfilled when they're next created or imported, but until then the ID can only be recovered
+        // from the file name if it's made up entirely of characters that survive sanitization.
+        for path in key_paths {
+            if path.with_extension("json").exists() {
+                continue;
+            }
+
+            let id = if let Some(id) = legacy_key_id(homedir_path, &path)? {
+                id
+            } else {
+                log::warn!(
+                    "key file {} has no metadata, so it won't be enumerated until its key is next created",
+                    path.display()
+                );
+                continue;
+            };
+
+            if seen.contains(&id) {
+                continue;
+            }
+
+            if let Some(entry) = filesystem_entry(&id, &path, NO_USAGE, false)? {
+                seen.insert(entry.id.clone());
+                entries.push(entry);
+            }
+        }
     }
+
+    Ok(entries)
+}
+
+/// Recovers the ID of a key or key pair from the name of its key file under the homedir, if possible.
+///
+/// Key file names are made up of the sanitized ID and a hash of the original ID (see [`homedir_key_path`]),
+/// so this only succeeds if sanitizing the ID didn't change it.
+fn legacy_key_id(
+    homedir_path: &std::path::Path,
+    path: &std::path::Path,
+) -> Result<Option<String>, crate::AZIOT_KEYS_RC> {
+    let id = path
+        .file_stem()
+        .and_then(std::ffi::OsStr::to_str)
+        .and_then(|file_stem| file_stem.rsplit_once('-'))
+        .map_or("", |(id, _)| id);
+
+    if !id.is_empty() && homedir_key_path(homedir_path, id)? == path {
+        Ok(Some(id.to_owned()))
+    } else {
+        Ok(None)
+    }
+}
+
+fn filesystem_entry(
+    id: &str,
+    path: &std::path::Path,
+    usage: crate::AZIOT_KEYS_KEY_USAGE,
+    preloaded: bool,
+) -> Result<Option<KeyEntry>, crate::AZIOT_KEYS_RC> {
+    let bytes = match read_key_file(path)? {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+
+    let location = url::Url::from_file_path(path)
+        .map_err(|()| err_external(format!("could not convert {} to a URI", path.display())))?
+        .to_string();
+
+    // Key pairs are stored as PEM-encoded private keys. Symmetric keys are stored as raw bytes.
+    let entry = match openssl::pkey::PKey::private_key_from_pem(&bytes) {
+        Ok(private_key) => KeyEntry {
+            id: id.to_owned(),
+            kind: crate::AZIOT_KEYS_KEY_KIND_KEY_PAIR,
+            algorithm: match private_key.id() {
+                openssl::pkey::Id::EC => crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_EC,
+                openssl::pkey::Id::RSA => crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_RSA,
+                openssl::pkey::Id::ED25519 => {
+                    crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_ED25519
+                }
+                _ => NO_ALGORITHM,
+            },
+            usage: NO_USAGE,
+            location,
+            preloaded,
+        },
+
+        Err(_) => KeyEntry {
+            id: id.to_owned(),
+            kind: crate::AZIOT_KEYS_KEY_KIND_KEY,
+            algorithm: NO_ALGORITHM,
+            usage,
+            location,
+            preloaded,
+        },
+    };
+
+    Ok(Some(entry))
+}
+
+/// Returns the keys in the slot identified by `uri`, along with their labels.
+///
+/// The returned entries have an empty ID, which the caller is expected to fill in.
+fn pkcs11_entries(
+    lib_path: &std::path::Path,
+    uri: &pkcs11::Uri,
+) -> Result<Vec<(Option<String>, KeyEntry)>, crate::AZIOT_KEYS_RC> {
+    let keys = with_pkcs11_session(lib_path, uri, |pkcs11_session| pkcs11_session.list_keys())?
+        .map_err(err_external)?;
+
+    let entries = keys
+        .into_iter()
+        .filter_map(|key| {
+            let (kind, algorithm, usage) = match (key.class, key.key_type) {
+                (pkcs11_sys::CKO_SECRET_KEY, pkcs11_sys::CKK_AES) => (
+                    crate::AZIOT_KEYS_KEY_KIND_KEY,
+                    NO_ALGORITHM,
+                    crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+                ),
+                (pkcs11_sys::CKO_SECRET_KEY, _) => (
+                    crate::AZIOT_KEYS_KEY_KIND_KEY,
+                    NO_ALGORITHM,
+                    crate::AZIOT_KEYS_KEY_USAGE_DERIVE,
+                ),
+                (pkcs11_sys::CKO_PRIVATE_KEY, pkcs11_sys::CKK_EC) => (
+                    crate::AZIOT_KEYS_KEY_KIND_KEY_PAIR,
+                    crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_EC,
+                    NO_USAGE,
+                ),
+                (pkcs11_sys::CKO_PRIVATE_KEY, pkcs11_sys::CKK_RSA) => (
+                    crate::AZIOT_KEYS_KEY_KIND_KEY_PAIR,
+                    crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_RSA,
+                    NO_USAGE,
+                ),
+                (pkcs11_sys::CKO_PRIVATE_KEY, pkcs11_sys::CKK_EC_EDWARDS) => (
+                    crate::AZIOT_KEYS_KEY_KIND_KEY_PAIR,
+                    crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_ED25519,
+                    NO_USAGE,
+                ),
+                _ => return None,
+            };
+
+            let mut location = uri.clone();
+            location.object_label.clone_from(&key.label);
+            location.pin = None;
+
+            let entry = KeyEntry {
+                id: String::new(),
+                kind,
+                algorithm,
+                usage,
+                location: location.to_string(),
+                preloaded: false,
+            };
+            Some((key.label, entry))
+        })
+        .collect();
+
+    Ok(entries)
 }
 
 impl From<openssl::error::Error> for crate::AZIOT_KEYS_RC {
@@ -547,3 +1592,546 @@ where
     log::error!("invalid parameter {:?}: {}", name, err);
     crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    lazy_static::lazy_static! {
+        /// Serializes the tests that use the library's global parameters.
+        static ref GLOBALS_LOCK: std::sync::Mutex<()> = Default::default();
+    }
+
+    /// A homedir in a new temporary directory, set as the `homedir_path` parameter.
+    ///
+    /// It holds a lock on the library's global parameters for as long as it exists,
+    /// and resets them when it's created and dropped.
+    pub(crate) struct TestHomedir {
+        pub(crate) path: std::path::PathBuf,
+        _guard: std::sync::MutexGuard<'static, ()>,
+    }
+
+    impl TestHomedir {
+        pub(crate) fn new() -> Self {
+            static NEXT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+            let guard = GLOBALS_LOCK
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            reset_globals();
+
+            let path = std::env::temp_dir().join(format!(
+                "aziot-keys-test-{}-{}",
+                std::process::id(),
+                NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
+            ));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+
+            let homedir = TestHomedir {
+                path,
+                _guard: guard,
+            };
+            assert_eq!(
+                set_parameter("homedir_path", homedir.path.to_str().unwrap()),
+                crate::AZIOT_KEYS_RC_OK,
+            );
+            homedir
+        }
+    }
+
+    impl Drop for TestHomedir {
+        fn drop(&mut self) {
+            reset_globals();
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    fn reset_globals() {
+        *super::HOMEDIR_PATH.write().unwrap() = None;
+        super::PKCS11_TOKENS.write().unwrap().clear();
+        super::KEY_ROUTES.write().unwrap().clear();
+        super::PRELOADED_KEYS.write().unwrap().clear();
+        crate::key_encryption_key::clear();
+    }
+
+    pub(crate) fn set_parameter(name: &str, value: &str) -> crate::AZIOT_KEYS_RC {
+        let name = c_string(name);
+        let value = c_string(value);
+        unsafe { super::set_parameter(name.as_ptr(), value.as_ptr()) }
+    }
+
+    pub(crate) fn c_string(s: &str) -> std::ffi::CString {
+        std::ffi::CString::new(s).unwrap()
+    }
+
+    #[test]
+    fn delete_preloaded_key() {
+        let homedir = TestHomedir::new();
+
+        let preloaded_key_path = homedir.path.join("preloaded.key");
+        std::fs::write(&preloaded_key_path, b"operator-provisioned key").unwrap();
+        let preloaded_key_uri = url::Url::from_file_path(&preloaded_key_path).unwrap();
+        assert_eq!(
+            set_parameter("preloaded_key:preloaded", preloaded_key_uri.as_str()),
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        let id = c_string("preloaded");
+        assert_eq!(
+            unsafe { crate::key::delete_key(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            unsafe { crate::key_pair::delete_key_pair(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert!(preloaded_key_path.exists());
+    }
+
+    #[test]
+    fn import_preloaded_key() {
+        let homedir = TestHomedir::new();
+
+        let preloaded_key_path = homedir.path.join("preloaded.key");
+        std::fs::write(&preloaded_key_path, b"operator-provisioned key").unwrap();
+        let preloaded_key_uri = url::Url::from_file_path(&preloaded_key_path).unwrap();
+        assert_eq!(
+            set_parameter("preloaded_key:preloaded", preloaded_key_uri.as_str()),
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        let id = c_string("preloaded");
+
+        let key = [0_u8; 32];
+        assert_eq!(
+            unsafe {
+                crate::key::import_key(
+                    id.as_ptr(),
+                    key.as_ptr(),
+                    key.len(),
+                    crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+                )
+            },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        let group =
+            openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1).unwrap();
+        let private_key =
+            openssl::pkey::PKey::from_ec_key(openssl::ec::EcKey::generate(&group).unwrap())
+                .unwrap();
+        let private_key = private_key.private_key_to_pem_pkcs8().unwrap();
+        assert_eq!(
+            unsafe {
+                crate::key_pair::import_key_pair(
+                    id.as_ptr(),
+                    crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_PEM,
+                    private_key.as_ptr(),
+                    private_key.len(),
+                    std::ptr::null(),
+                )
+            },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        // Wrapped keys and key pairs are only checked after they're unwrapped, so wrap real ones.
+        let unwrapping_key_pair_id = c_string("unwrapping");
+        let preferred_algorithms = c_string("rsa-2048");
+        assert_eq!(
+            unsafe {
+                crate::key_pair::create_key_pair_if_not_exists(
+                    unwrapping_key_pair_id.as_ptr(),
+                    preferred_algorithms.as_ptr(),
+                )
+            },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        let wrapping_key = crate::key_pair::load_unwrapping_key("unwrapping")
+            .unwrap()
+            .public_key_to_der()
+            .unwrap();
+
+        let wrapped_key = crate::wrap::wrap(
+            crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP,
+            &wrapping_key,
+            crate::AZIOT_KEYS_KEY_KIND_KEY,
+            crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+            &key,
+        )
+        .unwrap();
+        assert_eq!(
+            unsafe {
+                crate::key::import_wrapped_key(
+                    id.as_ptr(),
+                    unwrapping_key_pair_id.as_ptr(),
+                    crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP,
+                    wrapped_key.as_ptr(),
+                    wrapped_key.len(),
+                )
+            },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        let wrapped_key_pair = crate::wrap::wrap(
+            crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP,
+            &wrapping_key,
+            crate::AZIOT_KEYS_KEY_KIND_KEY_PAIR,
+            crate::AZIOT_KEYS_KEY_USAGE { inner: 0 },
+            &private_key,
+        )
+        .unwrap();
+        assert_eq!(
+            unsafe {
+                crate::key_pair::import_wrapped_key_pair(
+                    id.as_ptr(),
+                    unwrapping_key_pair_id.as_ptr(),
+                    crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP,
+                    wrapped_key_pair.as_ptr(),
+                    wrapped_key_pair.len(),
+                )
+            },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        assert_eq!(
+            std::fs::read(&preloaded_key_path).unwrap(),
+            b"operator-provisioned key",
+        );
+    }
+
+    #[test]
+    fn delete_key_pair() {
+        let _homedir = TestHomedir::new();
+
+        let id = c_string("key-pair");
+        let preferred_algorithms = c_string("ec-p256");
+        assert_eq!(
+            unsafe {
+                crate::key_pair::create_key_pair_if_not_exists(
+                    id.as_ptr(),
+                    preferred_algorithms.as_ptr(),
+                )
+            },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        assert_eq!(
+            unsafe { crate::key_pair::load_key_pair(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        assert_eq!(
+            unsafe { crate::key_pair::delete_key_pair(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        assert_eq!(
+            unsafe { crate::key_pair::load_key_pair(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        // Deleting a key pair that doesn't exist succeeds.
+        assert_eq!(
+            unsafe { crate::key_pair::delete_key_pair(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+    }
+
+    #[test]
+    fn enumerate_keys_without_metadata() {
+        let homedir = TestHomedir::new();
+
+        let preferred_algorithms = c_string("ec-p256");
+        for id in &["legacy", "legacy-key-pair"] {
+            let id = c_string(id);
+            assert_eq!(
+                unsafe {
+                    crate::key_pair::create_key_pair_if_not_exists(
+                        id.as_ptr(),
+                        preferred_algorithms.as_ptr(),
+                    )
+                },
+                crate::AZIOT_KEYS_RC_OK,
+            );
+        }
+
+        // Simulate key pairs that were created before metadata was saved.
+        let keys_dir = homedir.path.join("keys");
+        for dir_entry in std::fs::read_dir(&keys_dir).unwrap() {
+            let path = dir_entry.unwrap().path();
+            if path.extension() == Some(std::ffi::OsStr::new("json")) {
+                std::fs::remove_file(path).unwrap();
+            }
+        }
+
+        // The ID of the first key pair can be recovered from its file name. The ID of the second can't,
+        // since its file name only has the sanitized ID. Enumerating doesn't write any metadata.
+        let ids = |entries: Vec<super::KeyEntry>| -> Vec<String> {
+            entries.into_iter().map(|entry| entry.id).collect()
+        };
+        let metadata_files = || {
+            std::fs::read_dir(&keys_dir)
+                .unwrap()
+                .filter(|dir_entry| {
+                    dir_entry.as_ref().unwrap().path().extension()
+                        == Some(std::ffi::OsStr::new("json"))
+                })
+                .count()
+        };
+        assert_eq!(ids(super::enumerate_keys_inner().unwrap()), ["legacy"]);
+        assert_eq!(metadata_files(), 0);
+
+        // Loading the second key pair doesn't backfill its metadata either.
+        let id = c_string("legacy-key-pair");
+        assert_eq!(
+            unsafe { crate::key_pair::load_key_pair(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        assert_eq!(ids(super::enumerate_keys_inner().unwrap()), ["legacy"]);
+        assert_eq!(metadata_files(), 0);
+
+        // Creating it again does, after which it's enumerated too.
+        assert_eq!(
+            unsafe {
+                crate::key_pair::create_key_pair_if_not_exists(
+                    id.as_ptr(),
+                    preferred_algorithms.as_ptr(),
+                )
+            },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        assert_eq!(metadata_files(), 1);
+        let mut enumerated = ids(super::enumerate_keys_inner().unwrap());
+        enumerated.sort();
+        assert_eq!(enumerated, ["legacy", "legacy-key-pair"]);
+    }
+
+    /// Configures a PKCS#11 token without opening a session to it, since there's no PKCS#11 library to test with.
+    fn configure_pkcs11_token(token_name: &str, lib_path: Option<&str>, base_slot: Option<&str>) {
+        super::PKCS11_TOKENS.write().unwrap().insert(
+            token_name.to_owned(),
+            super::Pkcs11Token {
+                lib_path: lib_path.map(Into::into),
+                base_slot: base_slot.map(|base_slot| base_slot.parse().unwrap()),
+            },
+        );
+    }
+
+    #[test]
+    fn key_route_overlapping_patterns() {
+        let _homedir = TestHomedir::new();
+
+        // Without any routes, keys are routed to the default token.
+        assert_eq!(
+            super::key_route("device-id"),
+            super::KeyRoute::Pkcs11("default".to_owned()),
+        );
+
+        for (pattern, value) in &[
+            ("*", "filesystem"),
+            ("device-*", "tpm"),
+            ("device-?d", "other"),
+            ("device-id", "hsm"),
+        ] {
+            assert_eq!(
+                set_parameter(&format!("key_route:{}", pattern), value),
+                crate::AZIOT_KEYS_RC_OK,
+            );
+        }
+
+        // The pattern with the most non-wildcard characters wins, regardless of the order the routes were set in.
+        assert_eq!(
+            super::key_route("device-id"),
+            super::KeyRoute::Pkcs11("hsm".to_owned()),
+        );
+        assert_eq!(
+            super::key_route("device-xd"),
+            super::KeyRoute::Pkcs11("other".to_owned()),
+        );
+        assert_eq!(
+            super::key_route("device-key"),
+            super::KeyRoute::Pkcs11("tpm".to_owned()),
+        );
+        assert_eq!(super::key_route("module-key"), super::KeyRoute::Filesystem);
+
+        // Setting a route for the same pattern again replaces it.
+        assert_eq!(
+            set_parameter("key_route:device-*", "filesystem"),
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        assert_eq!(super::key_route("device-key"), super::KeyRoute::Filesystem);
+
+        // The pattern must be non-empty, and "filesystem" is not a valid token name.
+        assert_eq!(
+            set_parameter("key_route:", "filesystem"),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            set_parameter("key_route:device-*", ""),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            set_parameter("pkcs11_base_slot:filesystem", "pkcs11:slot-id=0"),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn key_route_filesystem() {
+        let homedir = TestHomedir::new();
+        configure_pkcs11_token(
+            "default",
+            Some("/nonexistent/libpkcs11.so"),
+            Some("pkcs11:slot-id=0"),
+        );
+        assert_eq!(
+            set_parameter("key_route:fs-*", "filesystem"),
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        // Keys routed to the filesystem skip the configured default token.
+        let locations = super::Location::of("fs-key").unwrap();
+        match &locations[..] {
+            [super::Location::Filesystem(path)] => {
+                assert_eq!(
+                    path,
+                    &super::homedir_key_path(&homedir.path, "fs-key").unwrap()
+                );
+            }
+            locations => panic!(
+                "expected only a filesystem location but got {:?}",
+                locations
+            ),
+        }
+
+        // Other keys use the default token first.
+        let locations = super::Location::of("other-key").unwrap();
+        match &locations[..] {
+            [super::Location::Pkcs11 { uri, .. }, super::Location::Filesystem(_)] => {
+                assert_eq!(uri.object_label.as_deref(), Some("other-key"));
+            }
+            locations => panic!(
+                "expected a PKCS#11 location followed by a filesystem location but got {:?}",
+                locations,
+            ),
+        }
+    }
+
+    #[test]
+    fn key_route_unconfigured_token() {
+        let homedir = TestHomedir::new();
+        assert_eq!(
+            set_parameter("key_route:hsm-*", "hsm"),
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        // A route to a named token that isn't configured must not fall back to the filesystem.
+        assert_eq!(
+            super::Location::of("hsm-key").unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        let id = c_string("hsm-key");
+        assert_eq!(
+            unsafe {
+                crate::key::create_key_if_not_exists(id.as_ptr(), crate::AZIOT_KEYS_KEY_USAGE_SIGN)
+            },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert!(!super::homedir_key_path(&homedir.path, "hsm-key")
+            .unwrap()
+            .exists());
+
+        // Neither can a token that only has one of its lib path and base slot configured.
+        configure_pkcs11_token("hsm", Some("/nonexistent/libpkcs11.so"), None);
+        assert_eq!(
+            super::Location::of("hsm-key").unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        configure_pkcs11_token("hsm", None, Some("pkcs11:slot-id=0"));
+        assert_eq!(
+            super::Location::of("hsm-key").unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        // The default token is optional, so other keys still fall back to the filesystem.
+        match &super::Location::of("other-key").unwrap()[..] {
+            [super::Location::Filesystem(_)] => (),
+            locations => panic!(
+                "expected only a filesystem location but got {:?}",
+                locations
+            ),
+        }
+    }
+
+    #[test]
+    fn pkcs11_object_label() {
+        let uri: pkcs11::Uri = "pkcs11:slot-id=0;object=key".parse().unwrap();
+        assert_eq!(super::pkcs11_object_label(&uri).unwrap(), "key");
+
+        let uri: pkcs11::Uri = "pkcs11:slot-id=0".parse().unwrap();
+        assert_eq!(
+            super::pkcs11_object_label(&uri).unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn concurrent_create_key_pair_if_not_exists() {
+        let homedir = TestHomedir::new();
+
+        // Every caller must end up with the same key pair, rather than a later one replacing the key pair an earlier one got.
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    let id = c_string("key-pair");
+                    let preferred_algorithms = c_string("ec-p256");
+                    assert_eq!(
+                        unsafe {
+                            crate::key_pair::create_key_pair_if_not_exists(
+                                id.as_ptr(),
+                                preferred_algorithms.as_ptr(),
+                            )
+                        },
+                        crate::AZIOT_KEYS_RC_OK,
+                    );
+
+                    let locations = super::Location::of("key-pair").unwrap();
+                    match &locations[..] {
+                        [super::Location::Filesystem(path)] => {
+                            super::read_key_file(path).unwrap().unwrap()
+                        }
+                        locations => panic!(
+                            "expected only a filesystem location but got {:?}",
+                            locations
+                        ),
+                    }
+                })
+            })
+            .collect();
+        let private_keys: Vec<_> = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+        assert!(private_keys
+            .iter()
+            .all(|private_key| *private_key == private_keys[0]));
+
+        // No temporary files are left behind.
+        let mut extensions: Vec<_> = std::fs::read_dir(homedir.path.join("keys"))
+            .unwrap()
+            .map(|dir_entry| dir_entry.unwrap().path())
+            .filter_map(|path| path.extension().map(ToOwned::to_owned))
+            .collect();
+        extensions.sort();
+        assert_eq!(extensions, ["json", "key"]);
+
+        // Creating a key file that already exists fails instead of replacing it.
+        let path = super::homedir_key_path(&homedir.path, "key-pair").unwrap();
+        assert_eq!(
+            super::write_key_file(&path, b"other key", false).unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            super::read_key_file(&path).unwrap().unwrap(),
+            private_keys[0]
+        );
+    }
+}
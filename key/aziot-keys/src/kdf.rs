@@ -0,0 +1,239 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Key derivation functions used to turn shared secrets and base keys into key material.
+
+/// The longest key that can be derived from a base key, and that [`kbkdf_counter`] derives, in bytes.
+///
+/// Derived keys are used as symmetric keys, so this is far more than any caller needs,
+/// but keeps a caller from making the service allocate and compute arbitrarily long outputs.
+pub(crate) const MAX_DERIVED_KEY_LEN: usize = 1024;
+
+/// RFC 5869 HKDF. An empty `salt` is treated as a string of zeros of the digest's length, as the RFC specifies.
+pub(crate) fn hkdf(
+    digest: openssl::hash::MessageDigest,
+    ikm: &[u8],
+    salt: &[u8],
+    info: &[u8],
+    len: usize,
+) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    let hash_len = digest.size();
+
+    if len > 255 * hash_len {
+        return Err(crate::implementation::err_invalid_parameter(
+            "parameters",
+            "HKDF output length is too large",
+        ));
+    }
+
+    let zero_salt;
+    let salt = if salt.is_empty() {
+        zero_salt = vec![0_u8; hash_len];
+        &zero_salt[..]
+    } else {
+        salt
+    };
+
+    let prk = hmac(digest, salt, &[ikm])?;
+
+    let mut okm = Vec::with_capacity(len + hash_len);
+    let mut t = vec![];
+    let mut counter = 1_u8;
+    while okm.len() < len {
+        t = hmac(digest, &prk, &[&t, info, &[counter]])?;
+        okm.extend_from_slice(&t);
+        counter = counter.wrapping_add(1);
+    }
+    okm.truncate(len);
+
+    Ok(okm)
+}
+
+/// NIST SP 800-108 KDF in counter mode. `prf` is called with the concatenated PRF input for each block,
+/// ie `[i]_32 || label || 0x00 || context || [L]_32`, and must return one block of output.
+pub(crate) fn kbkdf_counter(
+    mut prf: impl FnMut(&[u8]) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC>,
+    label: &[u8],
+    context: &[u8],
+    len: usize,
+) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    if len > MAX_DERIVED_KEY_LEN {
+        return Err(crate::implementation::err_invalid_parameter(
+            "parameters",
+            "KBKDF output length is too large",
+        ));
+    }
+    let len_bits: u32 =
+        std::convert::TryInto::try_into(len * 8).expect("bounded by MAX_DERIVED_KEY_LEN");
+
+    let mut input = Vec::with_capacity(4 + label.len() + 1 + context.len() + 4);
+    input.extend_from_slice(&[0; 4]);
+    input.extend_from_slice(label);
+    input.push(0x00);
+    input.extend_from_slice(context);
+    input.extend_from_slice(&len_bits.to_be_bytes());
+
+    let mut okm = Vec::with_capacity(len);
+    let mut counter = 1_u32;
+    while okm.len() < len {
+        input[..4].copy_from_slice(&counter.to_be_bytes());
+        let block = prf(&input)?;
+        okm.extend_from_slice(&block);
+        counter += 1;
+    }
+    okm.truncate(len);
+
+    Ok(okm)
+}
+
+pub(crate) fn hmac(
+    digest: openssl::hash::MessageDigest,
+    key: &[u8],
+    data: &[&[u8]],
+) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    let key = openssl::pkey::PKey::hmac(key)?;
+    let mut signer = openssl::sign::Signer::new(digest, &key)?;
+    for data in data {
+        signer.update(data)?;
+    }
+    let result = signer.sign_to_vec()?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn hkdf_rfc5869() {
+        // RFC 5869 appendix A.1 - A.3 (the SHA-256 test cases)
+        let test_cases: &[(Vec<u8>, Vec<u8>, Vec<u8>, &str)] = &[
+            (
+                vec![0x0b; 22],
+                (0x00..=0x0c).collect(),
+                (0xf0..=0xf9).collect(),
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865",
+            ),
+            (
+                (0x00..=0x4f).collect(),
+                (0x60..=0xaf).collect(),
+                (0xb0..=0xff).collect(),
+                "b11e398dc80327a1c8e7f78c596a49344f012eda2d4efad8a050cc4c19afa97c59045a99cac7827271cb41c65e590e09da3275600c2f09b8367793a9aca3db71cc30c58179ec3e87c14c01d5c1f3434f1d87",
+            ),
+            (
+                vec![0x0b; 22],
+                vec![],
+                vec![],
+                "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8",
+            ),
+        ];
+
+        for (ikm, salt, info, expected_okm) in test_cases {
+            let expected_okm = hex::decode(expected_okm).unwrap();
+            let okm = super::hkdf(
+                openssl::hash::MessageDigest::sha256(),
+                ikm,
+                salt,
+                info,
+                expected_okm.len(),
+            )
+            .unwrap();
+            assert_eq!(okm, expected_okm);
+        }
+    }
+
+    #[test]
+    fn hkdf_output_too_large() {
+        let digest = openssl::hash::MessageDigest::sha256();
+        assert_eq!(
+            super::hkdf(digest, b"ikm", b"", b"", 255 * 32)
+                .unwrap()
+                .len(),
+            255 * 32,
+        );
+        assert_eq!(
+            super::hkdf(digest, b"ikm", b"", b"", 255 * 32 + 1).unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn kbkdf_counter_hmac() {
+        // HMAC PRF, 32-bit counter before the fixed input data, `label || 0x00 || context || [L]_32` as the fixed input data.
+        // Generated with OpenSSL's KBKDF in counter mode, and cross-checked with pyca/cryptography's KBKDFHMAC.
+        let key: Vec<u8> = (0x00..=0x1f).collect();
+        let test_cases: &[(openssl::hash::MessageDigest, &[u8], &[u8], &str)] = &[
+            (
+                openssl::hash::MessageDigest::sha256(),
+                b"label",
+                b"context",
+                "b5b9302655fb936972e89fc9706fc6d7",
+            ),
+            (
+                openssl::hash::MessageDigest::sha256(),
+                b"label",
+                b"context",
+                "b9cd5f6323f01f4680650855f1ebea9b4c54c08131b506fc28c856364a38a2f4fb680c12ea51696887d9",
+            ),
+            (
+                openssl::hash::MessageDigest::sha512(),
+                b"label",
+                b"context",
+                "ab027949cfe94af9236b9f09a354d37c6acc659188ae5772d456570218604f3e5c1a9ab69b50a813194cd34e6b764722e78ca3fffc72459c6625428147cbf69a",
+            ),
+            (
+                openssl::hash::MessageDigest::sha256(),
+                b"",
+                b"",
+                "be27b734b68c7782d866819ae95ae24560bbd593476e9f8c0bf87db0b30a0316",
+            ),
+        ];
+
+        for &(digest, label, context, expected_okm) in test_cases {
+            let expected_okm = hex::decode(expected_okm).unwrap();
+            let okm = super::kbkdf_counter(
+                |input| super::hmac(digest, &key, &[input]),
+                label,
+                context,
+                expected_okm.len(),
+            )
+            .unwrap();
+            assert_eq!(okm, expected_okm);
+        }
+    }
+
+    #[test]
+    fn kbkdf_counter_output_too_large() {
+        let prf = |_: &[u8]| Ok(vec![0xff; 32]);
+        assert_eq!(
+            super::kbkdf_counter(prf, b"", b"", super::MAX_DERIVED_KEY_LEN)
+                .unwrap()
+                .len(),
+            super::MAX_DERIVED_KEY_LEN,
+        );
+        assert_eq!(
+            super::kbkdf_counter(prf, b"", b"", super::MAX_DERIVED_KEY_LEN + 1).unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn kbkdf_counter_prf_input() {
+        let mut inputs = vec![];
+        let okm = super::kbkdf_counter(
+            |input| {
+                inputs.push(input.to_owned());
+                Ok(vec![0xff; 4])
+            },
+            b"ab",
+            b"cd",
+            6,
+        )
+        .unwrap();
+        assert_eq!(okm, [0xff; 6]);
+        assert_eq!(
+            inputs,
+            [
+                b"\x00\x00\x00\x01ab\x00cd\x00\x00\x00\x30",
+                b"\x00\x00\x00\x02ab\x00cd\x00\x00\x00\x30",
+            ],
+        );
+    }
+}
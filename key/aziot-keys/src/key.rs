@@ -19,6 +19,9 @@ pub(crate) unsafe extern "C" fn create_key_if_not_exists(
             id
         };
 
+        // Held until this function returns, so that no other call creates, imports or deletes this ID in the meantime.
+        let _id_lock = crate::implementation::lock_id(id);
+
         let locations = crate::implementation::Location::of(id)?;
 
         if load_inner(&locations)?.is_none() {
@@ -28,6 +31,17 @@ pub(crate) unsafe extern "C" fn create_key_if_not_exists(
                     "key created successfully but could not be found",
                 ));
             }
+
+            crate::implementation::save_metadata(
+                id,
+                &locations,
+                Some(crate::implementation::Creation {
+                    origin: crate::implementation::Origin::Generated,
+                    usage: Some(usage),
+                }),
+            )?;
+        } else {
+            crate::implementation::save_metadata(id, &locations, None)?;
         }
 
         Ok(())
@@ -68,6 +82,25 @@ pub(crate) unsafe extern "C" fn import_key(
     bytes: *const u8,
     bytes_len: usize,
     usage: crate::AZIOT_KEYS_KEY_USAGE,
+) -> crate::AZIOT_KEYS_RC {
+    import_key_inner(id, bytes, bytes_len, usage, true)
+}
+
+pub(crate) unsafe extern "C" fn import_key_if_not_exists(
+    id: *const std::os::raw::c_char,
+    bytes: *const u8,
+    bytes_len: usize,
+    usage: crate::AZIOT_KEYS_KEY_USAGE,
+) -> crate::AZIOT_KEYS_RC {
+    import_key_inner(id, bytes, bytes_len, usage, false)
+}
+
+unsafe fn import_key_inner(
+    id: *const std::os::raw::c_char,
+    bytes: *const u8,
+    bytes_len: usize,
+    usage: crate::AZIOT_KEYS_KEY_USAGE,
+    overwrite: bool,
 ) -> crate::AZIOT_KEYS_RC {
     crate::r#catch(|| {
         let id = {
@@ -93,8 +126,20 @@ pub(crate) unsafe extern "C" fn import_key(
 
         let bytes = std::slice::from_raw_parts(bytes, bytes_len);
 
+        // Held until this function returns, so that no other call creates the key between checking for it and importing it.
+        let _id_lock = crate::implementation::lock_id(id);
+
+        crate::implementation::ensure_not_preloaded(id)?;
+
         let locations = crate::implementation::Location::of(id)?;
 
+        if !overwrite && load_inner(&locations)?.is_some() {
+            return Err(crate::implementation::err_invalid_parameter(
+                "id",
+                "a key with this ID already exists",
+            ));
+        }
+
         create_inner(&locations, CreateMethod::Import(bytes), usage)?;
         if load_inner(&locations)?.is_none() {
             return Err(crate::implementation::err_external(
@@ -102,6 +147,46 @@ pub(crate) unsafe extern "C" fn import_key(
             ));
         }
 
+        crate::implementation::save_metadata(
+            id,
+            &locations,
+            Some(crate::implementation::Creation {
+                origin: crate::implementation::Origin::Imported,
+                usage: Some(usage),
+            }),
+        )?;
+
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn delete_key(
+    id: *const std::os::raw::c_char,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let id = {
+            if id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "expected non-NULL",
+                ));
+            }
+            let id = std::ffi::CStr::from_ptr(id);
+            let id = id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("id", err))?;
+            id
+        };
+
+        let _id_lock = crate::implementation::lock_id(id);
+
+        crate::implementation::ensure_not_preloaded(id)?;
+
+        let locations = crate::implementation::Location::of(id)?;
+
+        delete_inner(&locations)?;
+        crate::implementation::delete_metadata(id, &locations)?;
+
         Ok(())
     })
 }
@@ -112,6 +197,26 @@ pub(crate) unsafe extern "C" fn derive_key(
     derivation_data_len: usize,
     derived_key: *mut std::os::raw::c_uchar,
     derived_key_len: *mut usize,
+) -> crate::AZIOT_KEYS_RC {
+    derive_key_with_mechanism(
+        base_id,
+        crate::AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256,
+        std::ptr::null(),
+        derivation_data,
+        derivation_data_len,
+        derived_key,
+        derived_key_len,
+    )
+}
+
+pub(crate) unsafe extern "C" fn derive_key_with_mechanism(
+    base_id: *const std::os::raw::c_char,
+    mechanism: crate::AZIOT_KEYS_KEY_DERIVATION_MECHANISM,
+    parameters: *const std::ffi::c_void,
+    derivation_data: *const u8,
+    derivation_data_len: usize,
+    derived_key: *mut std::os::raw::c_uchar,
+    derived_key_len: *mut usize,
 ) -> crate::AZIOT_KEYS_RC {
     crate::r#catch(|| {
         let base_id = {
@@ -144,8 +249,21 @@ pub(crate) unsafe extern "C" fn derive_key(
             }
         };
 
-        let expected_derived_key =
-            derive_key_common(&base_key, derivation_data, derivation_data_len)?;
+        check_usage(
+            base_id,
+            &locations,
+            &base_key,
+            crate::AZIOT_KEYS_KEY_USAGE_DERIVE,
+            "base_id",
+        )?;
+
+        let expected_derived_key = derive_key_common(
+            &base_key,
+            mechanism,
+            parameters,
+            derivation_data,
+            derivation_data_len,
+        )?;
         let expected_derived_key_len = expected_derived_key.len();
 
         let actual_derived_key_len = *derived_key_len_out.as_ref();
@@ -173,7 +291,286 @@ pub(crate) unsafe extern "C" fn derive_key(
     })
 }
 
+pub(crate) unsafe extern "C" fn get_key_parameter(
+    id: *const std::os::raw::c_char,
+    r#type: crate::AZIOT_KEYS_KEY_PARAMETER_TYPE,
+    value: *mut std::os::raw::c_uchar,
+    value_len: *mut usize,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let id = {
+            if id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "expected non-NULL",
+                ));
+            }
+            let id = std::ffi::CStr::from_ptr(id);
+            let id = id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("id", err))?;
+            id
+        };
+
+        let mut value_len_out = std::ptr::NonNull::new(value_len).ok_or_else(|| {
+            crate::implementation::err_invalid_parameter("value_len", "expected non-NULL")
+        })?;
+
+        let locations = crate::implementation::Location::of(id)?;
+
+        let key = load_inner(&locations)?
+            .ok_or_else(|| crate::implementation::err_invalid_parameter("id", "not found"))?;
+
+        let expected_value = match r#type {
+            crate::AZIOT_KEYS_KEY_PARAMETER_TYPE_USAGE => {
+                let usage = usage_of(id, &locations, &key)?.unwrap_or(NO_USAGE);
+                usage.inner.to_ne_bytes().to_vec()
+            }
+
+            crate::AZIOT_KEYS_KEY_PARAMETER_TYPE_ORIGIN => {
+                let origin = match &key {
+                    Key::FileSystem(_) => {
+                        match crate::implementation::load_metadata(id, &locations)?
+                            .and_then(|metadata| metadata.origin)
+                        {
+                            Some(crate::implementation::Origin::Generated) => {
+                                crate::AZIOT_KEYS_KEY_ORIGIN_GENERATED
+                            }
+                            Some(crate::implementation::Origin::Imported) => {
+                                crate::AZIOT_KEYS_KEY_ORIGIN_IMPORTED
+                            }
+                            None => crate::AZIOT_KEYS_KEY_ORIGIN_UNKNOWN,
+                        }
+                    }
+
+                    Key::Pkcs11(key) => {
+                        let attributes = key
+                            .attributes()
+                            .map_err(crate::implementation::err_external)?;
+                        if attributes.local {
+                            crate::AZIOT_KEYS_KEY_ORIGIN_GENERATED
+                        } else {
+                            crate::AZIOT_KEYS_KEY_ORIGIN_IMPORTED
+                        }
+                    }
+                };
+                origin.inner.to_ne_bytes().to_vec()
+            }
+
+            crate::AZIOT_KEYS_KEY_PARAMETER_TYPE_CREATION_TIME => {
+                // PKCS#11 has no attribute for the creation time of an object, so this is only known for filesystem keys.
+                let created_at = match &key {
+                    Key::FileSystem(_) => crate::implementation::load_metadata(id, &locations)?
+                        .and_then(|metadata| metadata.created_at),
+                    Key::Pkcs11(_) => None,
+                };
+                created_at.unwrap_or_default().to_ne_bytes().to_vec()
+            }
+
+            _ => {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "type",
+                    "unrecognized value",
+                ))
+            }
+        };
+        let expected_value_len = expected_value.len();
+
+        let actual_value_len = *value_len_out.as_ref();
+
+        *value_len_out.as_mut() = expected_value_len;
+
+        if !value.is_null() {
+            if actual_value_len < expected_value_len {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "value",
+                    "insufficient size",
+                ));
+            }
+
+            let value_out = std::slice::from_raw_parts_mut(value, actual_value_len);
+
+            value_out[..expected_value_len].copy_from_slice(&expected_value);
+        }
+
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn export_key(
+    id: *const std::os::raw::c_char,
+    mechanism: crate::AZIOT_KEYS_WRAP_MECHANISM,
+    wrapping_key: *const std::os::raw::c_uchar,
+    wrapping_key_len: usize,
+    wrapped_key: *mut std::os::raw::c_uchar,
+    wrapped_key_len: *mut usize,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let id = {
+            if id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "expected non-NULL",
+                ));
+            }
+            let id = std::ffi::CStr::from_ptr(id);
+            let id = id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("id", err))?;
+            id
+        };
+
+        if wrapping_key.is_null() {
+            return Err(crate::implementation::err_invalid_parameter(
+                "wrapping_key",
+                "expected non-NULL",
+            ));
+        }
+        let wrapping_key = std::slice::from_raw_parts(wrapping_key, wrapping_key_len);
+
+        let mut wrapped_key_len_out = std::ptr::NonNull::new(wrapped_key_len).ok_or_else(|| {
+            crate::implementation::err_invalid_parameter("wrapped_key_len", "expected non-NULL")
+        })?;
+
+        let locations = crate::implementation::Location::of(id)?;
+
+        let key = load_inner(&locations)?
+            .ok_or_else(|| crate::implementation::err_invalid_parameter("id", "not found"))?;
+
+        // The usage is part of the wrapped key, and a key whose usage is not known is not restricted to any,
+        // so exporting it would give the importer a key that's either unusable or unrestricted.
+        let usage = usage_of(id, &locations, &key)?.ok_or_else(|| {
+            crate::implementation::err_invalid_parameter(
+                "id",
+                "key's usage is not known, so it cannot be exported",
+            )
+        })?;
+
+        let key_bytes = match &key {
+            Key::FileSystem(key_bytes) => key_bytes,
+            Key::Pkcs11(_) => {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "PKCS#11 keys cannot be exported",
+                ))
+            }
+        };
+
+        let expected_wrapped_key = crate::wrap::wrap(
+            mechanism,
+            wrapping_key,
+            crate::AZIOT_KEYS_KEY_KIND_KEY,
+            usage,
+            key_bytes,
+        )?;
+        let expected_wrapped_key_len = expected_wrapped_key.len();
+
+        let actual_wrapped_key_len = *wrapped_key_len_out.as_ref();
+
+        *wrapped_key_len_out.as_mut() = expected_wrapped_key_len;
+
+        if !wrapped_key.is_null() {
+            if actual_wrapped_key_len < expected_wrapped_key_len {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "wrapped_key",
+                    "insufficient size",
+                ));
+            }
+
+            let wrapped_key_out =
+                std::slice::from_raw_parts_mut(wrapped_key, actual_wrapped_key_len);
+
+            wrapped_key_out[..expected_wrapped_key_len].copy_from_slice(&expected_wrapped_key);
+        }
+
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn import_wrapped_key(
+    id: *const std::os::raw::c_char,
+    unwrapping_key_pair_id: *const std::os::raw::c_char,
+    mechanism: crate::AZIOT_KEYS_WRAP_MECHANISM,
+    wrapped_key: *const std::os::raw::c_uchar,
+    wrapped_key_len: usize,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let id = {
+            if id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "expected non-NULL",
+                ));
+            }
+            let id = std::ffi::CStr::from_ptr(id);
+            let id = id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("id", err))?;
+            id
+        };
+
+        let unwrapping_key_pair_id = {
+            if unwrapping_key_pair_id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "unwrapping_key_pair_id",
+                    "expected non-NULL",
+                ));
+            }
+            let unwrapping_key_pair_id = std::ffi::CStr::from_ptr(unwrapping_key_pair_id);
+            let unwrapping_key_pair_id = unwrapping_key_pair_id.to_str().map_err(|err| {
+                crate::implementation::err_invalid_parameter("unwrapping_key_pair_id", err)
+            })?;
+            unwrapping_key_pair_id
+        };
+
+        if wrapped_key.is_null() {
+            return Err(crate::implementation::err_invalid_parameter(
+                "wrapped_key",
+                "expected non-NULL",
+            ));
+        }
+        let wrapped_key = std::slice::from_raw_parts(wrapped_key, wrapped_key_len);
+
+        let unwrapping_key = crate::key_pair::load_unwrapping_key(unwrapping_key_pair_id)?;
+        let unwrapped = crate::wrap::unwrap(
+            mechanism,
+            &unwrapping_key,
+            crate::AZIOT_KEYS_KEY_KIND_KEY,
+            wrapped_key,
+        )?;
+
+        let _id_lock = crate::implementation::lock_id(id);
+
+        crate::implementation::ensure_not_preloaded(id)?;
+
+        let locations = crate::implementation::Location::of(id)?;
+
+        create_inner(
+            &locations,
+            CreateMethod::Import(&unwrapped.payload),
+            unwrapped.usage,
+        )?;
+        if load_inner(&locations)?.is_none() {
+            return Err(crate::implementation::err_external(
+                "key created successfully but could not be found",
+            ));
+        }
+
+        crate::implementation::save_metadata(
+            id,
+            &locations,
+            Some(crate::implementation::Creation {
+                origin: crate::implementation::Origin::Imported,
+                usage: Some(unwrapped.usage),
+            }),
+        )?;
+
+        Ok(())
+    })
+}
+
 pub(crate) unsafe fn sign(
+    id: &str,
     locations: &[crate::implementation::Location],
     mechanism: crate::AZIOT_KEYS_SIGN_MECHANISM,
     parameters: *const std::ffi::c_void,
@@ -189,37 +586,50 @@ pub(crate) unsafe fn sign(
         }
     };
 
-    let (key, mechanism, _) = if mechanism == crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED {
-        derive_key_for_sign(&key, parameters)?
+    let (key, mechanism, _) = if mechanism == crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED
+        || mechanism == crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM
+    {
+        check_usage(
+            id,
+            locations,
+            &key,
+            crate::AZIOT_KEYS_KEY_USAGE_DERIVE,
+            "mechanism",
+        )?;
+        derive_key_for_sign(&key, mechanism, parameters)?
     } else {
+        check_usage(
+            id,
+            locations,
+            &key,
+            crate::AZIOT_KEYS_KEY_USAGE_SIGN,
+            "mechanism",
+        )?;
         (key, mechanism, parameters)
     };
 
-    if mechanism != crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256 {
-        return Err(crate::implementation::err_invalid_parameter(
-            "mechanism",
-            "unrecognized value",
-        ));
-    }
+    let digest_algorithm = hmac_digest_algorithm(mechanism)?;
 
     match key {
         Key::FileSystem(key) => {
-            use hmac::{Mac, NewMac};
-
-            let mut signer = hmac::Hmac::<sha2::Sha256>::new_varkey(&key)
-                .map_err(crate::implementation::err_external)?;
-
-            signer.update(digest);
-
-            let signature = signer.finalize();
-            let signature = signature.into_bytes().to_vec();
+            let signature = match digest_algorithm {
+                pkcs11::DigestAlgorithm::Sha256 => {
+                    hmac_sign::<hmac::Hmac<sha2::Sha256>>(&key, digest)?
+                }
+                pkcs11::DigestAlgorithm::Sha384 => {
+                    hmac_sign::<hmac::Hmac<sha2::Sha384>>(&key, digest)?
+                }
+                pkcs11::DigestAlgorithm::Sha512 => {
+                    hmac_sign::<hmac::Hmac<sha2::Sha512>>(&key, digest)?
+                }
+            };
             Ok((signature.len(), signature))
         }
 
         Key::Pkcs11(key) => {
-            let mut signature = vec![0_u8; 32];
+            let mut signature = vec![0_u8; hmac_len(digest_algorithm)];
             let signature_len = key
-                .sign(digest, &mut signature)
+                .sign(digest_algorithm, digest, &mut signature)
                 .map_err(crate::implementation::err_external)?;
             let signature_len =
                 std::convert::TryInto::try_into(signature_len).expect("CK_ULONG -> usize");
@@ -230,7 +640,9 @@ pub(crate) unsafe fn sign(
 }
 
 pub(crate) unsafe fn verify(
+    id: &str,
     locations: &[crate::implementation::Location],
+    mechanism: crate::AZIOT_KEYS_SIGN_MECHANISM,
     digest: &[u8],
     signature: &[u8],
 ) -> Result<bool, crate::AZIOT_KEYS_RC> {
@@ -244,30 +656,90 @@ pub(crate) unsafe fn verify(
         }
     };
 
-    match key {
-        Key::FileSystem(key) => {
-            use hmac::{Mac, NewMac};
-
-            let mut signer = hmac::Hmac::<sha2::Sha256>::new_varkey(&key)
-                .map_err(crate::implementation::err_external)?;
+    check_usage(
+        id,
+        locations,
+        &key,
+        crate::AZIOT_KEYS_KEY_USAGE_SIGN,
+        "mechanism",
+    )?;
 
-            signer.update(digest);
+    let digest_algorithm = hmac_digest_algorithm(mechanism)?;
 
-            // As hmac's docs say, it's important to use `verify` here instead of just running `finalize().into_bytes()` and comparing the signatures,
-            // because `verify` makes sure to be constant-time.
-            let ok = signer.verify(signature).is_ok();
+    match key {
+        Key::FileSystem(key) => {
+            let ok = match digest_algorithm {
+                pkcs11::DigestAlgorithm::Sha256 => {
+                    hmac_verify::<hmac::Hmac<sha2::Sha256>>(&key, digest, signature)?
+                }
+                pkcs11::DigestAlgorithm::Sha384 => {
+                    hmac_verify::<hmac::Hmac<sha2::Sha384>>(&key, digest, signature)?
+                }
+                pkcs11::DigestAlgorithm::Sha512 => {
+                    hmac_verify::<hmac::Hmac<sha2::Sha512>>(&key, digest, signature)?
+                }
+            };
             Ok(ok)
         }
 
         Key::Pkcs11(key) => {
             let ok = key
-                .verify(digest, signature)
+                .verify(digest_algorithm, digest, signature)
                 .map_err(crate::implementation::err_external)?;
             Ok(ok)
         }
     }
 }
 
+fn hmac_digest_algorithm(
+    mechanism: crate::AZIOT_KEYS_SIGN_MECHANISM,
+) -> Result<pkcs11::DigestAlgorithm, crate::AZIOT_KEYS_RC> {
+    match mechanism {
+        crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256 => Ok(pkcs11::DigestAlgorithm::Sha256),
+        crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA384 => Ok(pkcs11::DigestAlgorithm::Sha384),
+        crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA512 => Ok(pkcs11::DigestAlgorithm::Sha512),
+        _ => Err(crate::implementation::err_invalid_parameter(
+            "mechanism",
+            "unrecognized value",
+        )),
+    }
+}
+
+pub(crate) fn hmac_len(digest_algorithm: pkcs11::DigestAlgorithm) -> usize {
+    match digest_algorithm {
+        pkcs11::DigestAlgorithm::Sha256 => 32,
+        pkcs11::DigestAlgorithm::Sha384 => 48,
+        pkcs11::DigestAlgorithm::Sha512 => 64,
+    }
+}
+
+fn hmac_sign<M>(key: &[u8], digest: &[u8]) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC>
+where
+    M: hmac::Mac + hmac::NewMac,
+{
+    let mut signer = M::new_varkey(key).map_err(crate::implementation::err_external)?;
+
+    signer.update(digest);
+
+    let signature = signer.finalize();
+    let signature = signature.into_bytes().to_vec();
+    Ok(signature)
+}
+
+fn hmac_verify<M>(key: &[u8], digest: &[u8], signature: &[u8]) -> Result<bool, crate::AZIOT_KEYS_RC>
+where
+    M: hmac::Mac + hmac::NewMac,
+{
+    let mut signer = M::new_varkey(key).map_err(crate::implementation::err_external)?;
+
+    signer.update(digest);
+
+    // As hmac's docs say, it's important to use `verify` here instead of just running `finalize().into_bytes()` and comparing the signatures,
+    // because `verify` makes sure to be constant-time.
+    let ok = signer.verify(signature).is_ok();
+    Ok(ok)
+}
+
 // Ciphertext is formatted as:
 //
 // - Encryption scheme version (1 byte)
@@ -315,6 +787,7 @@ pub(crate) unsafe fn verify(
 // [1]: https://docs.oasis-open.org/pkcs11/pkcs11-curr/v2.40/pkcs11-curr-v2.40.html#_Toc370634467
 
 pub(crate) unsafe fn encrypt(
+    id: &str,
     locations: &[crate::implementation::Location],
     mechanism: crate::AZIOT_KEYS_ENCRYPT_MECHANISM,
     parameters: *const std::ffi::c_void,
@@ -330,19 +803,51 @@ pub(crate) unsafe fn encrypt(
         }
     };
 
-    let (key, mechanism, parameters) = if mechanism == crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED {
-        derive_key_for_encrypt(&key, parameters)?
+    let (key, mechanism, parameters) = if mechanism == crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED
+        || mechanism == crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM
+    {
+        check_usage(
+            id,
+            locations,
+            &key,
+            crate::AZIOT_KEYS_KEY_USAGE_DERIVE,
+            "mechanism",
+        )?;
+        derive_key_for_encrypt(&key, mechanism, parameters)?
     } else {
+        check_usage(
+            id,
+            locations,
+            &key,
+            crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+            "mechanism",
+        )?;
         (key, mechanism, parameters)
     };
 
-    if mechanism != crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD {
-        return Err(crate::implementation::err_invalid_parameter(
-            "mechanism",
+    match mechanism {
+        crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD => encrypt_aead(key, parameters, plaintext),
+
+        crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD
+        | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR
+        | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP
+        | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP_PAD => {
+            let mechanism = aes_mechanism(mechanism, parameters)?;
+            encrypt_aes(key, &mechanism, plaintext)
+        }
+
+        _ => Err(crate::implementation::err_invalid_parameter(
+            "mechanism",
             "unrecognized value",
-        ));
+        )),
     }
+}
 
+unsafe fn encrypt_aead(
+    key: Key,
+    parameters: *const std::ffi::c_void,
+    plaintext: &[u8],
+) -> Result<(usize, Vec<u8>), crate::AZIOT_KEYS_RC> {
     let (iv, aad) = {
         if parameters.is_null() {
             return Err(crate::implementation::err_invalid_parameter(
@@ -379,7 +884,11 @@ pub(crate) unsafe fn encrypt(
             ciphertext[0] = 0x02;
 
             let ciphertext_len = key
-                .encrypt(iv, aad, plaintext, &mut ciphertext[1..])
+                .encrypt(
+                    &pkcs11::AesMechanism::Gcm { iv, aad },
+                    plaintext,
+                    &mut ciphertext[1..],
+                )
                 .map_err(crate::implementation::err_external)?;
             let ciphertext_len: usize =
                 std::convert::TryInto::try_into(ciphertext_len).expect("CK_ULONG -> usize");
@@ -391,7 +900,47 @@ pub(crate) unsafe fn encrypt(
     }
 }
 
+unsafe fn encrypt_aes(
+    key: Key,
+    mechanism: &pkcs11::AesMechanism<'_>,
+    plaintext: &[u8],
+) -> Result<(usize, Vec<u8>), crate::AZIOT_KEYS_RC> {
+    match key {
+        Key::FileSystem(key) => {
+            let ciphertext = match mechanism {
+                pkcs11::AesMechanism::CbcPad { iv } | pkcs11::AesMechanism::Ctr { iv } => {
+                    openssl::symm::encrypt(
+                        aes_cipher(&key, mechanism)?,
+                        &key,
+                        Some(&iv[..]),
+                        plaintext,
+                    )?
+                }
+                pkcs11::AesMechanism::KeyWrap => crate::aes_key_wrap::wrap(&key, plaintext)?,
+                pkcs11::AesMechanism::KeyWrapPad => crate::aes_key_wrap::wrap_pad(&key, plaintext)?,
+                pkcs11::AesMechanism::Gcm { .. } => unreachable!("AEAD is handled by encrypt_aead"),
+            };
+            Ok((ciphertext.len(), ciphertext))
+        }
+
+        Key::Pkcs11(key) => {
+            // None of the mechanisms add more than one AES block to the plaintext.
+            let mut ciphertext = vec![0_u8; plaintext.len() + 16];
+
+            let ciphertext_len = key
+                .encrypt(mechanism, plaintext, &mut ciphertext)
+                .map_err(crate::implementation::err_external)?;
+            let ciphertext_len: usize =
+                std::convert::TryInto::try_into(ciphertext_len).expect("CK_ULONG -> usize");
+            ciphertext.truncate(ciphertext_len);
+
+            Ok((ciphertext_len, ciphertext))
+        }
+    }
+}
+
 pub(crate) unsafe fn decrypt(
+    id: &str,
     locations: &[crate::implementation::Location],
     mechanism: crate::AZIOT_KEYS_ENCRYPT_MECHANISM,
     parameters: *const std::ffi::c_void,
@@ -407,19 +956,51 @@ pub(crate) unsafe fn decrypt(
         }
     };
 
-    let (key, mechanism, parameters) = if mechanism == crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED {
-        derive_key_for_encrypt(&key, parameters)?
+    let (key, mechanism, parameters) = if mechanism == crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED
+        || mechanism == crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM
+    {
+        check_usage(
+            id,
+            locations,
+            &key,
+            crate::AZIOT_KEYS_KEY_USAGE_DERIVE,
+            "mechanism",
+        )?;
+        derive_key_for_encrypt(&key, mechanism, parameters)?
     } else {
+        check_usage(
+            id,
+            locations,
+            &key,
+            crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+            "mechanism",
+        )?;
         (key, mechanism, parameters)
     };
 
-    if mechanism != crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD {
-        return Err(crate::implementation::err_invalid_parameter(
+    match mechanism {
+        crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD => decrypt_aead(key, parameters, ciphertext),
+
+        crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD
+        | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR
+        | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP
+        | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP_PAD => {
+            let mechanism = aes_mechanism(mechanism, parameters)?;
+            decrypt_aes(key, &mechanism, ciphertext)
+        }
+
+        _ => Err(crate::implementation::err_invalid_parameter(
             "mechanism",
             "unrecognized value",
-        ));
+        )),
     }
+}
 
+unsafe fn decrypt_aead(
+    key: Key,
+    parameters: *const std::ffi::c_void,
+    ciphertext: &[u8],
+) -> Result<(usize, Vec<u8>), crate::AZIOT_KEYS_RC> {
     let (iv, aad) = {
         if parameters.is_null() {
             return Err(crate::implementation::err_invalid_parameter(
@@ -485,7 +1066,11 @@ pub(crate) unsafe fn decrypt(
 
             let mut plaintext = vec![0_u8; ciphertext.len() - 16];
             let plaintext_len = key
-                .decrypt(iv, aad, ciphertext, &mut plaintext)
+                .decrypt(
+                    &pkcs11::AesMechanism::Gcm { iv, aad },
+                    ciphertext,
+                    &mut plaintext,
+                )
                 .map_err(crate::implementation::err_external)?;
             let plaintext_len =
                 std::convert::TryInto::try_into(plaintext_len).expect("CK_ULONG -> usize");
@@ -495,34 +1080,404 @@ pub(crate) unsafe fn decrypt(
     }
 }
 
+unsafe fn decrypt_aes(
+    key: Key,
+    mechanism: &pkcs11::AesMechanism<'_>,
+    ciphertext: &[u8],
+) -> Result<(usize, Vec<u8>), crate::AZIOT_KEYS_RC> {
+    match key {
+        Key::FileSystem(key) => {
+            let plaintext = match mechanism {
+                pkcs11::AesMechanism::CbcPad { iv } | pkcs11::AesMechanism::Ctr { iv } => {
+                    openssl::symm::decrypt(
+                        aes_cipher(&key, mechanism)?,
+                        &key,
+                        Some(&iv[..]),
+                        ciphertext,
+                    )?
+                }
+                pkcs11::AesMechanism::KeyWrap => crate::aes_key_wrap::unwrap(&key, ciphertext)?,
+                pkcs11::AesMechanism::KeyWrapPad => {
+                    crate::aes_key_wrap::unwrap_pad(&key, ciphertext)?
+                }
+                pkcs11::AesMechanism::Gcm { .. } => unreachable!("AEAD is handled by decrypt_aead"),
+            };
+            Ok((plaintext.len(), plaintext))
+        }
+
+        Key::Pkcs11(key) => {
+            let mut plaintext = vec![0_u8; ciphertext.len()];
+
+            let plaintext_len = key
+                .decrypt(mechanism, ciphertext, &mut plaintext)
+                .map_err(crate::implementation::err_external)?;
+            let plaintext_len: usize =
+                std::convert::TryInto::try_into(plaintext_len).expect("CK_ULONG -> usize");
+            plaintext.truncate(plaintext_len);
+
+            Ok((plaintext_len, plaintext))
+        }
+    }
+}
+
+/// Starts a multi-part encryption or decryption with the key identified by `id`, depending on `mode`.
+pub(crate) unsafe fn encrypt_init(
+    id: &str,
+    locations: &[crate::implementation::Location],
+    mechanism: crate::AZIOT_KEYS_ENCRYPT_MECHANISM,
+    parameters: *const std::ffi::c_void,
+    mode: openssl::symm::Mode,
+) -> Result<crate::stream::Stream, crate::AZIOT_KEYS_RC> {
+    let key = match load_inner(locations)? {
+        Some(key) => key,
+        None => {
+            return Err(crate::implementation::err_invalid_parameter(
+                "id",
+                "key not found",
+            ))
+        }
+    };
+
+    let (key, mechanism, parameters) = if mechanism == crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED
+        || mechanism == crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM
+    {
+        check_usage(
+            id,
+            locations,
+            &key,
+            crate::AZIOT_KEYS_KEY_USAGE_DERIVE,
+            "mechanism",
+        )?;
+        derive_key_for_encrypt(&key, mechanism, parameters)?
+    } else {
+        check_usage(
+            id,
+            locations,
+            &key,
+            crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+            "mechanism",
+        )?;
+        (key, mechanism, parameters)
+    };
+
+    match mechanism {
+        crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD => {
+            let (iv, aad) = {
+                if parameters.is_null() {
+                    return Err(crate::implementation::err_invalid_parameter(
+                        "parameters",
+                        "expected non-NULL",
+                    ));
+                }
+
+                let parameters = &*parameters.cast::<crate::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS>();
+
+                let iv = std::slice::from_raw_parts(parameters.iv, parameters.iv_len);
+                let aad = std::slice::from_raw_parts(parameters.aad, parameters.aad_len);
+                (iv, aad)
+            };
+
+            let key =
+                match key {
+                    Key::FileSystem(key) => key,
+
+                    // Tokens are not required to support multi-part AES-GCM, and the ones that do may buffer the whole input
+                    // until the operation is completed, which defeats the purpose.
+                    Key::Pkcs11(_) => return Err(crate::implementation::err_invalid_parameter(
+                        "mechanism",
+                        "AEAD does not support multi-part operations with keys in PKCS#11 tokens",
+                    )),
+                };
+
+            let mut crypter = openssl::symm::Crypter::new(
+                openssl::symm::Cipher::aes_256_gcm(),
+                mode,
+                &key,
+                Some(iv),
+            )?;
+            crypter.aad_update(aad)?;
+
+            let stream = match mode {
+                openssl::symm::Mode::Encrypt => crate::stream::Stream::new(
+                    crate::stream::Operation::AeadEncrypt(crypter),
+                    vec![0x02],
+                ),
+                openssl::symm::Mode::Decrypt => crate::stream::Stream::new(
+                    crate::stream::Operation::AeadDecrypt {
+                        crypter,
+                        version: None,
+                        pending: vec![],
+                        plaintext: vec![],
+                    },
+                    vec![],
+                ),
+            };
+            Ok(stream)
+        }
+
+        crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD
+        | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR => {
+            let mechanism = aes_mechanism(mechanism, parameters)?;
+
+            let operation = match key {
+                Key::FileSystem(key) => {
+                    let cipher = aes_cipher(&key, &mechanism)?;
+                    let iv = match mechanism {
+                        pkcs11::AesMechanism::CbcPad { iv } | pkcs11::AesMechanism::Ctr { iv } => {
+                            &iv[..]
+                        }
+                        _ => unreachable!("only AES-CBC and AES-CTR reach here"),
+                    };
+                    let crypter = openssl::symm::Crypter::new(cipher, mode, &key, Some(iv))?;
+                    crate::stream::Operation::Crypter(crypter, cipher.block_size())
+                }
+
+                Key::Pkcs11(key) => match mode {
+                    openssl::symm::Mode::Encrypt => {
+                        key.encrypt_init(&mechanism)
+                            .map_err(crate::implementation::err_external)?;
+                        crate::stream::Operation::Pkcs11Encrypt(key)
+                    }
+                    openssl::symm::Mode::Decrypt => {
+                        key.decrypt_init(&mechanism)
+                            .map_err(crate::implementation::err_external)?;
+                        crate::stream::Operation::Pkcs11Decrypt(key)
+                    }
+                },
+            };
+            Ok(crate::stream::Stream::new(operation, vec![]))
+        }
+
+        crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP
+        | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP_PAD => {
+            Err(crate::implementation::err_invalid_parameter(
+                "mechanism",
+                "mechanism does not support multi-part operations",
+            ))
+        }
+
+        _ => Err(crate::implementation::err_invalid_parameter(
+            "mechanism",
+            "unrecognized value",
+        )),
+    }
+}
+
+/// Starts a multi-part HMAC signature with the key identified by `id`.
+pub(crate) unsafe fn sign_init(
+    id: &str,
+    locations: &[crate::implementation::Location],
+    mechanism: crate::AZIOT_KEYS_SIGN_MECHANISM,
+    parameters: *const std::ffi::c_void,
+) -> Result<crate::stream::Stream, crate::AZIOT_KEYS_RC> {
+    let key = match load_inner(locations)? {
+        Some(key) => key,
+        None => {
+            return Err(crate::implementation::err_invalid_parameter(
+                "id",
+                "key not found",
+            ))
+        }
+    };
+
+    let (key, mechanism, _) = if mechanism == crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED
+        || mechanism == crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM
+    {
+        check_usage(
+            id,
+            locations,
+            &key,
+            crate::AZIOT_KEYS_KEY_USAGE_DERIVE,
+            "mechanism",
+        )?;
+        derive_key_for_sign(&key, mechanism, parameters)?
+    } else {
+        check_usage(
+            id,
+            locations,
+            &key,
+            crate::AZIOT_KEYS_KEY_USAGE_SIGN,
+            "mechanism",
+        )?;
+        (key, mechanism, parameters)
+    };
+
+    let digest_algorithm = hmac_digest_algorithm(mechanism)?;
+
+    let operation = match key {
+        Key::FileSystem(key) => {
+            let hmac = match digest_algorithm {
+                pkcs11::DigestAlgorithm::Sha256 => crate::stream::Hmac::Sha256(
+                    hmac::NewMac::new_varkey(&key).map_err(crate::implementation::err_external)?,
+                ),
+                pkcs11::DigestAlgorithm::Sha384 => crate::stream::Hmac::Sha384(
+                    hmac::NewMac::new_varkey(&key).map_err(crate::implementation::err_external)?,
+                ),
+                pkcs11::DigestAlgorithm::Sha512 => crate::stream::Hmac::Sha512(
+                    hmac::NewMac::new_varkey(&key).map_err(crate::implementation::err_external)?,
+                ),
+            };
+            crate::stream::Operation::Hmac(Box::new(hmac))
+        }
+
+        Key::Pkcs11(key) => {
+            key.sign_init(digest_algorithm)
+                .map_err(crate::implementation::err_external)?;
+            crate::stream::Operation::Pkcs11Hmac(key, digest_algorithm)
+        }
+    };
+    Ok(crate::stream::Stream::new(operation, vec![]))
+}
+
+/// Parses the parameters of one of the `AZIOT_KEYS_ENCRYPT_MECHANISM_AES_*` mechanisms.
+unsafe fn aes_mechanism<'a>(
+    mechanism: crate::AZIOT_KEYS_ENCRYPT_MECHANISM,
+    parameters: *const std::ffi::c_void,
+) -> Result<pkcs11::AesMechanism<'a>, crate::AZIOT_KEYS_RC> {
+    let iv = || -> Result<&'a [u8; 16], crate::AZIOT_KEYS_RC> {
+        if parameters.is_null() {
+            return Err(crate::implementation::err_invalid_parameter(
+                "parameters",
+                "expected non-NULL",
+            ));
+        }
+
+        let parameters = &*parameters.cast::<crate::AZIOT_KEYS_ENCRYPT_IV_PARAMETERS>();
+
+        let iv = crate::key_pair::parameters_buf(parameters.iv, parameters.iv_len)?;
+        let iv = std::convert::TryInto::try_into(iv).map_err(|_| {
+            crate::implementation::err_invalid_parameter("parameters", "IV must be 16 bytes")
+        })?;
+        Ok(iv)
+    };
+
+    match mechanism {
+        crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD => {
+            Ok(pkcs11::AesMechanism::CbcPad { iv: iv()? })
+        }
+        crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR => Ok(pkcs11::AesMechanism::Ctr { iv: iv()? }),
+        crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP => Ok(pkcs11::AesMechanism::KeyWrap),
+        crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP_PAD => {
+            Ok(pkcs11::AesMechanism::KeyWrapPad)
+        }
+        _ => Err(crate::implementation::err_invalid_parameter(
+            "mechanism",
+            "unrecognized value",
+        )),
+    }
+}
+
+/// Selects the openssl cipher for AES-CBC or AES-CTR with the given filesystem key, based on the key's length.
+fn aes_cipher(
+    key: &[u8],
+    mechanism: &pkcs11::AesMechanism<'_>,
+) -> Result<openssl::symm::Cipher, crate::AZIOT_KEYS_RC> {
+    let cipher = match (mechanism, key.len()) {
+        (pkcs11::AesMechanism::CbcPad { .. }, 16) => openssl::symm::Cipher::aes_128_cbc(),
+        (pkcs11::AesMechanism::CbcPad { .. }, 24) => openssl::symm::Cipher::aes_192_cbc(),
+        (pkcs11::AesMechanism::CbcPad { .. }, 32) => openssl::symm::Cipher::aes_256_cbc(),
+        (pkcs11::AesMechanism::Ctr { .. }, 16) => openssl::symm::Cipher::aes_128_ctr(),
+        (pkcs11::AesMechanism::Ctr { .. }, 24) => openssl::symm::Cipher::aes_192_ctr(),
+        (pkcs11::AesMechanism::Ctr { .. }, 32) => openssl::symm::Cipher::aes_256_ctr(),
+        (pkcs11::AesMechanism::CbcPad { .. } | pkcs11::AesMechanism::Ctr { .. }, _) => {
+            return Err(crate::implementation::err_invalid_parameter(
+                "id",
+                "key is not a valid AES key",
+            ))
+        }
+        _ => unreachable!("only called for AES-CBC and AES-CTR"),
+    };
+    Ok(cipher)
+}
+
 enum Key {
     FileSystem(Vec<u8>),
     Pkcs11(pkcs11::Key),
 }
 
+const NO_USAGE: crate::AZIOT_KEYS_KEY_USAGE = crate::AZIOT_KEYS_KEY_USAGE { inner: 0 };
+
+/// Returns the usage of the given key, or `None` if it's not known.
+///
+/// The usage of filesystem keys is recorded in their metadata when they're created.
+/// The usage of PKCS#11 keys is determined from their `CKA_SIGN` and `CKA_ENCRYPT` attributes.
+fn usage_of(
+    id: &str,
+    locations: &[crate::implementation::Location],
+    key: &Key,
+) -> Result<Option<crate::AZIOT_KEYS_KEY_USAGE>, crate::AZIOT_KEYS_RC> {
+    match key {
+        Key::FileSystem(_) => {
+            let usage = crate::implementation::load_metadata(id, locations)?
+                .and_then(|metadata| metadata.usage)
+                .filter(|&usage| usage != 0)
+                .map(|usage| crate::AZIOT_KEYS_KEY_USAGE { inner: usage });
+            Ok(usage)
+        }
+
+        Key::Pkcs11(key) => {
+            let attributes = key
+                .attributes()
+                .map_err(crate::implementation::err_external)?;
+
+            let mut usage = NO_USAGE;
+            if attributes.sign {
+                usage.inner |= crate::AZIOT_KEYS_KEY_USAGE_DERIVE.inner;
+                usage.inner |= crate::AZIOT_KEYS_KEY_USAGE_SIGN.inner;
+            }
+            if attributes.encrypt {
+                usage.inner |= crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT.inner;
+            }
+            Ok(Some(usage))
+        }
+    }
+}
+
+/// Ensures that the given key was created with the given usage.
+///
+/// Keys whose usage is not known, such as pre-loaded filesystem keys and keys that were created before their usage was recorded,
+/// are not restricted.
+fn check_usage(
+    id: &str,
+    locations: &[crate::implementation::Location],
+    key: &Key,
+    usage: crate::AZIOT_KEYS_KEY_USAGE,
+    parameter_name: &'static str,
+) -> Result<(), crate::AZIOT_KEYS_RC> {
+    match usage_of(id, locations, key)? {
+        Some(allowed_usage) if allowed_usage.inner & usage.inner != usage.inner => {
+            Err(crate::implementation::err_invalid_parameter(
+                parameter_name,
+                "key was not created with the usage required by this operation",
+            ))
+        }
+
+        _ => Ok(()),
+    }
+}
+
+/// Loads the key from the first of the given locations that has it.
+///
+/// A key in a PKCS#11 token is loaded from a pooled session that is not shared with anything else while the key is alive,
+/// so that it can be used for multi-part operations.
 fn load_inner(
     locations: &[crate::implementation::Location],
 ) -> Result<Option<Key>, crate::AZIOT_KEYS_RC> {
     for location in locations {
         match location {
-            crate::implementation::Location::Filesystem(path) => match std::fs::read(path) {
-                Ok(key_bytes) => return Ok(Some(Key::FileSystem(key_bytes))),
-                Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
-                Err(err) => return Err(crate::implementation::err_external(err)),
-            },
+            crate::implementation::Location::Filesystem(path) => {
+                if let Some(key_bytes) = crate::implementation::read_key_file(path)? {
+                    return Ok(Some(Key::FileSystem(key_bytes)));
+                }
+            }
 
             crate::implementation::Location::Pkcs11 { lib_path, uri } => {
-                let pkcs11_context = pkcs11::Context::load(lib_path.clone())
-                    .map_err(crate::implementation::err_external)?;
-                let pkcs11_slot = pkcs11_context
-                    .find_slot(&uri.slot_identifier)
-                    .map_err(crate::implementation::err_external)?;
-                let pkcs11_session = pkcs11_context
-                    .open_session(pkcs11_slot, uri.pin.clone())
-                    .map_err(crate::implementation::err_external)?;
-
-                match pkcs11_session.get_key(uri.object_label.as_ref().map(AsRef::as_ref)) {
-                    Ok(key_pair) => return Ok(Some(Key::Pkcs11(key_pair))),
+                let result =
+                    crate::implementation::with_pkcs11_session(lib_path, uri, |pkcs11_session| {
+                        pkcs11_session.get_key(uri.object_label.as_ref().map(AsRef::as_ref))
+                    })?;
+                match result {
+                    Ok(key) => return Ok(Some(Key::Pkcs11(key))),
 
                     Err(pkcs11::GetKeyError::KeyDoesNotExist) => (),
 
@@ -535,6 +1490,27 @@ fn load_inner(
     Ok(None)
 }
 
+fn delete_inner(locations: &[crate::implementation::Location]) -> Result<(), crate::AZIOT_KEYS_RC> {
+    // The key may have been created in any of the locations, so delete it from all of them.
+    for location in locations {
+        match location {
+            crate::implementation::Location::Filesystem(path) => {
+                crate::implementation::delete_key_file(path)?;
+            }
+
+            crate::implementation::Location::Pkcs11 { lib_path, uri } => {
+                let label = crate::implementation::pkcs11_object_label(uri)?;
+                crate::implementation::with_pkcs11_session(lib_path, uri, |pkcs11_session| {
+                    pkcs11_session.delete_key(label)
+                })?
+                .map_err(crate::implementation::err_external)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Clone, Copy)]
 enum CreateMethod<'a> {
     Generate,
@@ -546,22 +1522,31 @@ fn create_inner(
     create_method: CreateMethod<'_>,
     usage: crate::AZIOT_KEYS_KEY_USAGE,
 ) -> Result<(), crate::AZIOT_KEYS_RC> {
+    // A key without any usage could not be used for anything, and would be indistinguishable from a key whose usage is not known.
+    if usage == NO_USAGE {
+        return Err(crate::implementation::err_invalid_parameter(
+            "usage",
+            "expected at least one usage",
+        ));
+    }
+
     for location in locations {
         match location {
             crate::implementation::Location::Filesystem(path) => {
-                let result = match create_method {
+                match create_method {
                     CreateMethod::Generate => {
                         // Filesystem uses AES-256-GCM for encryption keys and HMAC-SHA256 for hash keys,
                         // so it uses 256-bit == 32-byte keys for both.
 
                         let mut bytes = vec![0_u8; 32];
                         openssl::rand::rand_bytes(&mut bytes)?;
-                        std::fs::write(path, bytes)
+                        let () = crate::implementation::write_key_file(path, &bytes, false)?;
                     }
 
-                    CreateMethod::Import(bytes) => std::fs::write(path, bytes),
-                };
-                let () = result.map_err(crate::implementation::err_external)?;
+                    CreateMethod::Import(bytes) => {
+                        let () = crate::implementation::write_key_file(path, bytes, true)?;
+                    }
+                }
                 return Ok(());
             }
 
@@ -580,19 +1565,18 @@ fn create_inner(
                     }
                 };
 
-                let pkcs11_context = pkcs11::Context::load(lib_path.clone())
-                    .map_err(crate::implementation::err_external)?;
-                let pkcs11_slot = pkcs11_context
-                    .find_slot(&uri.slot_identifier)
-                    .map_err(crate::implementation::err_external)?;
-                let pkcs11_session = pkcs11_context
-                    .open_session(pkcs11_slot, uri.pin.clone())
-                    .map_err(crate::implementation::err_external)?;
-
                 match create_method {
                     CreateMethod::Generate => {
-                        let result = pkcs11_session
-                            .generate_key(uri.object_label.as_ref().map(AsRef::as_ref), usage);
+                        let result = crate::implementation::with_pkcs11_session(
+                            lib_path,
+                            uri,
+                            |pkcs11_session| {
+                                pkcs11_session.generate_key(
+                                    uri.object_label.as_ref().map(AsRef::as_ref),
+                                    usage,
+                                )
+                            },
+                        )?;
                         match result {
                             Ok(_) => return Ok(()),
 
@@ -611,11 +1595,17 @@ fn create_inner(
                     CreateMethod::Import(bytes) => {
                         // TODO: Verify if CAL actually smashes the stack for keys that are too large,
                         // and if not, if it returns a better error than CKR_GENERAL_ERROR
-                        let result = pkcs11_session.import_key(
-                            bytes,
-                            uri.object_label.as_ref().map(AsRef::as_ref),
-                            usage,
-                        );
+                        let result = crate::implementation::with_pkcs11_session(
+                            lib_path,
+                            uri,
+                            |pkcs11_session| {
+                                pkcs11_session.import_key(
+                                    bytes,
+                                    uri.object_label.as_ref().map(AsRef::as_ref),
+                                    usage,
+                                )
+                            },
+                        )?;
                         match result {
                             Ok(_) => return Ok(()),
 
@@ -635,8 +1625,11 @@ fn create_inner(
     ))
 }
 
+/// `mechanism` is either `AZIOT_KEYS_SIGN_MECHANISM_DERIVED` or `AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM`,
+/// which determines the type of `parameters`.
 unsafe fn derive_key_for_sign(
     key: &Key,
+    mechanism: crate::AZIOT_KEYS_SIGN_MECHANISM,
     parameters: *const std::ffi::c_void,
 ) -> Result<
     (
@@ -653,10 +1646,26 @@ unsafe fn derive_key_for_sign(
         ));
     }
 
-    let parameters = &*parameters.cast::<crate::AZIOT_KEYS_SIGN_DERIVED_PARAMETERS>();
+    // Callers of earlier API versions only know the shorter AZIOT_KEYS_SIGN_DERIVED_PARAMETERS,
+    // so the derivation mechanism is only read from the struct of the newer mechanism.
+    let parameters = if mechanism == crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM {
+        *parameters.cast::<crate::AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS>()
+    } else {
+        let parameters = *parameters.cast::<crate::AZIOT_KEYS_SIGN_DERIVED_PARAMETERS>();
+        crate::AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS {
+            derivation_data: parameters.derivation_data,
+            derivation_data_len: parameters.derivation_data_len,
+            mechanism: parameters.mechanism,
+            parameters: parameters.parameters,
+            derivation_mechanism: crate::AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256,
+            derivation_parameters: std::ptr::null(),
+        }
+    };
 
     let signature = derive_key_common(
         key,
+        parameters.derivation_mechanism,
+        parameters.derivation_parameters,
         parameters.derivation_data,
         parameters.derivation_data_len,
     )?;
@@ -666,8 +1675,11 @@ unsafe fn derive_key_for_sign(
     Ok((derived_key, parameters.mechanism, parameters.parameters))
 }
 
+/// `mechanism` is either `AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED` or `AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM`,
+/// which determines the type of `parameters`.
 unsafe fn derive_key_for_encrypt(
     key: &Key,
+    mechanism: crate::AZIOT_KEYS_ENCRYPT_MECHANISM,
     parameters: *const std::ffi::c_void,
 ) -> Result<
     (
@@ -684,10 +1696,26 @@ unsafe fn derive_key_for_encrypt(
         ));
     }
 
-    let parameters = &*parameters.cast::<crate::AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS>();
+    // Callers of earlier API versions only know the shorter AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS,
+    // so the derivation mechanism is only read from the struct of the newer mechanism.
+    let parameters = if mechanism == crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM {
+        *parameters.cast::<crate::AZIOT_KEYS_ENCRYPT_DERIVED_WITH_MECHANISM_PARAMETERS>()
+    } else {
+        let parameters = *parameters.cast::<crate::AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS>();
+        crate::AZIOT_KEYS_ENCRYPT_DERIVED_WITH_MECHANISM_PARAMETERS {
+            derivation_data: parameters.derivation_data,
+            derivation_data_len: parameters.derivation_data_len,
+            mechanism: parameters.mechanism,
+            parameters: parameters.parameters,
+            derivation_mechanism: crate::AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256,
+            derivation_parameters: std::ptr::null(),
+        }
+    };
 
     let signature = derive_key_common(
         key,
+        parameters.derivation_mechanism,
+        parameters.derivation_parameters,
         parameters.derivation_data,
         parameters.derivation_data_len,
     )?;
@@ -699,6 +1727,8 @@ unsafe fn derive_key_for_encrypt(
 
 unsafe fn derive_key_common(
     key: &Key,
+    mechanism: crate::AZIOT_KEYS_KEY_DERIVATION_MECHANISM,
+    parameters: *const std::ffi::c_void,
     derivation_data: *const std::os::raw::c_uchar,
     derivation_data_len: usize,
 ) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
@@ -711,24 +1741,110 @@ unsafe fn derive_key_common(
 
     let derivation_data = std::slice::from_raw_parts(derivation_data, derivation_data_len);
 
-    match key {
-        Key::FileSystem(key) => {
-            use hmac::{Mac, NewMac};
+    match mechanism {
+        crate::AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256 => {
+            key_hmac(key, pkcs11::DigestAlgorithm::Sha256, derivation_data)
+        }
 
-            let mut signer = hmac::Hmac::<sha2::Sha256>::new_varkey(&key)
-                .map_err(crate::implementation::err_external)?;
+        crate::AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HKDF => {
+            if parameters.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "parameters",
+                    "expected non-NULL",
+                ));
+            }
 
-            signer.update(derivation_data);
+            let parameters =
+                &*parameters.cast::<crate::AZIOT_KEYS_KEY_DERIVATION_HKDF_PARAMETERS>();
+
+            let digest_algorithm = crate::key_pair::digest_algorithm(parameters.digest_algorithm)?;
+            let salt = crate::key_pair::parameters_buf(parameters.salt, parameters.salt_len)?;
+            let output_len = derived_key_output_len(parameters.output_len)?;
+
+            match key {
+                Key::FileSystem(key) => crate::kdf::hkdf(
+                    crate::key_pair::message_digest(digest_algorithm),
+                    key,
+                    salt,
+                    derivation_data,
+                    output_len,
+                ),
+
+                Key::Pkcs11(_) => Err(crate::implementation::err_invalid_parameter(
+                    "mechanism",
+                    "HKDF is not supported for keys stored in PKCS#11",
+                )),
+            }
+        }
 
-            let signature = signer.finalize();
-            let signature = signature.into_bytes().to_vec();
-            Ok(signature)
+        crate::AZIOT_KEYS_KEY_DERIVATION_MECHANISM_KBKDF_COUNTER => {
+            if parameters.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "parameters",
+                    "expected non-NULL",
+                ));
+            }
+
+            let parameters =
+                &*parameters.cast::<crate::AZIOT_KEYS_KEY_DERIVATION_KBKDF_PARAMETERS>();
+
+            let digest_algorithm = crate::key_pair::digest_algorithm(parameters.digest_algorithm)?;
+            let context =
+                crate::key_pair::parameters_buf(parameters.context, parameters.context_len)?;
+            let output_len = derived_key_output_len(parameters.output_len)?;
+
+            crate::kdf::kbkdf_counter(
+                |input| key_hmac(key, digest_algorithm, input),
+                derivation_data,
+                context,
+                output_len,
+            )
         }
 
+        _ => Err(crate::implementation::err_invalid_parameter(
+            "mechanism",
+            "unrecognized value",
+        )),
+    }
+}
+
+fn derived_key_output_len(output_len: usize) -> Result<usize, crate::AZIOT_KEYS_RC> {
+    if output_len == 0 {
+        return Err(crate::implementation::err_invalid_parameter(
+            "parameters",
+            "derived key length must be non-zero",
+        ));
+    }
+
+    if output_len > crate::kdf::MAX_DERIVED_KEY_LEN {
+        return Err(crate::implementation::err_invalid_parameter(
+            "parameters",
+            format!(
+                "derived key length must be at most {} bytes",
+                crate::kdf::MAX_DERIVED_KEY_LEN
+            ),
+        ));
+    }
+
+    Ok(output_len)
+}
+
+fn key_hmac(
+    key: &Key,
+    digest_algorithm: pkcs11::DigestAlgorithm,
+    data: &[u8],
+) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    match key {
+        Key::FileSystem(key) => crate::kdf::hmac(
+            crate::key_pair::message_digest(digest_algorithm),
+            key,
+            &[data],
+        ),
+
         Key::Pkcs11(key) => {
-            let mut signature = vec![0_u8; 32];
+            let mut signature = vec![0_u8; hmac_len(digest_algorithm)];
             let signature_len = key
-                .sign(derivation_data, &mut signature)
+                .sign(digest_algorithm, data, &mut signature)
                 .map_err(crate::implementation::err_external)?;
             let signature_len =
                 std::convert::TryInto::try_into(signature_len).expect("CK_ULONG -> usize");
@@ -737,3 +1853,498 @@ unsafe fn derive_key_common(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::implementation::tests::{c_string, TestHomedir};
+
+    // NIST SP 800-38A F.2.5 and F.5.5
+    const AES_256_KEY: &str = "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4";
+    const PLAINTEXT: &str = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+
+    /// Imports the AES-256 key of the SP 800-38A vectors with the given ID and usage.
+    fn import_aes_key(id: &str, usage: crate::AZIOT_KEYS_KEY_USAGE) {
+        let id = c_string(id);
+        let key = hex::decode(AES_256_KEY).unwrap();
+        assert_eq!(
+            unsafe { super::import_key(id.as_ptr(), key.as_ptr(), key.len(), usage) },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+    }
+
+    fn encrypt(
+        id: &str,
+        mechanism: crate::AZIOT_KEYS_ENCRYPT_MECHANISM,
+        iv: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+        let locations = crate::implementation::Location::of(id)?;
+        let parameters = crate::AZIOT_KEYS_ENCRYPT_IV_PARAMETERS {
+            iv: iv.as_ptr(),
+            iv_len: iv.len(),
+        };
+        let (_, ciphertext) = unsafe {
+            super::encrypt(
+                id,
+                &locations,
+                mechanism,
+                (&parameters as *const crate::AZIOT_KEYS_ENCRYPT_IV_PARAMETERS).cast(),
+                plaintext,
+            )?
+        };
+        Ok(ciphertext)
+    }
+
+    fn decrypt(
+        id: &str,
+        mechanism: crate::AZIOT_KEYS_ENCRYPT_MECHANISM,
+        iv: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+        let locations = crate::implementation::Location::of(id)?;
+        let parameters = crate::AZIOT_KEYS_ENCRYPT_IV_PARAMETERS {
+            iv: iv.as_ptr(),
+            iv_len: iv.len(),
+        };
+        let (_, plaintext) = unsafe {
+            super::decrypt(
+                id,
+                &locations,
+                mechanism,
+                (&parameters as *const crate::AZIOT_KEYS_ENCRYPT_IV_PARAMETERS).cast(),
+                ciphertext,
+            )?
+        };
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn aes_cbc_pad() {
+        let _homedir = TestHomedir::new();
+        import_aes_key("key", crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT);
+
+        let iv = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let plaintext = hex::decode(PLAINTEXT).unwrap();
+        let expected_ciphertext = hex::decode("f58c4c04d6e5f1ba779eabfb5f7bfbd69cfc4e967edb808d679f777bc6702c7d39f23369a9d9bacfa530e26304231461b2eb05e2c39be9fcda6c19078c6a9d1b").unwrap();
+
+        // The plaintext is a multiple of the block size, so PKCS#7 padding adds a whole block after the SP 800-38A ciphertext.
+        let ciphertext = encrypt(
+            "key",
+            crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD,
+            &iv,
+            &plaintext,
+        )
+        .unwrap();
+        assert_eq!(ciphertext.len(), expected_ciphertext.len() + 16);
+        assert_eq!(
+            ciphertext[..expected_ciphertext.len()],
+            expected_ciphertext[..]
+        );
+
+        assert_eq!(
+            decrypt(
+                "key",
+                crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD,
+                &iv,
+                &ciphertext,
+            )
+            .unwrap(),
+            plaintext,
+        );
+
+        // Unpadded plaintext round-trips too.
+        let ciphertext = encrypt(
+            "key",
+            crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD,
+            &iv,
+            b"hello",
+        )
+        .unwrap();
+        assert_eq!(ciphertext.len(), 16);
+        assert_eq!(
+            decrypt(
+                "key",
+                crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD,
+                &iv,
+                &ciphertext,
+            )
+            .unwrap(),
+            b"hello",
+        );
+
+        // The SP 800-38A ciphertext without the padding block has invalid padding.
+        assert!(decrypt(
+            "key",
+            crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD,
+            &iv,
+            &expected_ciphertext[..48],
+        )
+        .is_err());
+
+        // The IV must be 16 bytes.
+        assert_eq!(
+            encrypt(
+                "key",
+                crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD,
+                &iv[..15],
+                &plaintext,
+            )
+            .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn aes_ctr() {
+        let _homedir = TestHomedir::new();
+        import_aes_key("key", crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT);
+
+        let iv = hex::decode("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff").unwrap();
+        let plaintext = hex::decode(PLAINTEXT).unwrap();
+        let expected_ciphertext = hex::decode("601ec313775789a5b7a7f504bbf3d228f443e3ca4d62b59aca84e990cacaf5c52b0930daa23de94ce87017ba2d84988ddfc9c58db67aada613c2dd08457941a6").unwrap();
+
+        assert_eq!(
+            encrypt(
+                "key",
+                crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR,
+                &iv,
+                &plaintext,
+            )
+            .unwrap(),
+            expected_ciphertext,
+        );
+        assert_eq!(
+            decrypt(
+                "key",
+                crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR,
+                &iv,
+                &expected_ciphertext,
+            )
+            .unwrap(),
+            plaintext,
+        );
+
+        // CTR is a stream cipher, so a partial block is not padded.
+        assert_eq!(
+            encrypt(
+                "key",
+                crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR,
+                &iv,
+                &plaintext[..5],
+            )
+            .unwrap(),
+            expected_ciphertext[..5],
+        );
+
+        assert_eq!(
+            encrypt(
+                "key",
+                crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR,
+                &[],
+                &plaintext,
+            )
+            .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn aes_requires_encrypt_usage() {
+        let _homedir = TestHomedir::new();
+        import_aes_key("key", crate::AZIOT_KEYS_KEY_USAGE_SIGN);
+
+        let iv = [0_u8; 16];
+        for &mechanism in &[
+            crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD,
+            crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR,
+            crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP,
+            crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP_PAD,
+        ] {
+            assert_eq!(
+                encrypt("key", mechanism, &iv, &[0; 16]).unwrap_err(),
+                crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+            );
+        }
+    }
+
+    #[test]
+    fn legacy_key_usage_is_not_restricted() {
+        let _homedir = TestHomedir::new();
+        import_aes_key("key", crate::AZIOT_KEYS_KEY_USAGE_SIGN);
+
+        // Keys created before usage was recorded have no metadata, so they are not restricted.
+        let locations = crate::implementation::Location::of("key").unwrap();
+        crate::implementation::delete_metadata("key", &locations).unwrap();
+
+        let iv = [0_u8; 16];
+        encrypt(
+            "key",
+            crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD,
+            &iv,
+            b"hello",
+        )
+        .unwrap();
+
+        // Asking for the key again with a usage does not restrict it either, since callers may already use it for something else.
+        let id = c_string("key");
+        assert_eq!(
+            unsafe {
+                super::create_key_if_not_exists(id.as_ptr(), crate::AZIOT_KEYS_KEY_USAGE_SIGN)
+            },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        encrypt(
+            "key",
+            crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD,
+            &iv,
+            b"hello",
+        )
+        .unwrap();
+        unsafe {
+            super::sign(
+                "key",
+                &locations,
+                crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256,
+                std::ptr::null(),
+                b"hello",
+            )
+        }
+        .unwrap();
+    }
+
+    #[test]
+    fn export_requires_known_usage() {
+        let _homedir = TestHomedir::new();
+        import_aes_key("key", crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT);
+
+        let locations = crate::implementation::Location::of("key").unwrap();
+        crate::implementation::delete_metadata("key", &locations).unwrap();
+
+        let wrapping_key = openssl::rsa::Rsa::generate(2048).unwrap();
+        let wrapping_key = openssl::pkey::PKey::from_rsa(wrapping_key).unwrap();
+        let wrapping_key = wrapping_key.public_key_to_der().unwrap();
+
+        let export = || {
+            let id = c_string("key");
+            let mut wrapped_key_len = 0;
+            unsafe {
+                super::export_key(
+                    id.as_ptr(),
+                    crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP,
+                    wrapping_key.as_ptr(),
+                    wrapping_key.len(),
+                    std::ptr::null_mut(),
+                    &mut wrapped_key_len,
+                )
+            }
+        };
+
+        assert_eq!(export(), crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER);
+
+        // Once the key's usage is recorded, it can be exported.
+        let id = c_string("key");
+        assert_eq!(
+            unsafe {
+                super::create_key_if_not_exists(id.as_ptr(), crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT)
+            },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        assert_eq!(export(), crate::AZIOT_KEYS_RC_OK);
+    }
+
+    #[test]
+    fn empty_usage_is_rejected() {
+        let _homedir = TestHomedir::new();
+
+        let id = c_string("key");
+        assert_eq!(
+            unsafe { super::create_key_if_not_exists(id.as_ptr(), super::NO_USAGE) },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        let key = [0_u8; 32];
+        assert_eq!(
+            unsafe { super::import_key(id.as_ptr(), key.as_ptr(), key.len(), super::NO_USAGE) },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        assert_eq!(
+            unsafe { super::load_key(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn import_key_if_not_exists_does_not_replace_key() {
+        let _homedir = TestHomedir::new();
+        import_aes_key("key", crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT);
+
+        let import_key_if_not_exists = |id: &str| {
+            let id = c_string(id);
+            let key = [0_u8; 32];
+            unsafe {
+                super::import_key_if_not_exists(
+                    id.as_ptr(),
+                    key.as_ptr(),
+                    key.len(),
+                    crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+                )
+            }
+        };
+
+        assert_eq!(
+            import_key_if_not_exists("key"),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        let locations = crate::implementation::Location::of("key").unwrap();
+        match super::load_inner(&locations).unwrap() {
+            Some(super::Key::FileSystem(key)) => assert_eq!(key, hex::decode(AES_256_KEY).unwrap()),
+            _ => panic!("expected the original filesystem key"),
+        }
+
+        assert_eq!(import_key_if_not_exists("new-key"), crate::AZIOT_KEYS_RC_OK);
+        let locations = crate::implementation::Location::of("new-key").unwrap();
+        match super::load_inner(&locations).unwrap() {
+            Some(super::Key::FileSystem(key)) => assert_eq!(key, [0_u8; 32]),
+            _ => panic!("expected the imported filesystem key"),
+        }
+    }
+
+    #[test]
+    fn derived_sign_mechanisms() {
+        let _homedir = TestHomedir::new();
+        import_aes_key("key", crate::AZIOT_KEYS_KEY_USAGE_DERIVE);
+        let locations = crate::implementation::Location::of("key").unwrap();
+
+        let derivation_data = b"derivation data";
+        let sign = |mechanism, parameters: *const std::ffi::c_void| unsafe {
+            super::sign("key", &locations, mechanism, parameters, b"hello")
+                .map(|(_, signature)| signature)
+        };
+
+        // The 2.0 parameters derive the key with HMAC-SHA256, same as the newer parameters with that derivation mechanism.
+        let parameters = crate::AZIOT_KEYS_SIGN_DERIVED_PARAMETERS {
+            derivation_data: derivation_data.as_ptr(),
+            derivation_data_len: derivation_data.len(),
+            mechanism: crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256,
+            parameters: std::ptr::null(),
+        };
+        let signature = sign(
+            crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED,
+            (&parameters as *const crate::AZIOT_KEYS_SIGN_DERIVED_PARAMETERS).cast(),
+        )
+        .unwrap();
+
+        let parameters = crate::AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS {
+            derivation_data: derivation_data.as_ptr(),
+            derivation_data_len: derivation_data.len(),
+            mechanism: crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256,
+            parameters: std::ptr::null(),
+            derivation_mechanism: crate::AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256,
+            derivation_parameters: std::ptr::null(),
+        };
+        assert_eq!(
+            sign(
+                crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM,
+                (&parameters as *const crate::AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS)
+                    .cast(),
+            )
+            .unwrap(),
+            signature,
+        );
+
+        let kbkdf_sign = |output_len| {
+            let derivation_parameters = crate::AZIOT_KEYS_KEY_DERIVATION_KBKDF_PARAMETERS {
+                digest_algorithm: crate::AZIOT_KEYS_DIGEST_ALGORITHM_SHA256,
+                context: std::ptr::null(),
+                context_len: 0,
+                output_len,
+            };
+            let parameters = crate::AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS {
+                derivation_mechanism: crate::AZIOT_KEYS_KEY_DERIVATION_MECHANISM_KBKDF_COUNTER,
+                derivation_parameters: (&derivation_parameters
+                    as *const crate::AZIOT_KEYS_KEY_DERIVATION_KBKDF_PARAMETERS)
+                    .cast(),
+                ..parameters
+            };
+            sign(
+                crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM,
+                (&parameters as *const crate::AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS)
+                    .cast(),
+            )
+        };
+        assert_ne!(kbkdf_sign(32).unwrap(), signature);
+        assert_eq!(
+            kbkdf_sign(crate::kdf::MAX_DERIVED_KEY_LEN + 1).unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn hmac_sha384_sha512() {
+        let _homedir = TestHomedir::new();
+
+        // RFC 4231 test cases 1, 2 and 6
+        for (i, (key, data, expected_sha384, expected_sha512)) in [
+            (
+                vec![0x0b; 20],
+                &b"Hi There"[..],
+                "afd03944d84895626b0825f4ab46907f15f9dadbe4101ec682aa034c7cebc59cfaea9ea9076ede7f4af152e8b2fa9cb6",
+                "87aa7cdea5ef619d4ff0b4241a1d6cb02379f4e2ce4ec2787ad0b30545e17cdedaa833b7d6b8a702038b274eaea3f4e4be9d914eeb61f1702e696c203a126854",
+            ),
+            (
+                b"Jefe".to_vec(),
+                &b"what do ya want for nothing?"[..],
+                "af45d2e376484031617f78d2b58a6b1b9c7ef464f5a01b47e42ec3736322445e8e2240ca5e69e2c78b3239ecfab21649",
+                "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+            ),
+            (
+                vec![0xaa; 131],
+                &b"Test Using Larger Than Block-Size Key - Hash Key First"[..],
+                "4ece084485813e9088d2c63a041bc5b44f9ef1012a2b588f3cd11f05033ac4c60c2ef6ab4030fe8296248df163f44952",
+                "80b24263c7c1a3ebb71493c1dd7be8b49b46d1f41b4aeec1121b013783f8f3526b56d037e05f2598bd0fd2215d6a1e5295e64f73f63f0aec8b915a985d786598",
+            ),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let id = format!("key{}", i);
+            let c_id = c_string(&id);
+            assert_eq!(
+                unsafe {
+                    super::import_key(
+                        c_id.as_ptr(),
+                        key.as_ptr(),
+                        key.len(),
+                        crate::AZIOT_KEYS_KEY_USAGE_SIGN,
+                    )
+                },
+                crate::AZIOT_KEYS_RC_OK,
+            );
+            let locations = crate::implementation::Location::of(&id).unwrap();
+
+            for &(mechanism, expected_signature) in &[
+                (crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA384, expected_sha384),
+                (crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA512, expected_sha512),
+            ] {
+                let expected_signature = hex::decode(expected_signature).unwrap();
+
+                let (signature_len, signature) =
+                    unsafe { super::sign(&id, &locations, mechanism, std::ptr::null(), data) }
+                        .unwrap();
+                assert_eq!(signature_len, expected_signature.len());
+                assert_eq!(signature, expected_signature);
+
+                assert!(unsafe { super::verify(&id, &locations, mechanism, data, &signature) }
+                    .unwrap());
+
+                let mut tampered_signature = signature;
+                tampered_signature[0] ^= 0x01;
+                assert!(!unsafe {
+                    super::verify(&id, &locations, mechanism, data, &tampered_signature)
+                }
+                .unwrap());
+            }
+        }
+    }
+}
@@ -0,0 +1,463 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Encryption of filesystem key files at rest.
+//!
+//! If the `key_encryption_key` parameter is set, key files created under the homedir are wrapped with AES-256-GCM
+//! using a key-encryption key (KEK) derived from the secret that the parameter points to.
+//! Existing plaintext key files are re-encrypted the first time they're read.
+
+lazy_static::lazy_static! {
+    static ref KEY_ENCRYPTION_KEY: std::sync::RwLock<Option<KeyEncryptionKey>> = Default::default();
+}
+
+/// Identifies an encrypted key file. Plaintext key files are either PEM or raw random bytes,
+/// so the odds of one starting with these 16 bytes by accident are negligible.
+const HEADER: &[u8; 16] = b"AZIOT-KEYS-ENC\x00\x01";
+
+const IV_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// The minimum length of the secret that the KEK is derived from.
+const MIN_SECRET_LEN: usize = 32;
+
+/// The source of the secret that the KEK is derived from, as specified by the `key_encryption_key` parameter.
+#[derive(Debug)]
+pub(crate) enum KeyEncryptionKeySource {
+    /// `file:///path/to/secret`
+    ///
+    /// A file containing the secret.
+    File(std::path::PathBuf),
+
+    /// `systemd-credential:name`
+    ///
+    /// A systemd credential passed to the service with `LoadCredential=` or `LoadCredentialEncrypted=`.
+    /// The latter can be used to seal the secret to the device's TPM.
+    SystemdCredential(String),
+
+    /// `keyring:description`
+    ///
+    /// A key of type `user` in one of the kernel keyrings that the service has access to.
+    Keyring(String),
+}
+
+impl std::str::FromStr for KeyEncryptionKeySource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(name) = s.strip_prefix("systemd-credential:") {
+            if name.is_empty() || name.contains('/') {
+                return Err(
+                    "systemd credential name must be non-empty and not contain '/'".to_owned(),
+                );
+            }
+
+            Ok(KeyEncryptionKeySource::SystemdCredential(name.to_owned()))
+        } else if let Some(description) = s.strip_prefix("keyring:") {
+            if description.is_empty() {
+                return Err("keyring key description must be non-empty".to_owned());
+            }
+
+            Ok(KeyEncryptionKeySource::Keyring(description.to_owned()))
+        } else {
+            let uri: url::Url = s.parse().map_err(|err| format!("{}", err))?;
+            if uri.scheme() != "file" {
+                return Err(format!("unsupported scheme {:?}", uri.scheme()));
+            }
+
+            let path = uri
+                .to_file_path()
+                .map_err(|()| format!("could not convert {} to a file path", uri))?;
+            Ok(KeyEncryptionKeySource::File(path))
+        }
+    }
+}
+
+impl KeyEncryptionKeySource {
+    fn read_secret(&self) -> Result<Vec<u8>, String> {
+        match self {
+            KeyEncryptionKeySource::File(path) => std::fs::read(path)
+                .map_err(|err| format!("could not read {}: {}", path.display(), err)),
+
+            KeyEncryptionKeySource::SystemdCredential(name) => {
+                let credentials_directory = std::env::var_os("CREDENTIALS_DIRECTORY")
+                    .ok_or("CREDENTIALS_DIRECTORY is not set; the service was not started with any systemd credentials")?;
+                let mut path: std::path::PathBuf = credentials_directory.into();
+                path.push(name);
+                std::fs::read(&path)
+                    .map_err(|err| format!("could not read systemd credential {:?}: {}", name, err))
+            }
+
+            KeyEncryptionKeySource::Keyring(description) => read_keyring_key(description),
+        }
+    }
+}
+
+struct KeyEncryptionKey(Vec<u8>);
+
+impl KeyEncryptionKey {
+    /// Derives the KEK from the secret with HMAC-SHA256, so that the secret can be of any length and format.
+    fn derive(secret: &[u8]) -> Result<Self, crate::AZIOT_KEYS_RC> {
+        if secret.len() < MIN_SECRET_LEN {
+            return Err(crate::implementation::err_invalid_parameter(
+                "value",
+                format!(
+                    "key-encryption key secret must be at least {} bytes long",
+                    MIN_SECRET_LEN
+                ),
+            ));
+        }
+
+        let hmac_key = openssl::pkey::PKey::hmac(secret)?;
+        let mut signer =
+            openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &hmac_key)?;
+        signer.update(b"aziot-keys filesystem key encryption")?;
+        let key = signer.sign_to_vec()?;
+        Ok(KeyEncryptionKey(key))
+    }
+}
+
+/// Sets the KEK from the value of the `key_encryption_key` parameter.
+pub(crate) fn set(value: &str) -> Result<(), crate::AZIOT_KEYS_RC> {
+    let source: KeyEncryptionKeySource = value
+        .parse()
+        .map_err(|err| crate::implementation::err_invalid_parameter("value", err))?;
+    let secret = source
+        .read_secret()
+        .map_err(crate::implementation::err_external)?;
+    let key_encryption_key = KeyEncryptionKey::derive(&secret)?;
+
+    let mut guard = KEY_ENCRYPTION_KEY.write().expect("fatal RwLock failure");
+    *guard = Some(key_encryption_key);
+
+    Ok(())
+}
+
+/// Unsets the KEK, so that tests start from a clean slate.
+#[cfg(test)]
+pub(crate) fn clear() {
+    let mut guard = KEY_ENCRYPTION_KEY.write().expect("fatal RwLock failure");
+    *guard = None;
+}
+
+/// Returns whether a KEK is configured.
+pub(crate) fn is_set() -> bool {
+    KEY_ENCRYPTION_KEY
+        .read()
+        .expect("fatal RwLock failure")
+        .is_some()
+}
+
+/// Returns whether the given contents of a key file are encrypted.
+pub(crate) fn is_encrypted(contents: &[u8]) -> bool {
+    contents.starts_with(HEADER)
+}
+
+/// Encrypts the given plaintext contents of the key file at `path` with the KEK.
+///
+/// The name of the file is bound to the ciphertext, so an encrypted key file can't be swapped with the file of another key.
+pub(crate) fn encrypt(
+    path: &std::path::Path,
+    plaintext: &[u8],
+) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    let guard = KEY_ENCRYPTION_KEY.read().expect("fatal RwLock failure");
+    let key_encryption_key = guard.as_ref().ok_or_else(|| {
+        crate::implementation::err_external("no key-encryption key is configured")
+    })?;
+
+    let mut iv = [0_u8; IV_LEN];
+    openssl::rand::rand_bytes(&mut iv)?;
+
+    let aad = aad(path)?;
+
+    let mut tag = [0_u8; TAG_LEN];
+    let ciphertext = openssl::symm::encrypt_aead(
+        openssl::symm::Cipher::aes_256_gcm(),
+        &key_encryption_key.0,
+        Some(&iv),
+        &aad,
+        plaintext,
+        &mut tag,
+    )?;
+
+    let mut result = Vec::with_capacity(HEADER.len() + IV_LEN + ciphertext.len() + TAG_LEN);
+    result.extend_from_slice(HEADER);
+    result.extend_from_slice(&iv);
+    result.extend_from_slice(&ciphertext);
+    result.extend_from_slice(&tag);
+    Ok(result)
+}
+
+/// Decrypts the given encrypted contents of the key file at `path` with the KEK.
+pub(crate) fn decrypt(
+    path: &std::path::Path,
+    contents: &[u8],
+) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    let guard = KEY_ENCRYPTION_KEY.read().expect("fatal RwLock failure");
+    let key_encryption_key = guard.as_ref().ok_or_else(|| {
+        crate::implementation::err_external(format!(
+            "{} is encrypted but no key-encryption key is configured",
+            path.display()
+        ))
+    })?;
+
+    let contents = &contents[HEADER.len()..];
+    if contents.len() < IV_LEN + TAG_LEN {
+        return Err(crate::implementation::err_external(format!(
+            "{} is truncated",
+            path.display()
+        )));
+    }
+    let (iv, contents) = contents.split_at(IV_LEN);
+    let (ciphertext, tag) = contents.split_at(contents.len() - TAG_LEN);
+
+    let aad = aad(path)?;
+
+    let plaintext = openssl::symm::decrypt_aead(
+        openssl::symm::Cipher::aes_256_gcm(),
+        &key_encryption_key.0,
+        Some(iv),
+        &aad,
+        ciphertext,
+        tag,
+    )
+    .map_err(|_| {
+        crate::implementation::err_external(format!(
+            "could not decrypt {}; it may have been encrypted with a different key-encryption key",
+            path.display()
+        ))
+    })?;
+    Ok(plaintext)
+}
+
+fn aad(path: &std::path::Path) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    let file_name = path
+        .file_name()
+        .and_then(std::ffi::OsStr::to_str)
+        .ok_or_else(|| {
+            crate::implementation::err_external(format!(
+                "{} does not have a valid file name",
+                path.display()
+            ))
+        })?;
+
+    let mut aad = HEADER.to_vec();
+    aad.extend_from_slice(file_name.as_bytes());
+    Ok(aad)
+}
+
+fn read_keyring_key(description: &str) -> Result<Vec<u8>, String> {
+    // Not all versions of the libc crate define these.
+    const KEYCTL_READ: std::os::raw::c_int = 11;
+
+    let key_type = std::ffi::CString::new("user").expect("hard-coded string has no NULs");
+    let c_description = std::ffi::CString::new(description)
+        .map_err(|err| format!("invalid keyring key description: {}", err))?;
+
+    unsafe {
+        // With a NULL callout, request_key(2) only searches the keyrings that the process already has access to.
+        let serial = libc::syscall(
+            libc::SYS_request_key,
+            key_type.as_ptr(),
+            c_description.as_ptr(),
+            std::ptr::null::<std::os::raw::c_char>(),
+            0,
+        );
+        if serial < 0 {
+            return Err(format!(
+                "could not find keyring key {:?}: {}",
+                description,
+                std::io::Error::last_os_error()
+            ));
+        }
+
+        // The payload may change between the two calls, so retry until the buffer is large enough.
+        let mut buf = vec![];
+        loop {
+            let len = libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_READ,
+                serial,
+                buf.as_mut_ptr(),
+                buf.len(),
+            );
+            if len < 0 {
+                return Err(format!(
+                    "could not read keyring key {:?}: {}",
+                    description,
+                    std::io::Error::last_os_error()
+                ));
+            }
+
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let len = len as usize;
+            if len <= buf.len() {
+                buf.truncate(len);
+                return Ok(buf);
+            }
+
+            buf.resize(len, 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::implementation::tests::{c_string, set_parameter, TestHomedir};
+
+    /// Writes a secret to a file in the homedir and sets it as the `key_encryption_key` parameter.
+    fn set_key_encryption_key(homedir: &TestHomedir, secret: &[u8]) -> crate::AZIOT_KEYS_RC {
+        let secret_path = homedir.path.join("kek-secret");
+        std::fs::write(&secret_path, secret).unwrap();
+        let secret_uri = url::Url::from_file_path(&secret_path).unwrap();
+        set_parameter("key_encryption_key", secret_uri.as_str())
+    }
+
+    #[test]
+    fn round_trip() {
+        let homedir = TestHomedir::new();
+        assert_eq!(
+            set_key_encryption_key(&homedir, &[0x01; 32]),
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        let keys_dir = homedir.path.join("keys");
+        std::fs::create_dir_all(&keys_dir).unwrap();
+        let path = keys_dir.join("key.key");
+
+        crate::implementation::write_key_file(&path, b"plaintext key", false).unwrap();
+        let contents = std::fs::read(&path).unwrap();
+        assert!(super::is_encrypted(&contents));
+        assert!(!contents
+            .windows(b"plaintext key".len())
+            .any(|window| window == b"plaintext key"));
+
+        assert_eq!(
+            crate::implementation::read_key_file(&path)
+                .unwrap()
+                .unwrap(),
+            b"plaintext key",
+        );
+
+        // The file name is bound to the ciphertext, so an encrypted key file can't be passed off as another key's.
+        let other_path = keys_dir.join("other.key");
+        std::fs::copy(&path, &other_path).unwrap();
+        assert_eq!(
+            crate::implementation::read_key_file(&other_path).unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_EXTERNAL,
+        );
+
+        // Truncated files are rejected.
+        std::fs::write(&path, &contents[..super::HEADER.len() + super::IV_LEN]).unwrap();
+        assert_eq!(
+            crate::implementation::read_key_file(&path).unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_EXTERNAL,
+        );
+
+        // Key files outside the homedir are managed by the user, so they're left as plaintext.
+        let preloaded_path = homedir.path.join("preloaded.key");
+        crate::implementation::write_key_file(&preloaded_path, b"plaintext key", false).unwrap();
+        assert_eq!(std::fs::read(&preloaded_path).unwrap(), b"plaintext key");
+    }
+
+    #[test]
+    fn plaintext_migration() {
+        let homedir = TestHomedir::new();
+
+        let id = c_string("key");
+        assert_eq!(
+            unsafe {
+                crate::key::create_key_if_not_exists(
+                    id.as_ptr(),
+                    crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+                )
+            },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        let keys_dir = homedir.path.join("keys");
+        let path = std::fs::read_dir(&keys_dir)
+            .unwrap()
+            .map(|dir_entry| dir_entry.unwrap().path())
+            .find(|path| path.extension() == Some(std::ffi::OsStr::new("key")))
+            .unwrap();
+        let plaintext = std::fs::read(&path).unwrap();
+        assert!(!super::is_encrypted(&plaintext));
+
+        // The existing plaintext key file is encrypted in place the first time it's read after a KEK is configured.
+        assert_eq!(
+            set_key_encryption_key(&homedir, &[0x01; 32]),
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        assert_eq!(
+            unsafe { crate::key::load_key(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        assert!(super::is_encrypted(&std::fs::read(&path).unwrap()));
+        assert!(std::fs::read_dir(&keys_dir)
+            .unwrap()
+            .all(|dir_entry| dir_entry.unwrap().path().extension()
+                != Some(std::ffi::OsStr::new("tmp"))));
+        assert_eq!(
+            crate::implementation::read_key_file(&path)
+                .unwrap()
+                .unwrap(),
+            plaintext,
+        );
+    }
+
+    #[test]
+    fn wrong_key_encryption_key() {
+        let homedir = TestHomedir::new();
+        assert_eq!(
+            set_key_encryption_key(&homedir, &[0x01; 32]),
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        let id = c_string("key");
+        assert_eq!(
+            unsafe {
+                crate::key::create_key_if_not_exists(
+                    id.as_ptr(),
+                    crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+                )
+            },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        assert_eq!(
+            set_key_encryption_key(&homedir, &[0x02; 32]),
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        assert_eq!(
+            unsafe { crate::key::load_key(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_ERR_EXTERNAL,
+        );
+
+        // Without a KEK, the encrypted key file can't be read at all.
+        super::clear();
+        assert_eq!(
+            unsafe { crate::key::load_key(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_ERR_EXTERNAL,
+        );
+
+        // The original KEK can still read it.
+        assert_eq!(
+            set_key_encryption_key(&homedir, &[0x01; 32]),
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        assert_eq!(
+            unsafe { crate::key::load_key(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+    }
+
+    #[test]
+    fn short_secret() {
+        let homedir = TestHomedir::new();
+        assert_eq!(
+            set_key_encryption_key(&homedir, &[0x01; super::MIN_SECRET_LEN - 1]),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert!(!super::is_set());
+    }
+}
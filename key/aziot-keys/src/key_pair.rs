@@ -24,6 +24,9 @@ pub(crate) unsafe extern "C" fn create_key_pair_if_not_exists(
                 crate::implementation::err_invalid_parameter("preferred_algorithms", err)
             })?;
 
+        // Held until this function returns, so that no other call creates, imports or deletes this ID in the meantime.
+        let _id_lock = crate::implementation::lock_id(id);
+
         let locations = crate::implementation::Location::of(id)?;
 
         if load_inner(&locations)?.is_none() {
@@ -33,6 +36,17 @@ pub(crate) unsafe extern "C" fn create_key_pair_if_not_exists(
                     "key created successfully but could not be found",
                 ));
             }
+
+            crate::implementation::save_metadata(
+                id,
+                &locations,
+                Some(crate::implementation::Creation {
+                    origin: crate::implementation::Origin::Generated,
+                    usage: None,
+                }),
+            )?;
+        } else {
+            crate::implementation::save_metadata(id, &locations, None)?;
         }
 
         Ok(())
@@ -70,6 +84,154 @@ pub(crate) unsafe extern "C" fn load_key_pair(
     })
 }
 
+pub(crate) unsafe extern "C" fn delete_key_pair(
+    id: *const std::os::raw::c_char,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let id = {
+            if id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "expected non-NULL",
+                ));
+            }
+            let id = std::ffi::CStr::from_ptr(id);
+            let id = id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("id", err))?;
+            id
+        };
+
+        let _id_lock = crate::implementation::lock_id(id);
+
+        crate::implementation::ensure_not_preloaded(id)?;
+
+        let locations = crate::implementation::Location::of(id)?;
+
+        delete_inner(&locations)?;
+        crate::implementation::delete_metadata(id, &locations)?;
+
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn move_key_pair(
+    from_id: *const std::os::raw::c_char,
+    to_id: *const std::os::raw::c_char,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let from_id = {
+            if from_id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "from_id",
+                    "expected non-NULL",
+                ));
+            }
+            let from_id = std::ffi::CStr::from_ptr(from_id);
+            let from_id = from_id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("from_id", err))?;
+            from_id
+        };
+
+        let to_id = {
+            if to_id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "to_id",
+                    "expected non-NULL",
+                ));
+            }
+            let to_id = std::ffi::CStr::from_ptr(to_id);
+            let to_id = to_id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("to_id", err))?;
+            to_id
+        };
+
+        if from_id == to_id {
+            return Err(crate::implementation::err_invalid_parameter(
+                "to_id",
+                "same as from_id",
+            ));
+        }
+
+        let _id_locks = crate::implementation::lock_ids(from_id, to_id);
+
+        crate::implementation::ensure_not_preloaded(from_id)?;
+        crate::implementation::ensure_not_preloaded(to_id)?;
+
+        let from_locations = crate::implementation::Location::of(from_id)?;
+        let to_locations = crate::implementation::Location::of(to_id)?;
+
+        let key_pair = load_inner(&from_locations)?
+            .ok_or_else(|| crate::implementation::err_invalid_parameter("from_id", "not found"))?;
+
+        match (key_pair, to_locations.first()) {
+            (
+                KeyPair::FileSystem(_, private_key),
+                Some(crate::implementation::Location::Filesystem(_)),
+            ) => {
+                // The new file is written before the old one is deleted, so a failure in between leaves the key pair under both IDs
+                // rather than under neither.
+                import_inner(&to_locations, &private_key)?;
+                delete_inner(&from_locations)?;
+                crate::implementation::move_metadata(
+                    from_id,
+                    &from_locations,
+                    to_id,
+                    &to_locations,
+                )?;
+            }
+
+            (
+                KeyPair::Pkcs11(_),
+                Some(crate::implementation::Location::Pkcs11 {
+                    lib_path: to_lib_path,
+                    uri: to_uri,
+                }),
+            ) => {
+                let (from_lib_path, from_uri) = from_locations
+                    .iter()
+                    .find_map(|location| match location {
+                        crate::implementation::Location::Pkcs11 { lib_path, uri } => {
+                            Some((lib_path, uri))
+                        }
+                        crate::implementation::Location::Filesystem(_) => None,
+                    })
+                    .ok_or_else(|| {
+                        crate::implementation::err_external("no PKCS#11 location for key pair")
+                    })?;
+                if from_lib_path != to_lib_path
+                    || from_uri.slot_identifier != to_uri.slot_identifier
+                {
+                    return Err(crate::implementation::err_invalid_parameter(
+                        "to_id",
+                        "key pair would be moved to a different PKCS#11 token",
+                    ));
+                }
+
+                let from_label = crate::implementation::pkcs11_object_label(from_uri)?;
+                let to_label = crate::implementation::pkcs11_object_label(to_uri)?;
+                crate::implementation::with_pkcs11_session(
+                    from_lib_path,
+                    from_uri,
+                    |pkcs11_session| pkcs11_session.rename_key_pair(from_label, to_label),
+                )?
+                .map_err(crate::implementation::err_external)?;
+            }
+
+            _ => {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "to_id",
+                    "key pair would be moved to a different location",
+                ))
+            }
+        }
+
+        Ok(())
+    })
+}
+
 pub(crate) unsafe extern "C" fn get_key_pair_parameter(
     id: *const std::os::raw::c_char,
     r#type: crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE,
@@ -120,14 +282,16 @@ pub(crate) unsafe extern "C" fn get_key_pair_parameter(
 
                     let value = match key_pair {
                         KeyPair::FileSystem(public_key, _) => {
-                            if public_key.ec_key().is_ok() {
+                            if public_key.id() == openssl::pkey::Id::ED25519 {
+                                crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_ED25519
+                            } else if public_key.ec_key().is_ok() {
                                 crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_EC
                             } else if public_key.rsa().is_ok() {
                                 crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_RSA
                             } else {
                                 return Err(crate::implementation::err_invalid_parameter(
                                     "id",
-                                    "key is neither RSA nor EC",
+                                    "key is neither RSA, EC nor Ed25519",
                                 ));
                             }
                         }
@@ -139,6 +303,10 @@ pub(crate) unsafe extern "C" fn get_key_pair_parameter(
                         KeyPair::Pkcs11(pkcs11::KeyPair::Rsa(_, _)) => {
                             crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_RSA
                         }
+
+                        KeyPair::Pkcs11(pkcs11::KeyPair::Ed25519(_, _)) => {
+                            crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_ED25519
+                        }
                     };
 
                     let value = value.inner.to_ne_bytes();
@@ -149,18 +317,25 @@ pub(crate) unsafe extern "C" fn get_key_pair_parameter(
             }
 
             crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_EC_CURVE_OID => {
-                let ec_key = key_pair.ec_key()?;
-
-                let curve_nid = ec_key.group().curve_name().ok_or_else(|| {
-                    crate::implementation::err_invalid_parameter(
-                        "type",
-                        "key does not have named curve",
-                    )
-                })?;
-                let curve = openssl2::EcCurve::from_nid(curve_nid).ok_or_else(|| {
-                    crate::implementation::err_invalid_parameter("type", "key curve not recognized")
-                })?;
-                let curve_oid = curve.as_oid_der();
+                let curve_oid = if key_pair.is_ed25519() {
+                    openssl2::ed25519::OID_DER
+                } else {
+                    let ec_key = key_pair.ec_key()?;
+
+                    let curve_nid = ec_key.group().curve_name().ok_or_else(|| {
+                        crate::implementation::err_invalid_parameter(
+                            "type",
+                            "key does not have named curve",
+                        )
+                    })?;
+                    let curve = openssl2::EcCurve::from_nid(curve_nid).ok_or_else(|| {
+                        crate::implementation::err_invalid_parameter(
+                            "type",
+                            "key curve not recognized",
+                        )
+                    })?;
+                    curve.as_oid_der()
+                };
 
                 let expected_value_len = curve_oid.len();
                 let actual_value_len = *value_len_out.as_ref();
@@ -184,16 +359,21 @@ pub(crate) unsafe extern "C" fn get_key_pair_parameter(
             }
 
             crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_EC_POINT => {
-                let ec_key = key_pair.ec_key()?;
-
-                let curve = ec_key.group();
-                let point = ec_key.public_key();
-                let mut big_num_context = openssl::bn::BigNumContext::new()?;
-                let point = point.to_bytes(
-                    curve,
-                    openssl::ec::PointConversionForm::COMPRESSED,
-                    &mut big_num_context,
-                )?;
+                let point = if key_pair.is_ed25519() {
+                    let public_key = key_pair.ed25519()?;
+                    openssl2::ed25519::public_key_to_raw(&public_key)?
+                } else {
+                    let ec_key = key_pair.ec_key()?;
+
+                    let curve = ec_key.group();
+                    let point = ec_key.public_key();
+                    let mut big_num_context = openssl::bn::BigNumContext::new()?;
+                    point.to_bytes(
+                        curve,
+                        openssl::ec::PointConversionForm::COMPRESSED,
+                        &mut big_num_context,
+                    )?
+                };
 
                 let expected_value_len = point.len();
                 let actual_value_len = *value_len_out.as_ref();
@@ -265,21 +445,480 @@ pub(crate) unsafe extern "C" fn get_key_pair_parameter(
                     value_out[..expected_value_len].copy_from_slice(&exponent);
                 }
 
-                Ok(())
+                Ok(())
+            }
+
+            _ => Err(crate::implementation::err_invalid_parameter(
+                "type",
+                "unrecognized value",
+            )),
+        }
+    })
+}
+
+pub(crate) unsafe extern "C" fn export_key_pair(
+    id: *const std::os::raw::c_char,
+    mechanism: crate::AZIOT_KEYS_WRAP_MECHANISM,
+    wrapping_key: *const std::os::raw::c_uchar,
+    wrapping_key_len: usize,
+    wrapped_key: *mut std::os::raw::c_uchar,
+    wrapped_key_len: *mut usize,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let id = {
+            if id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "expected non-NULL",
+                ));
+            }
+            let id = std::ffi::CStr::from_ptr(id);
+            let id = id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("id", err))?;
+            id
+        };
+
+        if wrapping_key.is_null() {
+            return Err(crate::implementation::err_invalid_parameter(
+                "wrapping_key",
+                "expected non-NULL",
+            ));
+        }
+        let wrapping_key = std::slice::from_raw_parts(wrapping_key, wrapping_key_len);
+
+        let mut wrapped_key_len_out = std::ptr::NonNull::new(wrapped_key_len).ok_or_else(|| {
+            crate::implementation::err_invalid_parameter("wrapped_key_len", "expected non-NULL")
+        })?;
+
+        let locations = crate::implementation::Location::of(id)?;
+
+        let key_pair = load_inner(&locations)?
+            .ok_or_else(|| crate::implementation::err_invalid_parameter("id", "not found"))?;
+
+        let private_key_pem = match &key_pair {
+            KeyPair::FileSystem(_, private_key) => private_key.private_key_to_pem_pkcs8()?,
+            KeyPair::Pkcs11(_) => {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "PKCS#11 key pairs cannot be exported",
+                ))
+            }
+        };
+
+        let expected_wrapped_key = crate::wrap::wrap(
+            mechanism,
+            wrapping_key,
+            crate::AZIOT_KEYS_KEY_KIND_KEY_PAIR,
+            crate::AZIOT_KEYS_KEY_USAGE { inner: 0 },
+            &private_key_pem,
+        )?;
+        let expected_wrapped_key_len = expected_wrapped_key.len();
+
+        let actual_wrapped_key_len = *wrapped_key_len_out.as_ref();
+
+        *wrapped_key_len_out.as_mut() = expected_wrapped_key_len;
+
+        if !wrapped_key.is_null() {
+            if actual_wrapped_key_len < expected_wrapped_key_len {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "wrapped_key",
+                    "insufficient size",
+                ));
+            }
+
+            let wrapped_key_out =
+                std::slice::from_raw_parts_mut(wrapped_key, actual_wrapped_key_len);
+
+            wrapped_key_out[..expected_wrapped_key_len].copy_from_slice(&expected_wrapped_key);
+        }
+
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn import_wrapped_key_pair(
+    id: *const std::os::raw::c_char,
+    unwrapping_key_pair_id: *const std::os::raw::c_char,
+    mechanism: crate::AZIOT_KEYS_WRAP_MECHANISM,
+    wrapped_key: *const std::os::raw::c_uchar,
+    wrapped_key_len: usize,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let id = {
+            if id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "expected non-NULL",
+                ));
+            }
+            let id = std::ffi::CStr::from_ptr(id);
+            let id = id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("id", err))?;
+            id
+        };
+
+        let unwrapping_key_pair_id = {
+            if unwrapping_key_pair_id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "unwrapping_key_pair_id",
+                    "expected non-NULL",
+                ));
+            }
+            let unwrapping_key_pair_id = std::ffi::CStr::from_ptr(unwrapping_key_pair_id);
+            let unwrapping_key_pair_id = unwrapping_key_pair_id.to_str().map_err(|err| {
+                crate::implementation::err_invalid_parameter("unwrapping_key_pair_id", err)
+            })?;
+            unwrapping_key_pair_id
+        };
+
+        if wrapped_key.is_null() {
+            return Err(crate::implementation::err_invalid_parameter(
+                "wrapped_key",
+                "expected non-NULL",
+            ));
+        }
+        let wrapped_key = std::slice::from_raw_parts(wrapped_key, wrapped_key_len);
+
+        let unwrapping_key = load_unwrapping_key(unwrapping_key_pair_id)?;
+        let unwrapped = crate::wrap::unwrap(
+            mechanism,
+            &unwrapping_key,
+            crate::AZIOT_KEYS_KEY_KIND_KEY_PAIR,
+            wrapped_key,
+        )?;
+
+        let private_key = openssl::pkey::PKey::private_key_from_pem(&unwrapped.payload)
+            .map_err(|err| crate::implementation::err_invalid_parameter("wrapped_key", err))?;
+
+        let _id_lock = crate::implementation::lock_id(id);
+
+        crate::implementation::ensure_not_preloaded(id)?;
+
+        let locations = crate::implementation::Location::of(id)?;
+
+        import_inner(&locations, &private_key)?;
+        if load_inner(&locations)?.is_none() {
+            return Err(crate::implementation::err_external(
+                "key created successfully but could not be found",
+            ));
+        }
+
+        crate::implementation::save_metadata(
+            id,
+            &locations,
+            Some(crate::implementation::Creation {
+                origin: crate::implementation::Origin::Imported,
+                usage: None,
+            }),
+        )?;
+
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn import_key_pair(
+    id: *const std::os::raw::c_char,
+    format: crate::AZIOT_KEYS_KEY_PAIR_FORMAT,
+    bytes: *const std::os::raw::c_uchar,
+    bytes_len: usize,
+    password: *const std::os::raw::c_char,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let id = {
+            if id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "expected non-NULL",
+                ));
+            }
+            let id = std::ffi::CStr::from_ptr(id);
+            let id = id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("id", err))?;
+            id
+        };
+
+        if bytes.is_null() {
+            return Err(crate::implementation::err_invalid_parameter(
+                "bytes",
+                "expected non-NULL",
+            ));
+        }
+        let bytes = std::slice::from_raw_parts(bytes, bytes_len);
+
+        let password = if password.is_null() {
+            None
+        } else {
+            let password = std::ffi::CStr::from_ptr(password);
+            let password = password
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("password", err))?;
+            Some(password)
+        };
+
+        let private_key = parse_private_key(format, bytes, password)?;
+
+        let _id_lock = crate::implementation::lock_id(id);
+
+        crate::implementation::ensure_not_preloaded(id)?;
+
+        let locations = crate::implementation::Location::of(id)?;
+
+        import_inner(&locations, &private_key)?;
+        if load_inner(&locations)?.is_none() {
+            return Err(crate::implementation::err_external(
+                "key created successfully but could not be found",
+            ));
+        }
+
+        crate::implementation::save_metadata(
+            id,
+            &locations,
+            Some(crate::implementation::Creation {
+                origin: crate::implementation::Origin::Imported,
+                usage: None,
+            }),
+        )?;
+
+        Ok(())
+    })
+}
+
+fn parse_private_key(
+    format: crate::AZIOT_KEYS_KEY_PAIR_FORMAT,
+    bytes: &[u8],
+    password: Option<&str>,
+) -> Result<openssl::pkey::PKey<openssl::pkey::Private>, crate::AZIOT_KEYS_RC> {
+    let private_key = match (format, password) {
+        (crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_PEM, None) => {
+            openssl::pkey::PKey::private_key_from_pem(bytes)
+        }
+
+        (crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_PEM, Some(password)) => {
+            openssl::pkey::PKey::private_key_from_pem_passphrase(bytes, password.as_bytes())
+        }
+
+        (crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_DER, None) => {
+            openssl::pkey::PKey::private_key_from_pkcs8(bytes)
+        }
+
+        (crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_DER, Some(password)) => {
+            openssl::pkey::PKey::private_key_from_pkcs8_passphrase(bytes, password.as_bytes())
+        }
+
+        (crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS12, password) => {
+            let password = password.ok_or_else(|| {
+                crate::implementation::err_invalid_parameter(
+                    "password",
+                    "expected non-NULL for PKCS#12",
+                )
+            })?;
+
+            openssl::pkcs12::Pkcs12::from_der(bytes)
+                .and_then(|pkcs12| pkcs12.parse(password))
+                .map(|parsed| parsed.pkey)
+        }
+
+        _ => {
+            return Err(crate::implementation::err_invalid_parameter(
+                "format",
+                "unrecognized value",
+            ))
+        }
+    };
+    let private_key =
+        private_key.map_err(|err| crate::implementation::err_invalid_parameter("bytes", err))?;
+
+    // Only accept the key types that key pairs can be generated as, since those are the only ones that the other functions support.
+    let supported = match private_key.id() {
+        openssl::pkey::Id::EC => {
+            let ec_key = private_key.ec_key()?;
+            ec_key
+                .group()
+                .curve_name()
+                .and_then(openssl2::EcCurve::from_nid)
+                .is_some()
+        }
+
+        openssl::pkey::Id::ED25519 | openssl::pkey::Id::RSA => true,
+
+        _ => false,
+    };
+    if !supported {
+        return Err(crate::implementation::err_invalid_parameter(
+            "bytes",
+            "private key type is not supported",
+        ));
+    }
+
+    Ok(private_key)
+}
+
+/// Loads the private key of the key pair identified by `id`, to unwrap keys with [`crate::wrap::unwrap`].
+///
+/// Unwrapping with PKCS#11 key pairs is not supported.
+pub(crate) fn load_unwrapping_key(
+    id: &str,
+) -> Result<openssl::pkey::PKey<openssl::pkey::Private>, crate::AZIOT_KEYS_RC> {
+    let locations = crate::implementation::Location::of(id)?;
+
+    match load_inner(&locations)? {
+        Some(KeyPair::FileSystem(_, private_key)) => Ok(private_key),
+
+        Some(KeyPair::Pkcs11(_)) => Err(crate::implementation::err_invalid_parameter(
+            "unwrapping_key_pair_id",
+            "PKCS#11 key pairs cannot be used to unwrap keys",
+        )),
+
+        None => Err(crate::implementation::err_invalid_parameter(
+            "unwrapping_key_pair_id",
+            "not found",
+        )),
+    }
+}
+
+pub(crate) unsafe extern "C" fn derive_shared_secret(
+    id: *const std::os::raw::c_char,
+    mechanism: crate::AZIOT_KEYS_KEY_AGREEMENT_MECHANISM,
+    parameters: *const std::ffi::c_void,
+    peer_public_key: *const std::os::raw::c_uchar,
+    peer_public_key_len: usize,
+    secret: *mut std::os::raw::c_uchar,
+    secret_len: *mut usize,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let id = {
+            if id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "expected non-NULL",
+                ));
+            }
+            let id = std::ffi::CStr::from_ptr(id);
+            let id = id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("id", err))?;
+            id
+        };
+
+        let hkdf_parameters = match mechanism {
+            crate::AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_ECDH => None,
+            crate::AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_ECDH_HKDF => {
+                Some(ecdh_hkdf_parameters(parameters)?)
+            }
+            _ => {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "mechanism",
+                    "unrecognized value",
+                ))
+            }
+        };
+
+        if peer_public_key.is_null() {
+            return Err(crate::implementation::err_invalid_parameter(
+                "peer_public_key",
+                "expected non-NULL",
+            ));
+        }
+        let peer_public_key = std::slice::from_raw_parts(peer_public_key, peer_public_key_len);
+        let peer_public_key = openssl::pkey::PKey::public_key_from_der(peer_public_key)
+            .map_err(|err| crate::implementation::err_invalid_parameter("peer_public_key", err))?;
+        let peer_ec_key = peer_public_key.ec_key().map_err(|_| {
+            crate::implementation::err_invalid_parameter("peer_public_key", "not an EC public key")
+        })?;
+
+        let mut secret_len_out = std::ptr::NonNull::new(secret_len).ok_or_else(|| {
+            crate::implementation::err_invalid_parameter("secret_len", "expected non-NULL")
+        })?;
+
+        let locations = crate::implementation::Location::of(id)?;
+
+        let key_pair = load_inner(&locations)?
+            .ok_or_else(|| crate::implementation::err_invalid_parameter("id", "not found"))?;
+
+        let ec_key = key_pair.ec_key()?;
+        if ec_key.group().curve_name() != peer_ec_key.group().curve_name() {
+            return Err(crate::implementation::err_invalid_parameter(
+                "peer_public_key",
+                "not on the same curve as the key pair",
+            ));
+        }
+
+        let shared_secret = match key_pair {
+            KeyPair::FileSystem(_, private_key) => {
+                let mut deriver = openssl::derive::Deriver::new(&private_key)?;
+                deriver.set_peer(&peer_public_key)?;
+                deriver.derive_to_vec()?
+            }
+
+            KeyPair::Pkcs11(pkcs11::KeyPair::Ec(_, private_key)) => {
+                let mut big_num_context = openssl::bn::BigNumContext::new()?;
+                let peer_point = peer_ec_key.public_key().to_bytes(
+                    peer_ec_key.group(),
+                    openssl::ec::PointConversionForm::UNCOMPRESSED,
+                    &mut big_num_context,
+                )?;
+
+                let field_len = ec_key.group().degree().div_ceil(8);
+                let field_len = std::convert::TryInto::try_into(field_len).expect("u32 -> usize");
+
+                private_key
+                    .derive_shared_secret(&peer_point, field_len)
+                    .map_err(|err| {
+                        crate::implementation::err_external(format!(
+                            "could not derive shared secret: {}",
+                            err
+                        ))
+                    })?
+            }
+
+            KeyPair::Pkcs11(pkcs11::KeyPair::Rsa(_, _) | pkcs11::KeyPair::Ed25519(_, _)) => {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "type",
+                    "not an EC key pair",
+                ))
+            }
+        };
+
+        let expected_secret = match hkdf_parameters {
+            Some((digest_algorithm, salt, info, output_len)) => crate::kdf::hkdf(
+                message_digest(digest_algorithm),
+                &shared_secret,
+                salt,
+                info,
+                output_len,
+            )?,
+            None => shared_secret,
+        };
+        let expected_secret_len = expected_secret.len();
+
+        let actual_secret_len = *secret_len_out.as_ref();
+
+        *secret_len_out.as_mut() = expected_secret_len;
+
+        if !secret.is_null() {
+            if actual_secret_len < expected_secret_len {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "secret",
+                    "insufficient size",
+                ));
             }
 
-            _ => Err(crate::implementation::err_invalid_parameter(
-                "type",
-                "unrecognized value",
-            )),
+            let secret_out = std::slice::from_raw_parts_mut(secret, actual_secret_len);
+
+            secret_out[..expected_secret_len].copy_from_slice(&expected_secret);
         }
+
+        Ok(())
     })
 }
 
 pub(crate) unsafe fn sign(
     locations: &[crate::implementation::Location],
     mechanism: crate::AZIOT_KEYS_SIGN_MECHANISM,
-    _parameters: *const std::ffi::c_void,
+    parameters: *const std::ffi::c_void,
     digest: &[u8],
 ) -> Result<(usize, Vec<u8>), crate::AZIOT_KEYS_RC> {
     let key_pair = load_inner(locations)?
@@ -308,6 +947,82 @@ pub(crate) unsafe fn sign(
                     Some((signature_len, signature))
                 }
 
+                (crate::AZIOT_KEYS_SIGN_MECHANISM_EDDSA, _, _)
+                    if private_key.id() == openssl::pkey::Id::ED25519 =>
+                {
+                    // EdDSA hashes the message internally, so the signer must not be given a digest.
+                    let mut signer = openssl::sign::Signer::new_without_digest(&private_key)?;
+                    let signature = signer.sign_oneshot_to_vec(digest)?;
+
+                    Some((openssl2::ed25519::SIGNATURE_LEN, signature))
+                }
+
+                (crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1, _, Ok(rsa)) => {
+                    let digest_algorithm = rsa_pkcs1_parameters(parameters)?;
+                    let digest_info = digest_info(digest_algorithm, digest)?;
+
+                    let signature_len = rsa_size(&rsa)?;
+                    let mut signature = vec![0_u8; signature_len];
+                    let actual_signature_len = rsa.private_encrypt(
+                        &digest_info,
+                        &mut signature,
+                        openssl::rsa::Padding::PKCS1,
+                    )?;
+                    signature.truncate(actual_signature_len);
+
+                    Some((signature_len, signature))
+                }
+
+                (crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS, _, Ok(rsa)) => {
+                    let (digest_algorithm, salt_len) = rsa_pss_parameters(parameters)?;
+                    let message_digest = message_digest(digest_algorithm);
+                    if digest.len() != message_digest.size() {
+                        return Err(crate::implementation::err_invalid_parameter(
+                            "digest",
+                            "digest length does not match digest algorithm",
+                        ));
+                    }
+
+                    let signature_len = rsa_size(&rsa)?;
+
+                    // The encoded message must fit the digest, the salt and two more bytes.
+                    let encoded_message_len = message_digest
+                        .size()
+                        .checked_add(salt_len)
+                        .and_then(|len| len.checked_add(2));
+                    if encoded_message_len.map_or(true, |len| len > signature_len) {
+                        return Err(crate::implementation::err_invalid_parameter(
+                            "parameters",
+                            "salt length is too long for the key",
+                        ));
+                    }
+                    let salt_len = std::convert::TryInto::try_into(salt_len).map_err(|err| {
+                        crate::implementation::err_invalid_parameter("parameters", err)
+                    })?;
+
+                    // openssl can only apply PSS padding when it computes the digest itself,
+                    // so pad the caller's digest manually and then sign it without padding.
+                    let mut encoded_message = vec![0_u8; signature_len];
+                    openssl2::openssl_returns_1(openssl_sys2::RSA_padding_add_PKCS1_PSS_mgf1(
+                        foreign_types_shared::ForeignType::as_ptr(&rsa),
+                        encoded_message.as_mut_ptr(),
+                        digest.as_ptr(),
+                        message_digest.as_ptr(),
+                        message_digest.as_ptr(),
+                        salt_len,
+                    ))?;
+
+                    let mut signature = vec![0_u8; signature_len];
+                    let actual_signature_len = rsa.private_encrypt(
+                        &encoded_message,
+                        &mut signature,
+                        openssl::rsa::Padding::NONE,
+                    )?;
+                    signature.truncate(actual_signature_len);
+
+                    Some((signature_len, signature))
+                }
+
                 _ => None,
             }
         }
@@ -361,6 +1076,69 @@ pub(crate) unsafe fn sign(
                 Some((signature_len, signature))
             }
 
+            (crate::AZIOT_KEYS_SIGN_MECHANISM_EDDSA, pkcs11::KeyPair::Ed25519(_, private_key)) => {
+                let signature_len = openssl2::ed25519::SIGNATURE_LEN;
+
+                let mut signature = vec![0_u8; signature_len];
+                let actual_signature_len =
+                    private_key.sign(digest, &mut signature).map_err(|err| {
+                        crate::implementation::err_external(format!("could not sign: {}", err))
+                    })?;
+                let actual_signature_len = std::convert::TryInto::try_into(actual_signature_len)
+                    .expect("CK_ULONG -> usize");
+                signature.truncate(actual_signature_len);
+
+                Some((signature_len, signature))
+            }
+
+            (
+                crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1
+                | crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS,
+                pkcs11::KeyPair::Rsa(public_key, private_key),
+            ) => {
+                let (rsa_mechanism, digest) = if mechanism
+                    == crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1
+                {
+                    let digest_algorithm = rsa_pkcs1_parameters(parameters)?;
+                    let digest_info = digest_info(digest_algorithm, digest)?;
+                    (pkcs11::RsaSignMechanism::Pkcs1, digest_info)
+                } else {
+                    let (digest_algorithm, salt_len) = rsa_pss_parameters(parameters)?;
+                    let salt_len = std::convert::TryInto::try_into(salt_len).map_err(|err| {
+                        crate::implementation::err_invalid_parameter("parameters", err)
+                    })?;
+                    (
+                        pkcs11::RsaSignMechanism::Pss {
+                            digest_algorithm,
+                            salt_len,
+                        },
+                        digest.to_owned(),
+                    )
+                };
+
+                let signature_len = {
+                    let rsa = public_key.parameters().map_err(|err| {
+                        crate::implementation::err_external(format!(
+                            "could not get key pair parameters: {}",
+                            err
+                        ))
+                    })?;
+                    rsa_size(&rsa)?
+                };
+
+                let mut signature = vec![0_u8; signature_len];
+                let actual_signature_len = private_key
+                    .sign(&rsa_mechanism, &digest, &mut signature)
+                    .map_err(|err| {
+                        crate::implementation::err_external(format!("could not sign: {}", err))
+                    })?;
+                let actual_signature_len = std::convert::TryInto::try_into(actual_signature_len)
+                    .expect("CK_ULONG -> usize");
+                signature.truncate(actual_signature_len);
+
+                Some((signature_len, signature))
+            }
+
             _ => None,
         },
     };
@@ -371,6 +1149,145 @@ pub(crate) unsafe fn sign(
     Ok(result)
 }
 
+unsafe fn rsa_pkcs1_parameters(
+    parameters: *const std::ffi::c_void,
+) -> Result<pkcs11::DigestAlgorithm, crate::AZIOT_KEYS_RC> {
+    if parameters.is_null() {
+        return Err(crate::implementation::err_invalid_parameter(
+            "parameters",
+            "expected non-NULL",
+        ));
+    }
+
+    let parameters = &*parameters.cast::<crate::AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS>();
+
+    digest_algorithm(parameters.digest_algorithm)
+}
+
+unsafe fn rsa_pss_parameters(
+    parameters: *const std::ffi::c_void,
+) -> Result<(pkcs11::DigestAlgorithm, usize), crate::AZIOT_KEYS_RC> {
+    if parameters.is_null() {
+        return Err(crate::implementation::err_invalid_parameter(
+            "parameters",
+            "expected non-NULL",
+        ));
+    }
+
+    let parameters = &*parameters.cast::<crate::AZIOT_KEYS_SIGN_RSA_PSS_PARAMETERS>();
+
+    let digest_algorithm = digest_algorithm(parameters.digest_algorithm)?;
+    Ok((digest_algorithm, parameters.salt_len))
+}
+
+unsafe fn ecdh_hkdf_parameters<'a>(
+    parameters: *const std::ffi::c_void,
+) -> Result<(pkcs11::DigestAlgorithm, &'a [u8], &'a [u8], usize), crate::AZIOT_KEYS_RC> {
+    if parameters.is_null() {
+        return Err(crate::implementation::err_invalid_parameter(
+            "parameters",
+            "expected non-NULL",
+        ));
+    }
+
+    let parameters = &*parameters.cast::<crate::AZIOT_KEYS_ECDH_HKDF_PARAMETERS>();
+
+    let digest_algorithm = digest_algorithm(parameters.digest_algorithm)?;
+    let salt = parameters_buf(parameters.salt, parameters.salt_len)?;
+    let info = parameters_buf(parameters.info, parameters.info_len)?;
+    if parameters.output_len == 0 {
+        return Err(crate::implementation::err_invalid_parameter(
+            "parameters",
+            "HKDF output length must be non-zero",
+        ));
+    }
+
+    Ok((digest_algorithm, salt, info, parameters.output_len))
+}
+
+/// Converts a buffer in a parameters struct to a slice. The pointer may be `NULL` if the length is 0.
+pub(crate) unsafe fn parameters_buf<'a>(
+    ptr: *const std::os::raw::c_uchar,
+    len: usize,
+) -> Result<&'a [u8], crate::AZIOT_KEYS_RC> {
+    if len == 0 {
+        Ok(&[])
+    } else if ptr.is_null() {
+        Err(crate::implementation::err_invalid_parameter(
+            "parameters",
+            "expected non-NULL buffer",
+        ))
+    } else {
+        Ok(std::slice::from_raw_parts(ptr, len))
+    }
+}
+
+pub(crate) fn digest_algorithm(
+    digest_algorithm: crate::AZIOT_KEYS_DIGEST_ALGORITHM,
+) -> Result<pkcs11::DigestAlgorithm, crate::AZIOT_KEYS_RC> {
+    match digest_algorithm {
+        crate::AZIOT_KEYS_DIGEST_ALGORITHM_SHA256 => Ok(pkcs11::DigestAlgorithm::Sha256),
+        crate::AZIOT_KEYS_DIGEST_ALGORITHM_SHA384 => Ok(pkcs11::DigestAlgorithm::Sha384),
+        crate::AZIOT_KEYS_DIGEST_ALGORITHM_SHA512 => Ok(pkcs11::DigestAlgorithm::Sha512),
+        _ => Err(crate::implementation::err_invalid_parameter(
+            "parameters",
+            "unrecognized digest algorithm",
+        )),
+    }
+}
+
+pub(crate) fn message_digest(
+    digest_algorithm: pkcs11::DigestAlgorithm,
+) -> openssl::hash::MessageDigest {
+    match digest_algorithm {
+        pkcs11::DigestAlgorithm::Sha256 => openssl::hash::MessageDigest::sha256(),
+        pkcs11::DigestAlgorithm::Sha384 => openssl::hash::MessageDigest::sha384(),
+        pkcs11::DigestAlgorithm::Sha512 => openssl::hash::MessageDigest::sha512(),
+    }
+}
+
+/// Encodes the given digest as the DER `DigestInfo` that RSASSA-PKCS1-v1_5 signs.
+fn digest_info(
+    digest_algorithm: pkcs11::DigestAlgorithm,
+    digest: &[u8],
+) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    // Ref: RFC 8017, section 9.2, note 1
+    let prefix: &[u8] = match digest_algorithm {
+        pkcs11::DigestAlgorithm::Sha256 => &[
+            0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x01, 0x05, 0x00, 0x04, 0x20,
+        ],
+        pkcs11::DigestAlgorithm::Sha384 => &[
+            0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x02, 0x05, 0x00, 0x04, 0x30,
+        ],
+        pkcs11::DigestAlgorithm::Sha512 => &[
+            0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x03, 0x05, 0x00, 0x04, 0x40,
+        ],
+    };
+
+    if digest.len() != message_digest(digest_algorithm).size() {
+        return Err(crate::implementation::err_invalid_parameter(
+            "digest",
+            "digest length does not match digest algorithm",
+        ));
+    }
+
+    let mut digest_info = prefix.to_owned();
+    digest_info.extend_from_slice(digest);
+    Ok(digest_info)
+}
+
+fn rsa_size<T>(rsa: &openssl::rsa::RsaRef<T>) -> Result<usize, crate::AZIOT_KEYS_RC>
+where
+    T: openssl::pkey::HasPublic,
+{
+    std::convert::TryInto::try_into(rsa.size()).map_err(|err| {
+        crate::implementation::err_external(format!("RSA_size returned invalid value: {}", err))
+    })
+}
+
 pub(crate) unsafe fn encrypt(
     locations: &[crate::implementation::Location],
     mechanism: crate::AZIOT_KEYS_ENCRYPT_MECHANISM,
@@ -417,7 +1334,7 @@ pub(crate) unsafe fn encrypt(
             (result_len, result)
         }
 
-        KeyPair::Pkcs11(pkcs11::KeyPair::Ec(_, _)) => {
+        KeyPair::Pkcs11(pkcs11::KeyPair::Ec(_, _) | pkcs11::KeyPair::Ed25519(_, _)) => {
             return Err(crate::implementation::err_invalid_parameter(
                 "mechanism",
                 "unrecognized value",
@@ -484,6 +1401,38 @@ enum KeyPair {
 }
 
 impl KeyPair {
+    fn is_ed25519(&self) -> bool {
+        match self {
+            KeyPair::FileSystem(public_key, _) => public_key.id() == openssl::pkey::Id::ED25519,
+            KeyPair::Pkcs11(pkcs11::KeyPair::Ed25519(_, _)) => true,
+            KeyPair::Pkcs11(_) => false,
+        }
+    }
+
+    fn ed25519(&self) -> Result<openssl::pkey::PKey<openssl::pkey::Public>, crate::AZIOT_KEYS_RC> {
+        let public_key = match self {
+            KeyPair::FileSystem(public_key, _) if public_key.id() == openssl::pkey::Id::ED25519 => {
+                Some(public_key.clone())
+            }
+
+            KeyPair::Pkcs11(pkcs11::KeyPair::Ed25519(public_key, _)) => {
+                let public_key = public_key.parameters().map_err(|err| {
+                    crate::implementation::err_external(format!(
+                        "could not get key pair parameters: {}",
+                        err
+                    ))
+                })?;
+                Some(public_key)
+            }
+
+            _ => None,
+        };
+        let public_key = public_key.ok_or_else(|| {
+            crate::implementation::err_invalid_parameter("type", "not an Ed25519 key pair")
+        })?;
+        Ok(public_key)
+    }
+
     fn ec_key(&self) -> Result<openssl::ec::EcKey<openssl::pkey::Public>, crate::AZIOT_KEYS_RC> {
         let ec_key = match self {
             KeyPair::FileSystem(public_key, _) => public_key.ec_key().ok(),
@@ -498,7 +1447,7 @@ impl KeyPair {
                 Some(ec_key)
             }
 
-            KeyPair::Pkcs11(pkcs11::KeyPair::Rsa(_, _)) => None,
+            KeyPair::Pkcs11(pkcs11::KeyPair::Rsa(_, _) | pkcs11::KeyPair::Ed25519(_, _)) => None,
         };
         let ec_key = ec_key.ok_or_else(|| {
             crate::implementation::err_invalid_parameter("type", "not an EC key pair")
@@ -510,7 +1459,7 @@ impl KeyPair {
         let rsa = match self {
             KeyPair::FileSystem(public_key, _) => public_key.rsa().ok(),
 
-            KeyPair::Pkcs11(pkcs11::KeyPair::Ec(_, _)) => None,
+            KeyPair::Pkcs11(pkcs11::KeyPair::Ec(_, _) | pkcs11::KeyPair::Ed25519(_, _)) => None,
 
             KeyPair::Pkcs11(pkcs11::KeyPair::Rsa(public_key, _)) => {
                 let rsa = public_key.parameters().map_err(|err| {
@@ -534,8 +1483,8 @@ fn load_inner(
 ) -> Result<Option<KeyPair>, crate::AZIOT_KEYS_RC> {
     for location in locations {
         match location {
-            crate::implementation::Location::Filesystem(path) => match std::fs::read(path) {
-                Ok(private_key_pem) => {
+            crate::implementation::Location::Filesystem(path) => {
+                if let Some(private_key_pem) = crate::implementation::read_key_file(path)? {
                     let private_key = openssl::pkey::PKey::private_key_from_pem(&private_key_pem)?;
 
                     // Copy private_key's public parameters into a new public key
@@ -544,23 +1493,14 @@ fn load_inner(
 
                     return Ok(Some(KeyPair::FileSystem(public_key, private_key)));
                 }
-
-                Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
-
-                Err(err) => return Err(crate::implementation::err_external(err)),
-            },
+            }
 
             crate::implementation::Location::Pkcs11 { lib_path, uri } => {
-                let pkcs11_context = pkcs11::Context::load(lib_path.clone())
-                    .map_err(crate::implementation::err_external)?;
-                let pkcs11_slot = pkcs11_context
-                    .find_slot(&uri.slot_identifier)
-                    .map_err(crate::implementation::err_external)?;
-                let pkcs11_session = pkcs11_context
-                    .open_session(pkcs11_slot, uri.pin.clone())
-                    .map_err(crate::implementation::err_external)?;
-
-                match pkcs11_session.get_key_pair(uri.object_label.as_ref().map(AsRef::as_ref)) {
+                let result =
+                    crate::implementation::with_pkcs11_session(lib_path, uri, |pkcs11_session| {
+                        pkcs11_session.get_key_pair(uri.object_label.as_ref().map(AsRef::as_ref))
+                    })?;
+                match result {
                     Ok(key_pair) => return Ok(Some(KeyPair::Pkcs11(key_pair))),
 
                     Err(pkcs11::GetKeyError::KeyDoesNotExist) => (),
@@ -574,6 +1514,54 @@ fn load_inner(
     Ok(None)
 }
 
+fn delete_inner(locations: &[crate::implementation::Location]) -> Result<(), crate::AZIOT_KEYS_RC> {
+    // The key pair may have been created in any of the locations, so delete it from all of them.
+    for location in locations {
+        match location {
+            crate::implementation::Location::Filesystem(path) => {
+                crate::implementation::delete_key_file(path)?;
+            }
+
+            crate::implementation::Location::Pkcs11 { lib_path, uri } => {
+                let label = crate::implementation::pkcs11_object_label(uri)?;
+                crate::implementation::with_pkcs11_session(lib_path, uri, |pkcs11_session| {
+                    pkcs11_session.delete_key_pair(label)
+                })?
+                .map_err(crate::implementation::err_external)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn import_inner(
+    locations: &[crate::implementation::Location],
+    private_key: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
+) -> Result<(), crate::AZIOT_KEYS_RC> {
+    let location = locations
+        .first()
+        .ok_or_else(|| crate::implementation::err_external("no valid location for key pair"))?;
+    match location {
+        crate::implementation::Location::Filesystem(path) => {
+            let private_key_pem = private_key.private_key_to_pem_pkcs8()?;
+            let () = crate::implementation::write_key_file(path, &private_key_pem, true)?;
+
+            Ok(())
+        }
+
+        crate::implementation::Location::Pkcs11 { lib_path, uri } => {
+            crate::implementation::with_pkcs11_session(lib_path, uri, |pkcs11_session| {
+                pkcs11_session
+                    .import_key_pair(private_key, uri.object_label.as_ref().map(AsRef::as_ref))
+            })?
+            .map_err(crate::implementation::err_external)?;
+
+            Ok(())
+        }
+    }
+}
+
 fn create_inner(
     locations: &[crate::implementation::Location],
     preferred_algorithms: &[PreferredAlgorithm],
@@ -601,6 +1589,17 @@ fn create_inner(
                     private_key
                 }
 
+                PreferredAlgorithm::NistP384 => {
+                    let mut group =
+                        openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::SECP384R1)?;
+                    group.set_asn1_flag(openssl::ec::Asn1Flag::NAMED_CURVE);
+                    let ec_key = openssl::ec::EcKey::generate(&group)?;
+                    let private_key = openssl::pkey::PKey::from_ec_key(ec_key)?;
+                    private_key
+                }
+
+                PreferredAlgorithm::Ed25519 => openssl::pkey::PKey::generate_ed25519()?,
+
                 PreferredAlgorithm::Rsa2048 => {
                     let rsa = openssl::rsa::Rsa::generate(2048)?;
                     let private_key = openssl::pkey::PKey::from_rsa(rsa)?;
@@ -615,72 +1614,93 @@ fn create_inner(
             };
 
             let private_key_pem = private_key.private_key_to_pem_pkcs8()?;
-            std::fs::write(&path, &private_key_pem).map_err(crate::implementation::err_external)?;
+            let () = crate::implementation::write_key_file(path, &private_key_pem, false)?;
 
             Ok(())
         }
 
         crate::implementation::Location::Pkcs11 { lib_path, uri } => {
-            let pkcs11_context = pkcs11::Context::load(lib_path.clone())
-                .map_err(crate::implementation::err_external)?;
-            let pkcs11_slot = pkcs11_context
-                .find_slot(&uri.slot_identifier)
-                .map_err(crate::implementation::err_external)?;
-            let pkcs11_session = pkcs11_context
-                .open_session(pkcs11_slot, uri.pin.clone())
-                .map_err(crate::implementation::err_external)?;
+            let exponent = openssl_sys::RSA_F4;
+            let exponent = exponent.to_be_bytes();
+            let exponent = openssl::bn::BigNum::from_slice(&exponent)?;
+
+            // The closure fails if no algorithm succeeded, so that it's retried if that was because the session was lost.
+            let result =
+                crate::implementation::with_pkcs11_session(lib_path, uri, |pkcs11_session| {
+                    for preferred_algorithm in preferred_algorithms {
+                        match preferred_algorithm {
+                            PreferredAlgorithm::NistP256 => {
+                                if pkcs11_session
+                                    .clone()
+                                    .generate_ec_key_pair(
+                                        openssl2::EcCurve::NistP256,
+                                        uri.object_label.as_ref().map(AsRef::as_ref),
+                                    )
+                                    .is_ok()
+                                {
+                                    return Ok(());
+                                }
+                            }
 
-            for preferred_algorithm in preferred_algorithms {
-                match preferred_algorithm {
-                    PreferredAlgorithm::NistP256 => {
-                        if pkcs11_session
-                            .clone()
-                            .generate_ec_key_pair(
-                                openssl2::EcCurve::NistP256,
-                                uri.object_label.as_ref().map(AsRef::as_ref),
-                            )
-                            .is_ok()
-                        {
-                            return Ok(());
-                        }
-                    }
+                            PreferredAlgorithm::NistP384 => {
+                                if pkcs11_session
+                                    .clone()
+                                    .generate_ec_key_pair(
+                                        openssl2::EcCurve::NistP384,
+                                        uri.object_label.as_ref().map(AsRef::as_ref),
+                                    )
+                                    .is_ok()
+                                {
+                                    return Ok(());
+                                }
+                            }
 
-                    PreferredAlgorithm::Rsa2048 => {
-                        let exponent = openssl_sys::RSA_F4;
-                        let exponent = exponent.to_be_bytes();
-                        let exponent = openssl::bn::BigNum::from_slice(&exponent)?;
-
-                        if pkcs11_session
-                            .clone()
-                            .generate_rsa_key_pair(
-                                2048,
-                                &exponent,
-                                uri.object_label.as_ref().map(AsRef::as_ref),
-                            )
-                            .is_ok()
-                        {
-                            return Ok(());
-                        }
-                    }
+                            PreferredAlgorithm::Ed25519 => {
+                                if pkcs11_session
+                                    .clone()
+                                    .generate_ed25519_key_pair(
+                                        uri.object_label.as_ref().map(AsRef::as_ref),
+                                    )
+                                    .is_ok()
+                                {
+                                    return Ok(());
+                                }
+                            }
+
+                            PreferredAlgorithm::Rsa2048 => {
+                                if pkcs11_session
+                                    .clone()
+                                    .generate_rsa_key_pair(
+                                        2048,
+                                        &exponent,
+                                        uri.object_label.as_ref().map(AsRef::as_ref),
+                                    )
+                                    .is_ok()
+                                {
+                                    return Ok(());
+                                }
+                            }
 
-                    PreferredAlgorithm::Rsa4096 => {
-                        let exponent = openssl_sys::RSA_F4;
-                        let exponent = exponent.to_be_bytes();
-                        let exponent = openssl::bn::BigNum::from_slice(&exponent)?;
-
-                        if pkcs11_session
-                            .clone()
-                            .generate_rsa_key_pair(
-                                4096,
-                                &exponent,
-                                uri.object_label.as_ref().map(AsRef::as_ref),
-                            )
-                            .is_ok()
-                        {
-                            return Ok(());
+                            PreferredAlgorithm::Rsa4096 => {
+                                if pkcs11_session
+                                    .clone()
+                                    .generate_rsa_key_pair(
+                                        4096,
+                                        &exponent,
+                                        uri.object_label.as_ref().map(AsRef::as_ref),
+                                    )
+                                    .is_ok()
+                                {
+                                    return Ok(());
+                                }
+                            }
                         }
                     }
-                }
+
+                    Err(())
+                })?;
+            if result.is_ok() {
+                return Ok(());
             }
 
             Err(crate::implementation::err_invalid_parameter(
@@ -694,6 +1714,8 @@ fn create_inner(
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum PreferredAlgorithm {
     NistP256,
+    NistP384,
+    Ed25519,
     Rsa2048,
     Rsa4096,
 }
@@ -732,6 +1754,10 @@ impl PreferredAlgorithm {
 
                 "ec-p256" => add_if_not_exists(&mut result, PreferredAlgorithm::NistP256),
 
+                "ec-p384" => add_if_not_exists(&mut result, PreferredAlgorithm::NistP384),
+
+                "ed25519" => add_if_not_exists(&mut result, PreferredAlgorithm::Ed25519),
+
                 "rsa-2048" => add_if_not_exists(&mut result, PreferredAlgorithm::Rsa2048),
 
                 "rsa-4096" => add_if_not_exists(&mut result, PreferredAlgorithm::Rsa4096),
@@ -742,3 +1768,616 @@ impl PreferredAlgorithm {
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::implementation::tests::{c_string, TestHomedir};
+
+    fn ec_private_key() -> openssl::pkey::PKey<openssl::pkey::Private> {
+        let group =
+            openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1).unwrap();
+        let ec_key = openssl::ec::EcKey::generate(&group).unwrap();
+        openssl::pkey::PKey::from_ec_key(ec_key).unwrap()
+    }
+
+    /// Converts a PEM document to DER by decoding its body.
+    fn pem_to_der(pem: &[u8]) -> Vec<u8> {
+        let pem = std::str::from_utf8(pem).unwrap();
+        let body: String = pem
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        openssl::base64::decode_block(&body).unwrap()
+    }
+
+    fn pkcs12(
+        private_key: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
+        password: &str,
+    ) -> Vec<u8> {
+        let mut name = openssl::x509::X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "test").unwrap();
+        let name = name.build();
+
+        let mut cert = openssl::x509::X509::builder().unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(private_key).unwrap();
+        cert.set_not_before(&openssl::asn1::Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&openssl::asn1::Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(private_key, openssl::hash::MessageDigest::sha256())
+            .unwrap();
+        let cert = cert.build();
+
+        openssl::pkcs12::Pkcs12::builder()
+            .build(password, "test", private_key, &cert)
+            .unwrap()
+            .to_der()
+            .unwrap()
+    }
+
+    fn assert_same_key(
+        parsed: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
+        expected: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
+    ) {
+        assert_eq!(
+            parsed.public_key_to_der().unwrap(),
+            expected.public_key_to_der().unwrap(),
+        );
+    }
+
+    #[test]
+    fn parse_pkcs8() {
+        let private_key = ec_private_key();
+
+        let pem = private_key.private_key_to_pem_pkcs8().unwrap();
+        let parsed =
+            super::parse_private_key(crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_PEM, &pem, None)
+                .unwrap();
+        assert_same_key(&parsed, &private_key);
+
+        let der = pem_to_der(&pem);
+        let parsed =
+            super::parse_private_key(crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_DER, &der, None)
+                .unwrap();
+        assert_same_key(&parsed, &private_key);
+
+        // The format must match the bytes.
+        assert_eq!(
+            super::parse_private_key(crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_DER, &pem, None)
+                .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            super::parse_private_key(crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_PEM, &der, None)
+                .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn parse_encrypted_pkcs8() {
+        let private_key = ec_private_key();
+
+        let pem = private_key
+            .private_key_to_pem_pkcs8_passphrase(openssl::symm::Cipher::aes_256_cbc(), b"password")
+            .unwrap();
+        let parsed = super::parse_private_key(
+            crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_PEM,
+            &pem,
+            Some("password"),
+        )
+        .unwrap();
+        assert_same_key(&parsed, &private_key);
+        assert_eq!(
+            super::parse_private_key(
+                crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_PEM,
+                &pem,
+                Some("wrong password"),
+            )
+            .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        let der = pem_to_der(&pem);
+        let parsed = super::parse_private_key(
+            crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_DER,
+            &der,
+            Some("password"),
+        )
+        .unwrap();
+        assert_same_key(&parsed, &private_key);
+        assert_eq!(
+            super::parse_private_key(
+                crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_DER,
+                &der,
+                Some("wrong password"),
+            )
+            .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            super::parse_private_key(crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_DER, &der, None)
+                .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn parse_pkcs12() {
+        let private_key = ec_private_key();
+        let pkcs12 = pkcs12(&private_key, "password");
+
+        let parsed = super::parse_private_key(
+            crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS12,
+            &pkcs12,
+            Some("password"),
+        )
+        .unwrap();
+        assert_same_key(&parsed, &private_key);
+
+        assert_eq!(
+            super::parse_private_key(crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS12, &pkcs12, None)
+                .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            super::parse_private_key(
+                crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS12,
+                &pkcs12,
+                Some("wrong password"),
+            )
+            .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn parse_unsupported() {
+        let private_key = ec_private_key();
+        let pem = private_key.private_key_to_pem_pkcs8().unwrap();
+
+        // Unrecognized format.
+        assert_eq!(
+            super::parse_private_key(crate::AZIOT_KEYS_KEY_PAIR_FORMAT { inner: 0 }, &pem, None)
+                .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        // Key types that key pairs can't be generated as.
+        let x25519 = openssl::pkey::PKey::generate_x25519().unwrap();
+        let pem = x25519.private_key_to_pem_pkcs8().unwrap();
+        assert_eq!(
+            super::parse_private_key(crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_PEM, &pem, None)
+                .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        let group = openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::SECP256K1).unwrap();
+        let secp256k1 =
+            openssl::pkey::PKey::from_ec_key(openssl::ec::EcKey::generate(&group).unwrap())
+                .unwrap();
+        let pem = secp256k1.private_key_to_pem_pkcs8().unwrap();
+        assert_eq!(
+            super::parse_private_key(crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_PEM, &pem, None)
+                .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn import_key_pair() {
+        let _homedir = TestHomedir::new();
+
+        let private_key = ec_private_key();
+        let pkcs12 = pkcs12(&private_key, "password");
+
+        let id = c_string("imported");
+        let password = c_string("wrong password");
+        assert_eq!(
+            unsafe {
+                super::import_key_pair(
+                    id.as_ptr(),
+                    crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS12,
+                    pkcs12.as_ptr(),
+                    pkcs12.len(),
+                    password.as_ptr(),
+                )
+            },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        assert_eq!(
+            unsafe { super::load_key_pair(id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        let password = c_string("password");
+        assert_eq!(
+            unsafe {
+                super::import_key_pair(
+                    id.as_ptr(),
+                    crate::AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS12,
+                    pkcs12.as_ptr(),
+                    pkcs12.len(),
+                    password.as_ptr(),
+                )
+            },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        let locations = crate::implementation::Location::of("imported").unwrap();
+        match super::load_inner(&locations).unwrap() {
+            Some(super::KeyPair::FileSystem(_, imported)) => {
+                assert_same_key(&imported, &private_key);
+            }
+            _ => panic!("expected imported key pair to be in the filesystem"),
+        }
+    }
+
+    /// Generates a key pair with the given preferred algorithms, and returns its locations and its public key.
+    fn generate(
+        id: &str,
+        preferred_algorithms: &str,
+    ) -> (
+        Vec<crate::implementation::Location>,
+        openssl::pkey::PKey<openssl::pkey::Public>,
+    ) {
+        let c_id = c_string(id);
+        let c_preferred_algorithms = c_string(preferred_algorithms);
+        assert_eq!(
+            unsafe {
+                super::create_key_pair_if_not_exists(c_id.as_ptr(), c_preferred_algorithms.as_ptr())
+            },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        let locations = crate::implementation::Location::of(id).unwrap();
+        let public_key = match super::load_inner(&locations).unwrap() {
+            Some(super::KeyPair::FileSystem(public_key, _)) => public_key,
+            _ => panic!("expected generated key pair to be in the filesystem"),
+        };
+        (locations, public_key)
+    }
+
+    fn key_pair_parameter(id: &str, r#type: crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE) -> Vec<u8> {
+        let id = c_string(id);
+
+        let mut value_len = 0;
+        assert_eq!(
+            unsafe {
+                super::get_key_pair_parameter(
+                    id.as_ptr(),
+                    r#type,
+                    std::ptr::null_mut(),
+                    &mut value_len,
+                )
+            },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        let mut value = vec![0_u8; value_len];
+        assert_eq!(
+            unsafe {
+                super::get_key_pair_parameter(
+                    id.as_ptr(),
+                    r#type,
+                    value.as_mut_ptr(),
+                    &mut value_len,
+                )
+            },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+        value.truncate(value_len);
+        value
+    }
+
+    #[test]
+    fn generate_and_sign_ec_p384() {
+        let _homedir = TestHomedir::new();
+
+        let (locations, public_key) = generate("ec-p384", "ec-p384");
+
+        let ec_key = public_key.ec_key().unwrap();
+        assert_eq!(
+            ec_key.group().curve_name(),
+            Some(openssl::nid::Nid::SECP384R1)
+        );
+        assert_eq!(
+            key_pair_parameter(
+                "ec-p384",
+                crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_EC_CURVE_OID
+            ),
+            openssl2::EcCurve::NistP384.as_oid_der(),
+        );
+
+        let digest = openssl::sha::sha384(b"message to sign");
+        let (signature_len, signature) = unsafe {
+            super::sign(
+                &locations,
+                crate::AZIOT_KEYS_SIGN_MECHANISM_ECDSA,
+                std::ptr::null(),
+                &digest,
+            )
+        }
+        .unwrap();
+        assert!(signature.len() <= signature_len);
+
+        let signature = openssl::ecdsa::EcdsaSig::from_der(&signature).unwrap();
+        assert!(signature.verify(&digest, &ec_key).unwrap());
+
+        // Ed25519 signing does not apply to EC keys.
+        assert_eq!(
+            unsafe {
+                super::sign(
+                    &locations,
+                    crate::AZIOT_KEYS_SIGN_MECHANISM_EDDSA,
+                    std::ptr::null(),
+                    &digest,
+                )
+            }
+            .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn generate_and_sign_ed25519() {
+        let _homedir = TestHomedir::new();
+
+        let (locations, public_key) = generate("ed25519", "ed25519");
+
+        assert_eq!(public_key.id(), openssl::pkey::Id::ED25519);
+        assert_eq!(
+            key_pair_parameter(
+                "ed25519",
+                crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_ALGORITHM
+            ),
+            crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_ED25519
+                .inner
+                .to_ne_bytes(),
+        );
+        assert_eq!(
+            key_pair_parameter(
+                "ed25519",
+                crate::AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_EC_POINT
+            ),
+            openssl2::ed25519::public_key_to_raw(&public_key).unwrap(),
+        );
+
+        // EdDSA signs the message itself, not a digest of it.
+        let message = b"message to sign";
+        let (signature_len, signature) = unsafe {
+            super::sign(
+                &locations,
+                crate::AZIOT_KEYS_SIGN_MECHANISM_EDDSA,
+                std::ptr::null(),
+                message,
+            )
+        }
+        .unwrap();
+        assert_eq!(signature_len, openssl2::ed25519::SIGNATURE_LEN);
+
+        let mut verifier = openssl::sign::Verifier::new_without_digest(&public_key).unwrap();
+        assert!(verifier.verify_oneshot(&signature, message).unwrap());
+
+        // ECDSA signing does not apply to Ed25519 keys.
+        assert_eq!(
+            unsafe {
+                super::sign(
+                    &locations,
+                    crate::AZIOT_KEYS_SIGN_MECHANISM_ECDSA,
+                    std::ptr::null(),
+                    message,
+                )
+            }
+            .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    const DIGEST_ALGORITHMS: &[(
+        crate::AZIOT_KEYS_DIGEST_ALGORITHM,
+        fn() -> openssl::hash::MessageDigest,
+    )] = &[
+        (
+            crate::AZIOT_KEYS_DIGEST_ALGORITHM_SHA256,
+            openssl::hash::MessageDigest::sha256,
+        ),
+        (
+            crate::AZIOT_KEYS_DIGEST_ALGORITHM_SHA384,
+            openssl::hash::MessageDigest::sha384,
+        ),
+        (
+            crate::AZIOT_KEYS_DIGEST_ALGORITHM_SHA512,
+            openssl::hash::MessageDigest::sha512,
+        ),
+    ];
+
+    fn digest(message_digest: openssl::hash::MessageDigest, message: &[u8]) -> Vec<u8> {
+        openssl::hash::hash(message_digest, message)
+            .unwrap()
+            .to_vec()
+    }
+
+    #[test]
+    fn sign_rsa_pkcs1() {
+        let _homedir = TestHomedir::new();
+
+        let (locations, public_key) = generate("rsa", "rsa-2048");
+
+        let message = b"message to sign";
+
+        for &(digest_algorithm, message_digest) in DIGEST_ALGORITHMS {
+            let message_digest = message_digest();
+
+            let parameters = crate::AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS { digest_algorithm };
+            let (signature_len, signature) = unsafe {
+                super::sign(
+                    &locations,
+                    crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1,
+                    (&parameters as *const crate::AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS).cast(),
+                    &digest(message_digest, message),
+                )
+            }
+            .unwrap();
+            assert_eq!(signature_len, 256);
+
+            let mut verifier = openssl::sign::Verifier::new(message_digest, &public_key).unwrap();
+            verifier
+                .set_rsa_padding(openssl::rsa::Padding::PKCS1)
+                .unwrap();
+            verifier.update(message).unwrap();
+            assert!(verifier.verify(&signature).unwrap());
+        }
+
+        // The digest must match the digest algorithm.
+        let parameters = crate::AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS {
+            digest_algorithm: crate::AZIOT_KEYS_DIGEST_ALGORITHM_SHA384,
+        };
+        assert_eq!(
+            unsafe {
+                super::sign(
+                    &locations,
+                    crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1,
+                    (&parameters as *const crate::AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS).cast(),
+                    &digest(openssl::hash::MessageDigest::sha256(), message),
+                )
+            }
+            .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        // Parameters are required.
+        assert_eq!(
+            unsafe {
+                super::sign(
+                    &locations,
+                    crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1,
+                    std::ptr::null(),
+                    &digest(openssl::hash::MessageDigest::sha256(), message),
+                )
+            }
+            .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn sign_rsa_pss() {
+        let _homedir = TestHomedir::new();
+
+        let (locations, public_key) = generate("rsa", "rsa-2048");
+
+        let message = b"message to sign";
+
+        let sign = |digest_algorithm, salt_len, digest: &[u8]| {
+            let parameters = crate::AZIOT_KEYS_SIGN_RSA_PSS_PARAMETERS {
+                digest_algorithm,
+                salt_len,
+            };
+            unsafe {
+                super::sign(
+                    &locations,
+                    crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS,
+                    (&parameters as *const crate::AZIOT_KEYS_SIGN_RSA_PSS_PARAMETERS).cast(),
+                    digest,
+                )
+            }
+        };
+
+        for &(digest_algorithm, message_digest) in DIGEST_ALGORITHMS {
+            let message_digest = message_digest();
+            let digest = digest(message_digest, message);
+
+            // No salt, a salt as long as the digest, and the longest salt that fits a 2048-bit key.
+            for &salt_len in &[0, message_digest.size(), 256 - message_digest.size() - 2] {
+                let (signature_len, signature) = sign(digest_algorithm, salt_len, &digest).unwrap();
+                assert_eq!(signature_len, 256);
+
+                let mut verifier =
+                    openssl::sign::Verifier::new(message_digest, &public_key).unwrap();
+                verifier
+                    .set_rsa_padding(openssl::rsa::Padding::PKCS1_PSS)
+                    .unwrap();
+                verifier
+                    .set_rsa_pss_saltlen(openssl::sign::RsaPssSaltlen::custom(
+                        std::convert::TryInto::try_into(salt_len).unwrap(),
+                    ))
+                    .unwrap();
+                verifier.set_rsa_mgf1_md(message_digest).unwrap();
+                verifier.update(message).unwrap();
+                assert!(verifier.verify(&signature).unwrap());
+            }
+
+            // A salt one byte longer than that does not fit.
+            assert_eq!(
+                sign(digest_algorithm, 256 - message_digest.size() - 1, &digest).unwrap_err(),
+                crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+            );
+            assert_eq!(
+                sign(digest_algorithm, usize::MAX, &digest).unwrap_err(),
+                crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+            );
+        }
+
+        let digest = digest(openssl::hash::MessageDigest::sha256(), message);
+
+        // The digest must match the digest algorithm.
+        assert_eq!(
+            sign(crate::AZIOT_KEYS_DIGEST_ALGORITHM_SHA384, 32, &digest).unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        // Unknown digest algorithms are rejected.
+        assert_eq!(
+            sign(crate::AZIOT_KEYS_DIGEST_ALGORITHM { inner: 0 }, 32, &digest).unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn move_key_pair() {
+        let _homedir = TestHomedir::new();
+
+        let (_, moved_public_key) = generate("new", "ec-p256");
+        let (to_locations, _) = generate("current", "ec-p256");
+
+        let from_id = c_string("new");
+        let to_id = c_string("current");
+        assert_eq!(
+            unsafe { super::move_key_pair(from_id.as_ptr(), to_id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        assert_eq!(
+            unsafe { super::load_key_pair(from_id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+        match super::load_inner(&to_locations).unwrap() {
+            Some(super::KeyPair::FileSystem(public_key, _)) => {
+                assert!(public_key.public_eq(&moved_public_key));
+            }
+            _ => panic!("expected moved key pair to be in the filesystem"),
+        }
+
+        let metadata = crate::implementation::load_metadata("current", &to_locations)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            metadata.origin,
+            Some(crate::implementation::Origin::Generated)
+        ));
+
+        // The key pair no longer exists with the old ID, so it can't be moved again.
+        assert_eq!(
+            unsafe { super::move_key_pair(from_id.as_ptr(), to_id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+
+        assert_eq!(
+            unsafe { super::move_key_pair(to_id.as_ptr(), to_id.as_ptr()) },
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+}
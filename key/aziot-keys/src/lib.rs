@@ -34,9 +34,10 @@
 //! that this library exports, as well as the function pointers to the key operations. See its docs for more details.
 //!
 //! All calls to [`aziot_keys_get_function_list`] or any function in [`AZIOT_KEYS_FUNCTION_LIST`] are serialized, ie a function will not be called
-//! while another function is running. However, it is not guaranteed that all function calls will be made from the same operating system thread.
-//! Thus, implementations do not need to worry about locking to prevent concurrent access, but must also not store data in thread-local storage
-//! in one function invocation and expect it to be accessible in another function invocation.
+//! while another function is running, unless the implementation advertises [`AZIOT_KEYS_CAPABILITY_THREAD_SAFE`] in its function list.
+//! However, it is not guaranteed that all function calls will be made from the same operating system thread.
+//! Thus, implementations that do not advertise thread-safety do not need to worry about locking to prevent concurrent access,
+//! but must also not store data in thread-local storage in one function invocation and expect it to be accessible in another function invocation.
 
 // DEVNOTE:
 //
@@ -73,9 +74,14 @@
 // (Incidentally, this would backfire if we did use a macro to generate the fns and enabled expansion in the cbindgen config. This is because
 // cbindgen does expansion via `rustc --pretty=expanded`, which also resolves `cfg()`s, so these fns would end up getting ignored by cbindgen too.
 
+mod aes_key_wrap;
 mod implementation;
+mod kdf;
 mod key;
+mod key_encryption_key;
 mod key_pair;
+mod stream;
+mod wrap;
 
 /// Return code of a function. This is a transparent wrapper around a `std::os::raw::c_uint` (`unsigned int`).
 ///
@@ -107,6 +113,11 @@ pub const AZIOT_KEYS_VERSION_2_0_0_0: AZIOT_KEYS_VERSION = AZIOT_KEYS_VERSION {
     inner: 0x02_00_00_00,
 };
 
+/// Version 2.1.0.0
+pub const AZIOT_KEYS_VERSION_2_1_0_0: AZIOT_KEYS_VERSION = AZIOT_KEYS_VERSION {
+    inner: 0x02_01_00_00,
+};
+
 /// The base struct of all of function lists.
 #[derive(Debug)]
 #[repr(C)]
@@ -121,7 +132,8 @@ pub struct AZIOT_KEYS_FUNCTION_LIST {
 #[derive(Debug)]
 #[repr(C)]
 pub struct AZIOT_KEYS_FUNCTION_LIST_2_0_0_0 {
-    /// The value of `base.version` must be [`AZIOT_KEYS_VERSION_2_0_0_0`].
+    /// The value of `base.version` must be [`AZIOT_KEYS_VERSION_2_0_0_0`],
+    /// unless this function list is embedded in the function list of a later API version, in which case it must be that version.
     pub base: AZIOT_KEYS_FUNCTION_LIST,
 
     /// Set a parameter on this library.
@@ -156,7 +168,8 @@ pub struct AZIOT_KEYS_FUNCTION_LIST_2_0_0_0 {
     ///
     /// `preferred_algorithms` dictates the caller's preference for the key algorithm. It is a string with components separated by COLON U+003A `:`,
     /// where each component specifies the name of an algorithm and will be attempted by the implementation in that order.
-    /// The valid components are `"ec-p256"` for secp256r1, `"rsa-2048"` for 2048-bit RSA, `"rsa-4096"` for 4096-bit RSA, and `"*"` which indicates
+    /// The valid components are `"ec-p256"` for secp256r1, `"ec-p384"` for secp384r1, `"ed25519"` for Ed25519,
+    /// `"rsa-2048"` for 2048-bit RSA, `"rsa-4096"` for 4096-bit RSA, and `"*"` which indicates
     /// any algorithm of the implementation's choice. For example, the caller might use `"ec-p256:rsa-2048:*"` to indicate that it would like
     /// the implementation to use secp256r1, else RSA-2048 if that fails, else any other algorithm of the implementation's choice if that also fails.
     ///
@@ -238,6 +251,7 @@ pub struct AZIOT_KEYS_FUNCTION_LIST_2_0_0_0 {
     /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
     ///   - `id` is `NULL`.
     ///   - `id` is invalid.
+    ///   - `usage` is empty.
     ///
     /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
     pub create_key_if_not_exists: unsafe extern "C" fn(
@@ -270,7 +284,9 @@ pub struct AZIOT_KEYS_FUNCTION_LIST_2_0_0_0 {
     /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
     ///   - `id` is `NULL`.
     ///   - `id` is invalid.
+    ///   - `id` is a pre-loaded key.
     ///   - `bytes` is `NULL`.
+    ///   - `usage` is empty.
     ///
     /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
     pub import_key: unsafe extern "C" fn(
@@ -375,8 +391,9 @@ pub struct AZIOT_KEYS_FUNCTION_LIST_2_0_0_0 {
     /// `ok` is set to 0 if the signature is invalid and non-zero if the signature is valid.
     /// The value stored in `ok` is only meaningful if the function returns `AZIOT_KEYS_RC_OK`, otherwise it must be ignored.
     ///
-    /// Note: The implementation is not required to support verification with key pairs, ie `AZIOT_KEYS_SIGN_MECHANISM_ECDSA`.
-    /// The caller can do the verification themselves with the public parameters of the EC key as obtained via `get_key_pair_parameter`.
+    /// Note: The implementation is not required to support verification with key pairs, ie `AZIOT_KEYS_SIGN_MECHANISM_ECDSA`,
+    /// `AZIOT_KEYS_SIGN_MECHANISM_EDDSA`, `AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1` and `AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS`.
+    /// The caller can do the verification themselves with the public parameters of the key pair as obtained via `get_key_pair_parameter`.
     ///
     /// # Errors
     ///
@@ -486,125 +503,725 @@ pub extern "C" fn cbindgen_unused_AZIOT_KEYS_FUNCTION_LIST_2_0_0_0(
     unimplemented!();
 }
 
-/// Get the list of functions for operations corresponding to the specified version.
-///
-/// Implementations can use this function for initialization, since it is guaranteed to be called before any operations.
-/// However it is not an error to call this function multiple times, for the same or different version,
-/// so implementations must ensure they only run their initialization once.
-///
-/// The pointer returned from this function must not be freed by the caller, and its contents must not be mutated.
-///
-/// # Errors
-///
-/// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
-///   - `version` is not recognized by this implementation.
-///   - `pfunction_list` is `NULL`.
-#[no_mangle]
-pub unsafe extern "C" fn aziot_keys_get_function_list(
-    version: AZIOT_KEYS_VERSION,
-    pfunction_list: *mut *const AZIOT_KEYS_FUNCTION_LIST,
-) -> AZIOT_KEYS_RC {
-    implementation::get_function_list(version, pfunction_list)
-}
-
-/// Used as the parameter type with `get_key_pair_parameter`.
-///
-/// One of the `AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_*` constants.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-#[repr(transparent)]
-pub struct AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE {
-    inner: std::os::raw::c_uint,
-}
-
-/// Used as the parameter type with `get_key_pair_parameter` to get the key algorithm.
-///
-/// The value returned by `get_key_pair_parameter` will be one of the `AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_*` constants.
-pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_ALGORITHM: AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE =
-    AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE { inner: 1 };
-
-/// Used as the parameter type with `get_key_pair_parameter` to get the curve OID of an EC key.
+/// The specific implementation of [`AZIOT_KEYS_FUNCTION_LIST`] for API version 2.1.0.0
 ///
-/// The value returned by `get_key_pair_parameter` will be a byte buffer containing a DER-encoded OID.
-pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_EC_CURVE_OID: AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE =
-    AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE { inner: 2 };
+/// This is a superset of API version 2.0.0.0 that adds functions to delete, enumerate, move, export and import keys and key pairs,
+/// to get the parameters of keys, to perform ECDH key agreement, to derive keys with a specific derivation mechanism,
+/// and to perform multi-part operations, as well as the capabilities of the implementation.
+/// Keys derived with a specific derivation mechanism can also be used with the [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM`]
+/// and [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM`] mechanisms, which libraries that implement this version must support.
+#[derive(Debug)]
+#[repr(C)]
+pub struct AZIOT_KEYS_FUNCTION_LIST_2_1_0_0 {
+    /// The functions from API version 2.0.0.0. The value of `v2_0_0_0.base.version` must be [`AZIOT_KEYS_VERSION_2_1_0_0`],
+    /// unless this function list is embedded in the function list of a later API version, in which case it must be that version.
+    ///
+    /// Since this is the first field, a pointer to this struct can also be used as a pointer to [`AZIOT_KEYS_FUNCTION_LIST`].
+    pub v2_0_0_0: AZIOT_KEYS_FUNCTION_LIST_2_0_0_0,
 
-/// Used as the parameter type with `get_key_pair_parameter` to get the point of an EC key.
-///
-/// The value returned by `get_key_pair_parameter` will be a byte buffer containing a DER-encoded octet string in RFC 5490 format.
-pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_EC_POINT: AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE =
-    AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE { inner: 3 };
+    /// Delete an existing key pair identified by the specified `id`.
+    ///
+    /// This function succeeds if a key pair with the given ID does not exist.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - `id` is a pre-loaded key pair.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub delete_key_pair: unsafe extern "C" fn(id: *const std::os::raw::c_char) -> AZIOT_KEYS_RC,
 
-/// Used as the parameter type with `get_key_pair_parameter` to get the modulus of an RSA key.
-///
-/// The value returned by `get_key_pair_parameter` will be a byte buffer holding a big-endian bignum.
-pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_RSA_MODULUS: AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE =
-    AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE { inner: 4 };
+    /// Delete an existing key identified by the specified `id`.
+    ///
+    /// This function succeeds if a key with the given ID does not exist.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - `id` is a pre-loaded key.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub delete_key: unsafe extern "C" fn(id: *const std::os::raw::c_char) -> AZIOT_KEYS_RC,
 
-/// Used as the parameter type with `get_key_pair_parameter` to get the exponent of an RSA key.
-///
-/// The value returned by `get_key_pair_parameter` will be a byte buffer holding a big-endian bignum.
-pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_RSA_EXPONENT: AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE =
-    AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE { inner: 5 };
+    /// Enumerate all keys and key pairs known to this library.
+    ///
+    /// This includes pre-loaded keys, keys and key pairs in the PKCS#11 base slot, and keys and key pairs in the filesystem.
+    /// Filesystem keys and key pairs that were created by an older version of this library are only included
+    /// if their ID can be recovered from their file name, or after they have been created again.
+    ///
+    /// `callback` is invoked once for each key or key pair, with `context` as its first parameter.
+    /// The `info` pointer and all the strings it points to are only valid for the duration of that invocation.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `callback` is `NULL`.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub enumerate_keys: unsafe extern "C" fn(
+        callback: AZIOT_KEYS_ENUMERATE_KEYS_CALLBACK,
+        context: *mut std::ffi::c_void,
+    ) -> AZIOT_KEYS_RC,
 
-/// The algorithm of a key pair, as returned by `get_key_pair_parameter`.
-///
-/// One of the `AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_*` constants.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-#[repr(transparent)]
-pub struct AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM {
-    inner: std::os::raw::c_uint,
-}
+    /// The capabilities of this implementation.
+    ///
+    /// A combination of the `AZIOT_KEYS_CAPABILITY_*` constants.
+    pub capabilities: AZIOT_KEYS_CAPABILITIES,
 
-/// The key pair is an EC key.
-pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_EC: AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM =
-    AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM { inner: 1 };
+    /// Get the value of a parameter of the key identified by the specified `id`.
+    ///
+    /// `type_` must be set to one of the `AZIOT_KEYS_KEY_PARAMETER_TYPE_*` constants.
+    ///
+    /// `value` is an output byte buffer allocated by the caller to store the parameter value.
+    /// The caller sets `value_len` to the address of the length of the buffer.
+    /// The implementation populates `value` with the parameter value and sets `value_len` to the number of bytes it wrote to `value`.
+    ///
+    /// It is allowed for the caller to call the function with `value` set to `NULL`. In this case the implementation calculates
+    /// an upper bound for how many bytes will be needed to store the parameter value, sets that in `value_len` and returns.
+    ///
+    /// The format of the data stored in `value` is determined by the `type_`. See the documentation of those constants for details.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - The key specified by `id` does not exist.
+    ///   - `type_` is not a valid parameter type.
+    ///   - `value` is insufficiently large to hold the parameter value.
+    ///   - `value_len` is `NULL`.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub get_key_parameter: unsafe extern "C" fn(
+        id: *const std::os::raw::c_char,
+        type_: AZIOT_KEYS_KEY_PARAMETER_TYPE,
+        value: *mut std::os::raw::c_uchar,
+        value_len: *mut usize,
+    ) -> AZIOT_KEYS_RC,
 
-/// The key pair is an RSA key.
-pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_RSA: AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM =
-    AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM { inner: 2 };
+    /// Export the key identified by the specified `id`, wrapped under the public key `wrapping_key`.
+    ///
+    /// `mechanism` must be set to one of the `AZIOT_KEYS_WRAP_MECHANISM_*` constants.
+    /// `wrapping_key` is the DER-encoded `SubjectPublicKeyInfo` of the public key to wrap the key under. Its algorithm must match the `mechanism`.
+    ///
+    /// `wrapped_key` is an output byte buffer allocated by the caller to store the wrapped key.
+    /// The caller sets `wrapped_key_len` to the address of the length of the buffer.
+    /// The implementation populates `wrapped_key` with the wrapped key and sets `wrapped_key_len` to the number of bytes it wrote to `wrapped_key`.
+    ///
+    /// It is allowed for the caller to call the function with `wrapped_key` set to `NULL`. In this case the implementation calculates
+    /// an upper bound for how many bytes will be needed to store the wrapped key, sets that in `wrapped_key_len` and returns.
+    ///
+    /// The wrapped key includes the usage of the key, and can be imported with `import_wrapped_key` using the private key of `wrapping_key`.
+    /// Keys whose usage is not known, such as pre-loaded keys, cannot be exported.
+    /// The implementation must not export keys that it cannot export securely, such as keys held in hardware that are not extractable.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - The key specified by `id` does not exist, or cannot be exported.
+    ///   - `mechanism` is not recognized by this implementation.
+    ///   - `wrapping_key` is `NULL`, invalid, or not valid for the `mechanism`.
+    ///   - `wrapped_key` is insufficiently large to hold the wrapped key.
+    ///   - `wrapped_key_len` is `NULL`.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub export_key: unsafe extern "C" fn(
+        id: *const std::os::raw::c_char,
+        mechanism: AZIOT_KEYS_WRAP_MECHANISM,
+        wrapping_key: *const std::os::raw::c_uchar,
+        wrapping_key_len: usize,
+        wrapped_key: *mut std::os::raw::c_uchar,
+        wrapped_key_len: *mut usize,
+    ) -> AZIOT_KEYS_RC,
 
-/// The usage of key being created with `create_key_if_not_exists` or
-/// being imported with `import_key`.
-///
-/// This is a bitflag type, so its values can be combined. But note that not all combinations of flags
-/// are valid.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-#[repr(transparent)]
-pub struct AZIOT_KEYS_KEY_USAGE {
-    inner: std::os::raw::c_uint,
-}
+    /// Export the private key of the key pair identified by the specified `id`, wrapped under the public key `wrapping_key`.
+    ///
+    /// The parameters have the same meaning as for `export_key`. The wrapped key pair can be imported with `import_wrapped_key_pair`.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - The key pair specified by `id` does not exist, or cannot be exported.
+    ///   - `mechanism` is not recognized by this implementation.
+    ///   - `wrapping_key` is `NULL`, invalid, or not valid for the `mechanism`.
+    ///   - `wrapped_key` is insufficiently large to hold the wrapped key pair.
+    ///   - `wrapped_key_len` is `NULL`.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub export_key_pair: unsafe extern "C" fn(
+        id: *const std::os::raw::c_char,
+        mechanism: AZIOT_KEYS_WRAP_MECHANISM,
+        wrapping_key: *const std::os::raw::c_uchar,
+        wrapping_key_len: usize,
+        wrapped_key: *mut std::os::raw::c_uchar,
+        wrapped_key_len: *mut usize,
+    ) -> AZIOT_KEYS_RC,
 
-/// The key can be used for deriving other keys.
-///
-/// Cannot be combined with [`AZIOT_KEYS_KEY_USAGE_ENCRYPT`]
-pub const AZIOT_KEYS_KEY_USAGE_DERIVE: AZIOT_KEYS_KEY_USAGE =
-    AZIOT_KEYS_KEY_USAGE { inner: 0x0001 };
+    /// Import a key that was exported with `export_key`, and save it such that it can be looked up later using the ID `id`.
+    ///
+    /// `unwrapping_key_pair_id` is the ID of the key pair whose public key the key was wrapped under.
+    /// `mechanism` must be the same mechanism that the key was exported with.
+    ///
+    /// The key is imported with the usage that it was exported with. If a key with ID `id` already exists, it is replaced.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - `id` is a pre-loaded key.
+    ///   - `unwrapping_key_pair_id` is `NULL`.
+    ///   - The key pair specified by `unwrapping_key_pair_id` does not exist, or cannot be used to unwrap keys.
+    ///   - `mechanism` is not recognized by this implementation.
+    ///   - `wrapped_key` is `NULL`, or is not a key that was wrapped with the `mechanism` under the public key of `unwrapping_key_pair_id`.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub import_wrapped_key: unsafe extern "C" fn(
+        id: *const std::os::raw::c_char,
+        unwrapping_key_pair_id: *const std::os::raw::c_char,
+        mechanism: AZIOT_KEYS_WRAP_MECHANISM,
+        wrapped_key: *const std::os::raw::c_uchar,
+        wrapped_key_len: usize,
+    ) -> AZIOT_KEYS_RC,
 
-/// The key can be used for encryption.
-///
-/// Cannot be combined with [`AZIOT_KEYS_KEY_USAGE_DERIVE`] or [`AZIOT_KEYS_KEY_USAGE_SIGN`]
-pub const AZIOT_KEYS_KEY_USAGE_ENCRYPT: AZIOT_KEYS_KEY_USAGE =
-    AZIOT_KEYS_KEY_USAGE { inner: 0x0010 };
+    /// Import a key pair that was exported with `export_key_pair`, and save it such that it can be looked up later using the ID `id`.
+    ///
+    /// The parameters have the same meaning as for `import_wrapped_key`. If a key pair with ID `id` already exists, it is replaced.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - `id` is a pre-loaded key pair.
+    ///   - `unwrapping_key_pair_id` is `NULL`.
+    ///   - The key pair specified by `unwrapping_key_pair_id` does not exist, or cannot be used to unwrap keys.
+    ///   - `mechanism` is not recognized by this implementation.
+    ///   - `wrapped_key` is `NULL`, or is not a key pair that was wrapped with the `mechanism` under the public key of `unwrapping_key_pair_id`.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub import_wrapped_key_pair: unsafe extern "C" fn(
+        id: *const std::os::raw::c_char,
+        unwrapping_key_pair_id: *const std::os::raw::c_char,
+        mechanism: AZIOT_KEYS_WRAP_MECHANISM,
+        wrapped_key: *const std::os::raw::c_uchar,
+        wrapped_key_len: usize,
+    ) -> AZIOT_KEYS_RC,
 
-/// The key can be used for signing.
-///
-/// Cannot be combined with [`AZIOT_KEYS_KEY_USAGE_ENCRYPT`]
-pub const AZIOT_KEYS_KEY_USAGE_SIGN: AZIOT_KEYS_KEY_USAGE = AZIOT_KEYS_KEY_USAGE_DERIVE;
+    /// Import a key pair from the given private key, and save it such that it can be looked up later using the ID `id`.
+    ///
+    /// `format` must be set to one of the `AZIOT_KEYS_KEY_PAIR_FORMAT_*` constants, and `bytes` must contain the private key in that format.
+    ///
+    /// `password` is the password that the private key is encrypted with. It is required for `AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS12`,
+    /// and may be set for the PKCS#8 formats if the private key is encrypted. Otherwise it must be `NULL`.
+    ///
+    /// The key pair is stored in the same location that `create_key_pair_if_not_exists` would have generated it in.
+    /// If a key pair with ID `id` already exists, it is replaced.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - `id` is a pre-loaded key pair.
+    ///   - `format` is not recognized by this implementation.
+    ///   - `bytes` is `NULL`, or is not a private key in the given `format`.
+    ///   - The private key is of a type that this implementation does not support.
+    ///   - `password` is `NULL` but the private key is encrypted, or `password` is incorrect.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub import_key_pair: unsafe extern "C" fn(
+        id: *const std::os::raw::c_char,
+        format: AZIOT_KEYS_KEY_PAIR_FORMAT,
+        bytes: *const std::os::raw::c_uchar,
+        bytes_len: usize,
+        password: *const std::os::raw::c_char,
+    ) -> AZIOT_KEYS_RC,
 
-/// The mechanism used with `sign` / `verify`.
-///
-/// One of the `AZIOT_KEYS_SIGN_MECHANISM_*` constants.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-#[repr(transparent)]
-pub struct AZIOT_KEYS_SIGN_MECHANISM {
-    inner: std::os::raw::c_uint,
-}
+    /// Perform a key agreement between the private key of the key pair identified by `id` and the peer public key `peer_public_key`,
+    /// and store the resulting secret in `secret`.
+    ///
+    /// `peer_public_key` must be a DER-encoded `SubjectPublicKeyInfo` of an EC public key on the same curve as the key pair.
+    ///
+    /// `mechanism` must be set to one of the `AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_*` constants.
+    /// `parameters` must be set based on the `mechanism`, as documented on the constants.
+    ///
+    /// If `secret` is `NULL`, `secret_len` is set to the length of the secret. Otherwise, `secret` must point to a buffer of
+    /// `secret_len` bytes, and `secret_len` is set to the number of bytes written to it.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - The key pair specified by `id` does not exist, or is not an EC key pair.
+    ///   - `mechanism` is not recognized by this implementation.
+    ///   - `parameters` is invalid.
+    ///   - `peer_public_key` is `NULL`, or is not an EC public key on the same curve as the key pair.
+    ///   - `secret` is insufficiently large to hold the result.
+    ///   - `secret_len` is `NULL`.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub derive_shared_secret: unsafe extern "C" fn(
+        id: *const std::os::raw::c_char,
+        mechanism: AZIOT_KEYS_KEY_AGREEMENT_MECHANISM,
+        parameters: *const std::ffi::c_void,
+        peer_public_key: *const std::os::raw::c_uchar,
+        peer_public_key_len: usize,
+        secret: *mut std::os::raw::c_uchar,
+        secret_len: *mut usize,
+    ) -> AZIOT_KEYS_RC,
 
-/// Used with `sign` / `verify` to sign / verify using ECDSA.
-///
-/// The `parameters` parameter of `sign` / `verify` is unused and ignored.
-pub const AZIOT_KEYS_SIGN_MECHANISM_ECDSA: AZIOT_KEYS_SIGN_MECHANISM =
-    AZIOT_KEYS_SIGN_MECHANISM { inner: 1 };
+    /// Import a symmetric key with the given `id`, unless a key with that ID already exists.
+    ///
+    /// This is identical to `import_key`, except that it fails instead of overwriting an existing key.
+    /// Checking for the existing key and importing the new one happen atomically with respect to
+    /// other calls that create, import or delete a key with the same ID.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - `id` is a pre-loaded key.
+    ///   - A key with the ID specified by `id` already exists.
+    ///   - `bytes` is `NULL`.
+    ///   - `usage` is empty.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub import_key_if_not_exists: unsafe extern "C" fn(
+        id: *const std::os::raw::c_char,
+        bytes: *const u8,
+        bytes_len: usize,
+        usage: AZIOT_KEYS_KEY_USAGE,
+    ) -> AZIOT_KEYS_RC,
+
+    /// Derive a key with a given base key using some derivation data and the given derivation mechanism, and return the derived key.
+    ///
+    /// This is identical to `derive_key`, except that the derivation process is specified by `mechanism` instead of always being
+    /// [`AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256`]. The derivation process must be identical to the one used by
+    /// `encrypt` with the `AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM` mechanism and `sign` with the
+    /// `AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM` mechanism for the same derivation mechanism and parameters.
+    ///
+    /// `mechanism` must be set to one of the `AZIOT_KEYS_KEY_DERIVATION_MECHANISM_*` constants.
+    /// `parameters` must be set based on the `mechanism`, as documented on the constants.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `base_id` is `NULL`.
+    ///   - `base_id` is invalid.
+    ///   - The key specified by `base_id` does not exist.
+    ///   - `mechanism` is not recognized by this implementation, or is not supported for the key specified by `base_id`.
+    ///   - `parameters` is invalid.
+    ///   - `derivation_data` is `NULL`.
+    ///   - `derived_key` is insufficiently large to hold the parameter value.
+    ///   - `derived_key_len` is `NULL`.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub derive_key_with_mechanism: unsafe extern "C" fn(
+        base_id: *const std::os::raw::c_char,
+        mechanism: AZIOT_KEYS_KEY_DERIVATION_MECHANISM,
+        parameters: *const std::ffi::c_void,
+        derivation_data: *const u8,
+        derivation_data_len: usize,
+        derived_key: *mut std::os::raw::c_uchar,
+        derived_key_len: *mut usize,
+    ) -> AZIOT_KEYS_RC,
+
+    /// Start a multi-part encryption using the key identified by the specified `id`.
+    ///
+    /// This is the multi-part equivalent of `encrypt`, for plaintext that is too large to be passed to `encrypt` at once.
+    /// The ciphertext produced by the multi-part operation is identical to the ciphertext that `encrypt` would produce
+    /// for the whole plaintext with the same mechanism and parameters.
+    ///
+    /// `mechanism` and `parameters` are interpreted as for `encrypt`. Only [`AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD`],
+    /// [`AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD`], [`AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR`], and [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED`]
+    /// and [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM`] with one of those mechanisms support multi-part operations. The implementation may not support
+    /// [`AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD`] for every key.
+    ///
+    /// On success, the implementation sets `stream` to a new stream. The caller passes the plaintext to `stream_update`,
+    /// completes the operation with `stream_final`, reads the ciphertext with `stream_read`, and frees the stream with `stream_free`.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - The key specified by `id` does not exist.
+    ///   - `mechanism` is not a valid encryption mechanism for the key specified by `id`, or does not support multi-part operations.
+    ///   - `parameters` is invalid.
+    ///   - `stream` is `NULL`.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub encrypt_init: unsafe extern "C" fn(
+        id: *const std::os::raw::c_char,
+        mechanism: AZIOT_KEYS_ENCRYPT_MECHANISM,
+        parameters: *const std::ffi::c_void,
+        stream: *mut *mut AZIOT_KEYS_STREAM,
+    ) -> AZIOT_KEYS_RC,
+
+    /// Start a multi-part decryption using the key identified by the specified `id`.
+    ///
+    /// This is the multi-part equivalent of `decrypt`. It accepts the same mechanisms as `encrypt_init`.
+    ///
+    /// For [`AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD`], the plaintext is only authenticated once the whole ciphertext has been received,
+    /// so none of it can be read from the stream until `stream_final` succeeds.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - The key specified by `id` does not exist.
+    ///   - `mechanism` is not a valid encryption mechanism for the key specified by `id`, or does not support multi-part operations.
+    ///   - `parameters` is invalid.
+    ///   - `stream` is `NULL`.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub decrypt_init: unsafe extern "C" fn(
+        id: *const std::os::raw::c_char,
+        mechanism: AZIOT_KEYS_ENCRYPT_MECHANISM,
+        parameters: *const std::ffi::c_void,
+        stream: *mut *mut AZIOT_KEYS_STREAM,
+    ) -> AZIOT_KEYS_RC,
+
+    /// Start a multi-part signature using the key identified by the specified `id`.
+    ///
+    /// This is the multi-part equivalent of `sign`, where the data passed to `stream_update` is treated as the `digest`.
+    /// Only [`AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256`], [`AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA384`], [`AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA512`]
+    /// and [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED`] and [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM`] with one of those mechanisms
+    /// support multi-part operations.
+    /// The signature can be read with `stream_read` after `stream_final` succeeds.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `id` is `NULL`.
+    ///   - `id` is invalid.
+    ///   - The key specified by `id` does not exist.
+    ///   - `mechanism` is not a valid signing mechanism for the key specified by `id`, or does not support multi-part operations.
+    ///   - `parameters` is invalid.
+    ///   - `stream` is `NULL`.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub sign_init: unsafe extern "C" fn(
+        id: *const std::os::raw::c_char,
+        mechanism: AZIOT_KEYS_SIGN_MECHANISM,
+        parameters: *const std::ffi::c_void,
+        stream: *mut *mut AZIOT_KEYS_STREAM,
+    ) -> AZIOT_KEYS_RC,
+
+    /// Pass the next part of the input of the multi-part operation to the given stream.
+    ///
+    /// Any output produced by the operation is kept in the stream until it is read with `stream_read`.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `stream` is `NULL`.
+    ///   - `stream_final` has already been called for `stream`.
+    ///   - `data` is `NULL`.
+    ///   - `data` is invalid for the operation.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub stream_update: unsafe extern "C" fn(
+        stream: *mut AZIOT_KEYS_STREAM,
+        data: *const std::os::raw::c_uchar,
+        data_len: usize,
+    ) -> AZIOT_KEYS_RC,
+
+    /// Complete the multi-part operation of the given stream.
+    ///
+    /// Any remaining output produced by the operation is kept in the stream until it is read with `stream_read`.
+    /// No more input can be passed to the stream after this function has been called, even if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `stream` is `NULL`.
+    ///   - `stream_final` has already been called for `stream`.
+    ///   - The input passed to the stream is invalid for the operation, such as a decryption whose ciphertext fails to authenticate.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub stream_final: unsafe extern "C" fn(stream: *mut AZIOT_KEYS_STREAM) -> AZIOT_KEYS_RC,
+
+    /// Read the output that the multi-part operation of the given stream has produced so far.
+    ///
+    /// `output` is an output byte buffer allocated by the caller to store the output.
+    /// The caller sets `output_len` to the address of the length of the buffer.
+    /// The implementation populates `output` with the output, sets `output_len` to the number of bytes it wrote to `output`,
+    /// and removes those bytes from the stream.
+    ///
+    /// It is allowed for the caller to call the function with `output` set to `NULL`. In this case the implementation sets
+    /// `output_len` to the number of bytes of output available in the stream and returns.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `stream` is `NULL`.
+    ///   - `output` is insufficiently large to hold the output.
+    ///   - `output_len` is `NULL`.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub stream_read: unsafe extern "C" fn(
+        stream: *mut AZIOT_KEYS_STREAM,
+        output: *mut std::os::raw::c_uchar,
+        output_len: *mut usize,
+    ) -> AZIOT_KEYS_RC,
+
+    /// Free the given stream, abandoning its multi-part operation if it has not been completed.
+    ///
+    /// `stream` may be `NULL`, in which case this function does nothing.
+    pub stream_free: unsafe extern "C" fn(stream: *mut AZIOT_KEYS_STREAM),
+
+    /// Move the key pair identified by `from_id` so that it is identified by `to_id` instead.
+    ///
+    /// If a key pair with `to_id` already exists, it is replaced. After this function succeeds, the key pair no longer exists with `from_id`.
+    ///
+    /// The private key never leaves the storage it was created in. In particular, a key pair that is stored in an HSM stays in the same HSM,
+    /// so `from_id` and `to_id` must both be stored in it.
+    ///
+    /// # Errors
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+    ///   - `from_id` is `NULL`.
+    ///   - `from_id` is invalid.
+    ///   - The key pair specified by `from_id` does not exist.
+    ///   - `to_id` is `NULL`.
+    ///   - `to_id` is invalid.
+    ///   - `from_id` or `to_id` identifies a preloaded key pair.
+    ///   - `from_id` and `to_id` are stored in different locations, such as one on the filesystem and one in an HSM.
+    ///
+    /// - `AZIOT_KEYS_RC_ERR_EXTERNAL`
+    pub move_key_pair: unsafe extern "C" fn(
+        from_id: *const std::os::raw::c_char,
+        to_id: *const std::os::raw::c_char,
+    ) -> AZIOT_KEYS_RC,
+}
+
+#[cfg(any())]
+#[no_mangle]
+pub extern "C" fn cbindgen_unused_AZIOT_KEYS_FUNCTION_LIST_2_1_0_0(
+) -> AZIOT_KEYS_FUNCTION_LIST_2_1_0_0 {
+    unimplemented!();
+}
+
+/// A multi-part operation started by `encrypt_init`, `decrypt_init` or `sign_init`.
+///
+/// The contents of this type are private to the implementation. The caller only ever handles pointers to it.
+pub struct AZIOT_KEYS_STREAM {
+    inner: stream::Stream,
+}
+
+/// The capabilities of an implementation, as reported in [`AZIOT_KEYS_FUNCTION_LIST_2_1_0_0`].
+///
+/// This is a bitflag type, so its values can be combined.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_CAPABILITIES {
+    inner: std::os::raw::c_uint,
+}
+
+/// The implementation has no optional capabilities.
+pub const AZIOT_KEYS_CAPABILITIES_NONE: AZIOT_KEYS_CAPABILITIES =
+    AZIOT_KEYS_CAPABILITIES { inner: 0x0000 };
+
+/// The functions of the implementation can be called concurrently from multiple threads.
+///
+/// If an implementation does not declare this capability, the caller must ensure that only one of its functions
+/// is executing at any time.
+pub const AZIOT_KEYS_CAPABILITY_THREAD_SAFE: AZIOT_KEYS_CAPABILITIES =
+    AZIOT_KEYS_CAPABILITIES { inner: 0x0001 };
+
+/// Get the list of functions for operations corresponding to the specified version.
+///
+/// Implementations can use this function for initialization, since it is guaranteed to be called before any operations.
+/// However it is not an error to call this function multiple times, for the same or different version,
+/// so implementations must ensure they only run their initialization once.
+///
+/// The pointer returned from this function must not be freed by the caller, and its contents must not be mutated.
+///
+/// # Errors
+///
+/// - `AZIOT_KEYS_RC_ERR_INVALID_PARAMETER`:
+///   - `version` is not recognized by this implementation.
+///   - `pfunction_list` is `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn aziot_keys_get_function_list(
+    version: AZIOT_KEYS_VERSION,
+    pfunction_list: *mut *const AZIOT_KEYS_FUNCTION_LIST,
+) -> AZIOT_KEYS_RC {
+    implementation::get_function_list(version, pfunction_list)
+}
+
+/// Used as the parameter type with `get_key_pair_parameter`.
+///
+/// One of the `AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_*` constants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE {
+    inner: std::os::raw::c_uint,
+}
+
+/// Used as the parameter type with `get_key_pair_parameter` to get the key algorithm.
+///
+/// The value returned by `get_key_pair_parameter` will be one of the `AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_*` constants.
+pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_ALGORITHM: AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE =
+    AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE { inner: 1 };
+
+/// Used as the parameter type with `get_key_pair_parameter` to get the curve OID of an EC key.
+///
+/// The value returned by `get_key_pair_parameter` will be a byte buffer containing a DER-encoded OID.
+/// For Ed25519 keys, this is the OID of the Ed25519 algorithm itself.
+pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_EC_CURVE_OID: AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE =
+    AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE { inner: 2 };
+
+/// Used as the parameter type with `get_key_pair_parameter` to get the point of an EC key.
+///
+/// The value returned by `get_key_pair_parameter` will be a byte buffer containing a DER-encoded octet string in RFC 5490 format.
+/// For Ed25519 keys, it will instead be the 32-byte raw public key in RFC 8032 format.
+pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_EC_POINT: AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE =
+    AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE { inner: 3 };
+
+/// Used as the parameter type with `get_key_pair_parameter` to get the modulus of an RSA key.
+///
+/// The value returned by `get_key_pair_parameter` will be a byte buffer holding a big-endian bignum.
+pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_RSA_MODULUS: AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE =
+    AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE { inner: 4 };
+
+/// Used as the parameter type with `get_key_pair_parameter` to get the exponent of an RSA key.
+///
+/// The value returned by `get_key_pair_parameter` will be a byte buffer holding a big-endian bignum.
+pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE_RSA_EXPONENT: AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE =
+    AZIOT_KEYS_KEY_PAIR_PARAMETER_TYPE { inner: 5 };
+
+/// Used as the parameter type with `get_key_parameter`.
+///
+/// One of the `AZIOT_KEYS_KEY_PARAMETER_TYPE_*` constants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_KEY_PARAMETER_TYPE {
+    inner: std::os::raw::c_uint,
+}
+
+/// Used as the parameter type with `get_key_parameter` to get the usage of a key.
+///
+/// The value returned by `get_key_parameter` will be a combination of the `AZIOT_KEYS_KEY_USAGE_*` constants.
+/// It will be zero if the usage of the key is not known, such as for pre-loaded keys; such keys can be used for any operation.
+pub const AZIOT_KEYS_KEY_PARAMETER_TYPE_USAGE: AZIOT_KEYS_KEY_PARAMETER_TYPE =
+    AZIOT_KEYS_KEY_PARAMETER_TYPE { inner: 1 };
+
+/// Used as the parameter type with `get_key_parameter` to get how a key was created.
+///
+/// The value returned by `get_key_parameter` will be one of the `AZIOT_KEYS_KEY_ORIGIN_*` constants.
+pub const AZIOT_KEYS_KEY_PARAMETER_TYPE_ORIGIN: AZIOT_KEYS_KEY_PARAMETER_TYPE =
+    AZIOT_KEYS_KEY_PARAMETER_TYPE { inner: 2 };
+
+/// Used as the parameter type with `get_key_parameter` to get when a key was created.
+///
+/// The value returned by `get_key_parameter` will be a `uint64_t` holding the number of seconds since the Unix epoch.
+/// It will be zero if the creation time of the key is not known.
+pub const AZIOT_KEYS_KEY_PARAMETER_TYPE_CREATION_TIME: AZIOT_KEYS_KEY_PARAMETER_TYPE =
+    AZIOT_KEYS_KEY_PARAMETER_TYPE { inner: 3 };
+
+/// How a key was created, as returned by `get_key_parameter`.
+///
+/// One of the `AZIOT_KEYS_KEY_ORIGIN_*` constants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_KEY_ORIGIN {
+    inner: std::os::raw::c_uint,
+}
+
+/// It is not known how the key was created.
+pub const AZIOT_KEYS_KEY_ORIGIN_UNKNOWN: AZIOT_KEYS_KEY_ORIGIN = AZIOT_KEYS_KEY_ORIGIN { inner: 0 };
+
+/// The key was generated by the implementation or by the hardware that stores it, so its value was never known to the caller.
+pub const AZIOT_KEYS_KEY_ORIGIN_GENERATED: AZIOT_KEYS_KEY_ORIGIN =
+    AZIOT_KEYS_KEY_ORIGIN { inner: 1 };
+
+/// The key was imported, such as with `import_key`.
+pub const AZIOT_KEYS_KEY_ORIGIN_IMPORTED: AZIOT_KEYS_KEY_ORIGIN =
+    AZIOT_KEYS_KEY_ORIGIN { inner: 2 };
+
+#[cfg(any())]
+#[no_mangle]
+pub extern "C" fn cbindgen_unused_AZIOT_KEYS_KEY_ORIGIN() -> AZIOT_KEYS_KEY_ORIGIN {
+    unimplemented!();
+}
+
+/// The algorithm of a key pair, as returned by `get_key_pair_parameter`.
+///
+/// One of the `AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_*` constants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM {
+    inner: std::os::raw::c_uint,
+}
+
+/// The key pair is an EC key.
+pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_EC: AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM =
+    AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM { inner: 1 };
+
+/// The key pair is an RSA key.
+pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_RSA: AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM =
+    AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM { inner: 2 };
+
+/// The key pair is an Ed25519 key.
+pub const AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_ED25519: AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM =
+    AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM { inner: 3 };
+
+/// The usage of key being created with `create_key_if_not_exists` or
+/// being imported with `import_key`.
+///
+/// This is a bitflag type, so its values can be combined. But note that not all combinations of flags
+/// are valid.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_KEY_USAGE {
+    inner: std::os::raw::c_uint,
+}
+
+/// The key can be used for deriving other keys.
+///
+/// Cannot be combined with [`AZIOT_KEYS_KEY_USAGE_ENCRYPT`]
+pub const AZIOT_KEYS_KEY_USAGE_DERIVE: AZIOT_KEYS_KEY_USAGE =
+    AZIOT_KEYS_KEY_USAGE { inner: 0x0001 };
+
+/// The key can be used for encryption.
+///
+/// Cannot be combined with [`AZIOT_KEYS_KEY_USAGE_DERIVE`] or [`AZIOT_KEYS_KEY_USAGE_SIGN`]
+pub const AZIOT_KEYS_KEY_USAGE_ENCRYPT: AZIOT_KEYS_KEY_USAGE =
+    AZIOT_KEYS_KEY_USAGE { inner: 0x0010 };
+
+/// The key can be used for signing.
+///
+/// Cannot be combined with [`AZIOT_KEYS_KEY_USAGE_ENCRYPT`]
+pub const AZIOT_KEYS_KEY_USAGE_SIGN: AZIOT_KEYS_KEY_USAGE = AZIOT_KEYS_KEY_USAGE_DERIVE;
+
+/// The mechanism used with `sign` / `verify`.
+///
+/// One of the `AZIOT_KEYS_SIGN_MECHANISM_*` constants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_SIGN_MECHANISM {
+    inner: std::os::raw::c_uint,
+}
+
+/// Used with `sign` / `verify` to sign / verify using ECDSA.
+///
+/// The `parameters` parameter of `sign` / `verify` is unused and ignored.
+pub const AZIOT_KEYS_SIGN_MECHANISM_ECDSA: AZIOT_KEYS_SIGN_MECHANISM =
+    AZIOT_KEYS_SIGN_MECHANISM { inner: 1 };
 
 /// Used with `sign` / `verify` to sign / verify using HMAC-SHA256.
 ///
@@ -619,6 +1236,112 @@ pub const AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256: AZIOT_KEYS_SIGN_MECHANISM =
 pub const AZIOT_KEYS_SIGN_MECHANISM_DERIVED: AZIOT_KEYS_SIGN_MECHANISM =
     AZIOT_KEYS_SIGN_MECHANISM { inner: 3 };
 
+/// Used with `sign` to sign using EdDSA (Ed25519).
+///
+/// Unlike the other mechanisms, the `digest` parameter of `sign` is the message itself, not a digest of it.
+///
+/// The `parameters` parameter of `sign` is unused and ignored.
+pub const AZIOT_KEYS_SIGN_MECHANISM_EDDSA: AZIOT_KEYS_SIGN_MECHANISM =
+    AZIOT_KEYS_SIGN_MECHANISM { inner: 4 };
+
+/// Used with `sign` to sign using RSASSA-PKCS1-v1_5.
+///
+/// The `digest` parameter of `sign` is the digest of the message, computed by the caller with the digest algorithm
+/// specified in `parameters`. The implementation wraps it in a `DigestInfo` before signing it.
+///
+/// The `parameters` parameter of `sign` must be set to an `AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS` value.
+pub const AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1: AZIOT_KEYS_SIGN_MECHANISM =
+    AZIOT_KEYS_SIGN_MECHANISM { inner: 5 };
+
+/// Used with `sign` to sign using RSASSA-PSS.
+///
+/// The `digest` parameter of `sign` is the digest of the message, computed by the caller with the digest algorithm
+/// specified in `parameters`. MGF1 uses the same digest algorithm.
+///
+/// The `parameters` parameter of `sign` must be set to an `AZIOT_KEYS_SIGN_RSA_PSS_PARAMETERS` value.
+pub const AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS: AZIOT_KEYS_SIGN_MECHANISM =
+    AZIOT_KEYS_SIGN_MECHANISM { inner: 6 };
+
+/// Used with `sign` / `verify` to sign / verify using HMAC-SHA384.
+///
+/// The `parameters` parameter of `sign` / `verify` is unused and ignored.
+pub const AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA384: AZIOT_KEYS_SIGN_MECHANISM =
+    AZIOT_KEYS_SIGN_MECHANISM { inner: 7 };
+
+/// Used with `sign` / `verify` to sign / verify using HMAC-SHA512.
+///
+/// The `parameters` parameter of `sign` / `verify` is unused and ignored.
+pub const AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA512: AZIOT_KEYS_SIGN_MECHANISM =
+    AZIOT_KEYS_SIGN_MECHANISM { inner: 8 };
+
+/// Used with `sign` / `verify` to sign / verify using a key derived with a specific derivation mechanism.
+///
+/// This is identical to [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED`], except that the derivation mechanism is specified by
+/// the parameters instead of always being [`AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256`]. Added in API version 2.1.0.0.
+///
+/// The `id` parameter of `sign` / `verify` is set to the ID of the base key.
+/// The `parameters` parameter of `sign` / `verify` must be set to an `AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS` value.
+pub const AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM: AZIOT_KEYS_SIGN_MECHANISM =
+    AZIOT_KEYS_SIGN_MECHANISM { inner: 9 };
+
+/// The digest algorithm used to compute the digest passed to `sign`.
+///
+/// One of the `AZIOT_KEYS_DIGEST_ALGORITHM_*` constants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_DIGEST_ALGORITHM {
+    inner: std::os::raw::c_uint,
+}
+
+/// SHA-256
+pub const AZIOT_KEYS_DIGEST_ALGORITHM_SHA256: AZIOT_KEYS_DIGEST_ALGORITHM =
+    AZIOT_KEYS_DIGEST_ALGORITHM { inner: 1 };
+
+/// SHA-384
+pub const AZIOT_KEYS_DIGEST_ALGORITHM_SHA384: AZIOT_KEYS_DIGEST_ALGORITHM =
+    AZIOT_KEYS_DIGEST_ALGORITHM { inner: 2 };
+
+/// SHA-512
+pub const AZIOT_KEYS_DIGEST_ALGORITHM_SHA512: AZIOT_KEYS_DIGEST_ALGORITHM =
+    AZIOT_KEYS_DIGEST_ALGORITHM { inner: 3 };
+
+/// Used with `sign` with the [`AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1`] mechanism.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub struct AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS {
+    /// The digest algorithm that was used to compute the digest.
+    ///
+    /// One of the `AZIOT_KEYS_DIGEST_ALGORITHM_*` constants.
+    pub digest_algorithm: AZIOT_KEYS_DIGEST_ALGORITHM,
+}
+
+#[cfg(any())]
+#[no_mangle]
+pub extern "C" fn cbindgen_unused_AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS(
+) -> AZIOT_KEYS_SIGN_RSA_PKCS1_PARAMETERS {
+    unimplemented!();
+}
+
+/// Used with `sign` with the [`AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS`] mechanism.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub struct AZIOT_KEYS_SIGN_RSA_PSS_PARAMETERS {
+    /// The digest algorithm that was used to compute the digest.
+    ///
+    /// One of the `AZIOT_KEYS_DIGEST_ALGORITHM_*` constants.
+    pub digest_algorithm: AZIOT_KEYS_DIGEST_ALGORITHM,
+
+    /// The length of the salt, in bytes.
+    pub salt_len: usize,
+}
+
+#[cfg(any())]
+#[no_mangle]
+pub extern "C" fn cbindgen_unused_AZIOT_KEYS_SIGN_RSA_PSS_PARAMETERS(
+) -> AZIOT_KEYS_SIGN_RSA_PSS_PARAMETERS {
+    unimplemented!();
+}
+
 /// Used with `sign` / `verify` with the [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED`] mechanism.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[repr(C)]
@@ -645,6 +1368,217 @@ pub extern "C" fn cbindgen_unused_AZIOT_KEYS_SIGN_DERIVED_PARAMETERS(
     unimplemented!();
 }
 
+/// Used with `sign` / `verify` with the [`AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM`] mechanism.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub struct AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS {
+    /// The data used to derive the new key.
+    pub derivation_data: *const std::os::raw::c_uchar,
+
+    /// The length of the `derivation_data` buffer.
+    pub derivation_data_len: usize,
+
+    /// The signature mechanism to use with the derived key.
+    ///
+    /// One of the `AZIOT_KEYS_SIGN_MECHANISM_*` constants.
+    pub mechanism: AZIOT_KEYS_SIGN_MECHANISM,
+
+    /// The parameters of the signature mechanism specified by `mechanism`.
+    pub parameters: *const std::ffi::c_void,
+
+    /// The mechanism used to derive the new key from the base key.
+    ///
+    /// One of the `AZIOT_KEYS_KEY_DERIVATION_MECHANISM_*` constants.
+    pub derivation_mechanism: AZIOT_KEYS_KEY_DERIVATION_MECHANISM,
+
+    /// The parameters of the derivation mechanism specified by `derivation_mechanism`.
+    pub derivation_parameters: *const std::ffi::c_void,
+}
+
+#[cfg(any())]
+#[no_mangle]
+pub extern "C" fn cbindgen_unused_AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS(
+) -> AZIOT_KEYS_SIGN_DERIVED_WITH_MECHANISM_PARAMETERS {
+    unimplemented!();
+}
+
+/// The format of the private key passed to `import_key_pair`.
+///
+/// One of the `AZIOT_KEYS_KEY_PAIR_FORMAT_*` constants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_KEY_PAIR_FORMAT {
+    inner: std::os::raw::c_uint,
+}
+
+/// A PEM-encoded PKCS#8 `PrivateKeyInfo` or `EncryptedPrivateKeyInfo`. Traditional (PKCS#1 or SEC1) PEM private keys are also accepted.
+pub const AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_PEM: AZIOT_KEYS_KEY_PAIR_FORMAT =
+    AZIOT_KEYS_KEY_PAIR_FORMAT { inner: 1 };
+
+/// A DER-encoded PKCS#8 `PrivateKeyInfo` or `EncryptedPrivateKeyInfo`.
+pub const AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS8_DER: AZIOT_KEYS_KEY_PAIR_FORMAT =
+    AZIOT_KEYS_KEY_PAIR_FORMAT { inner: 2 };
+
+/// A DER-encoded PKCS#12 archive containing a private key. Any certificates in the archive are ignored.
+pub const AZIOT_KEYS_KEY_PAIR_FORMAT_PKCS12: AZIOT_KEYS_KEY_PAIR_FORMAT =
+    AZIOT_KEYS_KEY_PAIR_FORMAT { inner: 3 };
+
+/// The mechanism used with `export_key` / `export_key_pair` and `import_wrapped_key` / `import_wrapped_key_pair`.
+///
+/// One of the `AZIOT_KEYS_WRAP_MECHANISM_*` constants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_WRAP_MECHANISM {
+    inner: std::os::raw::c_uint,
+}
+
+/// Wrap the key with a random content-encryption key, which is itself encrypted with the RSA wrapping key using RSA-OAEP with SHA-256.
+pub const AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP: AZIOT_KEYS_WRAP_MECHANISM =
+    AZIOT_KEYS_WRAP_MECHANISM { inner: 1 };
+
+/// Wrap the key with a content-encryption key derived from an ECDH key agreement between an ephemeral key and the EC wrapping key.
+pub const AZIOT_KEYS_WRAP_MECHANISM_ECDH: AZIOT_KEYS_WRAP_MECHANISM =
+    AZIOT_KEYS_WRAP_MECHANISM { inner: 2 };
+
+/// The mechanism used with `derive_shared_secret`.
+///
+/// One of the `AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_*` constants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_KEY_AGREEMENT_MECHANISM {
+    inner: std::os::raw::c_uint,
+}
+
+/// ECDH. The secret is the raw shared secret, ie the X coordinate of the shared point.
+///
+/// The `parameters` parameter of `derive_shared_secret` must be `NULL`.
+pub const AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_ECDH: AZIOT_KEYS_KEY_AGREEMENT_MECHANISM =
+    AZIOT_KEYS_KEY_AGREEMENT_MECHANISM { inner: 1 };
+
+/// ECDH followed by HKDF. The secret is the output of HKDF with the raw shared secret as the input keying material.
+///
+/// The `parameters` parameter of `derive_shared_secret` must be set to a pointer to an [`AZIOT_KEYS_ECDH_HKDF_PARAMETERS`] value.
+pub const AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_ECDH_HKDF: AZIOT_KEYS_KEY_AGREEMENT_MECHANISM =
+    AZIOT_KEYS_KEY_AGREEMENT_MECHANISM { inner: 2 };
+
+/// Used with `derive_shared_secret` with the [`AZIOT_KEYS_KEY_AGREEMENT_MECHANISM_ECDH_HKDF`] mechanism.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub struct AZIOT_KEYS_ECDH_HKDF_PARAMETERS {
+    /// The digest algorithm used by HKDF.
+    ///
+    /// One of the `AZIOT_KEYS_DIGEST_ALGORITHM_*` constants.
+    pub digest_algorithm: AZIOT_KEYS_DIGEST_ALGORITHM,
+
+    /// The HKDF salt. May be `NULL` if `salt_len` is 0, in which case a string of zeros of the digest's length is used.
+    pub salt: *const std::os::raw::c_uchar,
+
+    /// The length of the `salt` buffer.
+    pub salt_len: usize,
+
+    /// The HKDF info. May be `NULL` if `info_len` is 0.
+    pub info: *const std::os::raw::c_uchar,
+
+    /// The length of the `info` buffer.
+    pub info_len: usize,
+
+    /// The length of the secret to derive, in bytes. Must be at most 255 times the digest's length.
+    pub output_len: usize,
+}
+
+#[cfg(any())]
+#[no_mangle]
+pub extern "C" fn cbindgen_unused_AZIOT_KEYS_ECDH_HKDF_PARAMETERS(
+) -> AZIOT_KEYS_ECDH_HKDF_PARAMETERS {
+    unimplemented!();
+}
+
+/// The mechanism used to derive a key from a base key, with `derive_key_with_mechanism` and
+/// the `AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM` / `AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM` mechanisms.
+///
+/// One of the `AZIOT_KEYS_KEY_DERIVATION_MECHANISM_*` constants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_KEY_DERIVATION_MECHANISM {
+    inner: std::os::raw::c_uint,
+}
+
+/// The derived key is HMAC-SHA256(base key, derivation data). This is the mechanism used by `derive_key`.
+///
+/// The derivation parameters are unused and ignored.
+pub const AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256: AZIOT_KEYS_KEY_DERIVATION_MECHANISM =
+    AZIOT_KEYS_KEY_DERIVATION_MECHANISM { inner: 0 };
+
+/// The derived key is the output of RFC 5869 HKDF, with the base key as the input keying material and the derivation data as the info.
+///
+/// The derivation parameters must be set to a pointer to an [`AZIOT_KEYS_KEY_DERIVATION_HKDF_PARAMETERS`] value.
+///
+/// Not supported for base keys stored in PKCS#11, since HKDF uses the base key as the message of the extract step rather than as an HMAC key.
+pub const AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HKDF: AZIOT_KEYS_KEY_DERIVATION_MECHANISM =
+    AZIOT_KEYS_KEY_DERIVATION_MECHANISM { inner: 1 };
+
+/// The derived key is the output of the NIST SP 800-108 KDF in counter mode, with HMAC as the PRF keyed with the base key,
+/// and the derivation data as the label.
+///
+/// The input to the PRF for each block is `[i]_32 || label || 0x00 || context || [L]_32`, where `i` is the 1-based block counter
+/// and `L` is the length of the derived key in bits, both encoded as 32-bit big-endian integers.
+///
+/// The derivation parameters must be set to a pointer to an [`AZIOT_KEYS_KEY_DERIVATION_KBKDF_PARAMETERS`] value.
+pub const AZIOT_KEYS_KEY_DERIVATION_MECHANISM_KBKDF_COUNTER: AZIOT_KEYS_KEY_DERIVATION_MECHANISM =
+    AZIOT_KEYS_KEY_DERIVATION_MECHANISM { inner: 2 };
+
+/// Used with the [`AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HKDF`] derivation mechanism.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub struct AZIOT_KEYS_KEY_DERIVATION_HKDF_PARAMETERS {
+    /// The digest algorithm used by HKDF.
+    ///
+    /// One of the `AZIOT_KEYS_DIGEST_ALGORITHM_*` constants.
+    pub digest_algorithm: AZIOT_KEYS_DIGEST_ALGORITHM,
+
+    /// The HKDF salt. May be `NULL` if `salt_len` is 0, in which case a string of zeros of the digest's length is used.
+    pub salt: *const std::os::raw::c_uchar,
+
+    /// The length of the `salt` buffer.
+    pub salt_len: usize,
+
+    /// The length of the key to derive, in bytes. Must be non-zero and at most 1024.
+    pub output_len: usize,
+}
+
+#[cfg(any())]
+#[no_mangle]
+pub extern "C" fn cbindgen_unused_AZIOT_KEYS_KEY_DERIVATION_HKDF_PARAMETERS(
+) -> AZIOT_KEYS_KEY_DERIVATION_HKDF_PARAMETERS {
+    unimplemented!();
+}
+
+/// Used with the [`AZIOT_KEYS_KEY_DERIVATION_MECHANISM_KBKDF_COUNTER`] derivation mechanism.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub struct AZIOT_KEYS_KEY_DERIVATION_KBKDF_PARAMETERS {
+    /// The digest algorithm of the HMAC PRF.
+    ///
+    /// One of the `AZIOT_KEYS_DIGEST_ALGORITHM_*` constants.
+    pub digest_algorithm: AZIOT_KEYS_DIGEST_ALGORITHM,
+
+    /// The context. May be `NULL` if `context_len` is 0.
+    pub context: *const std::os::raw::c_uchar,
+
+    /// The length of the `context` buffer.
+    pub context_len: usize,
+
+    /// The length of the key to derive, in bytes. Must be non-zero and at most 1024.
+    pub output_len: usize,
+}
+
+#[cfg(any())]
+#[no_mangle]
+pub extern "C" fn cbindgen_unused_AZIOT_KEYS_KEY_DERIVATION_KBKDF_PARAMETERS(
+) -> AZIOT_KEYS_KEY_DERIVATION_KBKDF_PARAMETERS {
+    unimplemented!();
+}
+
 /// The mechanism used with `encrypt` / `decrypt`.
 ///
 /// One of the `AZIOT_KEYS_ENCRYPT_MECHANISM_*` constants.
@@ -682,6 +1616,51 @@ pub const AZIOT_KEYS_ENCRYPT_MECHANISM_RSA_NO_PADDING: AZIOT_KEYS_ENCRYPT_MECHAN
 pub const AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED: AZIOT_KEYS_ENCRYPT_MECHANISM =
     AZIOT_KEYS_ENCRYPT_MECHANISM { inner: 4 };
 
+/// Used with `encrypt` / `decrypt` to encrypt / decrypt using AES-CBC with PKCS#7 padding.
+///
+/// The AES key size is that of the key, so the key must be 16, 24 or 32 bytes. The ciphertext has no header or trailer,
+/// so it can be exchanged with other AES-CBC implementations.
+///
+/// The `parameters` parameter of `encrypt` / `decrypt` must be set to an `AZIOT_KEYS_ENCRYPT_IV_PARAMETERS` value.
+pub const AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD: AZIOT_KEYS_ENCRYPT_MECHANISM =
+    AZIOT_KEYS_ENCRYPT_MECHANISM { inner: 5 };
+
+/// Used with `encrypt` / `decrypt` to encrypt / decrypt using AES-CTR.
+///
+/// The IV is the initial 16-byte counter block, and the whole block is incremented as a 128-bit big-endian counter.
+/// The AES key size is that of the key, so the key must be 16, 24 or 32 bytes.
+///
+/// The `parameters` parameter of `encrypt` / `decrypt` must be set to an `AZIOT_KEYS_ENCRYPT_IV_PARAMETERS` value.
+pub const AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR: AZIOT_KEYS_ENCRYPT_MECHANISM =
+    AZIOT_KEYS_ENCRYPT_MECHANISM { inner: 6 };
+
+/// Used with `encrypt` / `decrypt` to wrap / unwrap key material using the RFC 3394 AES key wrap algorithm with the default IV.
+///
+/// The plaintext must be a multiple of 8 bytes and at least 16 bytes long.
+/// The AES key size is that of the key, so the key must be 16, 24 or 32 bytes.
+///
+/// The `parameters` parameter of `encrypt` / `decrypt` is unused and ignored.
+pub const AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP: AZIOT_KEYS_ENCRYPT_MECHANISM =
+    AZIOT_KEYS_ENCRYPT_MECHANISM { inner: 7 };
+
+/// Used with `encrypt` / `decrypt` to wrap / unwrap key material of any non-zero length using the RFC 5649 AES key wrap with padding algorithm.
+///
+/// The AES key size is that of the key, so the key must be 16, 24 or 32 bytes.
+///
+/// The `parameters` parameter of `encrypt` / `decrypt` is unused and ignored.
+pub const AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP_PAD: AZIOT_KEYS_ENCRYPT_MECHANISM =
+    AZIOT_KEYS_ENCRYPT_MECHANISM { inner: 8 };
+
+/// Used with `encrypt` / `decrypt` to encrypt / decrypt using a key derived with a specific derivation mechanism.
+///
+/// This is identical to [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED`], except that the derivation mechanism is specified by
+/// the parameters instead of always being [`AZIOT_KEYS_KEY_DERIVATION_MECHANISM_HMAC_SHA256`]. Added in API version 2.1.0.0.
+///
+/// The `id` parameter of `encrypt` / `decrypt` is set to the ID of the base key.
+/// The `parameters` parameter of `encrypt` / `decrypt` must be set to an `AZIOT_KEYS_ENCRYPT_DERIVED_WITH_MECHANISM_PARAMETERS` value.
+pub const AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM: AZIOT_KEYS_ENCRYPT_MECHANISM =
+    AZIOT_KEYS_ENCRYPT_MECHANISM { inner: 9 };
+
 /// Used with `encrypt` / `decrypt` with the [`AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD`] mechanism.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[repr(C)]
@@ -706,6 +1685,24 @@ pub extern "C" fn cbindgen_unused_AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS(
     unimplemented!();
 }
 
+/// Used with `encrypt` / `decrypt` with the [`AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD`] and [`AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR`] mechanisms.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub struct AZIOT_KEYS_ENCRYPT_IV_PARAMETERS {
+    /// The IV. Must be 16 bytes.
+    pub iv: *const std::os::raw::c_uchar,
+
+    /// The length of the `iv` buffer.
+    pub iv_len: usize,
+}
+
+#[cfg(any())]
+#[no_mangle]
+pub extern "C" fn cbindgen_unused_AZIOT_KEYS_ENCRYPT_IV_PARAMETERS(
+) -> AZIOT_KEYS_ENCRYPT_IV_PARAMETERS {
+    unimplemented!();
+}
+
 /// Used with `encrypt` / `decrypt` with the [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED`] mechanism.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[repr(C)]
@@ -732,6 +1729,89 @@ pub extern "C" fn cbindgen_unused_AZIOT_KEYS_ENCRYPT_DERIVED_PARAMETERS(
     unimplemented!();
 }
 
+/// Used with `encrypt` / `decrypt` with the [`AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM`] mechanism.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub struct AZIOT_KEYS_ENCRYPT_DERIVED_WITH_MECHANISM_PARAMETERS {
+    /// The data used to derive the new key.
+    pub derivation_data: *const std::os::raw::c_uchar,
+
+    /// The length of the `derivation_data` buffer.
+    pub derivation_data_len: usize,
+
+    /// The encryption mechanism to use with the derived key.
+    ///
+    /// One of the `AZIOT_KEYS_ENCRYPT_MECHANISM_*` constants.
+    pub mechanism: AZIOT_KEYS_ENCRYPT_MECHANISM,
+
+    /// The parameters of the encryption mechanism specified by `mechanism`.
+    pub parameters: *const std::ffi::c_void,
+
+    /// The mechanism used to derive the new key from the base key.
+    ///
+    /// One of the `AZIOT_KEYS_KEY_DERIVATION_MECHANISM_*` constants.
+    pub derivation_mechanism: AZIOT_KEYS_KEY_DERIVATION_MECHANISM,
+
+    /// The parameters of the derivation mechanism specified by `derivation_mechanism`.
+    pub derivation_parameters: *const std::ffi::c_void,
+}
+
+#[cfg(any())]
+#[no_mangle]
+pub extern "C" fn cbindgen_unused_AZIOT_KEYS_ENCRYPT_DERIVED_WITH_MECHANISM_PARAMETERS(
+) -> AZIOT_KEYS_ENCRYPT_DERIVED_WITH_MECHANISM_PARAMETERS {
+    unimplemented!();
+}
+
+/// The kind of an entry reported by `enumerate_keys`.
+///
+/// One of the `AZIOT_KEYS_KEY_KIND_*` constants.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(transparent)]
+pub struct AZIOT_KEYS_KEY_KIND {
+    inner: std::os::raw::c_uint,
+}
+
+/// The entry is a symmetric key.
+pub const AZIOT_KEYS_KEY_KIND_KEY: AZIOT_KEYS_KEY_KIND = AZIOT_KEYS_KEY_KIND { inner: 1 };
+
+/// The entry is an asymmetric key pair.
+pub const AZIOT_KEYS_KEY_KIND_KEY_PAIR: AZIOT_KEYS_KEY_KIND = AZIOT_KEYS_KEY_KIND { inner: 2 };
+
+/// Describes a key or key pair, as reported by `enumerate_keys`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub struct AZIOT_KEYS_KEY_INFO {
+    /// The ID of the key or key pair, as a NUL-terminated string.
+    pub id: *const std::os::raw::c_char,
+
+    /// Whether this is a key or a key pair.
+    pub kind: AZIOT_KEYS_KEY_KIND,
+
+    /// The algorithm of the key pair. One of the `AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM_*` constants.
+    ///
+    /// Always zero for keys.
+    pub algorithm: AZIOT_KEYS_KEY_PAIR_PARAMETER_ALGORITHM,
+
+    /// The usage of the key. A combination of the `AZIOT_KEYS_KEY_USAGE_*` constants.
+    ///
+    /// Always zero for key pairs, and zero for keys whose usage is not known.
+    pub usage: AZIOT_KEYS_KEY_USAGE,
+
+    /// Where the key or key pair is stored, as a NUL-terminated string.
+    ///
+    /// This is a `file://` URI for keys in the filesystem, or a `pkcs11:` URI for keys in a PKCS#11 token.
+    /// PKCS#11 URIs never contain the PIN.
+    pub location: *const std::os::raw::c_char,
+
+    /// Non-zero if the key or key pair was configured as a pre-loaded key.
+    pub preloaded: std::os::raw::c_int,
+}
+
+/// The callback invoked by `enumerate_keys` for each key or key pair.
+pub type AZIOT_KEYS_ENUMERATE_KEYS_CALLBACK =
+    Option<unsafe extern "C" fn(context: *mut std::ffi::c_void, info: *const AZIOT_KEYS_KEY_INFO)>;
+
 /// Catches the error, if any, and returns it. Otherwise returns [`AZIOT_KEYS_RC_OK`].
 fn r#catch(f: impl FnOnce() -> Result<(), AZIOT_KEYS_RC>) -> AZIOT_KEYS_RC {
     match f() {
@@ -0,0 +1,653 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Multi-part operations started with `encrypt_init`, `decrypt_init` and `sign_init`.
+
+pub(crate) unsafe extern "C" fn encrypt_init(
+    id: *const std::os::raw::c_char,
+    mechanism: crate::AZIOT_KEYS_ENCRYPT_MECHANISM,
+    parameters: *const std::ffi::c_void,
+    stream: *mut *mut crate::AZIOT_KEYS_STREAM,
+) -> crate::AZIOT_KEYS_RC {
+    encrypt_init_inner(
+        id,
+        mechanism,
+        parameters,
+        stream,
+        openssl::symm::Mode::Encrypt,
+    )
+}
+
+pub(crate) unsafe extern "C" fn decrypt_init(
+    id: *const std::os::raw::c_char,
+    mechanism: crate::AZIOT_KEYS_ENCRYPT_MECHANISM,
+    parameters: *const std::ffi::c_void,
+    stream: *mut *mut crate::AZIOT_KEYS_STREAM,
+) -> crate::AZIOT_KEYS_RC {
+    encrypt_init_inner(
+        id,
+        mechanism,
+        parameters,
+        stream,
+        openssl::symm::Mode::Decrypt,
+    )
+}
+
+unsafe fn encrypt_init_inner(
+    id: *const std::os::raw::c_char,
+    mechanism: crate::AZIOT_KEYS_ENCRYPT_MECHANISM,
+    parameters: *const std::ffi::c_void,
+    stream: *mut *mut crate::AZIOT_KEYS_STREAM,
+    mode: openssl::symm::Mode,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let id = {
+            if id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "expected non-NULL",
+                ));
+            }
+            let id = std::ffi::CStr::from_ptr(id);
+            let id = id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("id", err))?;
+            id
+        };
+
+        let mut stream_out = std::ptr::NonNull::new(stream).ok_or_else(|| {
+            crate::implementation::err_invalid_parameter("stream", "expected non-NULL")
+        })?;
+
+        let locations = crate::implementation::Location::of(id)?;
+
+        let stream = match mechanism {
+            crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_DERIVED_WITH_MECHANISM
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CBC_PAD
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_CTR
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AES_KEY_WRAP_PAD => {
+                crate::key::encrypt_init(id, &locations, mechanism, parameters, mode)?
+            }
+
+            crate::AZIOT_KEYS_ENCRYPT_MECHANISM_RSA_PKCS1
+            | crate::AZIOT_KEYS_ENCRYPT_MECHANISM_RSA_NO_PADDING => {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "mechanism",
+                    "mechanism does not support multi-part operations",
+                ))
+            }
+
+            _ => {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "mechanism",
+                    "unrecognized value",
+                ))
+            }
+        };
+
+        *stream_out.as_mut() = Box::into_raw(Box::new(crate::AZIOT_KEYS_STREAM { inner: stream }));
+
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn sign_init(
+    id: *const std::os::raw::c_char,
+    mechanism: crate::AZIOT_KEYS_SIGN_MECHANISM,
+    parameters: *const std::ffi::c_void,
+    stream: *mut *mut crate::AZIOT_KEYS_STREAM,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let id = {
+            if id.is_null() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "id",
+                    "expected non-NULL",
+                ));
+            }
+            let id = std::ffi::CStr::from_ptr(id);
+            let id = id
+                .to_str()
+                .map_err(|err| crate::implementation::err_invalid_parameter("id", err))?;
+            id
+        };
+
+        let mut stream_out = std::ptr::NonNull::new(stream).ok_or_else(|| {
+            crate::implementation::err_invalid_parameter("stream", "expected non-NULL")
+        })?;
+
+        let locations = crate::implementation::Location::of(id)?;
+
+        let stream = match mechanism {
+            crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA256
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA384
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_HMAC_SHA512
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_DERIVED_WITH_MECHANISM => {
+                crate::key::sign_init(id, &locations, mechanism, parameters)?
+            }
+
+            // These mechanisms sign a digest that the caller has already computed over the whole message,
+            // so there is nothing to gain from passing it in parts.
+            crate::AZIOT_KEYS_SIGN_MECHANISM_ECDSA
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_EDDSA
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PKCS1
+            | crate::AZIOT_KEYS_SIGN_MECHANISM_RSA_PSS => {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "mechanism",
+                    "mechanism does not support multi-part operations",
+                ))
+            }
+
+            _ => {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "mechanism",
+                    "unrecognized value",
+                ))
+            }
+        };
+
+        *stream_out.as_mut() = Box::into_raw(Box::new(crate::AZIOT_KEYS_STREAM { inner: stream }));
+
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn stream_update(
+    stream: *mut crate::AZIOT_KEYS_STREAM,
+    data: *const std::os::raw::c_uchar,
+    data_len: usize,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let stream = stream.as_mut().ok_or_else(|| {
+            crate::implementation::err_invalid_parameter("stream", "expected non-NULL")
+        })?;
+
+        let data = if data.is_null() {
+            return Err(crate::implementation::err_invalid_parameter(
+                "data",
+                "expected non-NULL",
+            ));
+        } else {
+            std::slice::from_raw_parts(data, data_len)
+        };
+
+        stream.inner.update(data)
+    })
+}
+
+pub(crate) unsafe extern "C" fn stream_final(
+    stream: *mut crate::AZIOT_KEYS_STREAM,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let stream = stream.as_mut().ok_or_else(|| {
+            crate::implementation::err_invalid_parameter("stream", "expected non-NULL")
+        })?;
+
+        stream.inner.finish()
+    })
+}
+
+pub(crate) unsafe extern "C" fn stream_read(
+    stream: *mut crate::AZIOT_KEYS_STREAM,
+    output: *mut std::os::raw::c_uchar,
+    output_len: *mut usize,
+) -> crate::AZIOT_KEYS_RC {
+    crate::r#catch(|| {
+        let stream = stream.as_mut().ok_or_else(|| {
+            crate::implementation::err_invalid_parameter("stream", "expected non-NULL")
+        })?;
+
+        let mut output_len_out = std::ptr::NonNull::new(output_len).ok_or_else(|| {
+            crate::implementation::err_invalid_parameter("output_len", "expected non-NULL")
+        })?;
+
+        let available = &stream.inner.output;
+
+        let actual_output_len = *output_len_out.as_ref();
+
+        *output_len_out.as_mut() = available.len();
+
+        if !output.is_null() {
+            if actual_output_len < available.len() {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "output",
+                    "insufficient size",
+                ));
+            }
+
+            let output_out = std::slice::from_raw_parts_mut(output, actual_output_len);
+            output_out[..available.len()].copy_from_slice(available);
+
+            stream.inner.output.clear();
+        }
+
+        Ok(())
+    })
+}
+
+pub(crate) unsafe extern "C" fn stream_free(stream: *mut crate::AZIOT_KEYS_STREAM) {
+    if !stream.is_null() {
+        drop(Box::from_raw(stream));
+    }
+}
+
+/// The state of a multi-part operation.
+pub(crate) struct Stream {
+    operation: Operation,
+
+    /// Output produced by the operation that has not been read by the caller yet.
+    output: Vec<u8>,
+
+    finished: bool,
+}
+
+pub(crate) enum Operation {
+    /// AES-CBC or AES-CTR encryption or decryption with a filesystem key, and the block size of its cipher.
+    Crypter(openssl::symm::Crypter, usize),
+
+    /// AEAD encryption with a filesystem key. The tag is appended to the ciphertext when the operation is completed.
+    AeadEncrypt(openssl::symm::Crypter),
+
+    /// AEAD decryption with a filesystem key.
+    AeadDecrypt {
+        crypter: openssl::symm::Crypter,
+
+        /// The version byte of the ciphertext, once it has been received.
+        version: Option<u8>,
+
+        /// Ciphertext that has been received but not decrypted yet,
+        /// either because the version header is incomplete or because it might be the trailing tag.
+        pending: Vec<u8>,
+
+        /// Plaintext that has been decrypted but not authenticated yet. It's only released to the caller
+        /// once the tag has been verified when the operation is completed.
+        plaintext: Vec<u8>,
+    },
+
+    /// AES-CBC or AES-CTR encryption with a key in a PKCS#11 token.
+    Pkcs11Encrypt(pkcs11::Object<()>),
+
+    /// AES-CBC or AES-CTR decryption with a key in a PKCS#11 token.
+    Pkcs11Decrypt(pkcs11::Object<()>),
+
+    /// HMAC with a filesystem key.
+    Hmac(Box<Hmac>),
+
+    /// HMAC with a key in a PKCS#11 token.
+    Pkcs11Hmac(pkcs11::Object<()>, pkcs11::DigestAlgorithm),
+}
+
+pub(crate) enum Hmac {
+    Sha256(hmac::Hmac<sha2::Sha256>),
+    Sha384(hmac::Hmac<sha2::Sha384>),
+    Sha512(hmac::Hmac<sha2::Sha512>),
+}
+
+impl Stream {
+    pub(crate) fn new(operation: Operation, output: Vec<u8>) -> Self {
+        Stream {
+            operation,
+            output,
+            finished: false,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<(), crate::AZIOT_KEYS_RC> {
+        let Stream {
+            operation,
+            output,
+            finished,
+        } = self;
+
+        if *finished {
+            return Err(crate::implementation::err_invalid_parameter(
+                "stream",
+                "operation has already been completed",
+            ));
+        }
+
+        match operation {
+            Operation::Crypter(crypter, _) | Operation::AeadEncrypt(crypter) => {
+                crypter_update(crypter, data, output)?;
+            }
+
+            Operation::AeadDecrypt {
+                crypter,
+                version,
+                pending,
+                plaintext,
+            } => {
+                pending.extend_from_slice(data);
+
+                if version.is_none() {
+                    match pending.first() {
+                        None => return Ok(()),
+
+                        Some(0x01) => {
+                            // Version 1 ciphertext has the tag before the ciphertext.
+                            if pending.len() <= 16 {
+                                return Ok(());
+                            }
+                            crypter.set_tag(&pending[1..=16])?;
+                            pending.drain(..=16);
+                            *version = Some(0x01);
+                        }
+
+                        Some(0x02) => {
+                            pending.remove(0);
+                            *version = Some(0x02);
+                        }
+
+                        Some(version) => {
+                            return Err(crate::implementation::err_invalid_parameter(
+                                "ciphertext",
+                                format!("unknown version {:?}", version),
+                            ));
+                        }
+                    }
+                }
+
+                // Version 2 ciphertext has the tag after the ciphertext, so the last 16 bytes received so far
+                // must be held back in case they're the tag.
+                let held_back = if *version == Some(0x02) { 16 } else { 0 };
+                if pending.len() > held_back {
+                    let ciphertext_len = pending.len() - held_back;
+                    crypter_update(crypter, &pending[..ciphertext_len], plaintext)?;
+                    pending.drain(..ciphertext_len);
+                }
+            }
+
+            Operation::Pkcs11Encrypt(key) => {
+                // AES-CBC and AES-CTR never output more than the input plus one AES block that might have been buffered.
+                let mut ciphertext = vec![0_u8; data.len() + 16];
+                let ciphertext_len = key
+                    .encrypt_update(data, &mut ciphertext)
+                    .map_err(crate::implementation::err_external)?;
+                let ciphertext_len: usize =
+                    std::convert::TryInto::try_into(ciphertext_len).expect("CK_ULONG -> usize");
+                output.extend_from_slice(&ciphertext[..ciphertext_len]);
+            }
+
+            Operation::Pkcs11Decrypt(key) => {
+                let mut plaintext = vec![0_u8; data.len() + 16];
+                let plaintext_len = key
+                    .decrypt_update(data, &mut plaintext)
+                    .map_err(crate::implementation::err_external)?;
+                let plaintext_len: usize =
+                    std::convert::TryInto::try_into(plaintext_len).expect("CK_ULONG -> usize");
+                output.extend_from_slice(&plaintext[..plaintext_len]);
+            }
+
+            Operation::Hmac(hmac) => match &mut **hmac {
+                Hmac::Sha256(signer) => hmac::Mac::update(signer, data),
+                Hmac::Sha384(signer) => hmac::Mac::update(signer, data),
+                Hmac::Sha512(signer) => hmac::Mac::update(signer, data),
+            },
+
+            Operation::Pkcs11Hmac(key, _) => {
+                key.sign_update(data)
+                    .map_err(crate::implementation::err_external)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), crate::AZIOT_KEYS_RC> {
+        let Stream {
+            operation,
+            output,
+            finished,
+        } = self;
+
+        if *finished {
+            return Err(crate::implementation::err_invalid_parameter(
+                "stream",
+                "operation has already been completed",
+            ));
+        }
+        *finished = true;
+
+        match operation {
+            Operation::Crypter(crypter, block_size) => {
+                let mut last = vec![0_u8; *block_size];
+                let last_len = crypter.finalize(&mut last)?;
+                output.extend_from_slice(&last[..last_len]);
+            }
+
+            Operation::AeadEncrypt(crypter) => {
+                let mut last = [0_u8; 16];
+                let last_len = crypter.finalize(&mut last)?;
+                output.extend_from_slice(&last[..last_len]);
+
+                let mut tag = [0_u8; 16];
+                crypter.get_tag(&mut tag)?;
+                output.extend_from_slice(&tag);
+            }
+
+            Operation::AeadDecrypt {
+                crypter,
+                version,
+                pending,
+                plaintext,
+            } => {
+                match version {
+                    Some(0x01) => (),
+
+                    Some(0x02) if pending.len() == 16 => crypter.set_tag(pending)?,
+
+                    _ => {
+                        return Err(crate::implementation::err_invalid_parameter(
+                            "ciphertext",
+                            "malformed",
+                        ))
+                    }
+                }
+
+                let mut last = [0_u8; 16];
+                let last_len = crypter.finalize(&mut last).map_err(|_| {
+                    plaintext.clear();
+                    crate::implementation::err_invalid_parameter(
+                        "ciphertext",
+                        "could not be authenticated",
+                    )
+                })?;
+                output.append(plaintext);
+                output.extend_from_slice(&last[..last_len]);
+            }
+
+            Operation::Pkcs11Encrypt(key) => {
+                let mut last = [0_u8; 16];
+                let last_len = key
+                    .encrypt_final(&mut last)
+                    .map_err(crate::implementation::err_external)?;
+                let last_len: usize =
+                    std::convert::TryInto::try_into(last_len).expect("CK_ULONG -> usize");
+                output.extend_from_slice(&last[..last_len]);
+            }
+
+            Operation::Pkcs11Decrypt(key) => {
+                let mut last = [0_u8; 16];
+                let last_len = key
+                    .decrypt_final(&mut last)
+                    .map_err(crate::implementation::err_external)?;
+                let last_len: usize =
+                    std::convert::TryInto::try_into(last_len).expect("CK_ULONG -> usize");
+                output.extend_from_slice(&last[..last_len]);
+            }
+
+            Operation::Hmac(hmac) => match &mut **hmac {
+                Hmac::Sha256(signer) => {
+                    output.extend_from_slice(&hmac::Mac::finalize_reset(signer).into_bytes());
+                }
+                Hmac::Sha384(signer) => {
+                    output.extend_from_slice(&hmac::Mac::finalize_reset(signer).into_bytes());
+                }
+                Hmac::Sha512(signer) => {
+                    output.extend_from_slice(&hmac::Mac::finalize_reset(signer).into_bytes());
+                }
+            },
+
+            Operation::Pkcs11Hmac(key, digest_algorithm) => {
+                let mut signature = vec![0_u8; crate::key::hmac_len(*digest_algorithm)];
+                let signature_len = key
+                    .sign_final(&mut signature)
+                    .map_err(crate::implementation::err_external)?;
+                let signature_len: usize =
+                    std::convert::TryInto::try_into(signature_len).expect("CK_ULONG -> usize");
+                output.extend_from_slice(&signature[..signature_len]);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        // A stream can be freed before it's completed, such as when the caller aborts it or an update fails.
+        // The PKCS#11 operation would then still be active in the key's session.
+        if self.finished {
+            return;
+        }
+
+        match &self.operation {
+            Operation::Pkcs11Encrypt(key)
+            | Operation::Pkcs11Decrypt(key)
+            | Operation::Pkcs11Hmac(key, _) => key.abandon_operation(),
+
+            Operation::Crypter(..)
+            | Operation::AeadEncrypt(_)
+            | Operation::AeadDecrypt { .. }
+            | Operation::Hmac(_) => (),
+        }
+    }
+}
+
+fn crypter_update(
+    crypter: &mut openssl::symm::Crypter,
+    input: &[u8],
+    output: &mut Vec<u8>,
+) -> Result<(), crate::AZIOT_KEYS_RC> {
+    // `Crypter::update` requires room for one more block than the input, for any block that was buffered by earlier calls.
+    let original_len = output.len();
+    output.resize(original_len + input.len() + 16, 0);
+    let written = crypter.update(input, &mut output[original_len..])?;
+    output.truncate(original_len + written);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::implementation::tests::{c_string, TestHomedir};
+
+    /// Decrypts the ciphertext with a multi-part AEAD operation, passing it in parts of `part_len` bytes.
+    ///
+    /// Returns the output that could be read after each update, and the result and output of `stream_final`.
+    unsafe fn decrypt_aead_in_parts(
+        id: &std::ffi::CStr,
+        parameters: &crate::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS,
+        ciphertext: &[u8],
+        part_len: usize,
+    ) -> (Vec<Vec<u8>>, crate::AZIOT_KEYS_RC, Vec<u8>) {
+        let read = |stream: *mut crate::AZIOT_KEYS_STREAM| {
+            let mut output_len = 0;
+            assert_eq!(
+                super::stream_read(stream, std::ptr::null_mut(), &mut output_len),
+                crate::AZIOT_KEYS_RC_OK,
+            );
+            let mut output = vec![0_u8; output_len];
+            assert_eq!(
+                super::stream_read(stream, output.as_mut_ptr(), &mut output_len),
+                crate::AZIOT_KEYS_RC_OK,
+            );
+            output
+        };
+
+        let mut stream = std::ptr::null_mut();
+        assert_eq!(
+            super::decrypt_init(
+                id.as_ptr(),
+                crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD,
+                (parameters as *const crate::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS).cast(),
+                &mut stream,
+            ),
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        let mut update_outputs = vec![];
+        for part in ciphertext.chunks(part_len) {
+            assert_eq!(
+                super::stream_update(stream, part.as_ptr(), part.len()),
+                crate::AZIOT_KEYS_RC_OK,
+            );
+            update_outputs.push(read(stream));
+        }
+
+        let final_result = super::stream_final(stream);
+        let final_output = read(stream);
+
+        super::stream_free(stream);
+
+        (update_outputs, final_result, final_output)
+    }
+
+    #[test]
+    fn aead_decrypt_releases_plaintext_only_after_authentication() {
+        let _homedir = TestHomedir::new();
+
+        let id = c_string("key");
+        assert_eq!(
+            unsafe {
+                crate::key::create_key_if_not_exists(
+                    id.as_ptr(),
+                    crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+                )
+            },
+            crate::AZIOT_KEYS_RC_OK,
+        );
+
+        let iv = [0x01_u8; 12];
+        let aad = b"aad";
+        let parameters = crate::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS {
+            iv: iv.as_ptr(),
+            iv_len: iv.len(),
+            aad: aad.as_ptr(),
+            aad_len: aad.len(),
+        };
+
+        let plaintext: Vec<u8> = (0..100).collect();
+        let locations = crate::implementation::Location::of("key").unwrap();
+        let (_, ciphertext) = unsafe {
+            crate::key::encrypt(
+                "key",
+                &locations,
+                crate::AZIOT_KEYS_ENCRYPT_MECHANISM_AEAD,
+                (&parameters as *const crate::AZIOT_KEYS_ENCRYPT_AEAD_PARAMETERS).cast(),
+                &plaintext,
+            )
+            .unwrap()
+        };
+
+        let (update_outputs, final_result, final_output) =
+            unsafe { decrypt_aead_in_parts(&id, &parameters, &ciphertext, 7) };
+        assert!(update_outputs.iter().all(Vec::is_empty));
+        assert_eq!(final_result, crate::AZIOT_KEYS_RC_OK);
+        assert_eq!(final_output, plaintext);
+
+        // Tampering with the ciphertext or the tag means that no plaintext is released at all.
+        for &tampered_index in &[1, ciphertext.len() - 1] {
+            let mut tampered = ciphertext.clone();
+            tampered[tampered_index] ^= 0x01;
+
+            let (update_outputs, final_result, final_output) =
+                unsafe { decrypt_aead_in_parts(&id, &parameters, &tampered, 7) };
+            assert!(update_outputs.iter().all(Vec::is_empty));
+            assert_eq!(final_result, crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER);
+            assert!(final_output.is_empty());
+        }
+    }
+}
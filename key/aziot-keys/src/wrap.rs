@@ -0,0 +1,485 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Wrapping of keys and key pairs so that they can be exported from one device and imported into another.
+//!
+//! A wrapped key has the layout:
+//!
+//! ```text
+//! header (16 bytes) || mechanism (u32) || kind (u32) || usage (u32) || encapsulated key length (u32) || encapsulated key
+//!     || IV (12 bytes) || ciphertext || tag (16 bytes)
+//! ```
+//!
+//! with all integers in big-endian. The key is encrypted with AES-256-GCM under a random or derived content-encryption key (CEK),
+//! with everything before the IV as AAD. The encapsulated key is how the recipient recovers the CEK:
+//!
+//! - For [`crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP`], it's the random CEK encrypted with the wrapping key using RSA-OAEP with SHA-256.
+//!
+//! - For [`crate::AZIOT_KEYS_WRAP_MECHANISM_ECDH`], it's the DER-encoded `SubjectPublicKeyInfo` of an ephemeral EC key
+//!   on the same curve as the wrapping key. The CEK is derived from the ECDH shared secret of the two keys with HKDF-SHA256.
+
+const HEADER: &[u8; 16] = b"AZIOT-KEYS-WRAP\x01";
+
+const CEK_LEN: usize = 32;
+const IV_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+const HKDF_INFO: &[u8] = b"aziot-keys key wrap";
+
+/// A key or key pair that was unwrapped with [`unwrap`].
+pub(crate) struct Unwrapped {
+    pub(crate) usage: crate::AZIOT_KEYS_KEY_USAGE,
+
+    /// The raw bytes of a key, or the PEM-encoded PKCS#8 private key of a key pair.
+    pub(crate) payload: Vec<u8>,
+}
+
+/// Wraps `payload` under the public key `wrapping_key`, which is a DER-encoded `SubjectPublicKeyInfo`.
+pub(crate) fn wrap(
+    mechanism: crate::AZIOT_KEYS_WRAP_MECHANISM,
+    wrapping_key: &[u8],
+    kind: crate::AZIOT_KEYS_KEY_KIND,
+    usage: crate::AZIOT_KEYS_KEY_USAGE,
+    payload: &[u8],
+) -> Result<Vec<u8>, crate::AZIOT_KEYS_RC> {
+    let wrapping_key = openssl::pkey::PKey::public_key_from_der(wrapping_key)
+        .map_err(|err| crate::implementation::err_invalid_parameter("wrapping_key", err))?;
+
+    let (cek, encapsulated_key) = match mechanism {
+        crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP => {
+            if wrapping_key.id() != openssl::pkey::Id::RSA {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "wrapping_key",
+                    "RSA-OAEP mechanism requires an RSA key",
+                ));
+            }
+
+            let mut cek = vec![0_u8; CEK_LEN];
+            openssl::rand::rand_bytes(&mut cek)?;
+
+            let mut encrypter = openssl::encrypt::Encrypter::new(&wrapping_key)?;
+            encrypter.set_rsa_padding(openssl::rsa::Padding::PKCS1_OAEP)?;
+            encrypter.set_rsa_oaep_md(openssl::hash::MessageDigest::sha256())?;
+            encrypter.set_rsa_mgf1_md(openssl::hash::MessageDigest::sha256())?;
+            let mut encapsulated_key = vec![0_u8; encrypter.encrypt_len(&cek)?];
+            let encapsulated_key_len = encrypter.encrypt(&cek, &mut encapsulated_key)?;
+            encapsulated_key.truncate(encapsulated_key_len);
+
+            (cek, encapsulated_key)
+        }
+
+        crate::AZIOT_KEYS_WRAP_MECHANISM_ECDH => {
+            let wrapping_ec_key = wrapping_key.ec_key().map_err(|_| {
+                crate::implementation::err_invalid_parameter(
+                    "wrapping_key",
+                    "ECDH mechanism requires an EC key",
+                )
+            })?;
+
+            let ephemeral_key = openssl::ec::EcKey::generate(wrapping_ec_key.group())?;
+            let ephemeral_key = openssl::pkey::PKey::from_ec_key(ephemeral_key)?;
+
+            let mut deriver = openssl::derive::Deriver::new(&ephemeral_key)?;
+            deriver.set_peer(&wrapping_key)?;
+            let shared_secret = deriver.derive_to_vec()?;
+            let cek = crate::kdf::hkdf(
+                openssl::hash::MessageDigest::sha256(),
+                &shared_secret,
+                &[],
+                HKDF_INFO,
+                CEK_LEN,
+            )?;
+
+            let encapsulated_key = ephemeral_key.public_key_to_der()?;
+
+            (cek, encapsulated_key)
+        }
+
+        _ => {
+            return Err(crate::implementation::err_invalid_parameter(
+                "mechanism",
+                "unrecognized value",
+            ))
+        }
+    };
+
+    let encapsulated_key_len: u32 = std::convert::TryInto::try_into(encapsulated_key.len())
+        .map_err(crate::implementation::err_external)?;
+
+    let mut result = HEADER.to_vec();
+    result.extend_from_slice(&mechanism.inner.to_be_bytes());
+    result.extend_from_slice(&kind.inner.to_be_bytes());
+    result.extend_from_slice(&usage.inner.to_be_bytes());
+    result.extend_from_slice(&encapsulated_key_len.to_be_bytes());
+    result.extend_from_slice(&encapsulated_key);
+
+    let mut iv = [0_u8; IV_LEN];
+    openssl::rand::rand_bytes(&mut iv)?;
+
+    let mut tag = [0_u8; TAG_LEN];
+    let ciphertext = openssl::symm::encrypt_aead(
+        openssl::symm::Cipher::aes_256_gcm(),
+        &cek,
+        Some(&iv),
+        &result,
+        payload,
+        &mut tag,
+    )?;
+
+    result.extend_from_slice(&iv);
+    result.extend_from_slice(&ciphertext);
+    result.extend_from_slice(&tag);
+
+    Ok(result)
+}
+
+/// Unwraps a key or key pair that was wrapped with [`wrap`] under the public key of `unwrapping_key`.
+///
+/// Fails if the wrapped key was not wrapped with the given `mechanism`, or is not of the given `kind`.
+pub(crate) fn unwrap(
+    mechanism: crate::AZIOT_KEYS_WRAP_MECHANISM,
+    unwrapping_key: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
+    kind: crate::AZIOT_KEYS_KEY_KIND,
+    wrapped_key: &[u8],
+) -> Result<Unwrapped, crate::AZIOT_KEYS_RC> {
+    fn malformed() -> crate::AZIOT_KEYS_RC {
+        crate::implementation::err_invalid_parameter("wrapped_key", "malformed")
+    }
+
+    fn read_u32(buf: &[u8], offset: usize) -> Result<std::os::raw::c_uint, crate::AZIOT_KEYS_RC> {
+        let bytes = buf.get(offset..(offset + 4)).ok_or_else(malformed)?;
+        let mut value = [0_u8; 4];
+        value.copy_from_slice(bytes);
+        Ok(u32::from_be_bytes(value))
+    }
+
+    if !wrapped_key.starts_with(HEADER) {
+        return Err(malformed());
+    }
+
+    let wrapped_mechanism = read_u32(wrapped_key, HEADER.len())?;
+    let wrapped_kind = read_u32(wrapped_key, HEADER.len() + 4)?;
+    let usage = read_u32(wrapped_key, HEADER.len() + 8)?;
+    let encapsulated_key_len: usize =
+        std::convert::TryInto::try_into(read_u32(wrapped_key, HEADER.len() + 12)?)
+            .map_err(|_| malformed())?;
+
+    if wrapped_mechanism != mechanism.inner {
+        return Err(crate::implementation::err_invalid_parameter(
+            "wrapped_key",
+            "was not wrapped with the specified mechanism",
+        ));
+    }
+
+    if wrapped_kind != kind.inner {
+        return Err(crate::implementation::err_invalid_parameter(
+            "wrapped_key",
+            if kind == crate::AZIOT_KEYS_KEY_KIND_KEY {
+                "is not a key"
+            } else {
+                "is not a key pair"
+            },
+        ));
+    }
+
+    let aad_len = (HEADER.len() + 16)
+        .checked_add(encapsulated_key_len)
+        .ok_or_else(malformed)?;
+    if wrapped_key.len().saturating_sub(IV_LEN + TAG_LEN) < aad_len {
+        return Err(malformed());
+    }
+    let (aad, rest) = wrapped_key.split_at(aad_len);
+    let encapsulated_key = &aad[(HEADER.len() + 16)..];
+    let (iv, rest) = rest.split_at(IV_LEN);
+    let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+
+    let cek = match mechanism {
+        crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP => {
+            if unwrapping_key.id() != openssl::pkey::Id::RSA {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "unwrapping_key_pair_id",
+                    "RSA-OAEP mechanism requires an RSA key pair",
+                ));
+            }
+
+            let mut decrypter = openssl::encrypt::Decrypter::new(unwrapping_key)?;
+            decrypter.set_rsa_padding(openssl::rsa::Padding::PKCS1_OAEP)?;
+            decrypter.set_rsa_oaep_md(openssl::hash::MessageDigest::sha256())?;
+            decrypter.set_rsa_mgf1_md(openssl::hash::MessageDigest::sha256())?;
+            let mut cek = vec![0_u8; decrypter.decrypt_len(encapsulated_key)?];
+            let cek_len = decrypter
+                .decrypt(encapsulated_key, &mut cek)
+                .map_err(|_| unwrap_failed())?;
+            cek.truncate(cek_len);
+            cek
+        }
+
+        crate::AZIOT_KEYS_WRAP_MECHANISM_ECDH => {
+            if unwrapping_key.id() != openssl::pkey::Id::EC {
+                return Err(crate::implementation::err_invalid_parameter(
+                    "unwrapping_key_pair_id",
+                    "ECDH mechanism requires an EC key pair",
+                ));
+            }
+
+            let ephemeral_key = openssl::pkey::PKey::public_key_from_der(encapsulated_key)
+                .map_err(|_| malformed())?;
+
+            let mut deriver = openssl::derive::Deriver::new(unwrapping_key)?;
+            deriver
+                .set_peer(&ephemeral_key)
+                .map_err(|_| unwrap_failed())?;
+            let shared_secret = deriver.derive_to_vec().map_err(|_| unwrap_failed())?;
+            crate::kdf::hkdf(
+                openssl::hash::MessageDigest::sha256(),
+                &shared_secret,
+                &[],
+                HKDF_INFO,
+                CEK_LEN,
+            )?
+        }
+
+        _ => {
+            return Err(crate::implementation::err_invalid_parameter(
+                "mechanism",
+                "unrecognized value",
+            ))
+        }
+    };
+
+    let payload = openssl::symm::decrypt_aead(
+        openssl::symm::Cipher::aes_256_gcm(),
+        &cek,
+        Some(iv),
+        aad,
+        ciphertext,
+        tag,
+    )
+    .map_err(|_| unwrap_failed())?;
+
+    Ok(Unwrapped {
+        usage: crate::AZIOT_KEYS_KEY_USAGE { inner: usage },
+        payload,
+    })
+}
+
+fn unwrap_failed() -> crate::AZIOT_KEYS_RC {
+    crate::implementation::err_invalid_parameter(
+        "wrapped_key",
+        "could not be unwrapped; it may have been wrapped under a different key",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    fn rsa_key() -> openssl::pkey::PKey<openssl::pkey::Private> {
+        openssl::pkey::PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap()
+    }
+
+    fn ec_key() -> openssl::pkey::PKey<openssl::pkey::Private> {
+        let group =
+            openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1).unwrap();
+        openssl::pkey::PKey::from_ec_key(openssl::ec::EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    fn wrap(
+        mechanism: crate::AZIOT_KEYS_WRAP_MECHANISM,
+        wrapping_key: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
+    ) -> Vec<u8> {
+        super::wrap(
+            mechanism,
+            &wrapping_key.public_key_to_der().unwrap(),
+            crate::AZIOT_KEYS_KEY_KIND_KEY,
+            crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+            b"payload",
+        )
+        .unwrap()
+    }
+
+    fn assert_unwrap_fails(
+        mechanism: crate::AZIOT_KEYS_WRAP_MECHANISM,
+        unwrapping_key: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
+        kind: crate::AZIOT_KEYS_KEY_KIND,
+        wrapped_key: &[u8],
+    ) {
+        assert_eq!(
+            super::unwrap(mechanism, unwrapping_key, kind, wrapped_key)
+                .map(|unwrapped| unwrapped.payload)
+                .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for &(mechanism, key) in &[
+            (
+                crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP,
+                rsa_key as fn() -> _,
+            ),
+            (crate::AZIOT_KEYS_WRAP_MECHANISM_ECDH, ec_key),
+        ] {
+            let key = key();
+
+            let wrapped_key = wrap(mechanism, &key);
+            let unwrapped = super::unwrap(
+                mechanism,
+                &key,
+                crate::AZIOT_KEYS_KEY_KIND_KEY,
+                &wrapped_key,
+            )
+            .unwrap();
+            assert_eq!(unwrapped.usage, crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT);
+            assert_eq!(unwrapped.payload, b"payload");
+
+            // Wrapping is randomized.
+            assert_ne!(wrap(mechanism, &key), wrapped_key);
+        }
+    }
+
+    #[test]
+    fn tampered() {
+        for &(mechanism, key) in &[
+            (
+                crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP,
+                rsa_key as fn() -> _,
+            ),
+            (crate::AZIOT_KEYS_WRAP_MECHANISM_ECDH, ec_key),
+        ] {
+            let key = key();
+
+            let wrapped_key = wrap(mechanism, &key);
+
+            let tamper = |offset: usize| {
+                let mut wrapped_key = wrapped_key.clone();
+                wrapped_key[offset] ^= 0x01;
+                wrapped_key
+            };
+
+            // Header
+            assert_unwrap_fails(mechanism, &key, crate::AZIOT_KEYS_KEY_KIND_KEY, &tamper(0));
+
+            // Usage, which is authenticated as part of the AAD
+            assert_unwrap_fails(
+                mechanism,
+                &key,
+                crate::AZIOT_KEYS_KEY_KIND_KEY,
+                &tamper(super::HEADER.len() + 11),
+            );
+
+            // Ciphertext, which is the 7 bytes of the payload before the tag
+            assert_unwrap_fails(
+                mechanism,
+                &key,
+                crate::AZIOT_KEYS_KEY_KIND_KEY,
+                &tamper(wrapped_key.len() - super::TAG_LEN - 1),
+            );
+
+            // Tag
+            assert_unwrap_fails(
+                mechanism,
+                &key,
+                crate::AZIOT_KEYS_KEY_KIND_KEY,
+                &tamper(wrapped_key.len() - 1),
+            );
+
+            // Truncated
+            assert_unwrap_fails(
+                mechanism,
+                &key,
+                crate::AZIOT_KEYS_KEY_KIND_KEY,
+                &wrapped_key[..(wrapped_key.len() - 1)],
+            );
+
+            // Encapsulated key length that overflows
+            let mut overflowing_wrapped_key = wrapped_key.clone();
+            overflowing_wrapped_key[(super::HEADER.len() + 12)..(super::HEADER.len() + 16)]
+                .copy_from_slice(&u32::MAX.to_be_bytes());
+            assert_unwrap_fails(
+                mechanism,
+                &key,
+                crate::AZIOT_KEYS_KEY_KIND_KEY,
+                &overflowing_wrapped_key,
+            );
+
+            // Different unwrapping key of the same type
+            let other_key = if mechanism == crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP {
+                rsa_key()
+            } else {
+                ec_key()
+            };
+            assert_unwrap_fails(
+                mechanism,
+                &other_key,
+                crate::AZIOT_KEYS_KEY_KIND_KEY,
+                &wrapped_key,
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_kind() {
+        let key = rsa_key();
+        let wrapped_key = wrap(crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP, &key);
+
+        assert_unwrap_fails(
+            crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP,
+            &key,
+            crate::AZIOT_KEYS_KEY_KIND_KEY_PAIR,
+            &wrapped_key,
+        );
+    }
+
+    #[test]
+    fn wrong_mechanism() {
+        let rsa_key = rsa_key();
+        let ec_key = ec_key();
+
+        // The mechanism must match the one the key was wrapped with.
+        let wrapped_key = wrap(crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP, &rsa_key);
+        assert_unwrap_fails(
+            crate::AZIOT_KEYS_WRAP_MECHANISM_ECDH,
+            &ec_key,
+            crate::AZIOT_KEYS_KEY_KIND_KEY,
+            &wrapped_key,
+        );
+
+        // The mechanism must match the type of the unwrapping key.
+        assert_unwrap_fails(
+            crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP,
+            &ec_key,
+            crate::AZIOT_KEYS_KEY_KIND_KEY,
+            &wrapped_key,
+        );
+
+        // The mechanism must match the type of the wrapping key.
+        for &(mechanism, wrapping_key) in &[
+            (crate::AZIOT_KEYS_WRAP_MECHANISM_RSA_OAEP, &ec_key),
+            (crate::AZIOT_KEYS_WRAP_MECHANISM_ECDH, &rsa_key),
+        ] {
+            assert_eq!(
+                super::wrap(
+                    mechanism,
+                    &wrapping_key.public_key_to_der().unwrap(),
+                    crate::AZIOT_KEYS_KEY_KIND_KEY,
+                    crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+                    b"payload",
+                )
+                .unwrap_err(),
+                crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+            );
+        }
+
+        // Unrecognized mechanisms are rejected.
+        assert_eq!(
+            super::wrap(
+                crate::AZIOT_KEYS_WRAP_MECHANISM { inner: 0 },
+                &rsa_key.public_key_to_der().unwrap(),
+                crate::AZIOT_KEYS_KEY_KIND_KEY,
+                crate::AZIOT_KEYS_KEY_USAGE_ENCRYPT,
+                b"payload",
+            )
+            .unwrap_err(),
+            crate::AZIOT_KEYS_RC_ERR_INVALID_PARAMETER,
+        );
+    }
+}
@@ -15,3 +15,10 @@ extern "C" {
         length: std::os::raw::c_long,
     ) -> *mut openssl_sys::ASN1_STRING;
 }
+
+extern "C" {
+    pub fn ASN1_BIT_STRING_get_bit(
+        a: *const openssl_sys::ASN1_BIT_STRING,
+        n: std::os::raw::c_int,
+    ) -> std::os::raw::c_int;
+}
@@ -6,11 +6,17 @@
 pub struct EVP_PKEY_METHOD([u8; 0]);
 
 pub const EVP_PKEY_FLAG_AUTOARGLEN: std::os::raw::c_int = 0x0002;
+pub const EVP_PKEY_FLAG_SIGCTX_CUSTOM: std::os::raw::c_int = 0x0004;
 
 extern "C" {
     pub fn EVP_PKEY_CTX_get0_pkey(
         ctx: *mut openssl_sys::EVP_PKEY_CTX,
     ) -> *mut openssl_sys::EVP_PKEY;
+
+    #[cfg(ossl111)]
+    pub fn EVP_MD_CTX_pkey_ctx(
+        ctx: *const openssl_sys::EVP_MD_CTX,
+    ) -> *mut openssl_sys::EVP_PKEY_CTX;
 }
 
 extern "C" {
@@ -56,6 +62,72 @@ extern "C" {
 }
 
 extern "C" {
+    #[cfg(ossl111)]
+    pub fn EVP_PKEY_meth_get_digestsign(
+        pmeth: *const EVP_PKEY_METHOD,
+        pdigestsign: *mut Option<
+            unsafe extern "C" fn(
+                ctx: *mut openssl_sys::EVP_MD_CTX,
+                sig: *mut std::os::raw::c_uchar,
+                siglen: *mut usize,
+                tbs: *const std::os::raw::c_uchar,
+                tbslen: usize,
+            ) -> std::os::raw::c_int,
+        >,
+    );
+    #[cfg(ossl111)]
+    pub fn EVP_PKEY_meth_get_digestverify(
+        pmeth: *const EVP_PKEY_METHOD,
+        pdigestverify: *mut Option<
+            unsafe extern "C" fn(
+                ctx: *mut openssl_sys::EVP_MD_CTX,
+                sig: *const std::os::raw::c_uchar,
+                siglen: usize,
+                tbs: *const std::os::raw::c_uchar,
+                tbslen: usize,
+            ) -> std::os::raw::c_int,
+        >,
+    );
+    #[cfg(ossl111)]
+    pub fn EVP_PKEY_meth_set_digestsign(
+        pmeth: *mut EVP_PKEY_METHOD,
+        digestsign: Option<
+            unsafe extern "C" fn(
+                ctx: *mut openssl_sys::EVP_MD_CTX,
+                sig: *mut std::os::raw::c_uchar,
+                siglen: *mut usize,
+                tbs: *const std::os::raw::c_uchar,
+                tbslen: usize,
+            ) -> std::os::raw::c_int,
+        >,
+    );
+    #[cfg(ossl111)]
+    pub fn EVP_PKEY_meth_set_digestverify(
+        pmeth: *mut EVP_PKEY_METHOD,
+        digestverify: Option<
+            unsafe extern "C" fn(
+                ctx: *mut openssl_sys::EVP_MD_CTX,
+                sig: *const std::os::raw::c_uchar,
+                siglen: usize,
+                tbs: *const std::os::raw::c_uchar,
+                tbslen: usize,
+            ) -> std::os::raw::c_int,
+        >,
+    );
+}
+
+extern "C" {
+    #[cfg(ossl111)]
+    pub fn EVP_PKEY_get0_engine(pkey: *const openssl_sys::EVP_PKEY) -> *mut openssl_sys::ENGINE;
+
+    #[cfg(ossl111)]
+    pub fn EVP_PKEY_new_raw_public_key(
+        r#type: std::os::raw::c_int,
+        e: *mut openssl_sys::ENGINE,
+        key: *const std::os::raw::c_uchar,
+        keylen: usize,
+    ) -> *mut openssl_sys::EVP_PKEY;
+
     #[cfg(ossl110)]
     pub fn EVP_PKEY_set1_engine(
         pkey: *mut openssl_sys::EVP_PKEY,
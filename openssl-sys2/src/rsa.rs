@@ -49,3 +49,14 @@ extern "C" {
         ) -> std::os::raw::c_int,
     ) -> std::os::raw::c_int;
 }
+
+extern "C" {
+    pub fn RSA_padding_add_PKCS1_PSS_mgf1(
+        rsa: *mut openssl_sys::RSA,
+        em: *mut std::os::raw::c_uchar,
+        m_hash: *const std::os::raw::c_uchar,
+        hash: *const openssl_sys::EVP_MD,
+        mgf1_hash: *const openssl_sys::EVP_MD,
+        s_len: std::os::raw::c_int,
+    ) -> std::os::raw::c_int;
+}
@@ -8,3 +8,34 @@ extern "C" {
         pkey: *const openssl_sys::EVP_PKEY,
     ) -> std::os::raw::c_int;
 }
+
+extern "C" {
+    pub fn i2d_X509_NAME(
+        a: *mut openssl_sys::X509_NAME,
+        out: *mut *mut std::os::raw::c_uchar,
+    ) -> std::os::raw::c_int;
+}
+
+extern "C" {
+    pub fn X509_EXTENSION_dup(
+        ex: *mut openssl_sys::X509_EXTENSION,
+    ) -> *mut openssl_sys::X509_EXTENSION;
+}
+
+extern "C" {
+    pub fn X509_REQ_get_attr_by_NID(
+        req: *const openssl_sys::X509_REQ,
+        nid: std::os::raw::c_int,
+        lastpos: std::os::raw::c_int,
+    ) -> std::os::raw::c_int;
+}
+
+#[repr(C)]
+pub struct BASIC_CONSTRAINTS {
+    pub ca: std::os::raw::c_int,
+    pub pathlen: *mut openssl_sys::ASN1_INTEGER,
+}
+
+extern "C" {
+    pub fn BASIC_CONSTRAINTS_free(a: *mut BASIC_CONSTRAINTS);
+}
@@ -286,27 +286,34 @@ impl ExDataAccessors for openssl_sys::RSA {
 pub enum EcCurve {
     /// secp256r1, known to openssl as prime256v1
     NistP256,
+
+    /// secp384r1
+    NistP384,
 }
 
 impl EcCurve {
     const SECP256R1_OID_DER: &'static [u8] =
         &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+    const SECP384R1_OID_DER: &'static [u8] = &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22];
 
     pub fn as_nid(self) -> openssl::nid::Nid {
         match self {
             EcCurve::NistP256 => openssl::nid::Nid::X9_62_PRIME256V1,
+            EcCurve::NistP384 => openssl::nid::Nid::SECP384R1,
         }
     }
 
     pub fn as_oid_der(self) -> &'static [u8] {
         match self {
             EcCurve::NistP256 => EcCurve::SECP256R1_OID_DER,
+            EcCurve::NistP384 => EcCurve::SECP384R1_OID_DER,
         }
     }
 
     pub fn from_nid(nid: openssl::nid::Nid) -> Option<Self> {
         match nid {
             openssl::nid::Nid::X9_62_PRIME256V1 => Some(EcCurve::NistP256),
+            openssl::nid::Nid::SECP384R1 => Some(EcCurve::NistP384),
             _ => None,
         }
     }
@@ -314,7 +321,56 @@ impl EcCurve {
     pub fn from_oid_der(oid: &[u8]) -> Option<Self> {
         match oid {
             EcCurve::SECP256R1_OID_DER => Some(EcCurve::NistP256),
+            EcCurve::SECP384R1_OID_DER => Some(EcCurve::NistP384),
             _ => None,
         }
     }
 }
+
+/// Helpers for Ed25519 keys, which openssl represents as raw 32-byte public keys rather than EC points.
+pub mod ed25519 {
+    /// The DER encoding of the Ed25519 OID (1.3.101.112), as used in PKCS#11 `CKA_EC_PARAMS`.
+    pub const OID_DER: &[u8] = &[0x06, 0x03, 0x2b, 0x65, 0x70];
+
+    /// The length of an Ed25519 signature.
+    pub const SIGNATURE_LEN: usize = 64;
+
+    /// The DER prefix of an Ed25519 `SubjectPublicKeyInfo`. It is followed by the 32-byte raw public key.
+    const SPKI_PREFIX: &[u8] = &[
+        0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+    ];
+
+    const PUBLIC_KEY_LEN: usize = 32;
+
+    const PRIVATE_KEY_LEN: usize = 32;
+
+    /// Construct an Ed25519 public key from its raw 32-byte encoding.
+    pub fn public_key_from_raw(
+        raw: &[u8],
+    ) -> Result<openssl::pkey::PKey<openssl::pkey::Public>, openssl::error::ErrorStack> {
+        let mut der = Vec::with_capacity(SPKI_PREFIX.len() + raw.len());
+        der.extend_from_slice(SPKI_PREFIX);
+        der.extend_from_slice(raw);
+        openssl::pkey::PKey::public_key_from_der(&der)
+    }
+
+    /// Get the raw 32-byte encoding of the given Ed25519 key.
+    pub fn public_key_to_raw<T>(
+        key: &openssl::pkey::PKeyRef<T>,
+    ) -> Result<Vec<u8>, openssl::error::ErrorStack>
+    where
+        T: openssl::pkey::HasPublic,
+    {
+        let der = key.public_key_to_der()?;
+        Ok(der[(der.len().saturating_sub(PUBLIC_KEY_LEN))..].to_vec())
+    }
+
+    /// Get the raw 32-byte encoding of the given Ed25519 private key.
+    pub fn private_key_to_raw(
+        key: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
+    ) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+        // Ed25519 private keys are always serialized as PKCS#8, which ends with the raw private key.
+        let der = key.private_key_to_der()?;
+        Ok(der[(der.len().saturating_sub(PRIVATE_KEY_LEN))..].to_vec())
+    }
+}
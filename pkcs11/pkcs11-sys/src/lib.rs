@@ -95,19 +95,33 @@ pub type CK_ATTRIBUTE_PTR_CONST = *const CK_ATTRIBUTE_IN;
 // CK_ATTRIBUTE_TYPE
 
 define_enum!(CK_ATTRIBUTE_TYPE {
+    CKA_CERTIFICATE_TYPE = 0x0000_0080,
     CKA_CLASS = 0x0000_0000,
+    CKA_COEFFICIENT = 0x0000_0128,
     CKA_DECRYPT = 0x0000_0105,
+    CKA_DERIVE = 0x0000_010c,
     CKA_EC_PARAMS = 0x0000_0180,
     CKA_EC_POINT = 0x0000_0181,
     CKA_ENCRYPT = 0x0000_0104,
+    CKA_EXPONENT_1 = 0x0000_0126,
+    CKA_EXPONENT_2 = 0x0000_0127,
+    CKA_EXTRACTABLE = 0x0000_0162,
+    CKA_ID = 0x0000_0102,
+    CKA_ISSUER = 0x0000_0081,
     CKA_KEY_TYPE = 0x0000_0100,
     CKA_LABEL = 0x0000_0003,
+    CKA_LOCAL = 0x0000_0163,
     CKA_MODULUS = 0x0000_0120,
     CKA_MODULUS_BITS = 0x0000_0121,
+    CKA_PRIME_1 = 0x0000_0124,
+    CKA_PRIME_2 = 0x0000_0125,
     CKA_PRIVATE = 0x0000_0002,
+    CKA_PRIVATE_EXPONENT = 0x0000_0123,
     CKA_PUBLIC_EXPONENT = 0x0000_0122,
     CKA_SENSITIVE = 0x0000_0103,
+    CKA_SERIAL_NUMBER = 0x0000_0082,
     CKA_SIGN = 0x0000_0108,
+    CKA_SUBJECT = 0x0000_0101,
     CKA_TOKEN = 0x0000_0001,
     CKA_VALUE = 0x0000_0011,
     CKA_VALUE_LEN = 0x0000_0161,
@@ -130,6 +144,12 @@ pub type CK_BYTE = u8;
 pub type CK_BYTE_PTR = *mut CK_BYTE;
 pub type CK_BYTE_PTR_CONST = *const CK_BYTE;
 
+// CK_CERTIFICATE_TYPE
+
+define_enum!(CK_CERTIFICATE_TYPE {
+    CKC_X_509 = 0x0000_0000,
+});
+
 // CK_CHAR
 
 pub type CK_CHAR = CK_BYTE;
@@ -176,6 +196,60 @@ impl CK_TOKEN_INFO_FLAGS {
 
 pub const CKF_TOKEN_INITIALIZED: CK_TOKEN_INFO_FLAGS = CK_TOKEN_INFO_FLAGS(0x0000_0400);
 
+#[derive(Clone, Copy, Debug)]
+#[repr(transparent)]
+pub struct CK_SLOT_INFO_FLAGS(CK_ULONG);
+
+impl CK_SLOT_INFO_FLAGS {
+    pub fn has(self, other: Self) -> bool {
+        (self.0 & other.0) != 0
+    }
+}
+
+pub const CKF_TOKEN_PRESENT: CK_SLOT_INFO_FLAGS = CK_SLOT_INFO_FLAGS(0x0000_0001);
+pub const CKF_REMOVABLE_DEVICE: CK_SLOT_INFO_FLAGS = CK_SLOT_INFO_FLAGS(0x0000_0002);
+pub const CKF_HW_SLOT: CK_SLOT_INFO_FLAGS = CK_SLOT_INFO_FLAGS(0x0000_0004);
+
+#[derive(Clone, Copy, Debug)]
+#[repr(transparent)]
+pub struct CK_MECHANISM_INFO_FLAGS(CK_ULONG);
+
+impl CK_MECHANISM_INFO_FLAGS {
+    pub fn has(self, other: Self) -> bool {
+        (self.0 & other.0) != 0
+    }
+}
+
+pub const CKF_HW: CK_MECHANISM_INFO_FLAGS = CK_MECHANISM_INFO_FLAGS(0x0000_0001);
+pub const CKF_ENCRYPT: CK_MECHANISM_INFO_FLAGS = CK_MECHANISM_INFO_FLAGS(0x0000_0100);
+pub const CKF_DECRYPT: CK_MECHANISM_INFO_FLAGS = CK_MECHANISM_INFO_FLAGS(0x0000_0200);
+pub const CKF_DIGEST: CK_MECHANISM_INFO_FLAGS = CK_MECHANISM_INFO_FLAGS(0x0000_0400);
+pub const CKF_SIGN: CK_MECHANISM_INFO_FLAGS = CK_MECHANISM_INFO_FLAGS(0x0000_0800);
+pub const CKF_VERIFY: CK_MECHANISM_INFO_FLAGS = CK_MECHANISM_INFO_FLAGS(0x0000_2000);
+pub const CKF_GENERATE: CK_MECHANISM_INFO_FLAGS = CK_MECHANISM_INFO_FLAGS(0x0000_8000);
+pub const CKF_GENERATE_KEY_PAIR: CK_MECHANISM_INFO_FLAGS = CK_MECHANISM_INFO_FLAGS(0x0001_0000);
+pub const CKF_WRAP: CK_MECHANISM_INFO_FLAGS = CK_MECHANISM_INFO_FLAGS(0x0002_0000);
+pub const CKF_UNWRAP: CK_MECHANISM_INFO_FLAGS = CK_MECHANISM_INFO_FLAGS(0x0004_0000);
+pub const CKF_DERIVE: CK_MECHANISM_INFO_FLAGS = CK_MECHANISM_INFO_FLAGS(0x0008_0000);
+
+// CK_EC_KDF_TYPE
+
+define_enum!(CK_EC_KDF_TYPE {
+    CKD_NULL = 0x0000_0001,
+});
+
+// CK_ECDH1_DERIVE_PARAMS
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct CK_ECDH1_DERIVE_PARAMS {
+    pub kdf: CK_EC_KDF_TYPE,
+    pub ulSharedDataLen: CK_ULONG,
+    pub pSharedData: CK_BYTE_PTR_CONST,
+    pub ulPublicDataLen: CK_ULONG,
+    pub pPublicData: CK_BYTE_PTR_CONST,
+}
+
 // CK_FUNCTION_LIST
 
 // Note: The spec requires these to all be non-null; unimplemented functions must still exist
@@ -195,11 +269,12 @@ pub struct CK_FUNCTION_LIST {
 
     pub C_GetSlotList: Option<CK_C_GetSlotList>,
 
-    _unused2: [Option<unsafe extern "C" fn()>; 1],
-
+    pub C_GetSlotInfo: Option<CK_C_GetSlotInfo>,
     pub C_GetTokenInfo: Option<CK_C_GetTokenInfo>,
+    pub C_GetMechanismList: Option<CK_C_GetMechanismList>,
+    pub C_GetMechanismInfo: Option<CK_C_GetMechanismInfo>,
 
-    _unused3: [Option<unsafe extern "C" fn()>; 5],
+    _unused3: [Option<unsafe extern "C" fn()>; 3],
 
     pub C_OpenSession: Option<CK_C_OpenSession>,
     pub C_CloseSession: Option<CK_C_CloseSession>,
@@ -223,26 +298,28 @@ pub struct CK_FUNCTION_LIST {
     _unused8: [Option<unsafe extern "C" fn()>; 1],
 
     pub C_GetAttributeValue: Option<CK_C_GetAttributeValue>,
-
-    _unused9: [Option<unsafe extern "C" fn()>; 1],
+    pub C_SetAttributeValue: Option<CK_C_SetAttributeValue>,
 
     pub C_FindObjectsInit: Option<CK_C_FindObjectsInit>,
     pub C_FindObjects: Option<CK_C_FindObjects>,
     pub C_FindObjectsFinal: Option<CK_C_FindObjectsFinal>,
     pub C_EncryptInit: Option<CK_C_EncryptInit>,
     pub C_Encrypt: Option<CK_C_Encrypt>,
-
-    _unused10: [Option<unsafe extern "C" fn()>; 2],
-
+    pub C_EncryptUpdate: Option<CK_C_EncryptUpdate>,
+    pub C_EncryptFinal: Option<CK_C_EncryptFinal>,
     pub C_DecryptInit: Option<CK_C_DecryptInit>,
     pub C_Decrypt: Option<CK_C_Decrypt>,
+    pub C_DecryptUpdate: Option<CK_C_DecryptUpdate>,
+    pub C_DecryptFinal: Option<CK_C_DecryptFinal>,
 
-    _unused11: [Option<unsafe extern "C" fn()>; 7],
+    _unused11: [Option<unsafe extern "C" fn()>; 5],
 
     pub C_SignInit: Option<CK_C_SignInit>,
     pub C_Sign: Option<CK_C_Sign>,
+    pub C_SignUpdate: Option<CK_C_SignUpdate>,
+    pub C_SignFinal: Option<CK_C_SignFinal>,
 
-    _unused12: [Option<unsafe extern "C" fn()>; 4],
+    _unused12: [Option<unsafe extern "C" fn()>; 2],
 
     pub C_VerifyInit: Option<CK_C_VerifyInit>,
     pub C_Verify: Option<CK_C_Verify>,
@@ -252,7 +329,11 @@ pub struct CK_FUNCTION_LIST {
     pub C_GenerateKey: Option<CK_C_GenerateKey>,
     pub C_GenerateKeyPair: Option<CK_C_GenerateKeyPair>,
 
-    _unused14: [Option<unsafe extern "C" fn()>; 8],
+    _unused14: [Option<unsafe extern "C" fn()>; 2],
+
+    pub C_DeriveKey: Option<CK_C_DeriveKey>,
+
+    _unused15: [Option<unsafe extern "C" fn()>; 5],
 }
 
 pub type CK_FUNCTION_LIST_PTR_CONST = *const CK_FUNCTION_LIST;
@@ -307,10 +388,20 @@ pub type CK_C_INITIALIZE_ARGS_PTR = *const CK_C_INITIALIZE_ARGS;
 define_enum!(CK_KEY_TYPE {
     CKK_AES = 0x0000_001f,
     CKK_EC = 0x0000_0003,
+    CKK_EC_EDWARDS = 0x0000_0040,
     CKK_GENERIC_SECRET = 0x0000_0010,
     CKK_RSA = 0x0000_0000,
 });
 
+// CK_AES_CTR_PARAMS
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct CK_AES_CTR_PARAMS {
+    pub ulCounterBits: CK_ULONG,
+    pub cb: [CK_BYTE; 16],
+}
+
 // CK_GCM_PARAMS
 
 #[derive(Debug)]
@@ -340,20 +431,47 @@ pub struct CK_MECHANISM_IN {
 
 pub type CK_MECHANISM_PTR_CONST = *const CK_MECHANISM_IN;
 
+// CK_MECHANISM_INFO
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct CK_MECHANISM_INFO {
+    pub ulMinKeySize: CK_ULONG,
+    pub ulMaxKeySize: CK_ULONG,
+    pub flags: CK_MECHANISM_INFO_FLAGS,
+}
+
+pub type CK_MECHANISM_INFO_PTR = *mut CK_MECHANISM_INFO;
+
 // CK_MECHANISM_TYPE
 
 define_enum!(CK_MECHANISM_TYPE {
     CKM_AES_KEY_GEN = 0x0000_1080,
     CKM_EC_KEY_PAIR_GEN = 0x0000_1040,
     CKM_ECDSA = 0x0000_1041,
+    CKM_ECDH1_DERIVE = 0x0000_1050,
+    CKM_EC_EDWARDS_KEY_PAIR_GEN = 0x0000_1055,
+    CKM_EDDSA = 0x0000_1057,
+    CKM_AES_CBC_PAD = 0x0000_1085,
+    CKM_AES_CTR = 0x0000_1086,
     CKM_AES_GCM = 0x0000_1087,
+    CKM_AES_KEY_WRAP = 0x0000_2109,
+    CKM_AES_KEY_WRAP_KWP = 0x0000_210b,
     CKM_RSA_PKCS = 0x0000_0001,
     CKM_RSA_X509 = 0x0000_0003,
+    CKM_RSA_PKCS_PSS = 0x0000_000d,
     CKM_RSA_PKCS_KEY_PAIR_GEN = 0x0000_0000,
     CKM_GENERIC_SECRET_KEY_GEN = 0x0000_0350,
+    CKM_SHA256 = 0x0000_0250,
     CKM_SHA256_HMAC = 0x0000_0251,
+    CKM_SHA384 = 0x0000_0260,
+    CKM_SHA384_HMAC = 0x0000_0261,
+    CKM_SHA512 = 0x0000_0270,
+    CKM_SHA512_HMAC = 0x0000_0271,
 });
 
+pub type CK_MECHANISM_TYPE_PTR = *mut CK_MECHANISM_TYPE;
+
 // CK_NOTIFICATION
 
 #[derive(Clone, Copy, Debug)]
@@ -363,6 +481,8 @@ pub struct CK_NOTIFICATION(CK_ULONG);
 // CK_OBJECT_CLASS
 
 define_enum!(CK_OBJECT_CLASS {
+    CKO_DATA = 0x0000_0000,
+    CKO_CERTIFICATE = 0x0000_0001,
     CKO_PUBLIC_KEY = 0x0000_0002,
     CKO_PRIVATE_KEY = 0x0000_0003,
     CKO_SECRET_KEY = 0x0000_0004,
@@ -388,6 +508,16 @@ define_enum!(CK_RSA_PKCS_MGF_TYPE {
     CKG_MGF1_SHA224 = 0x0000_0005,
 });
 
+// CK_RSA_PKCS_PSS_PARAMS
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct CK_RSA_PKCS_PSS_PARAMS {
+    pub hashAlg: CK_MECHANISM_TYPE,
+    pub mgf: CK_RSA_PKCS_MGF_TYPE,
+    pub sLen: CK_ULONG,
+}
+
 // CK_RV
 
 define_enum!(CK_RV {
@@ -495,6 +625,20 @@ impl std::str::FromStr for CK_SLOT_ID {
 
 pub type CK_SLOT_ID_PTR = *mut CK_SLOT_ID;
 
+// CK_SLOT_INFO
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct CK_SLOT_INFO {
+    pub slotDescription: [CK_UTF8CHAR; 64],
+    pub manufacturerID: [CK_UTF8CHAR; 32],
+    pub flags: CK_SLOT_INFO_FLAGS,
+    pub hardwareVersion: CK_VERSION,
+    pub firmwareVersion: CK_VERSION,
+}
+
+pub type CK_SLOT_INFO_PTR = *mut CK_SLOT_INFO;
+
 // CK_STATE
 
 define_enum!(CK_STATE {
@@ -540,6 +684,9 @@ pub type CK_ULONG = std::os::raw::c_ulong;
 
 pub type CK_ULONG_PTR = *mut CK_ULONG;
 
+/// The value of `ulValueLen` when an attribute's value can't be returned.
+pub const CK_UNAVAILABLE_INFORMATION: CK_ULONG = !0;
+
 // CK_USER_TYPE
 
 define_enum!(CK_USER_TYPE {
@@ -591,11 +738,31 @@ pub type CK_C_Decrypt = unsafe extern "C" fn(
     pData: CK_BYTE_PTR,
     pulDataLen: CK_ULONG_PTR,
 ) -> CK_RV;
+pub type CK_C_DecryptFinal = unsafe extern "C" fn(
+    hSession: CK_SESSION_HANDLE,
+    pLastPart: CK_BYTE_PTR,
+    pulLastPartLen: CK_ULONG_PTR,
+) -> CK_RV;
 pub type CK_C_DecryptInit = unsafe extern "C" fn(
     hSession: CK_SESSION_HANDLE,
     pMechanism: CK_MECHANISM_PTR_CONST,
     hKey: CK_OBJECT_HANDLE,
 ) -> CK_RV;
+pub type CK_C_DecryptUpdate = unsafe extern "C" fn(
+    hSession: CK_SESSION_HANDLE,
+    pEncryptedPart: CK_BYTE_PTR_CONST,
+    ulEncryptedPartLen: CK_ULONG,
+    pPart: CK_BYTE_PTR,
+    pulPartLen: CK_ULONG_PTR,
+) -> CK_RV;
+pub type CK_C_DeriveKey = unsafe extern "C" fn(
+    hSession: CK_SESSION_HANDLE,
+    pMechanism: CK_MECHANISM_PTR_CONST,
+    hBaseKey: CK_OBJECT_HANDLE,
+    pTemplate: CK_ATTRIBUTE_PTR_CONST,
+    ulAttributeCount: CK_ULONG,
+    phKey: CK_OBJECT_HANDLE_PTR,
+) -> CK_RV;
 pub type CK_C_DestroyObject =
     unsafe extern "C" fn(hSession: CK_SESSION_HANDLE, hObject: CK_OBJECT_HANDLE) -> CK_RV;
 pub type CK_C_Encrypt = unsafe extern "C" fn(
@@ -605,11 +772,23 @@ pub type CK_C_Encrypt = unsafe extern "C" fn(
     pEncryptedData: CK_BYTE_PTR,
     pulEncryptedDataLen: CK_ULONG_PTR,
 ) -> CK_RV;
+pub type CK_C_EncryptFinal = unsafe extern "C" fn(
+    hSession: CK_SESSION_HANDLE,
+    pLastEncryptedPart: CK_BYTE_PTR,
+    pulLastEncryptedPartLen: CK_ULONG_PTR,
+) -> CK_RV;
 pub type CK_C_EncryptInit = unsafe extern "C" fn(
     hSession: CK_SESSION_HANDLE,
     pMechanism: CK_MECHANISM_PTR_CONST,
     hKey: CK_OBJECT_HANDLE,
 ) -> CK_RV;
+pub type CK_C_EncryptUpdate = unsafe extern "C" fn(
+    hSession: CK_SESSION_HANDLE,
+    pPart: CK_BYTE_PTR_CONST,
+    ulPartLen: CK_ULONG,
+    pEncryptedPart: CK_BYTE_PTR,
+    pulEncryptedPartLen: CK_ULONG_PTR,
+) -> CK_RV;
 pub type CK_C_Finalize = unsafe extern "C" fn(pReserved: CK_VOID_PTR) -> CK_RV;
 pub type CK_C_FindObjects = unsafe extern "C" fn(
     hSession: CK_SESSION_HANDLE,
@@ -649,6 +828,16 @@ pub type CK_C_GetAttributeValue = unsafe extern "C" fn(
 pub type CK_C_GetFunctionList =
     unsafe extern "C" fn(ppFunctionList: CK_FUNCTION_LIST_PTR_PTR) -> CK_RV;
 pub type CK_C_GetInfo = unsafe extern "C" fn(pInfo: CK_INFO_PTR) -> CK_RV;
+pub type CK_C_GetMechanismInfo = unsafe extern "C" fn(
+    slotID: CK_SLOT_ID,
+    r#type: CK_MECHANISM_TYPE,
+    pInfo: CK_MECHANISM_INFO_PTR,
+) -> CK_RV;
+pub type CK_C_GetMechanismList = unsafe extern "C" fn(
+    slotID: CK_SLOT_ID,
+    pMechanismList: CK_MECHANISM_TYPE_PTR,
+    pulCount: CK_ULONG_PTR,
+) -> CK_RV;
 pub type CK_C_GetSessionInfo =
     unsafe extern "C" fn(hSession: CK_SESSION_HANDLE, pInfo: CK_SESSION_INFO_PTR) -> CK_RV;
 pub type CK_C_GetSlotList = unsafe extern "C" fn(
@@ -656,6 +845,8 @@ pub type CK_C_GetSlotList = unsafe extern "C" fn(
     pSlotList: CK_SLOT_ID_PTR,
     pulCount: CK_ULONG_PTR,
 ) -> CK_RV;
+pub type CK_C_GetSlotInfo =
+    unsafe extern "C" fn(slotID: CK_SLOT_ID, pInfo: CK_SLOT_INFO_PTR) -> CK_RV;
 pub type CK_C_GetTokenInfo =
     unsafe extern "C" fn(slotID: CK_SLOT_ID, pInfo: CK_TOKEN_INFO_PTR) -> CK_RV;
 pub type CK_C_Initialize = unsafe extern "C" fn(pReserved: CK_C_INITIALIZE_ARGS_PTR) -> CK_RV;
@@ -672,6 +863,12 @@ pub type CK_C_OpenSession = unsafe extern "C" fn(
     Notify: Option<CK_NOTIFY>,
     phSession: CK_SESSION_HANDLE_PTR,
 ) -> CK_RV;
+pub type CK_C_SetAttributeValue = unsafe extern "C" fn(
+    hSession: CK_SESSION_HANDLE,
+    hObject: CK_OBJECT_HANDLE,
+    pTemplate: CK_ATTRIBUTE_PTR_CONST,
+    ulCount: CK_ULONG,
+) -> CK_RV;
 pub type CK_C_Sign = unsafe extern "C" fn(
     hSession: CK_SESSION_HANDLE,
     pData: CK_BYTE_PTR_CONST,
@@ -679,11 +876,21 @@ pub type CK_C_Sign = unsafe extern "C" fn(
     pSignature: CK_BYTE_PTR,
     pulSignatureLen: CK_ULONG_PTR,
 ) -> CK_RV;
+pub type CK_C_SignFinal = unsafe extern "C" fn(
+    hSession: CK_SESSION_HANDLE,
+    pSignature: CK_BYTE_PTR,
+    pulSignatureLen: CK_ULONG_PTR,
+) -> CK_RV;
 pub type CK_C_SignInit = unsafe extern "C" fn(
     hSession: CK_SESSION_HANDLE,
     pMechanism: CK_MECHANISM_PTR_CONST,
     hKey: CK_OBJECT_HANDLE,
 ) -> CK_RV;
+pub type CK_C_SignUpdate = unsafe extern "C" fn(
+    hSession: CK_SESSION_HANDLE,
+    pPart: CK_BYTE_PTR_CONST,
+    ulPartLen: CK_ULONG,
+) -> CK_RV;
 pub type CK_C_Verify = unsafe extern "C" fn(
     hSession: CK_SESSION_HANDLE,
     pData: CK_BYTE_PTR_CONST,
@@ -30,10 +30,15 @@ pub struct Context {
     pub(crate) C_CloseSession: pkcs11_sys::CK_C_CloseSession,
     pub(crate) C_CreateObject: pkcs11_sys::CK_C_CreateObject,
     pub(crate) C_Decrypt: pkcs11_sys::CK_C_Decrypt,
+    pub(crate) C_DecryptFinal: pkcs11_sys::CK_C_DecryptFinal,
     pub(crate) C_DecryptInit: pkcs11_sys::CK_C_DecryptInit,
+    pub(crate) C_DecryptUpdate: pkcs11_sys::CK_C_DecryptUpdate,
+    pub(crate) C_DeriveKey: pkcs11_sys::CK_C_DeriveKey,
     pub(crate) C_DestroyObject: pkcs11_sys::CK_C_DestroyObject,
     pub(crate) C_Encrypt: pkcs11_sys::CK_C_Encrypt,
+    pub(crate) C_EncryptFinal: pkcs11_sys::CK_C_EncryptFinal,
     pub(crate) C_EncryptInit: pkcs11_sys::CK_C_EncryptInit,
+    pub(crate) C_EncryptUpdate: pkcs11_sys::CK_C_EncryptUpdate,
     C_Finalize: Option<pkcs11_sys::CK_C_Finalize>,
     pub(crate) C_FindObjects: pkcs11_sys::CK_C_FindObjects,
     pub(crate) C_FindObjectsFinal: pkcs11_sys::CK_C_FindObjectsFinal,
@@ -41,14 +46,20 @@ pub struct Context {
     pub(crate) C_GenerateKey: pkcs11_sys::CK_C_GenerateKey,
     pub(crate) C_GenerateKeyPair: pkcs11_sys::CK_C_GenerateKeyPair,
     pub(crate) C_GetAttributeValue: pkcs11_sys::CK_C_GetAttributeValue,
+    C_GetMechanismInfo: pkcs11_sys::CK_C_GetMechanismInfo,
+    C_GetMechanismList: pkcs11_sys::CK_C_GetMechanismList,
     pub(crate) C_GetSessionInfo: pkcs11_sys::CK_C_GetSessionInfo,
+    C_GetSlotInfo: pkcs11_sys::CK_C_GetSlotInfo,
     C_GetSlotList: pkcs11_sys::CK_C_GetSlotList,
     C_GetTokenInfo: pkcs11_sys::CK_C_GetTokenInfo,
     C_GetInfo: Option<pkcs11_sys::CK_C_GetInfo>,
     pub(crate) C_Login: pkcs11_sys::CK_C_Login,
     C_OpenSession: pkcs11_sys::CK_C_OpenSession,
+    pub(crate) C_SetAttributeValue: pkcs11_sys::CK_C_SetAttributeValue,
     pub(crate) C_Sign: pkcs11_sys::CK_C_Sign,
+    pub(crate) C_SignFinal: pkcs11_sys::CK_C_SignFinal,
     pub(crate) C_SignInit: pkcs11_sys::CK_C_SignInit,
+    pub(crate) C_SignUpdate: pkcs11_sys::CK_C_SignUpdate,
     pub(crate) C_Verify: pkcs11_sys::CK_C_Verify,
     pub(crate) C_VerifyInit: pkcs11_sys::CK_C_VerifyInit,
 }
@@ -125,18 +136,33 @@ impl Context {
             let C_Decrypt = (*function_list)
                 .C_Decrypt
                 .ok_or(LoadContextError::MissingFunction("C_Decrypt"))?;
+            let C_DecryptFinal = (*function_list)
+                .C_DecryptFinal
+                .ok_or(LoadContextError::MissingFunction("C_DecryptFinal"))?;
             let C_DecryptInit = (*function_list)
                 .C_DecryptInit
                 .ok_or(LoadContextError::MissingFunction("C_DecryptInit"))?;
+            let C_DecryptUpdate = (*function_list)
+                .C_DecryptUpdate
+                .ok_or(LoadContextError::MissingFunction("C_DecryptUpdate"))?;
+            let C_DeriveKey = (*function_list)
+                .C_DeriveKey
+                .ok_or(LoadContextError::MissingFunction("C_DeriveKey"))?;
             let C_DestroyObject = (*function_list)
                 .C_DestroyObject
                 .ok_or(LoadContextError::MissingFunction("C_DestroyObject"))?;
             let C_Encrypt = (*function_list)
                 .C_Encrypt
                 .ok_or(LoadContextError::MissingFunction("C_Encrypt"))?;
+            let C_EncryptFinal = (*function_list)
+                .C_EncryptFinal
+                .ok_or(LoadContextError::MissingFunction("C_EncryptFinal"))?;
             let C_EncryptInit = (*function_list)
                 .C_EncryptInit
                 .ok_or(LoadContextError::MissingFunction("C_EncryptInit"))?;
+            let C_EncryptUpdate = (*function_list)
+                .C_EncryptUpdate
+                .ok_or(LoadContextError::MissingFunction("C_EncryptUpdate"))?;
             let C_Finalize = (*function_list).C_Finalize;
             let C_FindObjects = (*function_list)
                 .C_FindObjects
@@ -157,9 +183,18 @@ impl Context {
                 .C_GetAttributeValue
                 .ok_or(LoadContextError::MissingFunction("C_GetAttributeValue"))?;
             let C_GetInfo = (*function_list).C_GetInfo;
+            let C_GetMechanismInfo = (*function_list)
+                .C_GetMechanismInfo
+                .ok_or(LoadContextError::MissingFunction("C_GetMechanismInfo"))?;
+            let C_GetMechanismList = (*function_list)
+                .C_GetMechanismList
+                .ok_or(LoadContextError::MissingFunction("C_GetMechanismList"))?;
             let C_GetSessionInfo = (*function_list)
                 .C_GetSessionInfo
                 .ok_or(LoadContextError::MissingFunction("C_GetSessionInfo"))?;
+            let C_GetSlotInfo = (*function_list)
+                .C_GetSlotInfo
+                .ok_or(LoadContextError::MissingFunction("C_GetSlotInfo"))?;
             let C_GetSlotList = (*function_list)
                 .C_GetSlotList
                 .ok_or(LoadContextError::MissingFunction("C_GetSlotList"))?;
@@ -172,12 +207,21 @@ impl Context {
             let C_OpenSession = (*function_list)
                 .C_OpenSession
                 .ok_or(LoadContextError::MissingFunction("C_OpenSession"))?;
+            let C_SetAttributeValue = (*function_list)
+                .C_SetAttributeValue
+                .ok_or(LoadContextError::MissingFunction("C_SetAttributeValue"))?;
             let C_Sign = (*function_list)
                 .C_Sign
                 .ok_or(LoadContextError::MissingFunction("C_Sign"))?;
+            let C_SignFinal = (*function_list)
+                .C_SignFinal
+                .ok_or(LoadContextError::MissingFunction("C_SignFinal"))?;
             let C_SignInit = (*function_list)
                 .C_SignInit
                 .ok_or(LoadContextError::MissingFunction("C_SignInit"))?;
+            let C_SignUpdate = (*function_list)
+                .C_SignUpdate
+                .ok_or(LoadContextError::MissingFunction("C_SignUpdate"))?;
             let C_Verify = (*function_list)
                 .C_Verify
                 .ok_or(LoadContextError::MissingFunction("C_Verify"))?;
@@ -211,10 +255,15 @@ impl Context {
                 C_CloseSession,
                 C_CreateObject,
                 C_Decrypt,
+                C_DecryptFinal,
                 C_DecryptInit,
+                C_DecryptUpdate,
+                C_DeriveKey,
                 C_DestroyObject,
                 C_Encrypt,
+                C_EncryptFinal,
                 C_EncryptInit,
+                C_EncryptUpdate,
                 C_Finalize,
                 C_FindObjects,
                 C_FindObjectsFinal,
@@ -223,13 +272,19 @@ impl Context {
                 C_GenerateKeyPair,
                 C_GetAttributeValue,
                 C_GetInfo,
+                C_GetMechanismInfo,
+                C_GetMechanismList,
                 C_GetSessionInfo,
+                C_GetSlotInfo,
                 C_GetSlotList,
                 C_GetTokenInfo,
                 C_Login,
                 C_OpenSession,
+                C_SetAttributeValue,
                 C_Sign,
+                C_SignFinal,
                 C_SignInit,
+                C_SignUpdate,
                 C_Verify,
                 C_VerifyInit,
             };
@@ -253,6 +308,77 @@ impl Context {
     }
 }
 
+#[cfg(test)]
+impl Context {
+    /// Create a context that uses the given session functions instead of a PKCS#11 library.
+    ///
+    /// Every other function fails with `CKR_FUNCTION_NOT_SUPPORTED`, so this is only useful for testing session management.
+    #[allow(non_snake_case)]
+    pub(crate) fn with_session_functions(
+        C_CloseSession: pkcs11_sys::CK_C_CloseSession,
+        C_GetSessionInfo: pkcs11_sys::CK_C_GetSessionInfo,
+        C_Login: pkcs11_sys::CK_C_Login,
+        C_OpenSession: pkcs11_sys::CK_C_OpenSession,
+    ) -> Self {
+        unsafe extern "C" fn not_supported() -> pkcs11_sys::CK_RV {
+            pkcs11_sys::CKR_FUNCTION_NOT_SUPPORTED
+        }
+
+        macro_rules! not_called {
+            ($ty:ident) => {
+                unsafe {
+                    std::mem::transmute::<
+                        unsafe extern "C" fn() -> pkcs11_sys::CK_RV,
+                        pkcs11_sys::$ty,
+                    >(not_supported)
+                }
+            };
+        }
+
+        Context {
+            sessions: Default::default(),
+
+            _library: crate::dl::Library::this_process(),
+
+            C_CloseSession,
+            C_CreateObject: not_called!(CK_C_CreateObject),
+            C_Decrypt: not_called!(CK_C_Decrypt),
+            C_DecryptFinal: not_called!(CK_C_DecryptFinal),
+            C_DecryptInit: not_called!(CK_C_DecryptInit),
+            C_DecryptUpdate: not_called!(CK_C_DecryptUpdate),
+            C_DeriveKey: not_called!(CK_C_DeriveKey),
+            C_DestroyObject: not_called!(CK_C_DestroyObject),
+            C_Encrypt: not_called!(CK_C_Encrypt),
+            C_EncryptFinal: not_called!(CK_C_EncryptFinal),
+            C_EncryptInit: not_called!(CK_C_EncryptInit),
+            C_EncryptUpdate: not_called!(CK_C_EncryptUpdate),
+            C_Finalize: None,
+            C_FindObjects: not_called!(CK_C_FindObjects),
+            C_FindObjectsFinal: not_called!(CK_C_FindObjectsFinal),
+            C_FindObjectsInit: not_called!(CK_C_FindObjectsInit),
+            C_GenerateKey: not_called!(CK_C_GenerateKey),
+            C_GenerateKeyPair: not_called!(CK_C_GenerateKeyPair),
+            C_GetAttributeValue: not_called!(CK_C_GetAttributeValue),
+            C_GetInfo: None,
+            C_GetMechanismInfo: not_called!(CK_C_GetMechanismInfo),
+            C_GetMechanismList: not_called!(CK_C_GetMechanismList),
+            C_GetSessionInfo,
+            C_GetSlotInfo: not_called!(CK_C_GetSlotInfo),
+            C_GetSlotList: not_called!(CK_C_GetSlotList),
+            C_GetTokenInfo: not_called!(CK_C_GetTokenInfo),
+            C_Login,
+            C_OpenSession,
+            C_SetAttributeValue: not_called!(CK_C_SetAttributeValue),
+            C_Sign: not_called!(CK_C_Sign),
+            C_SignFinal: not_called!(CK_C_SignFinal),
+            C_SignInit: not_called!(CK_C_SignInit),
+            C_SignUpdate: not_called!(CK_C_SignUpdate),
+            C_Verify: not_called!(CK_C_Verify),
+            C_VerifyInit: not_called!(CK_C_VerifyInit),
+        }
+    }
+}
+
 /// An error from loading a PKCS#11 library and creating a context.
 #[derive(Debug)]
 pub enum LoadContextError {
@@ -497,6 +623,209 @@ impl std::fmt::Display for GetTokenInfoError {
 
 impl std::error::Error for GetTokenInfoError {}
 
+impl Context {
+    /// Get the info of this slot.
+    pub fn slot_info(
+        &self,
+        slot_id: pkcs11_sys::CK_SLOT_ID,
+    ) -> Result<pkcs11_sys::CK_SLOT_INFO, GetSlotInfoError> {
+        unsafe {
+            let mut info = std::mem::MaybeUninit::uninit();
+
+            let result = (self.C_GetSlotInfo)(slot_id, info.as_mut_ptr());
+            if result != pkcs11_sys::CKR_OK {
+                return Err(GetSlotInfoError::GetSlotInfo(result));
+            }
+
+            let info = info.assume_init();
+            Ok(info)
+        }
+    }
+}
+
+/// An error from getting a slot's info.
+#[derive(Debug)]
+pub enum GetSlotInfoError {
+    GetSlotInfo(pkcs11_sys::CK_RV),
+}
+
+impl std::fmt::Display for GetSlotInfoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GetSlotInfoError::GetSlotInfo(result) => {
+                write!(f, "C_GetSlotInfo failed with {}", result)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GetSlotInfoError {}
+
+impl Context {
+    /// Get the mechanisms supported by the token in this slot, along with their info.
+    pub fn mechanisms(
+        &self,
+        slot_id: pkcs11_sys::CK_SLOT_ID,
+    ) -> Result<
+        Vec<(pkcs11_sys::CK_MECHANISM_TYPE, pkcs11_sys::CK_MECHANISM_INFO)>,
+        ListMechanismsError,
+    > {
+        // Like C_GetSlotList, C_GetMechanismList reports the required length with CKR_BUFFER_TOO_SMALL if the buffer is too small.
+        // See the comment in `Context::slots` for details.
+
+        unsafe {
+            let mut mechanism_types = vec![];
+
+            loop {
+                let mut actual_len = std::convert::TryInto::try_into(mechanism_types.len())
+                    .expect("usize -> CK_ULONG");
+                let result = (self.C_GetMechanismList)(
+                    slot_id,
+                    mechanism_types.as_mut_ptr(),
+                    &mut actual_len,
+                );
+                match result {
+                    pkcs11_sys::CKR_OK => {
+                        let actual_len =
+                            std::convert::TryInto::try_into(actual_len).expect("CK_ULONG -> usize");
+
+                        // If mechanism_types.len() < actual_len, then the PKCS#11 library has scribbled past the end of the buffer.
+                        // This is not safe to recover from.
+                        assert!(mechanism_types.len() >= actual_len);
+
+                        mechanism_types.truncate(actual_len);
+                        break;
+                    }
+
+                    pkcs11_sys::CKR_BUFFER_TOO_SMALL => {
+                        let actual_len =
+                            std::convert::TryInto::try_into(actual_len).expect("CK_ULONG -> usize");
+
+                        // The initial value doesn't matter since the library overwrites it.
+                        mechanism_types.resize(actual_len, pkcs11_sys::CKM_RSA_PKCS_KEY_PAIR_GEN);
+
+                        continue;
+                    }
+
+                    result => return Err(ListMechanismsError::GetMechanismList(result)),
+                }
+            }
+
+            let mut mechanisms = Vec::with_capacity(mechanism_types.len());
+            for mechanism_type in mechanism_types {
+                let mut info = std::mem::MaybeUninit::uninit();
+
+                let result = (self.C_GetMechanismInfo)(slot_id, mechanism_type, info.as_mut_ptr());
+                if result != pkcs11_sys::CKR_OK {
+                    return Err(ListMechanismsError::GetMechanismInfo(
+                        mechanism_type,
+                        result,
+                    ));
+                }
+
+                let info = info.assume_init();
+                mechanisms.push((mechanism_type, info));
+            }
+
+            Ok(mechanisms)
+        }
+    }
+}
+
+/// An error from listing the mechanisms supported by a token.
+#[derive(Debug)]
+pub enum ListMechanismsError {
+    GetMechanismInfo(pkcs11_sys::CK_MECHANISM_TYPE, pkcs11_sys::CK_RV),
+    GetMechanismList(pkcs11_sys::CK_RV),
+}
+
+impl std::fmt::Display for ListMechanismsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListMechanismsError::GetMechanismInfo(mechanism_type, result) => write!(
+                f,
+                "C_GetMechanismInfo({}) failed with {}",
+                mechanism_type, result
+            ),
+            ListMechanismsError::GetMechanismList(result) => {
+                write!(f, "C_GetMechanismList failed with {}", result)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ListMechanismsError {}
+
+impl Context {
+    /// Describe all the slots managed by this library that have a token, along with their tokens and the mechanisms those tokens support.
+    pub fn describe_slots(&self) -> Result<Vec<SlotDescription>, DescribeSlotsError> {
+        let mut result = vec![];
+
+        for slot_id in self.slots().map_err(DescribeSlotsError::ListSlots)? {
+            let slot_info = self
+                .slot_info(slot_id)
+                .map_err(DescribeSlotsError::GetSlotInfo)?;
+            let token_info = self
+                .token_info(slot_id)
+                .map_err(DescribeSlotsError::GetTokenInfo)?;
+            let mechanisms = self
+                .mechanisms(slot_id)
+                .map_err(DescribeSlotsError::ListMechanisms)?;
+
+            result.push(SlotDescription {
+                slot_id,
+                slot_info,
+                token_info,
+                mechanisms,
+            });
+        }
+
+        Ok(result)
+    }
+}
+
+/// A slot, as returned by [`Context::describe_slots`].
+#[derive(Debug)]
+pub struct SlotDescription {
+    pub slot_id: pkcs11_sys::CK_SLOT_ID,
+    pub slot_info: pkcs11_sys::CK_SLOT_INFO,
+    pub token_info: pkcs11_sys::CK_TOKEN_INFO,
+
+    /// The mechanisms supported by the token, along with their info.
+    pub mechanisms: Vec<(pkcs11_sys::CK_MECHANISM_TYPE, pkcs11_sys::CK_MECHANISM_INFO)>,
+}
+
+/// An error from describing all the slots managed by this library.
+#[derive(Debug)]
+pub enum DescribeSlotsError {
+    GetSlotInfo(GetSlotInfoError),
+    GetTokenInfo(GetTokenInfoError),
+    ListMechanisms(ListMechanismsError),
+    ListSlots(ListSlotsError),
+}
+
+impl std::fmt::Display for DescribeSlotsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DescribeSlotsError::GetSlotInfo(_) => f.write_str("could not get slot info"),
+            DescribeSlotsError::GetTokenInfo(_) => f.write_str("could not get token info"),
+            DescribeSlotsError::ListMechanisms(_) => f.write_str("could not list mechanisms"),
+            DescribeSlotsError::ListSlots(_) => f.write_str("could not list slots"),
+        }
+    }
+}
+
+impl std::error::Error for DescribeSlotsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DescribeSlotsError::GetSlotInfo(inner) => Some(inner),
+            DescribeSlotsError::GetTokenInfo(inner) => Some(inner),
+            DescribeSlotsError::ListMechanisms(inner) => Some(inner),
+            DescribeSlotsError::ListSlots(inner) => Some(inner),
+        }
+    }
+}
+
 impl Context {
     /// Open a read-write session against the token in this slot.
     ///
@@ -525,7 +854,21 @@ impl Context {
         })
     }
 
-    fn open_session_inner(
+    /// Open a new read-write session against the token in this slot that is not shared with the rest of the application.
+    ///
+    /// Multi-part operations like [`crate::Object::encrypt_init`] keep their state in the session, and a session can only have
+    /// one such operation active at a time. So objects used for multi-part operations should be loaded from a session
+    /// returned by this API instead of [`Context::open_session`].
+    pub fn open_dedicated_session(
+        self: std::sync::Arc<Self>,
+        slot_id: pkcs11_sys::CK_SLOT_ID,
+        pin: Option<String>,
+    ) -> Result<std::sync::Arc<crate::Session>, OpenSessionError> {
+        let session = self.open_session_inner(slot_id, pin)?;
+        Ok(std::sync::Arc::new(session))
+    }
+
+    pub(crate) fn open_session_inner(
         self: std::sync::Arc<Self>,
         slot_id: pkcs11_sys::CK_SLOT_ID,
         pin: Option<String>,
@@ -19,6 +19,16 @@ impl Library {
         Ok(Library { handle })
     }
 
+    /// Get a handle to the current process, for tests that don't load a real library.
+    #[cfg(test)]
+    pub(crate) fn this_process() -> Self {
+        unsafe {
+            let handle = libc::dlopen(std::ptr::null(), libc::RTLD_LAZY | libc::RTLD_LOCAL);
+            assert!(!handle.is_null(), "{}", dlerror());
+            Library { handle }
+        }
+    }
+
     /// Obtain a symbol from this library of the specified type.
     pub(crate) unsafe fn symbol<'library, F>(
         &'library self,
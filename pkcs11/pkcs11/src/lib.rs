@@ -17,20 +17,28 @@
 //! A Rust wrapper to consume a PKCS#11 library. Create a [`Context`] with [`Context::load`] to get started.
 
 mod context;
-pub use context::{Context, GetTokenInfoError, ListSlotsError, LoadContextError, OpenSessionError};
+pub use context::{
+    Context, DescribeSlotsError, FindSlotError, GetSlotInfoError, GetTokenInfoError,
+    ListMechanismsError, ListSlotsError, LoadContextError, OpenSessionError, SlotDescription,
+};
 
 mod dl;
 
 mod object;
 pub use object::{
-    DecryptError, EncryptError, GetKeyParametersError, Object, RsaSignMechanism, SignError,
-    VerifyError,
+    AesMechanism, DecryptError, DeriveError, DigestAlgorithm, EncryptError, GetKeyParametersError,
+    KeyAttributes, Object, RsaSignMechanism, SignError, VerifyError,
 };
 
+mod pool;
+pub use pool::{GetSessionError, SessionPool, SessionPoolMetrics, WithSessionError};
+
 mod session;
 pub use session::{
-    FindObjectsError, GenerateKeyError, GenerateKeyPairError, GetKeyError, ImportKeyError, Key,
-    KeyPair, KeyUsage, LoginError, PublicKey, Session,
+    DeleteCertError, DeleteKeyError, FindObjectsByFilterError, FindObjectsError, FoundObject,
+    GenerateKeyError, GenerateKeyPairError, GetCertError, GetKeyError, ImportCertError,
+    ImportKeyError, ImportKeyPairError, Key, KeyInfo, KeyPair, KeyUsage, ListKeysError, LoginError,
+    ObjectFilter, PublicKey, RenameKeyPairError, Session, TypedObject,
 };
 
 #[derive(Clone, Debug, PartialEq)]
@@ -20,10 +20,45 @@ impl<T> Object<T> {
     }
 }
 
+/// The digest algorithm used by an HMAC or RSA-PSS signature.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    fn hmac_mechanism(self) -> pkcs11_sys::CK_MECHANISM_TYPE {
+        match self {
+            DigestAlgorithm::Sha256 => pkcs11_sys::CKM_SHA256_HMAC,
+            DigestAlgorithm::Sha384 => pkcs11_sys::CKM_SHA384_HMAC,
+            DigestAlgorithm::Sha512 => pkcs11_sys::CKM_SHA512_HMAC,
+        }
+    }
+
+    fn hash_mechanism(self) -> pkcs11_sys::CK_MECHANISM_TYPE {
+        match self {
+            DigestAlgorithm::Sha256 => pkcs11_sys::CKM_SHA256,
+            DigestAlgorithm::Sha384 => pkcs11_sys::CKM_SHA384,
+            DigestAlgorithm::Sha512 => pkcs11_sys::CKM_SHA512,
+        }
+    }
+
+    fn mgf1(self) -> pkcs11_sys::CK_RSA_PKCS_MGF_TYPE {
+        match self {
+            DigestAlgorithm::Sha256 => pkcs11_sys::CKG_MGF1_SHA256,
+            DigestAlgorithm::Sha384 => pkcs11_sys::CKG_MGF1_SHA384,
+            DigestAlgorithm::Sha512 => pkcs11_sys::CKG_MGF1_SHA512,
+        }
+    }
+}
+
 impl Object<()> {
-    /// Use this key to sign the given digest and store the result into the given signature buffer.
+    /// Use this key to compute the HMAC of the given digest with the given digest algorithm and store the result into the given signature buffer.
     pub fn sign(
         &self,
+        digest_algorithm: DigestAlgorithm,
         digest: &[u8],
         signature: &mut [u8],
     ) -> Result<pkcs11_sys::CK_ULONG, SignError> {
@@ -31,21 +66,26 @@ impl Object<()> {
             // Signing with the key needs login
             self.session.login().map_err(SignError::LoginFailed)?;
 
-            let signature_len = sign_inner(
-                &self.session,
-                self.handle,
-                pkcs11_sys::CKM_SHA256_HMAC,
-                digest,
-                signature,
-            )?;
+            let mechanism = pkcs11_sys::CK_MECHANISM_IN {
+                mechanism: digest_algorithm.hmac_mechanism(),
+                pParameter: std::ptr::null(),
+                ulParameterLen: 0,
+            };
+            let signature_len =
+                sign_inner(&self.session, self.handle, &mechanism, digest, signature)?;
             Ok(signature_len)
         }
     }
 }
 
 impl Object<()> {
-    /// Use this key to verify the given digest has the given signature.
-    pub fn verify(&self, digest: &[u8], signature: &[u8]) -> Result<bool, VerifyError> {
+    /// Use this key to verify the given digest has the given HMAC computed with the given digest algorithm.
+    pub fn verify(
+        &self,
+        digest_algorithm: DigestAlgorithm,
+        digest: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, VerifyError> {
         unsafe {
             // Verifying with the key needs login
             self.session.login().map_err(VerifyError::LoginFailed)?;
@@ -53,7 +93,7 @@ impl Object<()> {
             let ok = verify_inner(
                 &self.session,
                 self.handle,
-                pkcs11_sys::CKM_SHA256_HMAC,
+                digest_algorithm.hmac_mechanism(),
                 digest,
                 signature,
             )?;
@@ -63,10 +103,174 @@ impl Object<()> {
 }
 
 impl Object<()> {
+    /// Abandon a multi-part operation started with [`Object::sign_init`], [`Object::encrypt_init`] or [`Object::decrypt_init`]
+    /// that won't be completed.
+    ///
+    /// PKCS#11 2.40 has no way to cancel an active operation, and the session can't start another one until it's completed.
+    /// So the session is closed once it's no longer used, instead of being returned to its pool where later operations
+    /// would fail with `CKR_OPERATION_ACTIVE`.
+    pub fn abandon_operation(&self) {
+        self.session.close_on_drop();
+    }
+}
+
+impl Object<()> {
+    /// Start a multi-part computation of the HMAC of some data with this key and the given digest algorithm.
+    ///
+    /// The data is passed to [`Object::sign_update`] and the HMAC is returned by [`Object::sign_final`].
+    /// The state of the operation is held by the session, so the key should have been loaded from a session
+    /// opened with [`crate::Context::open_dedicated_session`] or taken from a [`crate::SessionPool`].
+    pub fn sign_init(&self, digest_algorithm: DigestAlgorithm) -> Result<(), SignError> {
+        unsafe {
+            // Signing with the key needs login
+            self.session.login().map_err(SignError::LoginFailed)?;
+
+            let mechanism = pkcs11_sys::CK_MECHANISM_IN {
+                mechanism: digest_algorithm.hmac_mechanism(),
+                pParameter: std::ptr::null(),
+                ulParameterLen: 0,
+            };
+            let result =
+                (self.session.context.C_SignInit)(self.session.handle, &mechanism, self.handle);
+            if result != pkcs11_sys::CKR_OK {
+                return Err(SignError::SignInitFailed(result));
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Continue a multi-part HMAC computation started with [`Object::sign_init`] with the given data.
+    pub fn sign_update(&self, data: &[u8]) -> Result<(), SignError> {
+        unsafe {
+            let result = (self.session.context.C_SignUpdate)(
+                self.session.handle,
+                data.as_ptr(),
+                std::convert::TryInto::try_into(data.len()).expect("usize -> CK_ULONG"),
+            );
+            if result != pkcs11_sys::CKR_OK {
+                return Err(SignError::SignUpdateFailed(result));
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Complete a multi-part HMAC computation started with [`Object::sign_init`] and store the result into the given signature buffer.
+    pub fn sign_final(&self, signature: &mut [u8]) -> Result<pkcs11_sys::CK_ULONG, SignError> {
+        unsafe {
+            let original_signature_len =
+                std::convert::TryInto::try_into(signature.len()).expect("usize -> CK_ULONG");
+            let mut signature_len = original_signature_len;
+
+            let result = (self.session.context.C_SignFinal)(
+                self.session.handle,
+                signature.as_mut_ptr(),
+                &mut signature_len,
+            );
+            if result != pkcs11_sys::CKR_OK {
+                return Err(SignError::SignFinalFailed(result));
+            }
+            assert!(signature_len <= original_signature_len);
+
+            Ok(signature_len)
+        }
+    }
+}
+
+/// The mechanism used to encrypt or decrypt with an AES key.
+pub enum AesMechanism<'a> {
+    /// AES-GCM with a 16-byte tag appended to the ciphertext.
+    Gcm { iv: &'a [u8], aad: &'a [u8] },
+
+    /// AES-CBC with PKCS#7 padding.
+    CbcPad { iv: &'a [u8; 16] },
+
+    /// AES-CTR, with the whole 16-byte initial counter block used as the counter.
+    Ctr { iv: &'a [u8; 16] },
+
+    /// RFC 3394 AES key wrap.
+    KeyWrap,
+
+    /// RFC 5649 AES key wrap with padding.
+    KeyWrapPad,
+}
+
+impl AesMechanism<'_> {
+    /// Calls `f` with the PKCS#11 representation of this mechanism.
+    fn with_mechanism<T>(&self, f: impl FnOnce(&pkcs11_sys::CK_MECHANISM_IN) -> T) -> T {
+        let gcm_params;
+        let ctr_params;
+        let mechanism = match self {
+            AesMechanism::Gcm { iv, aad } => {
+                let iv_len = std::convert::TryInto::try_into(iv.len()).expect("usize -> CK_ULONG");
+
+                gcm_params = pkcs11_sys::CK_GCM_PARAMS {
+                    pIv: iv.as_ptr(),
+                    ulIvLen: iv_len,
+                    ulIvBits: iv_len * 8,
+                    pAAD: aad.as_ptr(),
+                    ulAADLen: std::convert::TryInto::try_into(aad.len())
+                        .expect("usize -> CK_ULONG"),
+                    ulTagBits: 16 * 8,
+                };
+
+                pkcs11_sys::CK_MECHANISM_IN {
+                    mechanism: pkcs11_sys::CKM_AES_GCM,
+                    pParameter: (&gcm_params as *const pkcs11_sys::CK_GCM_PARAMS).cast(),
+                    ulParameterLen: std::convert::TryInto::try_into(std::mem::size_of_val(
+                        &gcm_params,
+                    ))
+                    .expect("usize -> CK_ULONG"),
+                }
+            }
+
+            AesMechanism::CbcPad { iv } => pkcs11_sys::CK_MECHANISM_IN {
+                mechanism: pkcs11_sys::CKM_AES_CBC_PAD,
+                pParameter: iv.as_ptr().cast(),
+                ulParameterLen: std::convert::TryInto::try_into(iv.len())
+                    .expect("usize -> CK_ULONG"),
+            },
+
+            AesMechanism::Ctr { iv } => {
+                ctr_params = pkcs11_sys::CK_AES_CTR_PARAMS {
+                    ulCounterBits: 128,
+                    cb: **iv,
+                };
+
+                pkcs11_sys::CK_MECHANISM_IN {
+                    mechanism: pkcs11_sys::CKM_AES_CTR,
+                    pParameter: (&ctr_params as *const pkcs11_sys::CK_AES_CTR_PARAMS).cast(),
+                    ulParameterLen: std::convert::TryInto::try_into(std::mem::size_of_val(
+                        &ctr_params,
+                    ))
+                    .expect("usize -> CK_ULONG"),
+                }
+            }
+
+            // A NULL parameter selects the default IV of RFC 3394 / RFC 5649 respectively.
+            AesMechanism::KeyWrap => pkcs11_sys::CK_MECHANISM_IN {
+                mechanism: pkcs11_sys::CKM_AES_KEY_WRAP,
+                pParameter: std::ptr::null(),
+                ulParameterLen: 0,
+            },
+
+            AesMechanism::KeyWrapPad => pkcs11_sys::CK_MECHANISM_IN {
+                mechanism: pkcs11_sys::CKM_AES_KEY_WRAP_KWP,
+                pParameter: std::ptr::null(),
+                ulParameterLen: 0,
+            },
+        };
+
+        f(&mechanism)
+    }
+}
+
+impl Object<()> {
+    /// Use this key to encrypt the given plaintext with the given mechanism and store the result into the given ciphertext buffer.
     pub fn encrypt(
         &self,
-        iv: &[u8],
-        aad: &[u8],
+        mechanism: &AesMechanism<'_>,
         plaintext: &[u8],
         ciphertext: &mut [u8],
     ) -> Result<pkcs11_sys::CK_ULONG, EncryptError> {
@@ -74,40 +278,19 @@ impl Object<()> {
             // Encrypting with the key needs login
             self.session.login().map_err(EncryptError::LoginFailed)?;
 
-            let iv_len = std::convert::TryInto::try_into(iv.len()).expect("usize -> CK_ULONG");
-
-            let params = pkcs11_sys::CK_GCM_PARAMS {
-                pIv: iv.as_ptr(),
-                ulIvLen: iv_len,
-                ulIvBits: iv_len * 8,
-                pAAD: aad.as_ptr(),
-                ulAADLen: std::convert::TryInto::try_into(aad.len()).expect("usize -> CK_ULONG"),
-                ulTagBits: 16 * 8,
-            };
-            let mechanism = pkcs11_sys::CK_MECHANISM_IN {
-                mechanism: pkcs11_sys::CKM_AES_GCM,
-                pParameter: (&params as *const pkcs11_sys::CK_GCM_PARAMS).cast(),
-                ulParameterLen: std::convert::TryInto::try_into(std::mem::size_of_val(&params))
-                    .expect("usize -> CK_ULONG"),
-            };
-
-            let ciphertext_len = encrypt_inner(
-                &self.session,
-                self.handle,
-                &mechanism,
-                plaintext,
-                ciphertext,
-            )?;
+            let ciphertext_len = mechanism.with_mechanism(|mechanism| {
+                encrypt_inner(&self.session, self.handle, mechanism, plaintext, ciphertext)
+            })?;
             Ok(ciphertext_len)
         }
     }
 }
 
 impl Object<()> {
+    /// Use this key to decrypt the given ciphertext with the given mechanism and store the result into the given plaintext buffer.
     pub fn decrypt(
         &self,
-        iv: &[u8],
-        aad: &[u8],
+        mechanism: &AesMechanism<'_>,
         ciphertext: &[u8],
         plaintext: &mut [u8],
     ) -> Result<pkcs11_sys::CK_ULONG, DecryptError> {
@@ -115,35 +298,200 @@ impl Object<()> {
             // Decrypting with the key needs login
             self.session.login().map_err(DecryptError::LoginFailed)?;
 
-            let iv_len = std::convert::TryInto::try_into(iv.len()).expect("usize -> CK_ULONG");
+            let plaintext_len = mechanism.with_mechanism(|mechanism| {
+                decrypt_inner(&self.session, self.handle, mechanism, ciphertext, plaintext)
+            })?;
+            Ok(plaintext_len)
+        }
+    }
+}
 
-            let params = pkcs11_sys::CK_GCM_PARAMS {
-                pIv: iv.as_ptr(),
-                ulIvLen: iv_len,
-                ulIvBits: iv_len * 8,
-                pAAD: aad.as_ptr(),
-                ulAADLen: std::convert::TryInto::try_into(aad.len()).expect("usize -> CK_ULONG"),
-                ulTagBits: 16 * 8,
-            };
-            let mechanism = pkcs11_sys::CK_MECHANISM_IN {
-                mechanism: pkcs11_sys::CKM_AES_GCM,
-                pParameter: (&params as *const pkcs11_sys::CK_GCM_PARAMS).cast(),
-                ulParameterLen: std::convert::TryInto::try_into(std::mem::size_of_val(&params))
-                    .expect("usize -> CK_ULONG"),
-            };
+impl Object<()> {
+    /// Start a multi-part encryption with this key and the given mechanism.
+    ///
+    /// The plaintext is passed to [`Object::encrypt_update`] and the operation is completed with [`Object::encrypt_final`].
+    /// The state of the operation is held by the session, so the key should have been loaded from a session
+    /// opened with [`crate::Context::open_dedicated_session`] or taken from a [`crate::SessionPool`].
+    pub fn encrypt_init(&self, mechanism: &AesMechanism<'_>) -> Result<(), EncryptError> {
+        unsafe {
+            // Encrypting with the key needs login
+            self.session.login().map_err(EncryptError::LoginFailed)?;
+
+            let result = mechanism.with_mechanism(|mechanism| {
+                (self.session.context.C_EncryptInit)(self.session.handle, mechanism, self.handle)
+            });
+            if result != pkcs11_sys::CKR_OK {
+                return Err(EncryptError::EncryptInitFailed(result));
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Continue a multi-part encryption started with [`Object::encrypt_init`] with the given plaintext,
+    /// and store any resulting ciphertext into the given ciphertext buffer.
+    pub fn encrypt_update(
+        &self,
+        plaintext: &[u8],
+        ciphertext: &mut [u8],
+    ) -> Result<pkcs11_sys::CK_ULONG, EncryptError> {
+        unsafe {
+            let original_ciphertext_len =
+                std::convert::TryInto::try_into(ciphertext.len()).expect("usize -> CK_ULONG");
+            let mut ciphertext_len = original_ciphertext_len;
+
+            let result = (self.session.context.C_EncryptUpdate)(
+                self.session.handle,
+                plaintext.as_ptr(),
+                std::convert::TryInto::try_into(plaintext.len()).expect("usize -> CK_ULONG"),
+                ciphertext.as_mut_ptr(),
+                &mut ciphertext_len,
+            );
+            if result != pkcs11_sys::CKR_OK {
+                return Err(EncryptError::EncryptUpdateFailed(result));
+            }
+            assert!(ciphertext_len <= original_ciphertext_len);
+
+            Ok(ciphertext_len)
+        }
+    }
+
+    /// Complete a multi-part encryption started with [`Object::encrypt_init`], and store the remaining ciphertext into the given ciphertext buffer.
+    pub fn encrypt_final(
+        &self,
+        ciphertext: &mut [u8],
+    ) -> Result<pkcs11_sys::CK_ULONG, EncryptError> {
+        unsafe {
+            let original_ciphertext_len =
+                std::convert::TryInto::try_into(ciphertext.len()).expect("usize -> CK_ULONG");
+            let mut ciphertext_len = original_ciphertext_len;
+
+            let result = (self.session.context.C_EncryptFinal)(
+                self.session.handle,
+                ciphertext.as_mut_ptr(),
+                &mut ciphertext_len,
+            );
+            if result != pkcs11_sys::CKR_OK {
+                return Err(EncryptError::EncryptFinalFailed(result));
+            }
+            assert!(ciphertext_len <= original_ciphertext_len);
+
+            Ok(ciphertext_len)
+        }
+    }
+}
+
+impl Object<()> {
+    /// Start a multi-part decryption with this key and the given mechanism.
+    ///
+    /// The ciphertext is passed to [`Object::decrypt_update`] and the operation is completed with [`Object::decrypt_final`].
+    /// The state of the operation is held by the session, so the key should have been loaded from a session
+    /// opened with [`crate::Context::open_dedicated_session`] or taken from a [`crate::SessionPool`].
+    pub fn decrypt_init(&self, mechanism: &AesMechanism<'_>) -> Result<(), DecryptError> {
+        unsafe {
+            // Decrypting with the key needs login
+            self.session.login().map_err(DecryptError::LoginFailed)?;
+
+            let result = mechanism.with_mechanism(|mechanism| {
+                (self.session.context.C_DecryptInit)(self.session.handle, mechanism, self.handle)
+            });
+            if result != pkcs11_sys::CKR_OK {
+                return Err(DecryptError::DecryptInitFailed(result));
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Continue a multi-part decryption started with [`Object::decrypt_init`] with the given ciphertext,
+    /// and store any resulting plaintext into the given plaintext buffer.
+    pub fn decrypt_update(
+        &self,
+        ciphertext: &[u8],
+        plaintext: &mut [u8],
+    ) -> Result<pkcs11_sys::CK_ULONG, DecryptError> {
+        unsafe {
+            let original_plaintext_len =
+                std::convert::TryInto::try_into(plaintext.len()).expect("usize -> CK_ULONG");
+            let mut plaintext_len = original_plaintext_len;
+
+            let result = (self.session.context.C_DecryptUpdate)(
+                self.session.handle,
+                ciphertext.as_ptr(),
+                std::convert::TryInto::try_into(ciphertext.len()).expect("usize -> CK_ULONG"),
+                plaintext.as_mut_ptr(),
+                &mut plaintext_len,
+            );
+            if result != pkcs11_sys::CKR_OK {
+                return Err(DecryptError::DecryptUpdateFailed(result));
+            }
+            assert!(plaintext_len <= original_plaintext_len);
+
+            Ok(plaintext_len)
+        }
+    }
+
+    /// Complete a multi-part decryption started with [`Object::decrypt_init`], and store the remaining plaintext into the given plaintext buffer.
+    pub fn decrypt_final(
+        &self,
+        plaintext: &mut [u8],
+    ) -> Result<pkcs11_sys::CK_ULONG, DecryptError> {
+        unsafe {
+            let original_plaintext_len =
+                std::convert::TryInto::try_into(plaintext.len()).expect("usize -> CK_ULONG");
+            let mut plaintext_len = original_plaintext_len;
+
+            let result = (self.session.context.C_DecryptFinal)(
+                self.session.handle,
+                plaintext.as_mut_ptr(),
+                &mut plaintext_len,
+            );
+            if result != pkcs11_sys::CKR_OK {
+                return Err(DecryptError::DecryptFinalFailed(result));
+            }
+            assert!(plaintext_len <= original_plaintext_len);
 
-            let plaintext_len = decrypt_inner(
-                &self.session,
-                self.handle,
-                &mechanism,
-                ciphertext,
-                plaintext,
-            )?;
             Ok(plaintext_len)
         }
     }
 }
 
+impl Object<()> {
+    /// Get the attributes of this key object that describe what it can be used for and how it was created.
+    pub fn attributes(&self) -> Result<KeyAttributes, GetKeyParametersError> {
+        unsafe {
+            let get_bool = |r#type| -> Result<bool, GetKeyParametersError> {
+                let value = get_attribute_value_byte_buf(
+                    &self.session,
+                    self,
+                    r#type,
+                    self.session.context.C_GetAttributeValue,
+                )?;
+                Ok(value.iter().any(|&b| b != 0))
+            };
+
+            Ok(KeyAttributes {
+                sign: get_bool(pkcs11_sys::CKA_SIGN)?,
+                encrypt: get_bool(pkcs11_sys::CKA_ENCRYPT)?,
+                local: get_bool(pkcs11_sys::CKA_LOCAL)?,
+            })
+        }
+    }
+}
+
+/// The attributes of a key object, as returned by [`Object::attributes`].
+#[derive(Clone, Copy, Debug)]
+pub struct KeyAttributes {
+    /// `CKA_SIGN`: the key can be used to compute HMACs.
+    pub sign: bool,
+
+    /// `CKA_ENCRYPT`: the key can be used to encrypt data.
+    pub encrypt: bool,
+
+    /// `CKA_LOCAL`: the key was generated on the token, as opposed to being imported into it.
+    pub local: bool,
+}
+
 impl Object<openssl::ec::EcKey<openssl::pkey::Public>> {
     /// Get the EC parameters of this EC public key object.
     pub fn parameters(
@@ -200,6 +548,45 @@ impl Object<openssl::ec::EcKey<openssl::pkey::Public>> {
     }
 }
 
+impl Object<openssl::pkey::PKey<openssl::pkey::Public>> {
+    /// Get the parameters of this Ed25519 public key object.
+    pub fn parameters(
+        &self,
+    ) -> Result<openssl::pkey::PKey<openssl::pkey::Public>, GetKeyParametersError> {
+        unsafe {
+            // PKCS#11 v3 specifies CKA_EC_POINT of an Edwards key as a DER encoded octet string wrapping the raw public key,
+            // but some implementations return the raw public key directly, so accept both.
+            let point = get_attribute_value_byte_buf(
+                &self.session,
+                self,
+                pkcs11_sys::CKA_EC_POINT,
+                self.session.context.C_GetAttributeValue,
+            )?;
+            let point = if point.len() == 32 {
+                point
+            } else {
+                let point = openssl_sys2::d2i_ASN1_OCTET_STRING(
+                    std::ptr::null_mut(),
+                    &mut point.as_ptr().cast(),
+                    std::convert::TryInto::try_into(point.len()).expect("usize -> c_long"),
+                );
+                if point.is_null() {
+                    return Err(GetKeyParametersError::MalformedEcPoint(
+                        openssl::error::ErrorStack::get(),
+                    ));
+                }
+                let point: openssl::asn1::Asn1String =
+                    foreign_types_shared::ForeignType::from_ptr(point);
+                point.as_slice().to_owned()
+            };
+
+            let parameters = openssl2::ed25519::public_key_from_raw(&point)
+                .map_err(GetKeyParametersError::ConvertToOpenssl)?;
+            Ok(parameters)
+        }
+    }
+}
+
 /// An error from getting the parameters of a key object.
 #[derive(Debug)]
 pub enum GetKeyParametersError {
@@ -285,13 +672,153 @@ impl Object<openssl::ec::EcKey<openssl::pkey::Private>> {
             // Signing with the private key needs login
             self.session.login().map_err(SignError::LoginFailed)?;
 
-            let signature_len = sign_inner(
-                &self.session,
+            let mechanism = pkcs11_sys::CK_MECHANISM_IN {
+                mechanism: pkcs11_sys::CKM_ECDSA,
+                pParameter: std::ptr::null(),
+                ulParameterLen: 0,
+            };
+            let signature_len =
+                sign_inner(&self.session, self.handle, &mechanism, digest, signature)?;
+            Ok(signature_len)
+        }
+    }
+}
+
+impl Object<openssl::ec::EcKey<openssl::pkey::Private>> {
+    /// Use this key to perform ECDH key agreement with the given peer public key and return the shared secret.
+    ///
+    /// `peer_public_key` is the peer's public key as an uncompressed EC point, and `secret_len` is the size of the curve's field in bytes.
+    ///
+    /// The secret is derived into a temporary session object, which is destroyed after its value has been read.
+    pub fn derive_shared_secret(
+        &self,
+        peer_public_key: &[u8],
+        secret_len: usize,
+    ) -> Result<Vec<u8>, DeriveError> {
+        unsafe {
+            // Deriving with the private key needs login
+            self.session.login().map_err(DeriveError::LoginFailed)?;
+
+            let parameters = pkcs11_sys::CK_ECDH1_DERIVE_PARAMS {
+                kdf: pkcs11_sys::CKD_NULL,
+                ulSharedDataLen: 0,
+                pSharedData: std::ptr::null(),
+                ulPublicDataLen: std::convert::TryInto::try_into(peer_public_key.len())
+                    .expect("usize -> CK_ULONG"),
+                pPublicData: peer_public_key.as_ptr(),
+            };
+            let mechanism = pkcs11_sys::CK_MECHANISM_IN {
+                mechanism: pkcs11_sys::CKM_ECDH1_DERIVE,
+                pParameter: (&parameters as *const pkcs11_sys::CK_ECDH1_DERIVE_PARAMS).cast(),
+                ulParameterLen: std::convert::TryInto::try_into(std::mem::size_of_val(&parameters))
+                    .expect("usize -> CK_ULONG"),
+            };
+
+            let class = pkcs11_sys::CKO_SECRET_KEY;
+            let key_type = pkcs11_sys::CKK_GENERIC_SECRET;
+            let value_len: pkcs11_sys::CK_ULONG =
+                std::convert::TryInto::try_into(secret_len).expect("usize -> CK_ULONG");
+
+            let r#true = pkcs11_sys::CK_TRUE;
+            let true_size = std::convert::TryInto::try_into(std::mem::size_of_val(&r#true))
+                .expect("usize -> CK_ULONG");
+            let r#true = (&r#true as *const pkcs11_sys::CK_BBOOL).cast();
+
+            let r#false = pkcs11_sys::CK_FALSE;
+            let false_size = std::convert::TryInto::try_into(std::mem::size_of_val(&r#false))
+                .expect("usize -> CK_ULONG");
+            let r#false = (&r#false as *const pkcs11_sys::CK_BBOOL).cast();
+
+            let template = [
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_CLASS,
+                    pValue: (&class as *const pkcs11_sys::CK_OBJECT_CLASS).cast(),
+                    ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(&class))
+                        .expect("usize -> CK_ULONG"),
+                },
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_KEY_TYPE,
+                    pValue: (&key_type as *const pkcs11_sys::CK_KEY_TYPE).cast(),
+                    ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(&key_type))
+                        .expect("usize -> CK_ULONG"),
+                },
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_EXTRACTABLE,
+                    pValue: r#true,
+                    ulValueLen: true_size,
+                },
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_SENSITIVE,
+                    pValue: r#false,
+                    ulValueLen: false_size,
+                },
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_TOKEN,
+                    pValue: r#false,
+                    ulValueLen: false_size,
+                },
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_VALUE_LEN,
+                    pValue: (&value_len as *const pkcs11_sys::CK_ULONG).cast(),
+                    ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(&value_len))
+                        .expect("usize -> CK_ULONG"),
+                },
+            ];
+
+            let mut secret_handle = pkcs11_sys::CK_INVALID_OBJECT_HANDLE;
+            let result = (self.session.context.C_DeriveKey)(
+                self.session.handle,
+                &mechanism,
                 self.handle,
-                pkcs11_sys::CKM_ECDSA,
-                digest,
-                signature,
-            )?;
+                template.as_ptr().cast(),
+                std::convert::TryInto::try_into(template.len()).expect("usize -> CK_ULONG"),
+                &mut secret_handle,
+            );
+            if result != pkcs11_sys::CKR_OK {
+                return Err(DeriveError::DeriveKeyFailed(result));
+            }
+            if secret_handle == pkcs11_sys::CK_INVALID_OBJECT_HANDLE {
+                return Err(DeriveError::DeriveKeyDidNotReturnHandle);
+            }
+
+            let secret: Object<()> = Object::new(self.session.clone(), secret_handle);
+            let secret_value = get_attribute_value_byte_buf(
+                &self.session,
+                &secret,
+                pkcs11_sys::CKA_VALUE,
+                self.session.context.C_GetAttributeValue,
+            );
+
+            // The secret is a session object, so it would be destroyed when the session is closed anyway.
+            // So it's fine to ignore failures here.
+            let _ = (self.session.context.C_DestroyObject)(self.session.handle, secret_handle);
+
+            let secret_value = secret_value.map_err(DeriveError::GetSecretFailed)?;
+            Ok(secret_value)
+        }
+    }
+}
+
+impl Object<openssl::pkey::PKey<openssl::pkey::Private>> {
+    /// Use this Ed25519 key to sign the given message and store the result into the given signature buffer.
+    ///
+    /// Unlike `CKM_ECDSA`, `CKM_EDDSA` signs the message itself rather than a digest of it.
+    pub fn sign(
+        &self,
+        message: &[u8],
+        signature: &mut [u8],
+    ) -> Result<pkcs11_sys::CK_ULONG, SignError> {
+        unsafe {
+            // Signing with the private key needs login
+            self.session.login().map_err(SignError::LoginFailed)?;
+
+            let mechanism = pkcs11_sys::CK_MECHANISM_IN {
+                mechanism: pkcs11_sys::CKM_EDDSA,
+                pParameter: std::ptr::null(),
+                ulParameterLen: 0,
+            };
+            let signature_len =
+                sign_inner(&self.session, self.handle, &mechanism, message, signature)?;
             Ok(signature_len)
         }
     }
@@ -300,6 +827,12 @@ impl Object<openssl::ec::EcKey<openssl::pkey::Private>> {
 pub enum RsaSignMechanism {
     Pkcs1,
     X509,
+
+    /// RSASSA-PSS over a precomputed digest, using MGF1 with the same digest algorithm.
+    Pss {
+        digest_algorithm: DigestAlgorithm,
+        salt_len: pkcs11_sys::CK_ULONG,
+    },
 }
 
 impl Object<openssl::rsa::Rsa<openssl::pkey::Private>> {
@@ -314,12 +847,43 @@ impl Object<openssl::rsa::Rsa<openssl::pkey::Private>> {
             // Signing with the private key needs login
             self.session.login().map_err(SignError::LoginFailed)?;
 
+            let pss_params;
             let mechanism = match mechanism {
-                RsaSignMechanism::Pkcs1 => pkcs11_sys::CKM_RSA_PKCS,
-                RsaSignMechanism::X509 => pkcs11_sys::CKM_RSA_X509,
+                RsaSignMechanism::Pkcs1 => pkcs11_sys::CK_MECHANISM_IN {
+                    mechanism: pkcs11_sys::CKM_RSA_PKCS,
+                    pParameter: std::ptr::null(),
+                    ulParameterLen: 0,
+                },
+
+                RsaSignMechanism::X509 => pkcs11_sys::CK_MECHANISM_IN {
+                    mechanism: pkcs11_sys::CKM_RSA_X509,
+                    pParameter: std::ptr::null(),
+                    ulParameterLen: 0,
+                },
+
+                RsaSignMechanism::Pss {
+                    digest_algorithm,
+                    salt_len,
+                } => {
+                    pss_params = pkcs11_sys::CK_RSA_PKCS_PSS_PARAMS {
+                        hashAlg: digest_algorithm.hash_mechanism(),
+                        mgf: digest_algorithm.mgf1(),
+                        sLen: *salt_len,
+                    };
+
+                    pkcs11_sys::CK_MECHANISM_IN {
+                        mechanism: pkcs11_sys::CKM_RSA_PKCS_PSS,
+                        pParameter: (&pss_params as *const pkcs11_sys::CK_RSA_PKCS_PSS_PARAMS)
+                            .cast(),
+                        ulParameterLen: std::convert::TryInto::try_into(std::mem::size_of_val(
+                            &pss_params,
+                        ))
+                        .expect("usize -> CK_ULONG"),
+                    }
+                }
             };
             let signature_len =
-                sign_inner(&self.session, self.handle, mechanism, digest, signature)?;
+                sign_inner(&self.session, self.handle, &mechanism, digest, signature)?;
             Ok(signature_len)
         }
     }
@@ -354,16 +918,11 @@ impl Object<openssl::rsa::Rsa<openssl::pkey::Public>> {
 unsafe fn sign_inner(
     session: &crate::Session,
     handle: pkcs11_sys::CK_OBJECT_HANDLE,
-    mechanism: pkcs11_sys::CK_MECHANISM_TYPE,
+    mechanism: &pkcs11_sys::CK_MECHANISM_IN,
     digest: &[u8],
     signature: &mut [u8],
 ) -> Result<pkcs11_sys::CK_ULONG, SignError> {
-    let mechanism = pkcs11_sys::CK_MECHANISM_IN {
-        mechanism,
-        pParameter: std::ptr::null(),
-        ulParameterLen: 0,
-    };
-    let result = (session.context.C_SignInit)(session.handle, &mechanism, handle);
+    let result = (session.context.C_SignInit)(session.handle, mechanism, handle);
     if result != pkcs11_sys::CKR_OK {
         return Err(SignError::SignInitFailed(result));
     }
@@ -393,6 +952,8 @@ pub enum SignError {
     LoginFailed(crate::LoginError),
     SignInitFailed(pkcs11_sys::CK_RV),
     SignFailed(pkcs11_sys::CK_RV),
+    SignUpdateFailed(pkcs11_sys::CK_RV),
+    SignFinalFailed(pkcs11_sys::CK_RV),
 }
 
 impl std::fmt::Display for SignError {
@@ -401,6 +962,10 @@ impl std::fmt::Display for SignError {
             SignError::LoginFailed(_) => f.write_str("could not log in to the token"),
             SignError::SignInitFailed(result) => write!(f, "C_SignInit failed with {}", result),
             SignError::SignFailed(result) => write!(f, "C_Sign failed with {}", result),
+            SignError::SignUpdateFailed(result) => {
+                write!(f, "C_SignUpdate failed with {}", result)
+            }
+            SignError::SignFinalFailed(result) => write!(f, "C_SignFinal failed with {}", result),
         }
     }
 }
@@ -412,6 +977,8 @@ impl std::error::Error for SignError {
             SignError::LoginFailed(inner) => Some(inner),
             SignError::SignInitFailed(_) => None,
             SignError::SignFailed(_) => None,
+            SignError::SignUpdateFailed(_) => None,
+            SignError::SignFinalFailed(_) => None,
         }
     }
 }
@@ -514,6 +1081,8 @@ unsafe fn encrypt_inner(
 pub enum EncryptError {
     EncryptInitFailed(pkcs11_sys::CK_RV),
     EncryptFailed(pkcs11_sys::CK_RV),
+    EncryptUpdateFailed(pkcs11_sys::CK_RV),
+    EncryptFinalFailed(pkcs11_sys::CK_RV),
     LoginFailed(crate::LoginError),
 }
 
@@ -524,6 +1093,12 @@ impl std::fmt::Display for EncryptError {
                 write!(f, "C_EncryptInit failed with {}", result)
             }
             EncryptError::EncryptFailed(result) => write!(f, "C_Encrypt failed with {}", result),
+            EncryptError::EncryptUpdateFailed(result) => {
+                write!(f, "C_EncryptUpdate failed with {}", result)
+            }
+            EncryptError::EncryptFinalFailed(result) => {
+                write!(f, "C_EncryptFinal failed with {}", result)
+            }
             EncryptError::LoginFailed(_) => f.write_str("could not log in to the token"),
         }
     }
@@ -562,11 +1137,48 @@ unsafe fn decrypt_inner(
     Ok(plaintext_len)
 }
 
+#[derive(Debug)]
+pub enum DeriveError {
+    DeriveKeyDidNotReturnHandle,
+    DeriveKeyFailed(pkcs11_sys::CK_RV),
+    GetSecretFailed(GetKeyParametersError),
+    LoginFailed(crate::LoginError),
+}
+
+impl std::fmt::Display for DeriveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeriveError::DeriveKeyDidNotReturnHandle => {
+                f.write_str("C_DeriveKey succeeded but key handle is still CK_INVALID_HANDLE")
+            }
+            DeriveError::DeriveKeyFailed(result) => {
+                write!(f, "C_DeriveKey failed with {}", result)
+            }
+            DeriveError::GetSecretFailed(_) => f.write_str("could not get derived secret"),
+            DeriveError::LoginFailed(_) => f.write_str("could not log in to the token"),
+        }
+    }
+}
+
+impl std::error::Error for DeriveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        #[allow(clippy::match_same_arms)]
+        match self {
+            DeriveError::DeriveKeyDidNotReturnHandle => None,
+            DeriveError::DeriveKeyFailed(_) => None,
+            DeriveError::GetSecretFailed(inner) => Some(inner),
+            DeriveError::LoginFailed(inner) => Some(inner),
+        }
+    }
+}
+
 #[allow(clippy::pub_enum_variant_names)]
 #[derive(Debug)]
 pub enum DecryptError {
     DecryptInitFailed(pkcs11_sys::CK_RV),
     DecryptFailed(pkcs11_sys::CK_RV),
+    DecryptUpdateFailed(pkcs11_sys::CK_RV),
+    DecryptFinalFailed(pkcs11_sys::CK_RV),
     LoginFailed(crate::LoginError),
 }
 
@@ -577,6 +1189,12 @@ impl std::fmt::Display for DecryptError {
                 write!(f, "C_DecryptInit failed with {}", result)
             }
             DecryptError::DecryptFailed(result) => write!(f, "C_Decrypt failed with {}", result),
+            DecryptError::DecryptUpdateFailed(result) => {
+                write!(f, "C_DecryptUpdate failed with {}", result)
+            }
+            DecryptError::DecryptFinalFailed(result) => {
+                write!(f, "C_DecryptFinal failed with {}", result)
+            }
             DecryptError::LoginFailed(_) => f.write_str("could not log in to the token"),
         }
     }
@@ -0,0 +1,570 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+/// A pool of sessions against the token in a slot.
+///
+/// Sessions are opened and logged in to as needed, and returned to the pool when they're no longer in use,
+/// so that later operations don't need to open and log in to a new session.
+///
+/// Sessions are checked before being handed out again. A session that was lost, such as because the device
+/// was removed and reinserted, is discarded and replaced by a new session that is logged in again.
+/// The slot is looked up again whenever a new session is opened, so a token identified by its label is found
+/// even if it's in a different slot after being reinserted.
+pub struct SessionPool {
+    context: std::sync::Arc<crate::Context>,
+    slot_identifier: crate::UriSlotIdentifier,
+    pin: Option<String>,
+    max_idle_sessions: usize,
+
+    idle_sessions: std::sync::Mutex<Vec<std::sync::Arc<crate::Session>>>,
+
+    sessions_opened: std::sync::atomic::AtomicU64,
+    sessions_reused: std::sync::atomic::AtomicU64,
+    sessions_lost: std::sync::atomic::AtomicU64,
+    logins: std::sync::atomic::AtomicU64,
+}
+
+impl SessionPool {
+    /// Create a pool of sessions against the token in the slot with the given identifier.
+    ///
+    /// At most `max_idle_sessions` sessions are kept open while they're not in use. Sessions beyond that are closed
+    /// when they're returned to the pool.
+    pub fn new(
+        context: std::sync::Arc<crate::Context>,
+        slot_identifier: crate::UriSlotIdentifier,
+        pin: Option<String>,
+        max_idle_sessions: usize,
+    ) -> Self {
+        SessionPool {
+            context,
+            slot_identifier,
+            pin,
+            max_idle_sessions,
+
+            idle_sessions: Default::default(),
+
+            sessions_opened: Default::default(),
+            sessions_reused: Default::default(),
+            sessions_lost: Default::default(),
+            logins: Default::default(),
+        }
+    }
+
+    /// Get a session from the pool, opening a new one if there are no idle sessions.
+    ///
+    /// The session is returned to the pool when it's dropped, including any objects that were loaded from it.
+    /// Until then, it's not handed out to any other caller of this API, so it can also be used for multi-part operations.
+    pub fn get(
+        self: &std::sync::Arc<Self>,
+    ) -> Result<std::sync::Arc<crate::Session>, GetSessionError> {
+        loop {
+            let idle_session = self
+                .idle_sessions
+                .lock()
+                .expect("session pool mutex poisoned")
+                .pop();
+            let idle_session = match idle_session {
+                Some(idle_session) => idle_session,
+                None => break,
+            };
+
+            if idle_session.is_lost() {
+                self.discard(idle_session);
+                continue;
+            }
+
+            self.sessions_reused
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            return Ok(idle_session);
+        }
+
+        let slot = self
+            .context
+            .find_slot(&self.slot_identifier)
+            .map_err(GetSessionError::FindSlot)?;
+        let mut session = self
+            .context
+            .clone()
+            .open_session_inner(slot, self.pin.clone())
+            .map_err(GetSessionError::OpenSession)?;
+        session.pool = Some(std::sync::Arc::downgrade(self));
+        self.sessions_opened
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        // Log in to the new session up-front. If the token was reset, this is what logs in to it again.
+        if self.pin.is_some() {
+            unsafe {
+                session.login().map_err(GetSessionError::Login)?;
+            }
+            self.logins
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+
+        Ok(std::sync::Arc::new(session))
+    }
+
+    /// Run `f` with a session from the pool.
+    ///
+    /// If `f` fails and the session turns out to have been lost, such as because the device was removed and reinserted,
+    /// `f` is run once more with a new session.
+    ///
+    /// The returned `bool` is `true` if the session was lost and `f` was run again, whether or not that succeeded.
+    pub fn with_session<T, E>(
+        self: &std::sync::Arc<Self>,
+        mut f: impl FnMut(std::sync::Arc<crate::Session>) -> Result<T, E>,
+    ) -> (Result<T, WithSessionError<E>>, bool) {
+        let session = match self.get() {
+            Ok(session) => session,
+            Err(err) => return (Err(WithSessionError::GetSession(err)), false),
+        };
+        let err = match f(session.clone()) {
+            Ok(value) => return (Ok(value), false),
+            Err(err) => err,
+        };
+
+        if !session.is_lost() {
+            return (Err(WithSessionError::Operation(err)), false);
+        }
+
+        self.discard(session);
+
+        let result = self
+            .get()
+            .map_err(WithSessionError::GetSession)
+            .and_then(|session| f(session).map_err(WithSessionError::Operation));
+        (result, true)
+    }
+
+    /// Get the usage metrics of this pool.
+    pub fn metrics(&self) -> SessionPoolMetrics {
+        SessionPoolMetrics {
+            sessions_opened: self
+                .sessions_opened
+                .load(std::sync::atomic::Ordering::Relaxed),
+            sessions_reused: self
+                .sessions_reused
+                .load(std::sync::atomic::Ordering::Relaxed),
+            sessions_lost: self
+                .sessions_lost
+                .load(std::sync::atomic::Ordering::Relaxed),
+            logins: self.logins.load(std::sync::atomic::Ordering::Relaxed),
+            idle_sessions: self
+                .idle_sessions
+                .lock()
+                .expect("session pool mutex poisoned")
+                .len(),
+        }
+    }
+
+    /// Records that the given session was lost, and makes sure it's closed instead of being returned to the pool.
+    ///
+    /// If objects loaded from the session are still alive, it'll be returned to the pool when they're dropped,
+    /// and discarded the next time it would've been handed out.
+    fn discard(&self, session: std::sync::Arc<crate::Session>) {
+        self.sessions_lost
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        if let Ok(mut session) = std::sync::Arc::try_unwrap(session) {
+            session.pool = None;
+        }
+    }
+
+    /// Called by [`crate::Session`]'s `Drop` impl to return a session to the pool.
+    ///
+    /// Returns `false` if the pool has enough idle sessions already, in which case the caller should close the session.
+    pub(crate) fn put(
+        self: std::sync::Arc<Self>,
+        handle: pkcs11_sys::CK_SESSION_HANDLE,
+        pin: Option<String>,
+    ) -> bool {
+        let mut idle_sessions = self
+            .idle_sessions
+            .lock()
+            .expect("session pool mutex poisoned");
+        if idle_sessions.len() >= self.max_idle_sessions {
+            return false;
+        }
+
+        let mut session = crate::Session::new(self.context.clone(), handle, pin);
+        session.pool = Some(std::sync::Arc::downgrade(&self));
+        idle_sessions.push(std::sync::Arc::new(session));
+        true
+    }
+}
+
+/// Usage metrics of a [`SessionPool`], as returned by [`SessionPool::metrics`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SessionPoolMetrics {
+    /// The number of sessions that were opened.
+    pub sessions_opened: u64,
+
+    /// The number of times an idle session was handed out instead of opening a new one.
+    pub sessions_reused: u64,
+
+    /// The number of sessions that were discarded because they were lost, such as because the device was removed.
+    pub sessions_lost: u64,
+
+    /// The number of times a new session was logged in to.
+    pub logins: u64,
+
+    /// The number of sessions that are currently open but not in use.
+    pub idle_sessions: usize,
+}
+
+/// An error from getting a session from a [`SessionPool`].
+#[derive(Debug)]
+pub enum GetSessionError {
+    FindSlot(crate::FindSlotError),
+    Login(crate::LoginError),
+    OpenSession(crate::OpenSessionError),
+}
+
+impl std::fmt::Display for GetSessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GetSessionError::FindSlot(inner) => inner.fmt(f),
+            GetSessionError::Login(inner) => write!(f, "could not log in to the token: {}", inner),
+            GetSessionError::OpenSession(inner) => inner.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for GetSessionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GetSessionError::FindSlot(inner) => inner.source(),
+            GetSessionError::Login(_) => None,
+            GetSessionError::OpenSession(inner) => inner.source(),
+        }
+    }
+}
+
+/// An error from [`SessionPool::with_session`].
+#[derive(Debug)]
+pub enum WithSessionError<E> {
+    GetSession(GetSessionError),
+    Operation(E),
+}
+
+impl<E> std::fmt::Display for WithSessionError<E>
+where
+    E: std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WithSessionError::GetSession(inner) => inner.fmt(f),
+            WithSessionError::Operation(inner) => inner.fmt(f),
+        }
+    }
+}
+
+impl<E> std::error::Error for WithSessionError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WithSessionError::GetSession(inner) => inner.source(),
+            WithSessionError::Operation(inner) => inner.source(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    lazy_static::lazy_static! {
+        /// Serializes the tests, since they share the fake token.
+        static ref TEST_LOCK: std::sync::Mutex<()> = Default::default();
+
+        static ref TOKEN: std::sync::Mutex<Token> = Default::default();
+    }
+
+    /// The state of the fake token behind the session functions below.
+    #[derive(Default)]
+    struct Token {
+        num_sessions_opened: pkcs11_sys::CK_ULONG,
+        open_sessions: Vec<pkcs11_sys::CK_SESSION_HANDLE>,
+        lost_sessions: Vec<pkcs11_sys::CK_SESSION_HANDLE>,
+        logged_in: bool,
+
+        /// Sessions with a multi-part operation that was started and not completed.
+        active_operations: Vec<pkcs11_sys::CK_SESSION_HANDLE>,
+    }
+
+    /// Simulates the device being removed and reinserted. Sessions opened before this are lost, and the token is logged out.
+    fn reinsert_device() {
+        let mut token = TOKEN.lock().unwrap();
+        let open_sessions = std::mem::take(&mut token.open_sessions);
+        token.lost_sessions.extend(open_sessions);
+        token.logged_in = false;
+    }
+
+    unsafe extern "C" fn close_session(
+        hSession: pkcs11_sys::CK_SESSION_HANDLE,
+    ) -> pkcs11_sys::CK_RV {
+        let mut token = TOKEN.lock().unwrap();
+        if let Some(index) = token.open_sessions.iter().position(|&h| h == hSession) {
+            token.open_sessions.remove(index);
+            token.active_operations.retain(|&h| h != hSession);
+            pkcs11_sys::CKR_OK
+        } else {
+            pkcs11_sys::CKR_SESSION_HANDLE_INVALID
+        }
+    }
+
+    unsafe extern "C" fn get_session_info(
+        hSession: pkcs11_sys::CK_SESSION_HANDLE,
+        pInfo: pkcs11_sys::CK_SESSION_INFO_PTR,
+    ) -> pkcs11_sys::CK_RV {
+        let token = TOKEN.lock().unwrap();
+        if token.lost_sessions.contains(&hSession) {
+            return pkcs11_sys::CKR_DEVICE_REMOVED;
+        }
+        if !token.open_sessions.contains(&hSession) {
+            return pkcs11_sys::CKR_SESSION_HANDLE_INVALID;
+        }
+
+        pInfo.write(pkcs11_sys::CK_SESSION_INFO {
+            slotID: pkcs11_sys::CK_SLOT_ID(0),
+            state: if token.logged_in {
+                pkcs11_sys::CKS_RW_USER_FUNCTIONS
+            } else {
+                pkcs11_sys::CKS_RW_PUBLIC_SESSION
+            },
+            flags: pkcs11_sys::CKF_SERIAL_SESSION | pkcs11_sys::CKF_RW_SESSION,
+            ulDeviceError: 0,
+        });
+        pkcs11_sys::CKR_OK
+    }
+
+    unsafe extern "C" fn login(
+        hSession: pkcs11_sys::CK_SESSION_HANDLE,
+        _userType: pkcs11_sys::CK_USER_TYPE,
+        pPin: pkcs11_sys::CK_UTF8CHAR_PTR,
+        ulPinLen: pkcs11_sys::CK_ULONG,
+    ) -> pkcs11_sys::CK_RV {
+        let mut token = TOKEN.lock().unwrap();
+        if !token.open_sessions.contains(&hSession) {
+            return pkcs11_sys::CKR_SESSION_HANDLE_INVALID;
+        }
+
+        let pin =
+            std::slice::from_raw_parts(pPin, std::convert::TryInto::try_into(ulPinLen).unwrap());
+        if pin != b"1234" {
+            return pkcs11_sys::CKR_PIN_INCORRECT;
+        }
+
+        token.logged_in = true;
+        pkcs11_sys::CKR_OK
+    }
+
+    unsafe extern "C" fn open_session(
+        _slotID: pkcs11_sys::CK_SLOT_ID,
+        _flags: pkcs11_sys::CK_OPEN_SESSION_FLAGS,
+        _pApplication: pkcs11_sys::CK_VOID_PTR,
+        _Notify: Option<pkcs11_sys::CK_NOTIFY>,
+        phSession: pkcs11_sys::CK_SESSION_HANDLE_PTR,
+    ) -> pkcs11_sys::CK_RV {
+        let mut token = TOKEN.lock().unwrap();
+        token.num_sessions_opened += 1;
+        let handle = std::mem::transmute::<pkcs11_sys::CK_ULONG, pkcs11_sys::CK_SESSION_HANDLE>(
+            token.num_sessions_opened,
+        );
+        token.open_sessions.push(handle);
+        *phSession = handle;
+        pkcs11_sys::CKR_OK
+    }
+
+    unsafe extern "C" fn sign_init(
+        hSession: pkcs11_sys::CK_SESSION_HANDLE,
+        _pMechanism: pkcs11_sys::CK_MECHANISM_PTR_CONST,
+        _hKey: pkcs11_sys::CK_OBJECT_HANDLE,
+    ) -> pkcs11_sys::CK_RV {
+        let mut token = TOKEN.lock().unwrap();
+        if !token.open_sessions.contains(&hSession) {
+            return pkcs11_sys::CKR_SESSION_HANDLE_INVALID;
+        }
+        if token.active_operations.contains(&hSession) {
+            return pkcs11_sys::CKR_OPERATION_ACTIVE;
+        }
+
+        token.active_operations.push(hSession);
+        pkcs11_sys::CKR_OK
+    }
+
+    fn session_pool(
+        pin: Option<&str>,
+        max_idle_sessions: usize,
+    ) -> (
+        std::sync::MutexGuard<'static, ()>,
+        std::sync::Arc<super::SessionPool>,
+    ) {
+        let test_lock = TEST_LOCK
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        *TOKEN.lock().unwrap() = Default::default();
+
+        let mut context = crate::Context::with_session_functions(
+            close_session,
+            get_session_info,
+            login,
+            open_session,
+        );
+        context.C_SignInit = sign_init;
+        let session_pool = super::SessionPool::new(
+            std::sync::Arc::new(context),
+            crate::UriSlotIdentifier::SlotId(pkcs11_sys::CK_SLOT_ID(0)),
+            pin.map(ToOwned::to_owned),
+            max_idle_sessions,
+        );
+        (test_lock, std::sync::Arc::new(session_pool))
+    }
+
+    fn num_open_sessions() -> usize {
+        TOKEN.lock().unwrap().open_sessions.len()
+    }
+
+    #[test]
+    fn reuse_idle_sessions() {
+        let (_test_lock, session_pool) = session_pool(Some("1234"), 1);
+
+        let session = session_pool.get().unwrap();
+        let handle = session.handle;
+        drop(session);
+        assert_eq!(session_pool.metrics().idle_sessions, 1);
+
+        let session = session_pool.get().unwrap();
+        assert_eq!(session.handle, handle);
+
+        // Sessions returned to the pool beyond `max_idle_sessions` are closed.
+        let other_session = session_pool.get().unwrap();
+        assert_ne!(other_session.handle, handle);
+        assert_eq!(num_open_sessions(), 2);
+        drop(session);
+        drop(other_session);
+        assert_eq!(num_open_sessions(), 1);
+
+        assert_eq!(
+            session_pool.metrics(),
+            super::SessionPoolMetrics {
+                sessions_opened: 2,
+                sessions_reused: 1,
+                sessions_lost: 0,
+                logins: 2,
+                idle_sessions: 1,
+            },
+        );
+    }
+
+    #[test]
+    fn discard_lost_idle_session() {
+        let (_test_lock, session_pool) = session_pool(Some("1234"), 1);
+
+        let session = session_pool.get().unwrap();
+        let handle = session.handle;
+        drop(session);
+
+        reinsert_device();
+
+        let session = session_pool.get().unwrap();
+        assert_ne!(session.handle, handle);
+        assert!(!session.is_lost());
+        assert!(TOKEN.lock().unwrap().logged_in);
+
+        assert_eq!(
+            session_pool.metrics(),
+            super::SessionPoolMetrics {
+                sessions_opened: 2,
+                sessions_reused: 0,
+                sessions_lost: 1,
+                logins: 2,
+                idle_sessions: 0,
+            },
+        );
+    }
+
+    #[test]
+    fn with_session_retries_lost_session() {
+        let (_test_lock, session_pool) = session_pool(Some("1234"), 1);
+
+        let mut handles = vec![];
+        let (result, retried) = session_pool.with_session(|session| {
+            handles.push(session.handle);
+            if handles.len() == 1 {
+                reinsert_device();
+                Err("device removed")
+            } else {
+                Ok("success")
+            }
+        });
+        assert_eq!(result.unwrap(), "success");
+        assert!(retried);
+        assert_eq!(handles.len(), 2);
+        assert_ne!(handles[0], handles[1]);
+
+        let metrics = session_pool.metrics();
+        assert_eq!(metrics.sessions_lost, 1);
+        assert_eq!(metrics.logins, 2);
+        assert_eq!(metrics.idle_sessions, 1);
+    }
+
+    #[test]
+    fn with_session_retries_only_once() {
+        let (_test_lock, session_pool) = session_pool(None, 1);
+
+        let mut num_calls = 0;
+        let (result, retried) = session_pool.with_session(|_| -> Result<(), _> {
+            num_calls += 1;
+            reinsert_device();
+            Err("device removed")
+        });
+        assert!(matches!(
+            result,
+            Err(super::WithSessionError::Operation("device removed"))
+        ));
+        assert!(retried);
+        assert_eq!(num_calls, 2);
+    }
+
+    #[test]
+    fn with_session_does_not_retry_operation_error() {
+        let (_test_lock, session_pool) = session_pool(None, 1);
+
+        let mut num_calls = 0;
+        let (result, retried) = session_pool.with_session(|_| -> Result<(), _> {
+            num_calls += 1;
+            Err("operation failed")
+        });
+        assert!(matches!(
+            result,
+            Err(super::WithSessionError::Operation("operation failed"))
+        ));
+        assert!(!retried);
+        assert_eq!(num_calls, 1);
+
+        // The session wasn't lost, so it's returned to the pool.
+        let metrics = session_pool.metrics();
+        assert_eq!(metrics.sessions_lost, 0);
+        assert_eq!(metrics.idle_sessions, 1);
+    }
+
+    #[test]
+    fn abandoned_operation_closes_session() {
+        let (_test_lock, session_pool) = session_pool(Some("1234"), 1);
+
+        let session = session_pool.get().unwrap();
+        let handle = session.handle;
+        let key = crate::Object::<()>::new(session, pkcs11_sys::CK_INVALID_OBJECT_HANDLE);
+        key.sign_init(crate::DigestAlgorithm::Sha256).unwrap();
+
+        // Without completing the operation, the session must not be handed out again.
+        key.abandon_operation();
+        drop(key);
+        assert_eq!(num_open_sessions(), 0);
+        assert_eq!(session_pool.metrics().idle_sessions, 0);
+
+        let session = session_pool.get().unwrap();
+        assert_ne!(session.handle, handle);
+        let key = crate::Object::<()>::new(session, pkcs11_sys::CK_INVALID_OBJECT_HANDLE);
+        key.sign_init(crate::DigestAlgorithm::Sha256).unwrap();
+    }
+}
@@ -4,6 +4,12 @@ pub struct Session {
     pub(crate) context: std::sync::Arc<crate::Context>,
     pub(crate) handle: pkcs11_sys::CK_SESSION_HANDLE,
     pin: Option<String>,
+
+    /// The pool that the session is returned to when it's dropped, if it came from one.
+    pub(crate) pool: Option<std::sync::Weak<crate::SessionPool>>,
+
+    /// Set by [`Session::close_on_drop`].
+    close_on_drop: std::sync::atomic::AtomicBool,
 }
 
 impl Session {
@@ -16,8 +22,19 @@ impl Session {
             context,
             handle,
             pin,
+            pool: None,
+            close_on_drop: Default::default(),
         }
     }
+
+    /// Makes sure the session is closed when it's dropped, instead of being returned to the pool that it came from.
+    ///
+    /// This is for sessions that were left in a state that would break later users of the pool,
+    /// such as with a multi-part operation that was started but never completed.
+    pub fn close_on_drop(&self) {
+        self.close_on_drop
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
 }
 
 pub type Key = crate::Object<()>;
@@ -31,11 +48,16 @@ pub enum KeyPair {
         crate::Object<openssl::rsa::Rsa<openssl::pkey::Public>>,
         crate::Object<openssl::rsa::Rsa<openssl::pkey::Private>>,
     ),
+    Ed25519(
+        crate::Object<openssl::pkey::PKey<openssl::pkey::Public>>,
+        crate::Object<openssl::pkey::PKey<openssl::pkey::Private>>,
+    ),
 }
 
 pub enum PublicKey {
     Ec(crate::Object<openssl::ec::EcKey<openssl::pkey::Public>>),
     Rsa(crate::Object<openssl::rsa::Rsa<openssl::pkey::Public>>),
+    Ed25519(crate::Object<openssl::pkey::PKey<openssl::pkey::Public>>),
 }
 
 impl Session {
@@ -55,6 +77,10 @@ impl Session {
                 pkcs11_sys::CKK_RSA => {
                     Ok(PublicKey::Rsa(crate::Object::new(self, public_key_handle)))
                 }
+                pkcs11_sys::CKK_EC_EDWARDS => Ok(PublicKey::Ed25519(crate::Object::new(
+                    self,
+                    public_key_handle,
+                ))),
                 _ => Err(GetKeyError::MismatchedMechanismType),
             }
         }
@@ -85,6 +111,11 @@ impl Session {
                     crate::Object::new(self, private_key_handle),
                 )),
 
+                (pkcs11_sys::CKK_EC_EDWARDS, pkcs11_sys::CKK_EC_EDWARDS) => Ok(KeyPair::Ed25519(
+                    crate::Object::new(self.clone(), public_key_handle),
+                    crate::Object::new(self, private_key_handle),
+                )),
+
                 _ => Err(GetKeyError::MismatchedMechanismType),
             }
         }
@@ -727,7 +758,14 @@ impl Session {
                 ulValueLen: std::convert::TryInto::try_into(oid.len()).expect("usize -> CK_ULONG"),
             }];
 
-            let private_key_template = vec![];
+            // EC private keys can also be used for ECDH key agreement. See `Object::derive_shared_secret`
+            let r#true = pkcs11_sys::CK_TRUE;
+            let private_key_template = vec![pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_DERIVE,
+                pValue: (&r#true as *const pkcs11_sys::CK_BBOOL).cast(),
+                ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(&r#true))
+                    .expect("usize -> CK_ULONG"),
+            }];
 
             self.generate_key_pair_inner(
                 pkcs11_sys::CKM_EC_KEY_PAIR_GEN,
@@ -738,6 +776,37 @@ impl Session {
         }
     }
 
+    /// Generate an Ed25519 key pair in the current session with the given label.
+    pub fn generate_ed25519_key_pair(
+        self: std::sync::Arc<Self>,
+        label: Option<&str>,
+    ) -> Result<
+        (
+            crate::Object<openssl::pkey::PKey<openssl::pkey::Public>>,
+            crate::Object<openssl::pkey::PKey<openssl::pkey::Private>>,
+        ),
+        GenerateKeyPairError,
+    > {
+        unsafe {
+            let oid = openssl2::ed25519::OID_DER;
+
+            let public_key_template = vec![pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_EC_PARAMS,
+                pValue: oid.as_ptr().cast(),
+                ulValueLen: std::convert::TryInto::try_into(oid.len()).expect("usize -> CK_ULONG"),
+            }];
+
+            let private_key_template = vec![];
+
+            self.generate_key_pair_inner(
+                pkcs11_sys::CKM_EC_EDWARDS_KEY_PAIR_GEN,
+                public_key_template,
+                private_key_template,
+                label,
+            )
+        }
+    }
+
     /// Generate an RSA key pair in the current session with the given modulus size, exponent and label.
     pub fn generate_rsa_key_pair(
         self: std::sync::Arc<Self>,
@@ -963,65 +1032,1428 @@ impl std::error::Error for GenerateKeyPairError {
 }
 
 impl Session {
-    pub(crate) unsafe fn login(&self) -> Result<(), LoginError> {
-        let mut session_info = std::mem::MaybeUninit::uninit();
-        let result = (self.context.C_GetSessionInfo)(self.handle, session_info.as_mut_ptr());
-        if result != pkcs11_sys::CKR_OK {
-            return Err(LoginError::GetSessionInfoFailed(result));
-        }
+    /// Import a key pair in the current session with the given private key and label.
+    ///
+    /// Like generated key pairs, the key pair is stored as a public key object and a private key object with the same label.
+    pub fn import_key_pair(
+        self: std::sync::Arc<Self>,
+        private_key: &openssl::pkey::PKeyRef<openssl::pkey::Private>,
+        label: Option<&str>,
+    ) -> Result<(), ImportKeyPairError> {
+        // The values of the attributes that differ between key types, for the public key and the private key respectively.
+        let (key_type, public_key_values, private_key_values) = match private_key.id() {
+            openssl::pkey::Id::EC => {
+                let ec_key = private_key
+                    .ec_key()
+                    .map_err(ImportKeyPairError::ConvertFromOpenssl)?;
+                let curve = ec_key
+                    .group()
+                    .curve_name()
+                    .and_then(openssl2::EcCurve::from_nid)
+                    .ok_or(ImportKeyPairError::UnsupportedKeyType)?;
+                let oid = curve.as_oid_der().to_owned();
+
+                let mut big_num_context = openssl::bn::BigNumContext::new()
+                    .map_err(ImportKeyPairError::ConvertFromOpenssl)?;
+                let point = ec_key
+                    .public_key()
+                    .to_bytes(
+                        ec_key.group(),
+                        openssl::ec::PointConversionForm::UNCOMPRESSED,
+                        &mut big_num_context,
+                    )
+                    .map_err(ImportKeyPairError::ConvertFromOpenssl)?;
+
+                (
+                    pkcs11_sys::CKK_EC,
+                    vec![
+                        (pkcs11_sys::CKA_EC_PARAMS, oid.clone()),
+                        (pkcs11_sys::CKA_EC_POINT, der_octet_string(&point)),
+                    ],
+                    vec![
+                        (pkcs11_sys::CKA_EC_PARAMS, oid),
+                        (pkcs11_sys::CKA_VALUE, ec_key.private_key().to_vec()),
+                    ],
+                )
+            }
 
-        let session_info = session_info.assume_init();
-        match session_info.state {
-            pkcs11_sys::CKS_RO_USER_FUNCTIONS
-            | pkcs11_sys::CKS_RW_USER_FUNCTIONS
-            | pkcs11_sys::CKS_RW_SO_FUNCTIONS => return Ok(()),
+            openssl::pkey::Id::ED25519 => {
+                let oid = openssl2::ed25519::OID_DER.to_owned();
+                let point = openssl2::ed25519::public_key_to_raw(private_key)
+                    .map_err(ImportKeyPairError::ConvertFromOpenssl)?;
+                let value = openssl2::ed25519::private_key_to_raw(private_key)
+                    .map_err(ImportKeyPairError::ConvertFromOpenssl)?;
+
+                (
+                    pkcs11_sys::CKK_EC_EDWARDS,
+                    vec![
+                        (pkcs11_sys::CKA_EC_PARAMS, oid.clone()),
+                        (pkcs11_sys::CKA_EC_POINT, der_octet_string(&point)),
+                    ],
+                    vec![
+                        (pkcs11_sys::CKA_EC_PARAMS, oid),
+                        (pkcs11_sys::CKA_VALUE, value),
+                    ],
+                )
+            }
 
-            _ => (),
-        }
+            openssl::pkey::Id::RSA => {
+                let rsa = private_key
+                    .rsa()
+                    .map_err(ImportKeyPairError::ConvertFromOpenssl)?;
+
+                // Tokens need the CRT components as well as the private exponent.
+                let crt_component = |value: Option<&openssl::bn::BigNumRef>| {
+                    value
+                        .map(openssl::bn::BigNumRef::to_vec)
+                        .ok_or(ImportKeyPairError::UnsupportedKeyType)
+                };
+
+                (
+                    pkcs11_sys::CKK_RSA,
+                    vec![
+                        (pkcs11_sys::CKA_MODULUS, rsa.n().to_vec()),
+                        (pkcs11_sys::CKA_PUBLIC_EXPONENT, rsa.e().to_vec()),
+                    ],
+                    vec![
+                        (pkcs11_sys::CKA_MODULUS, rsa.n().to_vec()),
+                        (pkcs11_sys::CKA_PUBLIC_EXPONENT, rsa.e().to_vec()),
+                        (pkcs11_sys::CKA_PRIVATE_EXPONENT, rsa.d().to_vec()),
+                        (pkcs11_sys::CKA_PRIME_1, crt_component(rsa.p())?),
+                        (pkcs11_sys::CKA_PRIME_2, crt_component(rsa.q())?),
+                        (pkcs11_sys::CKA_EXPONENT_1, crt_component(rsa.dmp1())?),
+                        (pkcs11_sys::CKA_EXPONENT_2, crt_component(rsa.dmq1())?),
+                        (pkcs11_sys::CKA_COEFFICIENT, crt_component(rsa.iqmp())?),
+                    ],
+                )
+            }
 
-        if let Some(pin) = &self.pin {
-            let result = (self.context.C_Login)(
+            _ => return Err(ImportKeyPairError::UnsupportedKeyType),
+        };
+
+        unsafe {
+            // Deleting existing keys and importing new ones needs login
+            self.login().map_err(ImportKeyPairError::LoginFailed)?;
+
+            // If label is set, delete any existing objects with that label first
+            if let Some(label) = label {
+                for &class in &[pkcs11_sys::CKO_PUBLIC_KEY, pkcs11_sys::CKO_PRIVATE_KEY] {
+                    match self.get_key_inner(class, Some(label)) {
+                        Ok(key_handle) => {
+                            let result = (self.context.C_DestroyObject)(self.handle, key_handle);
+                            if result != pkcs11_sys::CKR_OK {
+                                return Err(ImportKeyPairError::DeleteExistingKeyFailed(result));
+                            }
+                        }
+                        Err(GetKeyError::KeyDoesNotExist) => (),
+                        Err(err) => return Err(ImportKeyPairError::GetExistingKeyFailed(err)),
+                    }
+                }
+            }
+
+            let public_key_class = pkcs11_sys::CKO_PUBLIC_KEY;
+            let private_key_class = pkcs11_sys::CKO_PRIVATE_KEY;
+
+            let r#true = pkcs11_sys::CK_TRUE;
+            let true_size = std::convert::TryInto::try_into(std::mem::size_of_val(&r#true))
+                .expect("usize -> CK_ULONG");
+            let r#true = (&r#true as *const pkcs11_sys::CK_BBOOL).cast();
+
+            let r#false = pkcs11_sys::CK_FALSE;
+            let false_size = std::convert::TryInto::try_into(std::mem::size_of_val(&r#false))
+                .expect("usize -> CK_ULONG");
+            let r#false = (&r#false as *const pkcs11_sys::CK_BBOOL).cast();
+
+            let key_type_attribute = pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_KEY_TYPE,
+                pValue: (&key_type as *const pkcs11_sys::CK_KEY_TYPE).cast(),
+                ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(&key_type))
+                    .expect("usize -> CK_ULONG"),
+            };
+
+            let label_attribute = label.map(|label| pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_LABEL,
+                pValue: label.as_ptr().cast(),
+                ulValueLen: std::convert::TryInto::try_into(label.len())
+                    .expect("usize -> CK_ULONG"),
+            });
+
+            let value_attribute = |(r#type, value): &(pkcs11_sys::CK_ATTRIBUTE_TYPE, Vec<u8>)| {
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: *r#type,
+                    pValue: value.as_ptr().cast(),
+                    ulValueLen: std::convert::TryInto::try_into(value.len())
+                        .expect("usize -> CK_ULONG"),
+                }
+            };
+
+            // Same attributes as generated key pairs. See `generate_key_pair_inner`
+            let mut public_key_template = vec![
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_CLASS,
+                    pValue: (&public_key_class as *const pkcs11_sys::CK_OBJECT_CLASS).cast(),
+                    ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(
+                        &public_key_class,
+                    ))
+                    .expect("usize -> CK_ULONG"),
+                },
+                key_type_attribute,
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_ENCRYPT,
+                    pValue: r#true,
+                    ulValueLen: true_size,
+                },
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_PRIVATE,
+                    pValue: r#false,
+                    ulValueLen: false_size,
+                },
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_TOKEN,
+                    pValue: r#true,
+                    ulValueLen: true_size,
+                },
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_VERIFY,
+                    pValue: r#true,
+                    ulValueLen: true_size,
+                },
+            ];
+            public_key_template.extend(public_key_values.iter().map(value_attribute));
+            public_key_template.extend(label_attribute);
+
+            let mut private_key_template = vec![
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_CLASS,
+                    pValue: (&private_key_class as *const pkcs11_sys::CK_OBJECT_CLASS).cast(),
+                    ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(
+                        &private_key_class,
+                    ))
+                    .expect("usize -> CK_ULONG"),
+                },
+                key_type_attribute,
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_DECRYPT,
+                    pValue: r#true,
+                    ulValueLen: true_size,
+                },
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_PRIVATE,
+                    pValue: r#true,
+                    ulValueLen: true_size,
+                },
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_SENSITIVE,
+                    pValue: r#true,
+                    ulValueLen: true_size,
+                },
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_SIGN,
+                    pValue: r#true,
+                    ulValueLen: true_size,
+                },
+                pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_TOKEN,
+                    pValue: r#true,
+                    ulValueLen: true_size,
+                },
+            ];
+            if key_type == pkcs11_sys::CKK_EC {
+                private_key_template.push(pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_DERIVE,
+                    pValue: r#true,
+                    ulValueLen: true_size,
+                });
+            }
+            private_key_template.extend(private_key_values.iter().map(value_attribute));
+            private_key_template.extend(label_attribute);
+
+            let mut public_key_handle = pkcs11_sys::CK_INVALID_OBJECT_HANDLE;
+            let result = (self.context.C_CreateObject)(
                 self.handle,
-                pkcs11_sys::CKU_USER,
-                pin.as_ptr().cast(),
-                std::convert::TryInto::try_into(pin.len()).expect("usize -> CK_ULONG"),
+                public_key_template.as_ptr().cast(),
+                std::convert::TryInto::try_into(public_key_template.len())
+                    .expect("usize -> CK_ULONG"),
+                &mut public_key_handle,
             );
-            if result != pkcs11_sys::CKR_OK && result != pkcs11_sys::CKR_USER_ALREADY_LOGGED_IN {
-                return Err(LoginError::LoginFailed(result));
+            if result != pkcs11_sys::CKR_OK {
+                return Err(ImportKeyPairError::CreateObjectFailed("public", result));
+            }
+            if public_key_handle == pkcs11_sys::CK_INVALID_OBJECT_HANDLE {
+                return Err(ImportKeyPairError::CreateObjectDidNotReturnHandle("public"));
+            }
+
+            let mut private_key_handle = pkcs11_sys::CK_INVALID_OBJECT_HANDLE;
+            let result = (self.context.C_CreateObject)(
+                self.handle,
+                private_key_template.as_ptr().cast(),
+                std::convert::TryInto::try_into(private_key_template.len())
+                    .expect("usize -> CK_ULONG"),
+                &mut private_key_handle,
+            );
+            if result != pkcs11_sys::CKR_OK
+                || private_key_handle == pkcs11_sys::CK_INVALID_OBJECT_HANDLE
+            {
+                // Don't leave a public key object behind without its private key.
+                let _ = (self.context.C_DestroyObject)(self.handle, public_key_handle);
+
+                if result != pkcs11_sys::CKR_OK {
+                    return Err(ImportKeyPairError::CreateObjectFailed("private", result));
+                }
+                return Err(ImportKeyPairError::CreateObjectDidNotReturnHandle(
+                    "private",
+                ));
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Encodes the given bytes as a DER octet string, as expected for `CKA_EC_POINT`.
+fn der_octet_string(value: &[u8]) -> Vec<u8> {
+    let mut result = vec![0x04];
+    // EC points are at most 133 bytes (P-521), so the length is at most two bytes in DER.
+    if value.len() >= 0x80 {
+        result.push(0x81);
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    result.push(value.len() as u8);
+    result.extend_from_slice(value);
+    result
+}
+
+/// An error from importing a key pair.
+#[derive(Debug)]
+pub enum ImportKeyPairError {
+    ConvertFromOpenssl(openssl::error::ErrorStack),
+    CreateObjectDidNotReturnHandle(&'static str),
+    CreateObjectFailed(&'static str, pkcs11_sys::CK_RV),
+    DeleteExistingKeyFailed(pkcs11_sys::CK_RV),
+    GetExistingKeyFailed(GetKeyError),
+    LoginFailed(crate::LoginError),
+    UnsupportedKeyType,
+}
+
+impl std::fmt::Display for ImportKeyPairError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportKeyPairError::ConvertFromOpenssl(_) => f.write_str("could not convert private key from openssl"),
+            ImportKeyPairError::CreateObjectDidNotReturnHandle(kind) =>
+                write!(f, "could not import key pair: C_CreateObject succeeded but {} key handle is still CK_INVALID_HANDLE", kind),
+            ImportKeyPairError::CreateObjectFailed(kind, result) => write!(f, "could not import key pair: C_CreateObject failed for {} key with {}", kind, result),
+            ImportKeyPairError::DeleteExistingKeyFailed(result) => write!(f, "C_DestroyObject failed with {}", result),
+            ImportKeyPairError::GetExistingKeyFailed(_) => write!(f, "could not get existing key object"),
+            ImportKeyPairError::LoginFailed(_) => f.write_str("could not log in to the token"),
+            ImportKeyPairError::UnsupportedKeyType => f.write_str("could not import key pair: key type is not supported"),
+        }
+    }
+}
+
+impl std::error::Error for ImportKeyPairError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        #[allow(clippy::match_same_arms)]
+        match self {
+            ImportKeyPairError::ConvertFromOpenssl(inner) => Some(inner),
+            ImportKeyPairError::CreateObjectDidNotReturnHandle(_) => None,
+            ImportKeyPairError::CreateObjectFailed(_, _) => None,
+            ImportKeyPairError::DeleteExistingKeyFailed(_) => None,
+            ImportKeyPairError::GetExistingKeyFailed(inner) => Some(inner),
+            ImportKeyPairError::LoginFailed(inner) => Some(inner),
+            ImportKeyPairError::UnsupportedKeyType => None,
+        }
+    }
+}
+
+impl Session {
+    /// Delete the symmetric key in the current session with the given label.
+    ///
+    /// Succeeds if no such key exists.
+    pub fn delete_key(self: std::sync::Arc<Self>, label: &str) -> Result<(), DeleteKeyError> {
+        unsafe { self.delete_key_inner(&[pkcs11_sys::CKO_SECRET_KEY], label) }
+    }
+
+    /// Delete the public and private keys of the key pair in the current session with the given label.
+    ///
+    /// Succeeds if no such key pair exists.
+    pub fn delete_key_pair(self: std::sync::Arc<Self>, label: &str) -> Result<(), DeleteKeyError> {
+        unsafe {
+            self.delete_key_inner(
+                &[pkcs11_sys::CKO_PUBLIC_KEY, pkcs11_sys::CKO_PRIVATE_KEY],
+                label,
+            )
+        }
+    }
+
+    /// The label is required, since without one `get_key_inner` would return whichever object of the class it finds first.
+    unsafe fn delete_key_inner(
+        &self,
+        classes: &[pkcs11_sys::CK_OBJECT_CLASS],
+        label: &str,
+    ) -> Result<(), DeleteKeyError> {
+        // Deleting private objects needs login
+        self.login().map_err(DeleteKeyError::LoginFailed)?;
+
+        for &class in classes {
+            match self.get_key_inner(class, Some(label)) {
+                Ok(key_handle) => {
+                    let result = (self.context.C_DestroyObject)(self.handle, key_handle);
+                    if result != pkcs11_sys::CKR_OK {
+                        return Err(DeleteKeyError::DeleteKeyFailed(result));
+                    }
+                }
+                Err(GetKeyError::KeyDoesNotExist) => (),
+                Err(err) => return Err(DeleteKeyError::GetKeyFailed(err)),
             }
-        } else {
-            // Don't fail if PIN was never provided to us. We decide to log in proactively, so it's *possible* the operation we're trying to log in for
-            // doesn't actually need a login.
-            //
-            // So we pretend to succeed. If the operation did require a login after all, it'll fail with the approprate error.
         }
 
         Ok(())
     }
 }
 
-/// An error from logging in to the token.
+/// An error from deleting a key or key pair.
 #[derive(Debug)]
-pub enum LoginError {
-    GetSessionInfoFailed(pkcs11_sys::CK_RV),
-    LoginFailed(pkcs11_sys::CK_RV),
+pub enum DeleteKeyError {
+    DeleteKeyFailed(pkcs11_sys::CK_RV),
+    GetKeyFailed(GetKeyError),
+    LoginFailed(crate::LoginError),
 }
 
-impl std::fmt::Display for LoginError {
+impl std::fmt::Display for DeleteKeyError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            LoginError::GetSessionInfoFailed(result) => {
-                write!(f, "C_GetSessionInfo failed with {}", result)
+            DeleteKeyError::DeleteKeyFailed(result) => {
+                write!(f, "C_DestroyObject failed with {}", result)
             }
-            LoginError::LoginFailed(result) => write!(f, "C_Login failed with {}", result),
+            DeleteKeyError::GetKeyFailed(_) => f.write_str("could not get key object"),
+            DeleteKeyError::LoginFailed(_) => f.write_str("could not log in to the token"),
         }
     }
 }
 
-impl std::error::Error for LoginError {}
+impl std::error::Error for DeleteKeyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeleteKeyError::DeleteKeyFailed(_) => None,
+            DeleteKeyError::GetKeyFailed(inner) => Some(inner),
+            DeleteKeyError::LoginFailed(inner) => Some(inner),
+        }
+    }
+}
+
+impl Session {
+    /// Rename the key pair in the current session with the given label, by changing the `CKA_LABEL` of its public and private keys.
+    ///
+    /// Any existing key pair with the new label is replaced. It is only deleted once the renamed key pair has its label,
+    /// and is restored if renaming fails. The key material never leaves the token.
+    pub fn rename_key_pair(
+        self: std::sync::Arc<Self>,
+        label: &str,
+        new_label: &str,
+    ) -> Result<(), RenameKeyPairError> {
+        unsafe {
+            // Modifying private objects needs login
+            self.login().map_err(RenameKeyPairError::LoginFailed)?;
+
+            let public_key_handle = self
+                .get_key_inner(pkcs11_sys::CKO_PUBLIC_KEY, Some(label))
+                .map_err(RenameKeyPairError::GetKeyFailed)?;
+            let private_key_handle = self
+                .get_key_inner(pkcs11_sys::CKO_PRIVATE_KEY, Some(label))
+                .map_err(RenameKeyPairError::GetKeyFailed)?;
+
+            let mut existing_key_handles = vec![];
+            for &class in &[pkcs11_sys::CKO_PUBLIC_KEY, pkcs11_sys::CKO_PRIVATE_KEY] {
+                match self.get_key_inner(class, Some(new_label)) {
+                    Ok(key_handle) => existing_key_handles.push(key_handle),
+                    Err(GetKeyError::KeyDoesNotExist) => (),
+                    Err(err) => return Err(RenameKeyPairError::GetKeyFailed(err)),
+                }
+            }
+
+            // Move the existing key pair out of the way, so that looking up the new label finds exactly one object of each class.
+            if !existing_key_handles.is_empty() {
+                let temporary_label = temporary_label(new_label)
+                    .map_err(RenameKeyPairError::GenerateTemporaryLabelFailed)?;
+                for (i, &key_handle) in existing_key_handles.iter().enumerate() {
+                    if let Err(err) = self.set_label(key_handle, &temporary_label) {
+                        self.restore_labels(&existing_key_handles[..i], new_label);
+                        return Err(err);
+                    }
+                }
+            }
+
+            if let Err(err) = self.set_label(private_key_handle, new_label) {
+                self.restore_labels(&existing_key_handles, new_label);
+                return Err(err);
+            }
+            if let Err(err) = self.set_label(public_key_handle, new_label) {
+                // Put the private key back under the old label so that the key pair stays usable.
+                let _ = self.set_label(private_key_handle, label);
+                self.restore_labels(&existing_key_handles, new_label);
+                return Err(err);
+            }
+
+            for key_handle in existing_key_handles {
+                let result = (self.context.C_DestroyObject)(self.handle, key_handle);
+                if result != pkcs11_sys::CKR_OK {
+                    return Err(RenameKeyPairError::DeleteExistingKeyFailed(
+                        DeleteKeyError::DeleteKeyFailed(result),
+                    ));
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    unsafe fn set_label(
+        &self,
+        key_handle: pkcs11_sys::CK_OBJECT_HANDLE,
+        label: &str,
+    ) -> Result<(), RenameKeyPairError> {
+        let template = pkcs11_sys::CK_ATTRIBUTE_IN {
+            r#type: pkcs11_sys::CKA_LABEL,
+            pValue: label.as_ptr().cast(),
+            ulValueLen: std::convert::TryInto::try_into(label.len()).expect("usize -> CK_ULONG"),
+        };
+        let result = (self.context.C_SetAttributeValue)(self.handle, key_handle, &template, 1);
+        if result != pkcs11_sys::CKR_OK {
+            return Err(RenameKeyPairError::SetLabelFailed(result));
+        }
+
+        Ok(())
+    }
+
+    /// Best-effort attempt to put the given objects back under the given label after a failed rename.
+    unsafe fn restore_labels(&self, key_handles: &[pkcs11_sys::CK_OBJECT_HANDLE], label: &str) {
+        for &key_handle in key_handles {
+            let _ = self.set_label(key_handle, label);
+        }
+    }
+}
+
+/// A label that no other object is expected to have, for holding objects with the given label while they're being replaced.
+fn temporary_label(label: &str) -> Result<String, openssl::error::ErrorStack> {
+    let mut suffix = [0_u8; 16];
+    openssl::rand::rand_bytes(&mut suffix)?;
+
+    let suffix: String = suffix.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!("{}.replaced-{}", label, suffix))
+}
+
+/// An error from renaming a key pair.
+#[derive(Debug)]
+pub enum RenameKeyPairError {
+    DeleteExistingKeyFailed(DeleteKeyError),
+    GenerateTemporaryLabelFailed(openssl::error::ErrorStack),
+    GetKeyFailed(GetKeyError),
+    LoginFailed(crate::LoginError),
+    SetLabelFailed(pkcs11_sys::CK_RV),
+}
+
+impl std::fmt::Display for RenameKeyPairError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenameKeyPairError::DeleteExistingKeyFailed(_) => {
+                f.write_str("could not delete existing key pair with the new label")
+            }
+            RenameKeyPairError::GenerateTemporaryLabelFailed(_) => {
+                f.write_str("could not generate temporary label for existing key pair")
+            }
+            RenameKeyPairError::GetKeyFailed(_) => f.write_str("could not get key object"),
+            RenameKeyPairError::LoginFailed(_) => f.write_str("could not log in to the token"),
+            RenameKeyPairError::SetLabelFailed(result) => {
+                write!(f, "C_SetAttributeValue(CKA_LABEL) failed with {}", result)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenameKeyPairError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenameKeyPairError::DeleteExistingKeyFailed(inner) => Some(inner),
+            RenameKeyPairError::GenerateTemporaryLabelFailed(inner) => Some(inner),
+            RenameKeyPairError::GetKeyFailed(inner) => Some(inner),
+            RenameKeyPairError::LoginFailed(inner) => Some(inner),
+            RenameKeyPairError::SetLabelFailed(_) => None,
+        }
+    }
+}
+
+impl Session {
+    /// Get the certificate chain in the current session with the given label.
+    ///
+    /// The chain is stored as one `CKO_CERTIFICATE` object per certificate, all with the same label. The certificates are returned
+    /// ordered from the leaf to the root, according to which certificate issued which.
+    ///
+    /// Returns `None` if there are no certificates with the given label.
+    pub fn get_cert(&self, label: &str) -> Result<Option<Vec<openssl::x509::X509>>, GetCertError> {
+        unsafe {
+            // Private objects are only visible after login
+            self.login().map_err(GetCertError::LoginFailed)?;
+
+            let cert_handles = self
+                .find_cert_handles(label)
+                .map_err(GetCertError::FindObjectsFailed)?;
+            if cert_handles.is_empty() {
+                return Ok(None);
+            }
+
+            let mut certs = Vec::with_capacity(cert_handles.len());
+            for cert_handle in cert_handles {
+                let cert = self
+                    .get_bytes_attribute(cert_handle, pkcs11_sys::CKA_VALUE)
+                    .map_err(GetCertError::GetValueFailed)?
+                    .ok_or(GetCertError::MissingValue)?;
+                let cert =
+                    openssl::x509::X509::from_der(&cert).map_err(GetCertError::InvalidCert)?;
+                certs.push(cert);
+            }
+
+            Ok(Some(order_cert_chain(certs)))
+        }
+    }
+
+    /// Import a certificate chain in the current session with the given label.
+    ///
+    /// Each certificate is stored as a separate `CKO_CERTIFICATE` object with the given label.
+    /// Any existing certificates with that label are deleted once the new ones have all been created,
+    /// so a failure to import leaves the existing certificates in place.
+    pub fn import_cert(
+        &self,
+        label: &str,
+        certs: &[openssl::x509::X509],
+    ) -> Result<(), ImportCertError> {
+        unsafe {
+            // Deleting existing certs and importing new ones needs login
+            self.login().map_err(ImportCertError::LoginFailed)?;
+
+            let existing_cert_handles = self.find_cert_handles(label).map_err(|err| {
+                ImportCertError::DeleteExistingCertFailed(DeleteCertError::FindObjectsFailed(err))
+            })?;
+
+            let certs: Vec<_> = certs
+                .iter()
+                .map(|cert| -> Result<_, openssl::error::ErrorStack> {
+                    Ok((
+                        cert.to_der()?,
+                        x509_name_to_der(cert.subject_name())?,
+                        x509_name_to_der(cert.issuer_name())?,
+                    ))
+                })
+                .collect::<Result<_, _>>()
+                .map_err(ImportCertError::ConvertCert)?;
+
+            let mut cert_handles = Vec::with_capacity(certs.len());
+            for (value, subject, issuer) in &certs {
+                match self.create_cert_object(label, value, subject, issuer) {
+                    Ok(cert_handle) => cert_handles.push(cert_handle),
+                    Err(err) => {
+                        // Don't leave a partial chain behind next to the existing one.
+                        for cert_handle in cert_handles {
+                            let _ = (self.context.C_DestroyObject)(self.handle, cert_handle);
+                        }
+                        return Err(err);
+                    }
+                }
+            }
+
+            for cert_handle in existing_cert_handles {
+                let result = (self.context.C_DestroyObject)(self.handle, cert_handle);
+                if result != pkcs11_sys::CKR_OK {
+                    return Err(ImportCertError::DeleteExistingCertFailed(
+                        DeleteCertError::DeleteCertFailed(result),
+                    ));
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    unsafe fn create_cert_object(
+        &self,
+        label: &str,
+        value: &[u8],
+        subject: &[u8],
+        issuer: &[u8],
+    ) -> Result<pkcs11_sys::CK_OBJECT_HANDLE, ImportCertError> {
+        let class = pkcs11_sys::CKO_CERTIFICATE;
+        let certificate_type = pkcs11_sys::CKC_X_509;
+
+        let r#true = pkcs11_sys::CK_TRUE;
+        let r#false = pkcs11_sys::CK_FALSE;
+        let bool_size = std::convert::TryInto::try_into(std::mem::size_of_val(&r#true))
+            .expect("usize -> CK_ULONG");
+
+        let cert_template = [
+            pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_CLASS,
+                pValue: (&class as *const pkcs11_sys::CK_OBJECT_CLASS).cast(),
+                ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(&class))
+                    .expect("usize -> CK_ULONG"),
+            },
+            pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_CERTIFICATE_TYPE,
+                pValue: (&certificate_type as *const pkcs11_sys::CK_CERTIFICATE_TYPE).cast(),
+                ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(
+                    &certificate_type,
+                ))
+                .expect("usize -> CK_ULONG"),
+            },
+            pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_TOKEN,
+                pValue: (&r#true as *const pkcs11_sys::CK_BBOOL).cast(),
+                ulValueLen: bool_size,
+            },
+            pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_PRIVATE,
+                pValue: (&r#false as *const pkcs11_sys::CK_BBOOL).cast(),
+                ulValueLen: bool_size,
+            },
+            pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_LABEL,
+                pValue: label.as_ptr().cast(),
+                ulValueLen: std::convert::TryInto::try_into(label.len())
+                    .expect("usize -> CK_ULONG"),
+            },
+            pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_SUBJECT,
+                pValue: subject.as_ptr().cast(),
+                ulValueLen: std::convert::TryInto::try_into(subject.len())
+                    .expect("usize -> CK_ULONG"),
+            },
+            pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_ISSUER,
+                pValue: issuer.as_ptr().cast(),
+                ulValueLen: std::convert::TryInto::try_into(issuer.len())
+                    .expect("usize -> CK_ULONG"),
+            },
+            pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_VALUE,
+                pValue: value.as_ptr().cast(),
+                ulValueLen: std::convert::TryInto::try_into(value.len())
+                    .expect("usize -> CK_ULONG"),
+            },
+        ];
+
+        let mut cert_handle = pkcs11_sys::CK_INVALID_OBJECT_HANDLE;
+
+        let result = (self.context.C_CreateObject)(
+            self.handle,
+            cert_template.as_ptr().cast(),
+            std::convert::TryInto::try_into(cert_template.len()).expect("usize -> CK_ULONG"),
+            &mut cert_handle,
+        );
+        if result != pkcs11_sys::CKR_OK {
+            return Err(ImportCertError::CreateObjectFailed(result));
+        }
+        if cert_handle == pkcs11_sys::CK_INVALID_OBJECT_HANDLE {
+            return Err(ImportCertError::CreateObjectDidNotReturnHandle);
+        }
+
+        Ok(cert_handle)
+    }
+
+    /// Delete the certificates in the current session with the given label.
+    ///
+    /// Succeeds if no such certificates exist.
+    pub fn delete_cert(&self, label: &str) -> Result<(), DeleteCertError> {
+        unsafe {
+            // Deleting private objects needs login
+            self.login().map_err(DeleteCertError::LoginFailed)?;
+
+            self.delete_cert_inner(label)
+        }
+    }
+
+    unsafe fn delete_cert_inner(&self, label: &str) -> Result<(), DeleteCertError> {
+        let cert_handles = self
+            .find_cert_handles(label)
+            .map_err(DeleteCertError::FindObjectsFailed)?;
+        for cert_handle in cert_handles {
+            let result = (self.context.C_DestroyObject)(self.handle, cert_handle);
+            if result != pkcs11_sys::CKR_OK {
+                return Err(DeleteCertError::DeleteCertFailed(result));
+            }
+        }
+
+        Ok(())
+    }
+
+    unsafe fn find_cert_handles(
+        &self,
+        label: &str,
+    ) -> Result<Vec<pkcs11_sys::CK_OBJECT_HANDLE>, FindObjectsError> {
+        let class = pkcs11_sys::CKO_CERTIFICATE;
+        let templates = [
+            pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_CLASS,
+                pValue: (&class as *const pkcs11_sys::CK_OBJECT_CLASS).cast(),
+                ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(&class))
+                    .expect("usize -> CK_ULONG"),
+            },
+            pkcs11_sys::CK_ATTRIBUTE_IN {
+                r#type: pkcs11_sys::CKA_LABEL,
+                pValue: label.as_ptr().cast(),
+                ulValueLen: std::convert::TryInto::try_into(label.len())
+                    .expect("usize -> CK_ULONG"),
+            },
+        ];
+
+        // Collect all the handles before using them, since some PKCS#11 implementations
+        // don't allow other operations on the session while a search is active.
+        let cert_handles = FindObjects::new(self, &templates)?.collect();
+        cert_handles
+    }
+}
+
+/// Orders the given certificates from the leaf to the root, by following each certificate to the one that issued it.
+///
+/// Certificates that aren't part of the leaf's chain are kept at the end, in their original order.
+fn order_cert_chain(mut certs: Vec<openssl::x509::X509>) -> Vec<openssl::x509::X509> {
+    fn issued(issuer: &openssl::x509::X509Ref, subject: &openssl::x509::X509Ref) -> bool {
+        issuer.issued(subject) == openssl::x509::X509VerifyResult::OK
+    }
+
+    // The leaf is the first certificate that didn't issue any of the others.
+    let leaf_index = certs.iter().position(|cert| {
+        !certs
+            .iter()
+            .any(|other| !std::ptr::eq(cert, other) && issued(cert, other))
+    });
+    let leaf_index = match leaf_index {
+        Some(leaf_index) => leaf_index,
+        None => return certs,
+    };
+
+    let mut result = Vec::with_capacity(certs.len());
+    result.push(certs.remove(leaf_index));
+
+    loop {
+        let last = result.last().expect("result has at least the leaf");
+        let issuer_index = match certs.iter().position(|cert| issued(cert, last)) {
+            Some(issuer_index) => issuer_index,
+            None => break,
+        };
+        result.push(certs.remove(issuer_index));
+    }
+
+    result.extend(certs);
+    result
+}
+
+fn x509_name_to_der(
+    name: &openssl::x509::X509NameRef,
+) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+    unsafe {
+        let name = foreign_types_shared::ForeignTypeRef::as_ptr(name);
+
+        let len = openssl_sys2::i2d_X509_NAME(name, std::ptr::null_mut());
+        let len: usize = match std::convert::TryInto::try_into(len) {
+            Ok(len) if len > 0 => len,
+            _ => return Err(openssl::error::ErrorStack::get()),
+        };
+
+        let mut buf = vec![0_u8; len];
+        let len = openssl_sys2::i2d_X509_NAME(name, &mut buf.as_mut_ptr());
+        let len: usize = match std::convert::TryInto::try_into(len) {
+            Ok(len) if len > 0 => len,
+            _ => return Err(openssl::error::ErrorStack::get()),
+        };
+        buf.truncate(len);
+
+        Ok(buf)
+    }
+}
+
+/// An error from getting a certificate chain.
+#[derive(Debug)]
+pub enum GetCertError {
+    FindObjectsFailed(FindObjectsError),
+    GetValueFailed(pkcs11_sys::CK_RV),
+    InvalidCert(openssl::error::ErrorStack),
+    LoginFailed(crate::LoginError),
+    MissingValue,
+}
+
+impl std::fmt::Display for GetCertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GetCertError::FindObjectsFailed(_) => f.write_str("could not find objects"),
+            GetCertError::GetValueFailed(result) => {
+                write!(f, "C_GetAttributeValue(CKA_VALUE) failed with {}", result)
+            }
+            GetCertError::InvalidCert(_) => f.write_str("certificate is not a valid X.509 cert"),
+            GetCertError::LoginFailed(_) => f.write_str("could not log in to the token"),
+            GetCertError::MissingValue => f.write_str("certificate object has no value"),
+        }
+    }
+}
+
+impl std::error::Error for GetCertError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        #[allow(clippy::match_same_arms)]
+        match self {
+            GetCertError::FindObjectsFailed(inner) => Some(inner),
+            GetCertError::GetValueFailed(_) => None,
+            GetCertError::InvalidCert(inner) => Some(inner),
+            GetCertError::LoginFailed(inner) => Some(inner),
+            GetCertError::MissingValue => None,
+        }
+    }
+}
+
+/// An error from importing a certificate chain.
+#[derive(Debug)]
+pub enum ImportCertError {
+    ConvertCert(openssl::error::ErrorStack),
+    CreateObjectDidNotReturnHandle,
+    CreateObjectFailed(pkcs11_sys::CK_RV),
+    DeleteExistingCertFailed(DeleteCertError),
+    LoginFailed(crate::LoginError),
+}
+
+impl std::fmt::Display for ImportCertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportCertError::ConvertCert(_) => f.write_str("could not convert certificate to DER"),
+            ImportCertError::CreateObjectDidNotReturnHandle => {
+                f.write_str("C_CreateObject succeeded but object handle is still CK_INVALID_HANDLE")
+            }
+            ImportCertError::CreateObjectFailed(result) => {
+                write!(f, "C_CreateObject failed with {}", result)
+            }
+            ImportCertError::DeleteExistingCertFailed(_) => {
+                f.write_str("could not delete existing certificate")
+            }
+            ImportCertError::LoginFailed(_) => f.write_str("could not log in to the token"),
+        }
+    }
+}
+
+impl std::error::Error for ImportCertError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        #[allow(clippy::match_same_arms)]
+        match self {
+            ImportCertError::ConvertCert(inner) => Some(inner),
+            ImportCertError::CreateObjectDidNotReturnHandle => None,
+            ImportCertError::CreateObjectFailed(_) => None,
+            ImportCertError::DeleteExistingCertFailed(inner) => Some(inner),
+            ImportCertError::LoginFailed(inner) => Some(inner),
+        }
+    }
+}
+
+/// An error from deleting a certificate chain.
+#[derive(Debug)]
+pub enum DeleteCertError {
+    DeleteCertFailed(pkcs11_sys::CK_RV),
+    FindObjectsFailed(FindObjectsError),
+    LoginFailed(crate::LoginError),
+}
+
+impl std::fmt::Display for DeleteCertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeleteCertError::DeleteCertFailed(result) => {
+                write!(f, "C_DestroyObject failed with {}", result)
+            }
+            DeleteCertError::FindObjectsFailed(_) => f.write_str("could not find objects"),
+            DeleteCertError::LoginFailed(_) => f.write_str("could not log in to the token"),
+        }
+    }
+}
+
+impl std::error::Error for DeleteCertError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeleteCertError::DeleteCertFailed(_) => None,
+            DeleteCertError::FindObjectsFailed(inner) => Some(inner),
+            DeleteCertError::LoginFailed(inner) => Some(inner),
+        }
+    }
+}
+
+impl Session {
+    /// List the keys in the current session, ie the symmetric keys and the private keys of key pairs.
+    ///
+    /// The public keys of key pairs are not listed separately.
+    pub fn list_keys(&self) -> Result<Vec<KeyInfo>, ListKeysError> {
+        unsafe {
+            // Private objects are only visible after login
+            self.login().map_err(ListKeysError::LoginFailed)?;
+
+            let mut result = vec![];
+
+            for &class in &[pkcs11_sys::CKO_SECRET_KEY, pkcs11_sys::CKO_PRIVATE_KEY] {
+                let templates = [pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_CLASS,
+                    pValue: (&class as *const pkcs11_sys::CK_OBJECT_CLASS).cast(),
+                    ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(&class))
+                        .expect("usize -> CK_ULONG"),
+                }];
+
+                // Collect all the handles before querying their attributes, since some PKCS#11 implementations
+                // don't allow other operations on the session while a search is active.
+                let key_handles: Vec<_> = FindObjects::new(self, &templates)
+                    .map_err(ListKeysError::FindObjectsFailed)?
+                    .collect::<Result<_, _>>()
+                    .map_err(ListKeysError::FindObjectsFailed)?;
+
+                for key_handle in key_handles {
+                    let key_type = self
+                        .get_key_mechanism_type(key_handle)
+                        .map_err(ListKeysError::GetKeyFailed)?;
+                    let label = self.get_label(key_handle)?;
+                    result.push(KeyInfo {
+                        class,
+                        key_type,
+                        label,
+                    });
+                }
+            }
+
+            Ok(result)
+        }
+    }
+
+    unsafe fn get_label(
+        &self,
+        key_handle: pkcs11_sys::CK_OBJECT_HANDLE,
+    ) -> Result<Option<String>, ListKeysError> {
+        let label = self
+            .get_bytes_attribute(key_handle, pkcs11_sys::CKA_LABEL)
+            .map_err(ListKeysError::GetLabelFailed)?;
+        let label = match label {
+            Some(label) => label,
+            None => return Ok(None),
+        };
+
+        let label = String::from_utf8(label).map_err(|_| ListKeysError::InvalidLabel)?;
+        Ok(Some(label))
+    }
+
+    /// Get the value of a variable-length attribute of the given object, such as `CKA_LABEL` or `CKA_ID`.
+    ///
+    /// Returns `None` if the value is empty, or if the object doesn't have the attribute.
+    unsafe fn get_bytes_attribute(
+        &self,
+        object_handle: pkcs11_sys::CK_OBJECT_HANDLE,
+        r#type: pkcs11_sys::CK_ATTRIBUTE_TYPE,
+    ) -> Result<Option<Vec<u8>>, pkcs11_sys::CK_RV> {
+        let mut attribute = pkcs11_sys::CK_ATTRIBUTE {
+            r#type,
+            pValue: std::ptr::null_mut(),
+            ulValueLen: 0,
+        };
+        let result =
+            (self.context.C_GetAttributeValue)(self.handle, object_handle, &mut attribute, 1);
+        if result == pkcs11_sys::CKR_ATTRIBUTE_TYPE_INVALID {
+            return Ok(None);
+        }
+        if result != pkcs11_sys::CKR_OK {
+            return Err(result);
+        }
+        if attribute.ulValueLen == pkcs11_sys::CK_UNAVAILABLE_INFORMATION {
+            return Ok(None);
+        }
+
+        let value_len =
+            std::convert::TryInto::try_into(attribute.ulValueLen).expect("CK_ULONG -> usize");
+        if value_len == 0 {
+            return Ok(None);
+        }
+
+        let mut value = vec![0_u8; value_len];
+        attribute.pValue = value.as_mut_ptr().cast();
+        let result =
+            (self.context.C_GetAttributeValue)(self.handle, object_handle, &mut attribute, 1);
+        if result != pkcs11_sys::CKR_OK {
+            return Err(result);
+        }
+
+        let value_len =
+            std::convert::TryInto::try_into(attribute.ulValueLen).expect("CK_ULONG -> usize");
+        value.truncate(value_len);
+
+        Ok(Some(value))
+    }
+}
+
+/// Information about a key in a slot, as returned by [`Session::list_keys`].
+#[derive(Clone, Debug)]
+pub struct KeyInfo {
+    /// The class of the key object. One of `CKO_SECRET_KEY` or `CKO_PRIVATE_KEY`.
+    pub class: pkcs11_sys::CK_OBJECT_CLASS,
+
+    /// The type of the key, such as `CKK_AES` or `CKK_EC`.
+    pub key_type: pkcs11_sys::CK_KEY_TYPE,
+
+    /// The label of the key, if it has one.
+    pub label: Option<String>,
+}
+
+/// An error from listing keys.
+#[derive(Debug)]
+pub enum ListKeysError {
+    FindObjectsFailed(FindObjectsError),
+    GetKeyFailed(GetKeyError),
+    GetLabelFailed(pkcs11_sys::CK_RV),
+    InvalidLabel,
+    LoginFailed(crate::LoginError),
+}
+
+impl std::fmt::Display for ListKeysError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListKeysError::FindObjectsFailed(_) => f.write_str("could not find objects"),
+            ListKeysError::GetKeyFailed(_) => f.write_str("could not get key object"),
+            ListKeysError::GetLabelFailed(result) => {
+                write!(f, "C_GetAttributeValue(CKA_LABEL) failed with {}", result)
+            }
+            ListKeysError::InvalidLabel => f.write_str("key label is not valid UTF-8"),
+            ListKeysError::LoginFailed(_) => f.write_str("could not log in to the token"),
+        }
+    }
+}
+
+impl std::error::Error for ListKeysError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        #[allow(clippy::match_same_arms)]
+        match self {
+            ListKeysError::FindObjectsFailed(inner) => Some(inner),
+            ListKeysError::GetKeyFailed(inner) => Some(inner),
+            ListKeysError::GetLabelFailed(_) => None,
+            ListKeysError::InvalidLabel => None,
+            ListKeysError::LoginFailed(inner) => Some(inner),
+        }
+    }
+}
+
+impl Session {
+    /// Find the objects in the current session that match the given filter.
+    ///
+    /// Logs in to the token first, so that private objects are found too. Key objects are returned as the corresponding
+    /// typed [`crate::Object`], so they can be used for the operations of their key type directly.
+    pub fn find_objects(
+        self: std::sync::Arc<Self>,
+        filter: &ObjectFilter,
+    ) -> Result<Vec<FoundObject>, FindObjectsByFilterError> {
+        unsafe {
+            // Private objects are only visible after login
+            self.login()
+                .map_err(FindObjectsByFilterError::LoginFailed)?;
+
+            let mut templates = vec![];
+            if let Some(class) = &filter.class {
+                templates.push(pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_CLASS,
+                    pValue: (class as *const pkcs11_sys::CK_OBJECT_CLASS).cast(),
+                    ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(class))
+                        .expect("usize -> CK_ULONG"),
+                });
+            }
+            if let Some(key_type) = &filter.key_type {
+                templates.push(pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_KEY_TYPE,
+                    pValue: (key_type as *const pkcs11_sys::CK_KEY_TYPE).cast(),
+                    ulValueLen: std::convert::TryInto::try_into(std::mem::size_of_val(key_type))
+                        .expect("usize -> CK_ULONG"),
+                });
+            }
+            if let Some(id) = &filter.id {
+                templates.push(pkcs11_sys::CK_ATTRIBUTE_IN {
+                    r#type: pkcs11_sys::CKA_ID,
+                    pValue: id.as_ptr().cast(),
+                    ulValueLen: std::convert::TryInto::try_into(id.len())
+                        .expect("usize -> CK_ULONG"),
+                });
+            }
+
+            // Collect all the handles before querying their attributes, since some PKCS#11 implementations
+            // don't allow other operations on the session while a search is active.
+            let object_handles: Vec<_> = FindObjects::new(&self, &templates)
+                .map_err(FindObjectsByFilterError::FindObjectsFailed)?
+                .collect::<Result<_, _>>()
+                .map_err(FindObjectsByFilterError::FindObjectsFailed)?;
+
+            let mut result = vec![];
+
+            for object_handle in object_handles {
+                // PKCS#11 can only match attributes exactly, so the label prefix is matched here.
+                let label = self
+                    .get_bytes_attribute(object_handle, pkcs11_sys::CKA_LABEL)
+                    .map_err(|result| {
+                        FindObjectsByFilterError::GetAttributeFailed(pkcs11_sys::CKA_LABEL, result)
+                    })?;
+                if let Some(label_prefix) = &filter.label_prefix {
+                    match &label {
+                        Some(label) if label.starts_with(label_prefix.as_bytes()) => (),
+                        _ => continue,
+                    }
+                }
+
+                let class = self.get_object_class(object_handle)?;
+
+                let key_type = match class {
+                    pkcs11_sys::CKO_PUBLIC_KEY
+                    | pkcs11_sys::CKO_PRIVATE_KEY
+                    | pkcs11_sys::CKO_SECRET_KEY => Some(
+                        self.get_key_mechanism_type(object_handle)
+                            .map_err(FindObjectsByFilterError::GetKeyFailed)?,
+                    ),
+                    _ => None,
+                };
+
+                let id = self
+                    .get_bytes_attribute(object_handle, pkcs11_sys::CKA_ID)
+                    .map_err(|result| {
+                        FindObjectsByFilterError::GetAttributeFailed(pkcs11_sys::CKA_ID, result)
+                    })?;
+
+                let object = match (class, key_type) {
+                    (pkcs11_sys::CKO_PUBLIC_KEY, Some(pkcs11_sys::CKK_EC)) => {
+                        TypedObject::EcPublicKey(crate::Object::new(self.clone(), object_handle))
+                    }
+                    (pkcs11_sys::CKO_PRIVATE_KEY, Some(pkcs11_sys::CKK_EC)) => {
+                        TypedObject::EcPrivateKey(crate::Object::new(self.clone(), object_handle))
+                    }
+                    (pkcs11_sys::CKO_PUBLIC_KEY, Some(pkcs11_sys::CKK_RSA)) => {
+                        TypedObject::RsaPublicKey(crate::Object::new(self.clone(), object_handle))
+                    }
+                    (pkcs11_sys::CKO_PRIVATE_KEY, Some(pkcs11_sys::CKK_RSA)) => {
+                        TypedObject::RsaPrivateKey(crate::Object::new(self.clone(), object_handle))
+                    }
+                    (pkcs11_sys::CKO_PUBLIC_KEY, Some(pkcs11_sys::CKK_EC_EDWARDS)) => {
+                        TypedObject::Ed25519PublicKey(crate::Object::new(
+                            self.clone(),
+                            object_handle,
+                        ))
+                    }
+                    (pkcs11_sys::CKO_PRIVATE_KEY, Some(pkcs11_sys::CKK_EC_EDWARDS)) => {
+                        TypedObject::Ed25519PrivateKey(crate::Object::new(
+                            self.clone(),
+                            object_handle,
+                        ))
+                    }
+                    (pkcs11_sys::CKO_SECRET_KEY, _) => {
+                        TypedObject::SecretKey(crate::Object::new(self.clone(), object_handle))
+                    }
+                    _ => TypedObject::Other(crate::Object::new(self.clone(), object_handle)),
+                };
+
+                result.push(FoundObject {
+                    class,
+                    key_type,
+                    label,
+                    id,
+                    object,
+                });
+            }
+
+            Ok(result)
+        }
+    }
+
+    unsafe fn get_object_class(
+        &self,
+        object_handle: pkcs11_sys::CK_OBJECT_HANDLE,
+    ) -> Result<pkcs11_sys::CK_OBJECT_CLASS, FindObjectsByFilterError> {
+        let mut class = pkcs11_sys::CKO_DATA;
+        let class_size = std::convert::TryInto::try_into(std::mem::size_of_val(&class))
+            .expect("usize -> CK_ULONG");
+        let mut attribute = pkcs11_sys::CK_ATTRIBUTE {
+            r#type: pkcs11_sys::CKA_CLASS,
+            pValue: (&mut class as *mut pkcs11_sys::CK_OBJECT_CLASS).cast(),
+            ulValueLen: class_size,
+        };
+        let result =
+            (self.context.C_GetAttributeValue)(self.handle, object_handle, &mut attribute, 1);
+        if result != pkcs11_sys::CKR_OK {
+            return Err(FindObjectsByFilterError::GetAttributeFailed(
+                pkcs11_sys::CKA_CLASS,
+                result,
+            ));
+        }
+
+        Ok(class)
+    }
+}
+
+/// The criteria for [`Session::find_objects`]. Criteria that are `None` match all objects.
+#[derive(Clone, Debug, Default)]
+pub struct ObjectFilter {
+    /// Only find objects of this class, such as `CKO_PRIVATE_KEY` or `CKO_CERTIFICATE`.
+    pub class: Option<pkcs11_sys::CK_OBJECT_CLASS>,
+
+    /// Only find keys of this type, such as `CKK_AES` or `CKK_EC`.
+    pub key_type: Option<pkcs11_sys::CK_KEY_TYPE>,
+
+    /// Only find objects whose label starts with this prefix.
+    pub label_prefix: Option<String>,
+
+    /// Only find objects with this `CKA_ID`.
+    pub id: Option<Vec<u8>>,
+}
+
+/// An object in a slot, as returned by [`Session::find_objects`].
+pub struct FoundObject {
+    /// The class of the object, such as `CKO_PRIVATE_KEY` or `CKO_CERTIFICATE`.
+    pub class: pkcs11_sys::CK_OBJECT_CLASS,
+
+    /// The type of the key, such as `CKK_AES` or `CKK_EC`, if the object is a key.
+    pub key_type: Option<pkcs11_sys::CK_KEY_TYPE>,
+
+    /// The label of the object, if it has one.
+    ///
+    /// Labels are meant to be UTF-8, but tokens don't enforce that, so this is the raw value of `CKA_LABEL`.
+    pub label: Option<Vec<u8>>,
+
+    /// The `CKA_ID` of the object, if it has one.
+    pub id: Option<Vec<u8>>,
+
+    /// A handle to the object.
+    pub object: TypedObject,
+}
+
+/// A handle to an object found by [`Session::find_objects`], typed according to its class and key type.
+pub enum TypedObject {
+    EcPublicKey(crate::Object<openssl::ec::EcKey<openssl::pkey::Public>>),
+    EcPrivateKey(crate::Object<openssl::ec::EcKey<openssl::pkey::Private>>),
+    RsaPublicKey(crate::Object<openssl::rsa::Rsa<openssl::pkey::Public>>),
+    RsaPrivateKey(crate::Object<openssl::rsa::Rsa<openssl::pkey::Private>>),
+    Ed25519PublicKey(crate::Object<openssl::pkey::PKey<openssl::pkey::Public>>),
+    Ed25519PrivateKey(crate::Object<openssl::pkey::PKey<openssl::pkey::Private>>),
+    SecretKey(Key),
+
+    /// Any other object, such as a certificate or a data object, or a key of an unsupported type.
+    Other(crate::Object<()>),
+}
+
+/// An error from finding objects with [`Session::find_objects`].
+#[derive(Debug)]
+pub enum FindObjectsByFilterError {
+    FindObjectsFailed(FindObjectsError),
+    GetAttributeFailed(pkcs11_sys::CK_ATTRIBUTE_TYPE, pkcs11_sys::CK_RV),
+    GetKeyFailed(GetKeyError),
+    LoginFailed(crate::LoginError),
+}
+
+impl std::fmt::Display for FindObjectsByFilterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FindObjectsByFilterError::FindObjectsFailed(_) => f.write_str("could not find objects"),
+            FindObjectsByFilterError::GetAttributeFailed(r#type, result) => {
+                write!(f, "C_GetAttributeValue({}) failed with {}", r#type, result)
+            }
+            FindObjectsByFilterError::GetKeyFailed(_) => f.write_str("could not get key object"),
+            FindObjectsByFilterError::LoginFailed(_) => {
+                f.write_str("could not log in to the token")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FindObjectsByFilterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        #[allow(clippy::match_same_arms)]
+        match self {
+            FindObjectsByFilterError::FindObjectsFailed(inner) => Some(inner),
+            FindObjectsByFilterError::GetAttributeFailed(_, _) => None,
+            FindObjectsByFilterError::GetKeyFailed(inner) => Some(inner),
+            FindObjectsByFilterError::LoginFailed(inner) => Some(inner),
+        }
+    }
+}
+
+impl Session {
+    /// Whether the session is no longer usable, such as because the device was removed or the session was closed by the token.
+    ///
+    /// Other errors from querying the session are not treated as the session being lost.
+    pub fn is_lost(&self) -> bool {
+        unsafe {
+            let mut session_info = std::mem::MaybeUninit::uninit();
+            let result = (self.context.C_GetSessionInfo)(self.handle, session_info.as_mut_ptr());
+            matches!(
+                result,
+                pkcs11_sys::CKR_DEVICE_ERROR
+                    | pkcs11_sys::CKR_DEVICE_REMOVED
+                    | pkcs11_sys::CKR_SESSION_CLOSED
+                    | pkcs11_sys::CKR_SESSION_HANDLE_INVALID
+                    | pkcs11_sys::CKR_TOKEN_NOT_PRESENT
+            )
+        }
+    }
+
+    pub(crate) unsafe fn login(&self) -> Result<(), LoginError> {
+        let mut session_info = std::mem::MaybeUninit::uninit();
+        let result = (self.context.C_GetSessionInfo)(self.handle, session_info.as_mut_ptr());
+        if result != pkcs11_sys::CKR_OK {
+            return Err(LoginError::GetSessionInfoFailed(result));
+        }
+
+        let session_info = session_info.assume_init();
+        match session_info.state {
+            pkcs11_sys::CKS_RO_USER_FUNCTIONS
+            | pkcs11_sys::CKS_RW_USER_FUNCTIONS
+            | pkcs11_sys::CKS_RW_SO_FUNCTIONS => return Ok(()),
+
+            _ => (),
+        }
+
+        if let Some(pin) = &self.pin {
+            let result = (self.context.C_Login)(
+                self.handle,
+                pkcs11_sys::CKU_USER,
+                pin.as_ptr().cast(),
+                std::convert::TryInto::try_into(pin.len()).expect("usize -> CK_ULONG"),
+            );
+            if result != pkcs11_sys::CKR_OK && result != pkcs11_sys::CKR_USER_ALREADY_LOGGED_IN {
+                return Err(LoginError::LoginFailed(result));
+            }
+        } else {
+            // Don't fail if PIN was never provided to us. We decide to log in proactively, so it's *possible* the operation we're trying to log in for
+            // doesn't actually need a login.
+            //
+            // So we pretend to succeed. If the operation did require a login after all, it'll fail with the approprate error.
+        }
+
+        Ok(())
+    }
+}
+
+/// An error from logging in to the token.
+#[derive(Debug)]
+pub enum LoginError {
+    GetSessionInfoFailed(pkcs11_sys::CK_RV),
+    LoginFailed(pkcs11_sys::CK_RV),
+}
+
+impl std::fmt::Display for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoginError::GetSessionInfoFailed(result) => {
+                write!(f, "C_GetSessionInfo failed with {}", result)
+            }
+            LoginError::LoginFailed(result) => write!(f, "C_Login failed with {}", result),
+        }
+    }
+}
+
+impl std::error::Error for LoginError {}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let close = *self.close_on_drop.get_mut();
+        if let Some(pool) = self.pool.take().and_then(|pool| pool.upgrade()) {
+            if !close && pool.put(self.handle, self.pin.take()) {
+                return;
+            }
+        }
 
-impl Drop for Session {
-    fn drop(&mut self) {
         unsafe {
             let _ = (self.context.C_CloseSession)(self.handle);
         }